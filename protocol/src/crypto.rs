@@ -0,0 +1,14 @@
+//! The keys, hashes and signatures that Quartznet data is made of.
+//!
+//! With the `gnunet` feature, which is on by default, these are the types of gnunet, which the daemon shares with the gnunet services.
+//! Without it, they are the types of `portable`, which are encoded the same way, so that the crate builds for targets that gnunet doesn't build for, like wasm.
+
+pub mod portable;
+
+#[cfg(feature = "gnunet")]
+pub use gnunet::{
+	crypto::HashCode,
+	identity::{PublicKey, Signature}
+};
+#[cfg(not(feature = "gnunet"))]
+pub use portable::{HashCode, PublicKey, Signature};
@@ -0,0 +1,292 @@
+//! The keys, hashes and signatures of gnunet, without gnunet.
+//!
+//! They are laid out like the structures of gnunet itself, and encoded as those bytes, so that they are encoded just like the types of gnunet.
+//! Their text is the base32 of gnunet as well.
+//! Only EdDSA signatures can be verified, ECDSA signatures never are.
+
+use std::{
+	convert::TryFrom,
+	fmt
+};
+
+use ed25519_dalek::Verifier;
+use serde::{de, ser::SerializeTuple, *};
+use sha2::{Digest, Sha512};
+
+
+
+/// The length of a hash, in bytes.
+pub const HASH_LENGTH: usize = 64;
+/// The length of a key without its type, in bytes.
+pub const KEY_LENGTH: usize = 32;
+/// The length of a signature without its type, in bytes.
+pub const SIGNATURE_LENGTH: usize = 64;
+/// The type of ECDSA keys and signatures, as gnunet numbers it.
+pub const KEY_TYPE_ECDSA: u32 = 65536;
+/// The type of EdDSA keys and signatures, as gnunet numbers it.
+pub const KEY_TYPE_EDDSA: u32 = 65556;
+
+/// The characters of the base32 of gnunet, which is Crockford's.
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// A SHA-512 hash.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HashCode ( [u8; HASH_LENGTH] );
+
+/// The public key of an ego, which is also the address of a channel.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PublicKey {
+	key_type: u32,
+	key: [u8; KEY_LENGTH]
+}
+
+/// A signature that was made with the private key of a `PublicKey`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Signature {
+	signature_type: u32,
+	signature: [u8; SIGNATURE_LENGTH]
+}
+
+
+
+impl HashCode {
+
+	/// Hashes the given data.
+	pub fn generate( data: &[u8] ) -> Self {
+		let mut hash = [0u8; HASH_LENGTH];
+		hash.copy_from_slice( &Sha512::digest( data ) );
+		Self ( hash )
+	}
+
+	/// Hashes the bincode encoding of the given value.
+	pub fn generate_from<T: Serialize + ?Sized>( value: &T ) -> Self {
+		Self::generate( &bincode::serialize( value ).expect("unable to encode value") )
+	}
+
+	pub fn from_string( string: &str ) -> Option<Self> {
+		let mut hash = [0u8; HASH_LENGTH];
+		hash.copy_from_slice( &decode_base32( string, HASH_LENGTH )? );
+		Some( Self ( hash ) )
+	}
+
+	pub fn to_bytes( &self ) -> [u8; HASH_LENGTH] {
+		self.0
+	}
+}
+
+impl fmt::Debug for HashCode {
+	fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result {
+		write!(f, "HashCode({})", self)
+	}
+}
+
+impl fmt::Display for HashCode {
+	fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result {
+		write!(f, "{}", encode_base32( &self.0 ))
+	}
+}
+
+impl Serialize for HashCode {
+	fn serialize<S: Serializer>( &self, serializer: S ) -> Result<S::Ok, S::Error> {
+		serialize_raw( &self.0, serializer )
+	}
+}
+
+impl<'de> Deserialize<'de> for HashCode {
+	fn deserialize<D: Deserializer<'de>>( deserializer: D ) -> Result<Self, D::Error> {
+		let mut hash = [0u8; HASH_LENGTH];
+		hash.copy_from_slice( &deserialize_raw( deserializer, HASH_LENGTH )? );
+		Ok( Self ( hash ) )
+	}
+}
+
+
+impl PublicKey {
+
+	/// Parses the text of a key, which includes its type.
+	pub fn from_string( string: &str ) -> Option<Self> {
+		Self::from_layout( &decode_base32( string, 4 + KEY_LENGTH )? )
+	}
+
+	/// Reads the key from the layout of gnunet, which starts with its type in network byte order.
+	fn from_layout( layout: &[u8] ) -> Option<Self> {
+		let key_type = u32::from_be_bytes( <[u8; 4]>::try_from( &layout[..4] ).ok()? );
+		if key_type != KEY_TYPE_ECDSA && key_type != KEY_TYPE_EDDSA {
+			return None
+		}
+
+		let mut key = [0u8; KEY_LENGTH];
+		key.copy_from_slice( &layout[4..] );
+		Some( Self { key_type, key } )
+	}
+
+	fn layout( &self ) -> Vec<u8> {
+		let mut layout = Vec::with_capacity( 4 + KEY_LENGTH );
+		layout.extend( &self.key_type.to_be_bytes() );
+		layout.extend( &self.key );
+		layout
+	}
+}
+
+impl fmt::Debug for PublicKey {
+	fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result {
+		write!(f, "PublicKey({})", self)
+	}
+}
+
+impl fmt::Display for PublicKey {
+	fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result {
+		write!(f, "{}", encode_base32( &self.layout() ))
+	}
+}
+
+impl Serialize for PublicKey {
+	fn serialize<S: Serializer>( &self, serializer: S ) -> Result<S::Ok, S::Error> {
+		serialize_raw( &self.layout(), serializer )
+	}
+}
+
+impl<'de> Deserialize<'de> for PublicKey {
+	fn deserialize<D: Deserializer<'de>>( deserializer: D ) -> Result<Self, D::Error> {
+		let layout = deserialize_raw( deserializer, 4 + KEY_LENGTH )?;
+		Self::from_layout( &layout ).ok_or_else(|| de::Error::custom("unknown key type"))
+	}
+}
+
+
+impl Signature {
+
+	/// Checks whether the signature was made over the given purpose and data with the private key of `public_key`.
+	/// Like gnunet, the purpose is signed as its size and number, both in network byte order, followed by the data.
+	pub fn verify( &self, purpose: u32, data: &[u8], public_key: &PublicKey ) -> bool {
+		if self.signature_type != KEY_TYPE_EDDSA || public_key.key_type != KEY_TYPE_EDDSA {
+			return false
+		}
+
+		let mut signed = Vec::with_capacity( 8 + data.len() );
+		signed.extend( &((8 + data.len()) as u32).to_be_bytes() );
+		signed.extend( &purpose.to_be_bytes() );
+		signed.extend( data );
+
+		let key = match ed25519_dalek::PublicKey::from_bytes( &public_key.key ) {
+			Ok(k) => k,
+			Err(_) => return false
+		};
+		let signature = match ed25519_dalek::Signature::try_from( &self.signature[..] ) {
+			Ok(s) => s,
+			Err(_) => return false
+		};
+		key.verify( &signed, &signature ).is_ok()
+	}
+}
+
+impl fmt::Debug for Signature {
+	fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result {
+		write!(f, "Signature({})", encode_base32( &self.signature ))
+	}
+}
+
+impl Serialize for Signature {
+	fn serialize<S: Serializer>( &self, serializer: S ) -> Result<S::Ok, S::Error> {
+		let mut layout = Vec::with_capacity( 4 + SIGNATURE_LENGTH );
+		layout.extend( &self.signature_type.to_be_bytes() );
+		layout.extend( &self.signature[..] );
+		serialize_raw( &layout, serializer )
+	}
+}
+
+impl<'de> Deserialize<'de> for Signature {
+	fn deserialize<D: Deserializer<'de>>( deserializer: D ) -> Result<Self, D::Error> {
+		let layout = deserialize_raw( deserializer, 4 + SIGNATURE_LENGTH )?;
+		let signature_type = u32::from_be_bytes( [layout[0], layout[1], layout[2], layout[3]] );
+		if signature_type != KEY_TYPE_ECDSA && signature_type != KEY_TYPE_EDDSA {
+			return Err( de::Error::custom("unknown signature type") )
+		}
+
+		let mut signature = [0u8; SIGNATURE_LENGTH];
+		signature.copy_from_slice( &layout[4..] );
+		Ok( Self { signature_type, signature } )
+	}
+}
+
+
+/// Encodes bytes as a tuple, which bincode lays out as the bytes themselves.
+fn serialize_raw<S: Serializer>( bytes: &[u8], serializer: S ) -> Result<S::Ok, S::Error> {
+	let mut tuple = serializer.serialize_tuple( bytes.len() )?;
+	for byte in bytes {
+		tuple.serialize_element( byte )?;
+	}
+	tuple.end()
+}
+
+/// Decodes the `len` bytes of `serialize_raw`.
+fn deserialize_raw<'de, D: Deserializer<'de>>( deserializer: D, len: usize ) -> Result<Vec<u8>, D::Error> {
+
+	struct RawVisitor ( usize );
+
+	impl<'de> de::Visitor<'de> for RawVisitor {
+		type Value = Vec<u8>;
+
+		fn expecting( &self, f: &mut fmt::Formatter ) -> fmt::Result {
+			write!(f, "{} bytes", self.0)
+		}
+
+		fn visit_seq<A: de::SeqAccess<'de>>( self, mut seq: A ) -> Result<Vec<u8>, A::Error> {
+			let mut bytes = Vec::with_capacity( self.0 );
+			for i in 0..self.0 {
+				bytes.push( seq.next_element()?.ok_or_else(|| de::Error::invalid_length( i, &self ))? );
+			}
+			Ok( bytes )
+		}
+	}
+
+	deserializer.deserialize_tuple( len, RawVisitor ( len ) )
+}
+
+/// Encodes the data in the base32 of gnunet, five bits per character, where the bits of the last character are padded with zeros.
+fn encode_base32( data: &[u8] ) -> String {
+	let mut string = String::with_capacity( (data.len() * 8).div_ceil( 5 ) );
+	let mut bits: u32 = 0;
+	let mut bit_count = 0;
+
+	for byte in data {
+		bits = (bits << 8) | *byte as u32;
+		bit_count += 8;
+		while bit_count >= 5 {
+			bit_count -= 5;
+			string.push( ALPHABET[((bits >> bit_count) & 31) as usize] as char );
+		}
+		bits &= (1 << bit_count) - 1;
+	}
+	if bit_count > 0 {
+		string.push( ALPHABET[((bits << (5 - bit_count)) & 31) as usize] as char );
+	}
+	string
+}
+
+/// Decodes the text of `encode_base32` into exactly `len` bytes.
+/// Like gnunet, lower case is accepted, as are the letters O, I and L for the digits that they look like.
+fn decode_base32( string: &str, len: usize ) -> Option<Vec<u8>> {
+	if string.len() != (len * 8).div_ceil( 5 ) {
+		return None
+	}
+
+	let mut data = Vec::with_capacity( len );
+	let mut bits: u32 = 0;
+	let mut bit_count = 0;
+	for c in string.bytes() {
+		let value = match c.to_ascii_uppercase() {
+			b'O' => 0,
+			b'I' | b'L' => 1,
+			c => ALPHABET.iter().position(|a| *a == c)? as u32
+		};
+		bits = (bits << 5) | value;
+		bit_count += 5;
+		if bit_count >= 8 {
+			bit_count -= 8;
+			data.push( (bits >> bit_count) as u8 );
+		}
+		bits &= (1 << bit_count) - 1;
+	}
+	Some( data )
+}
@@ -0,0 +1,109 @@
+//! The encryption of the messages of private channels.
+//!
+//! Every private channel has an invite code, a random secret that the owner shares with the subscribers it invites.
+//! The channel key is derived from the invite code and the address of the channel.
+//! Peers that don't have the invite code can still relay the messages of the swarm, but can't read them.
+
+use std::fmt::Write;
+
+use chacha20poly1305::{
+	aead::{Aead, NewAead},
+	ChaCha20Poly1305,
+	Key,
+	Nonce
+};
+use rand::RngCore;
+
+use crate::{
+	crypto::{HashCode, PublicKey},
+	validation::MessageMalformedError
+};
+
+
+
+/// The length of an invite code, in bytes.
+pub const INVITE_CODE_LENGTH: usize = 32;
+/// The length of the nonce that is put in front of every encrypted payload.
+pub const NONCE_LENGTH: usize = 12;
+
+/// The symmetric key with which all messages of a private channel are encrypted.
+#[derive(Clone)]
+pub struct ChannelKey ( [u8; 32] );
+
+/// The secret that is shared with the subscribers of a private channel.
+#[derive(Clone, PartialEq)]
+pub struct InviteCode ( [u8; INVITE_CODE_LENGTH] );
+
+
+
+impl ChannelKey {
+
+	/// Derives the key of the channel with the given address from its invite code.
+	pub fn derive( channel: &PublicKey, invite_code: &InviteCode ) -> Self {
+		let mut input = channel.to_string().into_bytes();
+		input.extend_from_slice( &invite_code.0 );
+		let hash = HashCode::generate( &input ).to_bytes();
+
+		let mut key = [0u8; 32];
+		key.copy_from_slice( &hash[..32] );
+		Self ( key )
+	}
+
+	/// Encrypts the payload, and puts the random nonce that was used in front of it.
+	pub fn encrypt( &self, payload: &[u8] ) -> Vec<u8> {
+		let mut nonce = [0u8; NONCE_LENGTH];
+		rand::thread_rng().fill_bytes( &mut nonce );
+
+		let cipher = ChaCha20Poly1305::new( Key::from_slice( &self.0 ) );
+		let encrypted = cipher.encrypt( Nonce::from_slice( &nonce ), payload ).expect("unable to encrypt payload");
+
+		let mut result = Vec::with_capacity( NONCE_LENGTH + encrypted.len() );
+		result.extend_from_slice( &nonce );
+		result.extend( encrypted );
+		result
+	}
+
+	/// Decrypts a payload that was encrypted with `encrypt`.
+	/// Fails if the payload wasn't encrypted with this key, or has been tampered with.
+	pub fn decrypt( &self, data: &[u8] ) -> Result<Vec<u8>, MessageMalformedError> {
+		if data.len() < NONCE_LENGTH {
+			Err(MessageMalformedError::MissingData("nonce".to_owned()))?
+		}
+
+		let cipher = ChaCha20Poly1305::new( Key::from_slice( &self.0 ) );
+		Ok( cipher.decrypt( Nonce::from_slice( &data[..NONCE_LENGTH] ), &data[NONCE_LENGTH..] )
+			.map_err(|_| MessageMalformedError::DecryptionFailed("channel message".to_owned()))? )
+	}
+}
+
+impl InviteCode {
+
+	pub fn generate() -> Self {
+		let mut code = [0u8; INVITE_CODE_LENGTH];
+		rand::thread_rng().fill_bytes( &mut code );
+		Self ( code )
+	}
+
+	/// Parses an invite code from its hexadecimal notation.
+	pub fn from_string( string: &str ) -> Option<Self> {
+		let string = string.trim();
+		if string.len() != INVITE_CODE_LENGTH * 2 || !string.is_ascii() {
+			return None
+		}
+
+		let mut code = [0u8; INVITE_CODE_LENGTH];
+		for (i, byte) in code.iter_mut().enumerate() {
+			*byte = u8::from_str_radix( &string[(i * 2)..(i * 2 + 2)], 16 ).ok()?;
+		}
+		Some( Self ( code ) )
+	}
+
+	/// The hexadecimal notation of the invite code, in which it is shared.
+	pub fn to_string( &self ) -> String {
+		let mut string = String::with_capacity( INVITE_CODE_LENGTH * 2 );
+		for byte in &self.0 {
+			write!( string, "{:02x}", byte ).unwrap();
+		}
+		string
+	}
+}
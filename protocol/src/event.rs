@@ -0,0 +1,344 @@
+use std::fmt;
+
+use serde::{*, ser::SerializeTuple};
+
+use crate::{
+	byte_enum,
+	crypto::{HashCode, PublicKey, Signature},
+	post::{Post, PostInfo}
+};
+
+
+
+/// The id of the genesis event, the `Create` event that every channel starts with.
+pub const GENESIS_EVENT_ID: u64 = 0;
+/// The maximum number of bytes of the content of a comment.
+pub const COMMENT_MAX_LEN: usize = 4096;
+/// The maximum number of bytes of the kind of a reaction.
+pub const REACTION_MAX_LEN: usize = 16;
+/// The maximum number of posts that can be pinned to the top of the feed of a channel.
+pub const PINNED_POSTS_MAX: usize = 5;
+
+pub enum EventType {
+	Channel,
+	Publisher( PublicKey )
+}
+struct EventTypeVisitor;
+
+byte_enum! {
+	pub enum ChannelEventType {
+		/// Contains the new profile information
+		UpdateChannelProfile = 0,
+		UpdatePublisherList = 1,
+		/// The genesis event, which contains a `ChannelCreateEventMessage`.
+		Create = 2,
+		/// Names a new owner for the channel, which contains a `TransferOwnershipEventMessage`.
+		TransferOwnership = 3,
+		/// The new owner accepts the channel, which contains an `AcceptOwnershipEventMessage`.
+		AcceptOwnership = 4,
+		/// Replaces the posts that are pinned to the top of the feed, which contains an `UpdatePinnedPostsEventMessage`.
+		UpdatePinnedPosts = 5,
+		/// Decides about a comment on one of the posts of the channel, or about everything that an author comments, which contains a `ModerateCommentsEventMessage`.
+		ModerateComments = 6,
+		/// The owner has deleted the channel, which contains a `CloseChannelEventMessage`.
+		/// It is the last event of the channel, nothing is published in it anymore.
+		Close = 7
+	}
+}
+
+byte_enum! {
+	pub enum PublisherEventType {
+		UpdateProfile = 0,
+		/// Publishes a post and the hashcode that identifies the content of the post.
+		PublishPost = 1,
+		/// Changes the hash code that identifies a post, and provides the diffs that change the previous state of the post to the new one.
+		RevisePost = 2,
+		/// Requests the participating nodes to 'forget' a post.
+		ForgetPost = 3,
+		/// Comments on a post of the publisher, which contains a `CommentEventData`.
+		/// Unlike the other publisher events, it is signed by the author of the comment, who doesn't need to be a publisher.
+		Comment = 4,
+		/// Reacts to a post of the publisher, which contains a `ReactionEventData`.
+		/// Like a comment, it is signed by whoever reacts.
+		Reaction = 5
+	}
+}
+
+/// Announces a new post.
+/// The post includes the ids of its attachments, so that subscribers know which files to fetch.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PublishPostEventData {
+	pub post: Post
+}
+
+/// Replaces the content of a post with a new revision.
+/// The new content is sent in full, rather than as diffs, so that a revision can be applied without having any of the previous ones.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct RevisePostEventData {
+	/// The id of the post that is revised.
+	pub post_id: u64,
+	pub revision: PostRevision,
+	/// The hash of `revision`.
+	pub hash: HashCode,
+	/// The signature of `hash`, by the publisher of the post.
+	pub signature: Signature,
+	pub content: String
+}
+
+/// The part of a post revision that is signed by the publisher.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PostRevision {
+	/// The hash of the post that is revised.
+	/// This binds the revision to the post, so that it can't be replayed onto another post.
+	pub post_hash: HashCode,
+	/// The revision number.
+	/// The original post has revision number 0, and every revision has a higher number than the one before.
+	pub number: u32,
+	/// The hash of the new content.
+	pub content_hash: HashCode,
+	/// The information of the post as of this revision, which replaces the tags, series and content warning of the post.
+	pub info: PostInfo
+}
+
+/// Requests the participating nodes to forget a post.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ForgetPostEventData {
+	/// The id of the post that should be forgotten.
+	pub post_id: u64,
+	/// The hash of the `ForgetPostRequest` for the post.
+	pub hash: HashCode,
+	/// The signature of `hash`, by the publisher of the post.
+	pub signature: Signature
+}
+
+/// The part of a forget request that is signed by the publisher.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ForgetPostRequest {
+	/// The hash of the post that should be forgotten.
+	pub post_hash: HashCode
+}
+
+/// A comment on a post, or a reply to another comment on it.
+/// Comments are spread in the swarm of the channel of the post, so that everyone who follows the channel can read them.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct CommentEventData {
+	/// The id of the post that is commented on.
+	pub post_id: u64,
+	pub author: PublicKey,
+	/// The hash of `comment`.
+	pub hash: HashCode,
+	/// The signature of `hash`, by `author`.
+	pub signature: Signature,
+	pub comment: CommentBody
+}
+
+/// The part of a comment that is signed by its author.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct CommentBody {
+	/// The hash of the post that is commented on.
+	/// This binds the comment to the post, so that it can't be replayed onto another post.
+	pub post_hash: HashCode,
+	/// The hash of the comment that this comment replies to, if any.
+	pub reply_to: Option<HashCode>,
+	/// In milliseconds since the UNIX epoch.
+	pub timestamp: u64,
+	/// Plain text, of at most `COMMENT_MAX_LEN` bytes.
+	pub content: String
+}
+
+/// A reaction to a post, like a like or an emoji.
+/// Everyone has at most one reaction per post: a newer reaction replaces an older one of the same signer.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ReactionEventData {
+	/// The id of the post that is reacted to.
+	pub post_id: u64,
+	pub signer: PublicKey,
+	/// The hash of `reaction`.
+	pub hash: HashCode,
+	/// The signature of `hash`, by `signer`.
+	pub signature: Signature,
+	pub reaction: Reaction
+}
+
+/// The part of a reaction that is signed.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Reaction {
+	/// The hash of the post that is reacted to, which binds the reaction to the post.
+	pub post_hash: HashCode,
+	/// The kind of reaction, usually a single emoji, of at most `REACTION_MAX_LEN` bytes.
+	pub kind: String,
+	/// In milliseconds since the UNIX epoch.
+	/// Of the reactions of the same signer to the same post, only the newest one counts.
+	pub timestamp: u64
+}
+
+/// This is always the first event for the channel timeline.
+/// This even message contains the parameters that define some settings of the channel.
+/// These parameters can't be changed because if a publisher doesn't notice that change in its UI, 
+///  the publisher would not know its data might be shared with more (or less) people than initially anticipated.
+#[derive(Clone, Deserialize, PartialEq, Serialize)]
+pub struct ChannelCreateEventData {
+
+	/// If true, the posts from the listed publishers can be shared with any subscriber peer.
+	pub public: bool,
+	/// The number of days that the content is requested to be replicated by the subscribers.
+	/// This is by no means a way to ensure a minimum or maximum replication time,
+	///  but it can be used to keep posts not stored for too long in channels that are intended for a more temporal way of shared messages/content,
+	///  like channels with the intention of being a chat room.
+	pub requested_replication_time: u32
+}
+
+/// Offers the channel to a new owner.
+/// Nothing changes until the new owner accepts it.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct OwnershipTransfer {
+	pub new_owner: PublicKey,
+	/// The number of milliseconds after the acceptance, during which the keys of the previous owners still validate channel events.
+	/// This gives the previous owner the time to hand over anything that is still underway.
+	pub grace_period: u64
+}
+
+/// A transfer of the channel, signed by a current owner of the channel.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct TransferOwnershipEventMessage {
+	/// The hash of `transfer`.
+	pub hash: HashCode,
+	pub signature: Signature,
+	pub transfer: OwnershipTransfer
+}
+
+/// Accepts the transfer of a channel.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct OwnershipAcceptance {
+	/// The hash of the `OwnershipTransfer` that is accepted.
+	pub transfer_hash: HashCode
+}
+
+/// The acceptance of a transfer, signed by the new owner.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct AcceptOwnershipEventMessage {
+	/// The hash of `acceptance`.
+	pub hash: HashCode,
+	pub signature: Signature,
+	pub acceptance: OwnershipAcceptance
+}
+
+/// What an owner of a channel decides about the comments on its posts.
+#[derive(Clone, Deserialize, Serialize)]
+pub enum ModerationAction {
+	/// Shows the comment with the given hash, which was held or hidden before.
+	ApproveComment( HashCode ),
+	/// Hides the comment with the given hash.
+	HideComment( HashCode ),
+	/// Deletes the content of the comment with the given hash.
+	DeleteComment( HashCode ),
+	/// Shows the comments of the given author from then on, without holding them first.
+	ApproveAuthor( PublicKey )
+}
+
+/// A moderation decision, as it is signed.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct CommentModeration {
+	/// In milliseconds since the UNIX epoch.
+	/// A decision only overrides the decisions about the same comment or author that were made before it, so that an old one can't be replayed over a newer one.
+	pub timestamp: u64,
+	pub action: ModerationAction
+}
+
+/// A moderation decision, signed by an owner of the channel.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ModerateCommentsEventMessage {
+	/// The hash of `moderation`.
+	pub hash: HashCode,
+	pub signature: Signature,
+	pub moderation: CommentModeration
+}
+
+/// The end of a channel, as it is signed.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ChannelClosing {
+	/// In milliseconds since the UNIX epoch.
+	pub timestamp: u64
+}
+
+/// The end of a channel, signed by an owner of the channel.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct CloseChannelEventMessage {
+	/// The hash of `closing`.
+	pub hash: HashCode,
+	pub signature: Signature,
+	pub closing: ChannelClosing
+}
+
+/// The genesis event of a channel, signed by the owner of the channel.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ChannelCreateEventMessage {
+	/// The hash of `data`.
+	pub hash: HashCode,
+	pub signature: Signature,
+	pub data: ChannelCreateEventData
+}
+
+
+
+impl Serialize for EventType {
+
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where
+		S: Serializer
+	{
+		let mut s = serializer.serialize_tuple(2)?;
+
+		match self {
+			EventType::Channel => s.serialize_element(&0u8)?,
+			EventType::Publisher(address) => {
+				s.serialize_element(&1u8)?;
+				s.serialize_element(address)?
+			}
+		}
+
+		s.end()
+	}
+}
+
+impl<'de> Deserialize<'de> for EventType {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where
+		D: Deserializer<'de>
+	{
+		// The length isn't known up front, and bincode doesn't hand out any more elements than the length that it is given.
+		deserializer.deserialize_tuple( usize::MAX, EventTypeVisitor )
+	}
+}
+
+impl<'de> de::Visitor<'de> for EventTypeVisitor {
+
+	type Value = EventType;
+
+	fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+		formatter.write_str("event type")
+	}
+
+	fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error> where
+		A: de::SeqAccess<'de>
+	{
+		
+		let is_channel: u8 = match seq.next_element()? {
+			None => return Err( de::Error::custom("no bytes") ),
+			Some(x) => x
+		};
+
+		let result = match is_channel {
+			0 => EventType::Channel,
+			1 => {
+				let address = match seq.next_element()? {
+					None => Err( de::Error::custom("not enough bytes to extract the address") )?,
+					Some(x) => x
+				};
+
+				EventType::Publisher( address )
+			},
+			other => Err( de::Error::custom("unknown event type found") )?
+		};
+
+		Ok( result )
+	}
+}
\ No newline at end of file
@@ -0,0 +1,15 @@
+//! The data structures, wire encoding and validation rules of the Quartznet protocol.
+//!
+//! This crate doesn't depend on the daemon, its database or any of the gnunet services.
+//! This allows alternative frontends (a wasm client or a mobile app for example) to encode, decode and verify Quartznet data without running a node.
+//!
+//! Without its default features, it doesn't depend on gnunet or on the C library of zstd either, so that it builds for wasm.
+//! `cargo check-wasm` checks that it still does.
+
+pub mod crypto;
+pub mod encryption;
+pub mod event;
+mod r#macro;
+pub mod message;
+pub mod post;
+pub mod validation;
@@ -0,0 +1,936 @@
+//! This module contains all network messages.
+//! So requests and responses, but also notifications.
+
+use std::{
+	borrow::Cow,
+	cmp::{min, Ordering},
+	collections::HashMap,
+	convert::TryInto,
+	fmt,
+	io::Read
+};
+
+use serde::{*, ser::SerializeTuple};
+
+use crate::{
+	byte_enum,
+	crypto::{HashCode, PublicKey, Signature},
+	encryption::ChannelKey,
+	post::*,
+	validation::MessageMalformedError
+};
+
+
+
+pub const PROFILE_DESCRIPTION_MAX_LEN: u16 = 1024;
+/// The maximum number of blocks that are sent in response to a single `BlocksRequest`.
+pub const BLOCKS_REQUEST_MAX_COUNT: usize = 8;
+/// The maximum number of files that are looked up for a single `FilesRequest` or `ManifestsRequest`.
+pub const FILES_REQUEST_MAX_COUNT: usize = 64;
+/// The maximum number of events that can be requested with a single `EventsRequest`.
+pub const EVENTS_REQUEST_MAX_COUNT: u16 = 100;
+/// The maximum number of posts that are sent in response to a single `PostSearchRequest`.
+pub const SEARCH_REQUEST_MAX_RESULTS: u16 = 20;
+/// The maximum number of posts that are sent in response to a single `PostSetRequest`, and the number of ids that a `PostsRequest` may cover.
+pub const POSTS_REQUEST_MAX_COUNT: usize = 256;
+/// The maximum number of post ids that a single `PostSummary` covers, which makes for a mask of 1 KiB.
+pub const SUMMARY_MAX_COUNT: u16 = 8192;
+/// The maximum number of ancestors that a `TopologyMessage::Ancestors` names.
+pub const TOPOLOGY_ANCESTORS_MAX: usize = 4;
+/// The number of parts that a peer is divided in by a `CensusMessage::Subtree`, so that a peer with several parents can be divided among them.
+pub const CENSUS_UNIT: u32 = 1000;
+/// The maximum number of bytes of response data in a single `ResponsePart` message.
+/// This leaves room for the headers and the encryption within the maximum size of a CADET message.
+pub const RESPONSE_PART_MAX_SIZE: usize = 60 * 1024;
+/// The number of bytes from which a message is compressed, smaller messages hardly get any smaller.
+pub const COMPRESSION_THRESHOLD: usize = 512;
+/// The zstd level that messages are compressed with, which is its default.
+pub const COMPRESSION_LEVEL: i32 = 3;
+/// The version of the protocol that this implementation speaks.
+/// 0.3 added the timestamp to channel profiles.
+/// 0.4 added responses that are sent in parts.
+/// 0.5 added compressed messages.
+/// 0.6 added pings and pongs.
+/// 0.7 added post summaries.
+/// 0.8 added requests for sets of posts and events.
+/// 0.9 added topology messages.
+/// 0.10 added census messages.
+/// 0.11 added manifest requests, and identifies new files by their Merkle root.
+pub const PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion { major: 0, minor: 11 };
+
+byte_enum! {
+	pub enum MessageDirectionType {
+		/// An event that needs to be redistributed.
+		Event = 0,
+		Request = 1,
+		Response = 2,
+		/// The first message on a channel, which contains a `HelloMessage`.
+		/// It is never encrypted, so that peers can find out whether they understand each other at all.
+		Hello = 3,
+		/// The last message on a channel, which contains a `GoodbyeMessage`.
+		/// Like the hello message, it is never encrypted.
+		Goodbye = 4,
+		/// A part of a response that is too large for a single message.
+		/// It starts with a `ResponsePartHeader`, followed by the next bytes of what would otherwise have been the response, after its request id.
+		ResponsePart = 5,
+		/// A message of another direction type that has been compressed.
+		/// It is followed by a `CompressionType` byte, and then by the compressed bytes of the whole message, including its own direction type.
+		/// A private channel encrypts the message after compressing it, so this direction type is all that is left readable.
+		Compressed = 6,
+		/// Asks the peer to show that it is still there, by sending a `Pong` back.
+		/// Neither carries anything besides its direction type.
+		Ping = 7,
+		Pong = 8,
+		/// Tells a parent or child about the shape of the swarm around the sender, with a `TopologyMessage`.
+		Topology = 9,
+		/// Tells a parent or child how many peers there are in the swarm, with a `CensusMessage`.
+		Census = 10
+	}
+}
+
+byte_enum! {
+	pub enum CompressionType {
+		Zstd = 0
+	}
+}
+
+byte_enum! {
+	#[derive(Clone, Copy, Debug)]
+	pub enum RequestType {
+		/// Requests the meta data or content of a post.
+		Posts = 0,
+		/// Request a number of files
+		Files,
+		/// Request blocks of a file
+		Blocks,
+		/// Requests a range of events that were missed.
+		Events,
+		/// Searches the posts of the channel that the responding node has.
+		Search,
+		/// Asks which posts of a publisher the responding node has.
+		Summary,
+		/// Requests the posts of a publisher that are in an `IdSet`.
+		PostSet,
+		/// Requests the events that are in an `IdSet`.
+		EventSet,
+		/// Requests the manifests of files that can be checked against their ids.
+		Manifests
+	}
+}
+
+byte_enum! {
+	pub enum ResponseResultType {
+		Success = 0,
+		InternalError
+	}
+}
+
+/// Peers can only talk to each other if they speak the same major version.
+/// Minor versions only add to the protocol, so peers with different minor versions fall back to the lowest of the two.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ProtocolVersion {
+	pub major: u16,
+	pub minor: u16
+}
+
+/// Sent by a node that leaves the swarm, to the peers that it is connected to.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct GoodbyeMessage {
+	/// The parent of the leaving node, so that its children can connect to that instead.
+	/// This is `None` when sent to the parent itself.
+	pub parent: Option<PublicKey>
+}
+
+/// Keeps the peers on both sides of a link informed about the swarm around them, so that a subtree can heal itself when its parent vanishes.
+#[derive(Clone, Deserialize, Serialize)]
+pub enum TopologyMessage {
+	/// Sent to the children, whenever the parents of the sender change.
+	/// The addresses of the parents of the sender, followed by their own ancestors, nearest first and at most `TOPOLOGY_ANCESTORS_MAX`.
+	/// A child that loses the sender reconnects to these, rather than finding its way back into the swarm from scratch.
+	Ancestors( Vec<PublicKey> ),
+	/// Sent to the parents, whenever the children of the sender change.
+	Capacity {
+		/// The number of children that the sender can still take.
+		free: u16,
+		/// Whether the sender is connected to the swarm through parents other than the recipient.
+		other_parents: bool
+	}
+}
+
+/// Counts the peers of a swarm, so that its owner can see how many follow the channel.
+/// Every peer divides the number of peers in its subtree, itself included, among its parents, so that a peer with several parents is only counted once.
+/// The root adds up what its children report, and the total is passed down from parent to child, so that every peer learns it.
+/// It is only an estimate, as it takes a while for the counts to travel through the swarm, and a peer can report anything.
+#[derive(Clone, Deserialize, Serialize)]
+pub enum CensusMessage {
+	/// Sent to the parents every so often: the share of the recipient in the subtree of the sender, in `CENSUS_UNIT`s of a peer.
+	Subtree( u32 ),
+	/// Sent to the children every so often: the number of peers in the whole swarm, as far as the sender knows.
+	Swarm( u32 )
+}
+
+/// The start of a `ResponsePart` message.
+/// The parts of a response are numbered from 0, and are sent in order.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ResponsePartHeader {
+	pub request_id: u32,
+	pub sequence: u32,
+	/// Whether this is the last part of the response.
+	pub last: bool
+}
+
+/// Sent by both sides of a channel right after it has been opened.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct HelloMessage {
+	pub version: ProtocolVersion
+}
+
+/// Requests the data of a block of a post.
+/// At most `BLOCKS_REQUEST_MAX_COUNT` blocks are responded to.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct BlocksRequest {
+	pub post_id: HashCode,
+	pub block_ids: Vec<HashCode>
+}
+
+/// Requests which blocks make up the given files.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct FilesRequest {
+	pub file_ids: Vec<HashCode>
+}
+
+/// A response to `FilesRequest`.
+/// Files that the responding node doesn't know are left out.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct FilesResponse {
+	pub files: HashMap<HashCode, Attachment>
+}
+
+/// Requests the manifests of the given files.
+/// Unlike a `FilesRequest`, it is only answered with manifests of which the file id is the Merkle root.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ManifestsRequest {
+	pub file_ids: Vec<HashCode>
+}
+
+/// A response to `ManifestsRequest`, with a manifest for every requested file, in the same order.
+/// Files that the responding node doesn't know, and files that are identified by the hash of their data, have no manifest.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ManifestsResponse {
+	pub manifests: Vec<Option<Attachment>>
+}
+
+/// Requests the posts with ids `post_id_start` up to (but not including) `post_id_start + post_id_count` of a publisher, which is at most `POSTS_REQUEST_MAX_COUNT` ids.
+/// This structure is followed by a bit mask of `posts_mask_length(post_id_count)` bytes, indicating which of those posts are requested.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PostsRequest {
+	pub timeline_id: PublicKey,
+	pub post_id_start: u64,
+	pub post_id_count: u16,
+	/// Whether or not the content of the posts should be included in the response.
+	pub include_content: bool
+}
+
+/// A post as it is sent in the response to a `PostsRequest`.
+/// The response consists of a bit mask, indicating which of the requested posts were found,
+///  followed by a list of `PostData`, in the same order as the posts in the mask.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PostData {
+	pub post: Post,
+	/// The content of the post, if it was requested and available.
+	pub content: Option<String>
+}
+
+/// Asks which of the posts of a publisher, from `post_id_start` on, the responding node has.
+/// Used to request only the posts that are missing, instead of every post in a range.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PostSummaryRequest {
+	pub timeline_id: PublicKey,
+	pub post_id_start: u64
+}
+
+/// A response to `PostSummaryRequest`.
+/// Covers the post ids `post_id_start` up to (but not including) `post_id_start + post_id_count`, which is at most `SUMMARY_MAX_COUNT` ids.
+/// The mask has `posts_mask_length(post_id_count)` bytes, indicating which of those posts the responding node has.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PostSummary {
+	pub post_id_start: u64,
+	pub post_id_count: u16,
+	pub mask: Vec<u8>,
+	/// The id of the newest post of the publisher that the responding node knows of, so that the requester knows whether to ask for more.
+	pub latest_post_id: Option<u64>
+}
+
+/// A set of post or event ids, made up of ranges of consecutive ids, minus the ids that are excepted from them.
+/// This describes long runs of ids with a few gaps in them as compactly as a handful of scattered ids.
+/// Sets that are received from peers need to be checked with `validate` before they are used.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct IdSet {
+	/// The ranges, in order, without overlapping or touching each other.
+	ranges: Vec<IdRange>,
+	/// The ids within the ranges that aren't in the set, in order.
+	exceptions: Vec<u64>
+}
+
+/// The ids `start` up to (but not including) `start + count`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct IdRange {
+	pub start: u64,
+	pub count: u64
+}
+
+/// Requests the posts of a publisher with the given ids.
+/// The response is a list of `PostData`, ordered by id, of the first `POSTS_REQUEST_MAX_COUNT` posts of the set that the responding node has.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PostSetRequest {
+	pub timeline_id: PublicKey,
+	pub post_ids: IdSet,
+	/// Whether or not the content of the posts should be included in the response.
+	pub include_content: bool
+}
+
+/// Requests the events with the given ids, which is answered with an `EventsResponse`.
+/// At most `EVENTS_REQUEST_MAX_COUNT` events are responded with.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct EventSetRequest {
+	pub event_ids: IdSet
+}
+
+/// Requests the events with ids `from_id` up to (but not including) `from_id + count`.
+/// Used by nodes that have missed some events, e.g. because they were offline.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct EventsRequest {
+	pub from_id: u64,
+	pub count: u16
+}
+
+/// A response to `EventsRequest`.
+/// Contains the event messages, ordered by id, in the same form as they are broadcasted.
+/// Events that the responding node doesn't have are left out.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct EventsResponse {
+	pub events: Vec<Vec<u8>>
+}
+
+/// The request send to one of the relays for the channel,
+///  requesting the last message that was published.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ChannelLastMessageRequest {}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ChannelLastMessageResponse {
+	message_id: HashCode
+}
+
+/// A response to `BlockRequest`.
+/// This contains the data of the blocks that were requested, in the same order.
+/// Blocks that the responding node doesn't have are `None`.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct BlocksResponse {
+	pub data: Vec<Option<Vec<u8>>>
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Message {
+	id: HashCode,
+	previous_message_id: HashCode,
+	type_: u8,
+	
+}
+
+/// Requests the meta info of a post
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PostMetaRequest {
+	pub post_ids: Vec<HashCode>
+}
+
+/// Response to `PostMetaRequest`.
+/// The response may or may not contain all post ids that the request requested.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PostMetaResponse {
+	pub metas: HashMap<HashCode, PostMeta>
+}
+
+/// A message that is sent to all subscribers of a blog, to indicate there is a new post to available.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PostNotification {
+	pub post_id: Signature
+}
+
+/// Searches for the posts of the channel that contain all of the keywords.
+/// At most `SEARCH_REQUEST_MAX_RESULTS` posts are responded to.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PostSearchRequest {
+	pub keywords: Vec<String>,
+	pub max_results: u16
+}
+
+/// A response to `PostSearchRequest`, with the best matches first.
+/// Posts that may not be shown yet are left out.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PostSearchResponse {
+	pub results: Vec<PostSearchResult>
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PostSearchResult {
+	pub publisher: PublicKey,
+	/// The post, with its original content if the responding node has it.
+	pub data: PostData
+}
+
+#[derive(Clone)]
+pub struct Profile {
+	pub revision: u64,
+	pub title: String,
+	pub description: String,
+	pub profile_picture: Option<HashCode>
+}
+struct ProfileVisitor;
+
+#[derive(Clone)]
+pub struct ChannelProfile {
+	pub base: Profile,
+	pub stylesheet: Option<HashCode>,
+	/// When the profile was changed, in milliseconds since the UNIX epoch, which decides between two profiles with the same revision.
+	/// Profiles from before protocol version 0.3 don't have it, and it is left out of their encoding so that their hashes stay the same.
+	/// It is the last field of the `UpdateChannelProfile` event, so its absence can be told from the end of the message.
+	pub timestamp: Option<u64>
+}
+struct ChannelProfileVisitor;
+
+/// The message to notify the channel swarm of new profile information.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct UpdateChannelProfileEventMessage {
+	pub hash: HashCode,
+	pub signature: Signature,
+	pub profile: ChannelProfile
+}
+
+/// The set of publishers that are allowed to publish in a channel.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PublisherList {
+	/// Only a list with a higher revision than the current one replaces it.
+	pub revision: u32,
+	pub publishers: Vec<PublicKey>
+}
+
+/// The message to notify the channel swarm of a new set of publishers.
+/// It is signed by the owner of the channel.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct UpdatePublisherListEventMessage {
+	pub hash: HashCode,
+	pub signature: Signature,
+	pub list: PublisherList
+}
+
+/// The posts of the channel itself that are pinned to the top of its feed, in the order in which they are shown.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PinnedPosts {
+	/// Only pinned posts with a higher revision than the current ones replace them.
+	pub revision: u32,
+	/// At most `PINNED_POSTS_MAX` post ids.
+	pub post_ids: Vec<u64>
+}
+
+/// The message to notify the channel swarm of the posts that are pinned now.
+/// It is signed by the owner of the channel.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct UpdatePinnedPostsEventMessage {
+	pub hash: HashCode,
+	pub signature: Signature,
+	pub pinned: PinnedPosts
+}
+
+
+
+/// Returns the number of bytes needed for a bit mask of `count` bits.
+pub fn posts_mask_length( count: u16 ) -> usize {
+	let mut length = (count / 8) as usize;
+	if count % 8 > 0 { length += 1 }
+	length
+}
+
+/// Reads the bit at `index` from the given mask.
+pub fn get_mask_bit( mask: &[u8], index: u16 ) -> bool {
+	debug_assert!(index/8 < mask.len() as u16, "index out of bounds");
+	let byte = mask[ (index / 8) as usize ];
+	let byte_index = index % 8;
+	byte & (1 << byte_index) > 0
+}
+
+/// Sets the bit at `index` in the given mask.
+pub fn set_mask_bit( mask: &mut [u8], index: u16 ) {
+	debug_assert!(index/8 < mask.len() as u16, "index out of bounds");
+	let byte_index = index % 8;
+	mask[ (index / 8) as usize ] |= 1 << byte_index;
+}
+
+
+
+impl Serialize for Profile {
+
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where
+		S: Serializer
+	{
+		let len = 2 + self.title.len() + 1 + self.description.len() + 2;
+		let mut s = serializer.serialize_tuple(len)?;
+
+		s.serialize_element( &self.revision )?;
+
+		// Title
+		let len = self.title.len() as u8;
+		s.serialize_element( &len )?;
+		let buf = self.title.as_bytes();
+		for i in 0..self.title.len() {
+			s.serialize_element( &buf[i] )?;
+		}
+
+		// Description
+		let len = self.description.len() as u16;
+		s.serialize_element( &len )?;
+		let buf = self.description.as_bytes();
+		for i in 0..self.description.len() {
+			s.serialize_element( &buf[i] )?;
+		}
+
+		// Profile picture
+		s.serialize_element( &self.profile_picture )?;
+
+		s.end()
+	}
+}
+
+impl<'de> Deserialize<'de> for Profile {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where
+		D: Deserializer<'de>
+	{
+		// The length isn't known up front, and bincode doesn't hand out any more elements than the length that it is given.
+		deserializer.deserialize_tuple( usize::MAX, ProfileVisitor )
+	}
+}
+
+impl ChannelProfile {
+
+	/// Whether this profile replaces the `other` one.
+	/// Two owners, or the same owner on two devices, can change the profile at the same time, and give both changes the same revision.
+	/// So that every node ends up with the same profile, whatever order they receive the changes in, the winner is decided by the revision,
+	///  then by the timestamp, and then by the hash of the profiles.
+	pub fn supersedes( &self, other: &Self ) -> bool {
+		self.compare( other ) == Ordering::Greater
+	}
+
+	/// Orders profiles by which one wins, the winning one being the greater.
+	/// Profiles are only equal if their hashes are equal, in which case they are the same profile.
+	pub fn compare( &self, other: &Self ) -> Ordering {
+		self.base.revision.cmp( &other.base.revision )
+			.then_with(|| self.timestamp.cmp( &other.timestamp ))
+			.then_with(|| HashCode::generate_from( self ).to_string().cmp( &HashCode::generate_from( other ).to_string() ))
+	}
+}
+
+impl Serialize for ChannelProfile {
+
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where
+		S: Serializer
+	{
+		let mut s = serializer.serialize_tuple( if self.timestamp.is_some() { 3 } else { 2 } )?;
+		s.serialize_element( &self.base )?;
+		s.serialize_element( &self.stylesheet )?;
+		if let Some(timestamp) = &self.timestamp {
+			s.serialize_element( timestamp )?;
+		}
+		s.end()
+	}
+}
+
+impl<'de> Deserialize<'de> for ChannelProfile {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where
+		D: Deserializer<'de>
+	{
+		// The timestamp is only there if there are bytes left.
+		deserializer.deserialize_tuple( usize::MAX, ChannelProfileVisitor )
+	}
+}
+
+impl<'de> de::Visitor<'de> for ChannelProfileVisitor {
+
+	type Value = ChannelProfile;
+
+	fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+		formatter.write_str("a channel profile structure")
+	}
+
+	fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error> where
+		A: de::SeqAccess<'de>
+	{
+		let base: Profile = match seq.next_element()? {
+			None => return Err( de::Error::custom("not enough bytes to extract the profile") ),
+			Some(x) => x
+		};
+		let stylesheet: Option<HashCode> = match seq.next_element()? {
+			None => return Err( de::Error::custom("not enough bytes to extract the stylesheet hash") ),
+			Some(x) => x
+		};
+		// Bincode fails to read past the end of the message, rather than telling that there are no elements left.
+		let timestamp: Option<u64> = seq.next_element().unwrap_or(None);
+
+		Ok( ChannelProfile {
+			base,
+			stylesheet,
+			timestamp
+		} )
+	}
+}
+
+impl fmt::Display for Profile {
+	fn fmt( &self, f: &mut fmt::Formatter<'_> ) -> fmt::Result {
+		write!(f, "{}", self.to_string())
+	}
+}
+
+impl<'de> de::Visitor<'de> for ProfileVisitor {
+
+	type Value = Profile;
+
+	fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+		formatter.write_str("a profile structure")
+	}
+
+	fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error> where
+		A: de::SeqAccess<'de>
+	{
+		
+		let revision: u64 = match seq.next_element()? {
+			None => return Err( de::Error::custom("not enough bytes to extract the revision") ),
+			Some(x) => x
+		};
+
+		// Title
+		let len: u8 = seq.next_element()?.unwrap();
+		let mut buf = vec![0u8; len as usize];
+		for i in 0..buf.len() {
+			buf[i] = seq.next_element()?.unwrap();
+		}
+		let title = match String::from_utf8( buf ) {
+			Err(_) => return Err( de::Error::custom("not enough bytes to extract the title") ),
+			Ok(x) => x
+		};
+
+		// Description
+		let len: u16 = seq.next_element()?.unwrap();
+		let mut buf = vec![0u8; len as usize];
+		for i in 0..buf.len() {
+			buf[i] = seq.next_element()?.unwrap();
+		}
+		let description = match String::from_utf8( buf ) {
+			Err(_) => return Err( de::Error::custom("not enough bytes to extract the description") ),
+			Ok(x) => x
+		};
+
+		let profile_picture: Option<HashCode> = match seq.next_element()? {
+			None => return Err( de::Error::custom("not enough bytes to extract the profile picture hash") ),
+			Some(x) => x
+		};
+
+		Ok( Profile {
+			revision,
+			title,
+			description,
+			profile_picture
+		} )
+	}
+}
+
+
+
+impl ProtocolVersion {
+
+	/// Returns the version to use with a peer that speaks the `other` version, or `None` if the peer can't be talked to.
+	pub fn negotiate( &self, other: &Self ) -> Option<Self> {
+		if self.major != other.major {
+			return None
+		}
+
+		Some( Self {
+			major: self.major,
+			minor: min( self.minor, other.minor )
+		})
+	}
+
+	/// Whether peers that speak this version understand goodbye messages, which were added in 0.2.
+	pub fn has_goodbye( &self ) -> bool {
+		self.major > 0 || self.minor >= 2
+	}
+
+	/// Whether peers that speak this version understand responses that are sent in parts, which were added in 0.4.
+	pub fn has_response_parts( &self ) -> bool {
+		self.major > 0 || self.minor >= 4
+	}
+
+	/// Whether peers that speak this version understand compressed messages, which were added in 0.5.
+	pub fn has_compression( &self ) -> bool {
+		self.major > 0 || self.minor >= 5
+	}
+
+	/// Whether peers that speak this version answer pings, which were added in 0.6.
+	pub fn has_ping( &self ) -> bool {
+		self.major > 0 || self.minor >= 6
+	}
+
+	/// Whether peers that speak this version understand post summary requests, which were added in 0.7.
+	pub fn has_post_summaries( &self ) -> bool {
+		self.major > 0 || self.minor >= 7
+	}
+
+	/// Whether peers that speak this version understand requests for sets of posts and events, which were added in 0.8.
+	pub fn has_id_sets( &self ) -> bool {
+		self.major > 0 || self.minor >= 8
+	}
+
+	/// Whether peers that speak this version understand topology messages, which were added in 0.9.
+	pub fn has_topology( &self ) -> bool {
+		self.major > 0 || self.minor >= 9
+	}
+
+	/// Whether peers that speak this version understand census messages, which were added in 0.10.
+	pub fn has_census( &self ) -> bool {
+		self.major > 0 || self.minor >= 10
+	}
+
+	/// Whether peers that speak this version understand manifest requests, which were added in 0.11.
+	pub fn has_manifests( &self ) -> bool {
+		self.major > 0 || self.minor >= 11
+	}
+
+	/// Whether peers that speak this version understand the given kind of request.
+	/// Requests that a peer doesn't understand make it think that we're sending malformed messages.
+	pub fn has_request( &self, request_type: RequestType ) -> bool {
+		match request_type {
+			RequestType::Summary => self.has_post_summaries(),
+			RequestType::PostSet | RequestType::EventSet => self.has_id_sets(),
+			RequestType::Manifests => self.has_manifests(),
+			_ => true
+		}
+	}
+}
+
+impl fmt::Display for ProtocolVersion {
+	fn fmt( &self, f: &mut fmt::Formatter<'_> ) -> fmt::Result {
+		write!(f, "{}.{}", self.major, self.minor)
+	}
+}
+
+impl IdSet {
+
+	/// The set of the ids `start` up to (but not including) `start + count`.
+	pub fn range( start: u64, count: u64 ) -> Self {
+		let mut set = Self::default();
+		if count > 0 {
+			set.ranges.push( IdRange { start, count } );
+		}
+		set
+	}
+
+	/// The set of the given ids, in any order.
+	/// Single ids that are missing between runs of ids are excepted from a range that spans them, because that takes less room than starting a new range.
+	pub fn from_ids( ids: impl IntoIterator<Item=u64> ) -> Self {
+		let mut ids: Vec<u64> = ids.into_iter().collect();
+		ids.sort_unstable();
+		ids.dedup();
+
+		let mut set = Self::default();
+		for id in ids {
+			match set.ranges.last_mut() {
+				Some(last) if last.start.checked_add( last.count ) == Some( id ) => last.count += 1,
+				Some(last) if last.start.checked_add( last.count ).and_then(|end| end.checked_add( 1 )) == Some( id ) => {
+					set.exceptions.push( id - 1 );
+					last.count += 2;
+				},
+				_ => set.ranges.push( IdRange { start: id, count: 1 } )
+			}
+		}
+		set
+	}
+
+	/// Leaves the given id out of the set.
+	pub fn except( &mut self, id: u64 ) {
+		if !self.contains( id ) { return }
+
+		let index = self.exceptions.binary_search( &id ).unwrap_err();
+		self.exceptions.insert( index, id );
+	}
+
+	pub fn contains( &self, id: u64 ) -> bool {
+		self.range_of( id ).is_some() && self.exceptions.binary_search( &id ).is_err()
+	}
+
+	/// The range that the given id falls in, if any.
+	fn range_of( &self, id: u64 ) -> Option<&IdRange> {
+		// No range compares as equal, so the search ends right after the last range that starts at or before the id.
+		let index = self.ranges.binary_search_by(|r| if r.start > id { Ordering::Greater } else { Ordering::Less }).unwrap_err();
+		let range = self.ranges.get( index.checked_sub( 1 )? )?;
+		if id - range.start < range.count { Some( range ) } else { None }
+	}
+
+	/// The number of ids in the set.
+	pub fn len( &self ) -> u64 {
+		let total = self.ranges.iter().fold( 0u64, |total, r| total.saturating_add( r.count ) );
+		total - self.exceptions.len() as u64
+	}
+
+	pub fn is_empty( &self ) -> bool {
+		self.len() == 0
+	}
+
+	/// The first id in the ranges, and the id after the last one.
+	/// Returns `None` for an empty set.
+	pub fn bounds( &self ) -> Option<(u64, u64)> {
+		let first = self.ranges.first()?;
+		let last = self.ranges.last()?;
+		Some(( first.start, last.start + last.count ))
+	}
+
+	pub fn ranges( &self ) -> &[IdRange] {
+		&self.ranges
+	}
+
+	/// The ids in the set, in order.
+	pub fn iter<'a>( &'a self ) -> impl Iterator<Item=u64> + 'a {
+		self.ranges.iter()
+			.flat_map(|r| r.start..(r.start + r.count))
+			.filter(move |id| self.exceptions.binary_search( id ).is_err())
+	}
+
+	/// Checks that a set that was received from a peer is well-formed, and doesn't make us go through more than `max_ids` ids.
+	/// The ranges need to be in order, without overlapping or touching each other, and the exceptions need to be in order and within the ranges.
+	pub fn validate( &self, max_ids: u64 ) -> Result<(), MessageMalformedError> {
+		let invalid = || MessageMalformedError::UnexpectedData("id set".to_owned());
+
+		let mut next_start = 0;
+		let mut total = 0u64;
+		for (i, range) in self.ranges.iter().enumerate() {
+			let end = range.start.checked_add( range.count ).ok_or_else( invalid )?;
+			if range.count == 0 || ( i > 0 && range.start <= next_start ) {
+				Err( invalid() )?
+			}
+			next_start = end;
+			total = total.saturating_add( range.count );
+		}
+		if total > max_ids {
+			Err( invalid() )?
+		}
+
+		let mut previous = None;
+		for exception in &self.exceptions {
+			if previous.map(|p| p >= *exception).unwrap_or(false) {
+				Err( invalid() )?
+			}
+			previous = Some( *exception );
+		}
+		// Every exception is within a range, so that `len` adds up.
+		if !self.exceptions.iter().all(|id| self.range_of( *id ).is_some()) {
+			Err( invalid() )?
+		}
+		Ok(())
+	}
+}
+
+/// Compresses a message of the swarm of a channel, before it is sealed.
+/// Returns `None` if the message is too small to be worth compressing, or doesn't get any smaller.
+/// Only peers that speak a version of the protocol that `has_compression` understand the compressed message.
+#[cfg(feature = "zstd")]
+pub fn compress_message( message: &[u8] ) -> Option<Vec<u8>> {
+	if message.len() < COMPRESSION_THRESHOLD {
+		return None
+	}
+
+	// Compressing into memory can only fail on invalid parameters.
+	let compressed = zstd::bulk::compress( message, COMPRESSION_LEVEL ).ok()?;
+	if 2 + compressed.len() >= message.len() {
+		return None
+	}
+
+	let mut framed = Vec::with_capacity( 2 + compressed.len() );
+	framed.push( MessageDirectionType::Compressed as u8 );
+	framed.push( CompressionType::Zstd as u8 );
+	framed.extend( compressed );
+	Some( framed )
+}
+
+/// Without the `zstd` feature, messages are never compressed, which every peer understands.
+/// Compressed messages can still be decompressed.
+#[cfg(not(feature = "zstd"))]
+pub fn compress_message( _message: &[u8] ) -> Option<Vec<u8>> {
+	None
+}
+
+/// Reverses `compress_message` for a message that has been opened, and leaves messages that aren't compressed as they are.
+/// A message that would decompress into more than `max_size` bytes is refused, so that a small message can't take up a lot of memory.
+pub fn decompress_message<'a>( message: Cow<'a, [u8]>, max_size: usize ) -> Result<Cow<'a, [u8]>, MessageMalformedError> {
+	if message[0] != MessageDirectionType::Compressed as u8 {
+		return Ok( message )
+	}
+	if message.len() < 2 {
+		Err(MessageMalformedError::MissingData("compression type".to_owned()))?
+	}
+
+	let compression: CompressionType = message[1].try_into()
+		.map_err(|_| MessageMalformedError::InvalidTypeId(message[1], "compression type".to_owned()))?;
+	let decompressed = match compression {
+		CompressionType::Zstd => decompress_zstd( &message[2..], max_size )
+			.ok_or_else(|| MessageMalformedError::DecompressionFailed("channel message".to_owned()))?
+	};
+
+	// A compressed message is never compressed again.
+	match decompressed.first() {
+		None => Err(MessageMalformedError::MissingData("direction type".to_owned()))?,
+		Some(d) if *d == MessageDirectionType::Compressed as u8 => Err(MessageMalformedError::UnexpectedData("compressed message".to_owned()))?,
+		Some(_) => {}
+	}
+	Ok( Cow::Owned( decompressed ) )
+}
+
+/// Decompresses a zstd frame, or returns `None` if it is invalid or would decompress into more than `max_size` bytes.
+/// This is done with ruzstd rather than the C library of zstd, so that it works on every target.
+fn decompress_zstd( compressed: &[u8], max_size: usize ) -> Option<Vec<u8>> {
+	let decoder = ruzstd::StreamingDecoder::new( compressed ).ok()?;
+
+	let mut decompressed = Vec::new();
+	decoder.take( max_size as u64 + 1 ).read_to_end( &mut decompressed ).ok()?;
+	if decompressed.len() > max_size {
+		return None
+	}
+	Some( decompressed )
+}
+
+/// Prepares a message of the swarm of a channel for sending.
+/// For private channels, everything but the direction type is encrypted with the channel `key`.
+/// Messages of public channels are sent as they are.
+pub fn seal_message( key: Option<&ChannelKey>, message: Vec<u8> ) -> Vec<u8> {
+	match key {
+		None => message,
+		Some(key) => {
+			let mut sealed = Vec::with_capacity( 1 + message.len() );
+			sealed.push( message[0] );
+			sealed.extend( key.encrypt( &message[1..] ) );
+			sealed
+		}
+	}
+}
+
+/// Reverses `seal_message` for a received message.
+pub fn open_message<'a>( key: Option<&ChannelKey>, message: &'a [u8] ) -> Result<Cow<'a, [u8]>, MessageMalformedError> {
+	if message.len() == 0 {
+		Err(MessageMalformedError::MissingData("direction type".to_owned()))?
+	}
+
+	match key {
+		None => Ok( Cow::Borrowed( message ) ),
+		Some(key) => {
+			let mut opened = vec![ message[0] ];
+			opened.extend( key.decrypt( &message[1..] )? );
+			Ok( Cow::Owned( opened ) )
+		}
+	}
+}
@@ -0,0 +1,200 @@
+use serde::{Serialize, Deserialize};
+
+use crate::{
+	byte_enum,
+	crypto::{HashCode, PublicKey, Signature}
+};
+
+
+
+/// The maximum number of characters of a tag.
+pub const TAG_MAX_LEN: usize = 32;
+/// The maximum number of tags of a single post.
+pub const TAGS_MAX_COUNT: usize = 16;
+/// The maximum number of characters of the name of a series.
+pub const SERIES_MAX_LEN: usize = 64;
+/// The maximum number of characters of a content warning.
+pub const CONTENT_WARNING_MAX_LEN: usize = 256;
+/// The bytes that the leaves and the inner nodes of a Merkle tree are hashed with, so that an inner node can't be passed off as a block.
+const MERKLE_LEAF_PREFIX: u8 = 0;
+const MERKLE_NODE_PREFIX: u8 = 1;
+
+byte_enum! {
+	/// The markup language that the content of a post is written in.
+	#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+	pub enum ContentFormat {
+		/// Plain text, in which paragraphs are separated by empty lines.
+		Plain = 0,
+		Markdown,
+		ReStructuredText
+	}
+}
+
+/// The manifest of a file: the hashes of the blocks that it is made of, in order.
+///
+/// A file is identified by the root of the Merkle tree over the hashes of its blocks, see `merkle_root`.
+/// That way, a manifest that any peer sends can be checked against the id of the file before a single block has been received, and every block against the manifest as it arrives.
+/// Files that were attached before 0.11 are identified by the hash of all of their data instead, which can only be checked once all of their blocks are there.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Attachment {
+	pub block_ids: Vec<HashCode>
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PostInfo {
+	pub publish_timestamp: u64,
+	pub tags: Vec<String>,
+	/// The time from which the post may be shown, in milliseconds since the UNIX epoch.
+	/// Subscribers store the post as soon as they receive it, but don't show it before then, so that a post can be released everywhere at the same time.
+	pub visible_from: Option<u64>,
+	/// The markup language of the content, and of all of its revisions.
+	pub format: ContentFormat,
+	/// The name of the series that the post is part of, if any.
+	pub series: Option<String>,
+	/// A warning that is shown instead of the content, until the reader chooses to see it.
+	pub content_warning: Option<String>
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Post {
+	pub id: u64,
+	/// The hash of the post `data`,
+	pub hash: HashCode,
+	pub signature: Signature,
+	pub meta: PostMeta
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PostMeta {
+	/// Some extra information about the post
+	pub info: PostInfo,
+	/// The id of the post that foregoes this one.
+	/// This is useful for obtaining older posts, as there is no 'index' for all posts of a blog.
+	pub content_hash: HashCode,
+	/// The ids of the files that this post holds as attachments.
+	/// E.g. photos, sound bites, video's, or basically anything.
+	pub attachment_ids: Vec<HashCode>,
+	/// The post that this post is a comment on, if any.
+	/// Comments are published on the commenter's own timeline, and link back to the post they comment on.
+	pub reply_to: Option<PostReference>
+}
+
+/// Identifies a post of any channel.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PostReference {
+	/// The address of the channel that the post was published in.
+	pub channel: PublicKey,
+	pub post_hash: HashCode
+}
+
+
+
+impl Default for ContentFormat {
+	fn default() -> Self {
+		Self::Plain
+	}
+}
+
+impl Attachment {
+
+	/// The root of the Merkle tree over the hashes of the blocks, which identifies the file.
+	pub fn merkle_root( &self ) -> HashCode {
+		merkle_root( &self.block_ids )
+	}
+
+	/// Whether this is the manifest of the file with the given id, which is the case if the id is its Merkle root.
+	/// Returns false for files that are identified by the hash of their data, which only their data can be checked against.
+	pub fn is_manifest_of( &self, file_id: &HashCode ) -> bool {
+		self.merkle_root() == *file_id
+	}
+}
+
+impl PostInfo {
+
+	/// Whether the post may be shown at the given time, in milliseconds since the UNIX epoch.
+	pub fn is_visible_at( &self, timestamp: u64 ) -> bool {
+		self.visible_from.map(|from| from <= timestamp).unwrap_or(true)
+	}
+}
+
+/// Calculates the root of the Merkle tree over the given block hashes.
+/// Pairs of nodes are hashed together level by level, and a node without a partner moves up a level as it is.
+/// The root of a file without any blocks is the hash of no data, just like the hash of its data would be.
+pub fn merkle_root( block_ids: &[HashCode] ) -> HashCode {
+	if block_ids.is_empty() {
+		return HashCode::generate( &[] )
+	}
+
+	let mut level: Vec<HashCode> = block_ids.iter()
+		.map(|id| HashCode::generate_from( &(MERKLE_LEAF_PREFIX, id) ))
+		.collect();
+	while level.len() > 1 {
+		level = level.chunks( 2 ).map(|pair| match pair {
+			[left, right] => HashCode::generate_from( &(MERKLE_NODE_PREFIX, left, right) ),
+			_ => pair[0].clone()
+		}).collect();
+	}
+	level.remove( 0 )
+}
+
+/// Brings the tags typed by a user into their normal form: trimmed and lowercase, without duplicates.
+pub fn normalize_tags<'a>( tags: impl Iterator<Item=&'a str> ) -> Vec<String> {
+	let mut normalized: Vec<String> = Vec::new();
+	for tag in tags {
+		let tag = tag.trim().to_lowercase();
+		if !tag.is_empty() && !normalized.contains( &tag ) {
+			normalized.push( tag );
+		}
+	}
+	normalized
+}
+
+/// Checks whether the information of a post follows the rules.
+/// Returns a description of the first rule that is broken.
+pub fn check_info( info: &PostInfo ) -> Result<(), String> {
+
+	check_tags( &info.tags )?;
+
+	if let Some(series) = &info.series {
+		if series.trim().is_empty() {
+			return Err( "the name of a series can not be empty".to_owned() )
+		}
+		if series.chars().count() > SERIES_MAX_LEN {
+			return Err( format!("the name of a series can have at most {} characters", SERIES_MAX_LEN) )
+		}
+	}
+	if let Some(warning) = &info.content_warning {
+		if warning.trim().is_empty() {
+			return Err( "a content warning can not be empty".to_owned() )
+		}
+		if warning.chars().count() > CONTENT_WARNING_MAX_LEN {
+			return Err( format!("a content warning can have at most {} characters", CONTENT_WARNING_MAX_LEN) )
+		}
+	}
+
+	Ok(())
+}
+
+/// Checks whether the tags of a post follow the rules.
+/// Tags need to be normalized, may only contain letters, digits, dashes and underscores, and are limited in length and count.
+/// Returns a description of the first rule that is broken.
+pub fn check_tags( tags: &[String] ) -> Result<(), String> {
+
+	if tags.len() > TAGS_MAX_COUNT {
+		return Err( format!("a post can have at most {} tags", TAGS_MAX_COUNT) )
+	}
+
+	for tag in tags {
+		if tag.is_empty() {
+			return Err( "tags can not be empty".to_owned() )
+		}
+		if tag.chars().count() > TAG_MAX_LEN {
+			return Err( format!("tags can have at most {} characters", TAG_MAX_LEN) )
+		}
+		if !tag.chars().all(|c| (c.is_alphanumeric() && !c.is_uppercase()) || c == '-' || c == '_') {
+			return Err( format!("tag \"{}\" contains characters other than lowercase letters, digits, dashes and underscores", tag) )
+		}
+	}
+
+	Ok(())
+}
@@ -0,0 +1,328 @@
+//! The validation rules that every node applies to the data it receives.
+//! Anything that fails these rules is considered to be malformed, and the peer that sent it is considered to be malicious.
+
+use std::{
+	fmt,
+	str::Utf8Error
+};
+
+
+use crate::{
+	crypto::{HashCode, PublicKey},
+	event::{AcceptOwnershipEventMessage, ChannelCreateEventMessage, CloseChannelEventMessage, CommentEventData, ForgetPostEventData, ForgetPostRequest, ModerateCommentsEventMessage, ReactionEventData, RevisePostEventData, TransferOwnershipEventMessage, COMMENT_MAX_LEN, PINNED_POSTS_MAX, REACTION_MAX_LEN},
+	message::*,
+	post::*
+};
+
+
+
+/// The purpose used for the signatures of Quartznet data.
+pub const SIGNATURE_PURPOSE: u32 = 777;
+
+#[derive(Debug)]
+pub enum MessageMalformedError {
+	/// When a deserialization with bincode failed.
+	DeserializationIssue( bincode::Error, String ),
+	/// When expecting a boolean but something other than a 1 or 0 was given.
+	InvalidBoolean( u8, String ),
+	InvalidHash( String ),
+	InvalidSignature( String ),
+	/// When some sort of type is give as a byte, and that byte uses an unknown ID.
+	InvalidTypeId( u8, String ),
+	/// When reading a string failed.
+	InvalidUtf8( Utf8Error, String ),
+	/// When the message of a private channel couldn't be decrypted with the channel key.
+	DecryptionFailed( String ),
+	/// When a compressed message couldn't be decompressed, or would decompress into too much data.
+	DecompressionFailed( String ),
+	/// When a event message appeared to be way to new.
+	InvalidEventId( u64 ),
+	/// When the message turns out to be too small for the data is should contain.
+	MissingData( String ),
+	/// When the information of a post, like its tags, breaks the rules.
+	InvalidPostInfo( String ),
+	/// When the message contains more data than was asked for.
+	UnexpectedData( String ),
+	UnknownPublisher( PublicKey )
+}
+
+pub trait Signature {
+	fn verify_hash( &self, hash: &HashCode, public_key: &PublicKey ) -> bool;
+}
+
+
+
+impl Signature for crate::crypto::Signature {
+	fn verify_hash( &self, hash: &HashCode, public_key: &PublicKey ) -> bool {
+		self.verify( SIGNATURE_PURPOSE, &hash.to_bytes(), public_key )
+	}
+}
+
+/// Checks whether the hash and the signature of an `UpdateChannelProfile` event are valid for the channel with the given `public_key`.
+pub fn validate_channel_profile_update( msg: &UpdateChannelProfileEventMessage, public_key: &PublicKey ) -> Result<(), MessageMalformedError> {
+
+	if HashCode::generate_from( &msg.profile ) != msg.hash {
+		Err(MessageMalformedError::InvalidHash("upgrade profile event message".to_owned()))?;
+	}
+
+	if !msg.signature.verify_hash( &msg.hash, public_key ) {
+		Err(MessageMalformedError::InvalidSignature("upgrade profile event message".to_owned()))?
+	}
+
+	Ok(())
+}
+
+/// Checks whether the genesis event was signed by the owner of the channel with the given `public_key`.
+pub fn validate_channel_genesis( msg: &ChannelCreateEventMessage, public_key: &PublicKey ) -> Result<(), MessageMalformedError> {
+
+	if HashCode::generate_from( &msg.data ) != msg.hash {
+		Err(MessageMalformedError::InvalidHash("channel create event message".to_owned()))?;
+	}
+
+	if !msg.signature.verify_hash( &msg.hash, public_key ) {
+		Err(MessageMalformedError::InvalidSignature("channel create event message".to_owned()))?
+	}
+
+	Ok(())
+}
+
+/// Checks whether the hash and the signature of an `UpdatePublisherList` event are valid for the channel with the given `public_key`.
+pub fn validate_publisher_list_update( msg: &UpdatePublisherListEventMessage, public_key: &PublicKey ) -> Result<(), MessageMalformedError> {
+
+	if HashCode::generate_from( &msg.list ) != msg.hash {
+		Err(MessageMalformedError::InvalidHash("update publisher list event message".to_owned()))?;
+	}
+
+	if !msg.signature.verify_hash( &msg.hash, public_key ) {
+		Err(MessageMalformedError::InvalidSignature("update publisher list event message".to_owned()))?
+	}
+
+	Ok(())
+}
+
+/// Checks whether the hash and the signature of an `UpdatePinnedPosts` event are valid for the channel with the given `public_key`, and whether it doesn't pin too many posts.
+pub fn validate_pinned_posts_update( msg: &UpdatePinnedPostsEventMessage, public_key: &PublicKey ) -> Result<(), MessageMalformedError> {
+
+	if msg.pinned.post_ids.len() > PINNED_POSTS_MAX {
+		Err(MessageMalformedError::UnexpectedData("update pinned posts event message".to_owned()))?;
+	}
+
+	if HashCode::generate_from( &msg.pinned ) != msg.hash {
+		Err(MessageMalformedError::InvalidHash("update pinned posts event message".to_owned()))?;
+	}
+
+	if !msg.signature.verify_hash( &msg.hash, public_key ) {
+		Err(MessageMalformedError::InvalidSignature("update pinned posts event message".to_owned()))?
+	}
+
+	Ok(())
+}
+
+/// Checks whether the hash and the signature of a `ModerateComments` event are valid for the owner with the given `public_key`.
+pub fn validate_comment_moderation( msg: &ModerateCommentsEventMessage, public_key: &PublicKey ) -> Result<(), MessageMalformedError> {
+
+	if HashCode::generate_from( &msg.moderation ) != msg.hash {
+		Err(MessageMalformedError::InvalidHash("moderate comments event message".to_owned()))?;
+	}
+
+	if !msg.signature.verify_hash( &msg.hash, public_key ) {
+		Err(MessageMalformedError::InvalidSignature("moderate comments event message".to_owned()))?
+	}
+
+	Ok(())
+}
+
+/// Checks whether the hash and the signature of a `Close` event are valid for the owner with the given `public_key`.
+pub fn validate_channel_closing( msg: &CloseChannelEventMessage, public_key: &PublicKey ) -> Result<(), MessageMalformedError> {
+
+	if HashCode::generate_from( &msg.closing ) != msg.hash {
+		Err(MessageMalformedError::InvalidHash("close channel event message".to_owned()))?;
+	}
+
+	if !msg.signature.verify_hash( &msg.hash, public_key ) {
+		Err(MessageMalformedError::InvalidSignature("close channel event message".to_owned()))?
+	}
+
+	Ok(())
+}
+
+/// Checks whether the hash and the signature of a `TransferOwnership` event are valid for the owner with the given `public_key`.
+pub fn validate_ownership_transfer( msg: &TransferOwnershipEventMessage, public_key: &PublicKey ) -> Result<(), MessageMalformedError> {
+
+	if HashCode::generate_from( &msg.transfer ) != msg.hash {
+		Err(MessageMalformedError::InvalidHash("transfer ownership event message".to_owned()))?;
+	}
+
+	if !msg.signature.verify_hash( &msg.hash, public_key ) {
+		Err(MessageMalformedError::InvalidSignature("transfer ownership event message".to_owned()))?
+	}
+
+	Ok(())
+}
+
+/// Checks whether an `AcceptOwnership` event accepts the transfer with the given hash, and whether it was signed by the new owner named in that transfer.
+pub fn validate_ownership_acceptance( msg: &AcceptOwnershipEventMessage, transfer_hash: &HashCode, new_owner: &PublicKey ) -> Result<(), MessageMalformedError> {
+
+	if HashCode::generate_from( &msg.acceptance ) != msg.hash || msg.acceptance.transfer_hash != *transfer_hash {
+		Err(MessageMalformedError::InvalidHash("accept ownership event message".to_owned()))?;
+	}
+
+	if !msg.signature.verify_hash( &msg.hash, new_owner ) {
+		Err(MessageMalformedError::InvalidSignature("accept ownership event message".to_owned()))?
+	}
+
+	Ok(())
+}
+
+/// Checks whether the hash of the post meta data is correct, whether the post was signed by the given publisher, and whether its information follows the rules.
+pub fn validate_post( post: &Post, publisher: &PublicKey ) -> Result<(), MessageMalformedError> {
+
+	check_info( &post.meta.info ).map_err( MessageMalformedError::InvalidPostInfo )?;
+
+	if HashCode::generate_from( &post.meta ) != post.hash {
+		Err(MessageMalformedError::InvalidHash("post meta".to_owned()))?
+	}
+
+	if !post.signature.verify_hash( &post.hash, publisher ) {
+		Err(MessageMalformedError::InvalidSignature("post".to_owned()))?
+	}
+
+	Ok(())
+}
+
+/// Checks whether a revision was made for the given post, whether it was signed by the given publisher, and whether the content belongs to it.
+/// A revision may change the tags, series and content warning of the post, but not when or in which format it was published.
+pub fn validate_post_revision( data: &RevisePostEventData, post: &Post, publisher: &PublicKey ) -> Result<(), MessageMalformedError> {
+
+	if data.revision.post_hash != post.hash || data.post_id != post.id {
+		Err(MessageMalformedError::InvalidHash("post revision post".to_owned()))?
+	}
+
+	let info = &data.revision.info;
+	check_info( info ).map_err( MessageMalformedError::InvalidPostInfo )?;
+	if info.publish_timestamp != post.meta.info.publish_timestamp ||
+		info.visible_from != post.meta.info.visible_from ||
+		info.format != post.meta.info.format
+	{
+		Err(MessageMalformedError::InvalidPostInfo("a revision can't change the publish time, release time or format".to_owned()))?
+	}
+
+	if HashCode::generate_from( &data.revision ) != data.hash {
+		Err(MessageMalformedError::InvalidHash("post revision".to_owned()))?
+	}
+
+	if !data.signature.verify_hash( &data.hash, publisher ) {
+		Err(MessageMalformedError::InvalidSignature("post revision".to_owned()))?
+	}
+
+	if HashCode::generate( data.content.as_bytes() ) != data.revision.content_hash {
+		Err(MessageMalformedError::InvalidHash("post revision content".to_owned()))?
+	}
+
+	Ok(())
+}
+
+/// Checks whether a request to forget the given post was signed by the given publisher.
+pub fn validate_forget_post( data: &ForgetPostEventData, post: &Post, publisher: &PublicKey ) -> Result<(), MessageMalformedError> {
+
+	let request = ForgetPostRequest { post_hash: post.hash.clone() };
+	if data.post_id != post.id || HashCode::generate_from( &request ) != data.hash {
+		Err(MessageMalformedError::InvalidHash("forget post request".to_owned()))?
+	}
+
+	if !data.signature.verify_hash( &data.hash, publisher ) {
+		Err(MessageMalformedError::InvalidSignature("forget post request".to_owned()))?
+	}
+
+	Ok(())
+}
+
+/// Checks whether a comment was made on the given post, whether it was signed by its author, and whether its content isn't empty or too long.
+pub fn validate_comment( data: &CommentEventData, post: &Post ) -> Result<(), MessageMalformedError> {
+
+	if data.comment.post_hash != post.hash || data.post_id != post.id {
+		Err(MessageMalformedError::InvalidHash("comment post".to_owned()))?
+	}
+
+	if data.comment.content.trim().is_empty() {
+		Err(MessageMalformedError::MissingData("comment content".to_owned()))?
+	}
+	if data.comment.content.len() > COMMENT_MAX_LEN {
+		Err(MessageMalformedError::UnexpectedData("comment content".to_owned()))?
+	}
+
+	if HashCode::generate_from( &data.comment ) != data.hash {
+		Err(MessageMalformedError::InvalidHash("comment".to_owned()))?
+	}
+
+	if !data.signature.verify_hash( &data.hash, &data.author ) {
+		Err(MessageMalformedError::InvalidSignature("comment".to_owned()))?
+	}
+
+	Ok(())
+}
+
+/// Checks whether a reaction was made to the given post, whether it was signed by its signer, and whether its kind isn't empty or too long.
+pub fn validate_reaction( data: &ReactionEventData, post: &Post ) -> Result<(), MessageMalformedError> {
+
+	if data.reaction.post_hash != post.hash || data.post_id != post.id {
+		Err(MessageMalformedError::InvalidHash("reaction post".to_owned()))?
+	}
+
+	let kind = &data.reaction.kind;
+	if kind.is_empty() || kind.len() > REACTION_MAX_LEN || kind.chars().any(char::is_whitespace) {
+		Err(MessageMalformedError::InvalidPostInfo("the kind of a reaction needs to be short, without whitespace".to_owned()))?
+	}
+
+	if HashCode::generate_from( &data.reaction ) != data.hash {
+		Err(MessageMalformedError::InvalidHash("reaction".to_owned()))?
+	}
+
+	if !data.signature.verify_hash( &data.hash, &data.signer ) {
+		Err(MessageMalformedError::InvalidSignature("reaction".to_owned()))?
+	}
+
+	Ok(())
+}
+
+/// Checks whether the content belongs to the post with the given meta data.
+pub fn validate_post_content( meta: &PostMeta, content: &str ) -> Result<(), MessageMalformedError> {
+
+	if HashCode::generate( content.as_bytes() ) != meta.content_hash {
+		Err(MessageMalformedError::InvalidHash("post content".to_owned()))?
+	}
+
+	Ok(())
+}
+
+
+
+impl fmt::Display for MessageMalformedError {
+	fn fmt( &self, f: &mut fmt::Formatter<'_> ) -> fmt::Result {
+		match self {
+			Self::DecryptionFailed(desc) => write!(f, "unable to decrypt {}", desc),
+			Self::DecompressionFailed(desc) => write!(f, "unable to decompress {}", desc),
+			Self::DeserializationIssue(e, desc) => write!(f, "deserialization issue while parsing {}: {}", desc, e),
+			Self::InvalidBoolean(id, desc) => write!(f, "invalid boolean found for {}: {}", desc, id),
+			Self::InvalidEventId(id) => write!(f, "invalid event ID: {}", id),
+			Self::InvalidHash(desc) => write!(f, "invalid checksum for {}", desc),
+			Self::InvalidSignature(desc) => write!(f, "signature verification failed for {}", desc),
+			Self::InvalidPostInfo(desc) => write!(f, "invalid post info: {}", desc),
+			Self::InvalidTypeId(id, desc) => write!(f, "invalid type id found for {}: {}", desc, id),
+			Self::InvalidUtf8(e, desc) => write!(f, "invalid UTF-8 for {}: {}", desc, e),
+			Self::MissingData(desc) => write!(f, "missing data for {}", desc),
+			Self::UnexpectedData(desc) => write!(f, "unexpected data for {}", desc),
+			Self::UnknownPublisher(address) => write!(f, "unknown publisher address: {}", address.to_string())
+		}
+	}
+}
+
+impl std::error::Error for MessageMalformedError {
+	fn source( &self ) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::DeserializationIssue(e, _) => Some(e),
+			Self::InvalidUtf8(e, _) => Some(e),
+			other => None
+		}
+	}
+}
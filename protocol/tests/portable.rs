@@ -0,0 +1,65 @@
+//! Checks that the portable keys, hashes and signatures are encoded just like the ones of gnunet, so that frontends without gnunet understand the nodes.
+
+use std::convert::TryInto;
+
+use gnunet::{
+	crypto::HashCode,
+	identity::{KeyType, PrivateKey}
+};
+use quartz_net_protocol::{
+	crypto::portable,
+	validation::SIGNATURE_PURPOSE
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+
+
+fn encode<T: Serialize>( value: &T ) -> Vec<u8> {
+	bincode::serialize( value ).unwrap()
+}
+
+/// Decodes what gnunet encoded into the portable type, and checks that it is encoded into the same bytes again.
+fn convert<T: Serialize, P: Serialize + DeserializeOwned>( value: &T ) -> P {
+	let bytes = encode( value );
+	let converted: P = bincode::deserialize( &bytes ).unwrap();
+	assert_eq!(encode( &converted ), bytes);
+	converted
+}
+
+#[test]
+fn hashes_are_the_same() {
+	for data in &[&b""[..], b"quartznet", &[0xff; 1000]] {
+		let hash = HashCode::generate( data );
+		let portable: portable::HashCode = convert( &hash );
+		assert_eq!(portable, portable::HashCode::generate( data ));
+		assert_eq!(portable.to_string(), hash.to_string());
+		assert_eq!(portable::HashCode::from_string( &hash.to_string() ), Some( portable ));
+	}
+
+	let value = (1u8, "value", vec![2u64, 3]);
+	assert_eq!(encode( &portable::HashCode::generate_from( &value ) ), encode( &HashCode::generate_from( &value ) ));
+}
+
+#[test]
+fn keys_are_the_same() {
+	let public_key = PrivateKey::generate( KeyType::Eddsa ).extract_public();
+	let portable: portable::PublicKey = convert( &public_key );
+	assert_eq!(portable.to_string(), public_key.to_string());
+	assert_eq!(portable::PublicKey::from_string( &public_key.to_string() ), Some( portable ));
+}
+
+#[test]
+fn signatures_of_gnunet_are_verified() {
+	let private_key = PrivateKey::generate( KeyType::Eddsa );
+	let public_key: portable::PublicKey = convert( &private_key.extract_public() );
+	let hash = HashCode::generate( b"signed" );
+	let raw_hash = encode( &hash );
+	let signature: portable::Signature = convert( &private_key.sign( (&*raw_hash).try_into().unwrap(), SIGNATURE_PURPOSE ).unwrap() );
+
+	assert!(signature.verify( SIGNATURE_PURPOSE, &hash.to_bytes(), &public_key ));
+	assert!(!signature.verify( SIGNATURE_PURPOSE + 1, &hash.to_bytes(), &public_key ));
+	assert!(!signature.verify( SIGNATURE_PURPOSE, &HashCode::generate( b"other" ).to_bytes(), &public_key ));
+
+	let other_key: portable::PublicKey = convert( &PrivateKey::generate( KeyType::Eddsa ).extract_public() );
+	assert!(!signature.verify( SIGNATURE_PURPOSE, &hash.to_bytes(), &other_key ));
+}
@@ -0,0 +1,173 @@
+//! Checks that nodes settle on the same channel profile when it is changed at the same time, whatever order they receive the changes in.
+//!
+//! The owner of a channel can change its profile on two devices before either one has seen the change of the other, which gives two profiles with the same revision.
+
+use std::cmp::Ordering;
+
+use quartz_net_protocol::{
+	crypto::HashCode,
+	message::*
+};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+
+
+/// The profile of a channel as a node sees it, applying updates the way that the node stores them.
+#[derive(Default)]
+struct Node {
+	current: Option<ChannelProfile>,
+	/// The profiles that lost to one with the same revision.
+	conflicts: Vec<ChannelProfile>
+}
+
+
+
+impl Node {
+
+	fn apply( &mut self, profile: &ChannelProfile ) {
+		let current = match &self.current {
+			None => {
+				self.current = Some( profile.clone() );
+				return
+			},
+			Some(c) => c.clone()
+		};
+
+		let stored = profile.supersedes( &current );
+		if current.base.revision == profile.base.revision && profile.compare( &current ) != Ordering::Equal {
+			self.conflicts.push( if stored { current } else { profile.clone() } );
+		}
+		if stored {
+			self.current = Some( profile.clone() );
+		}
+	}
+
+	fn title( &self ) -> &str {
+		&self.current.as_ref().unwrap().base.title
+	}
+}
+
+
+
+fn profile( revision: u64, title: &str, timestamp: Option<u64> ) -> ChannelProfile {
+	ChannelProfile {
+		base: Profile {
+			revision,
+			title: title.to_owned(),
+			description: String::new(),
+			profile_picture: None
+		},
+		stylesheet: None,
+		timestamp
+	}
+}
+
+/// Applies the updates in the given order to a new node.
+fn node_after( updates: &[&ChannelProfile] ) -> Node {
+	let mut node = Node::default();
+	for update in updates {
+		node.apply( update );
+	}
+	node
+}
+
+fn hash( profile: &ChannelProfile ) -> String {
+	HashCode::generate_from( profile ).to_string()
+}
+
+#[test]
+fn later_timestamp_wins() {
+	let laptop = profile( 4, "From the laptop", Some( 1000 ) );
+	let phone = profile( 4, "From the phone", Some( 2000 ) );
+
+	for node in &[node_after( &[&laptop, &phone] ), node_after( &[&phone, &laptop] )] {
+		assert_eq!(node.title(), "From the phone");
+		assert_eq!(node.conflicts.len(), 1);
+		assert_eq!(node.conflicts[0].base.title, "From the laptop");
+	}
+}
+
+#[test]
+fn hash_breaks_ties() {
+	let first = profile( 4, "First", Some( 1000 ) );
+	let second = profile( 4, "Second", Some( 1000 ) );
+	let expected = if hash( &first ) > hash( &second ) { "First" } else { "Second" };
+
+	for node in &[node_after( &[&first, &second] ), node_after( &[&second, &first] )] {
+		assert_eq!(node.title(), expected);
+		assert_eq!(node.conflicts.len(), 1);
+	}
+}
+
+#[test]
+fn higher_revision_wins_regardless_of_timestamp() {
+	// The clock of a device may be behind, but a device that has seen revision 4 always writes revision 5.
+	let old = profile( 4, "Old", Some( 5000 ) );
+	let new = profile( 5, "New", Some( 1000 ) );
+
+	for node in &[node_after( &[&old, &new] ), node_after( &[&new, &old] )] {
+		assert_eq!(node.title(), "New");
+		// An outdated profile is no conflict.
+		assert!(node.conflicts.is_empty());
+	}
+}
+
+#[test]
+fn profile_without_timestamp_loses() {
+	let legacy = profile( 4, "Legacy", None );
+	let timestamped = profile( 4, "Timestamped", Some( 0 ) );
+
+	for node in &[node_after( &[&legacy, &timestamped] ), node_after( &[&timestamped, &legacy] )] {
+		assert_eq!(node.title(), "Timestamped");
+		assert_eq!(node.conflicts.len(), 1);
+	}
+}
+
+#[test]
+fn same_profile_is_no_conflict() {
+	// An update can be received more than once, from different peers.
+	let update = profile( 4, "Once", Some( 1000 ) );
+	let node = node_after( &[&update, &update.clone(), &update] );
+
+	assert_eq!(node.title(), "Once");
+	assert!(node.conflicts.is_empty());
+	assert_eq!(update.compare( &update.clone() ), Ordering::Equal);
+	assert!(!update.supersedes( &update.clone() ));
+}
+
+#[test]
+fn comparison_is_antisymmetric() {
+	let mut rng = StdRng::seed_from_u64( 1 );
+
+	for _ in 0..200 {
+		let a = profile( rng.gen_range( 0..3 ), &rng.gen::<u8>().to_string(), if rng.gen() { Some( rng.gen_range( 0..3 ) ) } else { None } );
+		let b = profile( rng.gen_range( 0..3 ), &rng.gen::<u8>().to_string(), if rng.gen() { Some( rng.gen_range( 0..3 ) ) } else { None } );
+
+		assert_eq!(a.compare( &b ), b.compare( &a ).reverse());
+		assert!(!(a.supersedes( &b ) && b.supersedes( &a )));
+	}
+}
+
+#[test]
+fn nodes_converge_in_any_order() {
+	let mut rng = StdRng::seed_from_u64( 2 );
+
+	// Several devices that each changed the profile a few times, with clocks that don't agree.
+	let mut updates = Vec::new();
+	for device in 0..4 {
+		for revision in 1..=3 {
+			let timestamp = if device == 0 { None } else { Some( rng.gen_range( 0..4 ) ) };
+			updates.push( profile( revision, &format!("Device {} revision {}", device, revision), timestamp ) );
+		}
+	}
+	let expected = node_after( &updates.iter().collect::<Vec<_>>() );
+
+	for _ in 0..100 {
+		let mut order: Vec<&ChannelProfile> = updates.iter().collect();
+		order.shuffle( &mut rng );
+		let node = node_after( &order );
+
+		assert_eq!(node.title(), expected.title());
+		assert_eq!(node.current.as_ref().map(hash), expected.current.as_ref().map(hash));
+	}
+}
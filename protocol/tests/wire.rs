@@ -0,0 +1,907 @@
+//! Pins down the wire encoding of every message and event, so that a change to it can't go unnoticed.
+//!
+//! Peers of different releases need to understand each other, so the bytes that a value is encoded into may never change within a major protocol version.
+//! The golden vectors spell out those bytes field by field.
+//! The keys, hashes and signatures of gnunet are included as whatever gnunet encodes them into, as their encoding is up to gnunet.
+//! The round trip tests feed generated values through the encoder and the decoder, mostly to cover the hand-written serializers.
+
+use std::{
+	borrow::Cow,
+	collections::HashMap,
+	convert::{TryFrom, TryInto}
+};
+
+use gnunet::{
+	crypto::HashCode,
+	identity::{KeyType, PrivateKey, PublicKey, Signature}
+};
+use quartz_net_protocol::{
+	event::*,
+	message::*,
+	post::*,
+	validation::SIGNATURE_PURPOSE
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{de::DeserializeOwned, Serialize};
+
+
+
+/// The number of values that every round trip test generates.
+const ROUND_TRIPS: usize = 200;
+/// The characters that generated strings are made of, including some that take more than one byte.
+const CHARACTERS: &[char] = &['a', 'Z', '0', '9', ' ', '-', '_', '\n', 'é', 'ß', '語', '🦀'];
+
+/// Builds the bytes that a value is expected to be encoded into, in the way that bincode lays out its fields.
+struct Wire( Vec<u8> );
+
+
+
+impl Wire {
+
+	fn new() -> Self {
+		Self( Vec::new() )
+	}
+
+	fn u8( mut self, value: u8 ) -> Self {
+		self.0.push( value );
+		self
+	}
+
+	fn u16( mut self, value: u16 ) -> Self {
+		self.0.extend( &value.to_le_bytes() );
+		self
+	}
+
+	fn u32( mut self, value: u32 ) -> Self {
+		self.0.extend( &value.to_le_bytes() );
+		self
+	}
+
+	fn u64( mut self, value: u64 ) -> Self {
+		self.0.extend( &value.to_le_bytes() );
+		self
+	}
+
+	fn bool( self, value: bool ) -> Self {
+		self.u8( value as u8 )
+	}
+
+	/// The length of a sequence, a string or a map.
+	fn len( self, len: usize ) -> Self {
+		self.u64( len as u64 )
+	}
+
+	fn str( mut self, value: &str ) -> Self {
+		self = self.len( value.len() );
+		self.0.extend( value.as_bytes() );
+		self
+	}
+
+	fn none( self ) -> Self {
+		self.u8( 0 )
+	}
+
+	fn some( self ) -> Self {
+		self.u8( 1 )
+	}
+
+	fn bytes( mut self, value: &[u8] ) -> Self {
+		self.0.extend( value );
+		self
+	}
+
+	/// A value of which the encoding isn't pinned down here, like a key or a hash of gnunet.
+	fn value<T: Serialize>( self, value: &T ) -> Self {
+		self.bytes( &encode( value ) )
+	}
+}
+
+
+
+fn encode<T: Serialize>( value: &T ) -> Vec<u8> {
+	bincode::serialize( value ).unwrap()
+}
+
+fn decode<T: DeserializeOwned>( bytes: &[u8] ) -> T {
+	bincode::deserialize( bytes ).unwrap()
+}
+
+/// Asserts that a value is encoded into exactly the expected bytes, and that decoding those bytes gives a value that is encoded the same way.
+fn assert_wire<T: Serialize + DeserializeOwned>( value: &T, expected: Wire ) {
+	let bytes = encode( value );
+	assert_eq!(bytes, expected.0);
+	assert_eq!(encode( &decode::<T>( &bytes ) ), bytes);
+}
+
+/// Asserts that encoding a value, decoding it and encoding it again gives the same bytes, and that all of them are used.
+fn assert_round_trip<T: Serialize + DeserializeOwned>( value: &T ) -> T {
+	let bytes = encode( value );
+	let decoded: T = decode( &bytes );
+	assert_eq!(encode( &decoded ), bytes);
+	assert_eq!(bincode::serialized_size( &decoded ).unwrap() as usize, bytes.len());
+	decoded
+}
+
+fn hash( seed: &str ) -> HashCode {
+	HashCode::generate( seed.as_bytes() )
+}
+
+fn private_key() -> PrivateKey {
+	PrivateKey::generate( KeyType::Eddsa )
+}
+
+fn sign( private_key: &PrivateKey, hash: &HashCode ) -> Signature {
+	let raw_hash = encode( hash );
+	private_key.sign( (&*raw_hash).try_into().unwrap(), SIGNATURE_PURPOSE ).unwrap()
+}
+
+fn info() -> PostInfo {
+	PostInfo {
+		publish_timestamp: 1_600_000_000_000,
+		tags: vec!["rust".to_owned(), "gnunet".to_owned()],
+		visible_from: Some( 1_600_000_060_000 ),
+		format: ContentFormat::Markdown,
+		series: None,
+		content_warning: Some( "spoilers".to_owned() )
+	}
+}
+
+fn info_wire() -> Wire {
+	Wire::new()
+		.u64( 1_600_000_000_000 )
+		.len( 2 ).str("rust").str("gnunet")
+		.some().u64( 1_600_000_060_000 )
+		.u32( 1 )
+		.none()
+		.some().str("spoilers")
+}
+
+fn post( private_key: &PrivateKey ) -> Post {
+	let hash = hash("post");
+	Post {
+		id: 7,
+		signature: sign( private_key, &hash ),
+		hash,
+		meta: PostMeta {
+			info: info(),
+			content_hash: self::hash("content"),
+			attachment_ids: vec![ self::hash("attachment") ],
+			reply_to: Some( PostReference {
+				channel: private_key.extract_public(),
+				post_hash: self::hash("parent")
+			})
+		}
+	}
+}
+
+fn post_wire( post: &Post ) -> Wire {
+	let reply_to = post.meta.reply_to.as_ref().unwrap();
+	Wire::new()
+		.u64( 7 )
+		.value( &post.hash )
+		.value( &post.signature )
+		.bytes( &info_wire().0 )
+		.value( &post.meta.content_hash )
+		.len( 1 ).value( &post.meta.attachment_ids[0] )
+		.some().value( &reply_to.channel ).value( &reply_to.post_hash )
+}
+
+fn profile() -> Profile {
+	Profile {
+		revision: 3,
+		title: "Hi".to_owned(),
+		description: "abc".to_owned(),
+		profile_picture: None
+	}
+}
+
+fn random_string( rng: &mut StdRng, max_bytes: usize ) -> String {
+	let mut string = String::new();
+	let len = rng.gen_range( 0..=max_bytes );
+	loop {
+		let c = CHARACTERS[ rng.gen_range( 0..CHARACTERS.len() ) ];
+		if string.len() + c.len_utf8() > len { break }
+		string.push( c );
+	}
+	string
+}
+
+fn random_hash( rng: &mut StdRng ) -> HashCode {
+	HashCode::generate( &rng.gen::<[u8; 32]>() )
+}
+
+fn random_info( rng: &mut StdRng ) -> PostInfo {
+	let tag_count = rng.gen_range( 0..=TAGS_MAX_COUNT );
+	PostInfo {
+		publish_timestamp: rng.gen(),
+		tags: (0..tag_count).map(|_| random_string( rng, TAG_MAX_LEN )).collect(),
+		visible_from: if rng.gen() { Some( rng.gen() ) } else { None },
+		format: ContentFormat::try_from( rng.gen_range( 0..3u8 ) ).unwrap(),
+		series: if rng.gen() { Some( random_string( rng, SERIES_MAX_LEN ) ) } else { None },
+		content_warning: if rng.gen() { Some( random_string( rng, CONTENT_WARNING_MAX_LEN ) ) } else { None }
+	}
+}
+
+
+
+#[test]
+fn hello_and_goodbye() {
+	assert_wire( &PROTOCOL_VERSION, Wire::new().u16( 0 ).u16( 11 ) );
+	assert_wire( &HelloMessage { version: ProtocolVersion { major: 1, minor: 258 } }, Wire::new().u16( 1 ).u16( 258 ) );
+
+	assert_wire( &GoodbyeMessage { parent: None }, Wire::new().none() );
+	let parent = private_key().extract_public();
+	assert_wire( &GoodbyeMessage { parent: Some( parent.clone() ) }, Wire::new().some().value( &parent ) );
+}
+
+#[test]
+fn topology() {
+	let ancestor = private_key().extract_public();
+	assert_wire( &TopologyMessage::Ancestors( Vec::new() ), Wire::new().u32( 0 ).len( 0 ) );
+	assert_wire( &TopologyMessage::Ancestors( vec![ancestor.clone()] ), Wire::new().u32( 0 ).len( 1 ).value( &ancestor ) );
+	assert_wire( &TopologyMessage::Capacity { free: 3, other_parents: true }, Wire::new().u32( 1 ).u16( 3 ).bool( true ) );
+}
+
+#[test]
+fn census() {
+	assert_wire( &CensusMessage::Subtree( 1500 ), Wire::new().u32( 0 ).u32( 1500 ) );
+	assert_wire( &CensusMessage::Swarm( 42 ), Wire::new().u32( 1 ).u32( 42 ) );
+}
+
+#[test]
+fn byte_enums() {
+	assert_eq!(MessageDirectionType::Event as u8, 0);
+	assert_eq!(MessageDirectionType::Request as u8, 1);
+	assert_eq!(MessageDirectionType::Response as u8, 2);
+	assert_eq!(MessageDirectionType::Hello as u8, 3);
+	assert_eq!(MessageDirectionType::Goodbye as u8, 4);
+	assert_eq!(MessageDirectionType::ResponsePart as u8, 5);
+	assert_eq!(MessageDirectionType::Compressed as u8, 6);
+	assert_eq!(MessageDirectionType::Ping as u8, 7);
+	assert_eq!(MessageDirectionType::Pong as u8, 8);
+	assert_eq!(MessageDirectionType::Topology as u8, 9);
+	assert_eq!(MessageDirectionType::Census as u8, 10);
+	assert!(MessageDirectionType::try_from( 11 ).is_err());
+
+	assert_eq!(CompressionType::Zstd as u8, 0);
+	assert!(CompressionType::try_from( 1 ).is_err());
+
+	assert_eq!(RequestType::Posts as u8, 0);
+	assert_eq!(RequestType::Files as u8, 1);
+	assert_eq!(RequestType::Blocks as u8, 2);
+	assert_eq!(RequestType::Events as u8, 3);
+	assert_eq!(RequestType::Search as u8, 4);
+	assert_eq!(RequestType::Summary as u8, 5);
+	assert_eq!(RequestType::PostSet as u8, 6);
+	assert_eq!(RequestType::EventSet as u8, 7);
+	assert_eq!(RequestType::Manifests as u8, 8);
+	assert!(RequestType::try_from( 9 ).is_err());
+
+	assert_eq!(ResponseResultType::Success as u8, 0);
+	assert_eq!(ResponseResultType::InternalError as u8, 1);
+	assert!(ResponseResultType::try_from( 2 ).is_err());
+
+	assert_eq!(ChannelEventType::UpdateChannelProfile as u8, 0);
+	assert_eq!(ChannelEventType::UpdatePublisherList as u8, 1);
+	assert_eq!(ChannelEventType::Create as u8, 2);
+	assert_eq!(ChannelEventType::TransferOwnership as u8, 3);
+	assert_eq!(ChannelEventType::AcceptOwnership as u8, 4);
+	assert_eq!(ChannelEventType::UpdatePinnedPosts as u8, 5);
+	assert_eq!(ChannelEventType::ModerateComments as u8, 6);
+	assert_eq!(ChannelEventType::Close as u8, 7);
+	assert!(ChannelEventType::try_from( 8 ).is_err());
+
+	assert_eq!(PublisherEventType::UpdateProfile as u8, 0);
+	assert_eq!(PublisherEventType::PublishPost as u8, 1);
+	assert_eq!(PublisherEventType::RevisePost as u8, 2);
+	assert_eq!(PublisherEventType::ForgetPost as u8, 3);
+	assert_eq!(PublisherEventType::Comment as u8, 4);
+	assert_eq!(PublisherEventType::Reaction as u8, 5);
+	assert!(PublisherEventType::try_from( 6 ).is_err());
+
+	// Within a post, the format is encoded by serde rather than as a byte.
+	assert_wire( &ContentFormat::Plain, Wire::new().u32( 0 ) );
+	assert_wire( &ContentFormat::Markdown, Wire::new().u32( 1 ) );
+	assert_wire( &ContentFormat::ReStructuredText, Wire::new().u32( 2 ) );
+}
+
+#[test]
+fn posts_mask() {
+	assert_eq!(posts_mask_length( 0 ), 0);
+	assert_eq!(posts_mask_length( 1 ), 1);
+	assert_eq!(posts_mask_length( 8 ), 1);
+	assert_eq!(posts_mask_length( 9 ), 2);
+
+	let mut mask = vec![0u8; posts_mask_length( 12 )];
+	set_mask_bit( &mut mask, 0 );
+	set_mask_bit( &mut mask, 3 );
+	set_mask_bit( &mut mask, 11 );
+	assert_eq!(mask, vec![0b0000_1001, 0b0000_1000]);
+	assert!(get_mask_bit( &mask, 3 ));
+	assert!(!get_mask_bit( &mask, 4 ));
+}
+
+#[test]
+fn event_type() {
+	assert_wire( &EventType::Channel, Wire::new().u8( 0 ) );
+
+	let publisher = private_key().extract_public();
+	assert_wire( &EventType::Publisher( publisher.clone() ), Wire::new().u8( 1 ).value( &publisher ) );
+
+	assert!(bincode::deserialize::<EventType>( &[] ).is_err());
+	assert!(bincode::deserialize::<EventType>( &[1] ).is_err());
+	assert!(bincode::deserialize::<EventType>( &[2] ).is_err());
+}
+
+#[test]
+fn profile_golden() {
+	let expected = Wire::new()
+		.u64( 3 )
+		.u8( 2 ).bytes( b"Hi" )
+		.u16( 3 ).bytes( b"abc" )
+		.none();
+	assert_eq!(expected.0, vec![3, 0, 0, 0, 0, 0, 0, 0, 2, b'H', b'i', 3, 0, b'a', b'b', b'c', 0]);
+	assert_wire( &profile(), expected );
+
+	let picture = hash("picture");
+	let profile = Profile {
+		profile_picture: Some( picture.clone() ),
+		..profile()
+	};
+	assert_wire( &profile, Wire::new().u64( 3 ).u8( 2 ).bytes( b"Hi" ).u16( 3 ).bytes( b"abc" ).some().value( &picture ) );
+}
+
+#[test]
+fn profile_truncated() {
+	let bytes = encode( &Profile {
+		profile_picture: Some( hash("picture") ),
+		..profile()
+	});
+
+	for len in 0..bytes.len() {
+		assert!(bincode::deserialize::<Profile>( &bytes[..len] ).is_err(), "a profile cut off after {} bytes was accepted", len);
+	}
+}
+
+#[test]
+fn channel_profile_events() {
+	let stylesheet = hash("stylesheet");
+	let channel_profile = ChannelProfile {
+		base: profile(),
+		stylesheet: Some( stylesheet.clone() ),
+		timestamp: None
+	};
+	let profile_wire = || Wire::new().value( &profile() );
+	assert_wire( &channel_profile, profile_wire().some().value( &stylesheet ) );
+
+	// Profiles from before the timestamp are encoded as they always were, so that their hashes still match.
+	let timestamped = ChannelProfile {
+		timestamp: Some( 1_600_000_000_000 ),
+		..channel_profile.clone()
+	};
+	assert_wire( &timestamped, profile_wire().some().value( &stylesheet ).u64( 1_600_000_000_000 ) );
+	assert_eq!(decode::<ChannelProfile>( &encode( &channel_profile ) ).timestamp, None);
+	assert_eq!(decode::<ChannelProfile>( &encode( &timestamped ) ).timestamp, Some( 1_600_000_000_000 ));
+
+	let key = private_key();
+	let hash = hash("profile");
+	let message = UpdateChannelProfileEventMessage {
+		signature: sign( &key, &hash ),
+		hash: hash.clone(),
+		profile: channel_profile
+	};
+	let expected = Wire::new().value( &hash ).value( &message.signature ).bytes( &profile_wire().0 ).some().value( &stylesheet );
+	assert_wire( &message, expected );
+
+	let publisher = key.extract_public();
+	let list = PublisherList {
+		revision: 9,
+		publishers: vec![ publisher.clone() ]
+	};
+	assert_wire( &list, Wire::new().u32( 9 ).len( 1 ).value( &publisher ) );
+
+	let message = UpdatePublisherListEventMessage {
+		signature: sign( &key, &hash ),
+		hash: hash.clone(),
+		list
+	};
+	let expected = Wire::new().value( &hash ).value( &message.signature ).u32( 9 ).len( 1 ).value( &publisher );
+	assert_wire( &message, expected );
+}
+
+#[test]
+fn channel_genesis_and_ownership() {
+	let data = ChannelCreateEventData {
+		public: true,
+		requested_replication_time: 30
+	};
+	assert_wire( &data, Wire::new().bool( true ).u32( 30 ) );
+
+	let key = private_key();
+	let hash = hash("genesis");
+	let message = ChannelCreateEventMessage {
+		signature: sign( &key, &hash ),
+		hash: hash.clone(),
+		data
+	};
+	assert_wire( &message, Wire::new().value( &hash ).value( &message.signature ).bool( true ).u32( 30 ) );
+
+	let new_owner = private_key().extract_public();
+	let message = TransferOwnershipEventMessage {
+		signature: sign( &key, &hash ),
+		hash: hash.clone(),
+		transfer: OwnershipTransfer {
+			new_owner: new_owner.clone(),
+			grace_period: 86_400_000
+		}
+	};
+	assert_wire( &message, Wire::new().value( &hash ).value( &message.signature ).value( &new_owner ).u64( 86_400_000 ) );
+
+	let transfer_hash = self::hash("transfer");
+	let message = AcceptOwnershipEventMessage {
+		signature: sign( &key, &hash ),
+		hash: hash.clone(),
+		acceptance: OwnershipAcceptance {
+			transfer_hash: transfer_hash.clone()
+		}
+	};
+	assert_wire( &message, Wire::new().value( &hash ).value( &message.signature ).value( &transfer_hash ) );
+}
+
+#[test]
+fn pinned_posts_event() {
+	let pinned = PinnedPosts {
+		revision: 2,
+		post_ids: vec![ 7, 3 ]
+	};
+	assert_wire( &pinned, Wire::new().u32( 2 ).len( 2 ).u64( 7 ).u64( 3 ) );
+
+	let key = private_key();
+	let hash = hash("pinned");
+	let message = UpdatePinnedPostsEventMessage {
+		signature: sign( &key, &hash ),
+		hash: hash.clone(),
+		pinned
+	};
+	assert_wire( &message, Wire::new().value( &hash ).value( &message.signature ).u32( 2 ).len( 2 ).u64( 7 ).u64( 3 ) );
+}
+
+#[test]
+fn comment_moderation_event() {
+	let comment = hash("comment");
+	let author = private_key().extract_public();
+	assert_wire( &ModerationAction::ApproveComment( comment.clone() ), Wire::new().u32( 0 ).value( &comment ) );
+	assert_wire( &ModerationAction::HideComment( comment.clone() ), Wire::new().u32( 1 ).value( &comment ) );
+	assert_wire( &ModerationAction::DeleteComment( comment.clone() ), Wire::new().u32( 2 ).value( &comment ) );
+	assert_wire( &ModerationAction::ApproveAuthor( author.clone() ), Wire::new().u32( 3 ).value( &author ) );
+
+	let key = private_key();
+	let hash = hash("moderation");
+	let message = ModerateCommentsEventMessage {
+		signature: sign( &key, &hash ),
+		hash: hash.clone(),
+		moderation: CommentModeration {
+			timestamp: 1_600_000_000_000,
+			action: ModerationAction::HideComment( comment.clone() )
+		}
+	};
+	assert_wire( &message, Wire::new().value( &hash ).value( &message.signature ).u64( 1_600_000_000_000 ).u32( 1 ).value( &comment ) );
+}
+
+#[test]
+fn close_channel_event() {
+	let key = private_key();
+	let hash = hash("closing");
+	let message = CloseChannelEventMessage {
+		signature: sign( &key, &hash ),
+		hash: hash.clone(),
+		closing: ChannelClosing { timestamp: 1_600_000_000_000 }
+	};
+	assert_wire( &message, Wire::new().value( &hash ).value( &message.signature ).u64( 1_600_000_000_000 ) );
+}
+
+#[test]
+fn post_events() {
+	assert_wire( &info(), info_wire() );
+
+	let key = private_key();
+	let post = post( &key );
+	assert_wire( &post, post_wire( &post ) );
+	assert_wire( &PublishPostEventData { post: post.clone() }, post_wire( &post ) );
+
+	let hash = hash("revision");
+	let content_hash = self::hash("revised content");
+	let data = RevisePostEventData {
+		post_id: 7,
+		revision: PostRevision {
+			post_hash: post.hash.clone(),
+			number: 2,
+			content_hash: content_hash.clone(),
+			info: info()
+		},
+		signature: sign( &key, &hash ),
+		hash: hash.clone(),
+		content: "Revised".to_owned()
+	};
+	let expected = Wire::new()
+		.u64( 7 )
+		.value( &post.hash ).u32( 2 ).value( &content_hash ).bytes( &info_wire().0 )
+		.value( &hash )
+		.value( &data.signature )
+		.str("Revised");
+	assert_wire( &data, expected );
+
+	assert_wire( &ForgetPostRequest { post_hash: post.hash.clone() }, Wire::new().value( &post.hash ) );
+	let data = ForgetPostEventData {
+		post_id: 7,
+		signature: sign( &key, &hash ),
+		hash: hash.clone()
+	};
+	assert_wire( &data, Wire::new().u64( 7 ).value( &hash ).value( &data.signature ) );
+}
+
+#[test]
+fn comment_and_reaction_events() {
+	let key = private_key();
+	let author = key.extract_public();
+	let post_hash = hash("post");
+	let reply_to = hash("parent comment");
+	let hash = hash("comment");
+
+	let data = CommentEventData {
+		post_id: 7,
+		author: author.clone(),
+		signature: sign( &key, &hash ),
+		hash: hash.clone(),
+		comment: CommentBody {
+			post_hash: post_hash.clone(),
+			reply_to: Some( reply_to.clone() ),
+			timestamp: 1_600_000_000_000,
+			content: "Nice!".to_owned()
+		}
+	};
+	let expected = Wire::new()
+		.u64( 7 )
+		.value( &author )
+		.value( &hash )
+		.value( &data.signature )
+		.value( &post_hash ).some().value( &reply_to ).u64( 1_600_000_000_000 ).str("Nice!");
+	assert_wire( &data, expected );
+
+	let data = ReactionEventData {
+		post_id: 7,
+		signer: author.clone(),
+		signature: sign( &key, &hash ),
+		hash: hash.clone(),
+		reaction: Reaction {
+			post_hash: post_hash.clone(),
+			kind: "👍".to_owned(),
+			timestamp: 1_600_000_000_000
+		}
+	};
+	let expected = Wire::new()
+		.u64( 7 )
+		.value( &author )
+		.value( &hash )
+		.value( &data.signature )
+		.value( &post_hash ).str("👍").u64( 1_600_000_000_000 );
+	assert_wire( &data, expected );
+}
+
+#[test]
+fn requests_and_responses() {
+	let key = private_key();
+	let timeline = key.extract_public();
+
+	assert_wire( &EventsRequest { from_id: 5, count: 3 }, Wire::new().u64( 5 ).u16( 3 ) );
+	assert_wire( &EventsResponse { events: vec![ vec![1, 2], vec![] ] }, Wire::new().len( 2 ).len( 2 ).u8( 1 ).u8( 2 ).len( 0 ) );
+
+	let request = PostsRequest {
+		timeline_id: timeline.clone(),
+		post_id_start: 100,
+		post_id_count: 16,
+		include_content: true
+	};
+	assert_wire( &request, Wire::new().value( &timeline ).u64( 100 ).u16( 16 ).bool( true ) );
+
+	let request = PostSummaryRequest {
+		timeline_id: timeline.clone(),
+		post_id_start: 100
+	};
+	assert_wire( &request, Wire::new().value( &timeline ).u64( 100 ) );
+	let summary = PostSummary {
+		post_id_start: 100,
+		post_id_count: 12,
+		mask: vec![0x0F, 0x08],
+		latest_post_id: Some( 111 )
+	};
+	assert_wire( &summary, Wire::new().u64( 100 ).u16( 12 ).len( 2 ).u8( 0x0F ).u8( 0x08 ).some().u64( 111 ) );
+
+	let ids = IdSet::from_ids( vec![ 3, 4, 6, 7, 20 ] );
+	let ids_wire = || Wire::new().len( 2 ).u64( 3 ).u64( 5 ).u64( 20 ).u64( 1 ).len( 1 ).u64( 5 );
+	assert_wire( &ids, ids_wire() );
+	let request = PostSetRequest {
+		timeline_id: timeline.clone(),
+		post_ids: ids.clone(),
+		include_content: false
+	};
+	assert_wire( &request, Wire::new().value( &timeline ).bytes( &ids_wire().0 ).bool( false ) );
+	assert_wire( &EventSetRequest { event_ids: ids }, ids_wire() );
+
+	let header = ResponsePartHeader { request_id: 7, sequence: 2, last: true };
+	assert_wire( &header, Wire::new().u32( 7 ).u32( 2 ).bool( true ) );
+
+	let post = post( &key );
+	let data = PostData {
+		post: post.clone(),
+		content: Some( "Hello".to_owned() )
+	};
+	let data_wire = || Wire::new().bytes( &post_wire( &post ).0 ).some().str("Hello");
+	assert_wire( &data, data_wire() );
+
+	let file_id = hash("file");
+	let block_id = hash("block");
+	assert_wire( &FilesRequest { file_ids: vec![ file_id.clone() ] }, Wire::new().len( 1 ).value( &file_id ) );
+	let mut files = HashMap::new();
+	files.insert( file_id.clone(), Attachment { block_ids: vec![ block_id.clone() ] } );
+	assert_wire( &FilesResponse { files }, Wire::new().len( 1 ).value( &file_id ).len( 1 ).value( &block_id ) );
+	assert_wire( &ManifestsRequest { file_ids: vec![ file_id.clone() ] }, Wire::new().len( 1 ).value( &file_id ) );
+	let response = ManifestsResponse { manifests: vec![ Some( Attachment { block_ids: vec![ block_id.clone() ] } ), None ] };
+	assert_wire( &response, Wire::new().len( 2 ).some().len( 1 ).value( &block_id ).none() );
+
+	let request = BlocksRequest {
+		post_id: file_id.clone(),
+		block_ids: vec![ block_id.clone() ]
+	};
+	assert_wire( &request, Wire::new().value( &file_id ).len( 1 ).value( &block_id ) );
+	let response = BlocksResponse { data: vec![ Some( vec![0xAB] ), None ] };
+	assert_wire( &response, Wire::new().len( 2 ).some().len( 1 ).u8( 0xAB ).none() );
+
+	let request = PostSearchRequest {
+		keywords: vec!["rust".to_owned()],
+		max_results: SEARCH_REQUEST_MAX_RESULTS
+	};
+	assert_wire( &request, Wire::new().len( 1 ).str("rust").u16( 20 ) );
+	let response = PostSearchResponse {
+		results: vec![ PostSearchResult { publisher: timeline.clone(), data } ]
+	};
+	assert_wire( &response, Wire::new().len( 1 ).value( &timeline ).bytes( &data_wire().0 ) );
+}
+
+#[test]
+fn profile_round_trips() {
+	let mut rng = StdRng::seed_from_u64( 1 );
+
+	for _ in 0..ROUND_TRIPS {
+		let profile = Profile {
+			revision: rng.gen(),
+			title: random_string( &mut rng, u8::MAX as usize ),
+			description: random_string( &mut rng, PROFILE_DESCRIPTION_MAX_LEN as usize ),
+			profile_picture: if rng.gen() { Some( random_hash( &mut rng ) ) } else { None }
+		};
+
+		let decoded = assert_round_trip( &profile );
+		assert_eq!(decoded.revision, profile.revision);
+		assert_eq!(decoded.title, profile.title);
+		assert_eq!(decoded.description, profile.description);
+		assert_eq!(decoded.profile_picture.is_some(), profile.profile_picture.is_some());
+
+		// A channel profile has more after the profile, which needs to be found where the profile ends.
+		let channel_profile = ChannelProfile {
+			base: profile,
+			stylesheet: if rng.gen() { Some( random_hash( &mut rng ) ) } else { None },
+			timestamp: if rng.gen() { Some( rng.gen() ) } else { None }
+		};
+		let decoded = assert_round_trip( &channel_profile );
+		assert_eq!(decoded.timestamp, channel_profile.timestamp);
+	}
+}
+
+#[test]
+fn event_type_round_trips() {
+	let mut rng = StdRng::seed_from_u64( 2 );
+
+	for _ in 0..ROUND_TRIPS {
+		let event_type = if rng.gen() { EventType::Channel } else { EventType::Publisher( private_key().extract_public() ) };
+		let bytes = encode( &event_type );
+
+		// The event data follows the event type in the same message.
+		let mut message = bytes.clone();
+		message.extend( &rng.gen::<[u8; 16]>() );
+		let decoded: EventType = decode( &message );
+		assert_eq!(encode( &decoded ), bytes);
+		assert_eq!(bincode::serialized_size( &decoded ).unwrap() as usize, bytes.len());
+	}
+}
+
+#[test]
+fn post_round_trips() {
+	let mut rng = StdRng::seed_from_u64( 3 );
+	let key = private_key();
+
+	for _ in 0..ROUND_TRIPS {
+		let hash = random_hash( &mut rng );
+		let post = Post {
+			id: rng.gen(),
+			signature: sign( &key, &hash ),
+			hash,
+			meta: PostMeta {
+				info: random_info( &mut rng ),
+				content_hash: random_hash( &mut rng ),
+				attachment_ids: (0..rng.gen_range( 0..4 )).map(|_| random_hash( &mut rng )).collect(),
+				reply_to: if rng.gen() {
+					Some( PostReference { channel: key.extract_public(), post_hash: random_hash( &mut rng ) } )
+				} else { None }
+			}
+		};
+		assert_round_trip( &post );
+
+		let data = PostData {
+			content: if rng.gen() { Some( random_string( &mut rng, 1024 ) ) } else { None },
+			post
+		};
+		assert_round_trip( &data );
+	}
+}
+
+#[test]
+fn comment_round_trips() {
+	let mut rng = StdRng::seed_from_u64( 4 );
+	let key = private_key();
+
+	for _ in 0..ROUND_TRIPS {
+		let hash = random_hash( &mut rng );
+		let data = CommentEventData {
+			post_id: rng.gen(),
+			author: key.extract_public(),
+			signature: sign( &key, &hash ),
+			hash,
+			comment: CommentBody {
+				post_hash: random_hash( &mut rng ),
+				reply_to: if rng.gen() { Some( random_hash( &mut rng ) ) } else { None },
+				timestamp: rng.gen(),
+				content: random_string( &mut rng, COMMENT_MAX_LEN )
+			}
+		};
+		assert_round_trip( &data );
+	}
+}
+
+#[test]
+fn response_round_trips() {
+	let mut rng = StdRng::seed_from_u64( 5 );
+
+	for _ in 0..ROUND_TRIPS {
+		let events = EventsResponse {
+			events: (0..rng.gen_range( 0..EVENTS_REQUEST_MAX_COUNT as usize )).map(|_| {
+				(0..rng.gen_range( 0..64 )).map(|_| rng.gen()).collect()
+			}).collect()
+		};
+		assert_round_trip( &events );
+
+		let blocks = BlocksResponse {
+			data: (0..rng.gen_range( 0..=BLOCKS_REQUEST_MAX_COUNT )).map(|_| {
+				if rng.gen() { Some( (0..rng.gen_range( 0..256 )).map(|_| rng.gen()).collect() ) } else { None }
+			}).collect()
+		};
+		assert_round_trip( &blocks );
+
+		let search = PostSearchRequest {
+			keywords: (0..rng.gen_range( 0..8 )).map(|_| random_string( &mut rng, 32 )).collect(),
+			max_results: rng.gen()
+		};
+		assert_round_trip( &search );
+	}
+}
+
+#[test]
+fn compression_round_trips() {
+	let mut rng = StdRng::seed_from_u64( 6 );
+
+	for _ in 0..ROUND_TRIPS {
+		let mut message = vec![ MessageDirectionType::Event as u8 ];
+		let content = random_string( &mut rng, 256 );
+		for _ in 0..rng.gen_range( 0..16 ) {
+			message.extend( content.as_bytes() );
+		}
+
+		match compress_message( &message ) {
+			// Repeated content always gets smaller, so only messages below the threshold are left alone.
+			None => assert!(message.len() < COMPRESSION_THRESHOLD),
+			Some(compressed) => {
+				assert_eq!(compressed[0], MessageDirectionType::Compressed as u8);
+				assert!(compressed.len() < message.len());
+				let decompressed = decompress_message( Cow::Borrowed( &compressed ), message.len() ).unwrap();
+				assert_eq!(&*decompressed, &*message);
+				// A message that decompresses into more than is allowed is refused.
+				assert!(decompress_message( Cow::Borrowed( &compressed ), message.len() - 1 ).is_err());
+			}
+		}
+	}
+
+	// Messages that aren't compressed are left as they are.
+	let plain = vec![ MessageDirectionType::Request as u8, 1, 2, 3 ];
+	assert!(compress_message( &plain ).is_none());
+	assert_eq!(&*decompress_message( Cow::Borrowed( &plain ), 0 ).unwrap(), &*plain);
+}
+
+#[test]
+fn id_sets() {
+	let mut rng = StdRng::seed_from_u64( 7 );
+
+	for _ in 0..ROUND_TRIPS {
+		let ids: Vec<u64> = (0..rng.gen_range( 0..64 )).map(|_| rng.gen_range( 0..128 )).collect();
+		let mut expected = ids.clone();
+		expected.sort_unstable();
+		expected.dedup();
+
+		let mut set = IdSet::from_ids( ids );
+		set.validate( u64::MAX ).unwrap();
+		assert_eq!(set.iter().collect::<Vec<u64>>(), expected);
+		assert_eq!(set.len(), expected.len() as u64);
+		for id in 0..130 {
+			assert_eq!(set.contains( id ), expected.contains( &id ));
+		}
+
+		// Excepting an id takes it out of the set, and leaves the set valid.
+		if let Some(id) = expected.pop() {
+			set.except( id );
+			set.validate( u64::MAX ).unwrap();
+			assert!(!set.contains( id ));
+			assert_eq!(set.iter().collect::<Vec<u64>>(), expected);
+		}
+
+		assert_eq!(assert_round_trip( &set ), set);
+	}
+
+	// A range with a few exceptions describes a large request with gaps in it.
+	let mut set = IdSet::range( 1000, 10_000 );
+	set.except( 1500 );
+	set.except( 20_000 );
+	assert_eq!(set.len(), 9999);
+	assert_eq!(set.bounds(), Some(( 1000, 11_000 )));
+	assert!(set.contains( 1499 ) && !set.contains( 1500 ) && !set.contains( 11_000 ));
+	assert!(set.validate( 9999 ).is_err());
+	assert!(IdSet::range( 5, 0 ).is_empty());
+
+	// Ids at the end of the id space are no different.
+	let set = IdSet::from_ids( vec![ u64::MAX - 4, u64::MAX - 2, u64::MAX - 1 ] );
+	assert_eq!(set.bounds(), Some(( u64::MAX - 4, u64::MAX )));
+	assert!(set.contains( u64::MAX - 1 ) && !set.contains( u64::MAX - 3 ));
+
+	// Sets that break the rules are refused.
+	let invalid = [
+		Wire::new().len( 2 ).u64( 3 ).u64( 2 ).u64( 5 ).u64( 1 ).len( 0 ),	// ranges that touch
+		Wire::new().len( 2 ).u64( 5 ).u64( 1 ).u64( 3 ).u64( 1 ).len( 0 ),	// ranges out of order
+		Wire::new().len( 1 ).u64( 3 ).u64( 0 ).len( 0 ),	// an empty range
+		Wire::new().len( 1 ).u64( u64::MAX ).u64( 2 ).len( 0 ),	// a range past the last id
+		Wire::new().len( 1 ).u64( 3 ).u64( 2 ).len( 1 ).u64( 5 ),	// an exception outside of the ranges
+		Wire::new().len( 1 ).u64( 3 ).u64( 5 ).len( 2 ).u64( 5 ).u64( 4 )	// exceptions out of order
+	];
+	for wire in invalid.iter() {
+		let set: IdSet = decode( &wire.0 );
+		assert!(set.validate( u64::MAX ).is_err(), "{:?} should be invalid", set);
+	}
+}
+
+#[test]
+fn merkle_roots() {
+	let blocks: Vec<HashCode> = (0..3).map(|i| hash( &format!("block {}", i) )).collect();
+	let leaf = |id: &HashCode| HashCode::generate_from( &(0u8, id) );
+	let node = |left: &HashCode, right: &HashCode| HashCode::generate_from( &(1u8, left, right) );
+
+	// The leaves are paired up level by level, and the third block moves up without a partner.
+	assert_eq!(merkle_root( &blocks[..1] ), leaf( &blocks[0] ));
+	assert_eq!(merkle_root( &blocks[..2] ), node( &leaf( &blocks[0] ), &leaf( &blocks[1] ) ));
+	assert_eq!(merkle_root( &blocks ), node( &node( &leaf( &blocks[0] ), &leaf( &blocks[1] ) ), &leaf( &blocks[2] ) ));
+	assert_eq!(merkle_root( &[] ), HashCode::generate( &[] ));
+
+	// A manifest only belongs to the file whose id is its root, so the order of the blocks matters.
+	let manifest = Attachment { block_ids: blocks.clone() };
+	let root = manifest.merkle_root();
+	assert!(manifest.is_manifest_of( &root ));
+	let reordered = Attachment { block_ids: vec![ blocks[1].clone(), blocks[0].clone(), blocks[2].clone() ] };
+	assert!(!reordered.is_manifest_of( &root ));
+	let truncated = Attachment { block_ids: blocks[..2].to_vec() };
+	assert!(!truncated.is_manifest_of( &root ));
+}
@@ -0,0 +1,460 @@
+//! Lets accounts on ActivityPub servers, like Mastodon, follow channels.
+//!
+//! Every channel has an actor at `/channel/{address}/actor`, which can be found with WebFinger as `acct:{address}@{host}`.
+//! Follows that arrive in the inbox of the actor are stored as followers of the channel, and are accepted right away.
+//! The new posts of the owner of the channel are delivered to the inboxes of its followers as `Create` activities, every so often in the background.
+//! Like in the RSS and Atom feeds, only the posts of the owner are federated.
+//!
+//! Servers only accept activities that are signed with an HTTP signature, so every channel has an RSA key of its own, which is generated the first time that it is needed.
+//! The activities that arrive in the inbox are checked against the key of the actor that sent them in the same way.
+//! Federation only happens when `federation_url` is configured, because the ids of the actors need to be reachable by other servers.
+//! Actors are only fetched from, and activities only delivered to, public addresses, because anybody can make the node contact the URLs that they name.
+
+use std::{
+	fmt,
+	sync::Arc,
+	time::{Duration, SystemTime, UNIX_EPOCH}
+};
+
+use actix_web::{
+	http::{header, Uri},
+	HttpRequest
+};
+use gnunet::identity::PublicKey;
+use rand::rngs::OsRng;
+use rsa::{
+	pkcs1::{FromRsaPrivateKey, ToRsaPrivateKey},
+	pkcs8::{FromPublicKey, ToPublicKey},
+	Hash,
+	PaddingScheme,
+	PublicKey as _,
+	RsaPrivateKey,
+	RsaPublicKey
+};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tracing::{error, warn};
+
+use crate::{
+	config,
+	micropub,
+	persistence::{self, channel, timeline},
+	post::Post,
+	preview_cache,
+	runtime,
+	services::GnunetServices,
+	shutdown
+};
+
+
+
+/// The content type of activities and actors.
+pub const ACTIVITY_CONTENT_TYPE: &str = "application/activity+json";
+/// The content type that is asked for when fetching actors, which some servers require instead of `ACTIVITY_CONTENT_TYPE`.
+const LD_CONTENT_TYPE: &str = "application/ld+json; profile=\"https://www.w3.org/ns/activitystreams\"";
+const PUBLIC_COLLECTION: &str = "https://www.w3.org/ns/activitystreams#Public";
+/// The size of the keys of the channels, in bits.
+const KEY_BITS: usize = 2048;
+/// The number of seconds that the date of a signed request may differ from ours.
+const MAX_CLOCK_SKEW: u64 = 12 * 60 * 60;
+/// The maximum size of a fetched actor, in bytes.
+const MAX_ACTOR_SIZE: usize = 1024 * 1024;
+
+
+
+/// The key that a channel signs its requests with.
+pub struct Signer {
+	key: RsaPrivateKey,
+	/// The id of the public key, as it is found in the actor of the channel.
+	key_id: String
+}
+
+#[derive(Debug)]
+pub enum Error {
+	Persistence( persistence::Error ),
+	/// A key couldn't be generated, encoded or decoded.
+	Key( String ),
+	/// Another server couldn't be reached.
+	Http( String ),
+	/// Another server responded with an error status.
+	Status( u16 ),
+	/// A request or a response didn't have the form that it needs to have.
+	Invalid( &'static str ),
+	/// The signature of a request is missing or doesn't match.
+	Signature( &'static str )
+}
+
+
+
+/// The public URL of the web interface, if channels are federated at all.
+pub fn base_url() -> Option<String> {
+	config::get().federation_url.clone()
+}
+
+pub fn actor_id( base_url: &str, address: &PublicKey ) -> String {
+	format!("{}/channel/{}/actor", base_url, address)
+}
+
+/// The id of the note of a post, which is the address of its page as well.
+pub fn note_id( base_url: &str, address: &PublicKey, post_id: u64 ) -> String {
+	format!("{}/channel/address/{}/post/{}", base_url, address, post_id)
+}
+
+/// Makes the actor of a channel.
+pub fn actor( base_url: &str, address: &PublicKey, title: &str, description: &str, signer: &Signer ) -> Result<Value, Error> {
+	let id = actor_id( base_url, address );
+	let public_key = RsaPublicKey::from( &signer.key ).to_public_key_pem()
+		.map_err(|e| Error::Key( e.to_string() ))?;
+
+	Ok( json!({
+		"@context": ["https://www.w3.org/ns/activitystreams", "https://w3id.org/security/v1"],
+		"id": id,
+		"type": "Person",
+		"preferredUsername": address.to_string(),
+		"name": if title.is_empty() { address.to_string() } else { title.to_owned() },
+		"summary": description,
+		"url": format!("{}/channel/feed/address/{}", base_url, address),
+		"icon": {
+			"type": "Image",
+			"url": format!("{}/channel/{}/icon.svg", base_url, address)
+		},
+		"inbox": format!("{}/channel/{}/inbox", base_url, address),
+		"outbox": format!("{}/channel/{}/outbox", base_url, address),
+		"followers": format!("{}/channel/{}/followers", base_url, address),
+		"manuallyApprovesFollowers": false,
+		"publicKey": {
+			"id": signer.key_id,
+			"owner": id,
+			"publicKeyPem": public_key
+		}
+	}) )
+}
+
+/// Makes the `Create` activity of a post, with its content rendered to HTML.
+/// Returns `None` if the content of the post isn't there (yet).
+pub async fn create_activity( base_url: &str, address: &PublicKey, timeline: &timeline::Handle, post: &Post ) -> Result<Option<Value>, Error> {
+	let rendered = match preview_cache::load_full( timeline, post ).await? {
+		None => return Ok(None),
+		Some(r) => r
+	};
+
+	let actor = actor_id( base_url, address );
+	let id = note_id( base_url, address, post.id );
+	let published = humantime::format_rfc3339_seconds( UNIX_EPOCH + Duration::from_millis( post.meta.info.publish_timestamp ) ).to_string();
+	let followers = format!("{}/channel/{}/followers", base_url, address);
+	let tags: Vec<Value> = post.meta.info.tags.iter().map(|tag| json!({
+		"type": "Hashtag",
+		"name": format!("#{}", tag),
+		"href": format!("{}/channel/{}/tag/{}", base_url, address, tag)
+	})).collect();
+
+	Ok( Some( json!({
+		"@context": "https://www.w3.org/ns/activitystreams",
+		"id": format!("{}#create", id),
+		"type": "Create",
+		"actor": actor,
+		"published": published,
+		"to": [PUBLIC_COLLECTION],
+		"cc": [followers],
+		"object": {
+			"id": id,
+			"type": "Note",
+			"attributedTo": actor,
+			"url": id,
+			"published": published,
+			"to": [PUBLIC_COLLECTION],
+			"cc": [followers],
+			// The content warning of a post is what servers call its summary.
+			"summary": post.meta.info.content_warning,
+			"sensitive": post.meta.info.content_warning.is_some(),
+			"content": rendered.html,
+			"tag": tags
+		}
+	}) ) )
+}
+
+/// Makes the activity that accepts a follow.
+pub fn accept_activity( base_url: &str, address: &PublicKey, follow: &Value ) -> Value {
+	let actor = actor_id( base_url, address );
+	let nonce: u64 = rand::random();
+
+	json!({
+		"@context": "https://www.w3.org/ns/activitystreams",
+		"id": format!("{}#accept-{:x}", actor, nonce),
+		"type": "Accept",
+		"actor": actor,
+		"object": follow
+	})
+}
+
+/// Checks the HTTP signature of a request that arrived in an inbox, and the digest of its body.
+/// The actor that signed the request is fetched, signing the request for it with `signer`, because some servers only serve their actors to signed requests.
+/// Returns the actor that signed the request.
+pub async fn verify( req: &HttpRequest, body: &[u8], signer: &Signer ) -> Result<Value, Error> {
+	let header_value = |name: &str| req.headers().get( name ).and_then(|v| v.to_str().ok()).map(|v| v.to_owned());
+
+	let digest = header_value("digest").ok_or( Error::Signature("the digest is missing") )?;
+	if !digest.split(',').any(|d| d.trim() == digest_header( body )) {
+		return Err( Error::Signature("the digest doesn't match the body") )
+	}
+	let date = header_value("date").ok_or( Error::Signature("the date is missing") )?;
+	let date = httpdate::parse_http_date( &date ).map_err(|_| Error::Signature("the date is invalid"))?;
+	let now = SystemTime::now();
+	let skew = now.duration_since( date ).or_else(|_| date.duration_since( now )).unwrap_or_default();
+	if skew > Duration::from_secs( MAX_CLOCK_SKEW ) {
+		return Err( Error::Signature("the date is too far off") )
+	}
+
+	let signature = header_value("signature").ok_or( Error::Signature("the signature is missing") )?;
+	let mut key_id = None;
+	let mut headers = None;
+	let mut signature_bytes = None;
+	for field in signature.split(',') {
+		let (name, value) = match field.split_once('=') {
+			None => continue,
+			Some(f) => f
+		};
+		let value = value.trim().trim_matches('"');
+		match name.trim() {
+			"keyId" => key_id = Some( value.to_owned() ),
+			"headers" => headers = Some( value.to_owned() ),
+			"signature" => signature_bytes = base64::decode( value ).ok(),
+			_ => {}
+		}
+	}
+	let key_id = key_id.ok_or( Error::Signature("the signature has no key id") )?;
+	let signature_bytes = signature_bytes.ok_or( Error::Signature("the signature can't be decoded") )?;
+	// Without a list of headers, only the date is signed, which would let the body be replaced.
+	let headers = headers.ok_or( Error::Signature("the signature doesn't list its headers") )?;
+	let headers: Vec<&str> = headers.split_whitespace().collect();
+	if !headers.contains( &"(request-target)" ) || !headers.contains( &"digest" ) {
+		return Err( Error::Signature("the signature doesn't cover the request target and the digest") )
+	}
+
+	let mut lines = Vec::with_capacity( headers.len() );
+	for name in headers {
+		if name == "(request-target)" {
+			let path = req.uri().path_and_query().map(|p| p.as_str()).unwrap_or( req.path() );
+			lines.push( format!("(request-target): {} {}", req.method().as_str().to_lowercase(), path) );
+		}
+		else {
+			let value = header_value( name ).ok_or( Error::Signature("a signed header is missing") )?;
+			lines.push( format!("{}: {}", name, value) );
+		}
+	}
+
+	// The key belongs to the actor that the key id points into.
+	let actor_url = key_id.split('#').next().unwrap_or( &key_id );
+	let actor = fetch_actor( actor_url, signer ).await?;
+	if actor["publicKey"]["id"].as_str() != Some( &key_id ) {
+		return Err( Error::Signature("the key id doesn't belong to the actor") )
+	}
+	let public_key = actor["publicKey"]["publicKeyPem"].as_str()
+		.ok_or( Error::Invalid("the actor has no public key") )?;
+	let public_key = RsaPublicKey::from_public_key_pem( public_key ).map_err(|e| Error::Key( e.to_string() ))?;
+
+	let hashed = Sha256::digest( lines.join("\n").as_bytes() );
+	public_key.verify( PaddingScheme::new_pkcs1v15_sign( Some( Hash::SHA2_256 ) ), &hashed, &signature_bytes )
+		.map_err(|_| Error::Signature("the signature doesn't match"))?;
+
+	Ok( actor )
+}
+
+/// Fetches the actor with the given id.
+pub async fn fetch_actor( url: &str, signer: &Signer ) -> Result<Value, Error> {
+	let uri: Uri = url.parse().map_err(|_| Error::Invalid("the actor id is no URL"))?;
+	let date = httpdate::fmt_http_date( SystemTime::now() );
+	let host = uri.authority().ok_or( Error::Invalid("the actor id has no host") )?.to_string();
+	let signature = signer.sign( "get", &uri, &[("host", &host), ("date", &date)] )?;
+	let address = micropub::check_public_url( url ).await.map_err( Error::Http )?;
+
+	let mut response = client().get( url )
+		.address( address )
+		.insert_header(( header::ACCEPT, LD_CONTENT_TYPE ))
+		.insert_header(( header::HOST, host ))
+		.insert_header(( header::DATE, date ))
+		.insert_header(( "signature", signature ))
+		.send().await
+		.map_err(|e| Error::Http( e.to_string() ))?;
+	if !response.status().is_success() {
+		return Err( Error::Status( response.status().as_u16() ) )
+	}
+
+	let actor: Value = response.json().limit( MAX_ACTOR_SIZE ).await.map_err(|e| Error::Http( e.to_string() ))?;
+	if actor["id"].as_str() != Some( url ) {
+		return Err( Error::Invalid("the actor has another id than the one it was fetched by") )
+	}
+	Ok( actor )
+}
+
+/// Delivers an activity to an inbox, signed by the channel.
+pub async fn deliver( signer: &Signer, inbox: &str, activity: &Value ) -> Result<(), Error> {
+	let uri: Uri = inbox.parse().map_err(|_| Error::Invalid("the inbox is no URL"))?;
+	let body = serde_json::to_vec( activity ).unwrap();
+	let date = httpdate::fmt_http_date( SystemTime::now() );
+	let digest = digest_header( &body );
+	let host = uri.authority().ok_or( Error::Invalid("the inbox has no host") )?.to_string();
+	let signature = signer.sign( "post", &uri, &[("host", &host), ("date", &date), ("digest", &digest)] )?;
+	let address = micropub::check_public_url( inbox ).await.map_err( Error::Http )?;
+
+	let response = client().post( inbox )
+		.address( address )
+		.insert_header(( header::CONTENT_TYPE, ACTIVITY_CONTENT_TYPE ))
+		.insert_header(( header::HOST, host ))
+		.insert_header(( header::DATE, date ))
+		.insert_header(( "digest", digest ))
+		.insert_header(( "signature", signature ))
+		.send_body( body ).await
+		.map_err(|e| Error::Http( e.to_string() ))?;
+	if !response.status().is_success() {
+		return Err( Error::Status( response.status().as_u16() ) )
+	}
+	Ok(())
+}
+
+/// The client that other servers are contacted with.
+/// Requests are sent to the address that `micropub::check_public_url` has checked, and redirects aren't followed, as they could lead to an address that hasn't been checked.
+fn client() -> awc::Client {
+	awc::Client::builder()
+		.timeout( Duration::from_millis( config::FEDERATION_TIMEOUT ) )
+		.disable_redirects()
+		.finish()
+}
+
+/// The value of the `Digest` header of a body.
+fn digest_header( body: &[u8] ) -> String {
+	format!("SHA-256={}", base64::encode( Sha256::digest( body ) ))
+}
+
+/// Delivers the new posts of the channels with followers every so often, until the node shuts down.
+pub async fn deliver_periodically( services: Arc<GnunetServices> ) {
+	loop {
+		// Before the setup has been done, there are no channels to federate.
+		if persistence::database_exists() {
+			if let Some(base_url) = base_url() {
+				if let Err(e) = deliver_new_posts( &services, &base_url ).await {
+					error!("Unable to deliver posts to the followers of channels: {}", e);
+				}
+			}
+		}
+
+		if !shutdown::sleep( Duration::from_secs( config::FEDERATION_DELIVERY_INTERVAL ) ).await { break }
+	}
+}
+
+/// Delivers the posts of the owners of the channels that haven't been delivered to their followers yet.
+/// Followers that can't be reached miss the posts, like they would on other servers.
+async fn deliver_new_posts( services: &Arc<GnunetServices>, base_url: &str ) -> Result<(), Error> {
+	let db = persistence::Handle::connect( services.clone() ).await.map_err( persistence::Error::Database )?;
+
+	for address in db.list_federated_channels().await? {
+		let channel = match db.clone().get_channel( &address ).await? {
+			None => continue,
+			Some(c) => c
+		};
+		let mut timeline = match channel.get_timeline( &address ).await? {
+			None => continue,
+			Some(t) => t
+		};
+		let latest = match timeline.load_latest_post_id().await? {
+			None => continue,
+			Some(l) => l
+		};
+		let next = channel.load_federation_next_post_id().await?.unwrap_or(0);
+		if next > latest { continue }
+
+		// When a lot of posts arrive at once, only the latest ones are delivered.
+		let start = next.max( (latest + 1).saturating_sub( config::FEDERATION_OUTBOX_POSTS ) );
+		let posts = timeline.list_posts( start, (latest + 1 - start) as _ ).await?;
+		let signer = Signer::load( &channel, base_url, &address ).await?;
+		let followers = channel.list_followers().await?;
+
+		let now = SystemTime::now().duration_since( UNIX_EPOCH ).unwrap().as_millis() as u64;
+		let mut next = latest + 1;
+		for post in posts.into_iter().flatten() {
+			// Posts that aren't visible yet are delivered once they are, and so are the ones after them.
+			if !post.meta.info.is_visible_at( now ) {
+				next = post.id;
+				break
+			}
+			let activity = match create_activity( base_url, &address, &timeline, &post ).await? {
+				None => continue,
+				Some(a) => a
+			};
+			for follower in &followers {
+				if let Err(e) = deliver( &signer, &follower.inbox, &activity ).await {
+					warn!("Unable to deliver post {} of channel {} to {}: {}", post.id, address, follower.actor, e);
+				}
+			}
+		}
+		channel.store_federation_next_post_id( next ).await?;
+	}
+
+	Ok(())
+}
+
+
+
+impl Signer {
+
+	/// Loads the key of a channel, generating one if the channel doesn't have one yet.
+	pub async fn load( channel: &channel::Handle, base_url: &str, address: &PublicKey ) -> Result<Self, Error> {
+		let pem = match channel.load_federation_key().await? {
+			Some(p) => p,
+			None => {
+				let key = runtime::block_on(|| RsaPrivateKey::new( &mut OsRng, KEY_BITS )).await
+					.map_err(|e| Error::Key( e.to_string() ))?;
+				let pem = key.to_pkcs1_pem().map_err(|e| Error::Key( e.to_string() ))?.to_string();
+				// Another request may have stored a key in the meantime, which is used instead.
+				if channel.store_federation_key( &pem ).await? {
+					pem
+				}
+				else {
+					channel.load_federation_key().await?.ok_or( Error::Invalid("the key of the channel has disappeared") )?
+				}
+			}
+		};
+
+		Ok( Self {
+			key: RsaPrivateKey::from_pkcs1_pem( &pem ).map_err(|e| Error::Key( e.to_string() ))?,
+			key_id: format!("{}#main-key", actor_id( base_url, address ))
+		})
+	}
+
+	/// Makes the `Signature` header of a request, that signs its target and the given headers.
+	fn sign( &self, method: &str, uri: &Uri, headers: &[(&str, &str)] ) -> Result<String, Error> {
+		let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+		let mut lines = vec![ format!("(request-target): {} {}", method, path) ];
+		lines.extend( headers.iter().map(|(name, value)| format!("{}: {}", name, value)) );
+
+		let hashed = Sha256::digest( lines.join("\n").as_bytes() );
+		let signature = self.key.sign( PaddingScheme::new_pkcs1v15_sign( Some( Hash::SHA2_256 ) ), &hashed )
+			.map_err(|e| Error::Key( e.to_string() ))?;
+
+		let names: Vec<&str> = headers.iter().map(|(name, _)| *name).collect();
+		Ok( format!("keyId=\"{}\",algorithm=\"rsa-sha256\",headers=\"(request-target) {}\",signature=\"{}\"",
+			self.key_id, names.join(" "), base64::encode( signature )
+		) )
+	}
+}
+
+impl From<persistence::Error> for Error {
+	fn from( other: persistence::Error ) -> Self {
+		Self::Persistence( other )
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt( &self, f: &mut fmt::Formatter<'_> ) -> fmt::Result {
+		match self {
+			Self::Persistence(e) => write!(f, "{}", e),
+			Self::Key(e) => write!(f, "key error: {}", e),
+			Self::Http(e) => write!(f, "HTTP error: {}", e),
+			Self::Status(status) => write!(f, "the server responded with status {}", status),
+			Self::Invalid(message) => write!(f, "invalid ActivityPub data: {}", message),
+			Self::Signature(message) => write!(f, "invalid HTTP signature: {}", message)
+		}
+	}
+}
+
+impl std::error::Error for Error {}
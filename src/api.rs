@@ -0,0 +1,599 @@
+//! The JSON API, for clients other than the web interface.
+//!
+//! All endpoints live under `/api/v1`.
+//! Channels and publishers are identified by their address, except for the endpoints that need one of our own egos.
+//! Lists are paged with the `start` and `count` query parameters.
+//! All endpoints need the admin password, given with HTTP basic authentication, see `auth`.
+//! Errors are answered with their status and a plain text message, rather than the error pages of the web interface.
+
+use actix_web::{delete, error, get, http::header, post, HttpResponse, ResponseError, web};
+use gnunet::identity::PublicKey;
+use serde::*;
+use tracing::{error, warn};
+
+use std::{
+	sync::Arc,
+	time::{SystemTime, UNIX_EPOCH}
+};
+
+use crate::config;
+use crate::persistence::{self, audit::AuditAction, timeline::{self, PostFilter, SNIPPET_MATCH_END, SNIPPET_MATCH_START}};
+use crate::post::*;
+use crate::preview_cache;
+use crate::services;
+use crate::share::ShareLink;
+use crate::swarm::{self, Node};
+use crate::web as html;
+use crate::web_error::WebError;
+use crate::Globals;
+
+
+
+#[derive(Deserialize)]
+pub struct SubscriptionParams {
+	address: String
+}
+
+#[derive(Deserialize)]
+pub struct PostParams {
+	address: String,
+	post_id: u64
+}
+
+#[derive(Deserialize)]
+pub struct EgoParams {
+	ego: String
+}
+
+#[derive(Deserialize)]
+pub struct PageQuery {
+	#[serde(default)]
+	start: u64,
+	count: Option<u16>
+}
+
+/// What the posts of a timeline can be filtered on, which is all optional.
+#[derive(Deserialize)]
+pub struct PostFilterQuery {
+	tag: Option<String>,
+	/// The earliest publish time, in milliseconds since the UNIX epoch.
+	since: Option<u64>,
+	/// The publish time before which the posts have been published, in milliseconds since the UNIX epoch.
+	until: Option<u64>
+}
+
+#[derive(Serialize)]
+pub struct ChannelView {
+	address: String,
+	/// The name of our own ego that the channel belongs to, if it is one of ours.
+	ego: Option<String>,
+	/// Whether we are connected to the swarm of the channel at the moment.
+	connected: bool
+}
+
+#[derive(Serialize)]
+pub struct PostView {
+	id: u64,
+	hash: String,
+	/// In milliseconds since the UNIX epoch.
+	publish_timestamp: u64,
+	/// In milliseconds since the UNIX epoch.
+	visible_from: Option<u64>,
+	tags: Vec<String>,
+	format: ContentFormat,
+	/// The hashes of the attached files.
+	attachments: Vec<String>,
+	reply_to: Option<PostReferenceView>,
+	/// The current content, which is only included for single posts, and only if it has been received.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	content: Option<String>,
+	/// The preview of the current content as it is shown in the feeds, which is only included in lists of posts, and only if the content has been received.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	preview: Option<PreviewView>
+}
+
+#[derive(Serialize)]
+pub struct PreviewView {
+	html: String,
+	/// Whether the preview leaves out some of the content.
+	truncated: bool
+}
+
+#[derive(Serialize)]
+pub struct PostReferenceView {
+	channel: String,
+	post_hash: String
+}
+
+#[derive(Deserialize)]
+pub struct TagParams {
+	address: String,
+	keyword: String
+}
+
+#[derive(Serialize)]
+pub struct TagView {
+	keyword: String,
+	/// The number of posts that have been published with the tag.
+	count: u64
+}
+
+#[derive(Serialize)]
+pub struct PostsPage {
+	/// The id of the newest post of the publisher, if it has any.
+	latest_post_id: Option<u64>,
+	/// The posts that we have and that may be shown.
+	posts: Vec<PostView>,
+	/// The number of posts that pass the filter, if one has been given.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	matching: Option<u64>
+}
+
+#[derive(Deserialize)]
+pub struct SubscribeBody {
+	address: String
+}
+
+#[derive(Deserialize)]
+pub struct CreatePostBody {
+	message: String,
+	#[serde(default)]
+	tags: Vec<String>,
+	#[serde(default)]
+	format: ContentFormat,
+	/// In milliseconds since the UNIX epoch.
+	visible_from: Option<u64>
+}
+
+#[derive(Serialize)]
+pub struct CreatedPost {
+	id: u64,
+	hash: String
+}
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+	q: String,
+	#[serde(default)]
+	start: u64,
+	count: Option<u16>
+}
+
+#[derive(Serialize)]
+pub struct SearchResultView {
+	address: String,
+	post_id: u64,
+	/// The part of the content that matches best, as plain text.
+	snippet: String,
+	/// How well the post matches, where lower is better.
+	rank: f64
+}
+
+#[derive(Deserialize)]
+pub struct BatchBody {
+	posts: Vec<u64>,
+	/// One of `delete`, `add_tag`, `set_series` and `set_content_warning`.
+	action: String,
+	/// The tag, series or content warning, if the action needs one.
+	#[serde(default)]
+	value: String
+}
+
+#[derive(Serialize)]
+pub struct BatchResult {
+	/// The number of posts that have been changed.
+	changed: usize
+}
+
+#[derive(Serialize)]
+pub struct SyncStatus {
+	running: bool,
+	events_applied: u64,
+	posts_fetched: u64,
+	blocks_remaining: u64,
+	peers: Vec<String>
+}
+
+#[derive(Serialize)]
+pub struct PeerQueue {
+	peer: String,
+	/// The number of events of the peer that are waiting to be applied.
+	depth: usize,
+	capacity: usize,
+	/// The number of requests of the peer that are waiting to be answered.
+	requests: usize
+}
+
+
+
+fn parse_address( address: &str ) -> error::Result<PublicKey> {
+	PublicKey::from_string( address )
+		.ok_or_else(|| error::ErrorBadRequest("Invalid channel address."))
+}
+
+/// Answers an error of the web interface without its error page.
+fn plain_error( e: WebError ) -> error::Error {
+	error::InternalError::new( e.to_string(), e.status_code() ).into()
+}
+
+fn now() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as _
+}
+
+async fn connect( g: &Globals ) -> error::Result<persistence::Handle> {
+	Ok( persistence::Handle::connect( g.services.clone() ).await.map_err(|e| persistence::Error::Database(e))? )
+}
+
+fn post_view( post: &Post, content: Option<String>, preview: Option<PreviewView> ) -> PostView {
+	PostView {
+		id: post.id,
+		hash: post.hash.to_string(),
+		publish_timestamp: post.meta.info.publish_timestamp,
+		visible_from: post.meta.info.visible_from,
+		tags: post.meta.info.tags.clone(),
+		format: post.meta.info.format,
+		attachments: post.meta.attachment_ids.iter().map(|h| h.to_string()).collect(),
+		reply_to: post.meta.reply_to.as_ref().map(|r| PostReferenceView {
+			channel: r.channel.to_string(),
+			post_hash: r.post_hash.to_string()
+		}),
+		content,
+		preview
+	}
+}
+
+/// Creates the views of a list of posts, with their previews.
+async fn post_views_with_previews( timeline: &timeline::Handle, posts: &[Post] ) -> error::Result<Vec<PostView>> {
+	let mut views = Vec::with_capacity( posts.len() );
+	for post in posts {
+		let preview = preview_cache::load_summary( timeline, post ).await?.map(|p| PreviewView {
+			html: p.html.clone(),
+			truncated: p.truncated
+		});
+		views.push( post_view( post, None, preview ) );
+	}
+	Ok( views )
+}
+
+/// Lists all channels that we know: our own ones and the ones we follow.
+#[get("/api/v1/channels")]
+pub async fn channels( g: web::Data<Arc<Globals>> ) -> error::Result<HttpResponse> {
+
+	let db = connect( &g ).await?;
+	let subscriptions = g.subscriptions.read().await;
+
+	let mut channels = Vec::new();
+	for channel in db.list_channels().await? {
+		let address = channel.load_address().await?;
+		let ego = match db.get_timeline( &address ).await? {
+			None => None,
+			Some(t) => t.get_my_ego().await?
+		};
+		channels.push( ChannelView {
+			connected: subscriptions.as_ref().and_then(|s| s.node( &address )).map(|n| n.is_connected()).unwrap_or(false),
+			address: address.to_string(),
+			ego
+		});
+	}
+
+	Ok( HttpResponse::Ok().json( channels ) )
+}
+
+/// Lists a page of the posts of a publisher, oldest first.
+/// Posts that we don't have, that have been forgotten, or that may not be shown yet, are left out.
+/// With a `tag`, `since` or `until` query parameter, only the posts that pass all of them are listed, newest first, and `start` is the number of them to skip.
+#[get("/api/v1/timelines/{address}/posts")]
+pub async fn timeline_posts( g: web::Data<Arc<Globals>>, p: web::Path<SubscriptionParams>, q: web::Query<PageQuery>, f: web::Query<PostFilterQuery> ) -> error::Result<HttpResponse> {
+
+	let address = parse_address( &p.address )?;
+	let count = q.count.unwrap_or( config::API_PAGE_SIZE ).min( config::API_PAGE_MAX_SIZE );
+	if count == 0 {
+		return Err( error::ErrorBadRequest("The count needs to be positive.") )
+	}
+
+	let db = connect( &g ).await?;
+	let mut timeline = db.get_timeline( &address ).await?
+		.ok_or_else(|| error::ErrorNotFound("Unknown publisher."))?;
+	let own = timeline.get_my_ego().await?.is_some();
+
+	let tag = match &f.tag {
+		None => None,
+		Some(tag) => Some( normalize_tags( std::iter::once( tag.as_str() ) ).pop()
+			.ok_or_else(|| error::ErrorBadRequest("Invalid tag."))? )
+	};
+	let filter = PostFilter { tag, since: f.since, until: f.until };
+	if !filter.is_empty() {
+		let posts = timeline.list_filtered_posts( &filter, own, q.start, count ).await?;
+		let posts = post_views_with_previews( &timeline, &posts ).await?;
+		return Ok( HttpResponse::Ok().json( PostsPage {
+			latest_post_id: timeline.load_latest_post_id().await?,
+			posts,
+			matching: Some( timeline.count_filtered_posts( &filter, own ).await? )
+		}))
+	}
+
+	let now = now();
+	let posts: Vec<Post> = timeline.list_posts( q.start, count ).await?.into_iter()
+		.flatten()
+		.filter(|post| own || post.meta.info.is_visible_at( now ))
+		.collect();
+	let posts = post_views_with_previews( &timeline, &posts ).await?;
+
+	Ok( HttpResponse::Ok().json( PostsPage {
+		latest_post_id: timeline.load_latest_post_id().await?,
+		posts,
+		matching: None
+	}))
+}
+
+/// Lists the tags of a publisher, the most used first.
+/// The `count` query parameter limits the number of tags.
+#[get("/api/v1/timelines/{address}/tags")]
+pub async fn timeline_tags( g: web::Data<Arc<Globals>>, p: web::Path<SubscriptionParams>, q: web::Query<PageQuery> ) -> error::Result<HttpResponse> {
+
+	let address = parse_address( &p.address )?;
+	let count = q.count.unwrap_or( config::API_PAGE_SIZE ).min( config::API_PAGE_MAX_SIZE );
+
+	let db = connect( &g ).await?;
+	let timeline = db.get_timeline( &address ).await?
+		.ok_or_else(|| error::ErrorNotFound("Unknown publisher."))?;
+	let own = timeline.get_my_ego().await?.is_some();
+
+	let tags: Vec<TagView> = timeline.list_top_tags( own, count as _ ).await?
+		.into_iter().map(|t| TagView {
+			keyword: t.keyword,
+			count: t.count
+		}).collect();
+
+	Ok( HttpResponse::Ok().json( tags ) )
+}
+
+/// Lists a page of the posts of a publisher that have been published with the given tag, newest first.
+#[get("/api/v1/timelines/{address}/tags/{keyword}/posts")]
+pub async fn timeline_tag_posts( g: web::Data<Arc<Globals>>, p: web::Path<TagParams>, q: web::Query<PageQuery> ) -> error::Result<HttpResponse> {
+
+	let address = parse_address( &p.address )?;
+	let keyword = normalize_tags( std::iter::once( p.keyword.as_str() ) ).pop()
+		.ok_or_else(|| error::ErrorBadRequest("Invalid tag."))?;
+	let count = q.count.unwrap_or( config::API_PAGE_SIZE ).min( config::API_PAGE_MAX_SIZE );
+	if count == 0 {
+		return Err( error::ErrorBadRequest("The count needs to be positive.") )
+	}
+
+	let db = connect( &g ).await?;
+	let timeline = db.get_timeline( &address ).await?
+		.ok_or_else(|| error::ErrorNotFound("Unknown publisher."))?;
+	let own = timeline.get_my_ego().await?.is_some();
+
+	let posts = timeline.list_posts_by_tag( &keyword, own, q.start, count ).await?;
+	let posts = post_views_with_previews( &timeline, &posts ).await?;
+
+	Ok( HttpResponse::Ok().json( PostsPage {
+		latest_post_id: timeline.load_latest_post_id().await?,
+		posts,
+		matching: None
+	}))
+}
+
+/// Returns a single post, with its current content.
+#[get("/api/v1/timelines/{address}/posts/{post_id}")]
+pub async fn timeline_post( g: web::Data<Arc<Globals>>, p: web::Path<PostParams> ) -> error::Result<HttpResponse> {
+
+	let address = parse_address( &p.address )?;
+	let db = connect( &g ).await?;
+	let timeline = db.get_timeline( &address ).await?
+		.ok_or_else(|| error::ErrorNotFound("Unknown publisher."))?;
+	let post = timeline.load_post( p.post_id ).await?
+		.ok_or_else(|| error::ErrorNotFound("Post not found."))?;
+	if !post.meta.info.is_visible_at( now() ) && timeline.get_my_ego().await?.is_none() {
+		return Err( error::ErrorNotFound("Post not found.") )
+	}
+	let content = timeline.load_current_content( p.post_id ).await?;
+
+	Ok( HttpResponse::Ok().json( post_view( &post, content, None ) ) )
+}
+
+/// Publishes a post on the timeline of one of our own egos.
+/// Responds with 201 Created, and the location of the new post.
+#[post("/api/v1/egos/{ego}/posts")]
+pub async fn create_post( g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>, body: web::Json<CreatePostBody> ) -> error::Result<HttpResponse> {
+
+	let body = body.into_inner();
+	let tags = normalize_tags( body.tags.iter().map(|t| t.as_str()) );
+
+	let private_key = g.services.lookup_ego( &p.ego ).await?;
+	let address = private_key.extract_public().unwrap();
+	let db = connect( &g ).await?;
+	let timeline = db.get_timeline( &address ).await?
+		.ok_or_else(|| error::ErrorNotFound("Unknown publisher."))?;
+
+	// The defaults of the ego are added to what has been written.
+	let defaults = timeline.load_post_defaults().await?;
+	let tags = defaults.apply_tags( tags );
+	check_tags( &tags ).map_err( error::ErrorBadRequest )?;
+	let message = defaults.apply_footer( &body.message );
+
+	let info = PostInfo {
+		tags,
+		publish_timestamp: now(),
+		visible_from: body.visible_from,
+		format: body.format,
+		series: None,
+		content_warning: None
+	};
+	let (_, post) = timeline.create_post( &private_key, &message, info, Vec::new(), None ).await?;
+	db.clone().get_channel( &address ).await?
+		.ok_or_else(|| error::ErrorNotFound("Unknown channel."))?
+		.log_new_post( &timeline, &post ).await?;
+	db.record_action( Some( &p.ego ), AuditAction::PostCreated, &html::post_subject( &address, post.id ) ).await?;
+
+	let location = format!("/api/v1/timelines/{}/posts/{}", address, post.id);
+	Ok( HttpResponse::Created().append_header((header::LOCATION, location)).json( CreatedPost {
+		id: post.id,
+		hash: post.hash.to_string()
+	}))
+}
+
+/// Applies an action to many posts of one of our own egos at once.
+/// Either all posts are changed, or none of them are.
+#[post("/api/v1/egos/{ego}/posts/batch")]
+pub async fn batch( g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>, body: web::Json<BatchBody> ) -> error::Result<HttpResponse> {
+
+	let action = html::parse_batch_action( &body.action, &body.value ).map_err( plain_error )?;
+	let changed = html::apply_batch( &g, &p.ego, &body.posts, &action ).await.map_err( plain_error )?;
+
+	Ok( HttpResponse::Ok().json( BatchResult { changed } ) )
+}
+
+/// Searches the content of all posts that we have stored, the best matches first.
+#[get("/api/v1/search")]
+pub async fn search( g: web::Data<Arc<Globals>>, q: web::Query<SearchQuery> ) -> error::Result<HttpResponse> {
+
+	let count = q.count.unwrap_or( config::API_PAGE_SIZE ).min( config::API_PAGE_MAX_SIZE );
+	let db = connect( &g ).await?;
+	let results: Vec<SearchResultView> = db.search_posts( &q.q, count as _, q.start ).await?
+		.into_iter().map(|r| SearchResultView {
+			address: r.publisher.to_string(),
+			post_id: r.post_id,
+			snippet: r.snippet.replace(|c| c == SNIPPET_MATCH_START || c == SNIPPET_MATCH_END, ""),
+			rank: r.rank
+		}).collect();
+
+	Ok( HttpResponse::Ok().json( results ) )
+}
+
+/// Lists the channels that we follow, which doesn't include our own.
+#[get("/api/v1/subscriptions")]
+pub async fn subscriptions( g: web::Data<Arc<Globals>> ) -> error::Result<HttpResponse> {
+
+	let db = connect( &g ).await?;
+	let subscriptions = g.subscriptions.read().await;
+
+	let mut channels = Vec::new();
+	for address in db.list_subscriptions().await? {
+		if let Some(timeline) = db.get_timeline( &address ).await? {
+			if timeline.get_my_ego().await?.is_some() { continue }
+		}
+		channels.push( ChannelView {
+			connected: subscriptions.as_ref().and_then(|s| s.node( &address )).map(|n| n.is_connected()).unwrap_or(false),
+			address: address.to_string(),
+			ego: None
+		});
+	}
+
+	Ok( HttpResponse::Ok().json( channels ) )
+}
+
+/// Starts following a channel, given by its address or by a share link.
+/// Responds with 202 Accepted, as joining its swarm happens in the background.
+#[post("/api/v1/subscriptions")]
+pub async fn subscribe( g: web::Data<Arc<Globals>>, body: web::Json<SubscribeBody> ) -> error::Result<HttpResponse> {
+
+	let link = ShareLink::parse( &body.address )
+		.ok_or_else(|| error::ErrorBadRequest("Invalid channel address."))?;
+	html::subscribe( g.get_ref(), link.address, &link.peers ).await.map_err( plain_error )?;
+
+	Ok( HttpResponse::Accepted().finish() )
+}
+
+/// Stops following a channel, and deletes everything that we've stored of it.
+#[delete("/api/v1/subscriptions/{address}")]
+pub async fn unsubscribe( g: web::Data<Arc<Globals>>, p: web::Path<SubscriptionParams> ) -> error::Result<HttpResponse> {
+
+	let address = parse_address( &p.address )?;
+	html::unsubscribe( &g, &address ).await.map_err( plain_error )?;
+
+	Ok( HttpResponse::NoContent().finish() )
+}
+
+/// Returns the node that is connected to the swarm of the channel with the given address.
+async fn subscription_node( g: &Globals, address: &str ) -> error::Result<Node> {
+
+	let address = PublicKey::from_string( address )
+		.ok_or_else(|| error::ErrorBadRequest("Invalid channel address."))?;
+
+	g.subscriptions.read().await.as_ref()
+		.and_then(|s| s.node( &address ))
+		.ok_or_else(|| error::ErrorNotFound("Not connected to the swarm of this channel."))
+}
+
+/// Starts catching up with the swarm of a channel in the background.
+/// Responds with 409 Conflict if a sync is already going on.
+#[post("/api/v1/subscriptions/{address}/sync")]
+pub async fn subscription_sync( g: web::Data<Arc<Globals>>, p: web::Path<SubscriptionParams> ) -> error::Result<HttpResponse> {
+
+	let node = subscription_node( &g, &p.address ).await?;
+	if node.sync_status().running {
+		return Err( error::ErrorConflict("A sync is already running.") )
+	}
+
+	let address = p.address.clone();
+	actix_web::rt::spawn(async move {
+		if let Err(e) = node.sync().await {
+			warn!(channel = %address, "Unable to sync channel: {}", e);
+		}
+	});
+
+	Ok( HttpResponse::Accepted().finish() )
+}
+
+#[get("/api/v1/subscriptions/{address}/sync/status")]
+pub async fn subscription_sync_status( g: web::Data<Arc<Globals>>, p: web::Path<SubscriptionParams> ) -> error::Result<HttpResponse> {
+
+	let status = subscription_node( &g, &p.address ).await?.sync_status();
+
+	Ok( HttpResponse::Ok().json( SyncStatus {
+		running: status.running,
+		events_applied: status.events_applied,
+		posts_fetched: status.posts_fetched,
+		blocks_remaining: status.blocks_remaining,
+		peers: status.peers.iter().map(|p| p.to_string()).collect()
+	}))
+}
+
+/// Lists the number of received events that are waiting to be applied, and of requests that are waiting to be answered, for every peer of the swarm of a channel.
+#[get("/api/v1/subscriptions/{address}/queues")]
+pub async fn subscription_queues( g: web::Data<Arc<Globals>>, p: web::Path<SubscriptionParams> ) -> error::Result<HttpResponse> {
+
+	let node = subscription_node( &g, &p.address ).await?;
+	let requests = node.request_queue_depths();
+
+	Ok( HttpResponse::Ok().json( node.queue_depths().into_iter().map(|d| PeerQueue {
+		requests: requests.iter().filter(|r| r.source == d.source).map(|r| r.depth).sum(),
+		peer: d.source.to_string(),
+		depth: d.depth,
+		capacity: d.capacity
+	}).collect::<Vec<_>>() ) )
+}
+
+
+
+impl From<services::Error> for error::Error {
+	fn from( other: services::Error ) -> Self {
+		match other {
+			services::Error::EgoNotFound(_) => error::ErrorNotFound("Ego not found."),
+			services::Error::Unavailable(_, _) => {
+				error!("Gnunet error: {}", other);
+				error::ErrorServiceUnavailable("Gnunet is not available.")
+			},
+			services::Error::Request(_, _) => {
+				error!("Gnunet error: {}", other);
+				error::ErrorInternalServerError("Internal server error occurred")
+			}
+		}
+	}
+}
+
+impl From<swarm::Error> for error::Error {
+	fn from( other: swarm::Error ) -> Self {
+		warn!("Swarm error: {}", other);
+		error::ErrorBadGateway("Unable to retrieve the data from the swarm.")
+	}
+}
+
+impl From<persistence::Error> for error::Error {
+	fn from( other: persistence::Error ) -> Self {
+		error!("Persistence error: {}", other);
+		error::ErrorInternalServerError("Internal server error occurred")
+	}
+}
@@ -0,0 +1,143 @@
+//! Archival bundles of channels, so that they can be preserved, and verified later on, without QuartzNet or GNUnet.
+//!
+//! A bundle is a WARC file (ISO 28500) of resource records: the rendered pages, the signed events and posts in the form in which they are exchanged in the swarm, the attached files, the keys of the channel, and a manifest that lists all of them.
+
+use gnunet::crypto::HashCode;
+use serde::Serialize;
+
+
+
+/// The version of the layout of the bundle, which is raised when records are changed or removed.
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// How the records of a bundle can be verified, which is included in its manifest.
+pub const VERIFICATION: &str = "Hashes are SHA-512, written in GNUnet's base32 notation. \
+	Signatures are EdDSA signatures over a hash, with purpose 777, by the keys in keys.json. \
+	Every event record is an event id (u64, little endian), the bincode-encoded event type, a type byte and the bincode-encoded event message, which holds the hash and signature of its contents. \
+	Every post meta record is a bincode-encoded post: its id, the hash of its bincode-encoded meta data, the signature of that hash by its publisher, and the meta data, which holds the hash of the content record.";
+
+/// A record that has been written to the bundle, as it is listed in the manifest.
+#[derive(Serialize)]
+pub struct BundleEntry {
+	pub uri: String,
+	pub content_type: String,
+	pub size: usize,
+	/// The hash of the content of the record.
+	pub hash: String
+}
+
+/// The keys that the events and posts of the channel are signed with.
+#[derive(Serialize)]
+pub struct BundleKeys {
+	pub channel: String,
+	/// The keys that currently sign the events of the channel.
+	pub owners: Vec<String>,
+	/// The keys that may publish posts in the channel, including the channel itself.
+	pub publishers: Vec<String>
+}
+
+/// The last record of the bundle, which describes all records before it.
+#[derive(Serialize)]
+pub struct BundleManifest {
+	pub bundle_version: u32,
+	pub protocol_version: String,
+	pub channel: String,
+	/// In seconds since the UNIX epoch.
+	pub exported_at: u64,
+	pub verification: &'static str,
+	pub records: Vec<BundleEntry>
+}
+
+/// Writes the records of a WARC file into memory.
+pub struct WarcWriter {
+	data: Vec<u8>,
+	/// What the ids of the records start with, which makes them unique to this bundle.
+	id_prefix: String,
+	/// The time of the export, in the notation of WARC.
+	date: String,
+	record_count: u64,
+	entries: Vec<BundleEntry>
+}
+
+
+
+impl WarcWriter {
+
+	/// Starts a bundle that is made at the given time, in seconds since the UNIX epoch.
+	pub fn new( id_prefix: String, time: u64 ) -> Self {
+		Self {
+			data: Vec::new(),
+			id_prefix,
+			date: format_utc_datetime( time ),
+			record_count: 0,
+			entries: Vec::new()
+		}
+	}
+
+	/// Writes the `warcinfo` record, which describes the bundle as a whole.
+	/// This is supposed to be the first record.
+	pub fn write_info( &mut self, fields: &[(&str, String)] ) {
+		let mut content = String::new();
+		for (name, value) in fields {
+			content.push_str( &format!("{}: {}\r\n", name, value) );
+		}
+		self.write_record( "warcinfo", None, "application/warc-fields", content.as_bytes() );
+	}
+
+	/// Writes a `resource` record, and lists it for the manifest.
+	pub fn write_resource( &mut self, uri: &str, content_type: &str, content: &[u8] ) {
+		self.write_record( "resource", Some( uri ), content_type, content );
+		self.entries.push( BundleEntry {
+			uri: uri.to_owned(),
+			content_type: content_type.to_owned(),
+			size: content.len(),
+			hash: HashCode::generate( content ).to_string()
+		});
+	}
+
+	/// Takes the records that have been listed so far, to put them in the manifest.
+	pub fn take_entries( &mut self ) -> Vec<BundleEntry> {
+		std::mem::take( &mut self.entries )
+	}
+
+	pub fn finish( self ) -> Vec<u8> {
+		self.data
+	}
+
+	fn write_record( &mut self, warc_type: &str, uri: Option<&str>, content_type: &str, content: &[u8] ) {
+		self.record_count += 1;
+
+		let mut header = format!("WARC/1.1\r\nWARC-Type: {}\r\nWARC-Record-ID: <{}:{}>\r\nWARC-Date: {}\r\n",
+			warc_type, self.id_prefix, self.record_count, self.date);
+		if let Some(uri) = uri {
+			header.push_str( &format!("WARC-Target-URI: {}\r\n", uri) );
+		}
+		header.push_str( &format!("WARC-Block-Digest: gnunet-sha512:{}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+			HashCode::generate( content ), content_type, content.len()) );
+
+		self.data.extend_from_slice( header.as_bytes() );
+		self.data.extend_from_slice( content );
+		self.data.extend_from_slice( b"\r\n\r\n" );
+	}
+}
+
+
+
+/// Writes a time, in seconds since the UNIX epoch, in the notation of WARC (`YYYY-MM-DDThh:mm:ssZ`).
+pub fn format_utc_datetime( seconds: u64 ) -> String {
+	let days = (seconds / 86400) as i64;
+	let clock = seconds % 86400;
+
+	// The date of the day since the epoch, from Howard Hinnant's `civil_from_days`.
+	let z = days + 719468;
+	let era = if z >= 0 { z } else { z - 146096 } / 146097;
+	let day_of_era = z - era * 146097;
+	let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let mp = (5 * day_of_year + 2) / 153;
+	let day = day_of_year - (153 * mp + 2) / 5 + 1;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 };
+	let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+	format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, clock / 3600, clock % 3600 / 60, clock % 60)
+}
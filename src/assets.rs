@@ -0,0 +1,144 @@
+//! The files that the web interface is made of: the templates, and the static files like scripts and stylesheets.
+//!
+//! All of them are embedded in the binary, so that it works without the source tree.
+//! Each of them can be overridden by a file with the same path in a directory on disk, which is given by an environment variable.
+//! The template directory can be given in the configuration file as well.
+
+use std::{
+	borrow::Cow,
+	collections::BTreeSet,
+	env,
+	fs,
+	path::{Component, Path, PathBuf}
+};
+
+use rust_embed::RustEmbed;
+
+use crate::config;
+
+
+
+#[derive(RustEmbed)]
+#[folder = "templates/"]
+struct EmbeddedTemplates;
+
+#[derive(RustEmbed)]
+#[folder = "static/"]
+struct EmbeddedStatic;
+
+#[derive(Clone, Copy)]
+enum Kind {
+	Templates,
+	Static
+}
+
+/// A set of files that are looked up in the override directory first, and in the embedded files otherwise.
+pub struct Assets {
+	kind: Kind,
+	/// The directory with the files that override the embedded ones, if any.
+	dir: Option<PathBuf>
+}
+
+
+
+impl Assets {
+
+	/// The templates of the web interface, which can be overridden in the directory given by `QUARTZNET_TEMPLATES`, or by `template_dir` in the configuration file.
+	pub fn templates() -> Self {
+		let mut assets = Self::new( Kind::Templates, config::TEMPLATE_DIR_VAR );
+		if assets.dir.is_none() {
+			assets.dir = config::get().template_dir.clone();
+		}
+		assets
+	}
+
+	/// The static files of the web interface, which can be overridden in the directory given by `QUARTZNET_STATIC`.
+	pub fn static_files() -> Self {
+		Self::new( Kind::Static, config::STATIC_DIR_VAR )
+	}
+
+	fn new( kind: Kind, dir_var: &str ) -> Self {
+		Self {
+			kind,
+			dir: env::var_os( dir_var ).map( PathBuf::from )
+		}
+	}
+
+	/// The directory with the overriding files, if one has been configured.
+	pub fn dir( &self ) -> Option<&Path> {
+		self.dir.as_deref()
+	}
+
+	/// Returns the content of the file with the given path, relative to the root of the assets.
+	/// Paths that try to escape the root, like ones containing `..`, are not found.
+	pub fn get( &self, path: &str ) -> Option<Cow<'static, [u8]>> {
+		if !is_safe( path ) { return None }
+
+		if let Some(dir) = &self.dir {
+			if let Ok(content) = fs::read( dir.join( path ) ) {
+				return Some( Cow::Owned( content ) )
+			}
+		}
+
+		match self.kind {
+			Kind::Templates => EmbeddedTemplates::get( path ),
+			Kind::Static => EmbeddedStatic::get( path )
+		}
+	}
+
+	/// Lists the paths of all files, both the embedded ones and the overriding ones.
+	/// The paths use forward slashes.
+	pub fn list( &self ) -> Vec<String> {
+		let mut paths: BTreeSet<String> = match self.kind {
+			Kind::Templates => EmbeddedTemplates::iter().map(|p| p.into_owned()).collect(),
+			Kind::Static => EmbeddedStatic::iter().map(|p| p.into_owned()).collect()
+		};
+
+		if let Some(dir) = &self.dir {
+			collect_dir( dir, "", &mut paths );
+		}
+
+		paths.into_iter().collect()
+	}
+}
+
+/// Collects the paths of all files in the given directory and its subdirectories, prefixed with `prefix`.
+fn collect_dir( dir: &Path, prefix: &str, paths: &mut BTreeSet<String> ) {
+	let entries = match fs::read_dir( dir ) {
+		Err(_) => return,
+		Ok(e) => e
+	};
+
+	for entry in entries.flatten() {
+		let name = entry.file_name().to_string_lossy().into_owned();
+		let path = entry.path();
+		if path.is_dir() {
+			collect_dir( &path, &format!("{}{}/", prefix, name), paths );
+		}
+		else {
+			paths.insert( format!("{}{}", prefix, name) );
+		}
+	}
+}
+
+/// Whether the path stays within the root that it is relative to.
+fn is_safe( path: &str ) -> bool {
+	Path::new( path ).components().all(|c| match c {
+		Component::Normal(_) => true,
+		_ => false
+	})
+}
+
+/// Guesses the MIME type of a static file from its extension.
+pub fn mime_type( path: &str ) -> &'static str {
+	match Path::new( path ).extension().and_then(|e| e.to_str()) {
+		Some("css") => "text/css",
+		Some("html") => "text/html",
+		Some("js") => "text/javascript",
+		Some("json") => "application/json",
+		Some("png") => "image/png",
+		Some("svg") => "image/svg+xml",
+		Some("woff2") => "font/woff2",
+		_ => "application/octet-stream"
+	}
+}
@@ -0,0 +1,323 @@
+//! Protects the web interface against anyone who can reach it but isn't its administrator.
+//!
+//! Every page requires the administrator to be logged in with the admin password, except for the few that anyone needs to reach, see `is_protected`.
+//! A login starts a session, which is remembered by a cookie until it expires or the node restarts.
+//! Scripts and clients of the JSON API can give the password with HTTP basic authentication instead, the user name is ignored.
+//! Micropub clients give a token of the channel that they publish in instead, see `micropub`.
+//! The session cookie is `SameSite=Strict`, so other sites can't make the browser of the administrator submit forms to the node.
+//!
+//! With `local_only` in the configuration file, only requests from this computer are accepted at all, and those are trusted without a login.
+//!
+//! The admin password is chosen during the setup.
+//! Nodes that have been set up without one get a generated password when they start, which is written to the log once.
+
+use std::{
+	collections::HashMap,
+	future::Future,
+	net::IpAddr,
+	sync::{Mutex, RwLock},
+	time::{SystemTime, UNIX_EPOCH}
+};
+
+use actix_web::{
+	dev::{Service, ServiceRequest, ServiceResponse},
+	error::InternalError,
+	http::{header, Method},
+	HttpMessage,
+	HttpResponse
+};
+use futures::future::{self, Either};
+use gnunet::crypto::HashCode;
+use lazy_static::lazy_static;
+use rand::{distributions::Alphanumeric, Rng};
+use tracing::warn;
+
+use crate::{
+	config,
+	persistence::{self, audit::AuditAction},
+	setup
+};
+
+
+
+/// The name of the cookie that holds the token of a session.
+pub const SESSION_COOKIE: &str = "quartznet_session";
+/// The number of characters of a generated admin password.
+const GENERATED_PASSWORD_LENGTH: usize = 20;
+/// The number of characters of the token of a session.
+const SESSION_TOKEN_LENGTH: usize = 32;
+
+
+
+lazy_static! {
+	/// The salted hash of the admin password, as it is stored in the settings, once it is known.
+	static ref PASSWORD_HASH: RwLock<Option<String>> = RwLock::new( None );
+	/// The tokens of the sessions that are logged in, with the moment at which they expire in seconds since the UNIX epoch.
+	static ref SESSIONS: Mutex<HashMap<String, u64>> = Mutex::new( HashMap::new() );
+	/// The number of wrong passwords that have been given from an address, with the moment of the first of them in seconds since the UNIX epoch.
+	static ref FAILED_LOGINS: Mutex<HashMap<Option<IpAddr>, (u64, u32)>> = Mutex::new( HashMap::new() );
+	/// The passwords that have been accepted on the API, as hashed by `remembered_password`, with the moment at which they are forgotten in seconds since the UNIX epoch.
+	/// Clients of the API give the password with every request, which would otherwise take all rounds of PBKDF2 on the thread that serves the request every time.
+	static ref API_PASSWORDS: Mutex<HashMap<HashCode, u64>> = Mutex::new( HashMap::new() );
+	/// The key that accepted passwords are hashed with before they are remembered, which only lives as long as the process.
+	static ref API_PASSWORD_KEY: [u8; 32] = rand::thread_rng().gen();
+}
+
+
+
+/// The outcome of an attempt to log in with the admin password.
+pub enum LoginAttempt {
+	Accepted,
+	Refused,
+	/// Too many wrong passwords have been given from the same address lately, so the password wasn't checked.
+	Throttled
+}
+
+
+
+/// Loads the admin password, or generates one if the node has been set up without it.
+pub async fn load_password( db: &persistence::Handle ) -> persistence::Result<()> {
+
+	let hash = match db.load_setting( setup::SETTING_ADMIN_PASSWORD ).await? {
+		Some(h) => h,
+		None => {
+			// A setup that is in progress stores the password itself.
+			if db.load_setting( setup::SETTING_SETUP_COMPLETE ).await?.as_deref() != Some("true") {
+				return Ok(())
+			}
+			let password = generate_password();
+			let hash = setup::hash_password( &password );
+			db.store_setting( setup::SETTING_ADMIN_PASSWORD, &hash ).await?;
+			db.record_action( None, AuditAction::SettingChanged, setup::SETTING_ADMIN_PASSWORD ).await?;
+			warn!("No admin password was set, so one has been generated: {}", password);
+			warn!("Log in with it, and keep it somewhere safe, as it isn't shown again. `quartznet password` generates a new one.");
+			hash
+		}
+	};
+
+	set_password_hash( hash );
+	Ok(())
+}
+
+/// Starts using the given hash of the admin password, as made by `setup::hash_password`.
+/// The passwords that have been accepted on the API before have to be checked again.
+pub fn set_password_hash( hash: String ) {
+	*PASSWORD_HASH.write().unwrap() = Some( hash );
+	API_PASSWORDS.lock().unwrap().clear();
+}
+
+/// Generates a password that is hard to guess, but can still be typed over.
+pub fn generate_password() -> String {
+	rand::thread_rng().sample_iter( &Alphanumeric ).take( GENERATED_PASSWORD_LENGTH ).map( char::from ).collect()
+}
+
+/// Whether an admin password has been chosen or generated, which may have happened during a setup that didn't complete.
+pub fn has_password() -> bool {
+	PASSWORD_HASH.read().unwrap().is_some()
+}
+
+/// Returns whether the given password is the admin password.
+/// Without an admin password, no password is.
+pub fn check_password( password: &str ) -> bool {
+	match &*PASSWORD_HASH.read().unwrap() {
+		None => false,
+		Some(hash) => setup::verify_password( password, hash )
+	}
+}
+
+/// Checks the password given from the given address, unless too many wrong ones have been given from there within `config::LOGIN_ATTEMPTS_PERIOD`.
+pub fn attempt_login( address: Option<IpAddr>, password: &str ) -> LoginAttempt {
+	let now = now();
+	{
+		let mut failures = FAILED_LOGINS.lock().unwrap();
+		failures.retain(|_, (since, _)| *since + config::LOGIN_ATTEMPTS_PERIOD > now);
+		if failures.get( &address ).map(|(_, count)| *count >= config::LOGIN_ATTEMPTS_MAX).unwrap_or(false) {
+			return LoginAttempt::Throttled
+		}
+	}
+
+	if check_password( password ) {
+		FAILED_LOGINS.lock().unwrap().remove( &address );
+		LoginAttempt::Accepted
+	} else {
+		FAILED_LOGINS.lock().unwrap().entry( address ).or_insert(( now, 0 )).1 += 1;
+		LoginAttempt::Refused
+	}
+}
+
+/// Replaces the stored hash of the admin password if it was made in an older way than `setup::hash_password` makes them.
+/// This can only be done with the password itself, so it is done when the administrator logs in.
+pub async fn upgrade_password_hash( db: &persistence::Handle, password: &str ) -> persistence::Result<()> {
+
+	let outdated = PASSWORD_HASH.read().unwrap().as_deref().map( setup::needs_rehash ).unwrap_or(false);
+	if outdated {
+		let hash = setup::hash_password( password );
+		db.store_setting( setup::SETTING_ADMIN_PASSWORD, &hash ).await?;
+		set_password_hash( hash );
+	}
+	Ok(())
+}
+
+/// Starts a session, and returns its token.
+pub fn start_session() -> String {
+	let token: String = rand::thread_rng().sample_iter( &Alphanumeric ).take( SESSION_TOKEN_LENGTH ).map( char::from ).collect();
+	let now = now();
+
+	let mut sessions = SESSIONS.lock().unwrap();
+	sessions.retain(|_, expires| *expires > now);
+	sessions.insert( token.clone(), now + config::LOGIN_SESSION_DURATION );
+	token
+}
+
+/// Ends the session with the given token, if it exists.
+pub fn end_session( token: &str ) {
+	SESSIONS.lock().unwrap().remove( token );
+}
+
+/// The `Set-Cookie` header that remembers the session with the given token.
+pub fn session_cookie( token: &str ) -> String {
+	format!("{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Strict", SESSION_COOKIE, token, config::LOGIN_SESSION_DURATION)
+}
+
+/// The `Set-Cookie` header that makes the browser forget its session.
+pub fn expired_session_cookie() -> String {
+	format!("{}=; Path=/; Max-Age=0; HttpOnly; SameSite=Strict", SESSION_COOKIE)
+}
+
+/// Refuses the request if it needs the administrator to be logged in, and they aren't.
+/// With `local_only`, requests from elsewhere are refused altogether.
+pub fn guard<S, B>( request: ServiceRequest, service: &S ) -> impl Future<Output=Result<ServiceResponse<B>, actix_web::Error>> where
+	S: Service<ServiceRequest, Response=ServiceResponse<B>, Error=actix_web::Error>
+{
+	let local = request.peer_addr().map(|a| a.ip().is_loopback()).unwrap_or(false);
+	if config::get().local_only {
+		if !local {
+			let response = HttpResponse::Forbidden().body("This node only accepts connections from the computer that it runs on.");
+			return Either::Left( future::err( InternalError::from_response( "remote request", response ).into() ) )
+		}
+		return Either::Right( service.call( request ) )
+	}
+
+	if !is_protected( request.method(), request.path() ) || is_authenticated( &request ) {
+		return Either::Right( service.call( request ) )
+	}
+
+	let response = if request.path().starts_with("/api/") {
+		HttpResponse::Unauthorized()
+			.append_header(( header::WWW_AUTHENTICATE, "Basic realm=\"QuartzNet\"" ))
+			.body("Authentication required.")
+	} else if request.method() == Method::GET {
+		let next = match request.query_string() {
+			"" => request.path().to_owned(),
+			query => format!("{}?{}", request.path(), query)
+		};
+		HttpResponse::Found().append_header(( header::LOCATION, format!("/login?next={}", encode_query_value( &next )) )).finish()
+	} else {
+		HttpResponse::SeeOther().append_header(( header::LOCATION, "/login" )).finish()
+	};
+	Either::Left( future::err( InternalError::from_response( "not logged in", response ).into() ) )
+}
+
+/// Whether the route needs the administrator to be logged in.
+/// Every route does, except for the ones that anyone needs to be able to reach: logging in, the setup, the health checks, the static files, and what other servers fetch from the ActivityPub actors of the channels.
+/// A route that isn't listed here is protected, so that a new page can't give away anything by accident.
+fn is_protected( method: &Method, path: &str ) -> bool {
+
+	// The setup protects itself, see `web::setup_post`, and logging in can't require being logged in.
+	if path == "/login" || path == "/setup" {
+		return false
+	}
+	// Micropub clients give a token of their own, which the endpoint checks itself.
+	if path == "/api/micropub" {
+		return false
+	}
+	// Activities are delivered by other servers, and are verified by their signatures.
+	if method == Method::POST {
+		return !is_channel_route( path, &["inbox"] )
+	}
+	if !(method == Method::GET || method == Method::HEAD) {
+		return true
+	}
+
+	if path == "/healthz" || path == "/readyz" || path == "/favicon.svg" || path.starts_with("/static/") {
+		return false
+	}
+	// Other servers look up the actors of the channels, and fetch what the actors refer to.
+	if path == "/.well-known/webfinger" {
+		return false
+	}
+	!is_channel_route( path, &["actor", "outbox", "followers", "icon.svg"] )
+}
+
+/// Whether the path is `/channel/{address}/{name}` with one of the given names.
+fn is_channel_route( path: &str, names: &[&str] ) -> bool {
+	let mut segments = path.trim_start_matches('/').split('/');
+	segments.next() == Some("channel")
+		&& segments.next().map(|a| a.len() > 0).unwrap_or(false)
+		&& segments.next().map(|n| names.contains( &n )).unwrap_or(false)
+		&& segments.next().is_none()
+}
+
+/// Whether the request belongs to a session, or gives the admin password.
+/// The password is only accepted on the API, because browsers send the basic authentication that they remember along with requests from other sites, which would get around the protection of the session cookie.
+fn is_authenticated( request: &ServiceRequest ) -> bool {
+
+	if let Some(cookie) = request.cookie( SESSION_COOKIE ) {
+		let now = now();
+		if SESSIONS.lock().unwrap().get( cookie.value() ).map(|expires| *expires > now).unwrap_or(false) {
+			return true
+		}
+	}
+
+	if !request.path().starts_with("/api/") {
+		return false
+	}
+	let credentials = request.headers().get( header::AUTHORIZATION )
+		.and_then(|h| h.to_str().ok())
+		.and_then(|h| h.strip_prefix("Basic "))
+		.and_then(|c| base64::decode( c.trim() ).ok())
+		.and_then(|c| String::from_utf8( c ).ok());
+	match credentials {
+		None => false,
+		Some(c) => match c.find(':') {
+			None => false,
+			Some(i) => attempt_api_login( request.peer_addr().map(|a| a.ip()), &c[(i+1)..] )
+		}
+	}
+}
+
+/// Checks a password that is given with a request to the API, like `attempt_login` does.
+/// Once the password has been accepted, it is remembered for as long as a session lasts, so that it isn't hashed for every request.
+fn attempt_api_login( address: Option<IpAddr>, password: &str ) -> bool {
+	let remembered = remembered_password( password );
+	let now = now();
+	if API_PASSWORDS.lock().unwrap().get( &remembered ).map(|expires| *expires > now).unwrap_or(false) {
+		return true
+	}
+
+	match attempt_login( address, password ) {
+		LoginAttempt::Accepted => {
+			let mut passwords = API_PASSWORDS.lock().unwrap();
+			passwords.retain(|_, expires| *expires > now);
+			passwords.insert( remembered, now + config::LOGIN_SESSION_DURATION );
+			true
+		},
+		_ => false
+	}
+}
+
+/// Hashes a password with the key of this process, which is quick enough to do for every request, but doesn't leave the password itself in memory.
+fn remembered_password( password: &str ) -> HashCode {
+	let mut data = API_PASSWORD_KEY.to_vec();
+	data.extend_from_slice( password.as_bytes() );
+	HashCode::generate( &data )
+}
+
+/// Escapes the characters that would end a value in the query string of a URL.
+fn encode_query_value( value: &str ) -> String {
+	value.replace('%', "%25").replace('&', "%26").replace('+', "%2B").replace('#', "%23").replace('?', "%3F").replace(' ', "%20")
+}
+
+fn now() -> u64 {
+	SystemTime::now().duration_since( UNIX_EPOCH ).unwrap().as_secs()
+}
@@ -0,0 +1,117 @@
+//! The bus on which the changes to posts that arrive from the swarms are announced, so that open web pages can show them right away.
+//!
+//! Listeners listen to the changes of a single channel.
+//! The notifications that are recorded while the changes are applied are announced on a bus of their own, which has no channel to listen to.
+//! Listeners that have gone away are dropped the next time something is announced.
+
+use std::sync::Mutex;
+
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use gnunet::identity::PublicKey;
+use lazy_static::lazy_static;
+
+use crate::persistence::notification::Notification;
+
+
+
+#[derive(Clone, Copy)]
+pub enum PostChangeKind {
+	Published,
+	Revised,
+	Forgotten,
+	/// Someone has commented on the post.
+	Commented,
+	/// Someone has reacted to the post.
+	Reacted
+}
+
+/// A change to a post, that has been applied to the database already.
+#[derive(Clone)]
+pub struct PostChange {
+	/// The channel that the post belongs to.
+	pub channel: PublicKey,
+	pub publisher: PublicKey,
+	pub post_id: u64,
+	pub kind: PostChangeKind
+}
+
+/// A notification that has just been recorded.
+#[derive(Clone)]
+pub struct NewNotification {
+	pub notification: Notification,
+	/// The number of notifications that are unread, now that this one has been added.
+	pub unread: u64
+}
+
+pub struct PostBus {
+	listeners: Mutex<Vec<(PublicKey, UnboundedSender<PostChange>)>>
+}
+
+pub struct NotificationBus {
+	listeners: Mutex<Vec<UnboundedSender<NewNotification>>>
+}
+
+
+
+lazy_static! {
+	/// The bus that the swarms of all channels announce their changes on.
+	pub static ref POSTS: PostBus = PostBus::new();
+	/// The bus that the swarms of all channels announce their notifications on.
+	pub static ref NOTIFICATIONS: NotificationBus = NotificationBus::new();
+}
+
+impl PostBus {
+
+	pub fn new() -> Self {
+		Self {
+			listeners: Mutex::new( Vec::new() )
+		}
+	}
+
+	/// Starts listening to the changes to the posts of the given channel.
+	/// The listener stops when the receiver is dropped.
+	pub fn listen( &self, channel: PublicKey ) -> UnboundedReceiver<PostChange> {
+		let (sender, receiver) = mpsc::unbounded();
+		self.listeners.lock().unwrap().push(( channel, sender ));
+		receiver
+	}
+
+	/// Announces the change to everyone that listens to its channel.
+	pub fn announce( &self, change: PostChange ) {
+		let mut listeners = self.listeners.lock().unwrap();
+		listeners.retain(|(_, sender)| !sender.is_closed());
+
+		for (channel, sender) in listeners.iter() {
+			if *channel == change.channel {
+				let _ = sender.unbounded_send( change.clone() );
+			}
+		}
+	}
+}
+
+impl NotificationBus {
+
+	pub fn new() -> Self {
+		Self {
+			listeners: Mutex::new( Vec::new() )
+		}
+	}
+
+	/// Starts listening to the notifications.
+	/// The listener stops when the receiver is dropped.
+	pub fn listen( &self ) -> UnboundedReceiver<NewNotification> {
+		let (sender, receiver) = mpsc::unbounded();
+		self.listeners.lock().unwrap().push( sender );
+		receiver
+	}
+
+	/// Announces the notification to every listener.
+	pub fn announce( &self, notification: NewNotification ) {
+		let mut listeners = self.listeners.lock().unwrap();
+		listeners.retain(|sender| !sender.is_closed());
+
+		for sender in listeners.iter() {
+			let _ = sender.unbounded_send( notification.clone() );
+		}
+	}
+}
@@ -0,0 +1,711 @@
+//! The command line of the node, so that it can be administered and scripted without the web interface.
+//!
+//! Without a command, or with `serve`, the node itself is run.
+//! `doctor` checks everything that the node depends on.
+//! The other commands work on the database of the node, and exit when they are done:
+//! `channel create`, `post publish` and `post import` need gnunet for the keys of our egos, the others don't.
+//! `password` replaces the admin password of the web interface, `maintenance` checks and optimizes the database, which the node otherwise does every so often by itself.
+//! `channel delete` closes one of our own channels and deletes it along with its ego, `unsubscribe` deletes everything that is stored of a channel that we follow.
+//! `channel export` writes a static copy of a channel, `channel backup` and `channel restore` move all that is stored for a channel to another node, `export` and `import` move the channels that we follow to another node, `peers` works on the bad peer store and the reputations of the peers.
+//! Exports are JSON documents, which can be imported on another node, or on the same node after editing them, backups are binary archives, see `persistence::backup`.
+
+use std::{
+	fmt,
+	fs,
+	io::{self, Read, Write},
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration
+};
+
+use async_std::future::timeout;
+
+use clap::{Args, Parser, Subcommand};
+use gnunet::identity::PublicKey;
+use serde::*;
+
+use crate::{
+	auth,
+	config,
+	daemon,
+	feed_import,
+	logging,
+	persistence::{
+		self,
+		audit::AuditAction,
+		backup::BackupError,
+		peer::{now, BadPeer, StoredReputation}
+	},
+	post::ContentFormat,
+	services::{self, GnunetServices},
+	setup,
+	share::ShareLink,
+	static_site,
+	subscriptions,
+	swarm::{BadPeerStore, Reputation},
+	templates::Templates,
+	RETURN_CODE_CONFIG,
+	RETURN_CODE_GNUNET,
+	RETURN_CODE_PERSISTENCE,
+	RETURN_CODE_UNEXPECTED,
+	RETURN_CODE_USAGE
+};
+
+
+
+/// The client for the decentralized censorship-resistant blogging network Quartznet.
+#[derive(Parser)]
+#[clap(name = "quartznet", after_help = "Without a file, exports are written to the standard output and imports are read from the standard input.")]
+pub struct Cli {
+	/// The directory in which the database and all other data is stored.
+	#[clap(long, global = true, value_name = "path")]
+	pub data_dir: Option<PathBuf>,
+	#[clap(subcommand)]
+	pub command: Option<Command>
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+	/// Runs the node, which is also what happens without a command.
+	Serve( ServeArgs ),
+	/// Checks everything that the node depends on, and tells what to do about the problems it finds.
+	/// The node should be stopped first.
+	Doctor {
+		/// Leaves out joining the swarms of the channels that we follow, which can take a while.
+		#[clap(long)]
+		skip_swarms: bool
+	},
+	#[clap(flatten)]
+	Admin( AdminCommand )
+}
+
+/// The options of the node itself, which take precedence over the configuration file.
+#[derive(Args, Default)]
+pub struct ServeArgs {
+	/// The address on which the web interface is served.
+	#[clap(long, value_name = "address")]
+	pub bind: Option<String>,
+	/// The port on which the web interface is served.
+	#[clap(long, value_name = "port")]
+	pub port: Option<u16>
+}
+
+/// The commands that work on the database, without running the node.
+#[derive(Subcommand)]
+pub enum AdminCommand {
+	/// Manages our own channels.
+	#[clap(subcommand)]
+	Channel( ChannelCommand ),
+	/// Writes posts in our own channels.
+	#[clap(subcommand)]
+	Post( PostCommand ),
+	/// Follows a channel, given by its address or by a share link.
+	Subscribe {
+		address: String
+	},
+	/// Stops following a channel, and deletes everything that is stored of it, including the files of its posts.
+	Unsubscribe {
+		address: String
+	},
+	/// Exports the channels that we follow, with the peers that we know for their swarms.
+	Export {
+		file: Option<PathBuf>
+	},
+	/// Follows the channels of an export.
+	Import {
+		file: Option<PathBuf>
+	},
+	/// Manages the bans and reputations of the peers.
+	#[clap(subcommand)]
+	Peers( PeersCommand ),
+	/// Replaces the admin password of the web interface with a generated one, which is printed.
+	/// A running node keeps using the old password until it is restarted.
+	Password,
+	/// Checks the integrity of the database, updates its statistics and gives its free space back to the file system.
+	/// The node should be stopped first, as this locks the database while it runs.
+	Maintenance
+}
+
+#[derive(Subcommand)]
+pub enum ChannelCommand {
+	/// Creates a channel, along with the ego that it is published with.
+	Create {
+		/// The name of the ego.
+		name: String,
+		/// Encrypts the messages of the channel, so that only those with its invite code can read them.
+		#[clap(long)]
+		private: bool
+	},
+	/// Tells the swarm of a channel that it has been closed, and then deletes the channel along with its ego and private key.
+	/// The node should be stopped first, because the swarm is joined with the same peer identity.
+	Delete {
+		/// The name of the ego.
+		name: String,
+		/// Deletes the channel even if the closing hasn't reached any peer, in which case the subscribers never learn that it has ended.
+		#[clap(long)]
+		force: bool
+	},
+	/// Writes a static HTML copy of a channel into a directory, with a page for every post that is stored on this node.
+	/// The copy can be opened straight from the file system, or served by any web server.
+	Export {
+		/// The address of the channel.
+		address: String,
+		dir: PathBuf
+	},
+	/// Backs up everything that is stored for a channel into a single file, which can be restored on another node.
+	/// The keys of our own channels are kept by gnunet, and need to be moved separately.
+	Backup {
+		/// The address of the channel.
+		address: String,
+		file: Option<PathBuf>
+	},
+	/// Restores a channel from a backup, after verifying every hash and signature in it.
+	/// The channel must not be known to this node yet.
+	Restore {
+		file: Option<PathBuf>
+	}
+}
+
+#[derive(Subcommand)]
+pub enum PostCommand {
+	/// Publishes the content of a file as a new post.
+	/// Files ending in .md are Markdown, files ending in .rst are reStructuredText, all others are plain text.
+	Publish {
+		/// The name of the ego of the channel.
+		channel: String,
+		file: PathBuf,
+		/// A tag of the post, which can be given more than once.
+		#[clap(long = "tag", value_name = "tag")]
+		tags: Vec<String>
+	},
+	/// Imports the entries of an RSS or Atom feed as posts, with the dates and categories that they have in the feed.
+	/// Entries that have been imported before are skipped, so an old blog can be imported again to catch up with it.
+	Import {
+		/// The name of the ego of the channel.
+		channel: String,
+		url: String
+	}
+}
+
+#[derive(Subcommand)]
+pub enum PeersCommand {
+	/// Lists every peer that is flagged or has a reputation, the best ones first.
+	List,
+	Ban {
+		address: String,
+		reason: Option<String>
+	},
+	Unban {
+		address: String
+	},
+	Export {
+		file: Option<PathBuf>
+	},
+	Import {
+		file: Option<PathBuf>
+	}
+}
+
+#[derive(Debug)]
+pub enum Error {
+	/// The command can't be carried out as it is given, the message tells why.
+	Usage( String ),
+	Config( config::Error ),
+	DataDir( io::Error ),
+	Persistence( persistence::Error ),
+	Gnunet( services::Error ),
+	/// The file to export to or import from could not be written or read.
+	Io( io::Error ),
+	/// The file to import is not a valid export.
+	Json( serde_json::Error ),
+	/// `doctor` or `maintenance` found the given number of problems.
+	Problems( usize )
+}
+
+/// The channels that we follow, in the form in which they are exported.
+#[derive(Deserialize, Serialize)]
+struct SubscriptionsExport {
+	subscriptions: Vec<SubscriptionEntry>
+}
+
+#[derive(Deserialize, Serialize)]
+struct SubscriptionEntry {
+	address: String,
+	/// The peers that have been remembered for the swarm of the channel.
+	peers: Vec<String>
+}
+
+/// All that is known of the peers, in the form in which it is exported.
+#[derive(Deserialize, Serialize)]
+struct PeersExport {
+	bad_peers: Vec<BadPeerEntry>,
+	reputations: Vec<ReputationEntry>
+}
+
+#[derive(Deserialize, Serialize)]
+struct BadPeerEntry {
+	address: String,
+	reason: String,
+	offenses: u32,
+	/// In milliseconds since the UNIX epoch.
+	flagged_timestamp: u64,
+	/// In milliseconds since the UNIX epoch.
+	expires_timestamp: u64
+}
+
+#[derive(Deserialize, Serialize)]
+struct ReputationEntry {
+	address: String,
+	score: f64,
+	/// In milliseconds since the UNIX epoch.
+	updated_timestamp: u64
+}
+
+
+
+/// Runs the command, on the node with the given data directory, if any.
+pub async fn run( command: AdminCommand, data_dir: Option<PathBuf> ) -> Result<(), Error> {
+	config::load().map_err( Error::Config )?;
+	logging::init();
+	setup::load_data_dir( data_dir ).map_err( Error::DataDir )?;
+	if !persistence::database_exists() {
+		return Err( Error::Usage("The node hasn't been set up yet.".to_owned()) )
+	}
+
+	// Gnunet is only reached by the commands that need it.
+	let services = Arc::new( GnunetServices::new( gnunet::Handle::default() ) );
+	let db = persistence::Handle::connect( services.clone() ).await.map_err(|e| persistence::Error::Database( e ))?;
+
+	match command {
+		AdminCommand::Channel( ChannelCommand::Create { name, private } ) => create_channel( &services, db, &name, !private ).await,
+		AdminCommand::Channel( ChannelCommand::Delete { name, force } ) => delete_channel( &services, &db, &name, force ).await,
+		AdminCommand::Channel( ChannelCommand::Export { address, dir } ) => export_channel( &db, &address, &dir ).await,
+		AdminCommand::Channel( ChannelCommand::Backup { address, file } ) => back_up_channel( &db, &address, file.as_deref() ).await,
+		AdminCommand::Channel( ChannelCommand::Restore { file } ) => restore_channel( &db, file.as_deref() ).await,
+		AdminCommand::Post( PostCommand::Publish { channel, file, tags } ) => publish_post( &services, &db, &channel, &file, &tags ).await,
+		AdminCommand::Post( PostCommand::Import { channel, url } ) => import_feed( &services, &db, &channel, &url ).await,
+		AdminCommand::Subscribe { address } => subscribe( &db, &address ).await,
+		AdminCommand::Unsubscribe { address } => unsubscribe( &db, &address ).await,
+		AdminCommand::Export { file } => export_subscriptions( &db, file.as_deref() ).await,
+		AdminCommand::Import { file } => import_subscriptions( &db, file.as_deref() ).await,
+		AdminCommand::Peers( PeersCommand::List ) => list_peers( &db ).await,
+		AdminCommand::Peers( PeersCommand::Ban { address, reason } ) => ban_peer( &db, &address, reason.as_deref().unwrap_or("banned by the operator") ).await,
+		AdminCommand::Peers( PeersCommand::Unban { address } ) => unban_peer( &db, &address ).await,
+		AdminCommand::Peers( PeersCommand::Export { file } ) => export_peers( &db, file.as_deref() ).await,
+		AdminCommand::Peers( PeersCommand::Import { file } ) => import_peers( &db, file.as_deref() ).await,
+		AdminCommand::Password => reset_password( &db ).await,
+		AdminCommand::Maintenance => maintain_database( &db ).await
+	}
+}
+
+/// Writes an export to the file, or to the standard output if there is none.
+fn write_export<T: Serialize>( export: &T, file: Option<&Path> ) -> Result<(), Error> {
+	let json = serde_json::to_vec_pretty( export ).map_err( Error::Json )?;
+	match file {
+		None => io::stdout().write_all( &json ).map_err( Error::Io ),
+		Some(path) => fs::write( path, &json ).map_err( Error::Io )
+	}
+}
+
+/// Reads an export from the file, or from the standard input if there is none.
+fn read_export<T: de::DeserializeOwned>( file: Option<&Path> ) -> Result<T, Error> {
+	let json = match file {
+		None => {
+			let mut json = Vec::new();
+			io::stdin().read_to_end( &mut json ).map_err( Error::Io )?;
+			json
+		},
+		Some(path) => fs::read( path ).map_err( Error::Io )?
+	};
+	serde_json::from_slice( &json ).map_err( Error::Json )
+}
+
+fn parse_address( address: &str ) -> Result<PublicKey, Error> {
+	PublicKey::from_string( address )
+		.ok_or_else(|| Error::Usage( format!("Invalid peer address: {}", address) ))
+}
+
+/// Creates a channel and prints its address.
+async fn create_channel( services: &GnunetServices, mut db: persistence::Handle, name: &str, public: bool ) -> Result<(), Error> {
+	services.check().await.map_err( Error::Gnunet )?;
+
+	let channel = match db.create_channel( name, public ).await {
+		Err(persistence::Error::AlreadyExists) => return Err( Error::Usage("An ego with that name already exists.".to_owned()) ),
+		Err(persistence::Error::EgoConflict(persistence::EgoConflict::InUse(_))) => {
+			return Err( Error::Usage("You already have a channel with that name.".to_owned()) )
+		},
+		Err(persistence::Error::EgoConflict(persistence::EgoConflict::Unused(_))) => {
+			return Err( Error::Usage("An ego with that name already exists, but it has no channel. It can be adopted in the web interface.".to_owned()) )
+		},
+		Err(e) => return Err( e.into() ),
+		Ok(c) => c
+	};
+	let address = channel.load_address().await?;
+	db.record_action( Some( name ), AuditAction::ChannelCreated, &address.to_string() ).await?;
+
+	println!("{}", address);
+	Ok(())
+}
+
+/// Joins the swarm of the channel of the given ego to tell it that the channel has been closed, and then deletes the channel and the ego.
+/// Unless `force` is given, nothing is deleted if the closing doesn't reach any peer, and it is published by the node when it runs again instead.
+async fn delete_channel( services: &GnunetServices, db: &persistence::Handle, ego: &str, force: bool ) -> Result<(), Error> {
+	services.check().await.map_err( Error::Gnunet )?;
+
+	let private_key = services.lookup_ego( ego ).await.map_err( Error::Gnunet )?;
+	let address = private_key.extract_public().unwrap();
+	let channel = db.clone().get_channel( &address ).await?
+		.ok_or_else(|| Error::Usage( format!("Ego {} has no channel.", ego) ))?;
+
+	// Without the DHT, the swarm can still be joined through the peers that we know.
+	let node = match (db.load_subscription( &address ).await?, services.transport().await) {
+		(Some(sub), Ok(transport)) => {
+			let discovery = services.discovery().await.ok();
+			let relay_power = subscriptions::load_relay_power( db ).await?;
+			let connecting = sub.find_swarm_connection( channel, transport, discovery, relay_power, |_, _| {} );
+			timeout( Duration::from_secs( config::CLOSING_CONNECT_TIMEOUT ), connecting ).await.ok().flatten()
+		},
+		_ => None
+	};
+
+	let closed = daemon::close_channel( db, ego, &private_key, node.as_ref() ).await;
+	if let Some(node) = node {
+		node.disconnect().await;
+	}
+	let (channel, announced) = closed.map_err( daemon_error )?;
+	if !announced && !force {
+		return Err( Error::Usage( "The channel has been closed, but none of the peers of its swarm could be told. \
+			The node tells them once it runs again, try again after that, or delete the channel with --force.".to_owned() ) )
+	}
+
+	daemon::delete_closed_channel( services, db, ego, channel ).await.map_err( daemon_error )?;
+	Ok(())
+}
+
+/// Stops following a channel, and deletes everything that is stored of it.
+/// The node leaves its swarm the next time it starts.
+async fn unsubscribe( db: &persistence::Handle, address: &str ) -> Result<(), Error> {
+	let address = PublicKey::from_string( address )
+		.ok_or_else(|| Error::Usage( format!("Invalid channel address: {}", address) ))?;
+
+	if let Some(timeline) = db.get_timeline( &address ).await? {
+		if let Some(ego) = timeline.get_my_ego().await? {
+			return Err( Error::Usage( format!("Channel {} is our own, delete it with `channel delete {}` instead.", address, ego) ) )
+		}
+	}
+	let channel = db.clone().get_channel( &address ).await?
+		.ok_or_else(|| Error::Usage( format!("Not subscribed to channel {}.", address) ))?;
+
+	let (files, blocks) = channel.purge().await?;
+	db.record_action( None, AuditAction::Unsubscribed, &address.to_string() ).await?;
+
+	eprintln!("Deleted {} file(s) and {} block(s) that no other channel uses.", files, blocks);
+	Ok(())
+}
+
+fn daemon_error( e: daemon::Error ) -> Error {
+	match e {
+		daemon::Error::Invalid( message ) => Error::Usage( message ),
+		daemon::Error::Gnunet( e ) => Error::Gnunet( e ),
+		daemon::Error::Persistence( e ) => Error::Persistence( e ),
+		e => Error::Usage( e.to_string() )
+	}
+}
+
+/// Writes a static copy of a channel into the directory.
+async fn export_channel( db: &persistence::Handle, address: &str, dir: &Path ) -> Result<(), Error> {
+	let address = PublicKey::from_string( address )
+		.ok_or_else(|| Error::Usage( format!("Invalid channel address: {}", address) ))?;
+	let templates = Templates::load().map_err(|e| Error::Usage( format!("Unable to load the templates: {}", e) ))?;
+
+	let site = static_site::export( db, &templates, &address ).await.map_err(|e| match e {
+		static_site::Error::Persistence( e ) => Error::Persistence( e ),
+		e => Error::Usage( e.to_string() )
+	})?;
+	site.write_to( dir ).map_err( Error::Io )?;
+	Ok(())
+}
+
+/// Writes a backup of a channel to the file, or to the standard output if there is none.
+async fn back_up_channel( db: &persistence::Handle, address: &str, file: Option<&Path> ) -> Result<(), Error> {
+	let address = PublicKey::from_string( address )
+		.ok_or_else(|| Error::Usage( format!("Invalid channel address: {}", address) ))?;
+
+	let backup = match db.export_channel( &address ).await {
+		Err(persistence::Error::NotFound) => return Err( Error::Usage( format!("Unknown channel: {}", address) ) ),
+		result => result?
+	};
+	match file {
+		None => io::stdout().write_all( &backup ).map_err( Error::Io ),
+		Some(path) => fs::write( path, &backup ).map_err( Error::Io )
+	}
+}
+
+/// Restores a channel from a backup in the file, or from the standard input if there is none, and prints its address.
+async fn restore_channel( db: &persistence::Handle, file: Option<&Path> ) -> Result<(), Error> {
+	let backup = match file {
+		None => {
+			let mut backup = Vec::new();
+			io::stdin().read_to_end( &mut backup ).map_err( Error::Io )?;
+			backup
+		},
+		Some(path) => fs::read( path ).map_err( Error::Io )?
+	};
+
+	let address = db.import_channel( &backup ).await.map_err(|e| match e {
+		BackupError::Persistence( e ) => Error::Persistence( e ),
+		e => Error::Usage( e.to_string() )
+	})?;
+	db.record_action( None, AuditAction::ChannelRestored, &address.to_string() ).await?;
+
+	println!("{}", address);
+	Ok(())
+}
+
+/// Publishes the content of a file in the channel of the given ego, with the defaults of the ego added to it, and prints the id of the post.
+async fn publish_post( services: &GnunetServices, db: &persistence::Handle, ego: &str, file: &Path, tags: &[String] ) -> Result<(), Error> {
+	services.check().await.map_err( Error::Gnunet )?;
+
+	let content = fs::read_to_string( file ).map_err( Error::Io )?;
+	let format = match file.extension().and_then(|e| e.to_str()) {
+		Some("md") | Some("markdown") => ContentFormat::Markdown,
+		Some("rst") => ContentFormat::ReStructuredText,
+		_ => ContentFormat::Plain
+	};
+
+	let post = daemon::publish_post( services, db, ego, &content, format, tags ).await.map_err(|e| match e {
+		daemon::Error::Invalid( message ) => Error::Usage( message ),
+		daemon::Error::Gnunet( e ) => Error::Gnunet( e ),
+		daemon::Error::Persistence( e ) => Error::Persistence( e ),
+		e => Error::Usage( e.to_string() )
+	})?;
+
+	println!("{}", post.id);
+	Ok(())
+}
+
+/// Imports the entries of a feed as posts in the channel of the given ego, and prints how many there were.
+async fn import_feed( services: &GnunetServices, db: &persistence::Handle, ego: &str, url: &str ) -> Result<(), Error> {
+	services.check().await.map_err( Error::Gnunet )?;
+
+	let private_key = services.lookup_ego( ego ).await.map_err( Error::Gnunet )?;
+	let report = feed_import::import_feed( db, &private_key, ego, url ).await.map_err(|e| match e {
+		feed_import::Error::Persistence( persistence::Error::NotFound ) => Error::Usage( format!("Ego {} has no channel.", ego) ),
+		feed_import::Error::Persistence( e ) => Error::Persistence( e ),
+		e => Error::Usage( e.to_string() )
+	})?;
+
+	println!("Imported {} post(s), skipped {} entries that were imported before.", report.imported, report.skipped);
+	Ok(())
+}
+
+/// Stores the subscription to a channel.
+/// The node joins its swarm the next time it starts.
+async fn subscribe( db: &persistence::Handle, address: &str ) -> Result<(), Error> {
+	let link = ShareLink::parse( address )
+		.ok_or_else(|| Error::Usage( format!("Invalid channel address: {}", address) ))?;
+
+	if !db.subscribe( &link.address, &link.peers ).await?.1 {
+		eprintln!("Channel {} was already followed.", link.address);
+	}
+	Ok(())
+}
+
+/// Generates a new admin password, stores its hash, and prints it.
+async fn reset_password( db: &persistence::Handle ) -> Result<(), Error> {
+	let password = auth::generate_password();
+	db.store_setting( setup::SETTING_ADMIN_PASSWORD, &setup::hash_password( &password ) ).await?;
+	db.record_action( None, AuditAction::SettingChanged, setup::SETTING_ADMIN_PASSWORD ).await?;
+
+	println!("{}", password);
+	Ok(())
+}
+
+/// Maintains the database, and prints what has been done.
+/// Nothing is changed if the database turns out to be corrupt.
+async fn maintain_database( db: &persistence::Handle ) -> Result<(), Error> {
+	let report = db.maintain().await?;
+	if !report.integrity_errors.is_empty() {
+		for problem in &report.integrity_errors {
+			println!("{}", problem);
+		}
+		return Err( Error::Problems( report.integrity_errors.len() ) )
+	}
+
+	if report.converted {
+		println!("Converted the database to incremental vacuuming.");
+	}
+	println!("The database is intact, {} KiB freed.", report.freed_bytes / 1024);
+	Ok(())
+}
+
+/// Exports the channels that we follow, which doesn't include our own.
+async fn export_subscriptions( db: &persistence::Handle, file: Option<&Path> ) -> Result<(), Error> {
+	let mut subscriptions = Vec::new();
+	for address in db.list_subscriptions().await? {
+		if let Some(timeline) = db.get_timeline( &address ).await? {
+			if timeline.get_my_ego().await?.is_some() { continue }
+		}
+		let peers = match db.load_subscription( &address ).await? {
+			None => Vec::new(),
+			Some(sub) => sub.cached_peers.iter().map(|p| p.to_string()).collect()
+		};
+		subscriptions.push( SubscriptionEntry { address: address.to_string(), peers } );
+	}
+
+	write_export( &SubscriptionsExport { subscriptions }, file )
+}
+
+/// Follows all channels of an export, and remembers the peers that it has for them.
+/// The export is checked completely before anything is stored.
+async fn import_subscriptions( db: &persistence::Handle, file: Option<&Path> ) -> Result<(), Error> {
+	let export: SubscriptionsExport = read_export( file )?;
+
+	let mut subscriptions = Vec::with_capacity( export.subscriptions.len() );
+	for entry in export.subscriptions {
+		let mut peers = Vec::with_capacity( entry.peers.len() );
+		for peer in &entry.peers {
+			peers.push( parse_address( peer )? );
+		}
+		subscriptions.push(( parse_address( &entry.address )?, peers ));
+	}
+
+	let mut new = 0;
+	for (address, peers) in &subscriptions {
+		if db.subscribe( address, peers ).await?.1 {
+			new += 1;
+		}
+	}
+	println!("Imported {} subscription(s), of which {} new.", subscriptions.len(), new);
+	Ok(())
+}
+
+/// Prints every peer that is flagged or has a reputation, with its current score, the best ones first.
+async fn list_peers( db: &persistence::Handle ) -> Result<(), Error> {
+	let bad_peers = db.list_bad_peers().await?;
+	let mut addresses: Vec<PublicKey> = db.list_reputations().await?.into_iter().map(|r| r.address).collect();
+	for bad in &bad_peers {
+		if !addresses.contains( &bad.address ) {
+			addresses.push( bad.address.clone() );
+		}
+	}
+
+	let reputation = Reputation::new( db.clone() );
+	let now = now() as u64;
+	for address in reputation.rank( &addresses ).await? {
+		let score = reputation.score( &address ).await?;
+		match bad_peers.iter().find(|b| b.address == address).filter(|b| b.expires > now) {
+			None => println!("{}\t{:.2}", address, score),
+			Some(bad) => println!("{}\t{:.2}\tbanned for {} more minutes after {} offense(s): {}",
+				address, score, (bad.expires - now + 59_999) / 60_000, bad.offenses, bad.reason)
+		}
+	}
+	Ok(())
+}
+
+async fn ban_peer( db: &persistence::Handle, address: &str, reason: &str ) -> Result<(), Error> {
+	let address = parse_address( address )?;
+
+	BadPeerStore::load( db.clone() ).await?.ban( &address, reason ).await?;
+	Ok(())
+}
+
+async fn unban_peer( db: &persistence::Handle, address: &str ) -> Result<(), Error> {
+	let address = parse_address( address )?;
+
+	if !BadPeerStore::load( db.clone() ).await?.unban( &address ).await? {
+		eprintln!("Peer {} wasn't banned.", address);
+	}
+	Ok(())
+}
+
+async fn export_peers( db: &persistence::Handle, file: Option<&Path> ) -> Result<(), Error> {
+	let export = PeersExport {
+		bad_peers: db.list_bad_peers().await?.into_iter().map(|b| BadPeerEntry {
+			address: b.address.to_string(),
+			reason: b.reason,
+			offenses: b.offenses,
+			flagged_timestamp: b.flagged,
+			expires_timestamp: b.expires
+		}).collect(),
+		reputations: db.list_reputations().await?.into_iter().map(|r| ReputationEntry {
+			address: r.address.to_string(),
+			score: r.score,
+			updated_timestamp: r.updated
+		}).collect()
+	};
+
+	write_export( &export, file )
+}
+
+/// Imports an export, replacing the bans and reputations of the peers that are in it.
+/// The peers that aren't in it are left alone.
+async fn import_peers( db: &persistence::Handle, file: Option<&Path> ) -> Result<(), Error> {
+	let export: PeersExport = read_export( file )?;
+
+	let mut bad_peers = Vec::with_capacity( export.bad_peers.len() );
+	for entry in export.bad_peers {
+		bad_peers.push( BadPeer {
+			address: parse_address( &entry.address )?,
+			reason: entry.reason,
+			offenses: entry.offenses,
+			flagged: entry.flagged_timestamp,
+			expires: entry.expires_timestamp
+		});
+	}
+	let mut reputations = Vec::with_capacity( export.reputations.len() );
+	for entry in export.reputations {
+		// Scores that aren't numbers would make the peers impossible to rank.
+		if !entry.score.is_finite() {
+			return Err( Error::Usage( format!("Invalid score for peer {}.", entry.address) ) )
+		}
+		reputations.push( StoredReputation {
+			address: parse_address( &entry.address )?,
+			score: entry.score,
+			updated: entry.updated_timestamp
+		});
+	}
+
+	db.import_peers( &bad_peers, &reputations ).await?;
+	println!("Imported {} ban(s) and {} reputation(s).", bad_peers.len(), reputations.len());
+	Ok(())
+}
+
+
+
+impl Error {
+
+	pub fn return_code( &self ) -> i32 {
+		match self {
+			Self::Usage(_) => RETURN_CODE_USAGE,
+			Self::Config(_) => RETURN_CODE_CONFIG,
+			Self::DataDir(_) => RETURN_CODE_CONFIG,
+			Self::Persistence(_) => RETURN_CODE_PERSISTENCE,
+			Self::Gnunet(_) => RETURN_CODE_GNUNET,
+			Self::Io(_) => RETURN_CODE_UNEXPECTED,
+			Self::Json(_) => RETURN_CODE_USAGE,
+			Self::Problems(_) => RETURN_CODE_UNEXPECTED
+		}
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt( &self, f: &mut fmt::Formatter<'_> ) -> fmt::Result {
+		match self {
+			Self::Usage(message) => write!(f, "{}", message),
+			Self::Config(e) => write!(f, "{}", e),
+			Self::DataDir(e) => write!(f, "Unable to open the data directory: {}", e),
+			Self::Persistence(e) => write!(f, "Database error: {}", e),
+			Self::Gnunet(e) => write!(f, "Unable to reach gnunet, make sure it is running: {}", e),
+			Self::Io(e) => write!(f, "Unable to access the file: {}", e),
+			Self::Json(e) => write!(f, "Invalid export: {}", e),
+			Self::Problems(count) => write!(f, "Found {} problem(s).", count)
+		}
+	}
+}
+
+impl From<persistence::Error> for Error {
+	fn from( other: persistence::Error ) -> Self {
+		Self::Persistence( other )
+	}
+}
@@ -1,23 +1,14 @@
-use std::io;
+use std::{
+	convert::TryInto,
+	io
+};
 
 use gnunet::{
 	crypto::HashCode,
-	identity::PublicKey
+	identity::{PrivateKey, Signature}
 };
 
-
-
-pub trait Signature {
-	fn verify_hash( &self, hash: &HashCode, public_key: &PublicKey ) -> bool;
-}
-
-
-
-impl Signature for gnunet::identity::Signature {
-	fn verify_hash( &self, hash: &HashCode, public_key: &PublicKey ) -> bool {
-		self.verify( 777, &hash.to_bytes(), public_key )
-	}
-}
+use crate::validation::SIGNATURE_PURPOSE;
 
 
 
@@ -25,4 +16,11 @@ pub fn into_io_data_error<E>( error: E ) -> io::Error where
 	E: Into<Box<dyn std::error::Error + Send + Sync>>
 {
 	io::Error::new( io::ErrorKind::InvalidData, error )
-}
\ No newline at end of file
+}
+
+/// Signs a hash in the way that `validation::Signature::verify_hash` verifies it.
+pub fn sign_hash( private_key: &PrivateKey, hash: &HashCode ) -> Signature {
+	let raw_hash = bincode::serialize( hash ).expect("unable to serialize hash");
+
+	private_key.sign( (&*raw_hash).try_into().unwrap(), SIGNATURE_PURPOSE ).unwrap()
+}
@@ -1 +1,460 @@
-pub const RELAY_POWER: u8 = 1;
\ No newline at end of file
+//! The settings of the node.
+//!
+//! Most settings are constants.
+//! Some of them can be overridden in the configuration file, `~/.config/quartznet/config.toml`, which is loaded at startup.
+//! Settings that are left out of the file keep their default, and a missing file leaves all of them at their defaults.
+//! The file can be loaded again while the node runs, see `reload`.
+
+use std::{
+	collections::HashMap,
+	convert::TryFrom,
+	env,
+	fmt,
+	fs,
+	io,
+	path::PathBuf,
+	sync::{Arc, RwLock}
+};
+
+use lazy_static::lazy_static;
+use serde::*;
+
+use crate::message::RequestType;
+
+
+
+/// The environment variable that holds the directory to load the templates of the web interface from.
+pub const TEMPLATE_DIR_VAR: &str = "QUARTZNET_TEMPLATES";
+/// The environment variable that holds the directory with static files that override the embedded ones.
+pub const STATIC_DIR_VAR: &str = "QUARTZNET_STATIC";
+/// The environment variable that holds the data directory, unless it is given on the command line.
+pub const DATA_DIR_VAR: &str = "QUARTZNET_DATA_DIR";
+/// The environment variable that holds the log filter, which takes the place of the log levels in the configuration file.
+/// It has the syntax of `RUST_LOG`, e.g. `info,quartz_net::swarm=trace`.
+pub const LOG_FILTER_VAR: &str = "QUARTZNET_LOG";
+/// The number of milliseconds between checks for changes of the templates, while they are watched.
+pub const TEMPLATE_WATCH_INTERVAL: u64 = 1000;
+/// The number of milliseconds that the template directory needs to stay unchanged before the templates are reloaded.
+/// This way an editor that saves several files, or writes a file in several steps, causes only a single reload.
+pub const TEMPLATE_RELOAD_DELAY: u64 = 300;
+/// The address that the web interface listens on.
+pub const BIND_ADDRESS: &str = "0.0.0.0";
+/// The port that the web interface listens on.
+pub const PORT: u16 = 7777;
+/// The relay power that is used when no contribution profile has been chosen.
+pub const RELAY_POWER: u8 = 1;
+/// The highest relay power, which lets a swarm accept 256 children.
+pub const RELAY_POWER_MAX: u8 = 8;
+/// The number of posts on a page of the feed of a channel.
+pub const PAGE_SIZE: u16 = 10;
+/// The number of milliseconds to wait for the response of a peer to a request, unless another timeout is configured for its kind of request.
+pub const SESSION_TIMEOUT: u64 = 10000;
+/// The number of peers that are connected to at the same time, when looking for a connection to a swarm.
+pub const CONNECT_BATCH_SIZE: usize = 4;
+/// The number of milliseconds between the starts of the connection attempts within a batch.
+pub const CONNECT_STAGGER: u64 = 250;
+/// The maximum number of peers that are remembered per channel, to connect to after a restart.
+pub const CACHED_PEERS_MAX: usize = 16;
+/// The number of peers that are put in a share link, besides the channel itself.
+pub const SHARE_LINK_PEERS: usize = 3;
+/// The number of seconds between checks of whether the connection to a swarm is still alive.
+pub const CONNECTION_CHECK_INTERVAL: u64 = 30;
+/// The number of seconds that a peer may stay silent, before it is pinged to find out whether it is still there.
+pub const PING_INTERVAL: u64 = 20;
+/// The number of seconds that a peer may stay silent altogether, pings notwithstanding, before its channel is considered dead and is closed.
+pub const LIVENESS_DEADLINE: u64 = 60;
+/// The number of seconds between attempts to publish the events in the outbox of a channel.
+pub const OUTBOX_RETRY_INTERVAL: u64 = 5;
+/// The number of seconds between the checks for drafts that are due to be published.
+pub const DRAFT_SCHEDULE_INTERVAL: u64 = 30;
+/// The number of seconds between the garbage collections, which remove the posts that have expired and the files and blocks that nothing uses.
+pub const GARBAGE_COLLECTION_INTERVAL: u64 = 6 * 60 * 60;
+/// The number of seconds between the rounds of database maintenance, see `maintenance`.
+pub const MAINTENANCE_INTERVAL: u64 = 24 * 60 * 60;
+/// The number of seconds that a file that nothing uses is kept after it has been stored, because it may be about to be attached to a post.
+pub const ORPHAN_GRACE_PERIOD: u64 = 24 * 60 * 60;
+/// The number of seconds to wait before searching for a connection to a swarm again, after the first failure.
+/// The delay doubles with every failure after that.
+pub const RECONNECT_MIN_DELAY: u64 = 5;
+/// The maximum number of seconds to wait before searching for a connection to a swarm again.
+pub const RECONNECT_MAX_DELAY: u64 = 10 * 60;
+/// The number of seconds between the advertisements of free relay slots in the DHT.
+pub const DHT_ADVERTISE_INTERVAL: u64 = 10 * 60;
+/// The number of seconds after which an advertisement in the DHT expires.
+/// This is longer than the interval, so that there is no gap between advertisements.
+pub const DHT_ADVERTISE_EXPIRATION: u64 = 15 * 60;
+/// The number of seconds between the census messages that a node sends its parents and children.
+/// It takes a few of them for the count of the swarm to travel from its leaves to its root, and back down.
+pub const CENSUS_INTERVAL: u64 = 60;
+/// The number of seconds between the samples of the census that are kept for the followers dashboard of our own channels.
+pub const CENSUS_SAMPLE_INTERVAL: u64 = 10 * 60;
+/// The number of days for which the samples of the census are kept.
+pub const CENSUS_HISTORY_DAYS: u64 = 30;
+/// The maximum number of keywords that a channel can be listed under in the channel directory.
+pub const DIRECTORY_KEYWORDS_MAX: usize = 8;
+/// The number of seconds within which identical errors are only printed once.
+pub const ERROR_REPORT_WINDOW: u64 = 60;
+/// The maximum size of a single response from a peer, in bytes.
+/// A response to a `BlocksRequest` needs to fit, which can contain up to 8 blocks of 1 MiB.
+pub const MAX_RESPONSE_SIZE: usize = 9 * 1024 * 1024;
+/// The maximum number of bytes of all buffered responses together, in bytes.
+pub const MAX_BUFFERED_RESPONSE_BYTES: usize = 64 * 1024 * 1024;
+/// The number of received events of a single peer that can wait to be applied.
+/// A peer that sends more than that is made to wait, so that it can't crowd out the events of the other peers.
+pub const PEER_EVENT_QUEUE_SIZE: usize = 256;
+/// The number of received requests of a single peer that can wait to be answered.
+/// A peer that sends more than that isn't read from until there is room again, while the requests of the other peers keep being answered.
+pub const PEER_REQUEST_QUEUE_SIZE: usize = 32;
+/// The number of requests that the swarm of a channel answers at the same time.
+/// Answering mostly waits for the database, so a few workers keep a slow query from holding up the rest.
+pub const REQUEST_WORKERS: usize = 4;
+/// The number of events per second that a single peer may send us, on average.
+pub const PEER_EVENT_RATE: u32 = 10;
+/// The number of events that a single peer may send us at once, before the event rate applies.
+pub const PEER_EVENT_BURST: u32 = 100;
+/// The number of requests per second that a single peer may send us, on average.
+pub const PEER_REQUEST_RATE: u32 = 5;
+/// The number of requests that a single peer may send us at once, before the request rate applies.
+pub const PEER_REQUEST_BURST: u32 = 50;
+/// The number of messages that a peer may send over its rate limits in a session, before it is disconnected and flagged as a bad peer.
+/// The messages over the limits are dropped until then.
+pub const PEER_RATE_LIMIT_STRIKES: u64 = 100;
+/// The number of seconds' worth of the upload limit that a channel may upload at once, before the limit applies.
+pub const UPLOAD_BURST: u32 = 10;
+/// The number of parents that we stay connected to, so that the swarm can still reach us when one of them goes away.
+pub const PARENT_COUNT: u8 = 2;
+/// The highest number of parents that a swarm stays connected to.
+pub const PARENT_COUNT_MAX: u8 = 8;
+/// The number of seconds that a misbehaving peer is blocked for, per offense.
+pub const BAD_PEER_BAN_DURATION: u64 = 24 * 60 * 60;
+/// The number of seconds after which the reputation of a peer has decayed to half of what it was.
+pub const REPUTATION_HALF_LIFE: u64 = 7 * 24 * 60 * 60;
+/// The number of days over which the relay report of a channel is made.
+pub const RELAY_REPORT_DAYS: u64 = 30;
+/// The number of seconds to wait for another connection to finish writing to the database, before giving up.
+pub const DATABASE_BUSY_TIMEOUT: u64 = 10;
+/// The number of connections to the database that are kept open for reuse, when they aren't in use.
+pub const DATABASE_POOL_SIZE: usize = 8;
+/// The number of seconds that `quartznet doctor` tries to join the swarm of a channel, before it reports the swarm as unreachable.
+pub const DOCTOR_CONNECT_TIMEOUT: u64 = 30;
+/// The number of seconds that `quartznet channel delete` tries to join the swarm of the channel, to tell it that the channel has been closed.
+pub const CLOSING_CONNECT_TIMEOUT: u64 = 30;
+/// The number of seconds that `/healthz` and `/readyz` wait for the database or a gnunet service to respond, before reporting it as failing.
+pub const HEALTH_CHECK_TIMEOUT: u64 = 5;
+/// The number of posts in a page of the JSON API, if the client doesn't ask for a number.
+pub const API_PAGE_SIZE: u16 = 20;
+/// The maximum number of posts in a page of the JSON API.
+pub const API_PAGE_MAX_SIZE: u16 = 100;
+/// The number of seconds that a login to the web interface lasts.
+pub const LOGIN_SESSION_DURATION: u64 = 30 * 24 * 60 * 60;
+/// The number of times that the admin password can be given wrongly from one address, before further attempts from it are refused for a while.
+pub const LOGIN_ATTEMPTS_MAX: u32 = 5;
+/// The number of seconds after the first wrong password from an address, in which `LOGIN_ATTEMPTS_MAX` applies.
+pub const LOGIN_ATTEMPTS_PERIOD: u64 = 15 * 60;
+/// The number of latest posts that the RSS and Atom feeds of a channel contain.
+pub const SYNDICATION_POSTS: u64 = 20;
+/// The number of latest posts that the ActivityPub outbox of a channel contains.
+pub const FEDERATION_OUTBOX_POSTS: u64 = 20;
+/// The number of seconds between the checks for new posts to deliver to the followers of channels on ActivityPub servers.
+pub const FEDERATION_DELIVERY_INTERVAL: u64 = 60;
+/// The number of milliseconds to wait for an ActivityPub server to respond.
+pub const FEDERATION_TIMEOUT: u64 = 10000;
+/// The number of milliseconds to wait for the feed of a blog that is imported.
+pub const FEED_IMPORT_TIMEOUT: u64 = 30000;
+/// The maximum size of the feed of a blog that is imported, in bytes.
+pub const FEED_IMPORT_MAX_SIZE: usize = 32 * 1024 * 1024;
+/// The maximum number of bytes of rendered previews that are kept in memory, so that they don't have to be rendered again.
+pub const PREVIEW_CACHE_SIZE: usize = 8 * 1024 * 1024;
+/// The maximum number of rendered feed pages that are kept in memory.
+pub const PAGE_CACHE_PAGES: usize = 256;
+/// The number of seconds that a rendered feed page is kept, at most.
+pub const PAGE_CACHE_TIME: u64 = 60;
+/// The number of days of publication history that the calendar shows.
+pub const CALENDAR_HISTORY_DAYS: u64 = 30;
+/// The number of results on a page of search results.
+pub const SEARCH_PAGE_SIZE: u32 = 20;
+/// When the first page of search results has fewer results than this, the swarms of our subscriptions are searched as well.
+pub const SEARCH_THIN_RESULTS: usize = 5;
+/// The number of most used tags that are shown on the feed of a channel.
+pub const TAG_CLOUD_SIZE: u32 = 20;
+/// The widths, in pixels, in which smaller versions of attached images are offered to browsers.
+pub const PREVIEW_IMAGE_WIDTHS: &[u32] = &[320, 640, 1280];
+/// The maximum size of the profile picture of a channel, in bytes.
+pub const PROFILE_PICTURE_MAX_SIZE: usize = 4 * 1024 * 1024;
+/// The number of days that the previous owner of a transferred channel can still sign its events, unless chosen otherwise.
+pub const OWNERSHIP_GRACE_PERIOD_DAYS: u64 = 7;
+/// Whether the channels that are created are public, when that isn't chosen explicitly (e.g. when forking).
+pub const CHANNEL_PUBLIC: bool = true;
+/// The number of days that the posts of created channels are requested to be replicated.
+/// Zero means that there is no limit.
+pub const CHANNEL_REPLICATION_TIME: u32 = 0;
+/// The reactions that are offered on the page of a post.
+/// Reactions of other kinds that arrive from the swarm are counted as well.
+pub const REACTION_KINDS: &[&str] = &["👍", "❤️", "😂", "😮", "😢", "🎉"];
+
+/// The settings that can be given in the configuration file.
+#[derive(Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+	pub bind_address: String,
+	pub port: u16,
+	/// Only accepts requests to the web interface from this computer, which are trusted without a login, see `auth`.
+	/// Behind a reverse proxy on the same computer, all requests seem to come from this computer, so this should be left off then.
+	pub local_only: bool,
+	/// Overrides the relay power that follows from the contribution profile that was chosen during the setup.
+	pub relay_power: Option<u8>,
+	/// The data directory, unless it is given on the command line or in the environment.
+	pub data_dir: Option<PathBuf>,
+	/// The public URL of the web interface, e.g. `https://example.org`.
+	/// Channels can only be followed over ActivityPub when it is set, because other servers need to be able to reach us.
+	pub federation_url: Option<String>,
+	/// The directory with templates that take the place of the embedded ones, unless one is given in the environment.
+	pub template_dir: Option<PathBuf>,
+	/// Reloads the templates when the template directory changes, so that a theme can be worked on without restarting.
+	/// This is always done in debug builds.
+	pub watch_templates: bool,
+	pub page_size: u16,
+	/// In milliseconds.
+	pub session_timeout: u64,
+	/// The timeouts of single kinds of requests, which take the place of `session_timeout` for them, in milliseconds.
+	/// The kinds are given by their name in lowercase, e.g. `blocks = 30000`.
+	pub session_timeouts: HashMap<String, u64>,
+	/// The rate limits for the events and requests of every single peer, see `PEER_EVENT_RATE` and the like.
+	pub peer_event_rate: u32,
+	pub peer_event_burst: u32,
+	pub peer_request_rate: u32,
+	pub peer_request_burst: u32,
+	/// How long peers may be silent, in seconds, see `PING_INTERVAL` and `LIVENESS_DEADLINE`.
+	pub ping_interval: u64,
+	pub liveness_deadline: u64,
+	/// Overrides the number of parents that we stay connected to, which is set on the settings page, see `PARENT_COUNT`.
+	pub parent_count: Option<u8>,
+	/// The number of KiB per second that the swarm of a single channel may upload, or 0 for no limit.
+	/// Once a channel has gone over it, its rebroadcasts are held back and sent with a low priority, see `UPLOAD_BURST`.
+	pub upload_limit: u32,
+	/// The number of seconds between the rounds of database maintenance, or 0 to only do it with `quartznet maintenance`.
+	pub maintenance_interval: u64,
+	pub log_level: LogLevel,
+	/// The log levels of single modules, which take the place of `log_level` for them.
+	/// The modules are given by their path, e.g. `"quartz_net::swarm" = "debug"`.
+	pub log_levels: HashMap<String, LogLevel>,
+	pub log_format: LogFormat
+}
+
+/// How much the node logs, from only its errors to everything that it does.
+#[derive(Clone, Copy, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+	Error,
+	/// Things that went wrong but that the node can live with, like misbehaving peers.
+	Warning,
+	/// What the node is doing, like starting and stopping, and the requests to the web interface.
+	Info,
+	Debug,
+	/// Every message that is sent to or received from a peer.
+	Trace
+}
+
+/// How the log is written.
+#[derive(Clone, Copy, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+	/// One line of text per message, for people to read.
+	Text,
+	/// One JSON object per message, for log collectors.
+	Json
+}
+
+/// The reasons for which the configuration file can't be loaded.
+#[derive(Debug)]
+pub enum Error {
+	Io( io::Error ),
+	Toml( toml::de::Error ),
+	/// A setting has a value that can't be used, the message tells which and why.
+	Invalid( String )
+}
+
+
+
+lazy_static! {
+	/// Until the configuration file has been loaded, all settings have their defaults.
+	static ref CONFIG: RwLock<Arc<Config>> = RwLock::new( Arc::new( Config::default() ) );
+}
+
+impl Default for Config {
+	fn default() -> Self {
+		Self {
+			bind_address: BIND_ADDRESS.to_owned(),
+			port: PORT,
+			local_only: false,
+			relay_power: None,
+			data_dir: None,
+			federation_url: None,
+			template_dir: None,
+			watch_templates: false,
+			page_size: PAGE_SIZE,
+			session_timeout: SESSION_TIMEOUT,
+			session_timeouts: HashMap::new(),
+			peer_event_rate: PEER_EVENT_RATE,
+			peer_event_burst: PEER_EVENT_BURST,
+			peer_request_rate: PEER_REQUEST_RATE,
+			peer_request_burst: PEER_REQUEST_BURST,
+			ping_interval: PING_INTERVAL,
+			liveness_deadline: LIVENESS_DEADLINE,
+			parent_count: None,
+			upload_limit: 0,
+			maintenance_interval: MAINTENANCE_INTERVAL,
+			log_level: LogLevel::Info,
+			log_levels: HashMap::new(),
+			log_format: LogFormat::Text
+		}
+	}
+}
+
+/// Returns the configuration that is in use.
+pub fn get() -> Arc<Config> {
+	CONFIG.read().unwrap().clone()
+}
+
+/// Loads the configuration file, and uses it from then on.
+pub fn load() -> Result<(), Error> {
+	if let Some(config) = read()? {
+		*CONFIG.write().unwrap() = Arc::new( config );
+	}
+	Ok(())
+}
+
+/// Loads the configuration file again, and uses the settings that can be changed while the node runs from then on.
+/// The settings that are only used while the node starts keep the values that it started with.
+/// Returns the names of those that have been changed in the file, which only take effect after a restart.
+pub fn reload() -> Result<Vec<&'static str>, Error> {
+	let mut config = read()?.unwrap_or_default();
+	let current = get();
+
+	let mut restart = Vec::new();
+	if config.bind_address != current.bind_address {
+		restart.push("bind_address");
+		config.bind_address = current.bind_address.clone();
+	}
+	if config.port != current.port {
+		restart.push("port");
+		config.port = current.port;
+	}
+	if config.data_dir != current.data_dir {
+		restart.push("data_dir");
+		config.data_dir = current.data_dir.clone();
+	}
+	if config.template_dir != current.template_dir {
+		restart.push("template_dir");
+		config.template_dir = current.template_dir.clone();
+	}
+	if config.watch_templates != current.watch_templates {
+		restart.push("watch_templates");
+		config.watch_templates = current.watch_templates;
+	}
+	if config.log_format != current.log_format {
+		restart.push("log_format");
+		config.log_format = current.log_format;
+	}
+
+	*CONFIG.write().unwrap() = Arc::new( config );
+	Ok( restart )
+}
+
+/// Reads and checks the configuration file, or returns `None` if there is none.
+fn read() -> Result<Option<Config>, Error> {
+	let path = match config_file() {
+		None => return Ok(None),
+		Some(p) => p
+	};
+	let content = match fs::read_to_string( &path ) {
+		Err(e) => return if e.kind() == io::ErrorKind::NotFound { Ok(None) } else { Err( Error::Io( e ) ) },
+		Ok(c) => c
+	};
+
+	let mut config: Config = toml::from_str( &content ).map_err( Error::Toml )?;
+	if config.page_size == 0 {
+		return Err( Error::Invalid( "page_size needs to be positive".to_owned() ) )
+	}
+	if config.peer_event_rate == 0 || config.peer_event_burst == 0 || config.peer_request_rate == 0 || config.peer_request_burst == 0 {
+		return Err( Error::Invalid( "the peer rate limits need to be positive".to_owned() ) )
+	}
+	// A peer needs to be pinged at least once before its deadline, to get the chance to show that it is alive.
+	if config.ping_interval == 0 || config.liveness_deadline <= config.ping_interval {
+		return Err( Error::Invalid( "ping_interval needs to be positive, and shorter than liveness_deadline".to_owned() ) )
+	}
+	if config.parent_count.map(|c| c == 0 || c > PARENT_COUNT_MAX).unwrap_or(false) {
+		return Err( Error::Invalid( format!("parent_count needs to be between 1 and {}", PARENT_COUNT_MAX) ) )
+	}
+	// The number of child peers is two to the power of the relay power.
+	if config.relay_power.map(|p| p > RELAY_POWER_MAX).unwrap_or(false) {
+		return Err( Error::Invalid( format!("relay_power can be at most {}", RELAY_POWER_MAX) ) )
+	}
+	if let Some(url) = &mut config.federation_url {
+		if !url.starts_with("https://") && !url.starts_with("http://") {
+			return Err( Error::Invalid( "federation_url needs to be an http or https URL".to_owned() ) )
+		}
+		// The paths are appended to it.
+		while url.ends_with('/') { url.pop(); }
+	}
+	if let Some(kind) = config.session_timeouts.keys().find(|k| !is_request_kind( k )) {
+		return Err( Error::Invalid( format!("session_timeouts contains {}, which is no kind of request", kind) ) )
+	}
+	if let Some(module) = config.log_levels.keys().find(|m| !is_module_path( m )) {
+		return Err( Error::Invalid( format!("log_levels contains {}, which is no module path", module) ) )
+	}
+
+	Ok( Some( config ) )
+}
+
+/// The location of the configuration file, which doesn't need to exist.
+pub fn config_file() -> Option<PathBuf> {
+	env::var_os("HOME").map(|home| PathBuf::from( home ).join(".config").join("quartznet").join("config.toml"))
+}
+
+/// The name of a kind of request, as it is used in `session_timeouts` and in the metrics.
+pub fn request_kind( request_type: RequestType ) -> String {
+	format!("{:?}", request_type).to_lowercase()
+}
+
+fn is_request_kind( name: &str ) -> bool {
+	(0..=u8::MAX).filter_map(|i| RequestType::try_from( i ).ok()).any(|t| request_kind( t ) == name)
+}
+
+fn is_module_path( path: &str ) -> bool {
+	path.split("::").all(|part| !part.is_empty() && part.chars().all(|c| c.is_alphanumeric() || c == '_'))
+}
+
+
+
+impl Config {
+
+	/// The number of milliseconds to wait for the response to a request of the given kind.
+	pub fn session_timeout_for( &self, request_type: RequestType ) -> u64 {
+		self.session_timeouts.get( &request_kind( request_type ) ).cloned().unwrap_or( self.session_timeout )
+	}
+}
+
+impl LogLevel {
+
+	/// The name of the level in a log filter.
+	pub fn directive( self ) -> &'static str {
+		match self {
+			Self::Error => "error",
+			Self::Warning => "warn",
+			Self::Info => "info",
+			Self::Debug => "debug",
+			Self::Trace => "trace"
+		}
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt( &self, f: &mut fmt::Formatter<'_> ) -> fmt::Result {
+		match self {
+			Self::Io(e) => write!(f, "Unable to read the configuration file: {}", e),
+			Self::Toml(e) => write!(f, "Invalid configuration file: {}", e),
+			Self::Invalid(message) => write!(f, "Invalid configuration file: {}", message)
+		}
+	}
+}
@@ -0,0 +1,419 @@
+//! The node without its web interface, for applications that embed Quartznet.
+//!
+//! A `Daemon` is started on a data directory, starts logging unless the application has done so already, joins the swarms of all channels that it knows in the background, and publishes scheduled drafts.
+//! Channels can be created, subscribed to, published in and read through it, and everything else that is stored can be queried with `Daemon::database`.
+//! The node runs on the actix runtime, so it needs to be started from within one, like the binary does with `actix_web::main`.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), quartz_net::daemon::Error> {
+//! use quartz_net::{daemon::{Daemon, Options}, post::ContentFormat};
+//!
+//! let daemon = Daemon::start( Options::default() ).await?;
+//! let channel = daemon.create_channel( "news", true ).await?;
+//! daemon.publish( "news", "Hello, swarm!", ContentFormat::Plain, &[] ).await?;
+//! for post in daemon.list_posts( &channel, 0, 10 ).await? {
+//!     println!("{}: {:?}", post.id, daemon.load_content( &channel, post.id ).await?);
+//! }
+//! daemon.stop().await;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The shutdown of a daemon is final for the whole process, so a daemon can't be started again after it has been stopped.
+
+use std::{
+	fmt,
+	io,
+	path::PathBuf,
+	sync::Arc,
+	time::{SystemTime, UNIX_EPOCH}
+};
+
+use async_std::sync::RwLock;
+use gnunet::identity::{PrivateKey, PublicKey};
+use tracing::{error, info, warn};
+
+use crate::{
+	activitypub,
+	auth,
+	config,
+	directory,
+	logging,
+	maintenance,
+	persistence::{
+		self,
+		audit::AuditAction,
+		channel,
+		timeline::SearchResult
+	},
+	post::{check_tags, normalize_tags, ContentFormat, Post, PostInfo},
+	retention,
+	scheduler,
+	services::{self, GnunetServices},
+	setup,
+	shutdown,
+	subscriptions::{self, SharedSubscriptions, SubscriptionsManager},
+	swarm::Node,
+	web::post_subject,
+	RETURN_CODE_CONFIG,
+	RETURN_CODE_GNUNET,
+	RETURN_CODE_PERSISTENCE,
+	RETURN_CODE_USAGE
+};
+
+
+
+/// How a daemon is started.
+pub struct Options {
+	/// The data directory, or `None` to choose it like the binary does, from the environment, the configuration file or the defaults.
+	pub data_dir: Option<PathBuf>,
+	/// Whether to create the database if it doesn't exist yet.
+	/// The binary leaves this to the setup in the web interface.
+	pub create_database: bool
+}
+
+/// A running node.
+pub struct Daemon {
+	services: Arc<GnunetServices>,
+	subscriptions: SharedSubscriptions
+}
+
+/// The reasons for which the daemon can fail.
+#[derive(Debug)]
+pub enum Error {
+	/// The configuration file could not be read, or has invalid settings.
+	Config( config::Error ),
+	/// The location of the data directory could not be read, or the directory could not be created.
+	DataDir( io::Error ),
+	Persistence( persistence::Error ),
+	Gnunet( services::Error ),
+	/// What was asked for can't be done, the message tells why.
+	Invalid( String )
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+
+
+impl Default for Options {
+	fn default() -> Self {
+		Self {
+			data_dir: None,
+			create_database: true
+		}
+	}
+}
+
+impl Daemon {
+
+	/// Starts the components of the node in order, so that each one only starts after the ones it depends on.
+	/// Joining the swarms can take a while, so that is done in the background.
+	pub async fn start( options: Options ) -> Result<Self> {
+
+		// Configuration
+		config::load().map_err( Error::Config )?;
+		logging::init();
+		setup::load_data_dir( options.data_dir ).map_err( Error::DataDir )?;
+
+		// Persistence
+		// Connecting runs the migrations, so that the database is up to date before anything uses it.
+		let services = Arc::new( GnunetServices::new( gnunet::Handle::default() ) );
+		let mut database = None;
+		if options.create_database || persistence::database_exists() {
+			let db = persistence::Handle::connect( services.clone() ).await
+				.map_err(|e| Error::Persistence( e.into() ))?;
+			// The web interface can't be logged into before the admin password is known.
+			auth::load_password( &db ).await.map_err( Error::Persistence )?;
+			// What was stored for the private channels before they were encrypted at rest.
+			let encrypted = db.encrypt_private_channels().await.map_err( Error::Persistence )?;
+			if encrypted > 0 {
+				info!(rows = encrypted, "Encrypted the stored content of the private channels.");
+			}
+			database = Some( db );
+		}
+
+		// Only requests from this computer can complete the setup without the token.
+		if setup::is_required( services.clone() ).await.map_err( Error::Persistence )? {
+			warn!("The node hasn't been set up yet. To set it up from another computer than this one, enter the setup token {}", setup::setup_token());
+		}
+
+		// Gnunet services
+		services.check().await.map_err( Error::Gnunet )?;
+
+		// Our own channels from before channels started with a genesis event get one, which needs the keys of our egos.
+		if let Some(db) = &database {
+			let egos = services.list_egos().await.map_err( Error::Gnunet )?;
+			let created = db.create_missing_genesis_events( &egos ).await.map_err( Error::Persistence )?;
+			if created > 0 {
+				info!(channels = created, "Created the genesis events of our own channels that didn't have one.");
+			}
+		}
+
+		let daemon = Self {
+			services,
+			subscriptions: Arc::new( RwLock::new( None ) )
+		};
+
+		// Swarms
+		actix_web::rt::spawn( load_subscriptions( daemon.services.clone(), daemon.subscriptions.clone() ) );
+
+		// Drafts
+		actix_web::rt::spawn( scheduler::publish_scheduled_drafts( daemon.services.clone() ) );
+
+		// Retention
+		actix_web::rt::spawn( retention::collect_garbage_periodically( daemon.services.clone() ) );
+
+		// Database maintenance
+		actix_web::rt::spawn( maintenance::run_periodically( daemon.services.clone() ) );
+
+		// Federation
+		actix_web::rt::spawn( activitypub::deliver_periodically( daemon.services.clone() ) );
+
+		// Channel directory
+		actix_web::rt::spawn( directory::advertise_periodically( daemon.services.clone() ) );
+
+		Ok( daemon )
+	}
+
+	/// Stops the background tasks, leaves the swarms so that our children get handed to our parents, and closes the database.
+	/// The peers that we were connected to are saved, so that they can be tried first on the next start.
+	pub async fn stop( self ) {
+		shutdown::start();
+
+		// Dropping the subscriptions afterwards closes their connections to the database.
+		if let Some(subs) = self.subscriptions.write().await.take() {
+			subs.disconnect().await;
+			let _ = subs.save().await;
+			info!("Left the swarms.");
+		}
+
+		if !persistence::database_exists() { return }
+		let result = async {
+			persistence::Handle::connect( self.services.clone() ).await?.close().await
+		}.await;
+		if let Err(e) = result {
+			error!("Unable to close the database: {}", e);
+		}
+	}
+
+	/// The gnunet services that the daemon uses, which the web interface shares.
+	#[doc(hidden)]
+	pub fn services( &self ) -> &Arc<GnunetServices> {
+		&self.services
+	}
+
+	/// The subscriptions of the daemon, which the web interface shares.
+	#[doc(hidden)]
+	pub fn subscriptions( &self ) -> &SharedSubscriptions {
+		&self.subscriptions
+	}
+
+	/// Opens a new connection to the database, with which everything that the node has stored can be queried.
+	pub async fn database( &self ) -> Result<persistence::Handle> {
+		Ok( persistence::Handle::connect( self.services.clone() ).await.map_err(|e| Error::Persistence( e.into() ))? )
+	}
+
+	/// Creates a channel along with the ego that it is published with, and returns its address.
+	/// The messages of a channel that isn't `public` are encrypted, and can only be read by those that have its invite code.
+	pub async fn create_channel( &self, name: &str, public: bool ) -> Result<PublicKey> {
+		let mut db = self.database().await?;
+
+		let channel = db.create_channel( name, public ).await?;
+		let address = channel.load_address().await?;
+		db.record_action( Some( name ), AuditAction::ChannelCreated, &address.to_string() ).await?;
+		Ok( address )
+	}
+
+	/// Follows a channel, and joins its swarm in the background.
+	/// The given peers are tried before the owner of the channel.
+	pub async fn subscribe( &self, address: &PublicKey, peers: &[PublicKey] ) -> Result<()> {
+		let (channel, _) = self.database().await?.subscribe( address, peers ).await?;
+
+		subscriptions::join( self.subscriptions.clone(), channel, address.clone() );
+		Ok(())
+	}
+
+	/// Publishes a post in the channel of the given ego, with the defaults of the ego added to it.
+	/// It reaches the swarm as soon as we are connected to it.
+	pub async fn publish( &self, ego: &str, content: &str, format: ContentFormat, tags: &[String] ) -> Result<Post> {
+		publish_post( &self.services, &self.database().await?, ego, content, format, tags ).await
+	}
+
+	/// Deletes the channel of the given ego, along with the ego itself, after telling its swarm that the channel has been closed.
+	/// Returns false if the closing hasn't reached any peer yet, in which case nothing is deleted unless `force` is given, see `delete_channel`.
+	pub async fn delete_channel( &self, ego: &str, force: bool ) -> Result<bool> {
+		delete_channel( &self.services, &self.database().await?, &self.subscriptions, ego, force ).await
+	}
+
+	/// Lists the posts of a publisher, the oldest first.
+	/// Posts that we know of but haven't received yet are left out.
+	pub async fn list_posts( &self, publisher: &PublicKey, start: u64, count: u16 ) -> Result<Vec<Post>> {
+		if count == 0 { return Ok( Vec::new() ) }
+
+		let mut timeline = match self.database().await?.get_timeline( publisher ).await? {
+			None => return Ok( Vec::new() ),
+			Some(t) => t
+		};
+
+		Ok( timeline.list_posts( start, count ).await?.into_iter().flatten().collect() )
+	}
+
+	/// Loads the content of the current revision of a post, or `None` if we don't have it.
+	pub async fn load_content( &self, publisher: &PublicKey, post_id: u64 ) -> Result<Option<String>> {
+		match self.database().await?.get_timeline( publisher ).await? {
+			None => Ok(None),
+			Some(timeline) => Ok( timeline.load_current_content( post_id ).await? )
+		}
+	}
+
+	/// Searches the content of all posts that we have, the best matches first.
+	pub async fn search( &self, query: &str, limit: u32 ) -> Result<Vec<SearchResult>> {
+		Ok( self.database().await?.search_posts( query, limit, 0 ).await? )
+	}
+}
+
+/// Signs and stores a post in the channel of the given ego, with the defaults of the ego added to it, and records this in the audit log.
+pub async fn publish_post( services: &GnunetServices, db: &persistence::Handle, ego: &str, content: &str, format: ContentFormat, tags: &[String] ) -> Result<Post> {
+	let private_key = services.lookup_ego( ego ).await.map_err( Error::Gnunet )?;
+	let address = private_key.extract_public().unwrap();
+	let timeline = db.get_timeline( &address ).await?
+		.ok_or_else(|| Error::Invalid( format!("Ego {} has no channel.", ego) ))?;
+
+	let defaults = timeline.load_post_defaults().await?;
+	let tags = defaults.apply_tags( normalize_tags( tags.iter().map(|t| t.as_str()) ) );
+	check_tags( &tags ).map_err( Error::Invalid )?;
+	let content = defaults.apply_footer( content );
+
+	let post_info = PostInfo {
+		tags,
+		publish_timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as _,
+		visible_from: None,
+		format,
+		series: None,
+		content_warning: None
+	};
+	let (_, post) = timeline.create_post( &private_key, &content, post_info, Vec::new(), None ).await?;
+	db.clone().get_channel( &address ).await?
+		.ok_or( persistence::Error::NotFound )?
+		.log_new_post( &timeline, &post ).await?;
+	db.record_action( Some( ego ), AuditAction::PostCreated, &post_subject( &address, post.id ) ).await?;
+
+	Ok( post )
+}
+
+/// Deletes the channel of the given ego, along with the ego itself, after telling its swarm that the channel has been closed.
+/// The closing is published through the node that is connected to the swarm, if there is one.
+/// Returns false if it hasn't reached any peer yet, in which case nothing is deleted unless `force` is given, as the subscribers would never learn that the channel has ended.
+/// The closing stays in the outbox then, so it is published as soon as a peer connects, after which the deletion can be tried again.
+pub async fn delete_channel( services: &GnunetServices, db: &persistence::Handle, subscriptions: &SharedSubscriptions, ego: &str, force: bool ) -> Result<bool> {
+	let private_key = services.lookup_ego( ego ).await.map_err( Error::Gnunet )?;
+	let address = private_key.extract_public().unwrap();
+
+	let node = subscriptions.read().await.as_ref().and_then(|s| s.node( &address ));
+	let (channel, announced) = close_channel( db, ego, &private_key, node.as_ref() ).await?;
+	if !announced && !force {
+		return Ok(false)
+	}
+
+	// The swarm is left first, so that nothing is stored for the channel anymore while it is being deleted.
+	let sub = subscriptions.write().await.as_mut().and_then(|s| s.remove( &address ));
+	if let Some(sub) = sub {
+		sub.disconnect().await;
+	}
+	delete_closed_channel( services, db, ego, channel ).await?;
+	Ok(true)
+}
+
+/// Closes the channel of the given ego, and publishes its closing through `node`, along with the other events of the outbox.
+/// Returns the channel, and whether the closing has reached a peer, from which the rest of the swarm learns about it.
+pub async fn close_channel( db: &persistence::Handle, ego: &str, private_key: &PrivateKey, node: Option<&Node> ) -> Result<(channel::Handle, bool)> {
+	let address = private_key.extract_public().unwrap();
+	let channel = db.clone().get_channel( &address ).await?
+		.ok_or_else(|| Error::Invalid( format!("Ego {} has no channel.", ego) ))?;
+
+	if channel.load_closed_timestamp().await?.is_none() {
+		match channel.close( private_key ).await {
+			Err(persistence::Error::NotFound) => return Err( Error::Invalid( format!("Ego {} doesn't own its channel anymore.", ego) ) ),
+			other => other?
+		}
+		db.record_action( Some( ego ), AuditAction::ChannelClosed, &address.to_string() ).await?;
+	}
+
+	if let Some(node) = node {
+		if let Err(e) = node.publish_outbox().await {
+			warn!(channel = %address, "Unable to publish the closing of the channel: {}", e);
+		}
+	}
+	// The closing is the last event of the channel, so it has been published once the outbox is empty.
+	let announced = channel.list_outbox().await?.is_empty();
+	Ok(( channel, announced ))
+}
+
+/// Deletes everything that is stored for a channel of our own that has been closed, and its ego along with its private key.
+/// The channel needs to be left first, if we are connected to its swarm.
+pub async fn delete_closed_channel( services: &GnunetServices, db: &persistence::Handle, ego: &str, channel: channel::Handle ) -> Result<()> {
+	let address = channel.load_address().await?;
+
+	let (files, blocks) = channel.purge().await?;
+	services.delete_ego( ego ).await.map_err( Error::Gnunet )?;
+	db.record_action( Some( ego ), AuditAction::ChannelDeleted, &address.to_string() ).await?;
+
+	info!(channel = %address, files, blocks, "Deleted the channel of ego {}.", ego);
+	Ok(())
+}
+
+/// Connects to the swarms of all channels that we know.
+async fn load_subscriptions( services: Arc<GnunetServices>, subscriptions: SharedSubscriptions ) {
+
+	// Before the setup has been done, there is nothing to connect to.
+	if !persistence::database_exists() { return }
+
+	let result = async {
+		let db = persistence::Handle::connect( services.clone() ).await?;
+		let transport = services.transport().await?;
+		// Without the DHT we can still join the swarms through the peers that we know.
+		let discovery = match services.discovery().await {
+			Err(e) => { warn!("Unable to use the DHT for peer discovery: {}", e); None },
+			Ok(d) => Some(d)
+		};
+		SubscriptionsManager::load( db, transport, discovery ).await
+	}.await;
+
+	match result {
+		Err(e) => error!("Unable to connect to the swarms of our channels: {}", e),
+		Ok(subs) => *subscriptions.write().await = Some( subs )
+	}
+}
+
+
+
+impl Error {
+
+	pub fn return_code( &self ) -> i32 {
+		match self {
+			Self::Config(_) => RETURN_CODE_CONFIG,
+			Self::DataDir(_) => RETURN_CODE_CONFIG,
+			Self::Persistence(_) => RETURN_CODE_PERSISTENCE,
+			Self::Gnunet(_) => RETURN_CODE_GNUNET,
+			Self::Invalid(_) => RETURN_CODE_USAGE
+		}
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt( &self, f: &mut fmt::Formatter<'_> ) -> fmt::Result {
+		match self {
+			Self::Config(e) => write!(f, "{}", e),
+			Self::DataDir(e) => write!(f, "Unable to open the data directory: {}", e),
+			Self::Persistence(e) => write!(f, "Database error: {}", e),
+			Self::Gnunet(e) => write!(f, "Unable to reach gnunet, make sure it is running: {}", e),
+			Self::Invalid(message) => write!(f, "{}", message)
+		}
+	}
+}
+
+impl From<persistence::Error> for Error {
+	fn from( other: persistence::Error ) -> Self {
+		Self::Persistence( other )
+	}
+}
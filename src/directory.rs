@@ -0,0 +1,86 @@
+//! The channel directory, in which owners can list their channels so that others can find them by keyword.
+//!
+//! The listings live in the gnunet DHT, see `discovery`, and expire unless they are renewed.
+//! So the channels that are listed are advertised again every so often, for as long as the node runs.
+
+use std::{
+	sync::Arc,
+	time::Duration
+};
+
+use tracing::{error, warn};
+
+use crate::{
+	config,
+	discovery::ChannelListing,
+	persistence::{
+		self,
+		directory::DirectoryEntry,
+		peer::now,
+		Result
+	},
+	post::normalize_tags,
+	services::GnunetServices,
+	shutdown
+};
+
+
+
+/// Advertises the channels that are listed in the directory every so often, until the node shuts down.
+pub async fn advertise_periodically( services: Arc<GnunetServices> ) {
+	loop {
+		// Before the setup has been done, there is nothing to advertise.
+		if persistence::database_exists() {
+			if let Err(e) = advertise_all( &services ).await {
+				error!("Unable to advertise the channels in the directory: {}", e);
+			}
+		}
+
+		if !shutdown::sleep( Duration::from_secs( config::DHT_ADVERTISE_INTERVAL ) ).await { break }
+	}
+}
+
+async fn advertise_all( services: &Arc<GnunetServices> ) -> Result<()> {
+	let db = persistence::Handle::connect( services.clone() ).await.map_err( persistence::Error::Database )?;
+
+	for entry in db.list_directory_entries().await? {
+		if let Err(e) = advertise( services, &db, &entry ).await {
+			warn!(channel = %entry.address, "Unable to advertise the channel in the directory: {}", e);
+		}
+	}
+	Ok(())
+}
+
+/// Lists a single channel in the directory right away, with the title that it has now.
+pub async fn advertise( services: &GnunetServices, db: &persistence::Handle, entry: &DirectoryEntry ) -> Result<()> {
+	let private_key = services.lookup_ego( &entry.ego ).await?;
+	let title = match db.clone().get_channel( &entry.address ).await? {
+		None => return Ok(()),
+		Some(channel) => channel.fetch_profile().await?.map(|p| p.base.title).unwrap_or_default()
+	};
+
+	let listing = ChannelListing {
+		address: entry.address.clone(),
+		title,
+		keywords: entry.keywords.clone(),
+		timestamp: now() as _
+	};
+	services.discovery().await?
+		.advertise_channel( &private_key, listing, Duration::from_secs( config::DHT_ADVERTISE_EXPIRATION ) ).await?;
+	Ok(())
+}
+
+/// Finds the channels that are listed in the directory under all of the words of the query.
+/// Only the first word is looked up in the DHT, the others need to be among the keywords of the listings that it finds.
+pub async fn search( services: &GnunetServices, query: &str ) -> Result<Vec<ChannelListing>> {
+	let keywords = normalize_tags( query.split_whitespace() );
+	let first = match keywords.first() {
+		None => return Ok( Vec::new() ),
+		Some(k) => k
+	};
+
+	let listings = services.discovery().await?.find_channels( first ).await?;
+	Ok( listings.into_iter()
+		.filter(|l| keywords.iter().all(|k| l.keywords.contains( k )))
+		.collect() )
+}
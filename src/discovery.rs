@@ -0,0 +1,147 @@
+//! Finds peers of the swarm of a channel, and channels themselves, through the gnunet DHT.
+//!
+//! Nodes that have free relay slots advertise their peer identity under a key that is derived from the address of the channel.
+//! Nodes that want to join the swarm, but can't reach any of the peers they know, look that key up to find somebody to connect to.
+//!
+//! Owners that opt into the channel directory advertise a listing of their channel under a key for each of its keywords.
+//! The listings are signed with the key of the channel, so that nobody can advertise a channel with a title that its owner didn't give it.
+
+use std::{
+	sync::Arc,
+	time::Duration
+};
+
+use async_std::sync::Mutex;
+use bincode;
+use gnunet::{
+	crypto::HashCode,
+	dht,
+	identity::{PrivateKey, PublicKey, Signature}
+};
+use serde::*;
+
+use crate::{
+	common,
+	validation::Signature as _
+};
+
+
+
+pub struct Discovery {
+	dht: Arc<Mutex<dht::Handle>>,
+	/// The peer identity of our own node, which is what we advertise.
+	local_peer: PublicKey
+}
+
+/// What the directory tells about a channel.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ChannelListing {
+	pub address: PublicKey,
+	/// The title from the profile of the channel, which may be empty.
+	pub title: String,
+	/// The normalized keywords that the channel can be found by.
+	pub keywords: Vec<String>,
+	/// When the listing was made, in milliseconds since the UNIX epoch.
+	/// Of the listings of the same channel, the newest one is used.
+	pub timestamp: u64
+}
+
+/// A listing, signed by the channel that it is about.
+#[derive(Serialize, Deserialize)]
+struct SignedChannelListing {
+	listing: ChannelListing,
+	hash: HashCode,
+	signature: Signature
+}
+
+
+
+impl Discovery {
+
+	pub fn new( dht: Arc<Mutex<dht::Handle>>, local_peer: PublicKey ) -> Self {
+		Self {
+			dht,
+			local_peer
+		}
+	}
+
+	/// The DHT key under which the peers of the swarm of the given channel advertise themselves.
+	pub fn swarm_key( channel: &PublicKey ) -> HashCode {
+		HashCode::generate( format!("QuartzNet swarm {}", channel).as_bytes() )
+	}
+
+	/// Lets the DHT know that our node has room for children in the swarm of the given channel.
+	/// The advertisement expires after `expiration`, so it needs to be repeated for as long as there is room.
+	pub async fn advertise( &self, channel: &PublicKey, expiration: Duration ) -> gnunet::Result<()> {
+		let data = bincode::serialize( &self.local_peer ).expect("serialization error");
+
+		self.dht.lock().await.put( &Self::swarm_key( channel ), &data, expiration ).await
+	}
+
+	/// Looks up the peers that have advertised themselves for the swarm of the given channel.
+	/// Values that aren't peer identities, and our own advertisements, are left out.
+	pub async fn find_peers( &self, channel: &PublicKey ) -> gnunet::Result<Vec<PublicKey>> {
+		let values = self.dht.lock().await.get( &Self::swarm_key( channel ) ).await?;
+
+		let mut peers: Vec<PublicKey> = Vec::with_capacity( values.len() );
+		for value in values {
+			match bincode::deserialize::<PublicKey>( &value ) {
+				Err(_) => {},
+				Ok(peer) => if peer != self.local_peer && !peers.contains( &peer ) {
+					peers.push( peer );
+				}
+			}
+		}
+
+		Ok( peers )
+	}
+
+	/// The DHT key under which the channels with the given keyword are listed.
+	pub fn directory_key( keyword: &str ) -> HashCode {
+		HashCode::generate( format!("QuartzNet directory {}", keyword).as_bytes() )
+	}
+
+	/// Lists the channel in the directory under each of its keywords, signed with the key of the channel.
+	/// Like the advertisements of the swarms, the listing expires after `expiration`.
+	pub async fn advertise_channel( &self, private_key: &PrivateKey, listing: ChannelListing, expiration: Duration ) -> gnunet::Result<()> {
+		let hash = HashCode::generate_from( &listing );
+		let signed = SignedChannelListing {
+			signature: common::sign_hash( private_key, &hash ),
+			hash,
+			listing
+		};
+		let data = bincode::serialize( &signed ).expect("serialization error");
+
+		let mut dht = self.dht.lock().await;
+		for keyword in &signed.listing.keywords {
+			dht.put( &Self::directory_key( keyword ), &data, expiration ).await?;
+		}
+		Ok(())
+	}
+
+	/// Looks up the channels that are listed in the directory under the given normalized keyword.
+	/// Listings that aren't signed by their channel, or that don't have the keyword, are left out.
+	pub async fn find_channels( &self, keyword: &str ) -> gnunet::Result<Vec<ChannelListing>> {
+		let values = self.dht.lock().await.get( &Self::directory_key( keyword ) ).await?;
+
+		let mut listings: Vec<ChannelListing> = Vec::with_capacity( values.len() );
+		for value in values {
+			let signed: SignedChannelListing = match bincode::deserialize( &value ) {
+				Err(_) => continue,
+				Ok(s) => s
+			};
+			let listing = signed.listing;
+			if HashCode::generate_from( &listing ) != signed.hash || !signed.signature.verify_hash( &signed.hash, &listing.address ) { continue }
+			if !listing.keywords.iter().any(|k| k == keyword) { continue }
+
+			match listings.iter_mut().find(|l| l.address == listing.address) {
+				None => listings.push( listing ),
+				Some(known) => if listing.timestamp > known.timestamp {
+					*known = listing;
+				}
+			}
+		}
+
+		Ok( listings )
+	}
+}
@@ -0,0 +1,229 @@
+//! Troubleshooting of the node, with `quartznet doctor`.
+//!
+//! Every part that the node depends on is checked in turn: the configuration, the templates, the database, the clock, gnunet and its services, and the swarms of the channels that we follow.
+//! Each check prints a line saying whether it passed, and a hint on what to do about it when it didn't.
+//! Nothing is changed by the checks, the database is opened read-only and the swarms are left right after they have been joined.
+//! The node itself should be stopped first, because the swarms are joined with the same peer identity.
+
+use std::{
+	path::PathBuf,
+	sync::Arc,
+	time::{Duration, SystemTime, UNIX_EPOCH}
+};
+
+use async_std::future::timeout;
+
+use crate::{
+	cli::Error,
+	config,
+	persistence::{self, schema},
+	services::GnunetServices,
+	setup,
+	subscriptions,
+	templates::Templates
+};
+
+
+
+/// 2021-01-01, in milliseconds since the UNIX epoch.
+/// A clock that is behind this has certainly not been set.
+const EARLIEST_PLAUSIBLE_TIME: u64 = 1_609_459_200_000;
+/// How far the audit log may be ahead of the clock before it is reported, in milliseconds.
+const CLOCK_TOLERANCE: u64 = 5 * 60 * 1000;
+
+/// Prints the findings as they are made, and counts the problems.
+struct Report {
+	problems: usize,
+	warnings: usize
+}
+
+
+
+/// Runs all checks on the node with the given data directory, if any.
+/// Fails with the number of problems if any check failed, warnings alone don't fail.
+pub async fn run( data_dir: Option<PathBuf>, skip_swarms: bool ) -> Result<(), Error> {
+	let mut report = Report { problems: 0, warnings: 0 };
+
+	// Configuration
+	let config_file = config::config_file().map(|p| p.display().to_string()).unwrap_or_else(|| "the configuration file".to_owned());
+	match config::load() {
+		Err(e) => report.fail( "configuration", &e.to_string(), &format!("Fix {}, the defaults are used for the other checks.", config_file) ),
+		Ok(()) => report.ok( "configuration", &format!("{} is valid, or absent", config_file) )
+	}
+
+	if let Err(e) = setup::load_data_dir( data_dir ) {
+		report.fail( "data directory", &e.to_string(), "Give a directory that can be written to with --data-dir, or in the configuration file." );
+		return report.finish()
+	}
+	report.ok( "data directory", &persistence::data_dir().display().to_string() );
+
+	// Templates
+	match Templates::load() {
+		Err(e) => report.fail( "templates", &e.to_string(), &format!("Fix the templates in the directory given by {}, or unset it to use the built-in ones.", config::TEMPLATE_DIR_VAR) ),
+		Ok(_) => report.ok( "templates", "all templates compile" )
+	}
+
+	// Database
+	let database_current = check_database( &mut report );
+
+	// Clock
+	let now = SystemTime::now().duration_since( UNIX_EPOCH ).map(|d| d.as_millis() as u64).unwrap_or( 0 );
+	if now < EARLIEST_PLAUSIBLE_TIME {
+		report.fail( "clock", "the system clock is set before 2021", "Set the clock, posts and bans are timestamped with it." );
+	} else {
+		report.ok( "clock", "the system clock is plausible" );
+	}
+
+	// Gnunet
+	let services = Arc::new( GnunetServices::new( gnunet::Handle::default() ) );
+	if let Err(e) = services.check().await {
+		report.fail( "gnunet", &e.to_string(), "Start gnunet, for example with `gnunet-arm -s`, as the same user as the node." );
+		return report.finish()
+	}
+	report.ok( "gnunet", "the identity service is reachable" );
+
+	let transport = match services.transport().await {
+		Err(e) => {
+			report.fail( "cadet", &e.to_string(), "Make sure that the cadet service is enabled in the gnunet configuration, swarms can't be joined without it." );
+			None
+		},
+		Ok(c) => {
+			report.ok( "cadet", "the cadet service is reachable" );
+			Some(c)
+		}
+	};
+	let discovery = match services.discovery().await {
+		Err(e) => {
+			report.warn( "dht", &e.to_string(), "Enable the dht service, without it only the peers that we already know can be found." );
+			None
+		},
+		Ok(d) => {
+			report.ok( "dht", "the dht service is reachable" );
+			Some(d)
+		}
+	};
+
+	// Swarms
+	if skip_swarms {
+		return report.finish()
+	}
+	let transport = match transport {
+		None => return report.finish(),
+		Some(c) => c
+	};
+	if !database_current {
+		report.warn( "swarms", "not checked", "The swarms can only be checked on a database that is up to date." );
+		return report.finish()
+	}
+
+	let db = persistence::Handle::connect( services.clone() ).await.map_err(|e| persistence::Error::Database( e ))?;
+	let relay_power = subscriptions::load_relay_power( &db ).await?;
+	let addresses = db.list_subscriptions().await?;
+	if addresses.is_empty() {
+		report.ok( "swarms", "no channels are followed" );
+	}
+	for address in addresses {
+		let check = format!("swarm {}", address);
+		let sub = match db.load_subscription( &address ).await? {
+			None => {
+				report.warn( &check, "the subscription has not been saved yet", "Start the node once, so that it can look for the peers of the channel." );
+				continue
+			},
+			Some(s) => s
+		};
+		let channel = match db.clone().get_channel( &address ).await? {
+			None => {
+				report.fail( &check, "the channel is missing from the database", "Unsubscribe and subscribe to the channel again." );
+				continue
+			},
+			Some(c) => c
+		};
+
+		let connecting = sub.find_swarm_connection( channel, transport.clone(), discovery.clone(), relay_power, |_, _| {} );
+		match timeout( Duration::from_secs( config::DOCTOR_CONNECT_TIMEOUT ), connecting ).await {
+			Err(_) => report.warn( &check, "timed out while joining the swarm", "The peers may be slow or busy, try again later." ),
+			Ok(None) => report.warn( &check, "none of the peers of the swarm could be reached", "The channel may be offline. Ask its owner for a share link with peers that are online, and subscribe with it." ),
+			Ok(Some(node)) => {
+				node.disconnect().await;
+				report.ok( &check, "joined the swarm" );
+			}
+		}
+	}
+
+	report.finish()
+}
+
+/// Checks the integrity and the version of the database, and whether the clock has been turned back since the last administrative action.
+/// Returns whether the database is up to date.
+fn check_database( report: &mut Report ) -> bool {
+	if !persistence::database_exists() {
+		report.warn( "database", "the node hasn't been set up yet", "Start the node and open the web interface to run the setup." );
+		return false
+	}
+
+	let inspection = match persistence::inspect_database() {
+		Err(e) => {
+			report.fail( "database", &e.to_string(), "Make sure that the database can be read, and isn't locked by another program than the node." );
+			return false
+		},
+		Ok(i) => i
+	};
+
+	if inspection.integrity_errors.is_empty() {
+		report.ok( "database integrity", "no errors" );
+	} else {
+		report.fail( "database integrity", &inspection.integrity_errors.join("; "), "Stop the node and restore the database from a backup." );
+	}
+
+	let latest = schema::latest_version();
+	let current = if inspection.version > latest {
+		report.fail( "database version", &format!("version {} is newer than this release knows, which is {}", inspection.version, latest), "Upgrade Quartznet, or restore a backup that was made before the last upgrade." );
+		false
+	} else if inspection.version < latest {
+		report.warn( "database version", &format!("version {} of {}", inspection.version, latest), "Start the node once to migrate the database." );
+		false
+	} else {
+		report.ok( "database version", &format!("version {}, up to date", inspection.version) );
+		true
+	};
+
+	// An audit log entry from the future means the clock has been turned back.
+	let now = SystemTime::now().duration_since( UNIX_EPOCH ).map(|d| d.as_millis() as u64).unwrap_or( 0 );
+	if let Some(time) = inspection.latest_action {
+		if time > now + CLOCK_TOLERANCE {
+			report.warn( "clock", "the audit log has entries that are later than now", "The clock has been turned back, check that it is set right." );
+		}
+	}
+
+	current
+}
+
+
+
+impl Report {
+
+	fn ok( &mut self, check: &str, message: &str ) {
+		println!("ok    {}: {}", check, message);
+	}
+
+	fn warn( &mut self, check: &str, message: &str, hint: &str ) {
+		self.warnings += 1;
+		println!("warn  {}: {}", check, message);
+		println!("      {}", hint);
+	}
+
+	fn fail( &mut self, check: &str, message: &str, hint: &str ) {
+		self.problems += 1;
+		println!("FAIL  {}: {}", check, message);
+		println!("      {}", hint);
+	}
+
+	fn finish( self ) -> Result<(), Error> {
+		println!();
+		if self.problems > 0 {
+			return Err( Error::Problems( self.problems ) )
+		}
+		println!("No problems found, {} warning(s).", self.warnings);
+		Ok(())
+	}
+}
@@ -0,0 +1,93 @@
+//! Aggregates the errors that occur while communicating with peers.
+//!
+//! A misbehaving peer can cause the same error over and over again, which would flood the log if every occurrence was printed.
+//! Instead, only the first occurrence of an error within a time window is logged, and the number of repetitions is logged when the window ends.
+
+use std::{
+	collections::HashMap,
+	fmt,
+	sync::Mutex,
+	time::{Duration, Instant}
+};
+
+use gnunet::identity::PublicKey;
+use tracing::warn;
+
+
+
+pub struct ErrorReporter {
+	window: Duration,
+	entries: Mutex<HashMap<(Option<String>, String), Entry>>
+}
+
+struct Entry {
+	/// When the error was first reported in the current window.
+	since: Instant,
+	/// The number of times the error was reported since it was printed.
+	repeated: u64
+}
+
+
+
+impl ErrorReporter {
+
+	/// Creates a reporter that prints identical errors at most once per `window`.
+	pub fn new( window: Duration ) -> Self {
+		Self {
+			window,
+			entries: Mutex::new( HashMap::new() )
+		}
+	}
+
+	/// Reports an error that occurred for the given peer, or for no peer in particular if `None`.
+	/// The error is printed right away, unless the same error has already been printed within the current window.
+	pub fn report( &self, peer: Option<&PublicKey>, error: impl fmt::Display ) {
+		let key = ( peer.map(|p| p.to_string()), error.to_string() );
+		let mut entries = self.entries.lock().unwrap();
+
+		match entries.get_mut( &key ) {
+			Some(entry) => entry.repeated += 1,
+			None => {
+				print_error( &key, None );
+				entries.insert( key, Entry {
+					since: Instant::now(),
+					repeated: 0
+				});
+			}
+		}
+	}
+
+	/// Prints how many times the errors have been repeated, for the errors which window has ended.
+	/// This should be called periodically.
+	pub fn flush( &self ) {
+		let mut entries = self.entries.lock().unwrap();
+		let window = self.window;
+
+		entries.retain(|key, entry| {
+			if entry.since.elapsed() < window {
+				return true
+			}
+
+			if entry.repeated > 0 {
+				print_error( key, Some(( entry.repeated, window )) );
+			}
+			false
+		});
+	}
+
+	pub fn window( &self ) -> Duration {
+		self.window
+	}
+}
+
+fn print_error( key: &(Option<String>, String), repeated: Option<(u64, Duration)> ) {
+	// The node keeps going despite these errors, so they are only warnings.
+	let (peer, error) = key;
+
+	match (peer, repeated) {
+		(None, None) => warn!("{}", error),
+		(Some(p), None) => warn!(peer = %p, "{}", error),
+		(None, Some((count, window))) => warn!(repeated = count, window_secs = window.as_secs(), "{}", error),
+		(Some(p), Some((count, window))) => warn!(peer = %p, repeated = count, window_secs = window.as_secs(), "{}", error)
+	}
+}
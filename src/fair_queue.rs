@@ -0,0 +1,140 @@
+//! Takes the items that a number of sources produce in turn, so that a source that produces a lot can't hold back the others.
+//!
+//! Every source has a bounded queue of its own.
+//! A source that has filled up its queue waits until there is room again, while the items of the other sources keep being taken.
+
+use std::sync::Mutex;
+
+use async_std::channel::{self, Receiver, Sender};
+
+
+
+pub struct FairQueue<K, T> {
+	capacity: usize,
+	state: Mutex<State<K, T>>,
+	/// Holds at most one wake-up, which is sent whenever an item is added.
+	wake_sender: Sender<()>,
+	wake_receiver: Receiver<()>
+}
+
+struct State<K, T> {
+	sources: Vec<Source<K, T>>,
+	/// The position in `sources` of the source whose turn it is.
+	next: usize
+}
+
+struct Source<K, T> {
+	key: K,
+	receiver: Receiver<T>
+}
+
+/// The end of the queue of a source, in which it puts its items.
+/// The queue is removed once this is dropped and the remaining items have been taken.
+pub struct SourceSender<T> {
+	sender: Sender<T>,
+	wake: Sender<()>
+}
+
+/// The number of items that are waiting in the queue of a source.
+pub struct QueueDepth<K> {
+	pub source: K,
+	pub depth: usize,
+	pub capacity: usize
+}
+
+
+
+impl<K, T> FairQueue<K, T> where
+	K: Clone
+{
+
+	/// Creates a queue in which every source can have up to `capacity` items waiting.
+	pub fn new( capacity: usize ) -> Self {
+		let (wake_sender, wake_receiver) = channel::bounded( 1 );
+
+		Self {
+			capacity,
+			state: Mutex::new( State {
+				sources: Vec::new(),
+				next: 0
+			}),
+			wake_sender,
+			wake_receiver
+		}
+	}
+
+	/// Adds a source, and returns the end of its queue.
+	/// The same key can be used for more than one source, they still get their own queue and turn.
+	pub fn add_source( &self, key: K ) -> SourceSender<T> {
+		let (sender, receiver) = channel::bounded( self.capacity );
+		self.state.lock().unwrap().sources.push( Source { key, receiver } );
+
+		SourceSender {
+			sender,
+			wake: self.wake_sender.clone()
+		}
+	}
+
+	/// Waits for the next item, and returns it with the key of its source.
+	/// Returns `None` once the queue has been closed.
+	pub async fn next( &self ) -> Option<(K, T)> {
+		loop {
+			if let Some(next) = self.take() {
+				return Some( next )
+			}
+			if self.wake_receiver.recv().await.is_err() {
+				return None
+			}
+		}
+	}
+
+	/// Takes an item from the first source after the last one that had its turn, that has any.
+	fn take( &self ) -> Option<(K, T)> {
+		let mut state = self.state.lock().unwrap();
+
+		// Sources that are gone and have nothing left are of no use anymore.
+		state.sources.retain(|s| !(s.receiver.is_closed() && s.receiver.is_empty()));
+
+		let count = state.sources.len();
+		for i in 0..count {
+			let index = (state.next + i) % count;
+			if let Ok(item) = state.sources[index].receiver.try_recv() {
+				state.next = (index + 1) % count;
+				return Some(( state.sources[index].key.clone(), item ))
+			}
+		}
+		None
+	}
+
+	/// Stops `next` from waiting for items, and the sources from waiting for room.
+	pub fn close( &self ) {
+		for source in self.state.lock().unwrap().sources.iter() {
+			source.receiver.close();
+		}
+		self.wake_sender.close();
+	}
+
+	/// Returns the number of items that are waiting in the queue of every source.
+	pub fn depths( &self ) -> Vec<QueueDepth<K>> {
+		self.state.lock().unwrap().sources.iter().map(|s| QueueDepth {
+			source: s.key.clone(),
+			depth: s.receiver.len(),
+			capacity: self.capacity
+		}).collect()
+	}
+}
+
+impl<T> SourceSender<T> {
+
+	/// Puts an item in the queue, waiting for room if the queue is full.
+	/// Returns false if the queue has been closed, in which case the item will never be taken.
+	pub async fn send( &self, item: T ) -> bool {
+		if self.sender.send( item ).await.is_err() {
+			return false
+		}
+
+		// If a wake-up is already waiting, that one will do.
+		let _ = self.wake.try_send(());
+		true
+	}
+}
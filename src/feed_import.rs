@@ -0,0 +1,150 @@
+//! Imports the posts of an existing blog from its RSS or Atom feed, so that it can be moved to a channel.
+//!
+//! Every entry of the feed becomes a post, with the time at which it was published and its categories as tags.
+//! The HTML of an entry is kept as it is, in a Markdown post, headed by the title of the entry and followed by a link to where it was published originally.
+//! Entries are imported oldest first, so that the ids of the posts follow the order in which they were published.
+//! Importing the same feed again only adds the entries that weren't imported before, see `persistence::import`.
+
+use std::{
+	fmt,
+	time::{Duration, SystemTime, UNIX_EPOCH}
+};
+
+use actix_web::http::header;
+use feed_rs::model::Entry;
+use gnunet::identity::PrivateKey;
+
+use crate::{
+	config,
+	persistence::{
+		self,
+		audit::AuditAction,
+		import::{ImportedEntry, ImportReport}
+	},
+	post::{check_tags, normalize_tags, ContentFormat, PostInfo, TAGS_MAX_COUNT}
+};
+
+
+
+#[derive(Debug)]
+pub enum Error {
+	Persistence( persistence::Error ),
+	/// The feed couldn't be fetched.
+	Http( String ),
+	/// The server of the feed responded with an error status.
+	Status( u16 ),
+	/// The feed is no RSS or Atom feed.
+	Feed( String )
+}
+
+
+
+/// Imports the entries of the feed at the given URL as posts of the channel of the given ego.
+pub async fn import_feed( db: &persistence::Handle, private_key: &PrivateKey, ego: &str, url: &str ) -> Result<ImportReport, Error> {
+	let entries = fetch_entries( url ).await?;
+
+	let address = private_key.extract_public().unwrap();
+	let channel = db.clone().get_channel( &address ).await?
+		.ok_or( persistence::Error::NotFound )?;
+	let report = channel.import_entries( private_key, &entries ).await?;
+	if report.imported > 0 {
+		db.record_action( Some( ego ), AuditAction::PostsImported, &format!("{} from {}", address, url) ).await?;
+	}
+
+	Ok( report )
+}
+
+/// Fetches a feed, and turns its entries into posts, oldest first.
+pub async fn fetch_entries( url: &str ) -> Result<Vec<ImportedEntry>, Error> {
+	let mut response = awc::Client::builder()
+		.timeout( Duration::from_millis( config::FEED_IMPORT_TIMEOUT ) )
+		.finish()
+		.get( url )
+		.insert_header(( header::ACCEPT, "application/rss+xml, application/atom+xml, application/xml;q=0.9, */*;q=0.8" ))
+		.send().await
+		.map_err(|e| Error::Http( e.to_string() ))?;
+	if !response.status().is_success() {
+		return Err( Error::Status( response.status().as_u16() ) )
+	}
+	let body = response.body().limit( config::FEED_IMPORT_MAX_SIZE ).await
+		.map_err(|e| Error::Http( e.to_string() ))?;
+
+	let feed = feed_rs::parser::parse( &body[..] ).map_err(|e| Error::Feed( e.to_string() ))?;
+	let now = SystemTime::now().duration_since( UNIX_EPOCH ).unwrap().as_millis() as u64;
+	let mut entries: Vec<ImportedEntry> = feed.entries.into_iter().filter_map(|e| convert( e, now )).collect();
+	entries.sort_by_key(|e| e.info.publish_timestamp);
+
+	Ok( entries )
+}
+
+/// Turns an entry of a feed into a post.
+/// Entries without a title and without content are left out.
+/// Entries without a date get the current time.
+fn convert( entry: Entry, now: u64 ) -> Option<ImportedEntry> {
+	let title = entry.title.map(|t| t.content.trim().to_owned()).filter(|t| !t.is_empty());
+	let html = entry.content.and_then(|c| c.body)
+		.or_else(|| entry.summary.map(|s| s.content))
+		.filter(|h| !h.trim().is_empty());
+	if title.is_none() && html.is_none() { return None }
+
+	let mut content = String::new();
+	if let Some(title) = &title {
+		content.push_str( &format!("# {}\n\n", title.replace('\n', " ")) );
+	}
+	if let Some(html) = &html {
+		content.push_str( html.trim() );
+		content.push_str("\n\n");
+	}
+	if let Some(link) = entry.links.first() {
+		content.push_str( &format!("[Originally published here.](<{}>)\n", link.href) );
+	}
+
+	let publish_timestamp = entry.published.or( entry.updated )
+		.map(|t| t.timestamp_millis().max(0) as u64)
+		.unwrap_or( now );
+
+	Some( ImportedEntry {
+		entry_id: entry.id,
+		content,
+		info: PostInfo {
+			tags: convert_tags( entry.categories.iter().map(|c| c.term.as_str()) ),
+			publish_timestamp,
+			visible_from: None,
+			format: ContentFormat::Markdown,
+			series: None,
+			content_warning: None
+		}
+	})
+}
+
+/// Turns the categories of an entry into tags.
+/// Spaces become dashes, and categories that still aren't valid tags are left out, as are the ones that don't fit anymore.
+pub fn convert_tags<'a>( categories: impl Iterator<Item=&'a str> ) -> Vec<String> {
+	let dashed: Vec<String> = categories.map(|c| c.trim().split_whitespace().collect::<Vec<_>>().join("-")).collect();
+
+	let mut tags = normalize_tags( dashed.iter().map(|c| c.as_str()) );
+	tags.retain(|t| check_tags( std::slice::from_ref( t ) ).is_ok());
+	tags.truncate( TAGS_MAX_COUNT );
+	tags
+}
+
+
+
+impl From<persistence::Error> for Error {
+	fn from( other: persistence::Error ) -> Self {
+		Self::Persistence( other )
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt( &self, f: &mut fmt::Formatter<'_> ) -> fmt::Result {
+		match self {
+			Self::Persistence(e) => write!(f, "{}", e),
+			Self::Http(e) => write!(f, "Unable to fetch the feed: {}", e),
+			Self::Status(status) => write!(f, "Unable to fetch the feed, the server responded with status {}", status),
+			Self::Feed(e) => write!(f, "Unable to read the feed: {}", e)
+		}
+	}
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,138 @@
+//! The health and readiness of the node, for service managers and container orchestrators, see `/healthz` and `/readyz`.
+//!
+//! The node is healthy when its database can be queried, and the gnunet services that it can't do without respond: the identity service and CADET.
+//! It is ready when it is healthy and the swarms of its channels have been loaded, which only happens after a connection to each of them has been attempted.
+//! Whether those attempts succeeded doesn't matter, as a channel whose peers are all offline is no fault of the node.
+//! Every check is reported by name, so that it can be seen which dependency is failing.
+
+use std::{
+	sync::Arc,
+	time::Duration
+};
+
+use async_std::future::timeout;
+use rusqlite::NO_PARAMS;
+use serde::Serialize;
+
+use crate::{
+	config,
+	persistence,
+	services::GnunetServices,
+	subscriptions::SharedSubscriptions
+};
+
+
+
+/// The outcome of all checks.
+#[derive(Serialize)]
+pub struct HealthReport {
+	/// Whether all checks have passed.
+	pub ok: bool,
+	pub checks: Vec<Check>
+}
+
+/// The outcome of the check of a single dependency.
+#[derive(Serialize)]
+pub struct Check {
+	pub name: &'static str,
+	pub ok: bool,
+	/// What has been found, or why the check failed.
+	pub detail: String
+}
+
+
+
+impl HealthReport {
+
+	fn new( checks: Vec<Check> ) -> Self {
+		Self {
+			ok: checks.iter().all(|c| c.ok),
+			checks
+		}
+	}
+}
+
+impl Check {
+
+	fn pass( name: &'static str, detail: impl Into<String> ) -> Self {
+		Self { name, ok: true, detail: detail.into() }
+	}
+
+	fn fail( name: &'static str, detail: impl Into<String> ) -> Self {
+		Self { name, ok: false, detail: detail.into() }
+	}
+}
+
+
+
+/// Checks whether the node can do its work.
+pub async fn check_health( services: &Arc<GnunetServices> ) -> HealthReport {
+	HealthReport::new( vec![
+		check_database( services ).await,
+		check_identity( services ).await,
+		check_cadet( services ).await
+	])
+}
+
+/// Checks whether the node is healthy, and has tried to join the swarms of its channels.
+pub async fn check_readiness( services: &Arc<GnunetServices>, subscriptions: &SharedSubscriptions ) -> HealthReport {
+	HealthReport::new( vec![
+		check_database( services ).await,
+		check_identity( services ).await,
+		check_cadet( services ).await,
+		check_subscriptions( subscriptions ).await
+	])
+}
+
+async fn check_database( services: &Arc<GnunetServices> ) -> Check {
+	const NAME: &str = "database";
+
+	// Before the setup has been done, there is no database yet.
+	if !persistence::database_exists() {
+		return Check::fail( NAME, "the node hasn't been set up yet" )
+	}
+
+	let query = async {
+		let db = persistence::Handle::connect( services.clone() ).await?;
+		db.query_one("SELECT 1", NO_PARAMS, |_, row| row.get::<_, i64>(0) ).await
+	};
+	match timeout( Duration::from_secs( config::HEALTH_CHECK_TIMEOUT ), query ).await {
+		Err(_) => Check::fail( NAME, "timed out, the database may be locked" ),
+		Ok(Err(e)) => Check::fail( NAME, e.to_string() ),
+		Ok(Ok(_)) => Check::pass( NAME, "reachable" )
+	}
+}
+
+async fn check_identity( services: &Arc<GnunetServices> ) -> Check {
+	const NAME: &str = "identity";
+
+	// Listing the egos is an actual request, whereas a handle that has been connected before can have stopped working since.
+	match timeout( Duration::from_secs( config::HEALTH_CHECK_TIMEOUT ), services.list_egos() ).await {
+		Err(_) => Check::fail( NAME, "the gnunet identity service doesn't respond" ),
+		Ok(Err(e)) => Check::fail( NAME, e.to_string() ),
+		Ok(Ok(egos)) => Check::pass( NAME, format!("{} egos", egos.len()) )
+	}
+}
+
+async fn check_cadet( services: &Arc<GnunetServices> ) -> Check {
+	const NAME: &str = "cadet";
+
+	match timeout( Duration::from_secs( config::HEALTH_CHECK_TIMEOUT ), services.transport() ).await {
+		Err(_) => Check::fail( NAME, "the gnunet cadet service doesn't respond" ),
+		Ok(Err(e)) => Check::fail( NAME, e.to_string() ),
+		Ok(Ok(_)) => Check::pass( NAME, "reachable" )
+	}
+}
+
+/// The subscriptions are only loaded once a connection to the swarm of every channel has been attempted.
+async fn check_subscriptions( subscriptions: &SharedSubscriptions ) -> Check {
+	const NAME: &str = "subscriptions";
+
+	match &*subscriptions.read().await {
+		None => Check::fail( NAME, "the swarms of the channels haven't all been attempted yet" ),
+		Some(subs) => {
+			let connected = subs.nodes().iter().filter(|n| n.is_connected()).count();
+			Check::pass( NAME, format!("{} of {} swarms connected", connected, subs.len()) )
+		}
+	}
+}
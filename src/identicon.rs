@@ -0,0 +1,73 @@
+//! Generates identicons: simple symmetric images that are derived from an address.
+//! They are used as the default icon of a channel, until it has a profile picture.
+
+use std::fmt::Write;
+
+use gnunet::crypto::HashCode;
+
+
+
+/// The number of cells in both directions.
+const GRID_SIZE: usize = 5;
+/// The size of one cell in pixels.
+const CELL_SIZE: usize = 10;
+
+
+
+/// Generates an SVG identicon for the given data, which is usually an address.
+pub fn generate_svg( data: &[u8] ) -> String {
+	let hash = HashCode::generate( data ).to_bytes();
+
+	// Take the color from the first bytes, but keep it from getting too light to see.
+	let hue = ((hash[0] as u32) << 8 | hash[1] as u32) % 360;
+	let color = format!("hsl({}, 60%, 45%)", hue);
+
+	let size = GRID_SIZE * CELL_SIZE;
+	let mut svg = String::with_capacity( 2048 );
+	write!(svg, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{0}\" viewBox=\"0 0 {0} {0}\">", size).unwrap();
+	write!(svg, "<rect width=\"{0}\" height=\"{0}\" fill=\"#f0f0f0\"/>", size).unwrap();
+
+	// Only the left half (including the middle column) is derived from the hash, the right half mirrors it.
+	let half = (GRID_SIZE + 1) / 2;
+	for y in 0..GRID_SIZE {
+		for x in 0..half {
+			let bit = y * half + x;
+			let filled = hash[2 + bit / 8] & (1 << (bit % 8)) > 0;
+
+			if filled {
+				for column in [x, GRID_SIZE - 1 - x].iter() {
+					write!(svg, "<rect x=\"{}\" y=\"{}\" width=\"{2}\" height=\"{2}\" fill=\"{3}\"/>",
+						column * CELL_SIZE, y * CELL_SIZE, CELL_SIZE, color
+					).unwrap();
+					// The middle column mirrors onto itself.
+					if *column == GRID_SIZE - 1 - x { break }
+				}
+			}
+		}
+	}
+
+	svg.push_str("</svg>");
+	svg
+}
+
+/// Guesses the content type of an image by looking at its first bytes.
+pub fn sniff_image_type( data: &[u8] ) -> Option<&'static str> {
+	if data.starts_with( b"\x89PNG\r\n\x1a\n" ) {
+		Some("image/png")
+	}
+	else if data.starts_with( b"\xff\xd8\xff" ) {
+		Some("image/jpeg")
+	}
+	else if data.starts_with( b"GIF87a" ) || data.starts_with( b"GIF89a" ) {
+		Some("image/gif")
+	}
+	else if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+		Some("image/webp")
+	}
+	else if data.starts_with( b"<svg" ) || data.starts_with( b"<?xml" ) {
+		Some("image/svg+xml")
+	}
+	else {
+		None
+	}
+}
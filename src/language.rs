@@ -0,0 +1,107 @@
+//! The languages that the texts which QuartzNet writes itself, like the system posts in feeds, can be shown in.
+//!
+//! Posts are shown as their publishers wrote them, this only concerns the texts that are generated on this node.
+
+use crate::persistence::system_post::SystemPostKind;
+
+
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Language {
+	English,
+	Dutch,
+	German,
+	French,
+	Spanish
+}
+
+
+
+/// All languages, in the order in which they are offered.
+pub const LANGUAGES: &[Language] = &[
+	Language::English,
+	Language::Dutch,
+	Language::German,
+	Language::French,
+	Language::Spanish
+];
+
+
+
+impl Language {
+
+	/// The ISO 639-1 code of the language, under which the setting is stored.
+	pub fn code( &self ) -> &'static str {
+		match self {
+			Self::English => "en",
+			Self::Dutch => "nl",
+			Self::German => "de",
+			Self::French => "fr",
+			Self::Spanish => "es"
+		}
+	}
+
+	pub fn from_code( code: &str ) -> Option<Self> {
+		LANGUAGES.iter().find(|l| l.code() == code.trim()).cloned()
+	}
+
+	/// The name of the language, in the language itself.
+	pub fn name( &self ) -> &'static str {
+		match self {
+			Self::English => "English",
+			Self::Dutch => "Nederlands",
+			Self::German => "Deutsch",
+			Self::French => "Français",
+			Self::Spanish => "Español"
+		}
+	}
+
+	/// Describes a change to a channel, where `subject` is the key that the change applies to, if any.
+	pub fn system_post_text( &self, kind: SystemPostKind, subject: &str ) -> String {
+		use SystemPostKind::*;
+
+		match self {
+			Self::English => match kind {
+				ChannelCreated => "This channel has been created.".to_owned(),
+				PublisherAdded => format!("{} may now publish in this channel.", subject),
+				PublisherRevoked => format!("{} may no longer publish in this channel.", subject),
+				OwnershipOffered => format!("This channel is being handed to {}.", subject),
+				OwnershipTransferred => format!("This channel is now signed with the key {}.", subject)
+			},
+			Self::Dutch => match kind {
+				ChannelCreated => "Dit kanaal is aangemaakt.".to_owned(),
+				PublisherAdded => format!("{} mag nu in dit kanaal publiceren.", subject),
+				PublisherRevoked => format!("{} mag niet meer in dit kanaal publiceren.", subject),
+				OwnershipOffered => format!("Dit kanaal wordt overgedragen aan {}.", subject),
+				OwnershipTransferred => format!("Dit kanaal wordt nu ondertekend met de sleutel {}.", subject)
+			},
+			Self::German => match kind {
+				ChannelCreated => "Dieser Kanal wurde erstellt.".to_owned(),
+				PublisherAdded => format!("{} darf jetzt in diesem Kanal veröffentlichen.", subject),
+				PublisherRevoked => format!("{} darf nicht mehr in diesem Kanal veröffentlichen.", subject),
+				OwnershipOffered => format!("Dieser Kanal wird an {} übergeben.", subject),
+				OwnershipTransferred => format!("Dieser Kanal wird jetzt mit dem Schlüssel {} signiert.", subject)
+			},
+			Self::French => match kind {
+				ChannelCreated => "Cette chaîne a été créée.".to_owned(),
+				PublisherAdded => format!("{} peut désormais publier sur cette chaîne.", subject),
+				PublisherRevoked => format!("{} ne peut plus publier sur cette chaîne.", subject),
+				OwnershipOffered => format!("Cette chaîne est en cours de transfert à {}.", subject),
+				OwnershipTransferred => format!("Cette chaîne est désormais signée avec la clé {}.", subject)
+			},
+			Self::Spanish => match kind {
+				ChannelCreated => "Este canal ha sido creado.".to_owned(),
+				PublisherAdded => format!("{} ahora puede publicar en este canal.", subject),
+				PublisherRevoked => format!("{} ya no puede publicar en este canal.", subject),
+				OwnershipOffered => format!("Este canal se está traspasando a {}.", subject),
+				OwnershipTransferred => format!("Este canal ahora se firma con la clave {}.", subject)
+			}
+		}
+	}
+}
+
+impl Default for Language {
+	fn default() -> Self {
+		Self::English
+	}
+}
@@ -0,0 +1,128 @@
+//! The node of Quartznet, the decentralized censorship-resistant blogging network, as a library.
+//!
+//! Applications that want to embed a node without its web interface start a `daemon::Daemon`.
+//! Everything that the node stores can be read and changed through `persistence`, and the swarms of the channels are joined by `subscriptions` and `swarm`.
+//! The data structures and wire encoding of the protocol are re-exported from `quartz_net_protocol`.
+//!
+//! The `quartznet` binary is a thin layer on top of this library, which adds the web interface and the command line.
+
+pub use quartz_net_protocol::{encryption, event, message, post, validation};
+
+use std::sync::Arc;
+
+use assets::Assets;
+use daemon::Daemon;
+use services::GnunetServices;
+use subscriptions::SharedSubscriptions;
+use templates::Templates;
+
+
+
+#[doc(hidden)]
+pub mod activitypub;
+pub mod api;
+mod archive;
+#[doc(hidden)]
+pub mod assets;
+pub mod auth;
+mod bus;
+#[doc(hidden)]
+pub mod cli;
+mod common;
+pub mod config;
+pub mod daemon;
+mod directory;
+mod discovery;
+#[doc(hidden)]
+pub mod doctor;
+mod error_report;
+mod fair_queue;
+mod feed_import;
+pub mod health;
+mod identicon;
+mod language;
+#[doc(hidden)]
+pub mod live;
+pub mod logging;
+mod maintenance;
+pub mod metrics;
+pub mod micropub;
+pub mod persistence;
+mod page_cache;
+mod preview;
+mod preview_cache;
+mod rate_limit;
+pub mod reload;
+mod render;
+mod retention;
+mod runtime;
+mod scheduler;
+mod session_manager;
+pub mod services;
+mod setup;
+mod share;
+mod static_site;
+#[doc(hidden)]
+pub mod shutdown;
+pub mod subscriptions;
+pub mod swarm;
+#[doc(hidden)]
+pub mod templates;
+mod thumbnail;
+pub mod transport;
+pub mod tuning;
+#[doc(hidden)]
+pub mod web;
+#[doc(hidden)]
+pub mod web_error;
+
+
+
+pub const RETURN_CODE_OK: i32 = 0;
+pub const RETURN_CODE_UNEXPECTED: i32 = 1;
+/// The configuration or the templates could not be loaded.
+pub const RETURN_CODE_CONFIG: i32 = 2;
+/// The database could not be opened or migrated.
+pub const RETURN_CODE_PERSISTENCE: i32 = 3;
+/// The gnunet services could not be reached.
+pub const RETURN_CODE_GNUNET: i32 = 4;
+/// The HTTP server could not be started.
+pub const RETURN_CODE_HTTP: i32 = 5;
+/// A command was used incorrectly.
+pub const RETURN_CODE_USAGE: i32 = 6;
+
+
+
+/// What the handlers of the web interface share.
+#[doc(hidden)]
+pub struct Globals {
+	services: Arc<GnunetServices>,
+	/// The connections to the swarms of the channels we know, once they have been made.
+	subscriptions: SharedSubscriptions,
+	templates: Arc<Templates>,
+	/// The scripts, stylesheets and other files that are served as they are.
+	static_files: Assets
+}
+
+
+
+impl Globals {
+
+	/// Serves the web interface on top of the given daemon.
+	pub fn new( daemon: &Daemon, templates: Arc<Templates> ) -> Self {
+		web_error::use_templates( templates.clone() );
+		Self {
+			services: daemon.services().clone(),
+			subscriptions: daemon.subscriptions().clone(),
+			templates,
+			static_files: Assets::static_files()
+		}
+	}
+
+	/// Connects to the database for a single request.
+	/// The connection is taken from the pool, and goes back to it when the handle is dropped.
+	/// Requests don't share a single handle, as the transactions of one would take in the statements of the others.
+	pub async fn connect_database( &self ) -> persistence::Result<persistence::Handle> {
+		Ok( persistence::Handle::connect( self.services.clone() ).await? )
+	}
+}
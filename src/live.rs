@@ -0,0 +1,174 @@
+//! Pushes the changes to the posts of a channel to the browser, over a WebSocket.
+//!
+//! Every change that a swarm applies is sent as a JSON object like `{"kind":"published","publisher":"...","post_id":3}`.
+//! The page itself decides how to show it, which is by loading the post again.
+//!
+//! The notifications are pushed over a WebSocket of their own, like `{"kind":"comment","url":"...","unread":2}`.
+//! Every page listens to it, to keep the unread counter in its header up to date and to show desktop notifications if the user allowed those.
+
+use std::sync::Arc;
+
+use actix::{Actor, ActorContext, AsyncContext, StreamHandler};
+use actix_web::{error, get, HttpRequest, HttpResponse, web};
+use actix_web_actors::ws;
+use gnunet::identity::PublicKey;
+use serde::*;
+use tracing::error;
+
+use crate::{
+	bus::{self, NewNotification, PostChange, PostChangeKind},
+	persistence::notification::NotificationKind,
+	Globals
+};
+
+
+
+#[derive(Deserialize)]
+pub struct ChannelParams {
+	address: String
+}
+
+/// The WebSocket connection of a single page.
+struct ChannelSocket {
+	channel: PublicKey
+}
+
+#[derive(Serialize)]
+struct PostChangeView {
+	kind: &'static str,
+	publisher: String,
+	post_id: u64
+}
+
+/// The WebSocket connection over which a page receives the notifications.
+struct NotificationSocket {
+	/// The number of unread notifications when the page connected, which is sent first.
+	unread: u64
+}
+
+#[derive(Serialize)]
+struct NotificationView {
+	/// `None` for the message that only tells the number of unread notifications.
+	kind: Option<&'static str>,
+	/// The page of the post that the notification is about.
+	url: Option<String>,
+	unread: u64
+}
+
+
+
+/// Opens a WebSocket on which the changes to the posts of the channel are sent, as they arrive.
+#[get("/ws/channel/{address}")]
+pub async fn channel_socket( p: web::Path<ChannelParams>, req: HttpRequest, stream: web::Payload ) -> error::Result<HttpResponse> {
+
+	let channel = PublicKey::from_string( &p.address )
+		.ok_or_else(|| error::ErrorBadRequest("Invalid channel address."))?;
+
+	ws::start( ChannelSocket { channel }, &req, stream )
+}
+
+impl Actor for ChannelSocket {
+	type Context = ws::WebsocketContext<Self>;
+
+	fn started( &mut self, ctx: &mut Self::Context ) {
+		ctx.add_stream( bus::POSTS.listen( self.channel.clone() ) );
+	}
+}
+
+impl StreamHandler<PostChange> for ChannelSocket {
+
+	fn handle( &mut self, change: PostChange, ctx: &mut Self::Context ) {
+		let view = PostChangeView {
+			kind: match change.kind {
+				PostChangeKind::Published => "published",
+				PostChangeKind::Revised => "revised",
+				PostChangeKind::Forgotten => "forgotten",
+				PostChangeKind::Commented => "commented",
+				PostChangeKind::Reacted => "reacted"
+			},
+			publisher: change.publisher.to_string(),
+			post_id: change.post_id
+		};
+		ctx.text( serde_json::to_string( &view ).unwrap() );
+	}
+
+	/// The bus lives as long as the process, so the socket is simply kept open.
+	fn finished( &mut self, _ctx: &mut Self::Context ) {}
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for ChannelSocket {
+
+	/// Nothing is expected from the page, except for keeping the connection alive and closing it.
+	fn handle( &mut self, message: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context ) {
+		match message {
+			Ok(ws::Message::Ping(data)) => ctx.pong( &data ),
+			Ok(ws::Message::Close(reason)) => {
+				ctx.close( reason );
+				ctx.stop();
+			},
+			Err(_) => ctx.stop(),
+			_ => {}
+		}
+	}
+}
+
+
+
+/// Opens a WebSocket on which the notifications are sent, as they are recorded.
+#[get("/ws/notifications")]
+pub async fn notification_socket( g: web::Data<Arc<Globals>>, req: HttpRequest, stream: web::Payload ) -> error::Result<HttpResponse> {
+
+	let db = g.connect_database().await
+		.map_err(|e| { error!("Database error: {}", e); error::ErrorInternalServerError("Database error") })?;
+	let unread = db.count_unread_notifications().await
+		.map_err(|e| { error!("Database error: {}", e); error::ErrorInternalServerError("Database error") })?;
+
+	ws::start( NotificationSocket { unread }, &req, stream )
+}
+
+impl Actor for NotificationSocket {
+	type Context = ws::WebsocketContext<Self>;
+
+	fn started( &mut self, ctx: &mut Self::Context ) {
+		let view = NotificationView { kind: None, url: None, unread: self.unread };
+		ctx.text( serde_json::to_string( &view ).unwrap() );
+		ctx.add_stream( bus::NOTIFICATIONS.listen() );
+	}
+}
+
+impl StreamHandler<NewNotification> for NotificationSocket {
+
+	fn handle( &mut self, new: NewNotification, ctx: &mut Self::Context ) {
+		let notification = &new.notification;
+		let view = NotificationView {
+			kind: Some( match notification.kind {
+				NotificationKind::Post => "post",
+				NotificationKind::Revision => "revision",
+				NotificationKind::Comment => "comment",
+				NotificationKind::Mention => "mention"
+			}),
+			url: Some( format!("/channel/address/{}/post/{}", notification.publisher, notification.post_id) ),
+			unread: new.unread
+		};
+		ctx.text( serde_json::to_string( &view ).unwrap() );
+	}
+
+	/// The bus lives as long as the process, so the socket is simply kept open.
+	fn finished( &mut self, _ctx: &mut Self::Context ) {}
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for NotificationSocket {
+
+	/// Nothing is expected from the page, except for keeping the connection alive and closing it.
+	fn handle( &mut self, message: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context ) {
+		match message {
+			Ok(ws::Message::Ping(data)) => ctx.pong( &data ),
+			Ok(ws::Message::Close(reason)) => {
+				ctx.close( reason );
+				ctx.stop();
+			},
+			Err(_) => ctx.stop(),
+			_ => {}
+		}
+	}
+}
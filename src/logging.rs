@@ -0,0 +1,103 @@
+//! Logs what the node does, through `tracing`.
+//!
+//! How much is logged is set with `log_level` in the configuration file, and can be set per module with `log_levels`.
+//! The environment variable `QUARTZNET_LOG` takes the place of both, which is handy for a single run.
+//! Node operators that collect their logs can have them written as JSON, one object per line, by setting `log_format` to `json`.
+//! The log levels can be changed while the node runs, by reloading the configuration file.
+//!
+//! The swarm of every channel logs within a span of its own, and every connection to a peer within a span inside that one.
+//! The requests that peers make, and the requests to the web interface, get a span as well.
+//! This way, everything that happened with a single peer or request can be picked out of the log.
+
+use std::{
+	env,
+	future::Future,
+	io,
+	sync::Mutex,
+	time::Instant
+};
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use lazy_static::lazy_static;
+use tracing::{info, info_span, warn, Instrument};
+use tracing_subscriber::{
+	fmt,
+	prelude::*,
+	reload,
+	EnvFilter,
+	Registry
+};
+
+use crate::{
+	config::{self, Config, LogFormat},
+	metrics
+};
+
+
+
+lazy_static! {
+	/// Replaces the filter of the logger, if the logger is ours.
+	static ref FILTER: Mutex<Option<reload::Handle<EnvFilter, Registry>>> = Mutex::new( None );
+}
+
+/// Starts logging with the settings of the configuration file, so this should be called after it has been loaded.
+/// If the application that embeds the node has started logging already, its logger is left in place.
+pub fn init() {
+	let cfg = config::get();
+	let (levels, handle) = reload::Layer::new( filter( &cfg ) );
+	let (text, json) = match cfg.log_format {
+		LogFormat::Text => (Some( fmt::layer().with_writer( io::stderr ) ), None),
+		LogFormat::Json => (None, Some( fmt::layer().json().with_writer( io::stderr ) ))
+	};
+
+	if tracing_subscriber::registry().with( levels ).with( text ).with( json ).try_init().is_ok() {
+		*FILTER.lock().unwrap() = Some( handle );
+	}
+}
+
+/// Applies the log levels of the configuration that is in use now.
+pub fn reload() {
+	if let Some(handle) = &*FILTER.lock().unwrap() {
+		if let Err(e) = handle.reload( filter( &config::get() ) ) {
+			warn!("Unable to change the log levels: {}", e);
+		}
+	}
+}
+
+/// The filter that follows from the log levels in the configuration, unless the environment has one.
+fn filter( cfg: &Config ) -> EnvFilter {
+	if let Ok(f) = env::var( config::LOG_FILTER_VAR ) {
+		return EnvFilter::new( f )
+	}
+
+	let mut directives = vec![ cfg.log_level.directive().to_owned() ];
+	for (module, level) in &cfg.log_levels {
+		directives.push( format!("{}={}", module, level.directive()) );
+	}
+	EnvFilter::new( directives.join(",") )
+}
+
+/// Handles a request to the web interface within a span of its own, and logs how it went.
+/// How long it took is added to the metrics as well.
+/// This is meant to be given to `App::wrap_fn`.
+pub fn log_request<S, B>( request: ServiceRequest, service: &S ) -> impl Future<Output=Result<ServiceResponse<B>, actix_web::Error>> where
+	S: Service<ServiceRequest, Response=ServiceResponse<B>, Error=actix_web::Error>
+{
+	let span = info_span!("request", method = %request.method(), path = %request.path());
+	let started = Instant::now();
+	let response = span.in_scope(|| service.call( request ));
+
+	async move {
+		let response = response.await;
+		let elapsed = started.elapsed();
+		let elapsed_ms = elapsed.as_millis() as u64;
+		match &response {
+			Ok(r) => {
+				info!(status = r.status().as_u16(), elapsed_ms, "handled");
+				metrics::HTTP_REQUESTS.with_label_values( &[r.status().as_str()] ).observe( elapsed.as_secs_f64() );
+			},
+			Err(e) => warn!(elapsed_ms, "failed: {}", e)
+		}
+		response
+	}.instrument( span )
+}
@@ -1,70 +1,278 @@
 use actix_web::{App, HttpServer};
-use gnunet;
-use tera::Tera;
+use clap::Parser;
+use quartz_net::{
+	api,
+	auth,
+	cli::{self, Cli, Command, ServeArgs},
+	config,
+	daemon::{self, Daemon, Options},
+	doctor,
+	live,
+	logging,
+	micropub,
+	reload,
+	shutdown,
+	templates::Templates,
+	web,
+	Globals,
+	RETURN_CODE_CONFIG,
+	RETURN_CODE_HTTP,
+	RETURN_CODE_OK,
+	RETURN_CODE_UNEXPECTED,
+	RETURN_CODE_USAGE
+};
 
 use std::{
+	fmt,
+	io,
+	path::PathBuf,
+	process,
 	sync::Arc
 };
 
+use tracing::info;
 
 
-mod common;
-mod config;
-mod event;
-mod r#macro;
-mod message;
-mod persistence;
-mod post;
-mod runtime;
-mod session_manager;
-mod subscriptions;
-mod swarm;
-mod web;
 
+/// The reasons for which the node can fail to start.
+#[derive(Debug)]
+enum StartupError {
+	/// The templates of the web interface could not be loaded.
+	Templates( tera::Error ),
+	Daemon( daemon::Error ),
+	/// The HTTP server could not be bound to its address.
+	HttpBind( io::Error ),
+	/// The HTTP server stopped because of an error.
+	Http( io::Error )
+}
 
 
-pub const RETURN_CODE_OK: i32 = 0;
-pub const RETURN_CODE_UNEXPECTED: i32 = 1;
 
+#[actix_web::main]
+async fn main() {
 
+	// Clap reports a request for the help or the version as an error too, but it isn't a failure.
+	let args = match Cli::try_parse() {
+		Ok(a) => a,
+		Err(e) => {
+			let _ = e.print();
+			process::exit( if e.use_stderr() { RETURN_CODE_USAGE } else { RETURN_CODE_OK } )
+		}
+	};
 
-pub struct Globals {
-	gnunet: gnunet::Handle,
-	tera: tera::Tera
-}
+	// The other commands only work on the database, and exit without starting the node.
+	let result = match args.command {
+		None => run( args.data_dir, ServeArgs::default() ).await.map_err(|e| (e.to_string(), e.return_code())),
+		Some(Command::Serve(options)) => run( args.data_dir, options ).await.map_err(|e| (e.to_string(), e.return_code())),
+		Some(Command::Doctor { skip_swarms }) => doctor::run( args.data_dir, skip_swarms ).await.map_err(|e| (e.to_string(), e.return_code())),
+		Some(Command::Admin(command)) => cli::run( command, args.data_dir ).await.map_err(|e| (e.to_string(), e.return_code()))
+	};
 
+	let code = match result {
+		Ok(()) => RETURN_CODE_OK,
+		Err((message, code)) => {
+			eprintln!("{}", message);
+			code
+		}
+	};
+	process::exit( code );
+}
 
+/// Starts the components of the node in order, so that each one only starts after the ones it depends on.
+/// Then runs the HTTP server until it stops.
+async fn run( data_dir: Option<PathBuf>, options: ServeArgs ) -> Result<(), StartupError> {
 
-#[actix_web::main]
-async fn main() {
+	// The database is created by the setup in the web interface.
+	let daemon = Daemon::start( Options { data_dir, create_database: false } ).await.map_err(StartupError::Daemon)?;
 
-	let gnunet = gnunet::Handle::default();
-	let tera = Tera::new(concat!(env!("CARGO_MANIFEST_DIR"), "/templates/**/*")).unwrap();
-	let globals = Arc::new( Globals {
-		gnunet,
-		tera
-	});
+	// The template directory is only known once the daemon has loaded the configuration.
+	let templates = match Templates::load() {
+		Err(e) => {
+			daemon.stop().await;
+			return Err( StartupError::Templates( e ) )
+		},
+		Ok(t) => Arc::new( t )
+	};
+	templates.watch();
+	let globals = Arc::new( Globals::new( &daemon, templates ) );
 
-	let server = match HttpServer::new(move || {
+	// HTTP server
+	// The server stops on SIGTERM and ctrl-c, after which the rest of the node is stopped in order.
+	let cfg = config::get();
+	let bind_address = options.bind.unwrap_or_else(|| cfg.bind_address.clone());
+	let port = options.port.unwrap_or( cfg.port );
+	let server = HttpServer::new(move || {
 
 		App::new()
+			.wrap_fn(auth::guard)
+			.wrap_fn(logging::log_request)
 			.data(globals.clone())
 			.service(web::homepage)
+			.service(web::favicon)
+			.service(web::static_file)
+			.service(web::channel_icon)
+			.service(web::channel_manifest)
+			.service(web::webfinger)
+			.service(web::channel_actor)
+			.service(web::channel_outbox)
+			.service(web::channel_followers)
+			.service(web::channel_inbox)
+			.service(web::channel_archive)
+			.service(web::channel_static_site)
+			.service(web::channel_tag)
+			.service(web::channel_attachment)
+			.service(web::channel_attachment_thumbnail)
+			.service(web::channel_syndication)
 			.service(web::channel_feed)
 			.service(web::channel_feed_first)
-			//.service(web::channel_feed_post)
+			.service(web::channel_feed_batch)
+			.service(web::channel_feed_post)
+			.service(web::channel_draft_publish)
+			.service(web::channel_draft_delete)
+			.service(web::channel_editor)
+			.service(web::channel_post_edit)
+			.service(web::channel_post_pin)
+			.service(web::channel_post_unpin)
+			.service(web::channel_post_revise)
+			.service(web::channel_preview)
+			.service(web::channel_post)
+			.service(web::channel_post_comment)
+			.service(web::channel_post_reply)
+			.service(web::channel_post_react)
+			.service(web::channel_post_annotate)
+			.service(web::channel_post_annotation_delete)
+			.service(web::export_annotations)
+			.service(web::channel_fork)
+			.service(web::channel_invite)
+			.service(web::channel_transfer)
+			.service(web::channel_delete)
+			.service(web::channel_transfer_accept)
+			.service(web::channel_profile)
+			.service(web::channel_publishers)
+			.service(web::channel_publisher_add)
+			.service(web::channel_publisher_revoke)
+			.service(web::channel_directory)
+			.service(web::channel_publisher_block)
+			.service(web::channel_publisher_unblock)
+			.service(web::channel_moderation)
+			.service(web::channel_moderation_approve)
+			.service(web::channel_moderation_approve_author)
+			.service(web::channel_moderation_hide)
+			.service(web::channel_moderation_delete)
+			.service(web::channel_moderation_log)
+			.service(web::channel_profile_post)
+			.service(web::channel_profile_conflict_restore)
+			.service(web::channel_profile_conflict_dismiss)
+			.service(web::channel_defaults_post)
+			.service(web::channel_relays)
+			.service(web::channel_followers_dashboard)
+			.service(web::channel_micropub)
+			.service(web::channel_micropub_token_create)
+			.service(web::channel_micropub_token_revoke)
+			.service(web::channel_import)
+			.service(web::channel_import_feed)
+			.service(web::search)
+			.service(web::calendar)
+			.service(web::calendar_timezone)
+			.service(web::settings)
+			.service(web::settings_swarm)
+			.service(web::settings_language)
 			.service(web::channel_new)
 			.service(web::channel_new_post)
-	}).bind("0.0.0.0:7777") {
-		Err(e) => { eprintln!("Unable to start HTTP server: {}", e); return },
-		Ok(server) => server
+			.service(web::channel_adopt)
+			.service(web::identities)
+			.service(web::identity_new)
+			.service(web::identity_rename)
+			.service(web::identity_delete)
+			.service(web::channel_subscribe)
+			.service(web::channel_subscribe_post)
+			.service(web::share)
+			.service(web::share_post)
+			.service(web::open_uri)
+			.service(web::qr_code)
+			.service(web::channel_unsubscribe)
+			.service(web::setup)
+			.service(web::setup_post)
+			.service(web::login)
+			.service(web::login_post)
+			.service(web::logout)
+			.service(web::admin_peers)
+			.service(web::admin_peer)
+			.service(web::admin_channels)
+			.service(web::admin_channel_events)
+			.service(web::admin_channel_event_reapply)
+			.service(web::admin_channel_retention)
+			.service(web::admin_audit_export)
+			.service(web::admin_audit)
+			.service(web::admin_config)
+			.service(web::admin_config_reload)
+			.service(web::prometheus_metrics)
+			.service(web::status)
+			.service(web::healthz)
+			.service(web::readyz)
+			.service(api::channels)
+			.service(api::timeline_posts)
+			.service(api::timeline_post)
+			.service(api::timeline_tags)
+			.service(api::timeline_tag_posts)
+			.service(api::batch)
+			.service(api::create_post)
+			.service(api::search)
+			.service(api::subscriptions)
+			.service(api::subscribe)
+			.service(api::unsubscribe)
+			.service(api::subscription_sync)
+			.service(api::subscription_sync_status)
+			.service(api::subscription_queues)
+			.service(micropub::query)
+			.service(micropub::create)
+			.service(web::discover)
+			.service(web::notifications_read)
+			.service(web::notifications)
+			.service(live::channel_socket)
+			.service(live::notification_socket)
+	}).disable_signals().bind(( bind_address.as_str(), port ));
+	let server = match server {
+		Err(e) => {
+			daemon.stop().await;
+			return Err( StartupError::HttpBind( e ) )
+		},
+		Ok(s) => s
 	};
-	eprintln!("HTTP server starting...");
+	info!(address = %bind_address, port, "HTTP server starting...");
+
+	let server = server.run();
+	actix_web::rt::spawn( shutdown::stop_on_signal( server.clone() ) );
+	actix_web::rt::spawn( reload::reload_on_hangup() );
+	let result = server.await;
+	info!("HTTP server stopped.");
+
+	// Whatever stopped the server, the rest of the node stops as well.
+	daemon.stop().await;
+	result.map_err(StartupError::Http)
+}
+
 
-	match server.run().await {
-		Err(e) => eprintln!("HTTP server error: {}", e),
-		Ok(()) => {}
+
+impl StartupError {
+
+	fn return_code( &self ) -> i32 {
+		match self {
+			Self::Templates(_) => RETURN_CODE_CONFIG,
+			Self::Daemon(e) => e.return_code(),
+			Self::HttpBind(_) => RETURN_CODE_HTTP,
+			Self::Http(_) => RETURN_CODE_UNEXPECTED
+		}
 	}
+}
 
-	eprintln!("HTTP server stopped.")
-}
\ No newline at end of file
+impl fmt::Display for StartupError {
+	fn fmt( &self, f: &mut fmt::Formatter<'_> ) -> fmt::Result {
+		match self {
+			Self::Templates(e) => write!(f, "Unable to load the templates: {}", e),
+			Self::Daemon(e) => write!(f, "{}", e),
+			Self::HttpBind(e) => write!(f, "Unable to start HTTP server: {}", e),
+			Self::Http(e) => write!(f, "HTTP server error: {}", e)
+		}
+	}
+}
@@ -0,0 +1,98 @@
+//! Keeps the database healthy: checks its integrity, updates the statistics of the query planner and gives free pages back to the file system, see `persistence::maintenance`.
+//!
+//! This is done every `maintenance_interval` seconds in the background, not right after the node starts, as the integrity check reads the whole database.
+//! The outcome of the last round is kept for the status page, so that a corrupt database doesn't go unnoticed.
+//! It can also be done by hand with `quartznet maintenance`, while the node is stopped.
+
+use std::{
+	sync::{Arc, RwLock},
+	time::Duration
+};
+
+use lazy_static::lazy_static;
+use serde::Serialize;
+use tracing::{error, info};
+
+use crate::{
+	config,
+	persistence::{
+		self,
+		maintenance::MaintenanceReport,
+		peer::now,
+		Result
+	},
+	services::GnunetServices,
+	shutdown
+};
+
+
+
+/// How the last round of maintenance went, as it is shown on the status page.
+#[derive(Clone, Serialize)]
+pub struct MaintenanceStatus {
+	/// In seconds since the UNIX epoch.
+	pub time: u64,
+	/// Why the maintenance couldn't be done, if it couldn't.
+	pub error: Option<String>,
+	pub integrity_errors: Vec<String>,
+	pub freed_bytes: u64
+}
+
+
+
+lazy_static! {
+	/// The outcome of the last round of maintenance since the node started, if there has been one.
+	static ref LAST_STATUS: RwLock<Option<MaintenanceStatus>> = RwLock::new( None );
+}
+
+/// Returns how the last round of maintenance went.
+pub fn last_status() -> Option<MaintenanceStatus> {
+	LAST_STATUS.read().unwrap().clone()
+}
+
+/// Does the maintenance every so often, until the node shuts down.
+/// The interval is read again after every round, so that a change of the configuration is picked up without a restart.
+pub async fn run_periodically( services: Arc<GnunetServices> ) {
+	loop {
+		let interval = config::get().maintenance_interval;
+		let wait = if interval == 0 { config::MAINTENANCE_INTERVAL } else { interval };
+		if !shutdown::sleep( Duration::from_secs( wait ) ).await { break }
+
+		// The maintenance may have been turned off in the meantime, and before the setup has been done, there is no database yet.
+		if config::get().maintenance_interval == 0 || !persistence::database_exists() { continue }
+
+		let time = now() as u64 / 1000;
+		let status = match run( &services ).await {
+			Err(e) => {
+				error!("Unable to maintain the database: {}", e);
+				MaintenanceStatus { time, error: Some( e.to_string() ), integrity_errors: Vec::new(), freed_bytes: 0 }
+			},
+			Ok(r) => {
+				report( &r );
+				MaintenanceStatus { time, error: None, integrity_errors: r.integrity_errors, freed_bytes: r.freed_bytes }
+			}
+		};
+		*LAST_STATUS.write().unwrap() = Some( status );
+	}
+}
+
+/// Does all maintenance once, on a connection of its own, as none of it may run within a transaction.
+pub async fn run( services: &Arc<GnunetServices> ) -> Result<MaintenanceReport> {
+	let db = persistence::Handle::connect( services.clone() ).await.map_err( persistence::Error::Database )?;
+	db.maintain().await
+}
+
+fn report( report: &MaintenanceReport ) {
+	if !report.integrity_errors.is_empty() {
+		error!(
+			problems = report.integrity_errors.len(),
+			"The database is corrupt: {}", report.integrity_errors.join("; ")
+		);
+		return
+	}
+
+	if report.converted {
+		info!("Converted the database to incremental vacuuming.");
+	}
+	info!(bytes = report.freed_bytes, "Maintained the database, {} KiB freed.", report.freed_bytes / 1024);
+}
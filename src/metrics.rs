@@ -0,0 +1,229 @@
+//! The metrics of the node, for node operators to keep an eye on it.
+//!
+//! The metrics are exposed at `/metrics` in the Prometheus text format, and summarized on the `/status` page.
+//! Counters and timings are kept as things happen, while the state of the swarms is gathered whenever the metrics are asked for.
+
+use lazy_static::lazy_static;
+use prometheus::{
+	core::Collector,
+	register_histogram_vec,
+	register_int_counter,
+	register_int_gauge,
+	Encoder,
+	HistogramVec,
+	IntCounter,
+	IntGauge,
+	TextEncoder
+};
+use serde::Serialize;
+
+use crate::subscriptions::SharedSubscriptions;
+
+
+
+lazy_static! {
+	pub static ref SWARMS: IntGauge = register_int_gauge!(
+		"quartznet_swarms", "The number of channels which swarms we try to be connected to."
+	).unwrap();
+	pub static ref SWARMS_CONNECTED: IntGauge = register_int_gauge!(
+		"quartznet_swarms_connected", "The number of swarms in which we are connected to a parent."
+	).unwrap();
+	pub static ref CHILDREN: IntGauge = register_int_gauge!(
+		"quartznet_children", "The number of peers that are connected to the swarms through us."
+	).unwrap();
+	pub static ref EVENTS_PROCESSED: IntCounter = register_int_counter!(
+		"quartznet_events_processed_total", "The number of events that have been received from peers and processed."
+	).unwrap();
+	pub static ref EVENTS_REBROADCAST: IntCounter = register_int_counter!(
+		"quartznet_events_rebroadcast_total", "The number of times that an event has been passed on to a peer."
+	).unwrap();
+	pub static ref BYTES_SENT: IntCounter = register_int_counter!(
+		"quartznet_swarm_sent_bytes_total", "The number of bytes that have been sent to the peers of all swarms."
+	).unwrap();
+	pub static ref BYTES_RECEIVED: IntCounter = register_int_counter!(
+		"quartznet_swarm_received_bytes_total", "The number of bytes that have been received from the peers of all swarms."
+	).unwrap();
+	pub static ref THROTTLED_REBROADCASTS: IntCounter = register_int_counter!(
+		"quartznet_throttled_rebroadcasts_total", "The number of rebroadcasts that have been held back because their channel went over its upload limit."
+	).unwrap();
+	pub static ref MALFORMED_MESSAGES: IntCounter = register_int_counter!(
+		"quartznet_malformed_messages_total", "The number of malformed messages and events that peers have sent."
+	).unwrap();
+	pub static ref REPLAYED_EVENTS: IntCounter = register_int_counter!(
+		"quartznet_replayed_events_total", "The number of events that have been dropped because a peer sent them again, or sent them long after they were applied."
+	).unwrap();
+	pub static ref RATE_LIMITED_MESSAGES: IntCounter = register_int_counter!(
+		"quartznet_rate_limited_messages_total", "The number of events and requests that have been dropped because a peer sent them faster than allowed."
+	).unwrap();
+	pub static ref DEAD_CONNECTIONS: IntCounter = register_int_counter!(
+		"quartznet_dead_connections_total", "The number of channels with peers that have been closed because the peer stopped answering pings."
+	).unwrap();
+	pub static ref POSTS_EXPIRED: IntCounter = register_int_counter!(
+		"quartznet_posts_expired_total", "The number of posts that have been removed because their channel asked to keep them for a limited time."
+	).unwrap();
+	pub static ref RECLAIMED_BYTES: IntCounter = register_int_counter!(
+		"quartznet_reclaimed_bytes_total", "The number of bytes in the database that the garbage collections have freed."
+	).unwrap();
+	pub static ref SWARM_REQUESTS: HistogramVec = register_histogram_vec!(
+		"quartznet_swarm_request_duration_seconds", "The time it took peers to respond to our requests, per kind of request.", &["kind"]
+	).unwrap();
+	pub static ref HTTP_REQUESTS: HistogramVec = register_histogram_vec!(
+		"quartznet_http_request_duration_seconds", "The time it took to handle the requests to the web interface, per status code.", &["status"]
+	).unwrap();
+	pub static ref DATABASE: HistogramVec = register_histogram_vec!(
+		"quartznet_database_duration_seconds", "The time it took to run statements on the database, including the wait for the connection.", &["operation"],
+		vec![0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0]
+	).unwrap();
+}
+
+/// The metrics in short, as they are shown on the status page.
+#[derive(Serialize)]
+pub struct Summary {
+	pub swarms: i64,
+	pub swarms_connected: i64,
+	pub children: i64,
+	pub events_processed: u64,
+	pub events_rebroadcast: u64,
+	pub bytes_sent: u64,
+	pub bytes_received: u64,
+	pub throttled_rebroadcasts: u64,
+	pub malformed_messages: u64,
+	pub swarm_requests: Vec<Timing>,
+	pub http_requests: Vec<Timing>,
+	pub database: Vec<Timing>
+}
+
+/// What the swarm of a channel has sent and received since we connected to it, as it is shown on the status page.
+#[derive(Serialize)]
+pub struct ChannelTraffic {
+	pub channel: String,
+	pub sent: u64,
+	pub received: u64,
+	pub throttled: u64,
+	pub peers: Vec<PeerTraffic>
+}
+
+/// What has been sent to and received from a peer, during its current session.
+#[derive(Serialize)]
+pub struct PeerTraffic {
+	pub peer: String,
+	pub sent: u64,
+	pub received: u64
+}
+
+/// How many times something was timed, and how long it took on average.
+#[derive(Serialize)]
+pub struct Timing {
+	pub label: String,
+	pub count: u64,
+	pub average_ms: f64
+}
+
+
+
+/// Gathers the state of the swarms that we are connected to.
+pub async fn update( subscriptions: &SharedSubscriptions ) {
+	let nodes = match &*subscriptions.read().await {
+		None => {
+			SWARMS.set( 0 );
+			Vec::new()
+		},
+		Some(subs) => {
+			SWARMS.set( subs.len() as _ );
+			subs.nodes()
+		}
+	};
+
+	let mut children = 0;
+	for node in &nodes {
+		children += node.child_count().await;
+	}
+	SWARMS_CONNECTED.set( nodes.iter().filter(|n| n.is_connected()).count() as _ );
+	CHILDREN.set( children as _ );
+}
+
+/// Gathers the traffic of the swarms that we are connected to, the channel that has sent the most first.
+/// Unlike the counters, this is forgotten when a swarm is left.
+pub async fn traffic( subscriptions: &SharedSubscriptions ) -> Vec<ChannelTraffic> {
+	let nodes = match &*subscriptions.read().await {
+		None => return Vec::new(),
+		Some(subs) => subs.nodes()
+	};
+
+	let mut traffic = Vec::with_capacity( nodes.len() );
+	for node in &nodes {
+		let status = node.traffic().await;
+		traffic.push( ChannelTraffic {
+			channel: status.channel.to_string(),
+			sent: status.sent,
+			received: status.received,
+			throttled: status.throttled,
+			peers: status.peers.into_iter().map(|(peer, sent, received)| PeerTraffic {
+				peer: peer.to_string(),
+				sent,
+				received
+			}).collect()
+		});
+	}
+	traffic.sort_by(|a, b| b.sent.cmp( &a.sent ));
+	traffic
+}
+
+/// Encodes all metrics in the Prometheus text format.
+pub fn encode() -> Vec<u8> {
+	// The metrics are only registered once they are used, but the ones that haven't been used yet should be there as well.
+	lazy_static::initialize( &EVENTS_PROCESSED );
+	lazy_static::initialize( &EVENTS_REBROADCAST );
+	lazy_static::initialize( &BYTES_SENT );
+	lazy_static::initialize( &BYTES_RECEIVED );
+	lazy_static::initialize( &THROTTLED_REBROADCASTS );
+	lazy_static::initialize( &MALFORMED_MESSAGES );
+	lazy_static::initialize( &REPLAYED_EVENTS );
+	lazy_static::initialize( &RATE_LIMITED_MESSAGES );
+	lazy_static::initialize( &DEAD_CONNECTIONS );
+	lazy_static::initialize( &POSTS_EXPIRED );
+	lazy_static::initialize( &RECLAIMED_BYTES );
+	lazy_static::initialize( &SWARM_REQUESTS );
+	lazy_static::initialize( &HTTP_REQUESTS );
+	lazy_static::initialize( &DATABASE );
+
+	let mut buffer = Vec::new();
+	// Encoding into a vector can't fail.
+	TextEncoder::new().encode( &prometheus::gather(), &mut buffer ).unwrap();
+	buffer
+}
+
+pub fn summarize() -> Summary {
+	Summary {
+		swarms: SWARMS.get(),
+		swarms_connected: SWARMS_CONNECTED.get(),
+		children: CHILDREN.get(),
+		events_processed: EVENTS_PROCESSED.get(),
+		events_rebroadcast: EVENTS_REBROADCAST.get(),
+		bytes_sent: BYTES_SENT.get(),
+		bytes_received: BYTES_RECEIVED.get(),
+		throttled_rebroadcasts: THROTTLED_REBROADCASTS.get(),
+		malformed_messages: MALFORMED_MESSAGES.get(),
+		swarm_requests: timings( &SWARM_REQUESTS ),
+		http_requests: timings( &HTTP_REQUESTS ),
+		database: timings( &DATABASE )
+	}
+}
+
+/// Sums up the observations of a histogram, per label.
+fn timings( histogram: &HistogramVec ) -> Vec<Timing> {
+	let mut timings = Vec::new();
+	for family in histogram.collect() {
+		for metric in family.get_metric() {
+			let observations = metric.get_histogram();
+			let count = observations.get_sample_count();
+			timings.push( Timing {
+				label: metric.get_label().first().map(|l| l.get_value().to_owned()).unwrap_or_default(),
+				count,
+				average_ms: if count == 0 { 0.0 } else { observations.get_sample_sum() * 1000.0 / count as f64 }
+			});
+		}
+	}
+	timings.sort_by(|a, b| a.label.cmp( &b.label ));
+	timings
+}
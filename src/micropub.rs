@@ -0,0 +1,484 @@
+//! The Micropub endpoint, so that existing publishing clients can post in our own channels.
+//!
+//! Only creating posts is supported, which is done by posting an `h-entry` to `/api/micropub`, form-encoded, as multipart or as JSON.
+//! Its `content`, `name`, `category` and `photo` properties are used, the others are ignored.
+//! Photos can be uploaded along with the entry, or be given by their URL, in which case they are downloaded and attached.
+//! Only PNG, JPEG, GIF and WebP images are accepted, which is told by their content rather than by the type that they come with.
+//! Photos are only downloaded from public addresses, so that clients can't make the node fetch anything from its own network.
+//!
+//! Clients don't log in with the admin password, but with a token that has been created for one of our own channels on its Micropub page.
+//! The token is given as a bearer token, or as the `access_token` parameter.
+//! Uploads need the bearer token, so that the body isn't read before the client is known.
+//! Errors are answered with the JSON objects that the Micropub specification describes.
+
+use std::{
+	fmt,
+	net::{IpAddr, SocketAddr},
+	sync::Arc,
+	time::{Duration, SystemTime, UNIX_EPOCH}
+};
+
+use actix_multipart::Multipart;
+use actix_web::{get, http::{header, StatusCode, Uri}, post, HttpRequest, HttpResponse, ResponseError, web};
+use async_std::net::ToSocketAddrs;
+use futures::StreamExt;
+use gnunet::identity::PrivateKey;
+use serde::*;
+use serde_json::{json, Value};
+use tracing::error;
+
+use crate::{
+	config,
+	feed_import,
+	identicon,
+	persistence::{self, audit::AuditAction},
+	post::*,
+	services,
+	web as html,
+	Globals
+};
+
+
+
+/// The maximum size of an entry, apart from the photos that are uploaded with it.
+const ENTRY_MAX_SIZE: usize = 1024 * 1024;
+/// The maximum size of a multipart entry, with all of its photos together.
+const UPLOAD_MAX_SIZE: usize = html::MAX_ATTACHMENT_SIZE;
+/// The maximum number of fields of a multipart entry.
+const UPLOAD_MAX_FIELDS: usize = 100;
+/// The maximum number of photos of an entry, both uploaded and given by their URL.
+const PHOTOS_MAX: usize = 10;
+
+/// What a client has posted, in whichever way it was encoded.
+#[derive(Default)]
+struct Entry {
+	/// The `h` parameter, which is `entry` for posts.
+	kind: Option<String>,
+	/// Set for updates and deletions, which aren't supported.
+	action: Option<String>,
+	access_token: Option<String>,
+	/// The title.
+	name: Option<String>,
+	content: Option<String>,
+	/// Whether the content is HTML, rather than plain text.
+	html: bool,
+	categories: Vec<String>,
+	photo_urls: Vec<String>,
+	/// The photos that have been uploaded, with their MIME type.
+	photos: Vec<(Vec<u8>, String)>
+}
+
+#[derive(Deserialize)]
+pub struct MicropubQuery {
+	q: Option<String>,
+	access_token: Option<String>
+}
+
+#[derive(Debug)]
+pub struct MicropubError {
+	status: StatusCode,
+	/// One of the error codes of the specification, like `invalid_request`.
+	error: &'static str,
+	description: String
+}
+
+type Result<T> = std::result::Result<T, MicropubError>;
+
+
+
+impl Entry {
+
+	/// Takes a property of a form-encoded or multipart entry.
+	/// Properties that can have more than one value are named with `[]` after them.
+	fn add( &mut self, name: &str, value: String ) {
+		match name.trim_end_matches("[]") {
+			"h" => self.kind = Some( value ),
+			"action" => self.action = Some( value ),
+			"access_token" => self.access_token = Some( value ),
+			"name" => self.name = Some( value ),
+			"content" => self.content = Some( value ),
+			"category" => self.categories.push( value ),
+			"photo" => self.photo_urls.push( value ),
+			_ => {}
+		}
+	}
+
+	/// Reads an entry in the JSON syntax, which has the microformats2 structure.
+	fn from_json( value: &Value ) -> Self {
+		let properties = &value["properties"];
+		let mut entry = Self {
+			action: value["action"].as_str().map(|a| a.to_owned()),
+			kind: value["type"].get(0).and_then(|t| t.as_str()).map(|t| t.trim_start_matches("h-").to_owned()),
+			name: properties["name"].get(0).and_then(|n| n.as_str()).map(|n| n.to_owned()),
+			..Self::default()
+		};
+		match properties["content"].get(0) {
+			Some(Value::String(text)) => entry.content = Some( text.clone() ),
+			Some(Value::Object(content)) => if let Some(html) = content.get("html").and_then(|h| h.as_str()) {
+				entry.content = Some( html.to_owned() );
+				entry.html = true;
+			},
+			_ => {}
+		}
+		for category in properties["category"].as_array().into_iter().flatten() {
+			if let Some(category) = category.as_str() {
+				entry.categories.push( category.to_owned() );
+			}
+		}
+		// Photos can have alternative text, in which case they are objects with the URL as their value.
+		for photo in properties["photo"].as_array().into_iter().flatten() {
+			if let Some(url) = photo.as_str().or_else(|| photo["value"].as_str()) {
+				entry.photo_urls.push( url.to_owned() );
+			}
+		}
+
+		entry
+	}
+
+	/// Turns the entry into the content of a post, and its format.
+	/// Plain text stays plain text, but a title or HTML makes it Markdown, which HTML is valid in.
+	fn to_message( &self ) -> (String, ContentFormat) {
+		let content = self.content.as_deref().unwrap_or("").trim();
+		let name = self.name.as_deref().map(|n| n.trim()).filter(|n| !n.is_empty());
+		if name.is_none() && !self.html {
+			return (content.to_owned(), ContentFormat::Plain)
+		}
+
+		let mut message = String::new();
+		if let Some(name) = name {
+			message.push_str( &format!("# {}\n\n", name.replace('\n', " ")) );
+		}
+		message.push_str( content );
+		(message, ContentFormat::Markdown)
+	}
+}
+
+impl MicropubError {
+
+	fn new( status: StatusCode, error: &'static str, description: impl Into<String> ) -> Self {
+		Self { status, error, description: description.into() }
+	}
+
+	fn invalid_request( description: impl Into<String> ) -> Self {
+		Self::new( StatusCode::BAD_REQUEST, "invalid_request", description )
+	}
+
+	fn internal( description: impl Into<String> ) -> Self {
+		Self::new( StatusCode::INTERNAL_SERVER_ERROR, "server_error", description )
+	}
+}
+
+impl fmt::Display for MicropubError {
+	fn fmt( &self, f: &mut fmt::Formatter<'_> ) -> fmt::Result {
+		write!(f, "{}: {}", self.error, self.description)
+	}
+}
+
+impl ResponseError for MicropubError {
+
+	fn status_code( &self ) -> StatusCode {
+		self.status
+	}
+
+	fn error_response( &self ) -> HttpResponse {
+		HttpResponse::build( self.status ).json( json!({
+			"error": self.error,
+			"error_description": self.description
+		}))
+	}
+}
+
+impl From<persistence::Error> for MicropubError {
+	fn from( other: persistence::Error ) -> Self {
+		error!("Database error: {}", other);
+		Self::internal("Database error")
+	}
+}
+
+impl From<services::Error> for MicropubError {
+	fn from( other: services::Error ) -> Self {
+		error!("Service error: {}", other);
+		Self::new( StatusCode::SERVICE_UNAVAILABLE, "server_error", "The identity service can't be reached." )
+	}
+}
+
+/// Tells clients what the endpoint supports.
+/// Posting to other sites isn't supported, and neither is reading back the source of a post.
+#[get("/api/micropub")]
+pub async fn query( g: web::Data<Arc<Globals>>, req: HttpRequest, q: web::Query<MicropubQuery> ) -> Result<HttpResponse> {
+
+	authenticate( &g, &req, q.access_token.as_deref() ).await?;
+
+	match q.q.as_deref() {
+		Some("config") => Ok( HttpResponse::Ok().json( json!({ "syndicate-to": [] }) ) ),
+		Some("syndicate-to") => Ok( HttpResponse::Ok().json( json!({ "syndicate-to": [] }) ) ),
+		_ => Err( MicropubError::invalid_request("Only the config and syndicate-to queries are supported.") )
+	}
+}
+
+/// Publishes an entry as a post in the channel that the token belongs to.
+/// Responds with 201 Created, and the permalink of the new post.
+#[post("/api/micropub")]
+pub async fn create( g: web::Data<Arc<Globals>>, req: HttpRequest, payload: web::Payload ) -> Result<HttpResponse> {
+
+	// A client that gives its token in the header is known before anything of the body is read.
+	let known = match bearer_token( &req ) {
+		None => None,
+		Some(token) => Some( authenticate_token( &g, &token ).await? )
+	};
+	let mut entry = read_entry( &req, payload, known.is_some() ).await?;
+	let (ego, private_key) = match known {
+		Some(k) => k,
+		None => authenticate( &g, &req, entry.access_token.as_deref() ).await?
+	};
+
+	if entry.action.is_some() {
+		return Err( MicropubError::invalid_request("Only creating posts is supported.") )
+	}
+	if entry.kind.as_deref().unwrap_or("entry") != "entry" {
+		return Err( MicropubError::invalid_request("Only entries can be posted.") )
+	}
+	if entry.content.as_deref().unwrap_or("").trim().is_empty() && entry.photos.is_empty() && entry.photo_urls.is_empty() {
+		return Err( MicropubError::invalid_request("The entry has no content.") )
+	}
+	if entry.photos.len() + entry.photo_urls.len() > PHOTOS_MAX {
+		return Err( MicropubError::invalid_request( format!("An entry can have at most {} photos.", PHOTOS_MAX) ) )
+	}
+
+	let mut photos = std::mem::take( &mut entry.photos );
+	for url in &entry.photo_urls {
+		photos.push( download_photo( url ).await? );
+	}
+
+	let address = private_key.extract_public().unwrap();
+	let db = g.connect_database().await?;
+	let timeline = db.get_timeline( &address ).await?
+		.ok_or_else(|| MicropubError::internal("The channel of the token has no timeline."))?;
+
+	// The defaults of the ego are added, like they are to the posts that are written in the web interface.
+	let defaults = timeline.load_post_defaults().await?;
+	let tags = defaults.apply_tags( feed_import::convert_tags( entry.categories.iter().map(|c| c.as_str()) ) );
+	check_tags( &tags ).map_err( MicropubError::invalid_request )?;
+	let (message, format) = entry.to_message();
+	let message = defaults.apply_footer( &message );
+
+	let mut attachment_ids = Vec::with_capacity( photos.len() );
+	for (data, mime_type) in &photos {
+		attachment_ids.push( db.store_attachment( data, mime_type ).await? );
+	}
+
+	let info = PostInfo {
+		tags,
+		publish_timestamp: SystemTime::now().duration_since( UNIX_EPOCH ).unwrap().as_millis() as _,
+		visible_from: None,
+		format,
+		series: None,
+		content_warning: None
+	};
+	let (_, post) = timeline.create_post( &private_key, &message, info, attachment_ids, None ).await?;
+	db.clone().get_channel( &address ).await?
+		.ok_or_else(|| MicropubError::internal("The channel of the token is gone."))?
+		.log_new_post( &timeline, &post ).await?;
+	db.record_action( Some( &ego ), AuditAction::PostCreated, &html::post_subject( &address, post.id ) ).await?;
+
+	let connection = req.connection_info();
+	let location = format!("{}://{}/channel/address/{}/post/{}", connection.scheme(), connection.host(), address, post.id);
+	Ok( HttpResponse::Created().append_header((header::LOCATION, location)).finish() )
+}
+
+/// Finds the ego that the token of the request belongs to, along with its private key.
+async fn authenticate( g: &Globals, req: &HttpRequest, access_token: Option<&str> ) -> Result<(String, PrivateKey)> {
+
+	match bearer_token( req ).or_else(|| access_token.map(|t| t.to_owned())) {
+		None => Err( MicropubError::new( StatusCode::UNAUTHORIZED, "unauthorized", "No access token has been given." ) ),
+		Some(token) => authenticate_token( g, &token ).await
+	}
+}
+
+/// The token in the `Authorization` header of the request, if any.
+fn bearer_token( req: &HttpRequest ) -> Option<String> {
+	req.headers().get( header::AUTHORIZATION )
+		.and_then(|h| h.to_str().ok())
+		.and_then(|h| h.strip_prefix("Bearer "))
+		.map(|t| t.trim().to_owned())
+}
+
+/// Finds the ego that the token belongs to, along with its private key.
+async fn authenticate_token( g: &Globals, token: &str ) -> Result<(String, PrivateKey)> {
+
+	let db = g.connect_database().await?;
+	let address = db.find_micropub_token( token ).await?
+		.ok_or_else(|| MicropubError::new( StatusCode::FORBIDDEN, "forbidden", "The access token is invalid, or has been revoked." ))?;
+
+	// The token belongs to the channel, so the ego is looked up by its key, in case it has been renamed.
+	g.services.list_egos().await?.into_iter()
+		.find(|(_, key)| key.extract_public().as_ref() == Some( &address ))
+		.ok_or_else(|| MicropubError::new( StatusCode::FORBIDDEN, "forbidden", "The ego of the channel doesn't exist anymore." ))
+}
+
+/// Reads the entry from the body of the request, in the encoding that its content type tells.
+/// Multipart entries, which can be large, are only read from clients that are `known` already.
+async fn read_entry( req: &HttpRequest, mut payload: web::Payload, known: bool ) -> Result<Entry> {
+
+	let content_type = req.headers().get( header::CONTENT_TYPE )
+		.and_then(|h| h.to_str().ok())
+		.unwrap_or("")
+		.to_ascii_lowercase();
+
+	if content_type.starts_with("multipart/form-data") {
+		if !known {
+			return Err( MicropubError::new( StatusCode::UNAUTHORIZED, "unauthorized", "Uploads need the access token in the Authorization header." ) )
+		}
+		return read_multipart( Multipart::new( req.headers(), payload ) ).await
+	}
+
+	let mut body = Vec::new();
+	while let Some(chunk) = payload.next().await {
+		let chunk = chunk.map_err(|e| MicropubError::invalid_request( e.to_string() ))?;
+		if body.len() + chunk.len() > ENTRY_MAX_SIZE {
+			return Err( MicropubError::new( StatusCode::PAYLOAD_TOO_LARGE, "invalid_request", "The entry is too large." ) )
+		}
+		body.extend_from_slice( &chunk );
+	}
+
+	if content_type.starts_with("application/json") {
+		let value: Value = serde_json::from_slice( &body )
+			.map_err(|e| MicropubError::invalid_request( format!("Invalid JSON: {}", e) ))?;
+		return Ok( Entry::from_json( &value ) )
+	}
+
+	let body = std::str::from_utf8( &body ).map_err(|_| MicropubError::invalid_request("The entry is not valid UTF-8."))?;
+	let pairs = web::Query::<Vec<(String, String)>>::from_query( body )
+		.map_err(|e| MicropubError::invalid_request( e.to_string() ))?;
+	let mut entry = Entry::default();
+	for (name, value) in pairs.into_inner() {
+		entry.add( &name, value );
+	}
+	Ok( entry )
+}
+
+/// Reads a multipart entry, in which photos can be uploaded as files.
+async fn read_multipart( mut payload: Multipart ) -> Result<Entry> {
+
+	let mut entry = Entry::default();
+	let mut fields = 0;
+	let mut size = 0;
+	while let Some(field) = payload.next().await {
+		let mut field = field.map_err(|e| MicropubError::invalid_request( e.to_string() ))?;
+		fields += 1;
+		if fields > UPLOAD_MAX_FIELDS {
+			return Err( MicropubError::invalid_request("The entry has too many fields.") )
+		}
+		let name = field.content_disposition()
+			.and_then(|cd| cd.get_name().map(|n| n.to_owned()))
+			.unwrap_or_default();
+		let is_file = field.content_disposition().map(|cd| cd.get_filename().is_some()).unwrap_or(false);
+
+		let mut data = Vec::new();
+		while let Some(chunk) = field.next().await {
+			let chunk = chunk.map_err(|e| MicropubError::invalid_request( e.to_string() ))?;
+			size += chunk.len();
+			if size > UPLOAD_MAX_SIZE {
+				return Err( MicropubError::new( StatusCode::PAYLOAD_TOO_LARGE, "invalid_request", "The entry is too large." ) )
+			}
+			data.extend_from_slice( &chunk );
+		}
+
+		if is_file {
+			if name.trim_end_matches("[]") == "photo" && !data.is_empty() {
+				let mime_type = photo_type( &data )
+					.ok_or_else(|| MicropubError::invalid_request("Only PNG, JPEG, GIF and WebP photos can be uploaded."))?;
+				entry.photos.push(( data, mime_type ));
+			}
+		}
+		else {
+			let value = String::from_utf8( data ).map_err(|_| MicropubError::invalid_request("The entry is not valid UTF-8."))?;
+			entry.add( &name, value );
+		}
+	}
+	Ok( entry )
+}
+
+/// Downloads a photo that the entry refers to, so that it can be attached to the post.
+/// The connection goes to the address that has been checked, rather than to whatever the host resolves to by then, so that the host can't swap in another address in between.
+/// Redirects aren't followed, as they could lead to an address that hasn't been checked.
+async fn download_photo( url: &str ) -> Result<(Vec<u8>, String)> {
+
+	let failed = |e: String| MicropubError::invalid_request( format!("Unable to download photo {}: {}", url, e) );
+
+	let address = check_public_url( url ).await.map_err( failed )?;
+	let mut response = awc::Client::builder()
+		.timeout( Duration::from_millis( config::FEED_IMPORT_TIMEOUT ) )
+		.disable_redirects()
+		.finish()
+		.get( url )
+		.address( address )
+		.send().await
+		.map_err(|e| failed( e.to_string() ))?;
+	if !response.status().is_success() {
+		return Err( failed( format!("status {}", response.status()) ) )
+	}
+	let body = response.body().limit( html::MAX_ATTACHMENT_SIZE ).await
+		.map_err(|e| failed( e.to_string() ))?;
+	let mime_type = photo_type( &body )
+		.ok_or_else(|| failed( "it isn't a PNG, JPEG, GIF or WebP image".to_owned() ))?;
+
+	Ok(( body.to_vec(), mime_type ))
+}
+
+/// Tells the type of a photo from its content, if it is one of the types of images that are accepted.
+fn photo_type( data: &[u8] ) -> Option<String> {
+	identicon::sniff_image_type( data )
+		.filter(|t| html::INLINE_ATTACHMENT_TYPES.contains( t ))
+		.map(|t| t.to_owned())
+}
+
+/// Makes sure that the URL is an HTTP or HTTPS URL of which every address of the host is public.
+/// Returns the address to connect to, as the host may resolve to another one when it is asked again.
+pub(crate) async fn check_public_url( url: &str ) -> std::result::Result<SocketAddr, String> {
+
+	let uri: Uri = url.parse().map_err(|_| "it isn't a valid URL".to_owned())?;
+	let port = match uri.scheme_str() {
+		Some("http") => uri.port_u16().unwrap_or(80),
+		Some("https") => uri.port_u16().unwrap_or(443),
+		_ => return Err( "only HTTP and HTTPS URLs can be downloaded".to_owned() )
+	};
+	let host = uri.host().ok_or_else(|| "it has no host".to_owned())?
+		.trim_start_matches('[').trim_end_matches(']');
+
+	let addresses: Vec<SocketAddr> = (host, port).to_socket_addrs().await
+		.map_err(|e| e.to_string())?
+		.collect();
+	if addresses.is_empty() || !addresses.iter().all(|a| is_public_address( &a.ip() )) {
+		return Err( "its host isn't a public address".to_owned() )
+	}
+	Ok( addresses[0] )
+}
+
+/// Whether the address can be reached from anywhere, rather than being one of this computer, its network, or reserved.
+fn is_public_address( address: &IpAddr ) -> bool {
+	match address {
+		IpAddr::V4(a) => {
+			let octets = a.octets();
+			!(a.is_loopback() || a.is_private() || a.is_link_local() || a.is_unspecified() || a.is_broadcast()
+				|| a.is_documentation() || a.is_multicast()
+				// Shared address space of carrier-grade NAT, 100.64.0.0/10
+				|| (octets[0] == 100 && (octets[1] & 0xc0) == 64)
+				|| octets[0] == 0)
+		},
+		IpAddr::V6(a) => {
+			let segments = a.segments();
+			// IPv4 addresses mapped into IPv6, ::ffff:0:0/96
+			if segments[..5].iter().all(|s| *s == 0) && segments[5] == 0xffff {
+				let [b0, b1] = segments[6].to_be_bytes();
+				let [b2, b3] = segments[7].to_be_bytes();
+				return is_public_address( &IpAddr::V4( [b0, b1, b2, b3].into() ) )
+			}
+			!(a.is_loopback() || a.is_unspecified() || a.is_multicast()
+				// Unique local addresses, fc00::/7
+				|| (segments[0] & 0xfe00) == 0xfc00
+				// Link-local addresses, fe80::/10
+				|| (segments[0] & 0xffc0) == 0xfe80)
+		}
+	}
+}
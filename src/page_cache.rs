@@ -0,0 +1,114 @@
+//! Keeps the feed pages that subscribers see, so that a page that is requested again isn't loaded and rendered again.
+//!
+//! Pages are kept by the channel and the number of the page.
+//! All pages of a channel are dropped as soon as an event of the channel is applied, whether it has arrived from the swarm or has been made by us.
+//! Some things change without an event, like posts whose embargo ends and the peers in the share link, so pages are only kept for `config::PAGE_CACHE_TIME` seconds.
+//! The feeds of our own channels show drafts and settings as well, and aren't kept at all.
+//!
+//! Every page has an entity tag, so that browsers and gateways can ask whether the page that they have is still the current one.
+
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant}
+};
+
+use gnunet::crypto::HashCode;
+use lazy_static::lazy_static;
+
+use crate::config;
+
+
+
+/// A rendered page.
+pub struct Page {
+	pub html: String,
+	/// The value of the `ETag` header, quotes included.
+	pub etag: String
+}
+
+pub struct PageCache {
+	/// The maximum number of pages that are kept.
+	max_pages: usize,
+	entries: Mutex<HashMap<(i64, u32), Entry>>
+}
+
+struct Entry {
+	page: Arc<Page>,
+	created: Instant
+}
+
+
+
+lazy_static! {
+	/// The feed pages of all channels that have been shown lately.
+	pub static ref PAGES: PageCache = PageCache::new( config::PAGE_CACHE_PAGES );
+}
+
+impl Page {
+
+	pub fn new( html: String ) -> Self {
+		let etag = format!("\"{}\"", HashCode::generate( html.as_bytes() ));
+		Self { html, etag }
+	}
+
+	/// Whether the value of an `If-None-Match` header names this page, so that it doesn't have to be sent again.
+	pub fn matches( &self, if_none_match: &str ) -> bool {
+		if_none_match.split(',').any(|tag| {
+			let tag = tag.trim();
+			// Weak tags are fine too, as we only ever compare whole pages.
+			tag == "*" || tag.trim_start_matches("W/") == self.etag
+		})
+	}
+}
+
+impl PageCache {
+
+	pub fn new( max_pages: usize ) -> Self {
+		Self {
+			max_pages,
+			entries: Mutex::new( HashMap::new() )
+		}
+	}
+
+	/// Returns the page with the given number of the channel with the given id, if it is kept and still fresh.
+	pub fn get( &self, channel_id: i64, page: u32 ) -> Option<Arc<Page>> {
+		let entries = self.entries.lock().unwrap();
+		let entry = entries.get( &(channel_id, page) )?;
+		if entry.created.elapsed() > max_age() { return None }
+		Some( entry.page.clone() )
+	}
+
+	/// Keeps a page, and makes room for it by dropping the pages that are too old, or the oldest one otherwise.
+	pub fn insert( &self, channel_id: i64, page: u32, html: String ) -> Arc<Page> {
+		let cached = Arc::new( Page::new( html ) );
+		let mut entries = self.entries.lock().unwrap();
+
+		if entries.len() >= self.max_pages {
+			entries.retain(|_, e| e.created.elapsed() <= max_age());
+		}
+		if entries.len() >= self.max_pages {
+			let oldest = entries.iter().min_by_key(|(_, e)| e.created).map(|(k, _)| *k);
+			if let Some(key) = oldest {
+				entries.remove( &key );
+			}
+		}
+
+		entries.insert( (channel_id, page), Entry { page: cached.clone(), created: Instant::now() } );
+		cached
+	}
+
+	/// Drops all pages of the channel with the given id, because something has happened in it.
+	pub fn invalidate( &self, channel_id: i64 ) {
+		self.entries.lock().unwrap().retain(|(id, _), _| *id != channel_id);
+	}
+
+	/// Drops all pages, for changes that can affect every channel, like blocking a publisher.
+	pub fn clear( &self ) {
+		self.entries.lock().unwrap().clear();
+	}
+}
+
+fn max_age() -> Duration {
+	Duration::from_secs( config::PAGE_CACHE_TIME )
+}
@@ -3,7 +3,8 @@ use std::{
 	ops::{Deref, DerefMut},
 	panic::{UnwindSafe, AssertUnwindSafe},
 	path::*,
-	sync::{Arc, Mutex}
+	sync::{Arc, Mutex, RwLock},
+	time::Duration
 };
 
 use async_std::{
@@ -11,39 +12,104 @@ use async_std::{
 };
 use fallible_iterator::FallibleIterator;
 use gnunet::{
-	identity::{self, *}
+	crypto::HashCode,
+	identity::*
 };
 use lazy_static::lazy_static;
 use rusqlite::{self, NO_PARAMS, params, types::ToSql};
-use unsafe_send_sync::*;
-
-use crate::runtime;
+use tracing::{error, warn};
+
+use crate::{
+	common,
+	config,
+	encryption::InviteCode,
+	event::{ChannelCreateEventData, ChannelCreateEventMessage, ChannelEventType, GENESIS_EVENT_ID},
+	metrics,
+	post::Attachment,
+	runtime,
+	services::{self, GnunetServices},
+	setup
+};
 
+pub mod annotation;
+pub mod at_rest;
+pub mod audit;
+pub mod backup;
+pub mod batch;
+pub mod blocklist;
+pub mod census;
 pub mod channel;
+pub mod closing;
+pub mod comment;
+pub mod directory;
+pub mod draft;
+pub mod excerpt;
+pub mod federation;
+pub mod import;
+pub mod maintenance;
+pub mod micropub;
+pub mod moderation;
+pub mod notification;
+pub mod outbox;
+pub mod ownership;
+pub mod peer;
+pub mod pinned;
+pub mod pool;
 pub mod post;
+pub mod post_defaults;
+pub mod reaction;
+pub mod retention;
+pub mod schema;
+pub mod subscription;
+pub mod system_post;
+pub mod thumbnail;
 pub mod timeline;
 
 
 
+tokio::task_local! {
+	/// The connections that the current task has a transaction open on with `atomically`, identified by the address of their mutex.
+	static TRANSACTIONS: Vec<usize>;
+}
+
 lazy_static! {
-	pub static ref DATABASE_DIR: PathBuf = PathBuf::from( "/home/bamilab/.quartznet" );
+	/// Until `setup::load_data_dir` has chosen one, this is the data directory of the user.
+	static ref DATABASE_DIR: RwLock<PathBuf> = RwLock::new( setup::default_data_dir() );
 }
 
-pub struct Connection ( rusqlite::Connection );
+pub struct Connection ( pool::PooledConnection );
 
 #[derive(Clone)]
 pub struct Handle {
-	gnunet: gnunet::Handle,
-	db: Arc<Mutex<Connection>>
+	services: Arc<GnunetServices>,
+	/// The data directory that the database was opened in, which stays the same for this handle even if another one is chosen.
+	data_dir: Arc<PathBuf>,
+	/// The key that the content of private channels is encrypted with, see `at_rest`.
+	storage_key: Arc<at_rest::StorageKey>,
+	db: Arc<Mutex<Connection>>,
+	/// Held for as long as a transaction is open on the connection, so that the transactions of different tasks take turns rather than fail.
+	transactions: Arc<async_std::sync::Mutex<()>>
 }
 
+// Handles are shared between the tasks of the node, which may run on any thread.
+const _: fn() = || {
+	fn assert_send_sync<T: Send + Sync>() {}
+	assert_send_sync::<Handle>();
+};
+
 /// The error that may occur during the creation of a particular database model.
 #[derive(Debug)]
 pub enum Error {
 	/// The given information already exists for another entry of the model.
 	AlreadyExists,
+	/// The name of a new channel is already used by an ego.
+	EgoConflict( EgoConflict ),
+	/// The entry that the operation should be applied on is not known.
+	NotFound,
 	/// Gnunet error
 	Gnunet( gnunet::Error ),
+	/// An error from one of the gnunet services
+	Services( services::Error ),
 	/// A SQL error
 	Database( rusqlite::Error ),
 	// Any errors serializing structures into bytes or the other way around.
@@ -52,6 +118,50 @@ pub enum Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Why the name of a new channel can't be used.
+#[derive(Debug)]
+pub enum EgoConflict {
+	/// The ego already belongs to the channel with the given address.
+	InUse( PublicKey ),
+	/// The ego exists, but it doesn't belong to any channel, most likely because creating one has been interrupted.
+	/// Its channel can be created with `adopt_channel`.
+	Unused( PublicKey )
+}
+
+/// One of the egos of gnunet, as it relates to the channels on this node.
+pub struct Ego {
+	pub name: String,
+	pub address: PublicKey,
+	/// Whether the ego is the one that the channel with its address is published with.
+	pub has_channel: bool
+}
+
+
+
+/// Returns the directory in which the database and all other data is stored.
+pub fn data_dir() -> PathBuf {
+	DATABASE_DIR.read().unwrap().clone()
+}
+
+/// Changes the directory in which the database and all other data is stored.
+/// Only handles that connect afterwards will use the new directory.
+pub fn set_data_dir( path: PathBuf ) {
+	*DATABASE_DIR.write().unwrap() = path;
+}
+
+/// Returns whether or not the database has been created yet.
+pub fn database_exists() -> bool {
+	data_dir().join("db.sqlite").exists()
+}
+
+/// Opens the database read-only and looks at its state, without migrating it.
+/// This is what `quartznet doctor` uses, so that troubleshooting never changes anything.
+pub fn inspect_database() -> rusqlite::Result<schema::Inspection> {
+	let connection = rusqlite::Connection::open_with_flags( data_dir().join("db.sqlite"), rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY )?;
+	connection.busy_timeout( Duration::from_secs( config::DATABASE_BUSY_TIMEOUT ) )?;
+	schema::inspect( &connection )
+}
+
 
 
 impl Connection {
@@ -65,32 +175,215 @@ impl Connection {
 
 		on_result(result)
 	}
+
+	/// The key that the content of private channels is encrypted with, for the statements that read it.
+	pub fn storage_key( &self ) -> &at_rest::StorageKey {
+		self.0.storage_key()
+	}
 }
 
 impl Handle {
 
 	/// Creates a new ego and saves it to
-	pub async fn create_channel( &mut self, name: &str ) -> Result<channel::Handle> {
+	/// The messages of a channel that isn't `public` are encrypted, and can only be read by those that have its invite code.
+	pub async fn create_channel( &mut self, name: &str, public: bool ) -> Result<channel::Handle> {
 
-		let mut identity_service = identity::Handle::connect( self.gnunet.clone() ).await?;
+		self.check_ego_name( name ).await?;
 
 		let private_key = PrivateKey::generate( KeyType::Eddsa );
-		let success = identity_service.create( name, private_key.clone() ).await?;
+		let success = self.services.create_ego( name, private_key.clone() ).await?;
 		if !success { return Err( Error::AlreadyExists ) }
 
-		let public_key = Arc::new( private_key.extract_public().unwrap() );
-		let address_str = public_key.to_string();
-		
-		let row_id = self.query("INSERT INTO channel (address) VALUES (?); RETURNING ROWID", params![address_str], |_, mut rows| {
-			rows.next()?.unwrap().get(0)
-		}).await?;
+		self.init_channel( name, &private_key, public ).await
+	}
+
+	/// Creates the channel of an ego that already exists, but that doesn't have a channel yet.
+	/// This recovers from the creation of a channel that has been interrupted after its ego was created.
+	pub async fn adopt_channel( &mut self, name: &str, public: bool ) -> Result<channel::Handle> {
+
+		match self.check_ego_name( name ).await {
+			Err(Error::EgoConflict(EgoConflict::Unused(_))) => {},
+			Err(e) => return Err(e),
+			Ok(()) => return Err( Error::NotFound )
+		}
+
+		// A channel that has been marked with the name before, belongs to an ego that has been replaced since.
+		self.execute("DELETE FROM local_publishers WHERE ego = ?", params![name], |_| Ok(()) ).await?;
+		let private_key = self.services.lookup_ego( name ).await?;
+		self.init_channel( name, &private_key, public ).await
+	}
+
+	/// Lists all egos, and whether they have a channel.
+	pub async fn list_egos( &self ) -> Result<Vec<Ego>> {
+
+		let owned: Vec<(String, String)> = self.query("SELECT l.ego, p.address FROM local_publishers l INNER JOIN publisher p ON p.ROWID = l.publisher_id", NO_PARAMS,
+			|_, rows| rows.map(|row| Ok(( row.get(0)?, row.get(1)? ))).collect()
+		).await?;
+
+		let mut egos: Vec<Ego> = self.services.list_egos().await?.into_iter().map(|(name, private_key)| {
+			let address = private_key.extract_public().unwrap();
+			let has_channel = owned.iter().any(|(ego, a)| *ego == name && *a == address.to_string());
+			Ego { name, address, has_channel }
+		}).collect();
+		egos.sort_by(|a, b| a.name.cmp( &b.name ));
+		Ok( egos )
+	}
+
+	/// Gives an ego another name, and the channel that belongs to it along with it.
+	pub async fn rename_ego( &self, name: &str, new_name: &str ) -> Result<()> {
+
+		self.services.lookup_ego( name ).await?;
+		if !self.services.rename_ego( name, new_name ).await? {
+			return Err( Error::AlreadyExists )
+		}
+		self.execute("UPDATE local_publishers SET ego = ? WHERE ego = ?", params![new_name, name], |_| Ok(()) ).await?;
+		Ok(())
+	}
+
+	/// Deletes an ego that doesn't have a channel.
+	/// The ego of a channel can't be deleted, because nothing could be published in the channel anymore without its private key.
+	pub async fn delete_ego( &self, name: &str ) -> Result<()> {
+
+		match self.check_ego_name( name ).await {
+			Err(Error::EgoConflict(EgoConflict::Unused(_))) => {},
+			Err(e) => return Err(e),
+			Ok(()) => return Err( Error::NotFound )
+		}
+		Ok( self.services.delete_ego( name ).await? )
+	}
+
+	/// Checks whether a new channel can be created with the given ego name.
+	/// Marks of our own channels that refer to an ego that doesn't exist anymore are removed, as the name is free again.
+	async fn check_ego_name( &self, name: &str ) -> Result<()> {
+
+		let owned: Option<String> = self.query_one("SELECT p.address FROM local_publishers l INNER JOIN publisher p ON p.ROWID = l.publisher_id WHERE l.ego = ?",
+			params![name],
+			|_, row| row.get(0)
+		).await?;
+		let owned = owned.and_then(|a| PublicKey::from_string( &a ));
+
+		match self.services.lookup_ego( name ).await {
+			Ok(private_key) => {
+				let address = private_key.extract_public().unwrap();
+				if owned == Some( address.clone() ) {
+					Err( Error::EgoConflict( EgoConflict::InUse( address ) ) )
+				}
+				else {
+					Err( Error::EgoConflict( EgoConflict::Unused( address ) ) )
+				}
+			},
+			Err(services::Error::EgoNotFound(_)) => {
+				if let Some(address) = owned {
+					warn!("The ego {} of channel {} doesn't exist anymore, freeing up its name.", name, address);
+					self.execute("DELETE FROM local_publishers WHERE ego = ?", params![name], |_| Ok(()) ).await?;
+				}
+				Ok(())
+			},
+			Err(e) => Err( e.into() )
+		}
+	}
+
+	/// Stores the channel of the given ego, along with its genesis event.
+	/// Any part of it that has been stored already is kept.
+	/// The ego itself isn't touched, so this can also be used for channels of which the key isn't kept by gnunet.
+	pub async fn init_channel( &self, name: &str, private_key: &PrivateKey, public: bool ) -> Result<channel::Handle> {
+
+		let public_key = private_key.extract_public().unwrap();
 
+		// The channel's own key is also the key of its first publisher.
+		let channel = self.add_channel( &public_key ).await?;
 		self.own_channel( name, &public_key ).await?;
 
-		Ok(channel::Handle {
-			base: self.clone(),
-			id: row_id
-		})
+		if channel.load_parameters().await?.is_some() {
+			return Ok( channel )
+		}
+
+		// Every channel starts with its genesis event
+		Self::store_genesis( &channel, private_key, public ).await?;
+
+		if !public {
+			channel.store_invite_code( &InviteCode::generate() ).await?;
+		}
+
+		Ok( channel )
+	}
+
+	/// Stores a genesis event for the channels of our own egos that were created before channels started with one, as their swarms can't be started or joined without it.
+	/// A channel with an invite code is private, the others are public.
+	/// Returns the number of channels that have been given a genesis event.
+	pub async fn create_missing_genesis_events( &self, egos: &[(String, PrivateKey)] ) -> Result<usize> {
+
+		let mut created = 0;
+		for (_, private_key) in egos {
+			let address = private_key.extract_public().unwrap();
+			let channel = match self.clone().get_channel( &address ).await? {
+				None => continue,
+				Some(c) => c
+			};
+			if !self.is_own_publisher( &address ).await? || channel.load_parameters().await?.is_some() {
+				continue
+			}
+
+			let public = channel.load_invite_code().await?.is_none();
+			self.atomically( Self::store_genesis( &channel, private_key, public ) ).await?;
+			created += 1;
+		}
+		Ok( created )
+	}
+
+	/// Signs the genesis event of the channel, and stores it along with the parameters in it.
+	async fn store_genesis( channel: &channel::Handle, private_key: &PrivateKey, public: bool ) -> Result<()> {
+
+		let data = ChannelCreateEventData {
+			public,
+			requested_replication_time: config::CHANNEL_REPLICATION_TIME
+		};
+		let hash = HashCode::generate_from( &data );
+		let genesis = ChannelCreateEventMessage {
+			signature: common::sign_hash( private_key, &hash ),
+			hash,
+			data
+		};
+		let mut message = vec![ ChannelEventType::Create as u8 ];
+		message.extend( bincode::serialize( &genesis )? );
+		channel.store_event( GENESIS_EVENT_ID, &message ).await?;
+		channel.store_parameters( &genesis.data ).await?;
+		Ok(())
+	}
+
+	/// Creates a new channel with the given ego name, and copies all posts of the `original` publisher that we have stored into it.
+	/// Each copied post remembers the address and the hash of the post that it was copied from.
+	/// Returns the new channel and the number of posts that have been copied.
+	pub async fn fork_channel( &mut self, name: &str, original: &PublicKey ) -> Result<(channel::Handle, u64)> {
+
+		let source = self.get_timeline( original ).await?.ok_or( Error::NotFound )?;
+
+		let channel = self.create_channel( name, config::CHANNEL_PUBLIC ).await?;
+		let private_key = self.services.lookup_ego( name ).await?;
+		let target = self.get_timeline( &private_key.extract_public().unwrap() ).await?
+			.expect("publisher of new channel not found");
+
+		let mut copied = 0;
+		if let Some(latest_id) = source.load_latest_post_id().await? {
+			for post_id in 0..=latest_id {
+				let post = match source.load_post( post_id ).await? {
+					None => continue,
+					Some(p) => p
+				};
+				// Posts without content can't be signed again, so they are left out.
+				let content = match source.load_post_content( post_id ).await? {
+					None => continue,
+					Some(c) => c
+				};
+
+				let (handle, copy) = target.create_post( &private_key, &content, post.meta.info.clone(), post.meta.attachment_ids.clone(), post.meta.reply_to.clone() ).await?;
+				handle.store_origin( original, &post.hash ).await?;
+				channel.log_new_post( &target, &copy ).await?;
+				copied += 1;
+			}
+		}
+
+		Ok(( channel, copied ))
 	}
 
 	pub async fn execute<P, F, R>( &self, sql: &'static str, params: P, on_executed: F ) -> rusqlite::Result<R> where
@@ -99,17 +392,14 @@ impl Handle {
 		F: FnOnce(u64) -> rusqlite::Result<R>
 	{
 		let db = self.db.clone();
-		// params is safe to send to another thread because it does not get used anymore after this function, and it is not copied or cloned.
-		let params = UnsafeSend::new( params );
-		// The same goes for `on_executed`
-		let on_executed = UnsafeSend::new( on_executed );
 
 		runtime::block_on(move || {
+			let _timer = metrics::DATABASE.with_label_values( &["execute"] ).start_timer();
 			let guard = db.lock().unwrap();
 			let mut statement = guard.prepare( sql )?;
-			let affected = statement.execute(params.unwrap())?;
+			let affected = statement.execute( params )?;
 
-			(on_executed.unwrap())(affected as _)
+			on_executed( affected as _ )
 		}).await
 	}
 
@@ -117,7 +407,7 @@ impl Handle {
 		P: IntoIterator,
 		P::Item: ToSql
 	{
-		self.execute(sql, params, |affected| Ok( debug_assert!(affected != expected_rows, "unexpected number of rows affected: {} (should be {})", affected, expected_rows) ) ).await
+		self.execute(sql, params, |affected| Ok( debug_assert!(affected == expected_rows, "unexpected number of rows affected: {} (should be {})", affected, expected_rows) ) ).await
 	}
 
 	pub async fn execute_one<P>( &self, sql: &'static str, params: P ) -> rusqlite::Result<()> where
@@ -134,26 +424,113 @@ impl Handle {
 		let db = self.db.clone();
 
 		runtime::block_on(move || {
+			let _timer = metrics::DATABASE.with_label_values( &["insert"] ).start_timer();
 			let guard = db.lock().unwrap();
 			let mut statement = guard.prepare( sql )?;
 			statement.insert(params)
 		}).await
 	}
 
+	/// Takes another connection to the same database from the pool.
+	/// Statements on different connections don't end up in each other's transactions.
+	pub async fn reconnect( &self ) -> rusqlite::Result<Self> {
+		Self::connect_at( self.services.clone(), (*self.data_dir).clone() ).await
+	}
+
+	/// Runs `work` within a transaction, which is committed if it succeeds, and rolled back otherwise.
+	/// Unlike with `transaction`, `work` can use the async methods of the handle.
+	/// Within a transaction that the same task has open on this connection, `work` runs in a savepoint of it, which is only rolled back by itself if it fails.
+	/// The transactions of other tasks wait for this one to end, but the other statements that they run on this connection in the meantime still become part of it.
+	/// So a connection that transactions are run on shouldn't be shared with unrelated work, see `reconnect`.
+	pub async fn atomically<W, R, E>( &self, work: W ) -> std::result::Result<R, E> where
+		W: Future<Output=std::result::Result<R, E>>,
+		E: From<rusqlite::Error>
+	{
+		let connection = Arc::as_ptr( &self.db ) as usize;
+		let mut open = TRANSACTIONS.try_with(|t| t.clone()).unwrap_or_default();
+		if open.contains( &connection ) {
+			return self.within_savepoint( work ).await
+		}
+
+		let _turn = self.transactions.lock().await;
+		self.execute_batch("BEGIN IMMEDIATE").await?;
+
+		open.push( connection );
+		match TRANSACTIONS.scope( open, work ).await {
+			Ok(result) => {
+				self.execute_batch("COMMIT").await?;
+				Ok( result )
+			},
+			Err(e) => {
+				if let Err(rollback_error) = self.execute_batch("ROLLBACK").await {
+					error!("Unable to roll back transaction: {}", rollback_error);
+				}
+				Err(e)
+			}
+		}
+	}
+
+	/// Runs `work` within a savepoint of the transaction that is open, see `atomically`.
+	async fn within_savepoint<W, R, E>( &self, work: W ) -> std::result::Result<R, E> where
+		W: Future<Output=std::result::Result<R, E>>,
+		E: From<rusqlite::Error>
+	{
+		self.execute_batch("SAVEPOINT atomically").await?;
+
+		match work.await {
+			Ok(result) => {
+				self.execute_batch("RELEASE atomically").await?;
+				Ok( result )
+			},
+			Err(e) => {
+				if let Err(rollback_error) = self.execute_batch("ROLLBACK TO atomically; RELEASE atomically").await {
+					error!("Unable to roll back savepoint: {}", rollback_error);
+				}
+				Err(e)
+			}
+		}
+	}
+
+	async fn execute_batch( &self, sql: &'static str ) -> rusqlite::Result<()> {
+		let db = self.db.clone();
+
+		runtime::block_on(move || {
+			let _timer = metrics::DATABASE.with_label_values( &["batch"] ).start_timer();
+			db.lock().unwrap().execute_batch( sql )
+		}).await
+	}
+
+	/// Runs `on_transaction` within a transaction, which is committed if it succeeds, and rolled back otherwise.
+	/// Like with `atomically`, the transactions of other tasks wait for it to end.
+	/// It can't be run within a transaction that is open on the same connection.
+	pub async fn transaction<F, R>( &self, on_transaction: F ) -> rusqlite::Result<R> where
+		F: FnOnce(&rusqlite::Transaction) -> rusqlite::Result<R>
+	{
+		let connection = Arc::as_ptr( &self.db ) as usize;
+		let nested = TRANSACTIONS.try_with(|t| t.contains( &connection )).unwrap_or( false );
+		// SQLite refuses the nested transaction, which is better than waiting for our own turn forever.
+		let _turn = if nested { None } else { Some( self.transactions.lock().await ) };
+		let db = self.db.clone();
+
+		runtime::block_on(move || {
+			let _timer = metrics::DATABASE.with_label_values( &["transaction"] ).start_timer();
+			let mut guard = db.lock().unwrap();
+			let tx = guard.0.transaction()?;
+			let result = on_transaction( &tx )?;
+			tx.commit()?;
+			Ok( result )
+		}).await
+	}
+
 	pub async fn query<P, F, R>( &self, sql: &'static str, params: P, on_result: F ) -> rusqlite::Result<R> where
 		P: IntoIterator,
 		P::Item: ToSql,
 		F: FnOnce(&Connection, rusqlite::Rows) -> rusqlite::Result<R>
 	{
 		let db = self.db.clone();
-		// params is safe to send to another thread because it does not get used anymore after this function, and it is not copied or cloned.
-		// params is also UnwindSafe, because it is simply just references of references. No mutability exists.
-		// the rusqlite::params macro generates something like `&[&dyn ToSql]`, but we don't have the power to change this type to `&[&dyn ToSql + RefUnwindSafe]`.
-		//let params = UnsafeSend::new( params );
-		// The same goes for `on_result`
-		//let on_result = UnsafeSend::new( on_result );
 
 		runtime::block_on(move || {
+			let _timer = metrics::DATABASE.with_label_values( &["query"] ).start_timer();
 			let guard = db.lock().unwrap();
 			
 			guard.query( sql, params, |rows| {
@@ -205,35 +582,224 @@ impl Handle {
 		Ok( timelines )
 	}
 
-	pub async fn get_latest_id( &self, id_type: &str ) -> Result<Option<u64>> {
+	/// Loads the data of a block, if it is available locally.
+	pub async fn load_block( &self, hash: &HashCode ) -> Result<Option<Vec<u8>>> {
+
+		Ok( self.query_one("SELECT data, encrypted FROM block WHERE hash = ?",
+			params![hash.to_string()],
+			|con, row| at_rest::read_blob( con, row, 0, 1 )
+		).await? )
+	}
+
+	/// Returns whether the block with the given hash is available locally.
+	pub async fn has_block( &self, hash: &HashCode ) -> Result<bool> {
+
+		Ok( self.query_one("SELECT 1 FROM block WHERE hash = ?",
+			params![hash.to_string()],
+			|_, _| Ok(())
+		).await?.is_some() )
+	}
+
+	/// Stores the data of a block, encrypted if it belongs to a private channel.
+	/// Nothing happens if the block is already stored, unless it needs to be encrypted and isn't yet.
+	pub async fn store_block( &self, hash: &HashCode, data: &[u8], encrypt: bool ) -> Result<()> {
+
+		let data = self.storage_key.seal( data, encrypt );
+		self.insert("INSERT OR IGNORE INTO block (hash, data, encrypted) VALUES (?,?,?)",
+			params![hash.to_string(), data, encrypt]
+		).await?;
+		if encrypt {
+			self.execute("UPDATE block SET data = ?, encrypted = 1 WHERE hash = ? AND encrypted = 0",
+				params![data, hash.to_string()],
+				|_| Ok(())
+			).await?;
+		}
+
+		Ok(())
+	}
+
+	/// Splits the data of a file up into blocks, and stores both the blocks and the file.
+	/// The blocks are encrypted once the file is attached to a post of a private channel.
+	/// Returns the hash that identifies the file, which is the Merkle root of its manifest, so that peers can check the blocks as they receive them.
+	pub async fn store_attachment( &self, data: &[u8], mime_type: &str ) -> Result<HashCode> {
+
+		let blocks = channel::breakup_data( data, post::FILE_BLOCK_LENGTH );
+		let file = Attachment { block_ids: channel::hash_blocks( &blocks ) };
+
+		// The file goes first, so that the garbage collection never takes its blocks for orphans.
+		let file_hash = file.merkle_root();
+		self.store_file( &file_hash, &file ).await?;
+		self.execute_one("UPDATE file SET mime_type = ? WHERE hash = ?",
+			params![mime_type, file_hash.to_string()]
+		).await?;
+
+		for (block_id, block) in file.block_ids.iter().zip( blocks.iter() ) {
+			self.store_block( block_id, block, false ).await?;
+		}
+
+		Ok( file_hash )
+	}
+
+	/// Loads which blocks make up the file with the given hash, if we know the file.
+	pub async fn load_file( &self, hash: &HashCode ) -> Result<Option<Attachment>> {
+
+		let block_ids: Option<Vec<u8>> = self.query_one("SELECT block_ids FROM file WHERE hash = ?",
+			params![hash.to_string()],
+			|_, row| row.get(0)
+		).await?;
+
+		Ok( match block_ids {
+			None => None,
+			Some(data) => Some( Attachment { block_ids: bincode::deserialize( &data )? } )
+		})
+	}
+
+	/// Checks whether the blocks of the file with the given hash add up to it.
+	/// Files that are identified by the Merkle root of their manifest pass, as their blocks are checked against the manifest one by one.
+	/// Files that are identified by the hash of their data can only be checked once all of their blocks are there, so they pass until then as well.
+	pub async fn verify_file( &self, hash: &HashCode ) -> Result<bool> {
+
+		let file = match self.load_file( hash ).await? {
+			None => return Ok(true),
+			Some(f) => f
+		};
+		if file.is_manifest_of( hash ) {
+			return Ok(true)
+		}
+
+		for block_id in &file.block_ids {
+			if !self.has_block( block_id ).await? {
+				return Ok(true)
+			}
+		}
+		let mut data = Vec::new();
+		for block_id in &file.block_ids {
+			data.extend( self.load_block( block_id ).await?.ok_or( Error::NotFound )? );
+		}
+		Ok( HashCode::generate( &data ) == *hash )
+	}
+
+	/// Forgets which blocks make up the file with the given hash, so that its manifest is requested again.
+	/// Its blocks are left to the garbage collection, in case another file is made of them as well.
+	pub async fn forget_file( &self, hash: &HashCode ) -> Result<()> {
+
+		self.execute("DELETE FROM file WHERE hash = ?",
+			params![hash.to_string()],
+			|_| Ok(())
+		).await?;
+
+		Ok(())
+	}
+
+	/// Loads the MIME type of the file with the given hash, if it is known.
+	pub async fn load_file_mime_type( &self, hash: &HashCode ) -> Result<Option<String>> {
+
+		let mime_type: Option<Option<String>> = self.query_one("SELECT mime_type FROM file WHERE hash = ?",
+			params![hash.to_string()],
+			|_, row| row.get(0)
+		).await?;
+
+		Ok( mime_type.flatten() )
+	}
+
+	/// Stores which blocks make up the file with the given hash.
+	/// Nothing happens if the file is already stored, as the blocks of a file never change.
+	pub async fn store_file( &self, hash: &HashCode, file: &Attachment ) -> Result<()> {
 
-		let result: Option<i64> = self.query_one("SELECT id FROM latest_ids WHERE type = ?",
-			params![id_type],
+		self.insert("INSERT OR IGNORE INTO file (hash, block_ids, stored) VALUES (?,?,?)",
+			params![hash.to_string(), bincode::serialize( &file.block_ids )?, peer::now()]
+		).await?;
+
+		Ok(())
+	}
+
+	/// Loads a local setting, if it has been set.
+	pub async fn load_setting( &self, key: &str ) -> Result<Option<String>> {
+
+		Ok( self.query_one("SELECT value FROM setting WHERE key = ?",
+			params![key],
 			|_, row| row.get(0)
+		).await? )
+	}
+
+	/// Stores a local setting, overwriting any previous value.
+	pub async fn store_setting( &self, key: &str, value: &str ) -> Result<()> {
+
+		self.insert("INSERT OR REPLACE INTO setting (key, value) VALUES (?,?)",
+			params![key, value]
 		).await?;
 
-		Ok( result.map(|i| i as _) )
+		Ok(())
 	}
 
 	/// Marks the ego identified with the given name, as an ego that belongs .
 	pub async fn own_channel( &self, name: &str, address: &PublicKey ) -> Result<bool> {
 
-		let _ = self.insert("INSERT INTO local_publishers (publisher_id) VALUES ((SELECT ROWID FROM publishers WHERE address = ?))",
-			params![address.to_string()]
+		let _ = self.insert("INSERT OR REPLACE INTO local_publishers (publisher_id, ego) VALUES ((SELECT ROWID FROM publisher WHERE address = ?), ?)",
+			params![address.to_string(), name]
 		).await?;
 
 		Ok(true)
 	}
 
-	pub async fn connect( gnunet: gnunet::Handle ) -> rusqlite::Result<Self> {
-		 
-		let db_conn = runtime::block_on(|| {
-			rusqlite::Connection::open(DATABASE_DIR.join("db.sqlite"))
+	/// Returns the directory that the database of this handle is stored in.
+	pub fn data_dir( &self ) -> &Path {
+		&self.data_dir
+	}
+
+	/// Connects to the database in the data directory that is chosen now, with a connection of its own that is shared by the clones of the handle.
+	/// The first connection to a database migrates it.
+	pub async fn connect( services: Arc<GnunetServices> ) -> rusqlite::Result<Self> {
+		Self::connect_at( services, data_dir() ).await
+	}
+
+	/// Connects to a database that only lives in memory, for as long as a connection to it is open.
+	/// Handles that are connected with the same name share the database, so that every node of the integration tests can have one of its own.
+	pub async fn connect_in_memory( services: Arc<GnunetServices>, name: &str ) -> rusqlite::Result<Self> {
+		Self::connect_at( services, pool::memory_data_dir( name ) ).await
+	}
+
+	/// Connects to the database in the given data directory.
+	async fn connect_at( services: Arc<GnunetServices>, data_dir: PathBuf ) -> rusqlite::Result<Self> {
+
+		let data_dir = Arc::new( data_dir );
+		let connection = runtime::block_on(|| {
+			pool::Pool::get( &data_dir )?.checkout()
 		}).await?;
 
 		Ok(Self {
-			gnunet,
-			db: Arc::new( Mutex::new( Connection ( db_conn ) ) )
+			services,
+			data_dir,
+			storage_key: connection.storage_key_arc(),
+			db: Arc::new( Mutex::new( Connection ( connection ) ) ),
+			transactions: Arc::new( async_std::sync::Mutex::new(()) )
+		})
+	}
+
+	/// Closes the connections to the database, after having SQLite optimize it, as it recommends doing right before closing.
+	/// The connections of other handles are closed when those handles are dropped.
+	pub async fn close( self ) -> rusqlite::Result<()> {
+		let data_dir = self.data_dir.clone();
+		drop( self );
+
+		runtime::block_on(move || pool::Pool::close( &data_dir )).await
+	}
+
+	/// Adds the channel with the given address, if we don't know it yet.
+	/// It will be subscribed to the next time the subscriptions are loaded.
+	pub async fn add_channel( &self, address: &PublicKey ) -> Result<channel::Handle> {
+
+		if let Some(channel) = self.clone().get_channel( address ).await? {
+			return Ok( channel )
+		}
+
+		let address_str = address.to_string();
+		let row_id = self.insert("INSERT INTO channel (address) VALUES (?)", params![address_str]).await?;
+		self.insert("INSERT OR IGNORE INTO publisher (channel_id, address) VALUES (?,?)", params![row_id, address_str]).await?;
+
+		Ok( channel::Handle {
+			base: self.clone(),
+			id: row_id
 		})
 	}
 
@@ -270,13 +836,13 @@ impl Deref for Connection {
 	type Target = rusqlite::Connection;
 
 	fn deref( &self ) -> &Self::Target {
-		&self.0
+		&*self.0
 	}
 }
 
 impl DerefMut for Connection {
 	fn deref_mut( &mut self ) -> &mut Self::Target {
-		&mut self.0
+		&mut *self.0
 	}
 }
 
@@ -284,7 +850,10 @@ impl fmt::Display for Error {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		match self {
 			Self::AlreadyExists => write!(f, "already exists"),
+			Self::EgoConflict(c) => write!(f, "{}", c),
+			Self::NotFound => write!(f, "not found"),
 			Self::Gnunet(e) => write!(f, "gnunet error: {}", e),
+			Self::Services(e) => write!(f, "{}", e),
 			Self::Database(e) => write!(f, "database error: {}", e),
 			Self::Serialization(e) => write!(f, "(de)serialization error: {}", e)
 		}
@@ -292,11 +861,25 @@ impl fmt::Display for Error {
 }
 
 
+impl fmt::Display for EgoConflict {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::InUse(address) => write!(f, "ego already belongs to channel {}", address),
+			Self::Unused(address) => write!(f, "ego {} exists without a channel", address)
+		}
+	}
+}
+
 impl From<gnunet::Error> for Error {
 	fn from( e: gnunet::Error ) -> Self {
 		Self::Gnunet(e)
 	}
 }
+impl From<services::Error> for Error {
+	fn from( e: services::Error ) -> Self {
+		Self::Services(e)
+	}
+}
 impl From<rusqlite::Error> for Error {
 	fn from( e: rusqlite::Error ) -> Self {
 		Self::Database(e)
@@ -0,0 +1,94 @@
+//! This module provides the persistence of the annotations that the reader makes on posts.
+//!
+//! Annotations are private, they are never sent to the swarm.
+//! They are keyed by the hash of the post, and the range of the passage in the content of the post, in characters.
+//! The passage itself is kept as well, so that an annotation still makes sense after the post has been revised.
+
+use fallible_iterator::FallibleIterator;
+use gnunet::{
+	crypto::HashCode,
+	identity::PublicKey
+};
+use rusqlite::{NO_PARAMS, params};
+
+use crate::persistence::{
+	self,
+	peer::now,
+	Result
+};
+
+
+
+/// A highlighted passage of a post, with an optional note.
+pub struct Annotation {
+	pub id: i64,
+	/// The address of the channel that the post was published on.
+	pub channel: PublicKey,
+	pub post_hash: HashCode,
+	/// The character offset at which the passage starts.
+	pub start: u64,
+	/// The character offset right after the end of the passage.
+	pub end: u64,
+	pub passage: String,
+	pub note: Option<String>,
+	/// The time at which the annotation was made, in milliseconds since the UNIX epoch.
+	pub created: u64
+}
+
+
+
+fn parse_row( row: &rusqlite::Row ) -> rusqlite::Result<Annotation> {
+	let channel: String = row.get(1)?;
+	let post_hash: String = row.get(2)?;
+	let start: i64 = row.get(3)?;
+	let end: i64 = row.get(4)?;
+	let created: i64 = row.get(7)?;
+
+	Ok( Annotation {
+		id: row.get(0)?,
+		channel: PublicKey::from_string( &channel ).expect("invalid channel address"),
+		post_hash: HashCode::from_string( &post_hash ).expect("invalid hash code"),
+		start: start as _,
+		end: end as _,
+		passage: row.get(5)?,
+		note: row.get(6)?,
+		created: created as _
+	})
+}
+
+impl persistence::Handle {
+
+	/// Stores a new annotation on the given post, and returns its ID.
+	pub async fn store_annotation( &self, channel: &PublicKey, post_hash: &HashCode, start: u64, end: u64, passage: &str, note: Option<&str> ) -> Result<i64> {
+
+		Ok( self.insert("INSERT INTO annotation (channel_address, post_hash, start, end, passage, note, created) VALUES (?,?,?,?,?,?,?)",
+			params![channel.to_string(), post_hash.to_string(), start as i64, end as i64, passage, note, now()]
+		).await? )
+	}
+
+	/// Loads the annotations on the given post, in the order in which their passages appear.
+	pub async fn load_annotations( &self, post_hash: &HashCode ) -> Result<Vec<Annotation>> {
+
+		Ok( self.query("SELECT id, channel_address, post_hash, start, end, passage, note, created FROM annotation \
+			WHERE post_hash = ? ORDER BY start, end",
+			params![post_hash.to_string()],
+			|_, rows| rows.map(|row| parse_row( row )).collect()
+		).await? )
+	}
+
+	/// Loads all annotations, oldest first.
+	pub async fn list_annotations( &self ) -> Result<Vec<Annotation>> {
+
+		Ok( self.query("SELECT id, channel_address, post_hash, start, end, passage, note, created FROM annotation ORDER BY created",
+			NO_PARAMS,
+			|_, rows| rows.map(|row| parse_row( row )).collect()
+		).await? )
+	}
+
+	/// Deletes an annotation.
+	/// Returns whether it existed.
+	pub async fn delete_annotation( &self, id: i64 ) -> Result<bool> {
+
+		Ok( self.execute("DELETE FROM annotation WHERE id = ?", params![id], |affected| Ok( affected > 0 )).await? )
+	}
+}
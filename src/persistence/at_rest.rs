@@ -0,0 +1,313 @@
+//! This module provides the encryption at rest of what is stored for the private channels, so that a copy of the database alone doesn't tell what is said in them.
+//! The public channels are stored as they are received, so a node that only relays public channels never encrypts anything, and has nothing to turn off for throughput.
+//!
+//! Everything is encrypted with the storage key of the node, a random secret that is kept in `storage.key` in the data directory, beside the database rather than in it.
+//! The keys of the channels themselves can't be used for this, because their invite codes are stored in the database.
+//! Without `storage.key`, the content of the private channels can't be read anymore, so it needs to be backed up together with the database.
+//!
+//! Texts that are encrypted are stored as blobs in their text columns, which tells them apart from the texts of public channels and from the ones that were stored before.
+//! Blocks, events and thumbnails are blobs anyway, so they have an `encrypted` column instead.
+//! Blocks are shared between all channels, so a block that a private channel uses stays encrypted for the other channels that use it.
+//! Whether something is encrypted is decided when it is stored, and what was stored before is encrypted by `encrypt_private_channels` when the node starts.
+//!
+//! The private channels are left out of the search index, which can't be encrypted.
+//! Drafts are left as they are, as they haven't been published in any channel yet.
+
+use std::{
+	fmt::Write as _,
+	fs::{self, OpenOptions},
+	io::{self, Write},
+	path::Path
+};
+
+use chacha20poly1305::{
+	aead::{Aead, NewAead},
+	ChaCha20Poly1305,
+	Key,
+	Nonce
+};
+use fallible_iterator::FallibleIterator;
+use gnunet::crypto::HashCode;
+use rand::RngCore;
+use rusqlite::{
+	params,
+	types::{Type, Value, ValueRef},
+	NO_PARAMS
+};
+use thiserror::Error;
+
+use crate::{
+	encryption::NONCE_LENGTH,
+	persistence::{
+		self,
+		channel,
+		Result
+	}
+};
+
+
+
+/// The name of the file in the data directory that the storage key is kept in.
+pub const STORAGE_KEY_FILE: &str = "storage.key";
+/// The length of the storage key, in bytes.
+const KEY_LENGTH: usize = 32;
+
+/// The texts of a channel that are encrypted, as the statement that selects the ones that are still plain by their ROWID, and the statement that replaces one of them.
+/// The selecting statements take the id of the channel as their only parameter.
+const TEXTS: &[(&str, &str)] = &[
+	("SELECT ROWID, data FROM post_content WHERE typeof(data) = 'text' AND ROWID IN ( \
+		SELECT p.content_id FROM post p INNER JOIN publisher pb ON pb.ROWID = p.publisher_id WHERE pb.channel_id = ?1 \
+		UNION SELECT r.content_id FROM post_revision r INNER JOIN post p ON p.ROWID = r.post_id INNER JOIN publisher pb ON pb.ROWID = p.publisher_id WHERE pb.channel_id = ?1)",
+		"UPDATE post_content SET data = ? WHERE ROWID = ?"),
+	("SELECT ROWID, excerpt FROM post_excerpt WHERE typeof(excerpt) = 'text' AND post_hash IN ( \
+		SELECT p.hash FROM post p INNER JOIN publisher pb ON pb.ROWID = p.publisher_id WHERE pb.channel_id = ?1)",
+		"UPDATE post_excerpt SET excerpt = ? WHERE ROWID = ?"),
+	("SELECT ROWID, content FROM comment WHERE typeof(content) = 'text' AND post_id IN ( \
+		SELECT p.ROWID FROM post p INNER JOIN publisher pb ON pb.ROWID = p.publisher_id WHERE pb.channel_id = ?1)",
+		"UPDATE comment SET content = ? WHERE ROWID = ?"),
+	("SELECT ROWID, title FROM profile WHERE typeof(title) = 'text' AND id IN (SELECT profile_id FROM channel_profile WHERE channel_id = ?1)",
+		"UPDATE profile SET title = ? WHERE ROWID = ?"),
+	("SELECT ROWID, description FROM profile WHERE typeof(description) = 'text' AND id IN (SELECT profile_id FROM channel_profile WHERE channel_id = ?1)",
+		"UPDATE profile SET description = ? WHERE ROWID = ?")
+];
+
+/// The events of a channel that are encrypted, like `TEXTS`.
+const EVENTS: &[(&str, &str)] = &[
+	("SELECT ROWID, message FROM channel_event WHERE channel_id = ?1 AND encrypted = 0",
+		"UPDATE channel_event SET message = ?, encrypted = 1 WHERE ROWID = ?"),
+	("SELECT e.ROWID, e.message FROM publisher_event e INNER JOIN publisher p ON p.ROWID = e.publisher_id WHERE p.channel_id = ?1 AND e.encrypted = 0",
+		"UPDATE publisher_event SET message = ?, encrypted = 1 WHERE ROWID = ?"),
+	("SELECT ROWID, message FROM pending_event WHERE channel_id = ?1 AND encrypted = 0",
+		"UPDATE pending_event SET message = ?, encrypted = 1 WHERE ROWID = ?")
+];
+
+/// The symmetric key with which everything of the private channels is encrypted before it is stored.
+pub struct StorageKey ( Key );
+
+/// Something that has been stored encrypted can't be decrypted with the storage key.
+#[derive(Debug, Error)]
+#[error("unable to decrypt what is stored for a private channel, storage.key may have been replaced")]
+pub struct DecryptionError;
+
+
+
+impl StorageKey {
+
+	/// Makes up a new key, which is what databases in memory use.
+	pub fn generate() -> Self {
+		let mut key = [0u8; KEY_LENGTH];
+		rand::thread_rng().fill_bytes( &mut key );
+		Self ( Key::clone_from_slice( &key ) )
+	}
+
+	/// Loads the storage key of the given data directory, and creates it the first time.
+	/// The key is written in hexadecimal, like invite codes are.
+	pub fn load_or_create( data_dir: &Path ) -> io::Result<Self> {
+		let path = data_dir.join( STORAGE_KEY_FILE );
+
+		if path.exists() {
+			let hex = fs::read_to_string( &path )?;
+			return Self::from_string( &hex )
+				.ok_or_else(|| io::Error::new( io::ErrorKind::InvalidData, format!("{} is not a valid storage key", path.display()) ))
+		}
+
+		let key = Self::generate();
+		let mut options = OpenOptions::new();
+		options.write( true ).create_new( true );
+		// Only the user that runs the node may read the key.
+		#[cfg(unix)]
+		std::os::unix::fs::OpenOptionsExt::mode( &mut options, 0o600 );
+		options.open( &path )?.write_all( key.to_string().as_bytes() )?;
+
+		Ok( key )
+	}
+
+	fn from_string( string: &str ) -> Option<Self> {
+		let string = string.trim();
+		if string.len() != KEY_LENGTH * 2 || !string.is_ascii() {
+			return None
+		}
+
+		let mut key = [0u8; KEY_LENGTH];
+		for (i, byte) in key.iter_mut().enumerate() {
+			*byte = u8::from_str_radix( &string[(i * 2)..(i * 2 + 2)], 16 ).ok()?;
+		}
+		Some( Self ( Key::clone_from_slice( &key ) ) )
+	}
+
+	fn to_string( &self ) -> String {
+		let mut string = String::with_capacity( KEY_LENGTH * 2 );
+		for byte in self.0.iter() {
+			write!( string, "{:02x}", byte ).unwrap();
+		}
+		string
+	}
+
+	/// Encrypts the data, and puts the random nonce that was used in front of it.
+	pub fn encrypt( &self, data: &[u8] ) -> Vec<u8> {
+		let mut nonce = [0u8; NONCE_LENGTH];
+		rand::thread_rng().fill_bytes( &mut nonce );
+
+		let encrypted = ChaCha20Poly1305::new( &self.0 ).encrypt( Nonce::from_slice( &nonce ), data ).expect("unable to encrypt data");
+		let mut result = Vec::with_capacity( NONCE_LENGTH + encrypted.len() );
+		result.extend_from_slice( &nonce );
+		result.extend( encrypted );
+		result
+	}
+
+	/// Decrypts data that was encrypted with `encrypt`.
+	pub fn decrypt( &self, data: &[u8] ) -> std::result::Result<Vec<u8>, DecryptionError> {
+		if data.len() < NONCE_LENGTH { return Err( DecryptionError ) }
+
+		ChaCha20Poly1305::new( &self.0 ).decrypt( Nonce::from_slice( &data[..NONCE_LENGTH] ), &data[NONCE_LENGTH..] )
+			.map_err(|_| DecryptionError)
+	}
+
+	/// The value to store for a text, which is encrypted into a blob if `encrypt` is set.
+	pub fn seal_text( &self, text: &str, encrypt: bool ) -> Value {
+		if encrypt {
+			Value::Blob( self.encrypt( text.as_bytes() ) )
+		} else {
+			Value::Text( text.to_owned() )
+		}
+	}
+
+	/// The value to store for a blob, which is encrypted if `encrypt` is set.
+	/// Whether it is, needs to be stored along with it.
+	pub fn seal( &self, data: &[u8], encrypt: bool ) -> Vec<u8> {
+		if encrypt {
+			self.encrypt( data )
+		} else {
+			data.to_vec()
+		}
+	}
+}
+
+/// Reads a text column, which holds a blob if the text has been encrypted.
+pub fn read_text( connection: &persistence::Connection, row: &rusqlite::Row, index: usize ) -> rusqlite::Result<String> {
+	match row.get_raw_checked( index )? {
+		ValueRef::Blob(data) => {
+			let text = connection.storage_key().decrypt( data )
+				.map_err(|e| rusqlite::Error::FromSqlConversionFailure( index, Type::Blob, Box::new( e ) ))?;
+			String::from_utf8( text ).map_err(|e| rusqlite::Error::FromSqlConversionFailure( index, Type::Blob, Box::new( e ) ))
+		},
+		_ => row.get( index )
+	}
+}
+
+/// Reads a blob column, which has been encrypted if the column at `encrypted_index` is set.
+pub fn read_blob( connection: &persistence::Connection, row: &rusqlite::Row, index: usize, encrypted_index: usize ) -> rusqlite::Result<Vec<u8>> {
+	let data: Vec<u8> = row.get( index )?;
+	if !row.get::<_, bool>( encrypted_index )? {
+		return Ok( data )
+	}
+
+	connection.storage_key().decrypt( &data )
+		.map_err(|e| rusqlite::Error::FromSqlConversionFailure( index, Type::Blob, Box::new( e ) ))
+}
+
+impl persistence::Handle {
+
+	/// Encrypts everything of the private channels that is still stored in plain form, because it was stored before this was done, or before it was known that the channel is private.
+	/// Returns the number of rows that have been encrypted.
+	pub async fn encrypt_private_channels( &self ) -> Result<u64> {
+
+		let ids: Vec<i64> = self.query("SELECT id FROM channel WHERE public = 0", NO_PARAMS,
+			|_, rows| rows.map(|row| row.get(0)).collect()
+		).await?;
+
+		let mut encrypted = 0;
+		for id in ids {
+			let channel = channel::Handle { base: self.clone(), id };
+			encrypted += self.atomically( channel.encrypt_stored() ).await?;
+		}
+		Ok( encrypted )
+	}
+
+	/// Encrypts the blocks of the given files, if they aren't already.
+	/// The thumbnails of the files that were made before are dropped, they are made again when they are needed.
+	pub async fn encrypt_files( &self, file_hashes: &[HashCode] ) -> Result<u64> {
+
+		let mut encrypted = 0;
+		for file_hash in file_hashes {
+			let file = match self.load_file( file_hash ).await? {
+				None => continue,
+				Some(f) => f
+			};
+			for block_id in &file.block_ids {
+				let data: Option<Vec<u8>> = self.query_one("SELECT data FROM block WHERE hash = ? AND encrypted = 0",
+					params![block_id.to_string()],
+					|_, row| row.get(0)
+				).await?;
+				if let Some(data) = data {
+					self.execute_one("UPDATE block SET data = ?, encrypted = 1 WHERE hash = ?",
+						params![self.storage_key.encrypt( &data ), block_id.to_string()]
+					).await?;
+					encrypted += 1;
+				}
+			}
+			self.execute("DELETE FROM thumbnail WHERE file_hash = ? AND encrypted = 0", params![file_hash.to_string()], |_| Ok(()) ).await?;
+		}
+		Ok( encrypted )
+	}
+
+	/// Whether the blocks of the file with the given hash are encrypted, so that whatever is made of it should be as well.
+	pub async fn is_file_encrypted( &self, file_hash: &HashCode ) -> Result<bool> {
+
+		let first_block = match self.load_file( file_hash ).await? {
+			None => return Ok( false ),
+			Some(f) => match f.block_ids.into_iter().next() {
+				None => return Ok( false ),
+				Some(b) => b
+			}
+		};
+		Ok( self.query_one("SELECT encrypted FROM block WHERE hash = ?",
+			params![first_block.to_string()],
+			|_, row| row.get(0)
+		).await?.unwrap_or( false ) )
+	}
+}
+
+impl channel::Handle {
+
+	/// Encrypts everything of this channel that is still stored in plain form, and removes its posts from the search index.
+	/// Returns the number of rows that have been encrypted.
+	pub async fn encrypt_stored( &self ) -> Result<u64> {
+		let mut encrypted = 0;
+
+		for (select, update) in TEXTS {
+			let plain: Vec<(i64, String)> = self.base.query( *select, params![self.id],
+				|_, rows| rows.map(|row| Ok(( row.get(0)?, row.get(1)? ))).collect()
+			).await?;
+			for (row_id, text) in &plain {
+				self.base.execute_one( *update, params![self.base.storage_key.encrypt( text.as_bytes() ), row_id] ).await?;
+			}
+			encrypted += plain.len() as u64;
+		}
+
+		for (select, update) in EVENTS {
+			let plain: Vec<(i64, Vec<u8>)> = self.base.query( *select, params![self.id],
+				|_, rows| rows.map(|row| Ok(( row.get(0)?, row.get(1)? ))).collect()
+			).await?;
+			for (row_id, message) in &plain {
+				self.base.execute_one( *update, params![self.base.storage_key.encrypt( message ), row_id] ).await?;
+			}
+			encrypted += plain.len() as u64;
+		}
+
+		self.base.execute("DELETE FROM post_search WHERE rowid IN \
+			(SELECT p.ROWID FROM post p INNER JOIN publisher pb ON pb.ROWID = p.publisher_id WHERE pb.channel_id = ?)",
+			params![self.id],
+			|_| Ok(())
+		).await?;
+
+		let mut files = self.list_attachment_ids().await?;
+		if let Some(profile) = self.fetch_profile().await? {
+			files.extend( profile.base.profile_picture );
+			files.extend( profile.stylesheet );
+		}
+		encrypted += self.base.encrypt_files( &files ).await?;
+
+		Ok( encrypted )
+	}
+}
@@ -0,0 +1,152 @@
+//! This module provides the persistence of the audit log, which records the administrative actions that have been taken on this node.
+//!
+//! Only actions that are taken locally are recorded, not the changes that arrive from the swarms.
+//! The log is append-only: the database refuses to change or delete its entries.
+
+use fallible_iterator::FallibleIterator;
+use rusqlite::{NO_PARAMS, params};
+
+use crate::persistence::{
+	self,
+	peer::now,
+	Result
+};
+
+
+
+/// An action that is recorded in the audit log.
+#[derive(Clone, Copy)]
+pub enum AuditAction {
+	ChannelCreated,
+	ChannelForked,
+	ChannelRestored,
+	ChannelClosed,
+	ChannelDeleted,
+	EgoCreated,
+	EgoRenamed,
+	EgoDeleted,
+	ProfileUpdated,
+	PostCreated,
+	PostsRevised,
+	PostsForgotten,
+	PostsImported,
+	CommentPosted,
+	CommentApproved,
+	CommentHidden,
+	CommentDeleted,
+	CommenterApproved,
+	ReactionPosted,
+	DraftSaved,
+	DraftDeleted,
+	OwnershipOffered,
+	OwnershipAccepted,
+	PublisherAdded,
+	PublisherRevoked,
+	InviteAccepted,
+	Subscribed,
+	Unsubscribed,
+	SettingChanged,
+	ConfigReloaded
+}
+
+/// A recorded action.
+pub struct AuditEntry {
+	pub id: i64,
+	/// The time at which the action was taken, in milliseconds since the UNIX epoch.
+	pub time: u64,
+	/// The name of the ego that took the action, or `None` if it was taken by the administrator of the node.
+	pub actor: Option<String>,
+	pub action: String,
+	/// What the action was taken on, like the address of a channel or the name of a setting.
+	pub subject: String
+}
+
+
+
+impl AuditAction {
+
+	/// The name under which the action is stored.
+	/// These names are kept as they are, so that older entries remain readable.
+	pub fn name( &self ) -> &'static str {
+		match self {
+			Self::ChannelCreated => "channel created",
+			Self::ChannelForked => "channel forked",
+			Self::ChannelRestored => "channel restored",
+			Self::ChannelClosed => "channel closed",
+			Self::ChannelDeleted => "channel deleted",
+			Self::EgoCreated => "ego created",
+			Self::EgoRenamed => "ego renamed",
+			Self::EgoDeleted => "ego deleted",
+			Self::ProfileUpdated => "profile updated",
+			Self::PostCreated => "post created",
+			Self::PostsRevised => "posts revised",
+			Self::PostsForgotten => "posts forgotten",
+			Self::PostsImported => "posts imported",
+			Self::CommentPosted => "comment posted",
+			Self::CommentApproved => "comment approved",
+			Self::CommentHidden => "comment hidden",
+			Self::CommentDeleted => "comment deleted",
+			Self::CommenterApproved => "commenter approved",
+			Self::ReactionPosted => "reaction posted",
+			Self::DraftSaved => "draft saved",
+			Self::DraftDeleted => "draft deleted",
+			Self::OwnershipOffered => "ownership offered",
+			Self::OwnershipAccepted => "ownership accepted",
+			Self::PublisherAdded => "publisher added",
+			Self::PublisherRevoked => "publisher revoked",
+			Self::InviteAccepted => "invite accepted",
+			Self::Subscribed => "subscribed",
+			Self::Unsubscribed => "unsubscribed",
+			Self::SettingChanged => "setting changed",
+			Self::ConfigReloaded => "configuration reloaded"
+		}
+	}
+}
+
+fn parse_row( row: &rusqlite::Row ) -> rusqlite::Result<AuditEntry> {
+	let time: i64 = row.get(1)?;
+
+	Ok( AuditEntry {
+		id: row.get(0)?,
+		time: time as _,
+		actor: row.get(2)?,
+		action: row.get(3)?,
+		subject: row.get(4)?
+	})
+}
+
+impl persistence::Handle {
+
+	/// Adds an action to the audit log.
+	pub async fn record_action( &self, actor: Option<&str>, action: AuditAction, subject: &str ) -> Result<()> {
+
+		self.insert("INSERT INTO audit_log (time, actor, action, subject) VALUES (?,?,?,?)",
+			params![now(), actor, action.name(), subject]
+		).await?;
+		Ok(())
+	}
+
+	pub async fn count_audit_entries( &self ) -> Result<u64> {
+
+		let count: i64 = self.query_one("SELECT COUNT(*) FROM audit_log", NO_PARAMS, |_, row| row.get(0) ).await?.unwrap_or(0);
+		Ok( count as _ )
+	}
+
+	/// Loads a page of the audit log, the newest entries first.
+	pub async fn list_audit_entries( &self, offset: u64, limit: u32 ) -> Result<Vec<AuditEntry>> {
+
+		Ok( self.query("SELECT id, time, actor, action, subject FROM audit_log ORDER BY id DESC LIMIT ? OFFSET ?",
+			params![limit, offset as i64],
+			|_, rows| rows.map(|row| parse_row( row )).collect()
+		).await? )
+	}
+
+	/// Loads the whole audit log, oldest first.
+	pub async fn export_audit_log( &self ) -> Result<Vec<AuditEntry>> {
+
+		Ok( self.query("SELECT id, time, actor, action, subject FROM audit_log ORDER BY id",
+			NO_PARAMS,
+			|_, rows| rows.map(|row| parse_row( row )).collect()
+		).await? )
+	}
+}
@@ -0,0 +1,644 @@
+//! This module provides backups of channels, which hold everything that is stored for a channel in a single archive.
+//!
+//! A backup holds the event log, the posts of all publishers with their contents and revisions, the files that are attached to them and the blocks that we have of those, the profile and the subscription.
+//! It is restored on a node that doesn't know the channel yet, after every hash and signature in it has been verified, so that a damaged or tampered backup is refused as a whole.
+//! The events are replayed while they are verified, and the parameters, the profile and the publishers of the backup have to be what the events lead to, as they are stored without a signature of their own.
+//! The keys of our own channels are kept by gnunet, and are not part of a backup.
+
+use std::{
+	cmp::Ordering,
+	convert::TryFrom,
+	fmt
+};
+
+use fallible_iterator::FallibleIterator;
+use gnunet::{
+	crypto::HashCode,
+	identity::PublicKey
+};
+use rusqlite::params;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+	encryption::InviteCode,
+	event::{
+		AcceptOwnershipEventMessage,
+		ChannelCreateEventData,
+		ChannelCreateEventMessage,
+		ChannelEventType,
+		CloseChannelEventMessage,
+		CommentEventData,
+		ForgetPostEventData,
+		ModerateCommentsEventMessage,
+		PostRevision,
+		PublishPostEventData,
+		PublisherEventType,
+		ReactionEventData,
+		RevisePostEventData,
+		TransferOwnershipEventMessage,
+		GENESIS_EVENT_ID
+	},
+	message::{ChannelProfile, Profile, PublisherList, UpdateChannelProfileEventMessage, UpdatePinnedPostsEventMessage, UpdatePublisherListEventMessage},
+	persistence::{
+		self,
+		at_rest,
+		post::FILE_BLOCK_LENGTH,
+		subscription::Subscription,
+		Error,
+		Result
+	},
+	post::{merkle_root, Attachment, Post},
+	validation::*
+};
+
+
+
+/// The version of the format of backups, which is raised whenever it changes.
+pub const BACKUP_VERSION: u32 = 1;
+
+
+
+/// Everything that is stored for a channel, in the form in which it is backed up.
+#[derive(Deserialize, Serialize)]
+struct ChannelBackup {
+	version: u32,
+	address: PublicKey,
+	parameters: Option<ChannelCreateEventData>,
+	/// The invite code of a private channel, in its textual form.
+	invite_code: Option<String>,
+	last_event_id: Option<u64>,
+	publisher_list_revision: Option<u32>,
+	profile: Option<ChannelProfile>,
+	events: Vec<BackedUpEvent>,
+	publishers: Vec<BackedUpPublisher>,
+	files: Vec<BackedUpFile>,
+	/// The blocks of the files, as far as we have them.
+	blocks: Vec<(HashCode, Vec<u8>)>,
+	subscription: Option<Subscription>
+}
+
+#[derive(Deserialize, Serialize)]
+struct BackedUpEvent {
+	id: u64,
+	/// The publisher that the event belongs to, or `None` for events of the channel itself.
+	publisher: Option<PublicKey>,
+	/// The message as it is stored.
+	message: Vec<u8>
+}
+
+#[derive(Deserialize, Serialize)]
+struct BackedUpPublisher {
+	address: PublicKey,
+	revoked: bool,
+	posts: Vec<BackedUpPost>
+}
+
+#[derive(Deserialize, Serialize)]
+struct BackedUpPost {
+	post: Post,
+	/// The original content, if we have it.
+	content: Option<String>,
+	revisions: Vec<RevisePostEventData>
+}
+
+#[derive(Deserialize, Serialize)]
+struct BackedUpFile {
+	hash: HashCode,
+	block_ids: Vec<HashCode>,
+	mime_type: Option<String>
+}
+
+/// What the events of a backup lead to, as they are replayed in the order of their ids to verify them.
+struct EventReplay<'a> {
+	backup: &'a ChannelBackup,
+	/// The keys that sign the events of the channel.
+	/// The previous owners are kept after a transfer, because the grace period can't be told apart from the moment at which the backup was made.
+	owners: Vec<PublicKey>,
+	/// The new owner and the hash of a transfer that hasn't been accepted yet.
+	pending_transfer: Option<(PublicKey, HashCode)>,
+	parameters: Option<ChannelCreateEventData>,
+	publisher_list: Option<PublisherList>,
+	/// Everyone that any of the publisher lists has allowed to publish.
+	ever_listed: Vec<PublicKey>,
+	profile: Option<ChannelProfile>,
+	/// The posts that the events have published so far, with their publishers.
+	published: Vec<(PublicKey, Post)>
+}
+
+/// Why a backup can't be restored.
+#[derive(Debug)]
+pub enum BackupError {
+	Persistence( Error ),
+	/// The backup can't be read, or something in it doesn't match its hash or signature, as described.
+	Corrupt( String ),
+	/// The backup was made by a newer version of the node.
+	UnsupportedVersion( u32 ),
+	/// The channel of the backup is known already.
+	AlreadyExists( PublicKey )
+}
+
+
+
+impl persistence::Handle {
+
+	/// Backs up everything that is stored for the channel with the given address into a single archive.
+	pub async fn export_channel( &self, address: &PublicKey ) -> Result<Vec<u8>> {
+
+		let channel = self.clone().get_channel( address ).await?.ok_or( Error::NotFound )?;
+		let (invite_code, publisher_list_revision): (Option<String>, Option<i64>) = self.query_one("SELECT invite_code, publisher_list_revision FROM channel WHERE id = ?",
+			params![channel.id],
+			|_, row| Ok(( row.get(0)?, row.get(1)? ))
+		).await?.ok_or( Error::NotFound )?;
+
+		// Events can't be numerous enough to make loading them all at once a problem, the posts and files are much larger.
+		let events = self.query("SELECT id, NULL, message, encrypted FROM channel_event WHERE channel_id = ?1 \
+			UNION ALL SELECT e.id, p.address, e.message, e.encrypted FROM publisher_event e INNER JOIN publisher p ON p.ROWID = e.publisher_id \
+			WHERE p.channel_id = ?1 ORDER BY 1",
+			params![channel.id],
+			|con, rows| Ok( rows.map(|row| {
+				let id: i64 = row.get(0)?;
+				let address: Option<String> = row.get(1)?;
+				Ok( BackedUpEvent {
+					id: id as _,
+					publisher: address.map(|a| PublicKey::from_string( &a ).expect("invalid publisher address")),
+					message: at_rest::read_blob( con, row, 2, 3 )?
+				})
+			}).collect()? )
+		).await?;
+
+		let publisher_rows: Vec<(String, bool)> = self.query("SELECT address, revoked FROM publisher WHERE channel_id = ? ORDER BY ROWID",
+			params![channel.id],
+			|_, rows| Ok( rows.map(|row| Ok(( row.get(0)?, row.get(1)? ))).collect()? )
+		).await?;
+		let mut publishers = Vec::with_capacity( publisher_rows.len() );
+		for (address, revoked) in publisher_rows {
+			let address = PublicKey::from_string( &address ).expect("invalid publisher address");
+			let timeline = match self.get_timeline( &address ).await? {
+				None => continue,
+				Some(t) => t
+			};
+
+			let end = timeline.load_latest_post_id().await?.map(|id| id + 1).unwrap_or(0);
+			let mut posts = Vec::new();
+			for post_id in timeline.load_post_ids( 0, end, false ).await? {
+				let post = match timeline.load_post( post_id ).await? {
+					None => continue,
+					Some(p) => p
+				};
+				let revisions = self.load_signed_revisions( timeline.id, &post ).await?;
+				posts.push( BackedUpPost {
+					content: timeline.load_post_content( post_id ).await?,
+					post,
+					revisions
+				});
+			}
+			publishers.push( BackedUpPublisher { address, revoked, posts } );
+		}
+
+		// The files of the profile are backed up along with the attachments.
+		let profile = channel.fetch_profile().await?;
+		let mut file_ids = channel.list_attachment_ids().await?;
+		if let Some(profile) = &profile {
+			file_ids.extend( profile.base.profile_picture.iter().cloned() );
+			file_ids.extend( profile.stylesheet.iter().cloned() );
+		}
+		let mut files = Vec::with_capacity( file_ids.len() );
+		let mut blocks = Vec::new();
+		for file_id in file_ids {
+			if files.iter().any(|f: &BackedUpFile| f.hash == file_id) { continue }
+			let file = match self.load_file( &file_id ).await? {
+				None => continue,
+				Some(f) => f
+			};
+			for block_id in &file.block_ids {
+				if let Some(data) = self.load_block( block_id ).await? {
+					blocks.push(( block_id.clone(), data ));
+				}
+			}
+			files.push( BackedUpFile {
+				mime_type: self.load_file_mime_type( &file_id ).await?,
+				hash: file_id,
+				block_ids: file.block_ids
+			});
+		}
+
+		let backup = ChannelBackup {
+			version: BACKUP_VERSION,
+			address: address.clone(),
+			parameters: channel.load_parameters().await?,
+			invite_code,
+			last_event_id: channel.load_last_event_id().await?,
+			publisher_list_revision: publisher_list_revision.map(|r| r as _),
+			profile,
+			events,
+			publishers,
+			files,
+			blocks,
+			subscription: self.load_subscription( address ).await?
+		};
+		Ok( bincode::serialize( &backup )? )
+	}
+
+	/// Restores a channel from a backup made by `export_channel`, and returns its address.
+	/// The backup is verified completely before anything is stored, and then stored in a single transaction.
+	pub async fn import_channel( &self, data: &[u8] ) -> std::result::Result<PublicKey, BackupError> {
+
+		let backup: ChannelBackup = bincode::deserialize( data ).map_err(|e| BackupError::Corrupt( e.to_string() ))?;
+		if backup.version > BACKUP_VERSION {
+			return Err( BackupError::UnsupportedVersion( backup.version ) )
+		}
+		backup.verify()?;
+		if self.clone().get_channel( &backup.address ).await?.is_some() {
+			return Err( BackupError::AlreadyExists( backup.address ) )
+		}
+
+		self.atomically(async {
+			let channel = self.add_channel( &backup.address ).await?;
+			if let Some(parameters) = &backup.parameters {
+				channel.store_parameters( parameters ).await?;
+			}
+			if let Some(code) = backup.invite_code.as_ref().and_then(|c| InviteCode::from_string( c )) {
+				channel.store_invite_code( &code ).await?;
+			}
+			if let Some(revision) = backup.publisher_list_revision {
+				self.execute_one("UPDATE channel SET publisher_list_revision = ? WHERE id = ?",
+					params![revision as i64, channel.id]
+				).await?;
+			}
+			if let Some(profile) = &backup.profile {
+				channel.store_profile( profile ).await?;
+			}
+
+			for publisher in &backup.publishers {
+				self.insert("INSERT OR IGNORE INTO publisher (channel_id, address) VALUES (?,?)",
+					params![channel.id, publisher.address.to_string()]
+				).await?;
+				self.execute_one("UPDATE publisher SET revoked = ? WHERE channel_id = ? AND address = ?",
+					params![publisher.revoked, channel.id, publisher.address.to_string()]
+				).await?;
+				let timeline = channel.get_timeline( &publisher.address ).await?.ok_or( Error::NotFound )?;
+
+				for backed_up in &publisher.posts {
+					let post = match timeline.store_post( &backed_up.post ).await? {
+						None => continue,
+						Some(p) => p
+					};
+					if let Some(content) = &backed_up.content {
+						post.store_content( content ).await?;
+					}
+					for revision in &backed_up.revisions {
+						timeline.store_revision( revision ).await?;
+					}
+				}
+			}
+
+			for event in &backup.events {
+				match &event.publisher {
+					None => channel.store_event( event.id, &event.message ).await?,
+					Some(address) => channel.get_timeline( address ).await?.ok_or( Error::NotFound )?
+						.store_event( event.id, &event.message ).await?
+				}
+			}
+			if let Some(id) = backup.last_event_id {
+				channel.store_last_event_id( id ).await?;
+			}
+
+			// The file goes first, so that the garbage collection never takes its blocks for orphans.
+			for file in &backup.files {
+				self.store_file( &file.hash, &Attachment { block_ids: file.block_ids.clone() } ).await?;
+				if let Some(mime_type) = &file.mime_type {
+					self.execute_one("UPDATE file SET mime_type = ? WHERE hash = ?",
+						params![mime_type, file.hash.to_string()]
+					).await?;
+				}
+			}
+			let private = channel.is_private().await?;
+			for (hash, data) in &backup.blocks {
+				self.store_block( hash, data, private ).await?;
+			}
+
+			if let Some(subscription) = &backup.subscription {
+				self.save_subscription( subscription ).await?;
+			}
+			Ok::<_, BackupError>(())
+		}).await?;
+
+		Ok( backup.address )
+	}
+
+	/// Loads the revisions of a post in the form in which they were signed, so that they can be verified again.
+	/// Revisions that were stored before their information was kept can't be verified anymore, and are left out.
+	async fn load_signed_revisions( &self, publisher_id: i64, post: &Post ) -> Result<Vec<RevisePostEventData>> {
+
+		let rows: Vec<(i64, String, Vec<u8>, Option<Vec<u8>>, String)> = self.query("SELECT r.number, r.hash, r.signature, r.info, c.data FROM post_revision r \
+			INNER JOIN post p ON p.ROWID = r.post_id INNER JOIN post_content c ON c.ROWID = r.content_id \
+			WHERE p.publisher_id = ? AND p.id = ? ORDER BY r.number",
+			params![publisher_id, post.id as i64],
+			|con, rows| Ok( rows.map(|row| Ok(( row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, at_rest::read_text( con, row, 4 )? ))).collect()? )
+		).await?;
+
+		let mut revisions = Vec::with_capacity( rows.len() );
+		for (number, hash, signature, info, content) in rows {
+			let info = match info {
+				None => continue,
+				Some(i) => bincode::deserialize( &i )?
+			};
+			revisions.push( RevisePostEventData {
+				post_id: post.id,
+				revision: PostRevision {
+					post_hash: post.hash.clone(),
+					number: number as _,
+					content_hash: HashCode::generate( content.as_bytes() ),
+					info
+				},
+				hash: HashCode::from_string( &hash ).expect("invalid hash code"),
+				signature: bincode::deserialize( &signature )?,
+				content
+			});
+		}
+		Ok( revisions )
+	}
+}
+
+impl ChannelBackup {
+
+	/// Checks every post, content and revision against its hash and the signature of its publisher, and every block and complete file against its hash.
+	/// Every event is checked like the swarm checks it, and the parameters, profile and publishers have to match the events, see `EventReplay`.
+	fn verify( &self ) -> std::result::Result<(), BackupError> {
+
+		for publisher in &self.publishers {
+			for backed_up in &publisher.posts {
+				let post = &backed_up.post;
+				validate_post( post, &publisher.address )
+					.map_err(|e| BackupError::Corrupt( format!("post {} of {}: {}", post.id, publisher.address, e) ))?;
+				if let Some(content) = &backed_up.content {
+					validate_post_content( &post.meta, content )
+						.map_err(|e| BackupError::Corrupt( format!("post {} of {}: {}", post.id, publisher.address, e) ))?;
+				}
+				for revision in &backed_up.revisions {
+					validate_post_revision( revision, post, &publisher.address )
+						.map_err(|e| BackupError::Corrupt( format!("revision {} of post {} of {}: {}", revision.revision.number, post.id, publisher.address, e) ))?;
+				}
+			}
+		}
+
+		for (hash, data) in &self.blocks {
+			if data.len() > FILE_BLOCK_LENGTH || HashCode::generate( data ) != *hash {
+				return Err( BackupError::Corrupt( format!("block {} doesn't match its hash", hash) ) )
+			}
+		}
+		// Files that are identified by the Merkle root of their manifest are checked through their blocks already.
+		// Of the other files that are complete, the blocks have to add up to the file.
+		for file in &self.files {
+			if merkle_root( &file.block_ids ) == file.hash {
+				continue
+			}
+			let mut data = Vec::new();
+			let complete = file.block_ids.iter().all(|id| match self.blocks.iter().find(|(hash, _)| hash == id) {
+				None => false,
+				Some((_, block)) => { data.extend_from_slice( block ); true }
+			});
+			if complete && HashCode::generate( &data ) != file.hash {
+				return Err( BackupError::Corrupt( format!("file {} doesn't match its hash", file.hash) ) )
+			}
+		}
+
+		let mut replay = EventReplay::new( self );
+		for event in &self.events {
+			replay.apply( event )
+				.map_err(|e| BackupError::Corrupt( format!("event {}: {}", event.id, e) ))?;
+		}
+		replay.check().map_err( BackupError::Corrupt )
+	}
+}
+
+impl<'a> EventReplay<'a> {
+
+	fn new( backup: &'a ChannelBackup ) -> Self {
+		Self {
+			backup,
+			owners: vec![ backup.address.clone() ],
+			pending_transfer: None,
+			parameters: None,
+			publisher_list: None,
+			ever_listed: Vec::new(),
+			profile: None,
+			published: Vec::new()
+		}
+	}
+
+	/// Verifies the event with the same validators that the swarm uses, and applies it to what has been replayed so far.
+	/// Like in the swarm, events that refer to a post that we don't have can't be verified, and are let through.
+	fn apply( &mut self, event: &BackedUpEvent ) -> std::result::Result<(), MessageMalformedError> {
+
+		if event.message.len() == 0 {
+			Err( MessageMalformedError::MissingData("event".to_owned()) )?
+		}
+		let data = &event.message[1..];
+		match &event.publisher {
+			None => self.apply_channel_event( event.id, event.message[0], data ),
+			Some(publisher) => self.apply_publisher_event( publisher, event.message[0], data )
+		}
+	}
+
+	fn apply_channel_event( &mut self, id: u64, type_id: u8, data: &[u8] ) -> std::result::Result<(), MessageMalformedError> {
+
+		let event_type = ChannelEventType::try_from( type_id )
+			.map_err(|_| MessageMalformedError::InvalidTypeId( type_id, "channel event type".to_owned() ))?;
+		match event_type {
+			ChannelEventType::Create => {
+				// A channel is only created once, at the very start.
+				if id != GENESIS_EVENT_ID {
+					Err( MessageMalformedError::InvalidEventId( id ) )?
+				}
+				let msg: ChannelCreateEventMessage = decode( data, "channel create event message" )?;
+				validate_channel_genesis( &msg, &self.backup.address )?;
+				self.parameters = Some( msg.data );
+			},
+			ChannelEventType::UpdateChannelProfile => {
+				let msg: UpdateChannelProfileEventMessage = decode( data, "update profile event message" )?;
+				self.validate_by_owner(|owner| validate_channel_profile_update( &msg, owner ))?;
+				if self.profile.as_ref().map(|p| msg.profile.supersedes( p )).unwrap_or(true) {
+					self.profile = Some( msg.profile );
+				}
+			},
+			ChannelEventType::UpdatePublisherList => {
+				let msg: UpdatePublisherListEventMessage = decode( data, "update publisher list event message" )?;
+				self.validate_by_owner(|owner| validate_publisher_list_update( &msg, owner ))?;
+				for publisher in &msg.list.publishers {
+					if !self.ever_listed.contains( publisher ) {
+						self.ever_listed.push( publisher.clone() );
+					}
+				}
+				if self.publisher_list.as_ref().map(|l| msg.list.revision > l.revision).unwrap_or(true) {
+					self.publisher_list = Some( msg.list );
+				}
+			},
+			ChannelEventType::TransferOwnership => {
+				let msg: TransferOwnershipEventMessage = decode( data, "transfer ownership event message" )?;
+				self.validate_by_owner(|owner| validate_ownership_transfer( &msg, owner ))?;
+				self.pending_transfer = Some(( msg.transfer.new_owner, msg.hash ));
+			},
+			ChannelEventType::AcceptOwnership => {
+				let msg: AcceptOwnershipEventMessage = decode( data, "accept ownership event message" )?;
+				let (new_owner, hash) = self.pending_transfer.take()
+					.ok_or_else(|| MessageMalformedError::InvalidHash("accept ownership event message".to_owned()))?;
+				validate_ownership_acceptance( &msg, &hash, &new_owner )?;
+				self.owners.push( new_owner );
+			},
+			ChannelEventType::UpdatePinnedPosts => {
+				let msg: UpdatePinnedPostsEventMessage = decode( data, "update pinned posts event message" )?;
+				self.validate_by_owner(|owner| validate_pinned_posts_update( &msg, owner ))?;
+			},
+			ChannelEventType::ModerateComments => {
+				let msg: ModerateCommentsEventMessage = decode( data, "moderate comments event message" )?;
+				self.validate_by_owner(|owner| validate_comment_moderation( &msg, owner ))?;
+			},
+			ChannelEventType::Close => {
+				let msg: CloseChannelEventMessage = decode( data, "close channel event message" )?;
+				self.validate_by_owner(|owner| validate_channel_closing( &msg, owner ))?;
+			}
+		}
+		Ok(())
+	}
+
+	fn apply_publisher_event( &mut self, publisher: &PublicKey, type_id: u8, data: &[u8] ) -> std::result::Result<(), MessageMalformedError> {
+
+		// Only the publishers that the channel owner has listed at the time may publish.
+		let listed = *publisher == self.backup.address
+			|| self.publisher_list.as_ref().map(|l| l.publishers.contains( publisher )).unwrap_or(false);
+		if !listed {
+			Err( MessageMalformedError::UnknownPublisher( publisher.clone() ) )?
+		}
+
+		let event_type = PublisherEventType::try_from( type_id )
+			.map_err(|_| MessageMalformedError::InvalidTypeId( type_id, "publisher event type".to_owned() ))?;
+		match event_type {
+			PublisherEventType::UpdateProfile => {
+				let _: Profile = decode( data, "update profile event profile" )?;
+			},
+			PublisherEventType::PublishPost => {
+				let data: PublishPostEventData = decode( data, "publish post event" )?;
+				validate_post( &data.post, publisher )?;
+				self.published.push(( publisher.clone(), data.post ));
+			},
+			PublisherEventType::RevisePost => {
+				let data: RevisePostEventData = decode( data, "revise post event" )?;
+				if let Some(post) = self.find_post( publisher, data.post_id ) {
+					validate_post_revision( &data, post, publisher )?;
+				}
+			},
+			PublisherEventType::ForgetPost => {
+				let data: ForgetPostEventData = decode( data, "forget post event" )?;
+				if let Some(post) = self.find_post( publisher, data.post_id ) {
+					validate_forget_post( &data, post, publisher )?;
+				}
+			},
+			PublisherEventType::Comment => {
+				let data: CommentEventData = decode( data, "comment event" )?;
+				if let Some(post) = self.find_post( publisher, data.post_id ) {
+					validate_comment( &data, post )?;
+				}
+			},
+			PublisherEventType::Reaction => {
+				let data: ReactionEventData = decode( data, "reaction event" )?;
+				if let Some(post) = self.find_post( publisher, data.post_id ) {
+					validate_reaction( &data, post )?;
+				}
+			}
+		}
+		Ok(())
+	}
+
+	/// Checks a channel event with `validate`, for each of the owners, like `Node::validate_by_owner` does.
+	fn validate_by_owner<F>( &self, validate: F ) -> std::result::Result<(), MessageMalformedError> where
+		F: Fn( &PublicKey ) -> std::result::Result<(), MessageMalformedError>
+	{
+		let mut result = Err( MessageMalformedError::InvalidSignature("channel event".to_owned()) );
+		for owner in &self.owners {
+			result = validate( owner );
+			if result.is_ok() { break }
+		}
+		result
+	}
+
+	/// Finds a post of the publisher among the ones in the backup, which have been verified already, or among the ones that the events have published.
+	fn find_post( &self, publisher: &PublicKey, post_id: u64 ) -> Option<&Post> {
+		self.backup.publishers.iter()
+			.filter(|p| p.address == *publisher)
+			.flat_map(|p| p.posts.iter().map(|b| &b.post))
+			.chain( self.published.iter().filter(|(address, _)| address == publisher).map(|(_, post)| post) )
+			.find(|post| post.id == post_id)
+	}
+
+	/// Checks the parameters, the publishers and the profile of the backup against what the events have led to.
+	/// Returns a description of the first one that doesn't match.
+	fn check( &self ) -> std::result::Result<(), String> {
+
+		let backup = self.backup;
+		if backup.parameters != self.parameters {
+			return Err( "the parameters of the channel don't match its genesis event".to_owned() )
+		}
+
+		if backup.publisher_list_revision != self.publisher_list.as_ref().map(|l| l.revision) {
+			return Err( "the revision of the publisher list doesn't match the events".to_owned() )
+		}
+		let current: &[PublicKey] = match &self.publisher_list {
+			None => &[],
+			Some(list) => &list.publishers
+		};
+		for publisher in &backup.publishers {
+			// The channel's own key can always publish, whether it is listed or not.
+			if publisher.address == backup.address {
+				continue
+			}
+			if !self.ever_listed.contains( &publisher.address ) {
+				return Err( format!("publisher {} has never been listed", publisher.address) )
+			}
+			if publisher.revoked == current.contains( &publisher.address ) {
+				return Err( format!("publisher {} doesn't have the revocation that the events lead to", publisher.address) )
+			}
+		}
+
+		let profile_matches = match (&backup.profile, &self.profile) {
+			(None, None) => true,
+			(Some(stored), Some(replayed)) => stored.compare( replayed ) == Ordering::Equal,
+			_ => false
+		};
+		if !profile_matches {
+			return Err( "the profile doesn't match the events".to_owned() )
+		}
+		Ok(())
+	}
+}
+
+/// Reads the data of an event, in the form in which it is stored.
+fn decode<T: DeserializeOwned>( data: &[u8], what: &str ) -> std::result::Result<T, MessageMalformedError> {
+	bincode::deserialize( data )
+		.map_err(|e| MessageMalformedError::DeserializationIssue( e, what.to_owned() ))
+}
+
+impl From<Error> for BackupError {
+	fn from( other: Error ) -> Self {
+		Self::Persistence( other )
+	}
+}
+
+impl From<rusqlite::Error> for BackupError {
+	fn from( other: rusqlite::Error ) -> Self {
+		Self::Persistence( Error::Database( other ) )
+	}
+}
+
+impl fmt::Display for BackupError {
+	fn fmt( &self, f: &mut fmt::Formatter<'_> ) -> fmt::Result {
+		match self {
+			Self::Persistence(e) => write!(f, "{}", e),
+			Self::Corrupt(e) => write!(f, "The backup is corrupt: {}", e),
+			Self::UnsupportedVersion(version) => write!(f, "The backup has version {}, which is newer than this node supports.", version),
+			Self::AlreadyExists(address) => write!(f, "Channel {} is known already.", address)
+		}
+	}
+}
+
+impl std::error::Error for BackupError {}
@@ -0,0 +1,114 @@
+//! This module provides the changes that a publisher can make to many of its own posts at once.
+//!
+//! Every changed post results in a `RevisePost` or `ForgetPost` event, which is added to the event log and the outbox of the channel, so that subscribers learn about the change.
+//! A batch is applied in a single transaction: either all of its posts are changed, or none of them.
+
+use gnunet::identity::PrivateKey;
+use serde::Serialize;
+
+use crate::{
+	event::PublisherEventType,
+	persistence::{
+		channel,
+		Error,
+		Result
+	},
+	post::check_info
+};
+
+
+
+/// A change that can be made to many posts at once.
+pub enum BatchAction {
+	/// Asks everyone to forget the posts.
+	Delete,
+	/// Adds a tag to the posts that don't have it yet.
+	AddTag( String ),
+	/// Puts the posts in a series, or takes them out of their series if `None`.
+	SetSeries( Option<String> ),
+	/// Hides the content of the posts behind a warning, or shows it directly again if `None`.
+	SetContentWarning( Option<String> )
+}
+
+
+
+impl channel::Handle {
+
+	/// Applies the action to the posts with the given ids, on the timeline of the given publisher.
+	/// Posts that we don't have, and posts that the action doesn't change, are skipped.
+	/// So are posts that already have the maximum number of tags, when adding a tag.
+	/// Returns the number of posts that have been changed.
+	pub async fn apply_batch( &self, private_key: &PrivateKey, post_ids: &[u64], action: &BatchAction ) -> Result<usize> {
+
+		let address = private_key.extract_public().unwrap();
+		let timeline = self.get_timeline( &address ).await?.ok_or( Error::NotFound )?;
+
+		let mut detached_files = Vec::new();
+		let changed = self.base.atomically(async {
+			let mut changed = 0;
+
+			for &post_id in post_ids {
+				let message = match action {
+					BatchAction::Delete => match timeline.request_forget_post( private_key, post_id ).await? {
+						None => continue,
+						Some((data, files)) => {
+							detached_files.extend( files );
+							event_message( PublisherEventType::ForgetPost, &data )?
+						}
+					},
+					_ => {
+						let post = match timeline.load_post( post_id ).await? {
+							None => continue,
+							Some(p) => p
+						};
+						let content = match timeline.load_current_content( post_id ).await? {
+							None => continue,
+							Some(c) => c
+						};
+
+						let mut info = timeline.load_current_info( &post ).await?;
+						match action {
+							BatchAction::AddTag( tag ) => {
+								if info.tags.contains( tag ) { continue }
+								info.tags.push( tag.clone() );
+							},
+							BatchAction::SetSeries( series ) => {
+								if info.series == *series { continue }
+								info.series = series.clone();
+							},
+							BatchAction::SetContentWarning( warning ) => {
+								if info.content_warning == *warning { continue }
+								info.content_warning = warning.clone();
+							},
+							BatchAction::Delete => unreachable!()
+						}
+						if check_info( &info ).is_err() { continue }
+
+						match timeline.revise_post( private_key, post_id, &content, info ).await? {
+							None => continue,
+							Some(data) => event_message( PublisherEventType::RevisePost, &data )?
+						}
+					}
+				};
+
+				self.log_post_event( &timeline, post_id, &message ).await?;
+				changed += 1;
+			}
+
+			Ok::<usize, Error>( changed )
+		}).await?;
+
+		// The files of the deleted posts can only be removed once the posts are gone.
+		if detached_files.len() > 0 {
+			self.base.remove_unused_files( &detached_files ).await?;
+		}
+		Ok( changed )
+	}
+}
+
+/// Puts together a publisher event message, in the form in which it is stored.
+fn event_message<T: Serialize>( event_type: PublisherEventType, data: &T ) -> Result<Vec<u8>> {
+	let mut message = vec![event_type as u8];
+	message.extend( bincode::serialize( data )? );
+	Ok( message )
+}
@@ -0,0 +1,48 @@
+//! This module provides the persistence of the publishers that we've blocked.
+//!
+//! The events of a blocked publisher are still stored and passed on to the rest of the swarm, as other nodes may want them.
+//! Only what they would add to our own database, like their posts and comments, is left out.
+//! The list is local to this node, and applies to every channel that the publisher publishes in.
+
+use gnunet::identity::PublicKey;
+use rusqlite::params;
+
+use crate::persistence::{
+	self,
+	peer::now,
+	Result
+};
+
+
+
+impl persistence::Handle {
+
+	/// Blocks the publisher with the given address.
+	/// Returns whether it wasn't blocked already.
+	pub async fn block_publisher( &self, address: &PublicKey ) -> Result<bool> {
+
+		Ok( self.execute("INSERT OR IGNORE INTO blocked_publisher (address, time) VALUES (?,?)",
+			params![address.to_string(), now()],
+			|affected| Ok( affected > 0 )
+		).await? )
+	}
+
+	/// Unblocks the publisher with the given address.
+	/// Only the posts that arrive from then on are stored again.
+	/// Returns whether it was blocked.
+	pub async fn unblock_publisher( &self, address: &PublicKey ) -> Result<bool> {
+
+		Ok( self.execute("DELETE FROM blocked_publisher WHERE address = ?",
+			params![address.to_string()],
+			|affected| Ok( affected > 0 )
+		).await? )
+	}
+
+	pub async fn is_publisher_blocked( &self, address: &PublicKey ) -> Result<bool> {
+
+		Ok( self.query_one("SELECT 1 FROM blocked_publisher WHERE address = ?",
+			params![address.to_string()],
+			|_, _| Ok(())
+		).await?.is_some() )
+	}
+}
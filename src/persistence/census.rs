@@ -0,0 +1,73 @@
+//! This module provides the persistence of the census of the swarms of our own channels, for the history on the followers dashboard.
+//!
+//! The swarm node keeps a sample every `CENSUS_SAMPLE_INTERVAL`, with the number of our children and the estimated size of the whole swarm.
+//! Samples that are older than `CENSUS_HISTORY_DAYS` are dropped whenever a new one is stored, and the rest are aggregated per hour when they are loaded.
+
+use fallible_iterator::FallibleIterator;
+use rusqlite::params;
+
+use crate::{
+	config,
+	persistence::{
+		channel,
+		peer::now,
+		Result
+	}
+};
+
+
+
+/// The number of milliseconds in an hour, which is what the samples are aggregated over.
+const HOUR: i64 = 60 * 60 * 1000;
+
+/// The census of a swarm during an hour.
+pub struct CensusHour {
+	/// The start of the hour, in milliseconds since the UNIX epoch.
+	pub timestamp: u64,
+	/// The most children that we had during the hour.
+	pub children: u32,
+	/// The largest estimate of the size of the swarm during the hour, if it had been counted.
+	pub swarm_size: Option<u32>
+}
+
+
+
+impl channel::Handle {
+
+	/// Stores a sample of the census of the swarm, and drops the samples that have become too old.
+	pub async fn store_census( &self, children: u32, swarm_size: Option<u32> ) -> Result<()> {
+
+		let now = now();
+		let oldest = now - (config::CENSUS_HISTORY_DAYS * 24) as i64 * HOUR;
+		let id = self.id;
+		self.base.transaction(move |tx| {
+			tx.execute("INSERT INTO swarm_census (channel_id, timestamp, children, swarm_size) VALUES (?,?,?,?)",
+				params![id, now, children, swarm_size]
+			)?;
+			tx.execute("DELETE FROM swarm_census WHERE channel_id = ? AND timestamp < ?", params![id, oldest])?;
+			Ok(())
+		}).await?;
+
+		Ok(())
+	}
+
+	/// Loads the census of the swarm per hour, for the last `days` days, the oldest hour first.
+	/// Hours in which no sample has been taken, because the node wasn't running, are left out.
+	pub async fn list_census_history( &self, days: u64 ) -> Result<Vec<CensusHour>> {
+
+		let since = now() - (days * 24) as i64 * HOUR;
+		Ok( self.base.query("SELECT timestamp / ?1, MAX(children), MAX(swarm_size) FROM swarm_census \
+			WHERE channel_id = ?2 AND timestamp >= ?3 GROUP BY 1 ORDER BY 1",
+			params![HOUR, self.id, since],
+			|_, rows| Ok( rows.map(|row| {
+				let hour: i64 = row.get(0)?;
+				let swarm_size: Option<i64> = row.get(2)?;
+				Ok( CensusHour {
+					timestamp: (hour * HOUR) as _,
+					children: row.get::<_, i64>(1)? as _,
+					swarm_size: swarm_size.map(|s| s as _)
+				})
+			}).collect()? )
+		).await? )
+	}
+}
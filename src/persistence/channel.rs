@@ -1,4 +1,5 @@
 use std::{
+	cmp::Ordering,
 	ops::Deref,
 };
 
@@ -6,6 +7,7 @@ use async_std::{
 	prelude::*,
 };
 use bincode;
+use fallible_iterator::FallibleIterator;
 use gnunet::{
 	crypto::*,
 	identity::*
@@ -13,11 +15,21 @@ use gnunet::{
 use rusqlite::*;
 
 use crate::{
+	common,
+	page_cache,
 	persistence::{
 		self,
+		at_rest,
+		peer::now,
+		system_post::SystemPostKind,
+		timeline,
+		Error,
 		Result
 	},
-	message::*
+	encryption::{ChannelKey, InviteCode},
+	event::{ChannelCreateEventData, ChannelEventType, CommentEventData, EventType, PublishPostEventData, PublisherEventType, ReactionEventData, RevisePostEventData, GENESIS_EVENT_ID},
+	message::*,
+	post::{Post, PostInfo}
 };
 
 
@@ -28,6 +40,39 @@ pub struct Handle {
 	pub id: i64
 }
 
+/// The number of different messages that are kept for an event that is waiting to be applied.
+/// All but one of them are forged, so this only needs to leave room for the real one besides a few others.
+pub const PENDING_EVENT_MAX_COPIES: usize = 4;
+
+// The swarm of a channel keeps its handle, and uses it from every task that handles its peers.
+const _: fn() = || {
+	fn assert_send_sync<T: Send + Sync>() {}
+	assert_send_sync::<Handle>();
+};
+
+/// An event as it is stored in the event log of a channel.
+pub struct StoredEvent {
+	pub id: u64,
+	/// The publisher that the event belongs to, or `None` for events of the channel itself.
+	pub publisher: Option<PublicKey>,
+	/// The type of the event, which is a `ChannelEventType` or a `PublisherEventType` depending on `publisher`.
+	pub event_type: Option<u8>,
+	/// The size of the stored message, in bytes.
+	pub size: usize,
+	/// The hash of the stored message.
+	pub hash: HashCode,
+	/// Whether the event has been applied, or is still waiting for the events before it.
+	pub applied: bool
+}
+
+/// A profile that lost to another profile with the same revision, which is kept so that the owner can restore it.
+pub struct ProfileConflict {
+	pub id: i64,
+	pub profile: ChannelProfile,
+	/// When the profile lost, in milliseconds since the UNIX epoch.
+	pub recorded: u64
+}
+
 
 
 impl Handle {
@@ -46,11 +91,18 @@ impl Handle {
 	}
 
 	/// Stores the profile for this channel.
+	/// The title and description of a private channel are encrypted, and so are its picture and stylesheet.
 	pub async fn store_profile( &self, profile: &ChannelProfile ) -> Result<()> {
-		let data = bincode::serialize( profile )?;
 
 		let profile_picture = profile.base.profile_picture.as_ref().map(|h| h.to_string());
 		let stylesheet = profile.stylesheet.as_ref().map(|h| h.to_string());
+		let private = self.is_private().await?;
+		let title = self.base.storage_key.seal_text( &profile.base.title, private );
+		let description = self.base.storage_key.seal_text( &profile.base.description, private );
+		if private {
+			let files: Vec<HashCode> = profile.base.profile_picture.iter().chain( profile.stylesheet.iter() ).cloned().collect();
+			self.base.encrypt_files( &files ).await?;
+		}
 
 		let result: Option<i64> = self.base.query_one("SELECT profile_id FROM channel_profile WHERE channel_id = ?",
 			params![self.id],
@@ -59,36 +111,40 @@ impl Handle {
 
 		if let Some(profile_id) = result {
 
-			self.base.execute_one("UPDATE profile SET title = ?, description = ?, picture_hash = ? WHERE id = ?",
+			self.base.execute_one("UPDATE profile SET revision = ?, title = ?, description = ?, picture_hash = ? WHERE id = ?",
 				params![
-					profile.base.title,
-					profile.base.description,
+					profile.base.revision as i64,
+					title,
+					description,
 					profile_picture,
 					profile_id
 				]
 			).await?;
 
-			self.base.execute_one("UPDATE channel_profile SET stylesheet = ? WHERE ROWID = ?",
+			self.base.execute_one("UPDATE channel_profile SET stylesheet = ?, timestamp = ? WHERE channel_id = ?",
 				params![
-					stylesheet
+					stylesheet,
+					profile.timestamp.map(|t| t as i64),
+					self.id
 				]
 			).await?;
 		}
 		else {
-			let profile_id = self.base.insert("INSERT INTO profile (title, description, picture_hash) VALUES (?,?,?); RETURNING ROWID",
+			let profile_id = self.base.insert("INSERT INTO profile (revision, title, description, picture_hash) VALUES (?,?,?,?)",
 				params![
-					&profile.base.title,
-					&profile.base.description,
-					profile_picture,
-					self.id
+					profile.base.revision as i64,
+					title,
+					description,
+					profile_picture
 				]
 			).await?;
 
-			self.base.insert("INSERT INTO channel_profile (channel_id, profile_id, stylesheet) VALUES (?,?,?)",
+			self.base.insert("INSERT INTO channel_profile (channel_id, profile_id, stylesheet, timestamp) VALUES (?,?,?,?)",
 				params![
 					self.id,
 					profile_id,
-					stylesheet
+					stylesheet,
+					profile.timestamp.map(|t| t as i64)
 				]
 			).await?;
 		}
@@ -98,42 +154,639 @@ impl Handle {
 
 	pub async fn fetch_profile( &self ) -> Result<Option<ChannelProfile>> {
 
-		Ok( self.base.query_one("SELECT p.revision, p.title, p.description, p.picture_hash, c.stylesheet FROM channel_profile INNER JOIN channel_profile ON c.channel_id = p.ROWID WHERE o.ROWID = ?",
+		Ok( self.base.query_one("SELECT p.revision, p.title, p.description, p.picture_hash, c.stylesheet, c.timestamp FROM channel_profile c INNER JOIN profile p ON p.id = c.profile_id WHERE c.channel_id = ?",
 			params![self.id],
-			|_, row| {
-				let hash_string: Option<String> = row.get(2)?;
+			|con, row| {
+				let hash_string: Option<String> = row.get(3)?;
 				let hash = hash_string.map(|s| HashCode::from_string(&s).expect("invalid hash code"));
-				let stylesheet_hash_string: Option<String> = row.get(3)?;
+				let stylesheet_hash_string: Option<String> = row.get(4)?;
 				let stylesheet_hash = stylesheet_hash_string.map(|s| HashCode::from_string(&s).expect("invalid hash code"));
 
 				let revision: i64 = row.get(0)?;
+				let timestamp: Option<i64> = row.get(5)?;
 
 				Ok( ChannelProfile {
 					base: Profile {
 						revision: revision as _,
-						title: row.get(1)?,
-						description: row.get(2)?,
+						title: at_rest::read_text( con, row, 1 )?,
+						description: at_rest::read_text( con, row, 2 )?,
 						profile_picture: hash
 					},
-					stylesheet: stylesheet_hash
+					stylesheet: stylesheet_hash,
+					timestamp: timestamp.map(|t| t as _)
 				} )
 			}
 		).await? )
 	}
 
-	/// Stores an event message with the given id.
-	/// Storing multiple messages with the same id is possible.
+	/// Stores the profile if it wins from the current one, as decided by `ChannelProfile::supersedes`.
+	/// Of two different profiles with the same revision, the one that loses is kept as a conflict.
+	/// Returns whether the profile has been stored.
+	pub async fn apply_profile( &self, profile: &ChannelProfile ) -> Result<bool> {
+
+		let current = match self.fetch_profile().await? {
+			None => {
+				self.store_profile( profile ).await?;
+				return Ok(true)
+			},
+			Some(p) => p
+		};
+
+		let stored = profile.supersedes( &current );
+		if stored {
+			self.store_profile( profile ).await?;
+		}
+
+		if current.base.revision == profile.base.revision && profile.compare( &current ) != Ordering::Equal {
+			let loser = if stored { &current } else { profile };
+			self.base.insert("INSERT INTO profile_conflict (channel_id, revision, profile, recorded) VALUES (?,?,?,?)",
+				params![
+					self.id,
+					loser.base.revision as i64,
+					bincode::serialize( loser )?,
+					now()
+				]
+			).await?;
+		}
+
+		Ok( stored )
+	}
+
+	/// Lists the profiles that lost to another profile with the same revision, the most recent first.
+	pub async fn list_profile_conflicts( &self ) -> Result<Vec<ProfileConflict>> {
+
+		let rows: Vec<(i64, Vec<u8>, i64)> = self.base.query("SELECT id, profile, recorded FROM profile_conflict WHERE channel_id = ? ORDER BY id DESC",
+			params![self.id],
+			|_, rows| rows.map(|row| Ok(( row.get(0)?, row.get(1)?, row.get(2)? ))).collect()
+		).await?;
+
+		let mut conflicts = Vec::with_capacity( rows.len() );
+		for (id, profile, recorded) in rows {
+			conflicts.push( ProfileConflict {
+				id,
+				profile: bincode::deserialize( &profile )?,
+				recorded: recorded as _
+			});
+		}
+		Ok( conflicts )
+	}
+
+	/// Loads a profile that lost to another one, or `None` if there is no such conflict.
+	pub async fn load_profile_conflict( &self, id: i64 ) -> Result<Option<ChannelProfile>> {
+
+		let profile: Option<Vec<u8>> = self.base.query_one("SELECT profile FROM profile_conflict WHERE id = ? AND channel_id = ?",
+			params![id, self.id],
+			|_, row| row.get(0)
+		).await?;
+
+		Ok( match profile {
+			None => None,
+			Some(p) => Some( bincode::deserialize( &p )? )
+		})
+	}
+
+	/// Forgets a profile that lost to another one.
+	/// Returns whether there was such a conflict.
+	pub async fn delete_profile_conflict( &self, id: i64 ) -> Result<bool> {
+
+		Ok( self.base.execute("DELETE FROM profile_conflict WHERE id = ? AND channel_id = ?",
+			params![id, self.id],
+			|count| Ok( count > 0 )
+		).await? )
+	}
+
+	/// Changes the profile of the channel, as one of its owners.
+	/// The new profile gets the next revision, and is added to the event log so that it reaches the swarm.
+	/// Returns `Error::NotFound` if the given key doesn't own the channel.
+	pub async fn update_profile( &self, private_key: &PrivateKey, title: String, description: String, profile_picture: Option<HashCode> ) -> Result<ChannelProfile> {
+
+		let owner = private_key.extract_public().unwrap();
+		if !self.load_owners().await?.contains( &owner ) {
+			return Err( Error::NotFound )
+		}
+
+		let current = self.fetch_profile().await?;
+		let profile = ChannelProfile {
+			base: Profile {
+				revision: current.as_ref().map(|p| p.base.revision + 1).unwrap_or(1),
+				title,
+				description,
+				profile_picture
+			},
+			stylesheet: current.and_then(|p| p.stylesheet),
+			timestamp: Some( now() as _ )
+		};
+
+		let hash = HashCode::generate_from( &profile );
+		let msg = UpdateChannelProfileEventMessage {
+			signature: common::sign_hash( private_key, &hash ),
+			hash,
+			profile
+		};
+		let mut message = vec![ ChannelEventType::UpdateChannelProfile as u8 ];
+		message.extend( bincode::serialize( &msg )? );
+
+		self.base.atomically(async {
+			self.apply_profile( &msg.profile ).await?;
+			self.log_event( None, &message ).await?;
+			Ok::<(), Error>(())
+		}).await?;
+
+		Ok( msg.profile )
+	}
+
+	/// Loads the id of the last event that has been applied, if any.
+	/// All events up to and including this id have been applied.
+	pub async fn load_last_event_id( &self ) -> Result<Option<u64>> {
+
+		let id: Option<Option<i64>> = self.base.query_one("SELECT last_event_id FROM channel WHERE ROWID = ?",
+			params![self.id],
+			|_, row| row.get(0)
+		).await?;
+
+		Ok( id.flatten().map(|i| i as _) )
+	}
+
+	pub async fn store_last_event_id( &self, id: u64 ) -> Result<()> {
+
+		self.base.execute_one("UPDATE channel SET last_event_id = ? WHERE ROWID = ?",
+			params![id as i64, self.id]
+		).await?;
+
+		Ok(())
+	}
+
+	/// Returns the number of entries in the event log of the channel, including the events that are waiting to be applied.
+	pub async fn count_events( &self ) -> Result<u64> {
+
+		let count: i64 = self.base.query_one("SELECT (SELECT COUNT(*) FROM channel_event WHERE channel_id = ?1) + \
+			(SELECT COUNT(*) FROM publisher_event e INNER JOIN publisher p ON p.ROWID = e.publisher_id WHERE p.channel_id = ?1) + \
+			(SELECT COUNT(*) FROM pending_event WHERE channel_id = ?1)",
+			params![self.id],
+			|_, row| row.get(0)
+		).await?.unwrap_or(0);
+
+		Ok( count as _ )
+	}
+
+	/// Loads a page of the event log of the channel, the newest events first.
+	/// The events that are waiting to be applied are listed along with the applied ones.
+	pub async fn list_events( &self, offset: u64, limit: u32 ) -> Result<Vec<StoredEvent>> {
+
+		// The genesis event is applied when the channel is created or joined.
+		let last_event_id = self.load_last_event_id().await?.unwrap_or( GENESIS_EVENT_ID );
+
+		Ok( self.base.query("SELECT id, NULL, message, encrypted FROM channel_event WHERE channel_id = ?1 \
+			UNION ALL SELECT e.id, p.address, e.message, e.encrypted FROM publisher_event e INNER JOIN publisher p ON p.ROWID = e.publisher_id WHERE p.channel_id = ?1 \
+			UNION ALL SELECT id, publisher, message, encrypted FROM pending_event WHERE channel_id = ?1 \
+			ORDER BY 1 DESC LIMIT ?2 OFFSET ?3",
+			params![self.id, limit, offset as i64],
+			|con, rows| Ok( rows.map(|row| {
+				let id: i64 = row.get(0)?;
+				let address: Option<String> = row.get(1)?;
+				let message = at_rest::read_blob( con, row, 2, 3 )?;
+
+				Ok( StoredEvent {
+					id: id as _,
+					publisher: address.map(|a| PublicKey::from_string( &a ).expect("invalid publisher address")),
+					event_type: message.first().cloned(),
+					size: message.len(),
+					hash: HashCode::generate( &message ),
+					applied: id as u64 <= last_event_id
+				})
+			}).collect()? )
+		).await? )
+	}
+
+	/// Loads the stored event messages with ids in the range `from_id..(from_id + count)`, ordered by id.
+	/// The messages are reconstructed into the form in which they are broadcasted, so that they can be relayed as-is.
+	pub async fn load_events( &self, from_id: u64, count: u16 ) -> Result<Vec<(u64, Vec<u8>)>> {
+		let end_id = from_id + count as u64;
+
+		Ok( self.base.query("SELECT id, NULL, message, encrypted FROM channel_event WHERE channel_id = ?1 AND id >= ?2 AND id < ?3 \
+			UNION ALL SELECT e.id, p.address, e.message, e.encrypted FROM publisher_event e INNER JOIN publisher p ON p.ROWID = e.publisher_id \
+			WHERE p.channel_id = ?1 AND e.id >= ?2 AND e.id < ?3 ORDER BY 1",
+			params![self.id, from_id as i64, end_id as i64],
+			|con, rows| Ok( rows.map(|row| {
+				let id: i64 = row.get(0)?;
+				let address: Option<String> = row.get(1)?;
+				let stored = at_rest::read_blob( con, row, 2, 3 )?;
+
+				Ok(( id as u64, broadcast_form( id as u64, address, &stored ) ))
+			}).collect()? )
+		).await? )
+	}
+
+	/// Keeps an event that has arrived before the ones preceding it, until it can be applied.
+	/// Different messages may arrive for the same id, of which only the first `PENDING_EVENT_MAX_COPIES` are kept, and the same message only once.
+	/// Returns whether the message has been kept.
+	pub async fn store_pending_event( &self, id: u64, event_type: &EventType, message: &[u8] ) -> Result<bool> {
+
+		let publisher = match event_type {
+			EventType::Channel => None,
+			EventType::Publisher(address) => Some( address.to_string() )
+		};
+		let hash = HashCode::generate( &broadcast_form( id, publisher.clone(), message ) );
+		let encrypt = self.is_private().await?;
+		Ok( self.base.execute("INSERT OR IGNORE INTO pending_event (channel_id, id, publisher, hash, message, encrypted) \
+			SELECT ?1, ?2, ?3, ?4, ?5, ?6 WHERE (SELECT COUNT(*) FROM pending_event WHERE channel_id = ?1 AND id = ?2) < ?7",
+			params![self.id, id as i64, publisher, hash.to_string(), self.base.storage_key.seal( message, encrypt ), encrypt, PENDING_EVENT_MAX_COPIES as i64],
+			|affected| Ok( affected > 0 )
+		).await? )
+	}
+
+	/// Loads the messages that have arrived for the event with the given id, but haven't been applied yet, in the order in which they arrived.
+	/// Like with `load_events`, they are in the form in which they are broadcasted.
+	pub async fn load_pending_events( &self, id: u64 ) -> Result<Vec<Vec<u8>>> {
+
+		Ok( self.base.query("SELECT publisher, message, encrypted FROM pending_event WHERE channel_id = ? AND id = ? ORDER BY ROWID",
+			params![self.id, id as i64],
+			|con, rows| Ok( rows.map(|row| {
+				let address: Option<String> = row.get(0)?;
+				let stored = at_rest::read_blob( con, row, 1, 2 )?;
+				Ok( broadcast_form( id, address, &stored ) )
+			}).collect()? )
+		).await? )
+	}
+
+	/// Loads the ids of the events in the range `from_id..(from_id + count)` that have arrived, but are still waiting for the ones preceding them, ordered by id.
+	pub async fn load_pending_event_ids( &self, from_id: u64, count: u16 ) -> Result<Vec<u64>> {
+		let end_id = from_id + count as u64;
+
+		let ids: Vec<i64> = self.base.query("SELECT DISTINCT id FROM pending_event WHERE channel_id = ?1 AND id >= ?2 AND id < ?3 ORDER BY 1",
+			params![self.id, from_id as i64, end_id as i64],
+			|_, rows| Ok( rows.map(|row| row.get(0)).collect()? )
+		).await?;
+
+		Ok( ids.into_iter().map(|i| i as _).collect() )
+	}
+
+	/// Drops the messages that are waiting to be applied with ids up to the given one, as that event has been applied.
+	pub async fn remove_pending_events( &self, up_to_id: u64 ) -> Result<()> {
+
+		self.base.execute("DELETE FROM pending_event WHERE channel_id = ? AND id <= ?",
+			params![self.id, up_to_id as i64],
+			|_| Ok(())
+		).await?;
+
+		Ok(())
+	}
+
+	/// Loads the parameters from the genesis event of the channel, if we have it.
+	pub async fn load_parameters( &self ) -> Result<Option<ChannelCreateEventData>> {
+
+		let parameters: Option<Option<(bool, i64)>> = self.base.query_one("SELECT public, requested_replication_time FROM channel WHERE ROWID = ?",
+			params![self.id],
+			|_, row| {
+				let public: Option<bool> = row.get(0)?;
+				let time: Option<i64> = row.get(1)?;
+				Ok( public.and_then(|p| time.map(|t| (p, t))) )
+			}
+		).await?;
+
+		Ok( parameters.flatten().map(|(public, time)| ChannelCreateEventData {
+			public,
+			requested_replication_time: time as _
+		}))
+	}
+
+	/// Whether the channel is known to be private, in which case its content is encrypted at rest.
+	/// As long as the genesis event hasn't been received, it isn't known.
+	pub async fn is_private( &self ) -> Result<bool> {
+
+		let public: Option<Option<bool>> = self.base.query_one("SELECT public FROM channel WHERE ROWID = ?",
+			params![self.id],
+			|_, row| row.get(0)
+		).await?;
+		Ok( public.flatten() == Some( false ) )
+	}
+
+	/// Stores the parameters from the genesis event of the channel.
+	/// If the channel turns out to be private, whatever has been stored for it before is encrypted.
+	pub async fn store_parameters( &self, parameters: &ChannelCreateEventData ) -> Result<()> {
+
+		self.base.execute_one("UPDATE channel SET public = ?, requested_replication_time = ? WHERE ROWID = ?",
+			params![parameters.public, parameters.requested_replication_time as i64, self.id]
+		).await?;
+		if !parameters.public {
+			self.encrypt_stored().await?;
+		}
+		self.record_system_post( SystemPostKind::ChannelCreated, None ).await?;
+
+		Ok(())
+	}
+
+	/// Loads the invite code of the channel, if it is a private channel that we've been invited to.
+	pub async fn load_invite_code( &self ) -> Result<Option<InviteCode>> {
+
+		let code: Option<Option<String>> = self.base.query_one("SELECT invite_code FROM channel WHERE ROWID = ?",
+			params![self.id],
+			|_, row| row.get(0)
+		).await?;
+
+		Ok( code.flatten().map(|c| InviteCode::from_string( &c ).expect("invalid invite code")) )
+	}
+
+	/// Loads the key with which the messages of the channel are encrypted, or `None` if we don't know the channel to be private.
+	pub async fn load_key( &self ) -> Result<Option<ChannelKey>> {
+
+		let code = match self.load_invite_code().await? {
+			None => return Ok(None),
+			Some(c) => c
+		};
+		Ok( Some( ChannelKey::derive( &self.load_address().await?, &code ) ) )
+	}
+
+	pub async fn store_invite_code( &self, code: &InviteCode ) -> Result<()> {
+
+		self.base.execute_one("UPDATE channel SET invite_code = ? WHERE ROWID = ?",
+			params![code.to_string(), self.id]
+		).await?;
+
+		Ok(())
+	}
+
+	/// Loads the revision of the publisher list that is in effect, if any has been received.
+	pub async fn load_publisher_list_revision( &self ) -> Result<Option<u32>> {
+
+		let revision: Option<Option<i64>> = self.base.query_one("SELECT publisher_list_revision FROM channel WHERE ROWID = ?",
+			params![self.id],
+			|_, row| row.get(0)
+		).await?;
+
+		Ok( revision.flatten().map(|r| r as _) )
+	}
+
+	/// Replaces the set of publishers of this channel.
+	/// Publishers that are not on the new list are marked as revoked, rather than removed, so that the posts they've made before are kept.
+	pub async fn store_publisher_list( &self, list: &PublisherList ) -> Result<()> {
+
+		let address = self.load_address().await?;
+		let previous = self.list_publishers().await?;
+		for publisher in list.publishers.iter().filter(|p| **p != address && !previous.contains( p )) {
+			self.record_system_post( SystemPostKind::PublisherAdded, Some( publisher ) ).await?;
+		}
+		for publisher in previous.iter().filter(|p| !list.publishers.contains( p )) {
+			self.record_system_post( SystemPostKind::PublisherRevoked, Some( publisher ) ).await?;
+		}
+
+		self.base.execute("UPDATE publisher SET revoked = 1 WHERE channel_id = ?", params![self.id], |_| Ok(()) ).await?;
+
+		for address in &list.publishers {
+			let address = address.to_string();
+			self.base.insert("INSERT OR IGNORE INTO publisher (channel_id, address) VALUES (?,?)", params![self.id, address]).await?;
+			self.base.execute("UPDATE publisher SET revoked = 0 WHERE channel_id = ? AND address = ?", params![self.id, address], |_| Ok(()) ).await?;
+		}
+
+		self.base.execute_one("UPDATE channel SET publisher_list_revision = ? WHERE ROWID = ?",
+			params![list.revision as i64, self.id]
+		).await?;
+
+		Ok(())
+	}
+
+	/// Replaces the publishers of the channel, as one of its owners.
+	/// The new list gets the next revision, and is added to the event log so that it reaches the swarm.
+	/// Returns `Error::NotFound` if the given key doesn't own the channel.
+	pub async fn update_publisher_list( &self, private_key: &PrivateKey, publishers: Vec<PublicKey> ) -> Result<PublisherList> {
+
+		let owner = private_key.extract_public().unwrap();
+		if !self.load_owners().await?.contains( &owner ) {
+			return Err( Error::NotFound )
+		}
+
+		let list = PublisherList {
+			revision: self.load_publisher_list_revision().await?.map(|r| r + 1).unwrap_or(1),
+			publishers
+		};
+		let hash = HashCode::generate_from( &list );
+		let msg = UpdatePublisherListEventMessage {
+			signature: common::sign_hash( private_key, &hash ),
+			hash,
+			list
+		};
+		let mut message = vec![ ChannelEventType::UpdatePublisherList as u8 ];
+		message.extend( bincode::serialize( &msg )? );
+
+		self.base.atomically(async {
+			self.store_publisher_list( &msg.list ).await?;
+			self.log_event( None, &message ).await?;
+			Ok::<(), Error>(())
+		}).await?;
+
+		Ok( msg.list )
+	}
+
+	/// Returns whether the given address is allowed to publish in this channel.
+	/// The owner of the channel always is.
+	pub async fn is_publisher( &self, address: &PublicKey ) -> Result<bool> {
+
+		if *address == self.load_address().await? {
+			return Ok(true)
+		}
+
+		Ok( self.base.query_one("SELECT 1 FROM publisher WHERE channel_id = ? AND address = ? AND revoked = 0",
+			params![self.id, address.to_string()],
+			|_, _| Ok(())
+		).await?.is_some() )
+	}
+
+	/// Loads the time at which the newest post of the channel that may be shown already has been published, of any of its publishers.
+	pub async fn load_last_post_timestamp( &self ) -> Result<Option<u64>> {
+
+		let timestamp: Option<Option<i64>> = self.base.query_one("SELECT MAX(p.publish_timestamp) FROM post p \
+			INNER JOIN publisher pb ON pb.ROWID = p.publisher_id \
+			WHERE pb.channel_id = ? AND COALESCE(p.visible_from, 0) <= ?",
+			params![self.id, now()],
+			|_, row| row.get(0)
+		).await?;
+		Ok( timestamp.flatten().map(|t| t as _) )
+	}
+
+	/// Lists the addresses of the publishers that are currently allowed to publish in this channel, besides its owner.
+	pub async fn list_publishers( &self ) -> Result<Vec<PublicKey>> {
+
+		let address = self.load_address().await?.to_string();
+		Ok( self.base.query("SELECT address FROM publisher WHERE channel_id = ? AND address != ? AND revoked = 0 ORDER BY ROWID",
+			params![self.id, address],
+			|_, rows| Ok( rows.map(|row| {
+				let address: String = row.get(0)?;
+				Ok( PublicKey::from_string( &address ).expect("invalid publisher address") )
+			}).collect()? )
+		).await? )
+	}
+
+	/// Lists the hashes of all files that are attached to the posts of this channel.
+	pub async fn list_attachment_ids( &self ) -> Result<Vec<HashCode>> {
+
+		Ok( self.base.query("SELECT DISTINCT a.file_hash FROM post_attachment a INNER JOIN post p ON p.ROWID = a.post_id \
+			INNER JOIN publisher pb ON pb.ROWID = p.publisher_id WHERE pb.channel_id = ?",
+			params![self.id],
+			|_, rows| Ok( rows.map(|row| {
+				let hash: String = row.get(0)?;
+				Ok( HashCode::from_string( &hash ).expect("invalid hash code") )
+			}).collect()? )
+		).await? )
+	}
+
+	/// Whether the given file is attached to any of the posts of this channel.
+	pub async fn has_attachment( &self, file_hash: &HashCode ) -> Result<bool> {
+
+		Ok( self.base.query_one("SELECT 1 FROM post_attachment a INNER JOIN post p ON p.ROWID = a.post_id \
+			INNER JOIN publisher pb ON pb.ROWID = p.publisher_id WHERE pb.channel_id = ? AND a.file_hash = ? LIMIT 1",
+			params![self.id, file_hash.to_string()],
+			|_, _| Ok(())
+		).await?.is_some() )
+	}
+
+	/// Deletes everything that is stored for this channel: its events, its publishers and their posts, the subscription to it, and the marks of our own publishers.
+	/// The blocks and files are left in place, because they may be shared with other channels, see `purge`.
+	pub async fn delete( &self ) -> Result<()> {
+		// All statements take the id of the channel as their only parameter.
+		const STATEMENTS: &[&str] = &[
+			"DELETE FROM tags WHERE post_id IN (SELECT p.ROWID FROM post p INNER JOIN publisher pb ON pb.ROWID = p.publisher_id WHERE pb.channel_id = ?1)",
+			"DELETE FROM post_search WHERE rowid IN (SELECT p.ROWID FROM post p INNER JOIN publisher pb ON pb.ROWID = p.publisher_id WHERE pb.channel_id = ?1)",
+			"DELETE FROM post_attachment WHERE post_id IN (SELECT p.ROWID FROM post p INNER JOIN publisher pb ON pb.ROWID = p.publisher_id WHERE pb.channel_id = ?1)",
+			"DELETE FROM post_origin WHERE post_id IN (SELECT p.ROWID FROM post p INNER JOIN publisher pb ON pb.ROWID = p.publisher_id WHERE pb.channel_id = ?1)",
+			"DELETE FROM post_reference WHERE post_id IN (SELECT p.ROWID FROM post p INNER JOIN publisher pb ON pb.ROWID = p.publisher_id WHERE pb.channel_id = ?1)",
+			"DELETE FROM comment WHERE post_id IN (SELECT p.ROWID FROM post p INNER JOIN publisher pb ON pb.ROWID = p.publisher_id WHERE pb.channel_id = ?1)",
+			"DELETE FROM reaction WHERE post_id IN (SELECT p.ROWID FROM post p INNER JOIN publisher pb ON pb.ROWID = p.publisher_id WHERE pb.channel_id = ?1)",
+			"DELETE FROM post_excerpt WHERE post_hash IN (SELECT p.hash FROM post p INNER JOIN publisher pb ON pb.ROWID = p.publisher_id WHERE pb.channel_id = ?1)",
+			"DELETE FROM post_revision WHERE post_id IN (SELECT p.ROWID FROM post p INNER JOIN publisher pb ON pb.ROWID = p.publisher_id WHERE pb.channel_id = ?1)",
+			"DELETE FROM post WHERE publisher_id IN (SELECT ROWID FROM publisher WHERE channel_id = ?1)",
+			"DELETE FROM forgotten_post WHERE publisher_id IN (SELECT ROWID FROM publisher WHERE channel_id = ?1)",
+			"DELETE FROM publisher_event WHERE publisher_id IN (SELECT ROWID FROM publisher WHERE channel_id = ?1)",
+			"DELETE FROM outbox WHERE channel_id = ?1",
+			"DELETE FROM draft WHERE publisher_id IN (SELECT ROWID FROM publisher WHERE channel_id = ?1)",
+			"DELETE FROM imported_entry WHERE publisher_id IN (SELECT ROWID FROM publisher WHERE channel_id = ?1)",
+			"DELETE FROM local_publishers WHERE publisher_id IN (SELECT ROWID FROM publisher WHERE channel_id = ?1)",
+			"DELETE FROM publisher WHERE channel_id = ?1",
+			"DELETE FROM channel_event WHERE channel_id = ?1",
+			"DELETE FROM pending_event WHERE channel_id = ?1",
+			"DELETE FROM channel_profile WHERE channel_id = ?1",
+			"DELETE FROM profile_conflict WHERE channel_id = ?1",
+			"DELETE FROM relay_service WHERE channel_id = ?1",
+			"DELETE FROM channel_owner WHERE channel_id = ?1",
+			"DELETE FROM ownership_transfer WHERE channel_id = ?1",
+			"DELETE FROM system_post WHERE channel_id = ?1",
+			"DELETE FROM federation_follower WHERE channel_id = ?1",
+			"DELETE FROM notification WHERE channel_id = ?1",
+			"DELETE FROM pinned_post WHERE channel_id = ?1",
+			"DELETE FROM micropub_token WHERE channel_id = ?1",
+			"DELETE FROM comment_moderation WHERE channel_id = ?1",
+			"DELETE FROM swarm_census WHERE channel_id = ?1",
+			"DELETE FROM subscription_peer WHERE subscription_id IN (SELECT s.id FROM subscription s INNER JOIN channel c ON c.address = s.address WHERE c.id = ?1)",
+			"DELETE FROM subscription WHERE address IN (SELECT address FROM channel WHERE id = ?1)",
+			"DELETE FROM channel WHERE id = ?1"
+		];
+
+		let id = self.id;
+		self.base.transaction(move |tx| {
+			for statement in STATEMENTS {
+				tx.execute( statement, params![id] )?;
+			}
+			// The contents that no post refers to anymore
+			tx.execute("DELETE FROM post_content WHERE ROWID NOT IN \
+				(SELECT content_id FROM post WHERE content_id IS NOT NULL UNION SELECT content_id FROM post_revision)", NO_PARAMS)?;
+			Ok(())
+		}).await?;
+
+		Ok(())
+	}
+
+	/// Stores the message of an applied event with the given id in the event log.
+	/// There is only one event per id, so a message that is stored with the id before is replaced.
 	pub async fn store_event( &self, id: u64, message: &[u8] ) -> Result<()> {
 
-		self.base.insert("INSERT INTO channel_event (id, channel_id, message) VALUES (?,?,?)",
-			params![id as i64, self.id, message]).await?;
+		let encrypt = self.is_private().await?;
+		self.base.insert("INSERT OR REPLACE INTO channel_event (id, channel_id, message, encrypted) VALUES (?,?,?,?)",
+			params![id as i64, self.id, self.base.storage_key.seal( message, encrypt ), encrypt]).await?;
 		Ok(())
 	}
+
+	/// Adds an event of our own to the event log, right after the last event that has been applied, and marks it as applied.
+	/// The event belongs to the given publisher, or to the channel itself if there is none.
+	/// The event is put in the outbox as well, so that it is published to the swarm once we're connected to it.
+	/// Returns the id of the event.
+	pub async fn log_event( &self, publisher: Option<&timeline::Handle>, message: &[u8] ) -> Result<u64> {
+
+		let id = self.load_last_event_id().await?.unwrap_or( GENESIS_EVENT_ID ) + 1;
+		match publisher {
+			None => self.store_event( id, message ).await?,
+			Some(timeline) => timeline.store_event( id, message ).await?
+		}
+		self.store_last_event_id( id ).await?;
+		self.enqueue_event( id, None ).await?;
+		page_cache::PAGES.invalidate( self.id );
+
+		Ok( id )
+	}
+
+	/// Adds an event of our own that changes the post with the given id to the event log, like `log_event` does.
+	/// The post is remembered with the event in the outbox, so that it can be shown that the change hasn't reached the swarm yet.
+	pub async fn log_post_event( &self, timeline: &timeline::Handle, post_id: u64, message: &[u8] ) -> Result<u64> {
+
+		let id = self.log_event( Some( timeline ), message ).await?;
+		self.enqueue_event( id, Some(( timeline, post_id )) ).await?;
+
+		Ok( id )
+	}
+
+	/// Adds the `PublishPost` event for a post that has just been created on one of our own timelines.
+	pub async fn log_new_post( &self, timeline: &timeline::Handle, post: &Post ) -> Result<u64> {
+
+		let mut message = vec![ PublisherEventType::PublishPost as u8 ];
+		message.extend( bincode::serialize( &PublishPostEventData { post: post.clone() } )? );
+
+		self.log_post_event( timeline, post.id, &message ).await
+	}
+
+	/// Adds the `RevisePost` event for a revision of a post of one of our own timelines.
+	pub async fn log_revision( &self, timeline: &timeline::Handle, data: &RevisePostEventData ) -> Result<u64> {
+
+		let mut message = vec![ PublisherEventType::RevisePost as u8 ];
+		message.extend( bincode::serialize( data )? );
+
+		self.log_post_event( timeline, data.post_id, &message ).await
+	}
+
+	/// Revises a post of the given timeline of this channel, and adds the `RevisePost` event to the event log, all in one transaction.
+	/// Returns the data of the event, or `None` if we don't have the post.
+	pub async fn publish_revision( &self, timeline: &timeline::Handle, private_key: &PrivateKey, post_id: u64, content: &str, info: PostInfo ) -> Result<Option<RevisePostEventData>> {
+
+		self.base.atomically(async {
+			let data = match timeline.revise_post( private_key, post_id, content, info ).await? {
+				None => return Ok(None),
+				Some(d) => d
+			};
+			self.log_revision( timeline, &data ).await?;
+
+			Ok::<_, Error>( Some( data ) )
+		}).await
+	}
+
+	/// Adds the `Comment` event for a comment that one of our own egos has made on a post of the given timeline.
+	pub async fn log_comment( &self, timeline: &timeline::Handle, data: &CommentEventData ) -> Result<u64> {
+
+		let mut message = vec![ PublisherEventType::Comment as u8 ];
+		message.extend( bincode::serialize( data )? );
+
+		self.log_post_event( timeline, data.post_id, &message ).await
+	}
+
+	/// Adds the `Reaction` event for a reaction of one of our own egos to a post of the given timeline.
+	pub async fn log_reaction( &self, timeline: &timeline::Handle, data: &ReactionEventData ) -> Result<u64> {
+
+		let mut message = vec![ PublisherEventType::Reaction as u8 ];
+		message.extend( bincode::serialize( data )? );
+
+		self.log_post_event( timeline, data.post_id, &message ).await
+	}
 }
 
 /// Devides the given `data` up into blocks of `BLOCK_LENGTH` length.
 /// The last block may be smaller.
-fn breakup_data<'a>( data: &'a [u8], block_len: usize ) -> Vec<&'a [u8]> {
+pub fn breakup_data<'a>( data: &'a [u8], block_len: usize ) -> Vec<&'a [u8]> {
 	let mut i = 0;
 
 	// Calculate block count
@@ -161,7 +814,21 @@ fn breakup_data<'a>( data: &'a [u8], block_len: usize ) -> Vec<&'a [u8]> {
 	blocks
 }
 
-fn hash_blocks( blocks: &[&[u8]] ) -> Vec<HashCode> {
+/// Puts the id and the type of an event in front of its stored message, which is the form in which it is broadcasted.
+/// The type is given by the address of its publisher, or `None` for events of the channel itself.
+fn broadcast_form( id: u64, publisher: Option<String>, stored: &[u8] ) -> Vec<u8> {
+
+	let event_type = match publisher {
+		None => EventType::Channel,
+		Some(a) => EventType::Publisher( PublicKey::from_string( &a ).expect("invalid publisher address") )
+	};
+	let mut message = bincode::serialize( &id ).unwrap();
+	message.extend( bincode::serialize( &event_type ).unwrap() );
+	message.extend_from_slice( stored );
+	message
+}
+
+pub fn hash_blocks( blocks: &[&[u8]] ) -> Vec<HashCode> {
 
 	let mut results = Vec::with_capacity( blocks.len() );
 
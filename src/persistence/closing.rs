@@ -0,0 +1,93 @@
+//! This module provides the persistence of the end of a channel: its owner closing it, and everything that is stored for it being purged.
+//!
+//! Closing a channel puts a signed `Close` event at the end of its event log, which tells the subscribers that nothing will be published in it anymore.
+//! The subscribers keep the event, so that the peers that missed it still learn about it from them after the owner has deleted the channel.
+//! Purging deletes the rows of the channel like `channel::Handle::delete` does, and the files of its posts along with their blocks, unless another channel uses them as well.
+
+use gnunet::{
+	crypto::HashCode,
+	identity::*
+};
+use rusqlite::params;
+
+use crate::{
+	common,
+	event::{ChannelClosing, ChannelEventType, CloseChannelEventMessage},
+	page_cache,
+	persistence::{
+		channel,
+		peer::now,
+		Error,
+		Result
+	}
+};
+
+
+
+impl channel::Handle {
+
+	/// Loads when the owner has closed the channel, in milliseconds since the UNIX epoch, if it has been closed.
+	pub async fn load_closed_timestamp( &self ) -> Result<Option<u64>> {
+
+		let timestamp: Option<Option<i64>> = self.base.query_one("SELECT closed_timestamp FROM channel WHERE id = ?",
+			params![self.id],
+			|_, row| row.get(0)
+		).await?;
+
+		Ok( timestamp.flatten().map(|t| t as _) )
+	}
+
+	/// Marks the channel as closed.
+	/// A channel is only closed once, so a later closing doesn't change when it was closed.
+	pub async fn store_closing( &self, closing: &ChannelClosing ) -> Result<()> {
+
+		self.base.execute("UPDATE channel SET closed_timestamp = ? WHERE id = ? AND closed_timestamp IS NULL",
+			params![closing.timestamp as i64, self.id],
+			|_| Ok(())
+		).await?;
+		page_cache::PAGES.invalidate( self.id );
+		Ok(())
+	}
+
+	/// Closes the channel as one of its owners, and adds the closing to the event log, so that it reaches the swarm.
+	/// Nothing happens if the channel has been closed already, as its closing is in the event log already.
+	/// Returns `Error::NotFound` if the given key doesn't own the channel.
+	pub async fn close( &self, private_key: &PrivateKey ) -> Result<()> {
+
+		let owner = private_key.extract_public().unwrap();
+		if !self.load_owners().await?.contains( &owner ) {
+			return Err( Error::NotFound )
+		}
+		if self.load_closed_timestamp().await?.is_some() {
+			return Ok(())
+		}
+
+		let closing = ChannelClosing { timestamp: now() as _ };
+		let hash = HashCode::generate_from( &closing );
+		let msg = CloseChannelEventMessage {
+			signature: common::sign_hash( private_key, &hash ),
+			hash,
+			closing
+		};
+		let mut message = vec![ ChannelEventType::Close as u8 ];
+		message.extend( bincode::serialize( &msg )? );
+
+		self.base.atomically(async {
+			self.store_closing( &msg.closing ).await?;
+			self.log_event( None, &message ).await?;
+			Ok::<(), Error>(())
+		}).await?;
+
+		Ok(())
+	}
+
+	/// Deletes everything that is stored for this channel, including the files that are attached to its posts and the blocks that they are made of.
+	/// Files and blocks that another channel uses as well are left in place.
+	/// Returns the number of files and blocks that have been removed.
+	pub async fn purge( &self ) -> Result<(u64, u64)> {
+
+		let files = self.list_attachment_ids().await?;
+		self.delete().await?;
+		self.base.remove_unused_files( &files ).await
+	}
+}
@@ -0,0 +1,264 @@
+//! This module provides the persistence of the comments on posts.
+//!
+//! Comments are stored with the post that they comment on, and are removed together with it.
+//! Unlike posts, they are signed by their author, who doesn't have to be a publisher of the channel.
+//!
+//! Comments of authors that the owner hasn't approved are held until the owner approves them, and only approved comments are shown.
+//! The decisions of the owner reach the subscribers as events, see `persistence::moderation`, but the comments themselves are still passed on in the swarm.
+
+use fallible_iterator::FallibleIterator;
+use gnunet::{
+	crypto::HashCode,
+	identity::*
+};
+use rusqlite::params;
+
+use crate::{
+	common,
+	event::{CommentBody, CommentEventData},
+	persistence::{
+		self,
+		at_rest,
+		channel,
+		peer::now,
+		timeline,
+		Error,
+		Result
+	}
+};
+
+
+
+/// Whether a comment may be shown.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ModerationState {
+	/// The comment waits for the owner of the channel to approve or reject it.
+	Pending = 0,
+	Approved = 1,
+	/// The owner has hidden the comment.
+	/// It is kept, so that it isn't held again when it arrives once more.
+	Hidden = 2,
+	/// The owner has deleted the comment, and its content has been dropped.
+	Deleted = 3
+}
+
+/// A comment, as it is stored locally.
+pub struct StoredComment {
+	pub hash: HashCode,
+	pub author: PublicKey,
+	/// The hash of the comment that this comment replies to, if any.
+	pub reply_to: Option<HashCode>,
+	/// In milliseconds since the UNIX epoch.
+	pub timestamp: u64,
+	pub content: String
+}
+
+/// A comment that waits for the owner of the channel to approve or reject it.
+pub struct HeldComment {
+	/// The publisher of the post that is commented on.
+	pub publisher: PublicKey,
+	pub post_id: u64,
+	pub comment: StoredComment
+}
+
+
+
+fn parse_comment( connection: &persistence::Connection, row: &rusqlite::Row, start: usize ) -> rusqlite::Result<StoredComment> {
+	let hash: String = row.get(start)?;
+	let author: String = row.get(start + 1)?;
+	let reply_to: Option<String> = row.get(start + 2)?;
+	let timestamp: i64 = row.get(start + 3)?;
+
+	Ok( StoredComment {
+		hash: HashCode::from_string( &hash ).expect("invalid hash code"),
+		author: PublicKey::from_string( &author ).expect("invalid author address"),
+		reply_to: reply_to.map(|h| HashCode::from_string( &h ).expect("invalid hash code")),
+		timestamp: timestamp as _,
+		content: at_rest::read_text( connection, row, start + 4 )?
+	})
+}
+
+impl timeline::Handle {
+
+	/// Stores a comment on one of the posts of this timeline.
+	/// The comment should have been validated already.
+	/// Returns whether the comment was new to us, which it isn't if we don't have the post.
+	pub async fn store_comment( &self, data: &CommentEventData ) -> Result<bool> {
+
+		let row_id = match self.load_post_row_id( data.post_id ).await? {
+			None => return Ok(false),
+			Some(id) => id
+		};
+
+		let moderation = self.initial_moderation( &data.author, &data.hash ).await?;
+		let content = if moderation == ModerationState::Deleted { "" } else { &data.comment.content };
+		let private = self.is_private().await?;
+		let changes = self.base.execute("INSERT OR IGNORE INTO comment (post_id, hash, author, signature, reply_to, timestamp, content, moderation) VALUES (?,?,?,?,?,?,?,?)",
+			params![
+				row_id,
+				data.hash.to_string(),
+				data.author.to_string(),
+				bincode::serialize( &data.signature )?,
+				data.comment.reply_to.as_ref().map(|h| h.to_string()),
+				data.comment.timestamp as i64,
+				self.base.storage_key.seal_text( content, private ),
+				moderation as i64
+			],
+			|changes| Ok(changes)
+		).await?;
+
+		Ok( changes > 0 )
+	}
+
+	/// Comments on one of the posts of this timeline, as the owner of the given key, and stores the comment.
+	/// Returns the data of the `Comment` event, or `None` if we don't have the post.
+	pub async fn create_comment( &self, private_key: &PrivateKey, post_id: u64, content: &str, reply_to: Option<HashCode> ) -> Result<Option<CommentEventData>> {
+
+		let post = match self.load_post( post_id ).await? {
+			None => return Ok(None),
+			Some(p) => p
+		};
+
+		let comment = CommentBody {
+			post_hash: post.hash,
+			reply_to,
+			timestamp: now() as _,
+			content: content.to_owned()
+		};
+		let hash = HashCode::generate_from( &comment );
+		let data = CommentEventData {
+			post_id,
+			author: private_key.extract_public().unwrap(),
+			signature: common::sign_hash( private_key, &hash ),
+			hash,
+			comment
+		};
+		self.store_comment( &data ).await?;
+
+		Ok( Some( data ) )
+	}
+
+	/// Decides whether a new comment of the given author is held for moderation.
+	/// A decision that the owner has made about the comment already goes first.
+	/// Otherwise, comments are only held on moderated channels, unless an owner wrote them or has approved their author.
+	async fn initial_moderation( &self, author: &PublicKey, hash: &HashCode ) -> Result<ModerationState> {
+
+		let channel = match self.get_channel().await? {
+			None => return Ok( ModerationState::Approved ),
+			Some(c) => c
+		};
+		if let Some(state) = channel.load_comment_decision( hash ).await? {
+			return Ok( state )
+		}
+		if channel.load_owners().await?.contains( author ) || channel.is_approved_author( author ).await? || !channel.is_moderated().await? {
+			return Ok( ModerationState::Approved )
+		}
+		Ok( ModerationState::Pending )
+	}
+
+	/// Loads the approved comments on the post with the given id, oldest first.
+	pub async fn load_comment_thread( &self, post_id: u64 ) -> Result<Vec<StoredComment>> {
+
+		Ok( self.base.query("SELECT c.hash, c.author, c.reply_to, c.timestamp, c.content FROM comment c INNER JOIN post p ON p.ROWID = c.post_id \
+			WHERE p.publisher_id = ? AND p.id = ? AND c.moderation = ? ORDER BY c.timestamp, c.id",
+			params![self.id, post_id as i64, ModerationState::Approved as i64],
+			|con, rows| Ok( rows.map(|row| parse_comment( con, row, 0 )).collect()? )
+		).await? )
+	}
+
+	/// Returns whether the post with the given id has a comment with the given hash.
+	pub async fn has_comment( &self, post_id: u64, hash: &HashCode ) -> Result<bool> {
+
+		Ok( self.base.query_one("SELECT 1 FROM comment c INNER JOIN post p ON p.ROWID = c.post_id WHERE p.publisher_id = ? AND p.id = ? AND c.hash = ?",
+			params![self.id, post_id as i64, hash.to_string()],
+			|_, _| Ok(())
+		).await?.is_some() )
+	}
+}
+
+impl channel::Handle {
+
+	/// Loads the comments on the posts of this channel that wait for moderation, oldest first.
+	pub async fn list_held_comments( &self ) -> Result<Vec<HeldComment>> {
+
+		Ok( self.base.query("SELECT pb.address, p.id, c.hash, c.author, c.reply_to, c.timestamp, c.content FROM comment c \
+			INNER JOIN post p ON p.ROWID = c.post_id INNER JOIN publisher pb ON pb.ROWID = p.publisher_id \
+			WHERE pb.channel_id = ? AND c.moderation = ? ORDER BY c.timestamp, c.id",
+			params![self.id, ModerationState::Pending as i64],
+			|con, rows| Ok( rows.map(|row| {
+				let publisher: String = row.get(0)?;
+				let post_id: i64 = row.get(1)?;
+				Ok( HeldComment {
+					publisher: PublicKey::from_string( &publisher ).expect("invalid publisher address"),
+					post_id: post_id as _,
+					comment: parse_comment( con, row, 2 )?
+				})
+			}).collect()? )
+		).await? )
+	}
+
+	pub async fn count_held_comments( &self ) -> Result<u64> {
+
+		let count: Option<i64> = self.base.query_one("SELECT COUNT(*) FROM comment c \
+			INNER JOIN post p ON p.ROWID = c.post_id INNER JOIN publisher pb ON pb.ROWID = p.publisher_id \
+			WHERE pb.channel_id = ? AND c.moderation = ?",
+			params![self.id, ModerationState::Pending as i64],
+			|_, row| row.get(0)
+		).await?;
+		Ok( count.unwrap_or(0) as _ )
+	}
+
+	/// Changes the moderation state of a comment on one of the posts of this channel.
+	/// The content of a deleted comment is dropped, so it can't be shown again.
+	/// Returns whether the channel has a comment with the given hash that hasn't been deleted.
+	pub async fn moderate_comment( &self, hash: &HashCode, state: ModerationState ) -> Result<bool> {
+
+		let deleted = state == ModerationState::Deleted;
+		let changes = self.base.execute("UPDATE comment SET moderation = ?, content = CASE WHEN ? THEN '' ELSE content END \
+			WHERE hash = ? AND moderation != ? AND post_id IN \
+			(SELECT p.ROWID FROM post p INNER JOIN publisher pb ON pb.ROWID = p.publisher_id WHERE pb.channel_id = ?)",
+			params![state as i64, deleted, hash.to_string(), ModerationState::Deleted as i64, self.id],
+			|changes| Ok(changes)
+		).await?;
+		Ok( changes > 0 )
+	}
+
+	/// Approves the comments of the given author on the posts of this channel that are held.
+	pub async fn approve_held_comments( &self, author: &PublicKey ) -> Result<()> {
+
+		self.base.execute("UPDATE comment SET moderation = ? WHERE author = ? AND moderation = ? AND post_id IN \
+			(SELECT p.ROWID FROM post p INNER JOIN publisher pb ON pb.ROWID = p.publisher_id WHERE pb.channel_id = ?)",
+			params![ModerationState::Approved as i64, author.to_string(), ModerationState::Pending as i64, self.id],
+			|_| Ok(())
+		).await?;
+		Ok(())
+	}
+
+	/// Returns the author of the comment with the given hash on one of the posts of this channel, if there is such a comment that hasn't been deleted.
+	pub async fn load_comment_author( &self, hash: &HashCode ) -> Result<Option<PublicKey>> {
+
+		let author: Option<String> = self.base.query_one("SELECT c.author FROM comment c \
+			INNER JOIN post p ON p.ROWID = c.post_id INNER JOIN publisher pb ON pb.ROWID = p.publisher_id \
+			WHERE pb.channel_id = ? AND c.hash = ? AND c.moderation != ?",
+			params![self.id, hash.to_string(), ModerationState::Deleted as i64],
+			|_, row| row.get(0)
+		).await?;
+
+		Ok( author.map(|a| PublicKey::from_string( &a ).expect("invalid author address")) )
+	}
+
+	/// Comments on a post of the given timeline of this channel, and adds the `Comment` event to the event log, all in one transaction.
+	/// Returns the data of the event, or `None` if we don't have the post.
+	pub async fn post_comment( &self, timeline: &timeline::Handle, private_key: &PrivateKey, post_id: u64, content: &str, reply_to: Option<HashCode> ) -> Result<Option<CommentEventData>> {
+
+		self.base.atomically(async {
+			let data = match timeline.create_comment( private_key, post_id, content, reply_to ).await? {
+				None => return Ok(None),
+				Some(d) => d
+			};
+			self.log_comment( timeline, &data ).await?;
+
+			Ok::<_, Error>( Some( data ) )
+		}).await
+	}
+}
@@ -0,0 +1,74 @@
+//! This module provides the persistence of the settings with which our own channels are advertised in the channel directory.
+//!
+//! A channel is only advertised once its owner has opted in, by giving the keywords that it can be found by.
+//! The keywords are stored separated by spaces, as they are split at whitespace when they are given.
+
+use fallible_iterator::FallibleIterator;
+use gnunet::identity::PublicKey;
+use rusqlite::{NO_PARAMS, params};
+
+use crate::persistence::{
+	self,
+	channel,
+	Result
+};
+
+
+
+/// A channel of one of our egos that is advertised in the directory.
+pub struct DirectoryEntry {
+	pub ego: String,
+	pub address: PublicKey,
+	pub keywords: Vec<String>
+}
+
+
+
+fn split_keywords( keywords: &str ) -> Vec<String> {
+	keywords.split_whitespace().map(|k| k.to_owned()).collect()
+}
+
+impl channel::Handle {
+
+	/// Loads the keywords with which the channel is advertised in the directory, or `None` if it isn't advertised.
+	pub async fn load_directory_keywords( &self ) -> Result<Option<Vec<String>>> {
+
+		let keywords: Option<Option<String>> = self.base.query_one("SELECT directory_keywords FROM channel WHERE id = ?",
+			params![self.id],
+			|_, row| row.get(0)
+		).await?;
+
+		Ok( keywords.flatten().map(|k| split_keywords( &k )) )
+	}
+
+	/// Sets the keywords with which the channel is advertised in the directory, or stops advertising it with `None`.
+	pub async fn store_directory_keywords( &self, keywords: Option<&[String]> ) -> Result<()> {
+
+		self.base.execute_one("UPDATE channel SET directory_keywords = ? WHERE id = ?",
+			params![keywords.map(|k| k.join(" ")), self.id]
+		).await?;
+		Ok(())
+	}
+}
+
+impl persistence::Handle {
+
+	/// Lists the channels of our own egos that are advertised in the directory.
+	pub async fn list_directory_entries( &self ) -> Result<Vec<DirectoryEntry>> {
+
+		Ok( self.query("SELECT l.ego, c.address, c.directory_keywords FROM channel c \
+			INNER JOIN publisher p ON p.channel_id = c.id AND p.address = c.address \
+			INNER JOIN local_publishers l ON l.publisher_id = p.id \
+			WHERE c.directory_keywords IS NOT NULL", NO_PARAMS,
+			|_, rows| Ok( rows.map(|row| {
+				let address: String = row.get(1)?;
+				let keywords: String = row.get(2)?;
+				Ok( DirectoryEntry {
+					ego: row.get(0)?,
+					address: PublicKey::from_string( &address ).expect("invalid channel address"),
+					keywords: split_keywords( &keywords )
+				})
+			}).collect()? )
+		).await? )
+	}
+}
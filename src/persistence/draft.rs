@@ -0,0 +1,176 @@
+//! This module provides the persistence of drafts, which are posts that our own egos have written but haven't published yet.
+//!
+//! A draft isn't signed and isn't part of the event log, so nobody else knows about it.
+//! It only becomes a post when it is published, either by hand or once the time that it has been scheduled for has passed.
+
+use std::convert::TryFrom;
+
+use fallible_iterator::FallibleIterator;
+use gnunet::{
+	crypto::HashCode,
+	identity::*
+};
+use rusqlite::params;
+
+use crate::{
+	persistence::{
+		self,
+		channel,
+		peer::now,
+		timeline,
+		Error,
+		Result
+	},
+	post::*
+};
+
+
+
+pub struct Draft {
+	pub id: i64,
+	pub content: String,
+	/// The information that the post will be published with.
+	/// Its publish timestamp is the time at which the draft was saved, until it is published.
+	pub info: PostInfo,
+	pub attachment_ids: Vec<HashCode>,
+	/// The time at which the draft will be published, in milliseconds since the UNIX epoch, or `None` if it is only published by hand.
+	pub scheduled_at: Option<u64>
+}
+
+/// A draft of which the scheduled time has passed.
+pub struct DueDraft {
+	pub draft_id: i64,
+	/// The address of the publisher that the draft has been written for.
+	pub publisher: PublicKey,
+	/// The name of the ego that can sign for the publisher.
+	pub ego: String
+}
+
+
+
+fn parse_row( row: &rusqlite::Row ) -> rusqlite::Result<Draft> {
+	let tags: String = row.get(2)?;
+	let format: u8 = row.get(3)?;
+	let visible_from: Option<i64> = row.get(4)?;
+	let attachment_ids: Vec<u8> = row.get(7)?;
+	let created: i64 = row.get(8)?;
+	let scheduled_at: Option<i64> = row.get(9)?;
+
+	Ok( Draft {
+		id: row.get(0)?,
+		content: row.get(1)?,
+		info: PostInfo {
+			publish_timestamp: created as _,
+			tags: tags.split_whitespace().map(|t| t.to_owned()).collect(),
+			visible_from: visible_from.map(|t| t as _),
+			format: ContentFormat::try_from( format ).expect("invalid content format"),
+			series: row.get(5)?,
+			content_warning: row.get(6)?
+		},
+		attachment_ids: bincode::deserialize( &attachment_ids ).expect("invalid attachment ids"),
+		scheduled_at: scheduled_at.map(|t| t as _)
+	})
+}
+
+impl timeline::Handle {
+
+	/// Saves a draft for this publisher.
+	/// The publish timestamp of `info` is ignored, as a draft gets its publish timestamp when it is published.
+	/// Returns the id of the draft.
+	pub async fn store_draft( &self, content: &str, info: &PostInfo, attachment_ids: &[HashCode], scheduled_at: Option<u64> ) -> Result<i64> {
+
+		Ok( self.base.insert("INSERT INTO draft (publisher_id, content, tags, format, visible_from, series, content_warning, attachment_ids, created, scheduled_at) VALUES (?,?,?,?,?,?,?,?,?,?)",
+			params![
+				self.id,
+				content,
+				info.tags.join(" "),
+				info.format as u8,
+				info.visible_from.map(|t| t as i64),
+				info.series,
+				info.content_warning,
+				bincode::serialize( attachment_ids )?,
+				now(),
+				scheduled_at.map(|t| t as i64)
+			]
+		).await? )
+	}
+
+	/// Lists the drafts of this publisher, the ones that are scheduled first, in the order in which they will be published.
+	pub async fn list_drafts( &self ) -> Result<Vec<Draft>> {
+
+		Ok( self.base.query("SELECT id, content, tags, format, visible_from, series, content_warning, attachment_ids, created, scheduled_at FROM draft \
+			WHERE publisher_id = ? ORDER BY scheduled_at IS NULL, scheduled_at, created",
+			params![self.id],
+			|_, rows| Ok( rows.map(|row| parse_row( row )).collect()? )
+		).await? )
+	}
+
+	pub async fn load_draft( &self, draft_id: i64 ) -> Result<Option<Draft>> {
+
+		Ok( self.base.query_one("SELECT id, content, tags, format, visible_from, series, content_warning, attachment_ids, created, scheduled_at FROM draft \
+			WHERE publisher_id = ? AND id = ?",
+			params![self.id, draft_id],
+			|_, row| parse_row( row )
+		).await? )
+	}
+
+	/// Removes a draft without publishing it.
+	/// Returns whether there was such a draft.
+	pub async fn delete_draft( &self, draft_id: i64 ) -> Result<bool> {
+
+		let changes = self.base.execute("DELETE FROM draft WHERE publisher_id = ? AND id = ?",
+			params![self.id, draft_id],
+			|changes| Ok(changes)
+		).await?;
+		Ok( changes > 0 )
+	}
+}
+
+impl channel::Handle {
+
+	/// Signs and publishes a draft of the given timeline of this channel, and adds the `PublishPost` event to the event log, all in one transaction.
+	/// The post is published as of now, and the draft is removed.
+	/// Returns the post, or `None` if there is no such draft.
+	pub async fn publish_draft( &self, timeline: &timeline::Handle, private_key: &PrivateKey, draft_id: i64 ) -> Result<Option<Post>> {
+
+		self.base.atomically(async {
+			let draft = match timeline.load_draft( draft_id ).await? {
+				None => return Ok(None),
+				Some(d) => d
+			};
+
+			let info = PostInfo {
+				publish_timestamp: now() as _,
+				..draft.info
+			};
+			let (_, post) = timeline.create_post( private_key, &draft.content, info, draft.attachment_ids, None ).await?;
+			self.log_new_post( timeline, &post ).await?;
+			timeline.delete_draft( draft_id ).await?;
+
+			Ok::<_, Error>( Some( post ) )
+		}).await
+	}
+}
+
+impl persistence::Handle {
+
+	/// Lists the drafts that have been scheduled at or before the given time, in milliseconds since the UNIX epoch, oldest first.
+	/// Drafts of publishers that none of our egos can sign for anymore are left out.
+	pub async fn list_due_drafts( &self, until: u64 ) -> Result<Vec<DueDraft>> {
+
+		Ok( self.query("SELECT d.id, p.address, lp.ego FROM draft d \
+			INNER JOIN publisher p ON p.id = d.publisher_id \
+			INNER JOIN local_publishers lp ON lp.publisher_id = d.publisher_id \
+			WHERE d.scheduled_at <= ? AND lp.ego IS NOT NULL ORDER BY d.scheduled_at, d.id",
+			params![until as i64],
+			|_, rows| Ok( rows.map(|row| {
+				let address: String = row.get(1)?;
+				Ok( DueDraft {
+					draft_id: row.get(0)?,
+					publisher: PublicKey::from_string( &address ).expect("invalid publisher address"),
+					ego: row.get(2)?
+				})
+			}).collect()? )
+		).await? )
+	}
+}
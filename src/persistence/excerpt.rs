@@ -0,0 +1,42 @@
+//! This module provides the persistence of the plain text excerpts of posts, so that their content doesn't have to be rendered every time one is shown.
+//!
+//! Like the previews in memory, the excerpts are kept by the hash of the post and the number of its revision, so a revised post gets a new one.
+//! The excerpts of the posts of private channels are encrypted, like their content.
+
+use gnunet::crypto::HashCode;
+use rusqlite::params;
+
+use crate::persistence::{
+	self,
+	at_rest,
+	Result
+};
+
+
+
+impl persistence::Handle {
+
+	/// Loads the excerpt of the given revision of a post, if it has been made before.
+	pub async fn load_excerpt( &self, post_hash: &HashCode, revision: u32 ) -> Result<Option<String>> {
+
+		Ok( self.query_one("SELECT excerpt FROM post_excerpt WHERE post_hash = ? AND revision = ?",
+			params![post_hash.to_string(), revision],
+			|con, row| at_rest::read_text( con, row, 0 )
+		).await? )
+	}
+
+	/// Stores the excerpt of a revision of a post, and forgets the ones of its older revisions.
+	/// The excerpt is encrypted if `encrypt` is set, which it should be for the posts of private channels.
+	pub async fn store_excerpt( &self, post_hash: &HashCode, revision: u32, excerpt: &str, encrypt: bool ) -> Result<()> {
+
+		let post_hash = post_hash.to_string();
+		let excerpt = self.storage_key.seal_text( excerpt, encrypt );
+		self.atomically(async {
+			self.execute("DELETE FROM post_excerpt WHERE post_hash = ? AND revision < ?", params![post_hash, revision], |_| Ok(()) ).await?;
+			self.insert("INSERT OR REPLACE INTO post_excerpt (post_hash, revision, excerpt) VALUES (?,?,?)",
+				params![post_hash, revision, excerpt]
+			).await?;
+			Ok::<(), persistence::Error>(())
+		}).await
+	}
+}
@@ -0,0 +1,129 @@
+//! This module provides the persistence of the federation of channels with ActivityPub servers.
+//!
+//! Accounts on those servers can follow a channel, after which the new posts of its owner are delivered to their inboxes.
+//! Every channel signs what it sends with a key of its own, which is generated the first time that it is needed.
+
+use fallible_iterator::FallibleIterator;
+use gnunet::identity::PublicKey;
+use rusqlite::{NO_PARAMS, params};
+
+use crate::persistence::{
+	self,
+	channel,
+	peer::now,
+	Result
+};
+
+
+
+/// An account on an ActivityPub server that follows a channel.
+pub struct Follower {
+	/// The id of the actor of the account.
+	pub actor: String,
+	/// Where the activities of the channel are delivered to.
+	pub inbox: String,
+	/// In milliseconds since the UNIX epoch.
+	pub followed: u64
+}
+
+
+
+impl channel::Handle {
+
+	/// Loads the private key that the channel signs its activities with, in PEM format, if it has one.
+	pub async fn load_federation_key( &self ) -> Result<Option<String>> {
+
+		let key: Option<Option<String>> = self.base.query_one("SELECT federation_key FROM channel WHERE id = ?",
+			params![self.id],
+			|_, row| row.get(0)
+		).await?;
+		Ok( key.flatten() )
+	}
+
+	/// Stores the private key that the channel signs its activities with, unless it already has one.
+	/// Returns whether the key was stored.
+	pub async fn store_federation_key( &self, pem: &str ) -> Result<bool> {
+
+		Ok( self.base.execute("UPDATE channel SET federation_key = ? WHERE id = ? AND federation_key IS NULL",
+			params![pem, self.id],
+			|count| Ok( count > 0 )
+		).await? )
+	}
+
+	/// Adds a follower of the channel, or updates its inbox if it was following already.
+	/// The posts from `next_post_id` onwards are delivered to the followers, unless the channel had followers before.
+	pub async fn add_follower( &self, actor: &str, inbox: &str, next_post_id: u64 ) -> Result<()> {
+
+		let id = self.id;
+		let actor = actor.to_owned();
+		let inbox = inbox.to_owned();
+		self.base.transaction(move |tx| {
+			tx.execute("UPDATE channel SET federation_next_post_id = COALESCE(federation_next_post_id, ?) WHERE id = ?",
+				params![next_post_id as i64, id]
+			)?;
+			tx.execute("INSERT OR REPLACE INTO federation_follower (channel_id, actor, inbox, followed) VALUES (?,?,?,?)",
+				params![id, actor, inbox, now()]
+			)?;
+			Ok(())
+		}).await?;
+		Ok(())
+	}
+
+	/// Removes a follower of the channel.
+	/// Returns whether it was following.
+	pub async fn remove_follower( &self, actor: &str ) -> Result<bool> {
+
+		Ok( self.base.execute("DELETE FROM federation_follower WHERE channel_id = ? AND actor = ?",
+			params![self.id, actor],
+			|count| Ok( count > 0 )
+		).await? )
+	}
+
+	/// Lists the followers of the channel, the earliest first.
+	pub async fn list_followers( &self ) -> Result<Vec<Follower>> {
+
+		Ok( self.base.query("SELECT actor, inbox, followed FROM federation_follower WHERE channel_id = ? ORDER BY followed",
+			params![self.id],
+			|_, rows| Ok( rows.map(|row| {
+				let followed: i64 = row.get(2)?;
+				Ok( Follower {
+					actor: row.get(0)?,
+					inbox: row.get(1)?,
+					followed: followed as _
+				})
+			}).collect()? )
+		).await? )
+	}
+
+	/// Loads the id of the first post of the owner that hasn't been delivered to the followers yet.
+	pub async fn load_federation_next_post_id( &self ) -> Result<Option<u64>> {
+
+		let id: Option<Option<i64>> = self.base.query_one("SELECT federation_next_post_id FROM channel WHERE id = ?",
+			params![self.id],
+			|_, row| row.get(0)
+		).await?;
+		Ok( id.flatten().map(|i| i as _) )
+	}
+
+	pub async fn store_federation_next_post_id( &self, id: u64 ) -> Result<()> {
+
+		self.base.execute_one("UPDATE channel SET federation_next_post_id = ? WHERE id = ?",
+			params![id as i64, self.id]
+		).await?;
+		Ok(())
+	}
+}
+
+impl persistence::Handle {
+
+	/// Lists the addresses of the channels that have followers on ActivityPub servers.
+	pub async fn list_federated_channels( &self ) -> Result<Vec<PublicKey>> {
+
+		let addresses: Vec<String> = self.query("SELECT DISTINCT c.address FROM channel c INNER JOIN federation_follower f ON f.channel_id = c.id",
+			NO_PARAMS,
+			|_, rows| Ok( rows.map(|row| row.get(0)).collect()? )
+		).await?;
+
+		Ok( addresses.iter().filter_map(|a| PublicKey::from_string( a )).collect() )
+	}
+}
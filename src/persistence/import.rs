@@ -0,0 +1,71 @@
+//! This module provides the persistence of posts that are imported from elsewhere, like the feed of an old blog.
+//!
+//! The entries that have been imported are remembered by their id in the feed, so that importing the same feed again only adds the new entries.
+//! Every imported post results in a `PublishPost` event, and all of them are added in a single transaction: either the whole import succeeds, or nothing is imported.
+
+use gnunet::identity::PrivateKey;
+use rusqlite::params;
+
+use crate::{
+	persistence::{
+		channel,
+		Error,
+		Result
+	},
+	post::PostInfo
+};
+
+
+
+/// An entry of a feed, in the form of a post.
+pub struct ImportedEntry {
+	/// The id of the entry in the feed, which tells whether it has been imported before.
+	pub entry_id: String,
+	pub content: String,
+	pub info: PostInfo
+}
+
+/// What an import has done.
+pub struct ImportReport {
+	/// The number of entries that have been published as posts.
+	pub imported: usize,
+	/// The number of entries that had been imported before.
+	pub skipped: usize
+}
+
+
+
+impl channel::Handle {
+
+	/// Publishes the entries as posts on the timeline of the given publisher, in the given order.
+	/// Entries that have been imported on that timeline before are skipped.
+	pub async fn import_entries( &self, private_key: &PrivateKey, entries: &[ImportedEntry] ) -> Result<ImportReport> {
+
+		let address = private_key.extract_public().unwrap();
+		let timeline = self.get_timeline( &address ).await?.ok_or( Error::NotFound )?;
+
+		self.base.atomically(async {
+			let mut report = ImportReport { imported: 0, skipped: 0 };
+
+			for entry in entries {
+				let imported: Option<i64> = self.base.query_one("SELECT post_id FROM imported_entry WHERE publisher_id = ? AND entry_id = ?",
+					params![timeline.id, entry.entry_id],
+					|_, row| row.get(0)
+				).await?;
+				if imported.is_some() {
+					report.skipped += 1;
+					continue
+				}
+
+				let (_, post) = timeline.create_post( private_key, &entry.content, entry.info.clone(), Vec::new(), None ).await?;
+				self.log_new_post( &timeline, &post ).await?;
+				self.base.insert("INSERT INTO imported_entry (publisher_id, entry_id, post_id) VALUES (?,?,?)",
+					params![timeline.id, entry.entry_id, post.id as i64]
+				).await?;
+				report.imported += 1;
+			}
+
+			Ok::<ImportReport, Error>( report )
+		}).await
+	}
+}
@@ -0,0 +1,96 @@
+//! This module provides the upkeep of the database itself: checking its integrity, updating the statistics of the query planner, and returning free pages to the file system.
+//!
+//! Deleting data, like the garbage collection does, leaves free pages behind in the database file.
+//! They are reused before the file grows again, but with incremental vacuuming they can be given back as well.
+//! Databases that were created without it are converted once, with a full `VACUUM`, which rewrites the whole file and can take a while.
+//! None of this may run within a transaction, so it should be done on a connection of its own.
+
+use fallible_iterator::FallibleIterator;
+use rusqlite::NO_PARAMS;
+
+use crate::persistence::{
+	self,
+	Result
+};
+
+
+
+/// The value of `PRAGMA auto_vacuum` for incremental vacuuming.
+const AUTO_VACUUM_INCREMENTAL: i64 = 2;
+
+/// What a round of maintenance has found and done.
+#[derive(Default)]
+pub struct MaintenanceReport {
+	/// The problems that the integrity check has found, which is empty for a healthy database.
+	pub integrity_errors: Vec<String>,
+	/// The number of bytes that have been given back to the file system.
+	pub freed_bytes: u64,
+	/// Whether the database has been converted to incremental vacuuming, with a full vacuum.
+	pub converted: bool
+}
+
+
+
+impl persistence::Handle {
+
+	/// Runs all maintenance: checks the integrity first, as there is no point in optimizing a database that is corrupt.
+	pub async fn maintain( &self ) -> Result<MaintenanceReport> {
+
+		let integrity_errors = self.check_integrity().await?;
+		if !integrity_errors.is_empty() {
+			return Ok( MaintenanceReport { integrity_errors, ..MaintenanceReport::default() } )
+		}
+
+		self.analyze().await?;
+		let (freed_bytes, converted) = self.incremental_vacuum().await?;
+		Ok( MaintenanceReport {
+			integrity_errors,
+			freed_bytes,
+			converted
+		})
+	}
+
+	/// Returns the problems that SQLite finds in the database, if any.
+	pub async fn check_integrity( &self ) -> Result<Vec<String>> {
+
+		let messages: Vec<String> = self.query("PRAGMA integrity_check", NO_PARAMS,
+			|_, rows| rows.map(|row| row.get(0)).collect()
+		).await?;
+
+		Ok( messages.into_iter().filter(|m| m != "ok").collect() )
+	}
+
+	/// Updates the statistics that the query planner chooses its indexes by.
+	pub async fn analyze( &self ) -> Result<()> {
+		self.execute_batch("ANALYZE").await?;
+		Ok(())
+	}
+
+	/// Gives the free pages of the database back to the file system, and returns how many bytes that were.
+	/// Also returns whether the database had to be converted to incremental vacuuming first.
+	pub async fn incremental_vacuum( &self ) -> Result<(u64, bool)> {
+
+		let free_before = self.load_free_bytes().await?;
+
+		let auto_vacuum: i64 = self.query_one("PRAGMA auto_vacuum", NO_PARAMS, |_, row| row.get(0) ).await?.unwrap_or(0);
+		let converted = auto_vacuum != AUTO_VACUUM_INCREMENTAL;
+		if converted {
+			// The setting only takes effect for an existing database when it is vacuumed right after.
+			self.execute_batch("PRAGMA auto_vacuum = INCREMENTAL; VACUUM").await?;
+		}
+		else {
+			self.execute_batch("PRAGMA incremental_vacuum").await?;
+		}
+
+		let free_after = self.load_free_bytes().await?;
+		Ok(( free_before.saturating_sub( free_after ), converted ))
+	}
+
+	/// The number of bytes in the pages that are free.
+	async fn load_free_bytes( &self ) -> Result<u64> {
+
+		let free_pages: i64 = self.query_one("PRAGMA freelist_count", NO_PARAMS, |_, row| row.get(0) ).await?.unwrap_or(0);
+		let page_size: i64 = self.query_one("PRAGMA page_size", NO_PARAMS, |_, row| row.get(0) ).await?.unwrap_or(0);
+		Ok( (free_pages * page_size) as _ )
+	}
+}
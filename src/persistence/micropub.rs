@@ -0,0 +1,88 @@
+//! This module provides the persistence of the tokens with which Micropub clients publish in our own channels.
+//!
+//! A token belongs to a channel rather than to the name of its ego, so that it keeps working when the ego is renamed.
+//! Only the hash of a token is stored, so the token itself is only shown once, when it is created.
+
+use fallible_iterator::FallibleIterator;
+use gnunet::identity::PublicKey;
+use rand::{distributions::Alphanumeric, Rng};
+use rusqlite::params;
+use sha2::{Digest, Sha256};
+
+use crate::persistence::{
+	self,
+	channel,
+	peer::now,
+	Result
+};
+
+
+
+/// The number of characters of a token.
+const TOKEN_LENGTH: usize = 40;
+
+pub struct MicropubToken {
+	pub id: i64,
+	/// What the administrator has called the token, like the name of the client that uses it.
+	pub name: String,
+	/// In milliseconds since the UNIX epoch.
+	pub created: u64
+}
+
+
+
+fn hash_token( token: &str ) -> String {
+	Sha256::digest( token.as_bytes() ).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl channel::Handle {
+
+	/// Creates a token with which Micropub clients can publish in this channel, and returns it.
+	pub async fn create_micropub_token( &self, name: &str ) -> Result<String> {
+
+		let token: String = rand::thread_rng().sample_iter( &Alphanumeric ).take( TOKEN_LENGTH ).map( char::from ).collect();
+		self.base.insert("INSERT INTO micropub_token (channel_id, name, token_hash, created) VALUES (?,?,?,?)",
+			params![self.id, name, hash_token( &token ), now()]
+		).await?;
+
+		Ok( token )
+	}
+
+	pub async fn list_micropub_tokens( &self ) -> Result<Vec<MicropubToken>> {
+
+		Ok( self.base.query("SELECT id, name, created FROM micropub_token WHERE channel_id = ? ORDER BY id",
+			params![self.id],
+			|_, rows| rows.map(|row| {
+				let created: i64 = row.get(2)?;
+				Ok( MicropubToken {
+					id: row.get(0)?,
+					name: row.get(1)?,
+					created: created as _
+				})
+			}).collect()
+		).await? )
+	}
+
+	/// Revokes the token with the given id.
+	/// Returns false if this channel has no such token.
+	pub async fn revoke_micropub_token( &self, id: i64 ) -> Result<bool> {
+
+		Ok( self.base.execute("DELETE FROM micropub_token WHERE id = ? AND channel_id = ?", params![id, self.id],
+			|affected| Ok( affected > 0 )
+		).await? )
+	}
+}
+
+impl persistence::Handle {
+
+	/// Returns the address of the channel that the given token publishes in, if it is a valid token.
+	pub async fn find_micropub_token( &self, token: &str ) -> Result<Option<PublicKey>> {
+
+		let address: Option<String> = self.query_one("SELECT c.address FROM micropub_token t INNER JOIN channel c ON c.id = t.channel_id WHERE t.token_hash = ?",
+			params![hash_token( token )],
+			|_, row| row.get(0)
+		).await?;
+
+		Ok( address.map(|a| PublicKey::from_string( &a ).expect("invalid channel address")) )
+	}
+}
@@ -0,0 +1,189 @@
+//! This module provides the persistence of the decisions that the owners of a channel make about the comments on its posts.
+//!
+//! The decisions are spread as signed `ModerateComments` channel events, so that the subscribers show the same comments as the owner does.
+//! Every decision is kept, both for the moderation log of the channel, and because a decision can arrive before the comment that it is about.
+//! Once an owner has made a decision, the comments of the authors that haven't been approved are held on the nodes of the subscribers as well.
+//! Channels that have never been moderated, for instance because their owner runs an older release, show every comment, as nobody would ever approve them.
+
+use std::cmp::max;
+
+use fallible_iterator::FallibleIterator;
+use gnunet::{
+	crypto::HashCode,
+	identity::*
+};
+use rusqlite::params;
+
+use crate::{
+	common,
+	event::{ChannelEventType, CommentModeration, ModerateCommentsEventMessage, ModerationAction},
+	page_cache,
+	persistence::{
+		channel,
+		comment::ModerationState,
+		peer::now,
+		Error,
+		Result
+	}
+};
+
+
+
+/// The numbers under which the actions are stored.
+const ACTION_APPROVE_COMMENT: i64 = 0;
+const ACTION_HIDE_COMMENT: i64 = 1;
+const ACTION_DELETE_COMMENT: i64 = 2;
+const ACTION_APPROVE_AUTHOR: i64 = 3;
+
+/// A decision in the moderation log of a channel.
+pub struct ModerationEntry {
+	/// When the decision was made, in milliseconds since the UNIX epoch.
+	pub timestamp: u64,
+	pub action: ModerationAction
+}
+
+
+
+/// The number under which the action is stored, and the hash of the comment or the address of the author that it is about.
+fn action_key( action: &ModerationAction ) -> (i64, String) {
+	match action {
+		ModerationAction::ApproveComment(hash) => (ACTION_APPROVE_COMMENT, hash.to_string()),
+		ModerationAction::HideComment(hash) => (ACTION_HIDE_COMMENT, hash.to_string()),
+		ModerationAction::DeleteComment(hash) => (ACTION_DELETE_COMMENT, hash.to_string()),
+		ModerationAction::ApproveAuthor(author) => (ACTION_APPROVE_AUTHOR, author.to_string())
+	}
+}
+
+fn parse_action( action: i64, subject: &str ) -> ModerationAction {
+	let hash = || HashCode::from_string( subject ).expect("invalid hash code");
+	match action {
+		ACTION_APPROVE_COMMENT => ModerationAction::ApproveComment( hash() ),
+		ACTION_HIDE_COMMENT => ModerationAction::HideComment( hash() ),
+		ACTION_DELETE_COMMENT => ModerationAction::DeleteComment( hash() ),
+		_ => ModerationAction::ApproveAuthor( PublicKey::from_string( subject ).expect("invalid author address") )
+	}
+}
+
+impl channel::Handle {
+
+	/// Applies a moderation decision, and keeps it.
+	/// Returns false if a later decision about the same comment or author has been made already, in which case nothing changes.
+	pub async fn store_moderation( &self, moderation: &CommentModeration ) -> Result<bool> {
+
+		let (action, subject) = action_key( &moderation.action );
+		if self.load_latest_decision_time( &subject ).await?.map(|t| t >= moderation.timestamp).unwrap_or(false) {
+			return Ok(false)
+		}
+
+		self.base.insert("INSERT INTO comment_moderation (channel_id, action, subject, timestamp) VALUES (?,?,?,?)",
+			params![self.id, action, subject, moderation.timestamp as i64]
+		).await?;
+
+		match &moderation.action {
+			ModerationAction::ApproveComment(hash) => { self.moderate_comment( hash, ModerationState::Approved ).await?; },
+			ModerationAction::HideComment(hash) => { self.moderate_comment( hash, ModerationState::Hidden ).await?; },
+			ModerationAction::DeleteComment(hash) => { self.moderate_comment( hash, ModerationState::Deleted ).await?; },
+			ModerationAction::ApproveAuthor(author) => self.approve_held_comments( author ).await?
+		}
+		page_cache::PAGES.invalidate( self.id );
+
+		Ok(true)
+	}
+
+	/// Makes a moderation decision as one of the owners of the channel, applies it, and adds it to the event log so that it reaches the swarm.
+	/// Returns `Error::NotFound` if the given key doesn't own the channel.
+	pub async fn moderate( &self, private_key: &PrivateKey, action: ModerationAction ) -> Result<CommentModeration> {
+
+		let owner = private_key.extract_public().unwrap();
+		if !self.load_owners().await?.contains( &owner ) {
+			return Err( Error::NotFound )
+		}
+
+		// The decision needs to be later than the last one about the same subject, even when both are made within the same millisecond.
+		let (_, subject) = action_key( &action );
+		let latest = self.load_latest_decision_time( &subject ).await?;
+		let moderation = CommentModeration {
+			timestamp: max( now() as u64, latest.map(|t| t + 1).unwrap_or(0) ),
+			action
+		};
+		let hash = HashCode::generate_from( &moderation );
+		let msg = ModerateCommentsEventMessage {
+			signature: common::sign_hash( private_key, &hash ),
+			hash,
+			moderation
+		};
+		let mut message = vec![ ChannelEventType::ModerateComments as u8 ];
+		message.extend( bincode::serialize( &msg )? );
+
+		self.base.atomically(async {
+			self.store_moderation( &msg.moderation ).await?;
+			self.log_event( None, &message ).await?;
+			Ok::<(), Error>(())
+		}).await?;
+
+		Ok( msg.moderation )
+	}
+
+	/// The state that the latest decision about the comment with the given hash has put it in, if one has been made.
+	pub async fn load_comment_decision( &self, hash: &HashCode ) -> Result<Option<ModerationState>> {
+
+		let action: Option<i64> = self.base.query_one("SELECT action FROM comment_moderation WHERE channel_id = ? AND subject = ? ORDER BY timestamp DESC LIMIT 1",
+			params![self.id, hash.to_string()],
+			|_, row| row.get(0)
+		).await?;
+
+		Ok( action.map(|a| match a {
+			ACTION_HIDE_COMMENT => ModerationState::Hidden,
+			ACTION_DELETE_COMMENT => ModerationState::Deleted,
+			_ => ModerationState::Approved
+		}) )
+	}
+
+	/// Whether the comments of the given author are shown without being held first.
+	pub async fn is_approved_author( &self, author: &PublicKey ) -> Result<bool> {
+
+		Ok( self.base.query_one("SELECT 1 FROM comment_moderation WHERE channel_id = ? AND subject = ? AND action = ?",
+			params![self.id, author.to_string(), ACTION_APPROVE_AUTHOR],
+			|_, _| Ok(())
+		).await?.is_some() )
+	}
+
+	/// Whether the comments of authors that haven't been approved are held, which is the case for the channels that one of our egos owns, and for the channels that have been moderated.
+	pub async fn is_moderated( &self ) -> Result<bool> {
+
+		if self.load_owner_ego().await?.is_some() {
+			return Ok(true)
+		}
+		Ok( self.base.query_one("SELECT 1 FROM comment_moderation WHERE channel_id = ?",
+			params![self.id],
+			|_, _| Ok(())
+		).await?.is_some() )
+	}
+
+	/// Loads the latest moderation decisions of the channel, the newest first.
+	pub async fn list_moderation_log( &self, limit: u32 ) -> Result<Vec<ModerationEntry>> {
+
+		Ok( self.base.query("SELECT action, subject, timestamp FROM comment_moderation WHERE channel_id = ? ORDER BY timestamp DESC, id DESC LIMIT ?",
+			params![self.id, limit],
+			|_, rows| Ok( rows.map(|row| {
+				let subject: String = row.get(1)?;
+				let timestamp: i64 = row.get(2)?;
+				Ok( ModerationEntry {
+					timestamp: timestamp as _,
+					action: parse_action( row.get(0)?, &subject )
+				})
+			}).collect()? )
+		).await? )
+	}
+
+	/// The time of the latest decision about the given comment hash or author address, if one has been made.
+	async fn load_latest_decision_time( &self, subject: &str ) -> Result<Option<u64>> {
+
+		let timestamp: Option<Option<i64>> = self.base.query_one("SELECT MAX(timestamp) FROM comment_moderation WHERE channel_id = ? AND subject = ?",
+			params![self.id, subject],
+			|_, row| row.get(0)
+		).await?;
+
+		Ok( timestamp.flatten().map(|t| t as _) )
+	}
+}
@@ -0,0 +1,166 @@
+//! This module provides the persistence of the notifications, which tell what has happened in the channels that we follow and publish in.
+//!
+//! Notifications are recorded while the events of the swarms are applied, in the same transaction, so that an event that is applied again doesn't notify twice.
+//! They are kept until the channel that they belong to is deleted.
+
+use fallible_iterator::FallibleIterator;
+use gnunet::identity::PublicKey;
+use rusqlite::{NO_PARAMS, params};
+
+use crate::persistence::{
+	self,
+	channel,
+	peer::now,
+	Result
+};
+
+
+
+/// What a notification is about.
+#[derive(Clone, Copy, PartialEq)]
+pub enum NotificationKind {
+	/// A channel that we follow has published a post.
+	Post = 0,
+	/// A channel that we follow has revised a post.
+	Revision = 1,
+	/// Someone has commented on one of our own posts.
+	Comment = 2,
+	/// Someone has mentioned one of our egos in a comment on somebody else's post.
+	Mention = 3
+}
+
+#[derive(Clone)]
+pub struct Notification {
+	pub id: i64,
+	pub kind: NotificationKind,
+	/// The channel in which it happened.
+	pub channel: PublicKey,
+	/// The publisher of the post that it is about.
+	pub publisher: PublicKey,
+	pub post_id: u64,
+	/// The author of the comment, for comments and mentions.
+	pub author: Option<PublicKey>,
+	/// In milliseconds since the UNIX epoch.
+	pub time: u64,
+	pub read: bool
+}
+
+
+
+impl NotificationKind {
+
+	fn from_id( id: i64 ) -> Option<Self> {
+		match id {
+			0 => Some( Self::Post ),
+			1 => Some( Self::Revision ),
+			2 => Some( Self::Comment ),
+			3 => Some( Self::Mention ),
+			_ => None
+		}
+	}
+}
+
+/// Whether the text mentions the ego with the given name, which is done by writing `@` before its name.
+/// Addresses like `name@example.org` aren't mentions, and neither are longer names that start with the name.
+pub fn mentions( text: &str, ego: &str ) -> bool {
+	let mention = format!("@{}", ego);
+	let is_name_char = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+
+	text.match_indices( &mention ).any(|(i, _)| {
+		let before = text[..i].chars().next_back();
+		let after = text[(i + mention.len())..].chars().next();
+		!before.map(is_name_char).unwrap_or(false) && !after.map(is_name_char).unwrap_or(false)
+	})
+}
+
+fn parse_row( row: &rusqlite::Row ) -> rusqlite::Result<Notification> {
+	let kind: i64 = row.get(1)?;
+	let channel: String = row.get(2)?;
+	let publisher: String = row.get(3)?;
+	let post_id: i64 = row.get(4)?;
+	let author: Option<String> = row.get(5)?;
+	let time: i64 = row.get(6)?;
+
+	Ok( Notification {
+		id: row.get(0)?,
+		kind: NotificationKind::from_id( kind ).expect("invalid notification kind"),
+		channel: PublicKey::from_string( &channel ).expect("invalid channel address"),
+		publisher: PublicKey::from_string( &publisher ).expect("invalid publisher address"),
+		post_id: post_id as _,
+		author: author.map(|a| PublicKey::from_string( &a ).expect("invalid author address")),
+		time: time as _,
+		read: row.get(7)?
+	})
+}
+
+impl channel::Handle {
+
+	/// Records a notification about a post of the channel.
+	pub async fn record_notification( &self, kind: NotificationKind, publisher: &PublicKey, post_id: u64, author: Option<&PublicKey> ) -> Result<Notification> {
+
+		let time = now();
+		let id = self.base.insert("INSERT INTO notification (channel_id, kind, publisher, post_id, author, time) VALUES (?,?,?,?,?,?)",
+			params![self.id, kind as i64, publisher.to_string(), post_id as i64, author.map(|a| a.to_string()), time]
+		).await?;
+
+		Ok( Notification {
+			id,
+			kind,
+			channel: self.load_address().await?,
+			publisher: publisher.clone(),
+			post_id,
+			author: author.cloned(),
+			time: time as _,
+			read: false
+		})
+	}
+}
+
+impl persistence::Handle {
+
+	pub async fn count_notifications( &self ) -> Result<u64> {
+
+		let count: i64 = self.query_one("SELECT COUNT(*) FROM notification", NO_PARAMS, |_, row| row.get(0) ).await?.unwrap_or(0);
+		Ok( count as _ )
+	}
+
+	pub async fn count_unread_notifications( &self ) -> Result<u64> {
+
+		let count: i64 = self.query_one("SELECT COUNT(*) FROM notification WHERE read = 0", NO_PARAMS, |_, row| row.get(0) ).await?.unwrap_or(0);
+		Ok( count as _ )
+	}
+
+	/// Loads a page of the notifications, the newest first.
+	pub async fn list_notifications( &self, offset: u64, limit: u32 ) -> Result<Vec<Notification>> {
+
+		Ok( self.query("SELECT n.id, n.kind, c.address, n.publisher, n.post_id, n.author, n.time, n.read FROM notification n \
+			INNER JOIN channel c ON c.id = n.channel_id ORDER BY n.id DESC LIMIT ? OFFSET ?",
+			params![limit, offset as i64],
+			|_, rows| rows.map(|row| parse_row( row )).collect()
+		).await? )
+	}
+
+	/// Marks all notifications as read.
+	pub async fn mark_notifications_read( &self ) -> Result<()> {
+
+		self.execute("UPDATE notification SET read = 1 WHERE read = 0", NO_PARAMS, |_| Ok(()) ).await?;
+		Ok(())
+	}
+
+	/// Whether the publisher with the given address is one of our own.
+	pub async fn is_own_publisher( &self, address: &PublicKey ) -> Result<bool> {
+
+		Ok( self.query_one("SELECT 1 FROM local_publishers l INNER JOIN publisher p ON p.ROWID = l.publisher_id WHERE p.address = ?",
+			params![address.to_string()],
+			|_, _| Ok(())
+		).await?.is_some() )
+	}
+
+	/// Lists the names of the egos that have a channel on this node.
+	pub async fn list_own_ego_names( &self ) -> Result<Vec<String>> {
+
+		Ok( self.query("SELECT ego FROM local_publishers", NO_PARAMS,
+			|_, rows| rows.map(|row| row.get(0)).collect()
+		).await? )
+	}
+}
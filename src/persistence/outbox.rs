@@ -0,0 +1,91 @@
+//! This module provides the persistence of the outbox, which holds the events of our own that haven't reached the swarm yet.
+//!
+//! Every event that is added to the event log locally is put in the outbox as well.
+//! It stays there until it has been handed to a peer of the swarm, so that events that are made while we're not connected aren't lost.
+
+use fallible_iterator::FallibleIterator;
+use rusqlite::params;
+
+use crate::persistence::{
+	channel,
+	timeline,
+	Result
+};
+
+
+
+/// An event that is waiting to be published.
+pub struct OutboxEntry {
+	pub event_id: u64,
+	/// The number of times publishing the event has failed.
+	pub attempts: u32,
+	pub last_error: Option<String>
+}
+
+
+
+impl channel::Handle {
+
+	/// Puts the event with the given id in the outbox.
+	/// If the event changes a post, `post` is the timeline and the id of that post.
+	pub async fn enqueue_event( &self, event_id: u64, post: Option<(&timeline::Handle, u64)> ) -> Result<()> {
+
+		self.base.insert("INSERT OR REPLACE INTO outbox (channel_id, event_id, publisher_id, post_id) VALUES (?,?,?,?)",
+			params![
+				self.id,
+				event_id as i64,
+				post.map(|(timeline, _)| timeline.id),
+				post.map(|(_, id)| id as i64)
+			]
+		).await?;
+		Ok(())
+	}
+
+	/// Lists the events that are waiting to be published, in the order in which they need to be published.
+	pub async fn list_outbox( &self ) -> Result<Vec<OutboxEntry>> {
+
+		Ok( self.base.query("SELECT event_id, attempts, last_error FROM outbox WHERE channel_id = ? ORDER BY event_id",
+			params![self.id],
+			|_, rows| Ok( rows.map(|row| {
+				let event_id: i64 = row.get(0)?;
+				Ok( OutboxEntry {
+					event_id: event_id as _,
+					attempts: row.get(1)?,
+					last_error: row.get(2)?
+				})
+			}).collect()? )
+		).await? )
+	}
+
+	/// Takes the event out of the outbox, once it has been published.
+	pub async fn dequeue_event( &self, event_id: u64 ) -> Result<()> {
+
+		self.base.execute("DELETE FROM outbox WHERE channel_id = ? AND event_id = ?",
+			params![self.id, event_id as i64],
+			|_| Ok(())
+		).await?;
+		Ok(())
+	}
+
+	/// Remembers that publishing the event has failed, and why.
+	pub async fn record_outbox_failure( &self, event_id: u64, error: &str ) -> Result<()> {
+
+		self.base.execute("UPDATE outbox SET attempts = attempts + 1, last_error = ? WHERE channel_id = ? AND event_id = ?",
+			params![error, self.id, event_id as i64],
+			|_| Ok(())
+		).await?;
+		Ok(())
+	}
+}
+
+impl timeline::Handle {
+
+	/// Returns whether there are changes to the post that haven't reached the swarm yet.
+	pub async fn is_pending( &self, post_id: u64 ) -> Result<bool> {
+
+		Ok( self.base.query_one("SELECT 1 FROM outbox WHERE publisher_id = ? AND post_id = ?",
+			params![self.id, post_id as i64],
+			|_, _| Ok(())
+		).await?.is_some() )
+	}
+}
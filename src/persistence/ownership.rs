@@ -0,0 +1,186 @@
+//! This module provides the persistence of the owners of a channel, which can change when the channel is handed to another person.
+//!
+//! A channel starts out with its own address as its only owner.
+//! A transfer names a new owner, and takes effect when the new owner accepts it.
+//! From then on, the keys of the previous owners are only valid for the grace period of the transfer, while the key of the new owner is valid indefinitely.
+
+use fallible_iterator::FallibleIterator;
+use gnunet::{
+	crypto::HashCode,
+	identity::*
+};
+use rusqlite::params;
+
+use crate::{
+	common,
+	event::*,
+	persistence::{
+		channel,
+		peer::now,
+		system_post::SystemPostKind,
+		Error,
+		Result
+	}
+};
+
+
+
+/// A transfer of the channel that hasn't been accepted yet.
+pub struct PendingTransfer {
+	pub new_owner: PublicKey,
+	/// The hash of the transfer, which the acceptance refers to.
+	pub hash: HashCode,
+	/// In milliseconds.
+	pub grace_period: u64
+}
+
+
+
+impl channel::Handle {
+
+	/// Loads the keys that currently validate the events of the channel.
+	pub async fn load_owners( &self ) -> Result<Vec<PublicKey>> {
+
+		let address = self.load_address().await?;
+		let rows: Vec<(String, Option<i64>)> = self.base.query("SELECT address, valid_until FROM channel_owner WHERE channel_id = ?",
+			params![self.id],
+			|_, rows| Ok( rows.map(|row| Ok(( row.get(0)?, row.get(1)? ))).collect()? )
+		).await?;
+
+		let now = now();
+		let mut original_listed = false;
+		let mut owners = Vec::with_capacity( rows.len() + 1 );
+		for (owner, valid_until) in rows {
+			let owner = PublicKey::from_string( &owner ).expect("invalid owner address");
+			original_listed |= owner == address;
+			if valid_until.map(|t| t > now).unwrap_or(true) {
+				owners.push( owner );
+			}
+		}
+
+		// Until the channel has been transferred, its own address is its owner.
+		if !original_listed {
+			owners.insert( 0, address );
+		}
+
+		Ok( owners )
+	}
+
+	/// Returns the name of one of our egos that currently owns the channel, if any.
+	pub async fn load_owner_ego( &self ) -> Result<Option<String>> {
+
+		for owner in self.load_owners().await? {
+			let ego: Option<Option<String>> = self.base.query_one("SELECT lp.ego FROM local_publishers lp INNER JOIN publisher p ON p.id = lp.publisher_id WHERE p.address = ?",
+				params![owner.to_string()],
+				|_, row| row.get(0)
+			).await?;
+			if let Some(ego) = ego.flatten() {
+				return Ok( Some( ego ) )
+			}
+		}
+		Ok(None)
+	}
+
+	/// Loads the transfer that is waiting to be accepted, if there is one.
+	pub async fn load_pending_transfer( &self ) -> Result<Option<PendingTransfer>> {
+
+		Ok( self.base.query_one("SELECT new_owner, hash, grace_period FROM ownership_transfer WHERE channel_id = ?",
+			params![self.id],
+			|_, row| {
+				let new_owner: String = row.get(0)?;
+				let hash: String = row.get(1)?;
+				let grace_period: i64 = row.get(2)?;
+				Ok( PendingTransfer {
+					new_owner: PublicKey::from_string( &new_owner ).expect("invalid owner address"),
+					hash: HashCode::from_string( &hash ).expect("invalid hash code"),
+					grace_period: grace_period as _
+				})
+			}
+		).await? )
+	}
+
+	/// Stores a transfer that has been validated, replacing any earlier transfer that hasn't been accepted.
+	pub async fn store_pending_transfer( &self, transfer: &PendingTransfer ) -> Result<()> {
+
+		self.base.insert("INSERT OR REPLACE INTO ownership_transfer (channel_id, new_owner, hash, grace_period) VALUES (?,?,?,?)",
+			params![self.id, transfer.new_owner.to_string(), transfer.hash.to_string(), transfer.grace_period as i64]
+		).await?;
+		self.record_system_post( SystemPostKind::OwnershipOffered, Some( &transfer.new_owner ) ).await?;
+		Ok(())
+	}
+
+	/// Makes the new owner of the pending transfer the owner of the channel.
+	/// The current owners remain owners until the grace period of the transfer has passed.
+	pub async fn complete_transfer( &self, transfer: &PendingTransfer ) -> Result<()> {
+
+		let valid_until = now() + transfer.grace_period as i64;
+		for owner in self.load_owners().await? {
+			self.base.insert("INSERT OR REPLACE INTO channel_owner (channel_id, address, valid_until) VALUES (?,?,?)",
+				params![self.id, owner.to_string(), valid_until]
+			).await?;
+		}
+		self.base.insert("INSERT OR REPLACE INTO channel_owner (channel_id, address, valid_until) VALUES (?,?,NULL)",
+			params![self.id, transfer.new_owner.to_string()]
+		).await?;
+		self.base.execute("DELETE FROM ownership_transfer WHERE channel_id = ?", params![self.id], |_| Ok(()) ).await?;
+		self.record_system_post( SystemPostKind::OwnershipTransferred, Some( &transfer.new_owner ) ).await?;
+
+		Ok(())
+	}
+
+	/// Offers the channel to a new owner, as one of its current owners.
+	/// The transfer is added to the event log, so that it reaches the new owner through the swarm.
+	pub async fn transfer( &self, private_key: &PrivateKey, new_owner: PublicKey, grace_period: u64 ) -> Result<()> {
+
+		let owner = private_key.extract_public().unwrap();
+		if !self.load_owners().await?.contains( &owner ) {
+			return Err( Error::NotFound )
+		}
+
+		let transfer = OwnershipTransfer { new_owner, grace_period };
+		let hash = HashCode::generate_from( &transfer );
+		let msg = TransferOwnershipEventMessage {
+			signature: common::sign_hash( private_key, &hash ),
+			hash,
+			transfer
+		};
+		let mut message = vec![ ChannelEventType::TransferOwnership as u8 ];
+		message.extend( bincode::serialize( &msg )? );
+
+		self.base.atomically(async {
+			self.store_pending_transfer( &PendingTransfer {
+				new_owner: msg.transfer.new_owner.clone(),
+				hash: msg.hash.clone(),
+				grace_period
+			}).await?;
+			self.log_event( None, &message ).await?;
+			Ok::<(), Error>(())
+		}).await
+	}
+
+	/// Accepts the pending transfer, as the new owner that it names.
+	/// Returns `Error::NotFound` if there is no transfer to the given key.
+	pub async fn accept_transfer( &self, private_key: &PrivateKey ) -> Result<()> {
+
+		let transfer = match self.load_pending_transfer().await? {
+			Some(t) if t.new_owner == private_key.extract_public().unwrap() => t,
+			_ => return Err( Error::NotFound )
+		};
+
+		let acceptance = OwnershipAcceptance { transfer_hash: transfer.hash.clone() };
+		let hash = HashCode::generate_from( &acceptance );
+		let msg = AcceptOwnershipEventMessage {
+			signature: common::sign_hash( private_key, &hash ),
+			hash,
+			acceptance
+		};
+		let mut message = vec![ ChannelEventType::AcceptOwnership as u8 ];
+		message.extend( bincode::serialize( &msg )? );
+
+		self.base.atomically(async {
+			self.complete_transfer( &transfer ).await?;
+			self.log_event( None, &message ).await?;
+			Ok::<(), Error>(())
+		}).await
+	}
+}
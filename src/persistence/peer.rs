@@ -0,0 +1,390 @@
+//! This module provides the persistence of the statistics we keep about the peers we've been connected to.
+//!
+//! Every connection with a peer is a session, and the counters are kept per session.
+//! The totals of a peer are the sums over all its sessions.
+
+use std::time::{Duration, SystemTime};
+
+use fallible_iterator::FallibleIterator;
+use gnunet::identity::PublicKey;
+use rusqlite::{NO_PARAMS, params};
+
+use crate::{
+	message::RequestType,
+	persistence::{
+		self,
+		Error,
+		Result
+	}
+};
+
+
+
+#[derive(Clone)]
+pub struct Handle {
+	pub base: persistence::Handle,
+	pub address: PublicKey
+}
+
+/// The counters of a single session with a peer.
+#[derive(Clone, Default)]
+pub struct Counters {
+	/// The number of messages received from the peer.
+	pub messages: u64,
+	/// The number of bytes received from the peer.
+	pub bytes: u64,
+	/// The number of malformed messages received from the peer.
+	pub malformed: u64,
+	/// The number of requests that the peer didn't respond to in time.
+	pub timeouts: u64
+}
+
+/// A session with a peer, as it is stored.
+pub struct Session {
+	/// The time at which the session started, in milliseconds since the UNIX epoch.
+	pub started: u64,
+	/// The time at which the session ended, or `None` if it is still going on.
+	pub ended: Option<u64>,
+	pub counters: Counters
+}
+
+/// How much a peer has served in the swarm of a channel, over a number of days.
+pub struct RelayStats {
+	pub address: PublicKey,
+	/// The number of `Posts` requests that the peer has responded to.
+	pub posts: u64,
+	/// The number of `Blocks` requests that the peer has responded to.
+	pub blocks: u64,
+	/// The total size of the responses.
+	pub bytes: u64,
+	/// The last day on which the peer has served anything, in days since the UNIX epoch.
+	pub last_day: u64
+}
+
+/// A peer that has been flagged for misbehaving, as it is stored.
+pub struct BadPeer {
+	pub address: PublicKey,
+	/// Why the peer has been flagged the last time.
+	pub reason: String,
+	pub offenses: u32,
+	/// The time at which the peer has been flagged the last time, in milliseconds since the UNIX epoch.
+	pub flagged: u64,
+	/// The time at which the ban ends, in milliseconds since the UNIX epoch.
+	pub expires: u64
+}
+
+/// The reputation score of a peer, as it was when it was last updated.
+pub struct StoredReputation {
+	pub address: PublicKey,
+	pub score: f64,
+	/// In milliseconds since the UNIX epoch.
+	pub updated: u64
+}
+
+/// The totals of all sessions with a peer.
+pub struct Stats {
+	pub address: String,
+	pub sessions: u64,
+	pub counters: Counters,
+	/// The total time that we've been connected to the peer, in milliseconds.
+	pub uptime: u64,
+	/// The time at which we've last been connected to the peer, in milliseconds since the UNIX epoch.
+	pub last_seen: u64
+}
+
+
+
+/// The current time, in milliseconds since the UNIX epoch.
+pub fn now() -> i64 {
+	SystemTime::now().duration_since( SystemTime::UNIX_EPOCH ).unwrap().as_millis() as _
+}
+
+/// The current day, in days since the UNIX epoch.
+fn today() -> i64 {
+	now() / (24 * 60 * 60 * 1000)
+}
+
+/// Completes the query that sums the sessions up per peer, with the given clauses.
+/// The first parameter needs to be the current time, because sessions that haven't ended yet count as running until now.
+macro_rules! stats_query {
+	( $clauses:literal ) => {
+		concat!( "SELECT address, COUNT(*), SUM(messages), SUM(bytes), SUM(malformed), SUM(timeouts), \
+			SUM(COALESCE(ended, ?1) - started), MAX(COALESCE(ended, ?1)) FROM peer_session ", $clauses )
+	}
+}
+
+fn row_to_stats( row: &rusqlite::Row<'_> ) -> rusqlite::Result<Stats> {
+	let values: Vec<i64> = (1..8).map(|i| row.get(i)).collect::<rusqlite::Result<_>>()?;
+
+	Ok( Stats {
+		address: row.get(0)?,
+		sessions: values[0] as _,
+		counters: Counters {
+			messages: values[1] as _,
+			bytes: values[2] as _,
+			malformed: values[3] as _,
+			timeouts: values[4] as _
+		},
+		uptime: values[5] as _,
+		last_seen: values[6] as _
+	})
+}
+
+impl Stats {
+
+	/// A score between 0 and 1 that expresses how well the peer has behaved.
+	/// Malformed messages weigh heavily, because they are a sign of malicious intent, while timeouts can happen to anyone.
+	pub fn score( &self ) -> f64 {
+		let c = &self.counters;
+		let good = c.messages as f64;
+		let bad = (c.malformed * 10 + c.timeouts) as f64;
+
+		(good + 1.0) / (good + bad + 1.0)
+	}
+}
+
+impl persistence::Handle {
+
+	/// Removes the bans that have expired.
+	/// Returns the number of peers that are no longer blocked.
+	pub async fn expire_bad_peers( &self ) -> Result<usize> {
+
+		Ok( self.execute("DELETE FROM bad_peer WHERE expires_timestamp <= ?",
+			params![now()],
+			|affected| Ok(affected as _)
+		).await? )
+	}
+
+	/// Loads all peers that are flagged, including the ones of which the ban has expired but hasn't been lifted yet.
+	/// The ones that stay blocked the longest come first.
+	pub async fn list_bad_peers( &self ) -> Result<Vec<BadPeer>> {
+
+		Ok( self.query("SELECT address, reason, offenses, flagged_timestamp, expires_timestamp FROM bad_peer ORDER BY expires_timestamp DESC",
+			NO_PARAMS,
+			|_, rows| Ok( rows.map(|row| {
+				let address: String = row.get(0)?;
+				let offenses: i64 = row.get(2)?;
+				let flagged: i64 = row.get(3)?;
+				let expires: i64 = row.get(4)?;
+
+				Ok( BadPeer {
+					address: PublicKey::from_string( &address ).expect("invalid peer address"),
+					reason: row.get(1)?,
+					offenses: offenses as _,
+					flagged: flagged as _,
+					expires: expires as _
+				})
+			}).collect()? )
+		).await? )
+	}
+
+	/// Loads the reputation scores of all peers, as they were when they were last updated.
+	pub async fn list_reputations( &self ) -> Result<Vec<StoredReputation>> {
+
+		Ok( self.query("SELECT address, score, updated_timestamp FROM peer_reputation ORDER BY address",
+			NO_PARAMS,
+			|_, rows| Ok( rows.map(|row| {
+				let address: String = row.get(0)?;
+				let updated: i64 = row.get(2)?;
+
+				Ok( StoredReputation {
+					address: PublicKey::from_string( &address ).expect("invalid peer address"),
+					score: row.get(1)?,
+					updated: updated as _
+				})
+			}).collect()? )
+		).await? )
+	}
+
+	/// Stores the given bans and reputations, replacing what is stored for the same peers, all in one transaction.
+	pub async fn import_peers( &self, bad_peers: &[BadPeer], reputations: &[StoredReputation] ) -> Result<()> {
+
+		self.atomically(async {
+			for bad in bad_peers {
+				self.execute("INSERT OR REPLACE INTO bad_peer (address, reason, offenses, flagged_timestamp, expires_timestamp) VALUES (?,?,?,?,?)",
+					params![bad.address.to_string(), bad.reason, bad.offenses as i64, bad.flagged as i64, bad.expires as i64],
+					|_| Ok(())
+				).await?;
+			}
+			for reputation in reputations {
+				self.get_peer( &reputation.address ).store_reputation( reputation.score, reputation.updated as _ ).await?;
+			}
+
+			Ok::<_, Error>(())
+		}).await
+	}
+
+	pub fn get_peer( &self, address: &PublicKey ) -> Handle {
+		Handle {
+			base: self.clone(),
+			address: address.clone()
+		}
+	}
+
+	/// Loads the totals of all peers that we have statistics of, the most recently seen first.
+	pub async fn list_peer_stats( &self ) -> Result<Vec<Stats>> {
+
+		Ok( self.query( stats_query!("GROUP BY address ORDER BY 8 DESC"),
+			params![now()],
+			|_, rows| Ok( rows.map(|row| row_to_stats( row )).collect()? )
+		).await? )
+	}
+
+	/// Loads which peers have served requests in the swarm of the channel with the given row id, during the last `days` days.
+	/// The peers that have served the most data come first.
+	pub async fn list_relays( &self, channel_id: i64, days: u64 ) -> Result<Vec<RelayStats>> {
+
+		Ok( self.query("SELECT address, SUM(posts), SUM(blocks), SUM(bytes), MAX(day) FROM relay_service \
+			WHERE channel_id = ? AND day > ? GROUP BY address ORDER BY 4 DESC",
+			params![channel_id, today() - days as i64],
+			|_, rows| Ok( rows.map(|row| {
+				let address: String = row.get(0)?;
+				let values: Vec<i64> = (1..5).map(|i| row.get(i)).collect::<rusqlite::Result<_>>()?;
+
+				Ok( RelayStats {
+					address: PublicKey::from_string( &address ).expect("invalid peer address"),
+					posts: values[0] as _,
+					blocks: values[1] as _,
+					bytes: values[2] as _,
+					last_day: values[3] as _
+				})
+			}).collect()? )
+		).await? )
+	}
+}
+
+impl Handle {
+
+	/// Starts a new session with the peer.
+	/// Returns the id of the session, with which its counters can be updated.
+	pub async fn start_session( &self ) -> Result<i64> {
+
+		Ok( self.base.insert("INSERT INTO peer_session (address, started) VALUES (?,?)",
+			params![self.address.to_string(), now()]
+		).await? )
+	}
+
+	/// Stores the counters of the session, and marks it as ended if `ended` is true.
+	pub async fn update_session( &self, session_id: i64, counters: &Counters, ended: bool ) -> Result<()> {
+
+		self.base.execute_one("UPDATE peer_session SET messages = ?, bytes = ?, malformed = ?, timeouts = ?, ended = ? WHERE ROWID = ?",
+			params![
+				counters.messages as i64,
+				counters.bytes as i64,
+				counters.malformed as i64,
+				counters.timeouts as i64,
+				if ended { Some( now() ) } else { None },
+				session_id
+			]
+		).await?;
+
+		Ok(())
+	}
+
+	/// Blocks the peer because it has misbehaved.
+	/// Every offense adds `ban_duration` to the time that the peer stays blocked.
+	pub async fn flag_bad( &self, reason: &str, ban_duration: Duration ) -> Result<()> {
+
+		self.base.execute("INSERT INTO bad_peer (address, reason, offenses, flagged_timestamp, expires_timestamp) VALUES (?1,?2,1,?3,?3 + ?4) \
+			ON CONFLICT (address) DO UPDATE SET reason = ?2, offenses = offenses + 1, flagged_timestamp = ?3, expires_timestamp = ?3 + ?4 * (offenses + 1)",
+			params![self.address.to_string(), reason, now(), ban_duration.as_millis() as i64],
+			|_| Ok(())
+		).await?;
+
+		Ok(())
+	}
+
+	/// Lifts the ban of the peer, and forgets its offenses.
+	/// Returns whether the peer was flagged.
+	pub async fn unflag_bad( &self ) -> Result<bool> {
+
+		let changes = self.base.execute("DELETE FROM bad_peer WHERE address = ?",
+			params![self.address.to_string()],
+			|changes| Ok(changes)
+		).await?;
+		Ok( changes > 0 )
+	}
+
+	/// Loads the reputation score of the peer and the time at which it was last updated, in milliseconds since the UNIX epoch.
+	/// Returns `None` if the peer has no reputation yet.
+	pub async fn load_reputation( &self ) -> Result<Option<(f64, i64)>> {
+
+		Ok( self.base.query_one("SELECT score, updated_timestamp FROM peer_reputation WHERE address = ?",
+			params![self.address.to_string()],
+			|_, row| Ok(( row.get(0)?, row.get(1)? ))
+		).await? )
+	}
+
+	pub async fn store_reputation( &self, score: f64, timestamp: i64 ) -> Result<()> {
+
+		self.base.execute("INSERT OR REPLACE INTO peer_reputation (address, score, updated_timestamp) VALUES (?,?,?)",
+			params![self.address.to_string(), score, timestamp],
+			|_| Ok(())
+		).await?;
+
+		Ok(())
+	}
+
+	/// Returns whether the peer is blocked at the moment.
+	pub async fn is_blocked( &self ) -> Result<bool> {
+
+		Ok( self.base.query_one("SELECT 1 FROM bad_peer WHERE address = ? AND expires_timestamp > ?",
+			params![self.address.to_string(), now()],
+			|_, _| Ok(())
+		).await?.is_some() )
+	}
+
+	/// Records that the peer has responded to a request in the swarm of the channel with the given row id.
+	/// Only `Posts` and `Blocks` requests count as serving the channel.
+	pub async fn record_served( &self, channel_id: i64, request_type: RequestType, bytes: u64 ) -> Result<()> {
+
+		let (posts, blocks) = match request_type {
+			RequestType::Posts => (1, 0),
+			RequestType::Blocks => (0, 1),
+			_ => return Ok(())
+		};
+
+		self.base.execute("INSERT INTO relay_service (channel_id, address, day, posts, blocks, bytes) VALUES (?1,?2,?3,?4,?5,?6) \
+			ON CONFLICT (channel_id, address, day) DO UPDATE SET posts = posts + ?4, blocks = blocks + ?5, bytes = bytes + ?6",
+			params![channel_id, self.address.to_string(), today(), posts, blocks, bytes as i64],
+			|_| Ok(())
+		).await?;
+
+		Ok(())
+	}
+
+	/// Loads the totals of all sessions with the peer, or `None` if we have never been connected to it.
+	pub async fn load_stats( &self ) -> Result<Option<Stats>> {
+
+		let stats = self.base.query_one( stats_query!("WHERE address = ?2 GROUP BY address"),
+			params![now(), self.address.to_string()],
+			|_, row| row_to_stats( row )
+		).await?;
+
+		Ok( stats )
+	}
+
+	/// Loads the latest `limit` sessions with the peer, the newest first.
+	pub async fn load_sessions( &self, limit: u32 ) -> Result<Vec<Session>> {
+
+		Ok( self.base.query("SELECT started, ended, messages, bytes, malformed, timeouts FROM peer_session WHERE address = ? ORDER BY started DESC LIMIT ?",
+			params![self.address.to_string(), limit],
+			|_, rows| Ok( rows.map(|row| {
+				let started: i64 = row.get(0)?;
+				let ended: Option<i64> = row.get(1)?;
+				let counters: Vec<i64> = (2..6).map(|i| row.get(i)).collect::<rusqlite::Result<_>>()?;
+
+				Ok( Session {
+					started: started as _,
+					ended: ended.map(|e| e as _),
+					counters: Counters {
+						messages: counters[0] as _,
+						bytes: counters[1] as _,
+						malformed: counters[2] as _,
+						timeouts: counters[3] as _
+					}
+				})
+			}).collect()? )
+		).await? )
+	}
+}
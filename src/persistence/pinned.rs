@@ -0,0 +1,101 @@
+//! This module provides the persistence of the posts that the owner of a channel has pinned to the top of its feed.
+//!
+//! The pinned posts are spread as a signed channel event, so that every subscriber shows the same ones.
+//! Like the publisher list, every change replaces all of them, and only a change with a higher revision is applied.
+
+use fallible_iterator::FallibleIterator;
+use gnunet::{
+	crypto::HashCode,
+	identity::*
+};
+use rusqlite::params;
+
+use crate::{
+	common,
+	event::{ChannelEventType, PINNED_POSTS_MAX},
+	message::{PinnedPosts, UpdatePinnedPostsEventMessage},
+	persistence::{
+		channel,
+		Error,
+		Result
+	}
+};
+
+
+
+impl channel::Handle {
+
+	/// Loads the ids of the posts of the channel itself that are pinned, in the order in which they are shown.
+	pub async fn load_pinned_posts( &self ) -> Result<Vec<u64>> {
+
+		Ok( self.base.query("SELECT post_id FROM pinned_post WHERE channel_id = ? ORDER BY position",
+			params![self.id],
+			|_, rows| Ok( rows.map(|row| {
+				let post_id: i64 = row.get(0)?;
+				Ok( post_id as u64 )
+			}).collect()? )
+		).await? )
+	}
+
+	/// Loads the revision of the pinned posts that are in effect, if any have been received.
+	pub async fn load_pinned_posts_revision( &self ) -> Result<Option<u32>> {
+
+		let revision: Option<Option<i64>> = self.base.query_one("SELECT pinned_posts_revision FROM channel WHERE id = ?",
+			params![self.id],
+			|_, row| row.get(0)
+		).await?;
+
+		Ok( revision.flatten().map(|r| r as _) )
+	}
+
+	/// Replaces the pinned posts of this channel.
+	pub async fn store_pinned_posts( &self, pinned: &PinnedPosts ) -> Result<()> {
+
+		self.base.execute("DELETE FROM pinned_post WHERE channel_id = ?", params![self.id], |_| Ok(()) ).await?;
+		for (position, post_id) in pinned.post_ids.iter().enumerate() {
+			self.base.insert("INSERT INTO pinned_post (channel_id, position, post_id) VALUES (?,?,?)",
+				params![self.id, position as i64, *post_id as i64]
+			).await?;
+		}
+
+		self.base.execute_one("UPDATE channel SET pinned_posts_revision = ? WHERE id = ?",
+			params![pinned.revision as i64, self.id]
+		).await?;
+
+		Ok(())
+	}
+
+	/// Replaces the pinned posts of the channel, as one of its owners.
+	/// The new pinned posts get the next revision, and are added to the event log so that they reach the swarm.
+	/// At most `PINNED_POSTS_MAX` posts can be pinned, as other nodes reject the event otherwise.
+	/// Returns `Error::NotFound` if the given key doesn't own the channel.
+	pub async fn update_pinned_posts( &self, private_key: &PrivateKey, post_ids: Vec<u64> ) -> Result<PinnedPosts> {
+
+		let owner = private_key.extract_public().unwrap();
+		if !self.load_owners().await?.contains( &owner ) {
+			return Err( Error::NotFound )
+		}
+		debug_assert!(post_ids.len() <= PINNED_POSTS_MAX, "too many pinned posts");
+
+		let pinned = PinnedPosts {
+			revision: self.load_pinned_posts_revision().await?.map(|r| r + 1).unwrap_or(1),
+			post_ids
+		};
+		let hash = HashCode::generate_from( &pinned );
+		let msg = UpdatePinnedPostsEventMessage {
+			signature: common::sign_hash( private_key, &hash ),
+			hash,
+			pinned
+		};
+		let mut message = vec![ ChannelEventType::UpdatePinnedPosts as u8 ];
+		message.extend( bincode::serialize( &msg )? );
+
+		self.base.atomically(async {
+			self.store_pinned_posts( &msg.pinned ).await?;
+			self.log_event( None, &message ).await?;
+			Ok::<(), Error>(())
+		}).await?;
+
+		Ok( msg.pinned )
+	}
+}
@@ -0,0 +1,189 @@
+//! The connections to the database, which are opened once and then reused by the handles that come after.
+//!
+//! Every handle has a connection of its own for as long as it lives, so that its transactions don't take in the statements of other handles.
+//! When the last clone of a handle is dropped, its connection goes back to the pool of the data directory it was opened in.
+//! The database is in WAL mode, so that the handles can read while another one writes.
+
+use std::{
+	collections::HashMap,
+	ops::{Deref, DerefMut},
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
+	time::Duration
+};
+
+use lazy_static::lazy_static;
+use rusqlite::{self, NO_PARAMS, OpenFlags};
+
+use crate::{
+	config,
+	persistence::{at_rest::StorageKey, schema}
+};
+
+
+
+/// What the data directory of a database in memory starts with, followed by the name of the database.
+const MEMORY_PREFIX: &str = ":memory:";
+
+lazy_static! {
+	/// The pools of all data directories that databases have been opened in.
+	static ref POOLS: Mutex<HashMap<PathBuf, Arc<Pool>>> = Mutex::new( HashMap::new() );
+}
+
+/// The connections to the database of a data directory that aren't in use.
+pub struct Pool {
+	data_dir: PathBuf,
+	/// The key that the content of private channels is encrypted with, which is loaded together with the database.
+	storage_key: Arc<StorageKey>,
+	idle: Mutex<Vec<rusqlite::Connection>>
+}
+
+/// A connection that goes back to its pool when it is dropped.
+pub struct PooledConnection {
+	connection: Option<rusqlite::Connection>,
+	pool: Arc<Pool>
+}
+
+
+
+/// The data directory under which the database in memory with the given name is pooled.
+/// Nothing is stored in it, as the directory doesn't exist.
+pub fn memory_data_dir( name: &str ) -> PathBuf {
+	PathBuf::from( format!("{}{}", MEMORY_PREFIX, name) )
+}
+
+impl Pool {
+
+	/// Returns the pool of the given data directory.
+	/// The first time, the database is created if it doesn't exist, and migrated, and the storage key is loaded or created.
+	pub fn get( data_dir: &Path ) -> rusqlite::Result<Arc<Self>> {
+		let mut pools = POOLS.lock().unwrap();
+		if let Some(pool) = pools.get( data_dir ) {
+			return Ok( pool.clone() )
+		}
+
+		// A database in memory doesn't outlive the process, so neither does its key.
+		let storage_key = if data_dir.to_str().map(|d| d.starts_with( MEMORY_PREFIX )).unwrap_or( false ) {
+			StorageKey::generate()
+		} else {
+			StorageKey::load_or_create( data_dir ).map_err(|e| rusqlite::Error::SqliteFailure(
+				rusqlite::ffi::Error::new( rusqlite::ffi::SQLITE_CANTOPEN ),
+				Some( format!("Unable to load the storage key: {}", e) )
+			))?
+		};
+
+		let pool = Arc::new( Self {
+			data_dir: data_dir.to_owned(),
+			storage_key: Arc::new( storage_key ),
+			idle: Mutex::new( Vec::new() )
+		});
+		let mut connection = pool.open()?;
+		schema::migrate( &mut connection, data_dir )?;
+		pool.idle.lock().unwrap().push( connection );
+
+		pools.insert( data_dir.to_owned(), pool.clone() );
+		Ok( pool )
+	}
+
+	/// Takes a connection that isn't in use, or opens a new one if there is none.
+	pub fn checkout( self: &Arc<Self> ) -> rusqlite::Result<PooledConnection> {
+		let idle = self.idle.lock().unwrap().pop();
+		let connection = match idle {
+			None => self.open()?,
+			Some(c) => c
+		};
+
+		Ok( PooledConnection {
+			connection: Some( connection ),
+			pool: self.clone()
+		})
+	}
+
+	/// Closes all idle connections of the pool of the given data directory, after having SQLite optimize the database, as it recommends doing right before closing.
+	/// The connections that are still in use are closed when they are dropped, rather than going back to the pool.
+	pub fn close( data_dir: &Path ) -> rusqlite::Result<()> {
+		let pool = match POOLS.lock().unwrap().remove( data_dir ) {
+			None => return Ok(()),
+			Some(p) => p
+		};
+		let idle: Vec<rusqlite::Connection> = pool.idle.lock().unwrap().drain(..).collect();
+
+		for (i, connection) in idle.into_iter().enumerate() {
+			if i == 0 {
+				connection.execute_batch("PRAGMA optimize")?;
+			}
+			connection.close().map_err(|(_, e)| e)?;
+		}
+		Ok(())
+	}
+
+	fn open( &self ) -> rusqlite::Result<rusqlite::Connection> {
+		if let Some(name) = self.data_dir.to_str().and_then(|d| d.strip_prefix( MEMORY_PREFIX )) {
+			return Self::open_in_memory( name )
+		}
+
+		let connection = rusqlite::Connection::open( self.data_dir.join("db.sqlite") )?;
+		// Other connections may be writing at the same time, which can take a while if they're in a transaction.
+		connection.busy_timeout( Duration::from_secs( config::DATABASE_BUSY_TIMEOUT ) )?;
+		// Setting the journal mode returns the mode that is in use, so it has to be queried.
+		let _: String = connection.query_row("PRAGMA journal_mode = WAL", NO_PARAMS, |row| row.get(0))?;
+		// With WAL, this is still safe from corruption, only the last transactions may be lost on a power failure.
+		connection.execute_batch("PRAGMA synchronous = NORMAL")?;
+		Ok( connection )
+	}
+
+	/// Opens a connection to the shared database in memory with the given name.
+	/// The database lives for as long as any connection to it, which the pool keeps open at least one of.
+	fn open_in_memory( name: &str ) -> rusqlite::Result<rusqlite::Connection> {
+		let flags = OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE | OpenFlags::SQLITE_OPEN_URI;
+		let connection = rusqlite::Connection::open_with_flags( format!("file:{}?mode=memory&cache=shared", name), flags )?;
+		connection.busy_timeout( Duration::from_secs( config::DATABASE_BUSY_TIMEOUT ) )?;
+		// The connections of a shared cache lock the tables that they use, which readers don't need to wait for.
+		connection.execute_batch("PRAGMA read_uncommitted = true")?;
+		Ok( connection )
+	}
+
+	/// Whether this is still the pool of its data directory, which it isn't anymore once it has been closed.
+	fn is_open( self: &Arc<Self> ) -> bool {
+		POOLS.lock().unwrap().get( &self.data_dir ).map(|p| Arc::ptr_eq( p, self )).unwrap_or( false )
+	}
+}
+
+impl PooledConnection {
+
+	pub fn storage_key( &self ) -> &StorageKey {
+		&self.pool.storage_key
+	}
+
+	pub fn storage_key_arc( &self ) -> Arc<StorageKey> {
+		self.pool.storage_key.clone()
+	}
+}
+
+impl Deref for PooledConnection {
+	type Target = rusqlite::Connection;
+
+	fn deref( &self ) -> &Self::Target {
+		self.connection.as_ref().unwrap()
+	}
+}
+
+impl DerefMut for PooledConnection {
+	fn deref_mut( &mut self ) -> &mut Self::Target {
+		self.connection.as_mut().unwrap()
+	}
+}
+
+impl Drop for PooledConnection {
+	fn drop( &mut self ) {
+		let connection = self.connection.take().unwrap();
+
+		// A connection that is left in a transaction, by `atomically` being cancelled for example, would take it to the next handle.
+		if !connection.is_autocommit() || !self.pool.is_open() { return }
+
+		let mut idle = self.pool.idle.lock().unwrap();
+		if idle.len() < config::DATABASE_POOL_SIZE {
+			idle.push( connection );
+		}
+	}
+}
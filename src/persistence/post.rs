@@ -7,12 +7,14 @@ use async_std::{
 };
 use gnunet::{
 	crypto::*,
+	identity::PublicKey
 };
 use rusqlite::params;
 use thiserror::Error;
 
 use crate::{
 	persistence::{
+		at_rest,
 		timeline,
 		Result
 	}
@@ -42,17 +44,14 @@ impl Handle {
 
 	pub async fn load_block( &self, block_id: &HashCode ) -> Result<Option<Vec<u8>>> {
 		
-		Ok( self.timeline.base.query_one("SELECT data FROM block WHERE hash = ?",
-			params![block_id.to_string()],
-			|_, row| row.get(0)
-		).await? )
+		self.timeline.base.load_block( block_id ).await
 	}
 
 	pub async fn load_content( &self ) -> Result<Option<String>> {
 		
 		Ok( self.timeline.base.query_one("SELECT data FROM post_content WHERE ROWID = (SELECT content_id FROM post WHERE ROWID = ?)",
 			params![self.id],
-			|_, row| row.get(0)
+			|con, row| at_rest::read_text( con, row, 0 )
 		).await? )
 	}
 
@@ -126,11 +125,7 @@ impl Handle {
 
 	pub async fn store_block( &self, id: &HashCode, block: &[u8] ) -> Result<()> {
 
-		self.timeline.base.insert("INSERT INTO block (hash, data) VALUES (?,?)",
-			params![id.to_string(), block]
-		).await?;
-
-		Ok(())
+		self.timeline.base.store_block( id, block, self.timeline.is_private().await? ).await
 	}
 
 	pub async fn store_blocks( &self, ids: &[HashCode], blocks: &[&[u8]] ) -> Result<()> {
@@ -145,12 +140,27 @@ impl Handle {
 		Ok(())
 	}
 
+	/// Remembers that this post is a copy of the post with the given hash, from the given publisher.
+	pub async fn store_origin( &self, publisher: &PublicKey, post_hash: &HashCode ) -> Result<()> {
+
+		self.timeline.base.insert("INSERT OR REPLACE INTO post_origin (post_id, publisher_address, post_hash) VALUES (?,?,?)",
+			params![self.id, publisher.to_string(), post_hash.to_string()]
+		).await?;
+
+		Ok(())
+	}
+
 	/// Stores the content `data` for the post with the given `id`.
 	pub async fn store_content( &self, body: &str ) -> Result<i64> {
 
-		let content_id = self.timeline.base.insert("INSERT INTO post_content (body) VALUES (?)", params![body]).await?;
+		let private = self.timeline.is_private().await?;
+		let content_id = self.timeline.base.insert("INSERT INTO post_content (data) VALUES (?)", params![self.timeline.base.storage_key.seal_text( body, private )]).await?;
 
 		self.timeline.base.execute_one("UPDATE post SET content_id = ? WHERE ROWID = ?", params![content_id as i64, self.id]).await?;
+		// A revision that arrived before the content is newer, and has been indexed already.
+		if self.timeline.base.query_one("SELECT 1 FROM post_revision WHERE post_id = ?", params![self.id], |_, _| Ok(()) ).await?.is_none() {
+			self.timeline.index_content( self.id, body ).await?;
+		}
 
 		Ok(content_id)
 	}
@@ -0,0 +1,69 @@
+//! This module provides the persistence of the defaults for the new posts of our own egos.
+//!
+//! Every ego can have tags that its new posts get, and a footer that is added to the end of their content, like the signature under a letter.
+//! The defaults are applied when a post is written, so comments and the posts that are copied into a fork don't get them.
+
+use rusqlite::params;
+
+use crate::persistence::{
+	timeline,
+	Result
+};
+
+
+
+#[derive(Default)]
+pub struct PostDefaults {
+	pub tags: Vec<String>,
+	pub footer: Option<String>
+}
+
+
+
+impl PostDefaults {
+
+	/// Puts the default tags in front of the given ones, leaving out the ones that are given already.
+	pub fn apply_tags( &self, tags: Vec<String> ) -> Vec<String> {
+		let mut applied: Vec<String> = self.tags.iter().filter(|t| !tags.contains( t )).cloned().collect();
+		applied.extend( tags );
+		applied
+	}
+
+	/// Appends the footer to the content, separated by an empty line.
+	pub fn apply_footer( &self, content: &str ) -> String {
+		match &self.footer {
+			None => content.to_owned(),
+			Some(footer) => format!("{}\n\n{}", content.trim_end(), footer)
+		}
+	}
+}
+
+impl timeline::Handle {
+
+	/// Loads the defaults for the new posts of this publisher, which are empty if it isn't one of ours.
+	pub async fn load_post_defaults( &self ) -> Result<PostDefaults> {
+
+		let defaults = self.base.query_one("SELECT default_tags, footer FROM local_publishers WHERE publisher_id = ?",
+			params![self.id],
+			|_, row| {
+				let tags: String = row.get(0)?;
+				Ok( PostDefaults {
+					tags: tags.split_whitespace().map(|t| t.to_owned()).collect(),
+					footer: row.get(1)?
+				})
+			}
+		).await?;
+		Ok( defaults.unwrap_or_default() )
+	}
+
+	/// Replaces the defaults for the new posts of this publisher.
+	/// Returns false if the publisher isn't one of ours.
+	pub async fn store_post_defaults( &self, defaults: &PostDefaults ) -> Result<bool> {
+
+		let changes = self.base.execute("UPDATE local_publishers SET default_tags = ?, footer = ? WHERE publisher_id = ?",
+			params![defaults.tags.join(" "), defaults.footer, self.id],
+			|changes| Ok(changes)
+		).await?;
+		Ok( changes > 0 )
+	}
+}
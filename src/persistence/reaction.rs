@@ -0,0 +1,129 @@
+//! This module provides the persistence of the reactions to posts.
+//!
+//! Only the newest reaction of every signer to a post is kept, so that the counts can't be inflated by reacting over and over.
+
+use fallible_iterator::FallibleIterator;
+use gnunet::{
+	crypto::HashCode,
+	identity::*
+};
+use rusqlite::params;
+
+use crate::{
+	common,
+	event::{Reaction, ReactionEventData},
+	persistence::{
+		channel,
+		peer::now,
+		timeline,
+		Error,
+		Result
+	}
+};
+
+
+
+/// The number of signers that have reacted to a post in the same way.
+pub struct ReactionCount {
+	pub kind: String,
+	pub count: u64
+}
+
+
+
+impl timeline::Handle {
+
+	/// Stores a reaction to one of the posts of this timeline, unless its signer has reacted to the post more recently.
+	/// The reaction should have been validated already.
+	/// Returns whether the reaction was stored, which it isn't if we don't have the post.
+	pub async fn store_reaction( &self, data: &ReactionEventData ) -> Result<bool> {
+
+		let row_id = match self.load_post_row_id( data.post_id ).await? {
+			None => return Ok(false),
+			Some(id) => id
+		};
+
+		let signer = data.signer.to_string();
+		let newest: Option<i64> = self.base.query_one("SELECT timestamp FROM reaction WHERE post_id = ? AND signer = ?",
+			params![row_id, signer],
+			|_, row| row.get(0)
+		).await?;
+		if newest.map(|t| t >= data.reaction.timestamp as i64).unwrap_or(false) {
+			return Ok(false)
+		}
+
+		self.base.insert("INSERT OR REPLACE INTO reaction (post_id, signer, kind, hash, signature, timestamp) VALUES (?,?,?,?,?,?)",
+			params![
+				row_id,
+				signer,
+				data.reaction.kind,
+				data.hash.to_string(),
+				bincode::serialize( &data.signature )?,
+				data.reaction.timestamp as i64
+			]
+		).await?;
+
+		Ok(true)
+	}
+
+	/// Reacts to one of the posts of this timeline, as the owner of the given key, and stores the reaction.
+	/// Returns the data of the `Reaction` event, or `None` if we don't have the post.
+	pub async fn create_reaction( &self, private_key: &PrivateKey, post_id: u64, kind: &str ) -> Result<Option<ReactionEventData>> {
+
+		let post = match self.load_post( post_id ).await? {
+			None => return Ok(None),
+			Some(p) => p
+		};
+
+		let reaction = Reaction {
+			post_hash: post.hash,
+			kind: kind.to_owned(),
+			timestamp: now() as _
+		};
+		let hash = HashCode::generate_from( &reaction );
+		let data = ReactionEventData {
+			post_id,
+			signer: private_key.extract_public().unwrap(),
+			signature: common::sign_hash( private_key, &hash ),
+			hash,
+			reaction
+		};
+		self.store_reaction( &data ).await?;
+
+		Ok( Some( data ) )
+	}
+
+	/// Counts the reactions to the post with the given id, per kind, the most common kind first.
+	pub async fn count_reactions( &self, post_id: u64 ) -> Result<Vec<ReactionCount>> {
+
+		Ok( self.base.query("SELECT r.kind, COUNT(*) AS n FROM reaction r INNER JOIN post p ON p.ROWID = r.post_id \
+			WHERE p.publisher_id = ? AND p.id = ? GROUP BY r.kind ORDER BY n DESC, r.kind",
+			params![self.id, post_id as i64],
+			|_, rows| Ok( rows.map(|row| {
+				let count: i64 = row.get(1)?;
+				Ok( ReactionCount {
+					kind: row.get(0)?,
+					count: count as _
+				})
+			}).collect()? )
+		).await? )
+	}
+}
+
+impl channel::Handle {
+
+	/// Reacts to a post of the given timeline of this channel, and adds the `Reaction` event to the event log, all in one transaction.
+	/// Returns the data of the event, or `None` if we don't have the post.
+	pub async fn post_reaction( &self, timeline: &timeline::Handle, private_key: &PrivateKey, post_id: u64, kind: &str ) -> Result<Option<ReactionEventData>> {
+
+		self.base.atomically(async {
+			let data = match timeline.create_reaction( private_key, post_id, kind ).await? {
+				None => return Ok(None),
+				Some(d) => d
+			};
+			self.log_reaction( timeline, &data ).await?;
+
+			Ok::<_, Error>( Some( data ) )
+		}).await
+	}
+}
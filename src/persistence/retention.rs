@@ -0,0 +1,195 @@
+//! This module provides the persistence side of the retention policy: which posts have expired, and which files and blocks nothing uses anymore.
+//!
+//! A channel can ask its subscribers to keep its posts for a number of days, with `requested_replication_time` in its genesis event.
+//! Zero means that the posts are kept forever, and the posts of our own channels are always kept.
+//! A subscription can be set to keep everything of its channel regardless.
+//!
+//! The event log is kept whole, because peers that join a swarm catch up through it from the start, and can't get past a gap in it.
+
+use std::collections::HashSet;
+
+use fallible_iterator::FallibleIterator;
+use gnunet::{
+	crypto::HashCode,
+	identity::PublicKey
+};
+use rusqlite::{self, NO_PARAMS, params};
+
+use crate::persistence::{
+	self,
+	timeline,
+	Result
+};
+
+
+
+/// What a garbage collection has removed.
+#[derive(Default)]
+pub struct Reclaimed {
+	pub posts: u64,
+	pub files: u64,
+	pub blocks: u64,
+	/// The number of bytes in the database that have been freed, which are reused before the database file grows again.
+	pub bytes: u64
+}
+
+
+
+impl persistence::Handle {
+
+	/// Returns whether the subscription to the given channel keeps all of its posts, whatever the channel asks for.
+	pub async fn load_keep_everything( &self, address: &PublicKey ) -> Result<bool> {
+
+		Ok( self.query_one("SELECT keep_everything FROM subscription WHERE address = ?",
+			params![address.to_string()],
+			|_, row| row.get(0)
+		).await?.unwrap_or( false ) )
+	}
+
+	/// Sets whether the subscription to the given channel keeps all of its posts.
+	/// Returns false if we don't follow the channel.
+	pub async fn store_keep_everything( &self, address: &PublicKey, keep: bool ) -> Result<bool> {
+
+		Ok( self.execute("UPDATE subscription SET keep_everything = ? WHERE address = ?",
+			params![keep, address.to_string()],
+			|affected| Ok( affected > 0 )
+		).await? )
+	}
+
+	/// Removes the posts that have expired at the given time, in milliseconds since the UNIX epoch, and then the files and blocks that nothing uses anymore.
+	/// Files that have been stored after `orphans_before` are left alone, because they may be about to be attached to a post.
+	pub async fn collect_garbage( &self, now: u64, orphans_before: u64 ) -> Result<Reclaimed> {
+		let used_before = self.used_space().await?;
+		let mut reclaimed = Reclaimed::default();
+
+		// The channels that we follow and don't publish in, that don't keep their posts forever
+		let channels: Vec<(i64, i64)> = self.query("SELECT c.ROWID, c.requested_replication_time FROM channel c \
+			INNER JOIN subscription s ON s.address = c.address \
+			WHERE c.requested_replication_time > 0 AND s.keep_everything = 0 \
+			AND NOT EXISTS (SELECT 1 FROM publisher pb INNER JOIN local_publishers l ON l.publisher_id = pb.ROWID WHERE pb.channel_id = c.ROWID)",
+			NO_PARAMS,
+			|_, rows| Ok( rows.map(|row| Ok(( row.get(0)?, row.get(1)? ))).collect()? )
+		).await?;
+
+		for (channel_id, days) in channels {
+			let expires_before = now.saturating_sub( days as u64 * 24 * 60 * 60 * 1000 );
+			let expired: Vec<(i64, i64)> = self.query("SELECT p.publisher_id, p.id FROM post p INNER JOIN publisher pb ON pb.ROWID = p.publisher_id \
+				WHERE pb.channel_id = ? AND p.publish_timestamp < ?",
+				params![channel_id, expires_before as i64],
+				|_, rows| Ok( rows.map(|row| Ok(( row.get(0)?, row.get(1)? ))).collect()? )
+			).await?;
+
+			for (publisher_id, post_id) in expired {
+				let timeline = timeline::Handle { base: self.clone(), id: publisher_id };
+				timeline.expire_post( post_id as _ ).await?;
+				reclaimed.posts += 1;
+			}
+		}
+
+		let (files, blocks) = self.remove_orphans( orphans_before ).await?;
+		reclaimed.files = files;
+		reclaimed.blocks = blocks;
+
+		reclaimed.bytes = used_before.saturating_sub( self.used_space().await? );
+		Ok( reclaimed )
+	}
+
+	/// Removes the files that no post, draft or profile uses, and the blocks that no file is made up of.
+	/// Returns the number of files and blocks that have been removed.
+	async fn remove_orphans( &self, stored_before: u64 ) -> Result<(u64, u64)> {
+
+		let in_drafts = self.list_draft_attachments().await?;
+
+		Ok( self.transaction(move |tx| {
+			let files: Vec<String> = tx.prepare("SELECT hash FROM file WHERE (stored IS NULL OR stored < ?) \
+				AND hash NOT IN (SELECT file_hash FROM post_attachment) \
+				AND hash NOT IN (SELECT picture_hash FROM profile WHERE picture_hash IS NOT NULL)")?
+				.query( params![stored_before as i64] )?
+				.map(|row| row.get(0))
+				.collect()?;
+			let mut removed_files = 0;
+			for hash in files.iter().filter(|h| !in_drafts.contains( *h )) {
+				tx.execute("DELETE FROM thumbnail WHERE file_hash = ?", params![hash])?;
+				removed_files += tx.execute("DELETE FROM file WHERE hash = ?", params![hash])? as u64;
+			}
+
+			let removed_blocks = remove_orphan_blocks( tx )?;
+
+			Ok(( removed_files, removed_blocks ))
+		}).await? )
+	}
+
+	/// Removes the given files, unless a post, draft or profile uses them, and the blocks that no file is made up of anymore.
+	/// Unlike the garbage collection, this doesn't wait for the files to be old enough, so it is meant for the files of posts that have just been deleted.
+	/// Returns the number of files and blocks that have been removed.
+	pub async fn remove_unused_files( &self, hashes: &[HashCode] ) -> Result<(u64, u64)> {
+
+		let in_drafts = self.list_draft_attachments().await?;
+		let hashes: Vec<String> = hashes.iter().map(|h| h.to_string()).filter(|h| !in_drafts.contains( h )).collect();
+
+		Ok( self.transaction(move |tx| {
+			let mut removed_files = 0;
+			for hash in &hashes {
+				let used = tx.prepare("SELECT 1 FROM post_attachment WHERE file_hash = ?1 UNION SELECT 1 FROM profile WHERE picture_hash = ?1")?
+					.exists( params![hash] )?;
+				if !used {
+					tx.execute("DELETE FROM thumbnail WHERE file_hash = ?", params![hash])?;
+					removed_files += tx.execute("DELETE FROM file WHERE hash = ?", params![hash])? as u64;
+				}
+			}
+			let removed_blocks = remove_orphan_blocks( tx )?;
+
+			Ok(( removed_files, removed_blocks ))
+		}).await? )
+	}
+
+	/// The hashes of the files that are attached to drafts, which are only stored in the draft itself.
+	async fn list_draft_attachments( &self ) -> Result<HashSet<String>> {
+
+		let drafts: Vec<Vec<u8>> = self.query("SELECT attachment_ids FROM draft", NO_PARAMS,
+			|_, rows| Ok( rows.map(|row| row.get(0)).collect()? )
+		).await?;
+		let mut in_drafts = HashSet::new();
+		for attachment_ids in drafts {
+			let attachment_ids: Vec<HashCode> = bincode::deserialize( &attachment_ids )?;
+			in_drafts.extend( attachment_ids.iter().map(|h| h.to_string()) );
+		}
+		Ok( in_drafts )
+	}
+
+	/// The number of bytes of the database that are in use, leaving out the pages that are free to be reused.
+	async fn used_space( &self ) -> Result<u64> {
+
+		let used: i64 = self.query_one("SELECT (page_count - freelist_count) * page_size FROM pragma_page_count(), pragma_freelist_count(), pragma_page_size()",
+			NO_PARAMS,
+			|_, row| row.get(0)
+		).await?.unwrap_or( 0 );
+		Ok( used as u64 )
+	}
+}
+
+/// Removes the blocks that no file is made up of, and returns how many there were.
+fn remove_orphan_blocks( tx: &rusqlite::Transaction ) -> rusqlite::Result<u64> {
+
+	// Files are stored before their blocks, so a block that no file has is never about to be used.
+	let mut in_files = HashSet::new();
+	let block_ids: Vec<Vec<u8>> = tx.prepare("SELECT block_ids FROM file")?
+		.query( NO_PARAMS )?
+		.map(|row| row.get(0))
+		.collect()?;
+	for ids in block_ids {
+		let ids: Vec<HashCode> = bincode::deserialize( &ids )
+			.map_err(|e| rusqlite::Error::FromSqlConversionFailure( 0, rusqlite::types::Type::Blob, e ))?;
+		in_files.extend( ids.iter().map(|h| h.to_string()) );
+	}
+	let blocks: Vec<String> = tx.prepare("SELECT hash FROM block")?
+		.query( NO_PARAMS )?
+		.map(|row| row.get(0))
+		.collect()?;
+	let mut removed = 0;
+	for hash in blocks.iter().filter(|h| !in_files.contains( *h )) {
+		removed += tx.execute("DELETE FROM block WHERE hash = ?", params![hash])? as u64;
+	}
+
+	Ok( removed )
+}
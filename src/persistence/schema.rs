@@ -0,0 +1,550 @@
+//! The database schema, and the migrations that bring an older database up to date.
+//!
+//! Every migration is run exactly once, in order.
+//! The number of migrations that have been applied is kept in SQLite's `user_version` pragma.
+
+use std::path::Path;
+
+use rusqlite::{self, NO_PARAMS};
+
+use crate::persistence::subscription;
+
+
+
+/// What `inspect` found out about a database.
+pub struct Inspection {
+	/// The number of migrations that have been applied.
+	pub version: usize,
+	/// What SQLite's integrity check reported, which is nothing for a healthy database.
+	pub integrity_errors: Vec<String>,
+	/// When the last entry of the audit log was recorded, in milliseconds since the UNIX epoch.
+	pub latest_action: Option<u64>
+}
+
+/// All migrations, in the order in which they need to be applied.
+/// Never change a migration that has been released, add a new one instead.
+const MIGRATIONS: &[&str] = &[
+	// 1: The initial schema
+	"CREATE TABLE IF NOT EXISTS channel (
+		id INTEGER PRIMARY KEY,
+		address TEXT NOT NULL UNIQUE
+	);
+	CREATE TABLE IF NOT EXISTS publisher (
+		id INTEGER PRIMARY KEY,
+		channel_id INTEGER REFERENCES channel(id),
+		address TEXT NOT NULL UNIQUE,
+		last_post_id INTEGER
+	);
+	CREATE TABLE IF NOT EXISTS local_publishers (
+		publisher_id INTEGER PRIMARY KEY REFERENCES publisher(id),
+		ego TEXT
+	);
+	CREATE TABLE IF NOT EXISTS latest_ids (
+		type TEXT PRIMARY KEY,
+		id INTEGER NOT NULL
+	);
+	CREATE TABLE IF NOT EXISTS profile (
+		id INTEGER PRIMARY KEY,
+		revision INTEGER NOT NULL DEFAULT 0,
+		title TEXT NOT NULL,
+		description TEXT NOT NULL,
+		picture_hash TEXT
+	);
+	CREATE TABLE IF NOT EXISTS channel_profile (
+		channel_id INTEGER PRIMARY KEY REFERENCES channel(id),
+		profile_id INTEGER NOT NULL REFERENCES profile(id),
+		stylesheet TEXT
+	);
+	CREATE TABLE IF NOT EXISTS channel_event (
+		id INTEGER NOT NULL,
+		channel_id INTEGER NOT NULL REFERENCES channel(id),
+		message BLOB NOT NULL
+	);
+	CREATE INDEX IF NOT EXISTS channel_event_id ON channel_event (channel_id, id);
+	CREATE TABLE IF NOT EXISTS publisher_event (
+		id INTEGER NOT NULL,
+		publisher_id INTEGER NOT NULL REFERENCES publisher(id),
+		message BLOB NOT NULL
+	);
+	CREATE INDEX IF NOT EXISTS publisher_event_id ON publisher_event (publisher_id, id);
+	CREATE TABLE IF NOT EXISTS post_content (
+		data TEXT NOT NULL
+	);
+	CREATE TABLE IF NOT EXISTS post (
+		id INTEGER NOT NULL,
+		publisher_id INTEGER NOT NULL REFERENCES publisher(id),
+		hash TEXT NOT NULL,
+		signature BLOB NOT NULL,
+		publish_timestamp INTEGER NOT NULL,
+		content_hash TEXT NOT NULL,
+		attachment_count INTEGER NOT NULL DEFAULT 0,
+		content_id INTEGER REFERENCES post_content(ROWID),
+		UNIQUE (publisher_id, id)
+	);
+	CREATE TABLE IF NOT EXISTS block (
+		hash TEXT PRIMARY KEY,
+		data BLOB NOT NULL
+	);
+	CREATE TABLE IF NOT EXISTS tags (
+		keyword TEXT NOT NULL,
+		post_id INTEGER NOT NULL REFERENCES post(ROWID)
+	);
+	CREATE INDEX IF NOT EXISTS tags_keyword ON tags (keyword);",
+
+	// 2: Local settings
+	"CREATE TABLE setting (
+		key TEXT PRIMARY KEY,
+		value TEXT NOT NULL
+	);",
+
+	// 3: Files, which are made up of blocks
+	"CREATE TABLE file (
+		hash TEXT PRIMARY KEY,
+		block_ids BLOB NOT NULL
+	);",
+
+	// 4: The files that are attached to posts
+	"CREATE TABLE post_attachment (
+		post_id INTEGER NOT NULL REFERENCES post(ROWID),
+		file_hash TEXT NOT NULL REFERENCES file(hash),
+		position INTEGER NOT NULL,
+		PRIMARY KEY (post_id, position)
+	);",
+
+	// 5: Where posts that have been copied from another channel came from
+	"CREATE TABLE post_origin (
+		post_id INTEGER PRIMARY KEY REFERENCES post(ROWID),
+		publisher_address TEXT NOT NULL,
+		post_hash TEXT NOT NULL
+	);",
+
+	// 6: The MIME type of files, if it is known
+	"ALTER TABLE file ADD COLUMN mime_type TEXT;",
+
+	// 7: The revisions of posts
+	// The content of the original post stays in place, so that it can still be provided to peers.
+	"CREATE TABLE post_revision (
+		post_id INTEGER NOT NULL REFERENCES post(ROWID),
+		number INTEGER NOT NULL,
+		hash TEXT NOT NULL,
+		signature BLOB NOT NULL,
+		content_id INTEGER NOT NULL REFERENCES post_content(ROWID),
+		received_timestamp INTEGER NOT NULL,
+		PRIMARY KEY (post_id, number)
+	);",
+
+	// 8: The posts that their publishers have asked to forget
+	"CREATE TABLE forgotten_post (
+		publisher_id INTEGER NOT NULL REFERENCES publisher(id),
+		id INTEGER NOT NULL,
+		forgotten_timestamp INTEGER NOT NULL,
+		PRIMARY KEY (publisher_id, id)
+	);",
+
+	// 9: The statistics of every session with a peer
+	"CREATE TABLE peer_session (
+		address TEXT NOT NULL,
+		started INTEGER NOT NULL,
+		ended INTEGER,
+		messages INTEGER NOT NULL DEFAULT 0,
+		bytes INTEGER NOT NULL DEFAULT 0,
+		malformed INTEGER NOT NULL DEFAULT 0,
+		timeouts INTEGER NOT NULL DEFAULT 0
+	);
+	CREATE INDEX peer_session_address ON peer_session (address, started);",
+
+	// 10: The revision of the publisher list of a channel, and the publishers that have been removed from it
+	"ALTER TABLE channel ADD COLUMN publisher_list_revision INTEGER;
+	ALTER TABLE publisher ADD COLUMN revoked INTEGER NOT NULL DEFAULT 0;",
+
+	// 11: The parameters of a channel, from its genesis event
+	"ALTER TABLE channel ADD COLUMN public INTEGER;
+	ALTER TABLE channel ADD COLUMN requested_replication_time INTEGER;",
+
+	// 12: The posts that posts are a comment on
+	"CREATE TABLE post_reference (
+		post_id INTEGER PRIMARY KEY REFERENCES post(ROWID),
+		channel_address TEXT NOT NULL,
+		post_hash TEXT NOT NULL
+	);
+	CREATE INDEX post_reference_target ON post_reference (channel_address, post_hash);",
+
+	// 13: The invite code of private channels, from which the channel key is derived
+	"ALTER TABLE channel ADD COLUMN invite_code TEXT;",
+
+	// 14: The requests that peers have served in the swarm of a channel, per day
+	"CREATE TABLE relay_service (
+		channel_id INTEGER NOT NULL REFERENCES channel(ROWID),
+		address TEXT NOT NULL,
+		day INTEGER NOT NULL,
+		posts INTEGER NOT NULL DEFAULT 0,
+		blocks INTEGER NOT NULL DEFAULT 0,
+		bytes INTEGER NOT NULL DEFAULT 0,
+		PRIMARY KEY (channel_id, address, day)
+	);",
+
+	// 15: The id of the last event of a channel that has been applied
+	"ALTER TABLE channel ADD COLUMN last_event_id INTEGER;",
+
+	// 16: The peers that have misbehaved, and until when they are blocked
+	"CREATE TABLE bad_peer (
+		address TEXT PRIMARY KEY,
+		reason TEXT NOT NULL,
+		offenses INTEGER NOT NULL,
+		flagged_timestamp INTEGER NOT NULL,
+		expires_timestamp INTEGER NOT NULL
+	);",
+
+	// 17: The reputation of peers, as it was when it was last updated
+	"CREATE TABLE peer_reputation (
+		address TEXT PRIMARY KEY,
+		score REAL NOT NULL,
+		updated_timestamp INTEGER NOT NULL
+	);",
+
+	// 18: The subscriptions, which used to be saved in files
+	"CREATE TABLE subscription (
+		id INTEGER PRIMARY KEY,
+		address TEXT NOT NULL UNIQUE
+	);
+	CREATE TABLE subscription_peer (
+		subscription_id INTEGER NOT NULL REFERENCES subscription(id),
+		address TEXT NOT NULL,
+		cached INTEGER NOT NULL,
+		position INTEGER NOT NULL,
+		PRIMARY KEY (subscription_id, cached, address)
+	);",
+
+	// 19: post.visible_from
+	"ALTER TABLE post ADD COLUMN visible_from INTEGER;",
+
+	// 20: annotation
+	"CREATE TABLE annotation (
+		id INTEGER PRIMARY KEY,
+		channel_address TEXT NOT NULL,
+		post_hash TEXT NOT NULL,
+		start INTEGER NOT NULL,
+		end INTEGER NOT NULL,
+		passage TEXT NOT NULL,
+		note TEXT,
+		created INTEGER NOT NULL
+	);
+	CREATE INDEX annotation_post_hash ON annotation (post_hash);",
+
+	// 21: post.format
+	"ALTER TABLE post ADD COLUMN format INTEGER NOT NULL DEFAULT 0;",
+
+	// 22: post.series, post.content_warning and the post info of revisions
+	"ALTER TABLE post ADD COLUMN series TEXT;
+	ALTER TABLE post ADD COLUMN content_warning TEXT;
+	ALTER TABLE post_revision ADD COLUMN info BLOB;",
+
+	// 23: The owners of channels that have been transferred, and the transfers that haven't been accepted yet
+	"CREATE TABLE channel_owner (
+		channel_id INTEGER NOT NULL REFERENCES channel(ROWID),
+		address TEXT NOT NULL,
+		valid_until INTEGER,
+		PRIMARY KEY (channel_id, address)
+	);
+	CREATE TABLE ownership_transfer (
+		channel_id INTEGER PRIMARY KEY REFERENCES channel(ROWID),
+		new_owner TEXT NOT NULL,
+		hash TEXT NOT NULL,
+		grace_period INTEGER NOT NULL
+	);",
+
+	// 24: The full-text index of the current content of posts, by the ROWID of the post
+	"CREATE VIRTUAL TABLE post_search USING fts5 (content);
+	INSERT INTO post_search (rowid, content) SELECT p.ROWID, c.data FROM post p INNER JOIN post_content c ON c.ROWID = COALESCE(
+		(SELECT r.content_id FROM post_revision r WHERE r.post_id = p.ROWID ORDER BY r.number DESC LIMIT 1), p.content_id );",
+
+	// 25: The audit log of local administrative actions, which can only be appended to
+	"CREATE TABLE audit_log (
+		id INTEGER PRIMARY KEY,
+		time INTEGER NOT NULL,
+		actor TEXT,
+		action TEXT NOT NULL,
+		subject TEXT NOT NULL
+	);
+	CREATE TRIGGER audit_log_no_update BEFORE UPDATE ON audit_log BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END;
+	CREATE TRIGGER audit_log_no_delete BEFORE DELETE ON audit_log BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END;",
+
+	// 26: Smaller versions of attached images, where a missing MIME type and data mean that the original is used
+	"CREATE TABLE thumbnail (
+		file_hash TEXT NOT NULL,
+		width INTEGER NOT NULL,
+		mime_type TEXT,
+		data BLOB,
+		PRIMARY KEY (file_hash, width)
+	);",
+
+	// 27: The events of our own that haven't reached the swarm yet, where the post is set for events that change a post
+	"CREATE TABLE outbox (
+		channel_id INTEGER NOT NULL REFERENCES channel(id),
+		event_id INTEGER NOT NULL,
+		publisher_id INTEGER REFERENCES publisher(ROWID),
+		post_id INTEGER,
+		attempts INTEGER NOT NULL DEFAULT 0,
+		last_error TEXT,
+		PRIMARY KEY (channel_id, event_id)
+	);
+	CREATE INDEX outbox_post ON outbox (publisher_id, post_id);",
+
+	// 28: The comments on posts, which are spread in the swarm of the channel of the post
+	"CREATE TABLE comment (
+		id INTEGER PRIMARY KEY,
+		post_id INTEGER NOT NULL REFERENCES post(ROWID),
+		hash TEXT NOT NULL UNIQUE,
+		author TEXT NOT NULL,
+		signature BLOB NOT NULL,
+		reply_to TEXT,
+		timestamp INTEGER NOT NULL,
+		content TEXT NOT NULL
+	);
+	CREATE INDEX comment_post ON comment (post_id, timestamp);",
+
+	// 29: The changes to the governance of channels, that are shown in their feeds
+	"CREATE TABLE system_post (
+		id INTEGER PRIMARY KEY,
+		channel_id INTEGER NOT NULL REFERENCES channel(id),
+		kind INTEGER NOT NULL,
+		subject TEXT,
+		timestamp INTEGER NOT NULL
+	);
+	CREATE INDEX system_post_channel ON system_post (channel_id, timestamp);",
+
+	// 30: The reactions to posts, of which only the newest one of every signer is kept
+	"CREATE TABLE reaction (
+		post_id INTEGER NOT NULL REFERENCES post(ROWID),
+		signer TEXT NOT NULL,
+		kind TEXT NOT NULL,
+		hash TEXT NOT NULL,
+		signature BLOB NOT NULL,
+		timestamp INTEGER NOT NULL,
+		PRIMARY KEY (post_id, signer)
+	);",
+
+	// 31: The posts that our own egos have written but haven't published yet, optionally to be published at a set time
+	"CREATE TABLE draft (
+		id INTEGER PRIMARY KEY,
+		publisher_id INTEGER NOT NULL REFERENCES publisher(id),
+		content TEXT NOT NULL,
+		tags TEXT NOT NULL,
+		format INTEGER NOT NULL,
+		visible_from INTEGER,
+		series TEXT,
+		content_warning TEXT,
+		attachment_ids BLOB NOT NULL,
+		created INTEGER NOT NULL,
+		scheduled_at INTEGER
+	);
+	CREATE INDEX draft_scheduled ON draft (scheduled_at);",
+
+
+	// 32: Whether comments have been approved by the owner of the channel, the comments that came before count as approved
+	"ALTER TABLE comment ADD COLUMN moderation INTEGER NOT NULL DEFAULT 1;",
+
+	// 33: The tags and the footer that the new posts of our own egos get
+	"ALTER TABLE local_publishers ADD COLUMN default_tags TEXT NOT NULL DEFAULT '';
+	ALTER TABLE local_publishers ADD COLUMN footer TEXT;",
+
+	// 34: When channel profiles were changed, and the profiles that lost to another one with the same revision
+	"ALTER TABLE channel_profile ADD COLUMN timestamp INTEGER;
+	CREATE TABLE profile_conflict (
+		id INTEGER PRIMARY KEY,
+		channel_id INTEGER NOT NULL REFERENCES channel(id),
+		revision INTEGER NOT NULL,
+		profile BLOB NOT NULL,
+		recorded INTEGER NOT NULL
+	);
+	CREATE INDEX profile_conflict_channel ON profile_conflict (channel_id);",
+
+	// 35: The posts that have been removed because their channel asked to keep them for a limited time, the subscriptions that keep everything anyway, and when files were stored
+	"ALTER TABLE forgotten_post ADD COLUMN expired INTEGER NOT NULL DEFAULT 0;
+	ALTER TABLE subscription ADD COLUMN keep_everything INTEGER NOT NULL DEFAULT 0;
+	ALTER TABLE file ADD COLUMN stored INTEGER;",
+
+	// 36: The followers of channels on ActivityPub servers, the keys that the channels sign their activities with, and the first post that hasn't been delivered to the followers yet
+	"CREATE TABLE federation_follower (
+		channel_id INTEGER NOT NULL REFERENCES channel(id),
+		actor TEXT NOT NULL,
+		inbox TEXT NOT NULL,
+		followed INTEGER NOT NULL,
+		PRIMARY KEY (channel_id, actor)
+	);
+	ALTER TABLE channel ADD COLUMN federation_key TEXT;
+	ALTER TABLE channel ADD COLUMN federation_next_post_id INTEGER;",
+
+	// 37: The entries of feeds that have been imported as posts
+	"CREATE TABLE imported_entry (
+		publisher_id INTEGER NOT NULL REFERENCES publisher(id),
+		entry_id TEXT NOT NULL,
+		post_id INTEGER NOT NULL,
+		PRIMARY KEY (publisher_id, entry_id)
+	);",
+
+	// 38: The plain text excerpts of the revisions of posts
+	"CREATE TABLE post_excerpt (
+		post_hash TEXT NOT NULL,
+		revision INTEGER NOT NULL,
+		excerpt TEXT NOT NULL,
+		PRIMARY KEY (post_hash, revision)
+	);",
+
+	// 39: The notifications about what has happened in the channels that we follow and publish in
+	"CREATE TABLE notification (
+		id INTEGER PRIMARY KEY,
+		channel_id INTEGER NOT NULL REFERENCES channel(id),
+		kind INTEGER NOT NULL,
+		publisher TEXT NOT NULL,
+		post_id INTEGER NOT NULL,
+		author TEXT,
+		time INTEGER NOT NULL,
+		read INTEGER NOT NULL DEFAULT 0
+	);
+	CREATE INDEX notification_unread ON notification (read);",
+
+	// 40: The publishers whose posts we don't want to see, in any channel
+	"CREATE TABLE blocked_publisher (
+		address TEXT PRIMARY KEY,
+		time INTEGER NOT NULL
+	);",
+
+	// 41: The keywords under which our own channels are advertised in the directory, if they are
+	"ALTER TABLE channel ADD COLUMN directory_keywords TEXT;",
+
+	// 42: The posts that the owner of a channel has pinned to the top of its feed
+	"ALTER TABLE channel ADD COLUMN pinned_posts_revision INTEGER;
+	CREATE TABLE pinned_post (
+		channel_id INTEGER NOT NULL REFERENCES channel(id),
+		position INTEGER NOT NULL,
+		post_id INTEGER NOT NULL,
+		PRIMARY KEY (channel_id, position)
+	);",
+
+	// 43: The latest ids were never kept in their own table, every channel has its `last_event_id`
+	"DROP TABLE latest_ids;",
+
+	// 44: The tokens with which Micropub clients publish in our own channels
+	"CREATE TABLE micropub_token (
+		id INTEGER PRIMARY KEY,
+		channel_id INTEGER NOT NULL REFERENCES channel(id),
+		name TEXT NOT NULL,
+		token_hash TEXT NOT NULL UNIQUE,
+		created INTEGER NOT NULL
+	);",
+
+	// 45: Whether blocks, events and thumbnails are encrypted at rest, which is done for the private channels
+	// Texts don't need a column, as they are encrypted into blobs.
+	"ALTER TABLE block ADD COLUMN encrypted INTEGER NOT NULL DEFAULT 0;
+	ALTER TABLE channel_event ADD COLUMN encrypted INTEGER NOT NULL DEFAULT 0;
+	ALTER TABLE publisher_event ADD COLUMN encrypted INTEGER NOT NULL DEFAULT 0;
+	ALTER TABLE thumbnail ADD COLUMN encrypted INTEGER NOT NULL DEFAULT 0;",
+
+	// 46: The decisions of the owners of channels about the comments on their posts
+	// The subject is the hash of a comment, or the address of an author.
+	"CREATE TABLE comment_moderation (
+		id INTEGER PRIMARY KEY,
+		channel_id INTEGER NOT NULL REFERENCES channel(id),
+		action INTEGER NOT NULL,
+		subject TEXT NOT NULL,
+		timestamp INTEGER NOT NULL
+	);
+	CREATE INDEX comment_moderation_subject ON comment_moderation (channel_id, subject);",
+
+	// 47: When the owner of a channel has closed it
+	"ALTER TABLE channel ADD COLUMN closed_timestamp INTEGER;",
+
+	// 48: The indexes that the filters of feeds need
+	"CREATE INDEX post_publish_timestamp ON post (publisher_id, publish_timestamp);
+	CREATE INDEX tags_post ON tags (post_id, keyword);",
+
+	// 49: The samples of the census of the swarms of our own channels
+	// The size of the swarm is unknown until the count has reached us.
+	"CREATE TABLE swarm_census (
+		channel_id INTEGER NOT NULL REFERENCES channel(id),
+		timestamp INTEGER NOT NULL,
+		children INTEGER NOT NULL,
+		swarm_size INTEGER
+	);
+	CREATE INDEX swarm_census_channel ON swarm_census (channel_id, timestamp);",
+
+	// 50: The events that have arrived before the ones preceding them, which are kept apart until they can be applied
+	// Peers may send different messages for the same id, so a message is kept per hash, where the publisher is NULL for events of the channel itself.
+	// The event log only holds applied events from now on, once per id, so the copies of early events and the events that never got applied are dropped from it.
+	"CREATE TABLE pending_event (
+		channel_id INTEGER NOT NULL REFERENCES channel(id),
+		id INTEGER NOT NULL,
+		publisher TEXT,
+		hash TEXT NOT NULL,
+		message BLOB NOT NULL,
+		encrypted INTEGER NOT NULL DEFAULT 0,
+		PRIMARY KEY (channel_id, id, hash)
+	);
+	DELETE FROM channel_event WHERE id > (SELECT COALESCE(c.last_event_id, 0) FROM channel c WHERE c.id = channel_event.channel_id);
+	DELETE FROM publisher_event WHERE id > (SELECT COALESCE(c.last_event_id, 0) FROM publisher p INNER JOIN channel c ON c.id = p.channel_id WHERE p.id = publisher_event.publisher_id);
+	DELETE FROM channel_event WHERE ROWID NOT IN (SELECT MAX(ROWID) FROM channel_event GROUP BY channel_id, id);
+	DELETE FROM publisher_event WHERE ROWID NOT IN (SELECT MAX(ROWID) FROM publisher_event GROUP BY publisher_id, id);
+	DROP INDEX channel_event_id;
+	CREATE UNIQUE INDEX channel_event_id ON channel_event (channel_id, id);
+	DROP INDEX publisher_event_id;
+	CREATE UNIQUE INDEX publisher_event_id ON publisher_event (publisher_id, id);"
+];
+
+
+
+/// The version of the database once all migrations have been applied.
+pub fn latest_version() -> usize {
+	MIGRATIONS.len()
+}
+
+/// Looks at the state of the database without changing it, for troubleshooting.
+pub fn inspect( connection: &rusqlite::Connection ) -> rusqlite::Result<Inspection> {
+	let version: i64 = connection.query_row("PRAGMA user_version", NO_PARAMS, |row| row.get(0))?;
+
+	let mut statement = connection.prepare("PRAGMA integrity_check")?;
+	let integrity_errors = statement.query_map( NO_PARAMS, |row| row.get::<_, String>(0) )?
+		.collect::<rusqlite::Result<Vec<_>>>()?
+		.into_iter()
+		.filter(|message| message != "ok")
+		.collect();
+
+	// The audit log only exists from migration 25 on.
+	let latest_action = if version >= 25 {
+		connection.query_row("SELECT MAX(time) FROM audit_log", NO_PARAMS, |row| row.get::<_, Option<i64>>(0))?.map(|t| t as u64)
+	} else {
+		None
+	};
+
+	Ok( Inspection {
+		version: version as usize,
+		integrity_errors,
+		latest_action
+	})
+}
+
+/// Applies all migrations that haven't been applied to the database yet.
+/// Data that was kept outside of the database is looked for in `data_dir`.
+pub fn migrate( connection: &mut rusqlite::Connection, data_dir: &Path ) -> rusqlite::Result<()> {
+
+	let version: i64 = connection.query_row("PRAGMA user_version", NO_PARAMS, |row| row.get(0))?;
+
+	for (i, migration) in MIGRATIONS.iter().enumerate().skip( version as usize ) {
+		let tx = connection.transaction()?;
+		tx.execute_batch( migration )?;
+		migrate_data( i + 1, &tx, data_dir )?;
+		// Pragma's don't accept parameters.
+		tx.execute_batch( &format!("PRAGMA user_version = {}", i + 1) )?;
+		tx.commit()?;
+	}
+
+	Ok(())
+}
+
+/// Runs the part of a migration that can't be done in SQL, right after the SQL of the migration with the given number.
+fn migrate_data( number: usize, tx: &rusqlite::Transaction, data_dir: &Path ) -> rusqlite::Result<()> {
+	match number {
+		18 => subscription::import_files( tx, data_dir ),
+		_ => Ok(())
+	}
+}
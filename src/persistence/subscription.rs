@@ -0,0 +1,161 @@
+//! This module provides the persistence of the subscriptions: the peers that we know for the swarm of each channel.
+//!
+//! Subscriptions used to be saved as bincode files in the `subscriptions` directory.
+//! Those files are imported by a migration.
+
+use std::{
+	fs,
+	path::Path
+};
+
+use fallible_iterator::FallibleIterator;
+use gnunet::identity::PublicKey;
+use rusqlite::{self, NO_PARAMS, params};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::persistence::{
+	self,
+	audit::AuditAction,
+	channel,
+	Result
+};
+
+
+
+/// The peers that we know for the swarm of a channel.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Subscription {
+	/// The address of the owner of the channel.
+	/// This key also identifies the channel.
+	pub owner: PublicKey,
+	/// The set of publishers that are known for this channel
+	pub publishers: Vec<PublicKey>,
+	/// Some extra peers that were remembered from the last session.
+	/// The idea is that if the owner and the publishers are not online, these peers can be tried connecting to to find a connection back to the swarm.
+	pub cached_peers: Vec<PublicKey>
+}
+
+
+
+/// Imports the subscription files from the given data directory, which are left in place.
+/// Files that can't be read are skipped, because they only cost us the peers that we knew.
+pub fn import_files( tx: &rusqlite::Transaction, data_dir: &Path ) -> rusqlite::Result<()> {
+
+	let entries = match fs::read_dir( data_dir.join("subscriptions") ) {
+		Err(_) => return Ok(()),	// Nothing to import
+		Ok(e) => e
+	};
+
+	for entry in entries.flatten() {
+		let path = entry.path();
+		// Only the subscription files themselves, not their temporary files and backups.
+		if path.extension().is_some() { continue }
+
+		let sub: Subscription = match fs::read( &path ).ok().and_then(|c| bincode::deserialize( &c ).ok()) {
+			None => {
+				warn!("Unable to import subscription file {}, skipping it.", path.display());
+				continue
+			},
+			Some(s) => s
+		};
+		store( tx, &sub )?;
+	}
+
+	Ok(())
+}
+
+/// Replaces the stored subscription of the channel of `sub.owner`.
+fn store( connection: &rusqlite::Connection, sub: &Subscription ) -> rusqlite::Result<()> {
+
+	let owner = sub.owner.to_string();
+	connection.execute("INSERT OR IGNORE INTO subscription (address) VALUES (?)", params![owner])?;
+	let id: i64 = connection.query_row("SELECT id FROM subscription WHERE address = ?", params![owner], |row| row.get(0))?;
+
+	connection.execute("DELETE FROM subscription_peer WHERE subscription_id = ?", params![id])?;
+	let peers = sub.publishers.iter().map(|p| (p, false)).enumerate()
+		.chain( sub.cached_peers.iter().map(|p| (p, true)).enumerate() );
+	for (position, (peer, cached)) in peers {
+		connection.execute("INSERT OR IGNORE INTO subscription_peer (subscription_id, address, cached, position) VALUES (?,?,?,?)",
+			params![id, peer.to_string(), cached, position as i64]
+		)?;
+	}
+
+	Ok(())
+}
+
+fn parse_address( address: String ) -> rusqlite::Result<PublicKey> {
+	PublicKey::from_string( &address )
+		.ok_or_else(|| rusqlite::Error::InvalidColumnType( 0, "address".to_owned(), rusqlite::types::Type::Text ))
+}
+
+impl persistence::Handle {
+
+	/// Loads the subscription of the channel with the given address, or `None` if it hasn't been saved yet.
+	pub async fn load_subscription( &self, owner: &PublicKey ) -> Result<Option<Subscription>> {
+
+		let id: Option<i64> = self.query_one("SELECT id FROM subscription WHERE address = ?",
+			params![owner.to_string()],
+			|_, row| row.get(0)
+		).await?;
+		let id = match id {
+			None => return Ok(None),
+			Some(i) => i
+		};
+
+		let peers: Vec<(String, bool)> = self.query("SELECT address, cached FROM subscription_peer WHERE subscription_id = ? ORDER BY cached, position",
+			params![id],
+			|_, rows| rows.map(|row| Ok(( row.get(0)?, row.get(1)? ))).collect()
+		).await?;
+
+		let mut sub = Subscription {
+			owner: owner.clone(),
+			publishers: Vec::new(),
+			cached_peers: Vec::new()
+		};
+		for (address, cached) in peers {
+			let peer = parse_address( address )?;
+			if cached { sub.cached_peers.push( peer ) } else { sub.publishers.push( peer ) }
+		}
+
+		Ok( Some( sub ) )
+	}
+
+	/// Saves the subscription, replacing the one that was saved before.
+	pub async fn save_subscription( &self, sub: &Subscription ) -> Result<()> {
+		let sub = sub.clone();
+
+		Ok( self.transaction(move |tx| store( tx, &sub )).await? )
+	}
+
+	/// Stores the subscription to a channel, and remembers the given peers for its swarm, so that they are tried before the owner of the channel.
+	/// Returns the channel, and whether it wasn't subscribed to before.
+	pub async fn subscribe( &self, address: &PublicKey, peers: &[PublicKey] ) -> Result<(channel::Handle, bool)> {
+
+		let channel = self.add_channel( address ).await?;
+		match self.load_subscription( address ).await? {
+			None => {
+				let mut sub = Subscription::new( address.clone() );
+				sub.remember_peers( peers );
+				self.save_subscription( &sub ).await?;
+				self.record_action( None, AuditAction::Subscribed, &address.to_string() ).await?;
+				Ok(( channel, true ))
+			},
+			Some(mut sub) => {
+				if peers.len() > 0 {
+					sub.remember_peers( peers );
+					self.save_subscription( &sub ).await?;
+				}
+				Ok(( channel, false ))
+			}
+		}
+	}
+
+	/// Lists the addresses of the channels of which a subscription has been saved.
+	pub async fn list_subscriptions( &self ) -> Result<Vec<PublicKey>> {
+
+		Ok( self.query("SELECT address FROM subscription", NO_PARAMS,
+			|_, rows| rows.map(|row| parse_address( row.get(0)? )).collect()
+		).await? )
+	}
+}
@@ -0,0 +1,90 @@
+//! This module provides the persistence of system posts, which show the changes to the governance of a channel in its feed.
+//!
+//! A system post is recorded whenever such a change is applied, whether it was made locally or arrived from the swarm.
+//! Only the kind of change and what it applies to is kept, the text is written in the language of the reader when it is shown.
+
+use std::convert::TryFrom;
+
+use fallible_iterator::FallibleIterator;
+use gnunet::identity::PublicKey;
+use rusqlite::params;
+
+use crate::persistence::{
+	channel,
+	peer::now,
+	Result
+};
+
+
+
+/// A change to a channel that is shown in its feed.
+#[derive(Clone, Copy, PartialEq)]
+pub enum SystemPostKind {
+	ChannelCreated = 0,
+	PublisherAdded = 1,
+	PublisherRevoked = 2,
+	/// The channel has been offered to a new owner.
+	OwnershipOffered = 3,
+	/// The new owner has accepted the channel, so that its events are signed with another key from now on.
+	OwnershipTransferred = 4
+}
+
+pub struct SystemPost {
+	pub kind: SystemPostKind,
+	/// The key that the change applies to, if any.
+	pub subject: Option<PublicKey>,
+	/// The time at which the change has been applied on this node, in milliseconds since the UNIX epoch.
+	pub timestamp: u64
+}
+
+
+
+impl TryFrom<i64> for SystemPostKind {
+	type Error = ();
+
+	fn try_from( value: i64 ) -> std::result::Result<Self, ()> {
+		Ok( match value {
+			0 => Self::ChannelCreated,
+			1 => Self::PublisherAdded,
+			2 => Self::PublisherRevoked,
+			3 => Self::OwnershipOffered,
+			4 => Self::OwnershipTransferred,
+			_ => return Err(())
+		})
+	}
+}
+
+impl channel::Handle {
+
+	/// Records a change to the channel, to show in its feed.
+	pub async fn record_system_post( &self, kind: SystemPostKind, subject: Option<&PublicKey> ) -> Result<()> {
+
+		self.base.insert("INSERT INTO system_post (channel_id, kind, subject, timestamp) VALUES (?,?,?,?)",
+			params![self.id, kind as i64, subject.map(|s| s.to_string()), now()]
+		).await?;
+		Ok(())
+	}
+
+	/// Lists the system posts that have been recorded at or after `since`, and before `until` if given, oldest first.
+	/// Both are in milliseconds since the UNIX epoch.
+	/// System posts of a kind that this version doesn't know are left out.
+	pub async fn list_system_posts( &self, since: u64, until: Option<u64> ) -> Result<Vec<SystemPost>> {
+
+		let posts: Vec<Option<SystemPost>> = self.base.query("SELECT kind, subject, timestamp FROM system_post \
+			WHERE channel_id = ? AND timestamp >= ? AND (? IS NULL OR timestamp < ?) ORDER BY timestamp, id",
+			params![self.id, since as i64, until.map(|u| u as i64), until.map(|u| u as i64)],
+			|_, rows| Ok( rows.map(|row| {
+				let kind: i64 = row.get(0)?;
+				let subject: Option<String> = row.get(1)?;
+				let timestamp: i64 = row.get(2)?;
+				Ok( SystemPostKind::try_from( kind ).ok().map(|kind| SystemPost {
+					kind,
+					subject: subject.map(|s| PublicKey::from_string( &s ).expect("invalid subject address")),
+					timestamp: timestamp as _
+				}))
+			}).collect()? )
+		).await?;
+
+		Ok( posts.into_iter().flatten().collect() )
+	}
+}
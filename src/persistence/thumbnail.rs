@@ -0,0 +1,60 @@
+//! This module provides the cache of the smaller versions of images that are attached to posts.
+//!
+//! Every entry is keyed by the hash of the attachment and the width that it was scaled to.
+//! Images that are narrower than the width are remembered too, so that they don't need to be decoded again to find that out.
+//! The thumbnails of images that are encrypted at rest are encrypted as well.
+
+use gnunet::crypto::HashCode;
+use rusqlite::params;
+
+use crate::persistence::{
+	self,
+	at_rest,
+	Result
+};
+
+
+
+/// A cached version of an image in a certain width.
+pub enum Thumbnail {
+	/// The image isn't wider than the width, so the original is used.
+	Original,
+	Scaled {
+		mime_type: String,
+		data: Vec<u8>
+	}
+}
+
+
+
+impl persistence::Handle {
+
+	/// Loads the version of the image with the given hash in the given width, if it has been made before.
+	pub async fn load_thumbnail( &self, file_hash: &HashCode, width: u32 ) -> Result<Option<Thumbnail>> {
+
+		Ok( self.query_one("SELECT mime_type, data, encrypted FROM thumbnail WHERE file_hash = ? AND width = ?",
+			params![file_hash.to_string(), width],
+			|con, row| {
+				let mime_type: Option<String> = row.get(0)?;
+				let data: Option<Vec<u8>> = row.get(1)?;
+				Ok( match (mime_type, data) {
+					(Some(mime_type), Some(_)) => Thumbnail::Scaled { mime_type, data: at_rest::read_blob( con, row, 1, 2 )? },
+					_ => Thumbnail::Original
+				})
+			}
+		).await? )
+	}
+
+	pub async fn store_thumbnail( &self, file_hash: &HashCode, width: u32, thumbnail: &Thumbnail ) -> Result<()> {
+
+		let encrypt = self.is_file_encrypted( file_hash ).await?;
+		let (mime_type, data) = match thumbnail {
+			Thumbnail::Original => (None, None),
+			Thumbnail::Scaled { mime_type, data } => (Some( mime_type ), Some( self.storage_key.seal( data, encrypt ) ))
+		};
+		self.insert("INSERT OR REPLACE INTO thumbnail (file_hash, width, mime_type, data, encrypted) VALUES (?,?,?,?,?)",
+			params![file_hash.to_string(), width, mime_type, data, encrypt]
+		).await?;
+		Ok(())
+	}
+}
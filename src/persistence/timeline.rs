@@ -5,9 +5,12 @@
 //! * Publishing of a post
 //! * Revision of the content of a post
 //! * Request to forget a post (a.k.a. post deletion)
+//! * Comment on a post, by anyone
+//! * Reaction to a post, by anyone
 
 use std::{
-	convert::TryInto,
+	collections::HashMap,
+	convert::{TryFrom, TryInto},
 	str,
 	time::SystemTime
 };
@@ -21,15 +24,20 @@ use gnunet::{
 	crypto::*,
 	identity::*
 };
-use rusqlite::params;
+use rusqlite::{self, params};
 
 use crate::{
 	persistence::{
 		self,
+		at_rest,
+		channel,
 		post,
 		Result
 	},
-	post::*
+	common,
+	event::{ForgetPostEventData, ForgetPostRequest, PostRevision, RevisePostEventData},
+	post::*,
+	preview_cache
 };
 
 
@@ -40,16 +48,95 @@ pub struct Handle {
 	pub id: i64
 }
 
+/// A revision of a post, as it is stored locally.
+pub struct StoredRevision {
+	pub number: u32,
+	/// The time at which we received the revision, in milliseconds since the UNIX epoch.
+	pub received_timestamp: u64,
+	pub content: String
+}
+
+/// The post that a copied post was copied from.
+pub struct PostOrigin {
+	pub publisher: PublicKey,
+	pub hash: HashCode
+}
+
+/// A post that comments on another post.
+pub struct Comment {
+	pub publisher: PublicKey,
+	pub post_id: u64,
+	pub publish_timestamp: u64
+}
+
+/// A post that matches a search query.
+pub struct SearchResult {
+	pub publisher: PublicKey,
+	pub post_id: u64,
+	/// The part of the content that matches best.
+	/// The matching words are preceded by `SNIPPET_MATCH_START` and followed by `SNIPPET_MATCH_END`.
+	pub snippet: String,
+	/// How well the post matches, where lower is better.
+	pub rank: f64
+}
+
+/// A tag, with the number of posts that have it.
+pub struct TagCount {
+	pub keyword: String,
+	pub count: u64
+}
+
+/// What the posts of a timeline can be filtered on.
+/// The conditions that are set all need to hold.
+#[derive(Clone, Default)]
+pub struct PostFilter {
+	/// A tag that the posts have been published with.
+	pub tag: Option<String>,
+	/// The earliest time at which the posts have been published, in milliseconds since the UNIX epoch.
+	pub since: Option<u64>,
+	/// The time before which the posts have been published, in milliseconds since the UNIX epoch.
+	pub until: Option<u64>
+}
+
+/// When a post has been, or will be, released.
+pub struct ScheduleEntry {
+	pub post_id: u64,
+	/// In milliseconds since the UNIX epoch.
+	pub publish_timestamp: u64,
+	/// The time that the post has been scheduled for, if any, in milliseconds since the UNIX epoch.
+	pub visible_from: Option<u64>
+}
+
 
 
 /// The block length used for 
 pub const POST_BLOCK_LENGTH: usize = 1024;
 /// The purpose used for the signatures
 pub const POST_SIGNATURE_PURPOSE: u32 = 777;
+/// The character that precedes a matching word in the snippet of a search result.
+pub const SNIPPET_MATCH_START: char = '\u{1}';
+/// The character that follows a matching word in the snippet of a search result.
+pub const SNIPPET_MATCH_END: char = '\u{2}';
+
+impl PostFilter {
+
+	/// Whether nothing is filtered out.
+	pub fn is_empty( &self ) -> bool {
+		self.tag.is_none() && self.since.is_none() && self.until.is_none()
+	}
+
+	fn since_param( &self ) -> i64 {
+		self.since.map(|t| t.min( i64::MAX as u64 ) as i64).unwrap_or(0)
+	}
+
+	fn until_param( &self ) -> i64 {
+		self.until.map(|t| t.min( i64::MAX as u64 ) as i64).unwrap_or( i64::MAX )
+	}
+}
 
 impl Handle {
 
-	pub async fn create_post( &self, private_key: &PrivateKey, content: &str, info: PostInfo ) -> Result<(post::Handle, Post)> {
+	pub async fn create_post( &self, private_key: &PrivateKey, content: &str, info: PostInfo, attachment_ids: Vec<HashCode>, reply_to: Option<PostReference> ) -> Result<(post::Handle, Post)> {
 
 		let post_id = match self.load_latest_post_id().await? {
 			None => 0,
@@ -62,34 +149,40 @@ impl Handle {
 		let post_data = PostMeta {
 			info,
 			content_hash,
-			attachment_ids: Vec::new()
+			attachment_ids,
+			reply_to
 		};
 		let raw_post_data = bincode::serialize( &post_data ).expect("unable to serialize post data");
 		let post_hash = HashCode::generate( &*raw_post_data );
 
 		let raw_post_hash = bincode::serialize( &post_hash ).expect("unable to serialize post ID");
 		let signature = private_key.sign( (&*raw_post_hash).try_into().unwrap(), POST_SIGNATURE_PURPOSE ).unwrap();
-		let raw_signature = bincode::serialize( &signature ).expect("unable to serialize signature");
-
-		let post_handle = self.clone().into_post( post_id as _ );
-		let content_id = post_handle.store_content( content ).await?;
 
-		let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap();
+		let private = self.is_private().await?;
+		let content_id = self.base.insert("INSERT INTO post_content (data) VALUES (?)", params![self.base.storage_key.seal_text( content, private )]).await?;
 
-		let row_id = self.base.insert("INSERT INTO post (id, publisher_id, hash, signature, publish_timestamp, content_hash, attachment_count, content_id VALUES (?,?,?,?,?,?,?)",
+		let row_id = self.base.insert("INSERT INTO post (id, publisher_id, hash, signature, publish_timestamp, content_hash, attachment_count, content_id, visible_from, format, series, content_warning) VALUES (?,?,?,?,?,?,?,?,?,?,?,?)",
 			params![
 				post_id as i64,
 				self.id,
 				post_hash.to_string(),
 				bincode::serialize(&signature)?,
-				timestamp.as_millis() as i64,
+				post_data.info.publish_timestamp as i64,
 				post_data.content_hash.to_string(),
-				0i64,
-				content_id
+				post_data.attachment_ids.len() as i64,
+				content_id,
+				post_data.info.visible_from.map(|t| t as i64),
+				post_data.info.format as u8,
+				post_data.info.series,
+				post_data.info.content_warning
 			]
 		).await?;
 
 		self.index_tags( row_id, &*tags ).await?;
+		self.index_content( row_id, content ).await?;
+		self.store_attachment_ids( row_id, &post_data.attachment_ids ).await?;
+		self.store_reference( row_id, post_data.reply_to.as_ref() ).await?;
+		self.update_latest_post_id( post_id ).await?;
 
 		let handle = post::Handle {
 			timeline: self.clone(),
@@ -111,43 +204,502 @@ impl Handle {
 		).await? )
 	}
 
+	/// Returns the channel that the publisher publishes in.
+	pub async fn get_channel( &self ) -> Result<Option<channel::Handle>> {
+
+		let channel_id: Option<Option<i64>> = self.base.query_one("SELECT channel_id FROM publisher WHERE ROWID = ?",
+			params![self.id],
+			|_, row| row.get(0)
+		).await?;
+
+		Ok( channel_id.flatten().map(|id| channel::Handle {
+			base: self.base.clone(),
+			id
+		}))
+	}
+
+	/// Whether the publisher publishes in a private channel, in which case its content is encrypted at rest.
+	pub async fn is_private( &self ) -> Result<bool> {
+
+		match self.get_channel().await? {
+			None => Ok( false ),
+			Some(channel) => channel.is_private().await
+		}
+	}
+
 	/// Loads the post if it is available locally.
 	/// If the post is not available locally, return `None`.
 	pub async fn load_post( &self, post_id: u64 ) -> Result<Option<Post>> {
 
-		let post = self.base.query_one("SELECT publisher_id, hash, signature, publish_timestamp, content_id, attachment_count FROM post WHERE id = ?",
-			params![post_id as i64],
+		let post = self.base.query_one("SELECT publisher_id, hash, signature, publish_timestamp, content_hash, attachment_count, visible_from, format, series, content_warning FROM post WHERE publisher_id = ? AND id = ?",
+			params![self.id, post_id as i64],
 			|con, row| {
-				let attachment_count: i64 = row.get(5)?;
-				let hash_str: String = row.get(1)?;
-				let signature: Vec<u8> = row.get(2)?;
-				let timestamp: i64 = row.get(3)?;
-				let content_id: String = row.get(4)?;
+				let mut post = read_post( post_id, row )?;
 				
-				let tags: Vec<String> = con.query("SELECT keyword FROM tags WHERE post_id = (SELECT ROWID FROM post WHERE id = ?)",
-					params![post_id as i64],
+				post.meta.info.tags = con.query("SELECT keyword FROM tags WHERE post_id = (SELECT ROWID FROM post WHERE publisher_id = ? AND id = ?)",
+					params![self.id, post_id as i64],
+					|rows| Ok( rows.map(|row| row.get(0)).collect()? )
+				)?;
+				let attachment_ids: Vec<String> = con.query("SELECT file_hash FROM post_attachment WHERE post_id = (SELECT ROWID FROM post WHERE publisher_id = ? AND id = ?) ORDER BY position",
+					params![self.id, post_id as i64],
 					|rows| Ok( rows.map(|row| row.get(0)).collect()? )
 				)?;
+				let reply_to: Option<(String, String)> = con.query("SELECT channel_address, post_hash FROM post_reference WHERE post_id = (SELECT ROWID FROM post WHERE publisher_id = ? AND id = ?)",
+					params![self.id, post_id as i64],
+					|rows| Ok( rows.map(|row| Ok(( row.get(0)?, row.get(1)? ))).next()? )
+				)?;
 
-				Ok( Post {
-					id: post_id,
-					hash: HashCode::from_string( &hash_str ).unwrap(),
-					signature: bincode::deserialize( &*signature ).unwrap(),
-					meta: PostMeta {
-						info: PostInfo {
-							publish_timestamp: timestamp as _,
-							tags
-						},
-						content_hash: HashCode::from_string( &content_id ).unwrap(),
-						attachment_ids: Vec::new()
-					}
-				})
+				post.meta.attachment_ids = attachment_ids.iter().map(|h| HashCode::from_string( h ).unwrap()).collect();
+				post.meta.reply_to = reply_to.map(|(address, hash)| read_post_reference( &address, &hash ));
+				Ok( post )
 			}
 		).await?;
 
 		Ok( post )
 	}
 
+	/// Loads the posts with ids from `start` up to `end`, with `None` for the ones that aren't available locally.
+	/// The posts, their tags, attachments and references are read in one go, rather than post by post.
+	pub async fn load_posts( &self, start: u64, end: u64 ) -> Result<Vec<Option<Post>>> {
+		if end <= start { return Ok( Vec::new() ) }
+
+		let posts = self.base.query("SELECT p.id, p.hash, p.signature, p.publish_timestamp, p.content_hash, p.attachment_count, p.visible_from, p.format, p.series, p.content_warning, t.keyword \
+			FROM post p LEFT JOIN tags t ON t.post_id = p.ROWID \
+			WHERE p.publisher_id = ?1 AND p.id >= ?2 AND p.id < ?3 ORDER BY p.id, t.ROWID",
+			params![self.id, start as i64, end as i64],
+			|con, mut rows| {
+				let mut posts: Vec<Option<Post>> = (start..end).map(|_| None).collect();
+				
+				// Every post comes in as many rows as it has tags, or in one row if it has none.
+				while let Some(row) = rows.next()? {
+					let post_id: i64 = row.get(0)?;
+					let slot = &mut posts[(post_id as u64 - start) as usize];
+					if slot.is_none() {
+						*slot = Some( read_post( post_id as _, row )? );
+					}
+					if let Some(keyword) = row.get::<_, Option<String>>(10)? {
+						slot.as_mut().unwrap().meta.info.tags.push( keyword );
+					}
+				}
+
+				let attachments: Vec<(i64, String)> = con.query("SELECT p.id, a.file_hash FROM post_attachment a INNER JOIN post p ON p.ROWID = a.post_id \
+					WHERE p.publisher_id = ?1 AND p.id >= ?2 AND p.id < ?3 ORDER BY p.id, a.position",
+					params![self.id, start as i64, end as i64],
+					|rows| Ok( rows.map(|row| Ok(( row.get(0)?, row.get(1)? ))).collect()? )
+				)?;
+				for (post_id, hash) in attachments {
+					if let Some(post) = &mut posts[(post_id as u64 - start) as usize] {
+						post.meta.attachment_ids.push( HashCode::from_string( &hash ).unwrap() );
+					}
+				}
+
+				let references: Vec<(i64, String, String)> = con.query("SELECT p.id, r.channel_address, r.post_hash FROM post_reference r INNER JOIN post p ON p.ROWID = r.post_id \
+					WHERE p.publisher_id = ?1 AND p.id >= ?2 AND p.id < ?3",
+					params![self.id, start as i64, end as i64],
+					|rows| Ok( rows.map(|row| Ok(( row.get(0)?, row.get(1)?, row.get(2)? ))).collect()? )
+				)?;
+				for (post_id, address, hash) in references {
+					if let Some(post) = &mut posts[(post_id as u64 - start) as usize] {
+						post.meta.reply_to = Some( read_post_reference( &address, &hash ) );
+					}
+				}
+
+				Ok( posts )
+			}
+		).await?;
+
+		Ok( posts )
+	}
+
+	/// Loads the ids from `start` up to `end` of the posts that are available locally, in order.
+	/// With `include_removed`, the ids of the posts that have been forgotten or have expired are included as well, as there is no point in fetching those.
+	pub async fn load_post_ids( &self, start: u64, end: u64, include_removed: bool ) -> Result<Vec<u64>> {
+		if end <= start { return Ok( Vec::new() ) }
+
+		let query = if include_removed {
+			"SELECT id FROM post WHERE publisher_id = ?1 AND id >= ?2 AND id < ?3 \
+			UNION SELECT id FROM forgotten_post WHERE publisher_id = ?1 AND id >= ?2 AND id < ?3 ORDER BY id"
+		} else {
+			"SELECT id FROM post WHERE publisher_id = ?1 AND id >= ?2 AND id < ?3 ORDER BY id"
+		};
+		let ids: Vec<i64> = self.base.query( query,
+			params![self.id, start as i64, end as i64],
+			|_, rows| Ok( rows.map(|row| row.get(0)).collect()? )
+		).await?;
+
+		Ok( ids.into_iter().map(|i| i as _).collect() )
+	}
+
+	/// Loads the content of the post, if it is available locally.
+	pub async fn load_post_content( &self, post_id: u64 ) -> Result<Option<String>> {
+
+		Ok( self.base.query_one("SELECT c.data FROM post_content c INNER JOIN post p ON p.content_id = c.ROWID WHERE p.publisher_id = ? AND p.id = ?",
+			params![self.id, post_id as i64],
+			|con, row| at_rest::read_text( con, row, 0 )
+		).await? )
+	}
+
+	/// Loads the content of the latest revision of the post, or the original content if it hasn't been revised.
+	pub async fn load_current_content( &self, post_id: u64 ) -> Result<Option<String>> {
+
+		Ok( self.base.query_one("SELECT c.data FROM post_content c INNER JOIN post p ON c.ROWID = COALESCE( \
+				(SELECT r.content_id FROM post_revision r WHERE r.post_id = p.ROWID ORDER BY r.number DESC LIMIT 1), p.content_id ) \
+			WHERE p.publisher_id = ? AND p.id = ?",
+			params![self.id, post_id as i64],
+			|con, row| at_rest::read_text( con, row, 0 )
+		).await? )
+	}
+
+	/// Loads the number of the latest revision of the post, which is 0 if it hasn't been revised.
+	pub async fn load_current_revision_number( &self, post_id: u64 ) -> Result<u32> {
+
+		let number: Option<i64> = self.base.query_one("SELECT MAX(r.number) FROM post_revision r INNER JOIN post p ON p.ROWID = r.post_id \
+			WHERE p.publisher_id = ? AND p.id = ?",
+			params![self.id, post_id as i64],
+			|_, row| row.get(0)
+		).await?.flatten();
+		Ok( number.map(|n| n as u32).unwrap_or(0) )
+	}
+
+	/// Loads the numbers of the latest revisions of the posts with ids from `start` up to `end`.
+	/// Posts that haven't been revised, or that we don't have, are left out.
+	pub async fn load_current_revision_numbers( &self, start: u64, end: u64 ) -> Result<HashMap<u64, u32>> {
+
+		Ok( self.base.query("SELECT p.id, MAX(r.number) FROM post_revision r INNER JOIN post p ON p.ROWID = r.post_id \
+			WHERE p.publisher_id = ?1 AND p.id >= ?2 AND p.id < ?3 GROUP BY p.id",
+			params![self.id, start as i64, end as i64],
+			|_, rows| Ok( rows.map(|row| {
+				let post_id: i64 = row.get(0)?;
+				let number: i64 = row.get(1)?;
+				Ok(( post_id as u64, number as u32 ))
+			}).collect()? )
+		).await? )
+	}
+
+	/// Loads the current content of the posts with ids from `start` up to `end`, like `load_current_content` does for a single post.
+	pub async fn load_current_contents( &self, start: u64, end: u64 ) -> Result<HashMap<u64, String>> {
+
+		Ok( self.base.query("SELECT p.id, c.data FROM post_content c INNER JOIN post p ON c.ROWID = COALESCE( \
+				(SELECT r.content_id FROM post_revision r WHERE r.post_id = p.ROWID ORDER BY r.number DESC LIMIT 1), p.content_id ) \
+			WHERE p.publisher_id = ?1 AND p.id >= ?2 AND p.id < ?3",
+			params![self.id, start as i64, end as i64],
+			|con, rows| Ok( rows.map(|row| {
+				let post_id: i64 = row.get(0)?;
+				Ok(( post_id as u64, at_rest::read_text( con, row, 1 )? ))
+			}).collect()? )
+		).await? )
+	}
+
+	/// Loads all revisions of the post that we know of, oldest first.
+	/// The original content is not included.
+	pub async fn load_revisions( &self, post_id: u64 ) -> Result<Vec<StoredRevision>> {
+
+		Ok( self.base.query("SELECT r.number, r.received_timestamp, c.data FROM post_revision r \
+			INNER JOIN post p ON p.ROWID = r.post_id INNER JOIN post_content c ON c.ROWID = r.content_id \
+			WHERE p.publisher_id = ? AND p.id = ? ORDER BY r.number",
+			params![self.id, post_id as i64],
+			|con, rows| Ok( rows.map(|row| {
+				let number: i64 = row.get(0)?;
+				let timestamp: i64 = row.get(1)?;
+				Ok( StoredRevision {
+					number: number as _,
+					received_timestamp: timestamp as _,
+					content: at_rest::read_text( con, row, 2 )?
+				})
+			}).collect()? )
+		).await? )
+	}
+
+	/// Stores a revision of a post.
+	/// The revision should have been validated already.
+	/// Returns false if we already had this revision.
+	pub async fn store_revision( &self, data: &RevisePostEventData ) -> Result<bool> {
+
+		let row_id = match self.load_post_row_id( data.post_id ).await? {
+			None => return Ok(false),
+			Some(id) => id
+		};
+		let exists = self.base.query_one("SELECT 1 FROM post_revision WHERE post_id = ? AND number = ?",
+			params![row_id, data.revision.number as i64],
+			|_, _| Ok(())
+		).await?.is_some();
+		if exists { return Ok(false) }
+
+		let private = self.is_private().await?;
+		let content_id = self.base.insert("INSERT INTO post_content (data) VALUES (?)", params![self.base.storage_key.seal_text( &data.content, private )]).await?;
+		let now = SystemTime::now().duration_since( SystemTime::UNIX_EPOCH ).unwrap().as_millis() as i64;
+		self.base.insert("INSERT INTO post_revision (post_id, number, hash, signature, content_id, received_timestamp, info) VALUES (?,?,?,?,?,?,?)",
+			params![
+				row_id,
+				data.revision.number as i64,
+				data.hash.to_string(),
+				bincode::serialize( &data.signature )?,
+				content_id,
+				now,
+				bincode::serialize( &data.revision.info )?
+			]
+		).await?;
+
+		// Only the newest revision is searchable, as revisions can arrive out of order.
+		let newer = self.base.query_one("SELECT 1 FROM post_revision WHERE post_id = ? AND number > ?",
+			params![row_id, data.revision.number as i64],
+			|_, _| Ok(())
+		).await?.is_some();
+		if !newer {
+			self.index_content( row_id, &data.content ).await?;
+		}
+		preview_cache::PREVIEWS.invalidate( &data.revision.post_hash );
+
+		Ok(true)
+	}
+
+	/// Loads the information of the post as of its latest revision.
+	/// The tags, series and content warning of a post can be changed by revising it, but the post itself keeps its original information, as that is what it was signed with.
+	pub async fn load_current_info( &self, post: &Post ) -> Result<PostInfo> {
+
+		let info: Option<Option<Vec<u8>>> = self.base.query_one("SELECT r.info FROM post_revision r INNER JOIN post p ON p.ROWID = r.post_id \
+			WHERE p.publisher_id = ? AND p.id = ? AND r.info IS NOT NULL ORDER BY r.number DESC LIMIT 1",
+			params![self.id, post.id as i64],
+			|_, row| row.get(0)
+		).await?;
+
+		Ok( match info.flatten() {
+			None => post.meta.info.clone(),
+			Some(raw) => bincode::deserialize( &raw )?
+		})
+	}
+
+	/// Revises one of our own posts, and stores the revision.
+	/// Returns the data of the `RevisePost` event, or `None` if we don't have the post.
+	pub async fn revise_post( &self, private_key: &PrivateKey, post_id: u64, content: &str, info: PostInfo ) -> Result<Option<RevisePostEventData>> {
+
+		let post = match self.load_post( post_id ).await? {
+			None => return Ok(None),
+			Some(p) => p
+		};
+		let revision = PostRevision {
+			post_hash: post.hash,
+			number: self.load_current_revision_number( post_id ).await? + 1,
+			content_hash: HashCode::generate( content.as_bytes() ),
+			info
+		};
+		let hash = HashCode::generate_from( &revision );
+		let data = RevisePostEventData {
+			post_id,
+			revision,
+			signature: common::sign_hash( private_key, &hash ),
+			hash,
+			content: content.to_owned()
+		};
+		self.store_revision( &data ).await?;
+
+		Ok( Some( data ) )
+	}
+
+	/// Forgets one of our own posts, see `forget_post`.
+	/// Returns the data of the `ForgetPost` event along with the files that were attached to the post, or `None` if we don't have the post.
+	pub async fn request_forget_post( &self, private_key: &PrivateKey, post_id: u64 ) -> Result<Option<(ForgetPostEventData, Vec<HashCode>)>> {
+
+		let post = match self.load_post( post_id ).await? {
+			None => return Ok(None),
+			Some(p) => p
+		};
+
+		let hash = HashCode::generate_from( &ForgetPostRequest { post_hash: post.hash } );
+		let data = ForgetPostEventData {
+			post_id,
+			signature: common::sign_hash( private_key, &hash ),
+			hash
+		};
+		let files = self.forget_post( post_id ).await?;
+
+		Ok( Some(( data, files )) )
+	}
+
+	/// Stores a post that was received from another peer.
+	/// The post should have been validated already.
+	/// Returns `None` if the publisher has asked to forget the post, or if it has expired.
+	pub async fn store_post( &self, post: &Post ) -> Result<Option<post::Handle>> {
+
+		if self.is_removed( post.id ).await? {
+			return Ok(None)
+		}
+		if let Some(row_id) = self.load_post_row_id( post.id ).await? {
+			return Ok( Some( self.clone().into_post( row_id ) ) )
+		}
+
+		let row_id = self.base.insert("INSERT INTO post (id, publisher_id, hash, signature, publish_timestamp, content_hash, attachment_count, visible_from, format, series, content_warning) VALUES (?,?,?,?,?,?,?,?,?,?,?)",
+			params![
+				post.id as i64,
+				self.id,
+				post.hash.to_string(),
+				bincode::serialize( &post.signature )?,
+				post.meta.info.publish_timestamp as i64,
+				post.meta.content_hash.to_string(),
+				post.meta.attachment_ids.len() as i64,
+				post.meta.info.visible_from.map(|t| t as i64),
+				post.meta.info.format as u8,
+				post.meta.info.series,
+				post.meta.info.content_warning
+			]
+		).await?;
+
+		self.index_tags( row_id, &post.meta.info.tags ).await?;
+		self.store_attachment_ids( row_id, &post.meta.attachment_ids ).await?;
+		self.store_reference( row_id, post.meta.reply_to.as_ref() ).await?;
+
+		if self.load_latest_post_id().await?.map(|latest| post.id > latest).unwrap_or(true) {
+			self.update_latest_post_id( post.id ).await?;
+		}
+
+		Ok( Some( self.clone().into_post( row_id ) ) )
+	}
+
+	/// Removes everything we have of the post: its content, revisions, tags and attachments.
+	/// A tombstone is kept, so that the post won't be stored again if a peer still provides it.
+	/// This needs to run within `atomically`, so that the post isn't left half removed.
+	/// Returns the files that were attached to the post, which are only removed by `remove_unused_files` once the removal has been committed, because they may be used elsewhere.
+	pub async fn forget_post( &self, post_id: u64 ) -> Result<Vec<HashCode>> {
+
+		// A post that has expired before can still be forgotten, which is what its tombstone shows from then on.
+		let now = SystemTime::now().duration_since( SystemTime::UNIX_EPOCH ).unwrap().as_millis() as i64;
+		self.base.insert("INSERT OR REPLACE INTO forgotten_post (publisher_id, id, forgotten_timestamp, expired) VALUES (?,?,?,0)",
+			params![self.id, post_id as i64, now]
+		).await?;
+
+		self.remove_post( post_id ).await
+	}
+
+	/// Removes the post like `forget_post` does, because it is older than the channel asked to keep its posts.
+	/// Unlike a forgotten post, no tombstone is shown for it.
+	/// Its files are left to the garbage collection that expires it.
+	pub async fn expire_post( &self, post_id: u64 ) -> Result<()> {
+
+		let now = SystemTime::now().duration_since( SystemTime::UNIX_EPOCH ).unwrap().as_millis() as i64;
+		self.base.atomically(async {
+			self.base.insert("INSERT OR IGNORE INTO forgotten_post (publisher_id, id, forgotten_timestamp, expired) VALUES (?,?,?,1)",
+				params![self.id, post_id as i64, now]
+			).await?;
+
+			self.remove_post( post_id ).await
+		}).await?;
+		Ok(())
+	}
+
+	/// Returns the files that were attached to the post.
+	async fn remove_post( &self, post_id: u64 ) -> Result<Vec<HashCode>> {
+
+		let row_id = match self.load_post_row_id( post_id ).await? {
+			None => return Ok( Vec::new() ),
+			Some(id) => id
+		};
+		let hash: String = self.base.query_one("SELECT hash FROM post WHERE ROWID = ?", params![row_id], |_, row| row.get(0) ).await?
+			.expect("missing post");
+		preview_cache::PREVIEWS.invalidate( &HashCode::from_string( &hash ).expect("invalid hash code") );
+
+		let file_hashes: Vec<HashCode> = self.base.query("SELECT DISTINCT file_hash FROM post_attachment WHERE post_id = ?",
+			params![row_id],
+			|_, rows| Ok( rows.map(|row| {
+				let hash: String = row.get(0)?;
+				Ok( HashCode::from_string( &hash ).expect("invalid hash code") )
+			}).collect()? )
+		).await?;
+
+		self.base.execute("DELETE FROM post_attachment WHERE post_id = ?", params![row_id], |_| Ok(()) ).await?;
+		self.base.execute("DELETE FROM tags WHERE post_id = ?", params![row_id], |_| Ok(()) ).await?;
+		self.base.execute("DELETE FROM post_search WHERE rowid = ?", params![row_id], |_| Ok(()) ).await?;
+		self.base.execute("DELETE FROM post_origin WHERE post_id = ?", params![row_id], |_| Ok(()) ).await?;
+		self.base.execute("DELETE FROM post_reference WHERE post_id = ?", params![row_id], |_| Ok(()) ).await?;
+		self.base.execute("DELETE FROM comment WHERE post_id = ?", params![row_id], |_| Ok(()) ).await?;
+		self.base.execute("DELETE FROM reaction WHERE post_id = ?", params![row_id], |_| Ok(()) ).await?;
+		self.base.execute("DELETE FROM post_content WHERE ROWID IN (SELECT content_id FROM post_revision WHERE post_id = ?)", params![row_id], |_| Ok(()) ).await?;
+		self.base.execute("DELETE FROM post_revision WHERE post_id = ?", params![row_id], |_| Ok(()) ).await?;
+		self.base.execute("DELETE FROM post_content WHERE ROWID = (SELECT content_id FROM post WHERE ROWID = ?)", params![row_id], |_| Ok(()) ).await?;
+		self.base.execute("DELETE FROM post WHERE ROWID = ?", params![row_id], |_| Ok(()) ).await?;
+
+		Ok( file_hashes )
+	}
+
+	/// Returns whether the publisher has asked to forget the post with the given id.
+	pub async fn is_forgotten( &self, post_id: u64 ) -> Result<bool> {
+
+		Ok( self.base.query_one("SELECT 1 FROM forgotten_post WHERE publisher_id = ? AND id = ? AND expired = 0",
+			params![self.id, post_id as i64],
+			|_, _| Ok(())
+		).await?.is_some() )
+	}
+
+	/// Returns whether the post with the given id has been forgotten or has expired.
+	async fn is_removed( &self, post_id: u64 ) -> Result<bool> {
+
+		Ok( self.base.query_one("SELECT 1 FROM forgotten_post WHERE publisher_id = ? AND id = ?",
+			params![self.id, post_id as i64],
+			|_, _| Ok(())
+		).await?.is_some() )
+	}
+
+	/// Loads where the post with the given id was copied from, if it was copied from another channel.
+	pub async fn load_post_origin( &self, post_id: u64 ) -> Result<Option<PostOrigin>> {
+
+		Ok( self.base.query_one("SELECT o.publisher_address, o.post_hash FROM post_origin o INNER JOIN post p ON p.ROWID = o.post_id WHERE p.publisher_id = ? AND p.id = ?",
+			params![self.id, post_id as i64],
+			|_, row| {
+				let address: String = row.get(0)?;
+				let hash: String = row.get(1)?;
+				Ok( PostOrigin {
+					publisher: PublicKey::from_string( &address ).expect("invalid publisher address"),
+					hash: HashCode::from_string( &hash ).expect("invalid hash code")
+				})
+			}
+		).await? )
+	}
+
+	/// Returns the row id of the post with the given id, if we have it.
+	pub async fn load_post_row_id( &self, post_id: u64 ) -> Result<Option<i64>> {
+
+		Ok( self.base.query_one("SELECT ROWID FROM post WHERE publisher_id = ? AND id = ?",
+			params![self.id, post_id as i64],
+			|_, row| row.get(0)
+		).await? )
+	}
+
+	async fn store_attachment_ids( &self, post_row_id: i64, attachment_ids: &[HashCode] ) -> Result<()> {
+
+		for (position, file_hash) in attachment_ids.iter().enumerate() {
+			self.base.insert("INSERT INTO post_attachment (post_id, file_hash, position) VALUES (?,?,?)",
+				params![post_row_id, file_hash.to_string(), position as i64]).await?;
+		}
+		// The blocks of our own attachments are already stored, and those of others are encrypted when they arrive.
+		if self.is_private().await? {
+			self.base.encrypt_files( attachment_ids ).await?;
+		}
+
+		Ok(())
+	}
+
+	/// Indexes the post that the post comments on, so that the comment can be found from the post it comments on.
+	async fn store_reference( &self, post_row_id: i64, reply_to: Option<&PostReference> ) -> Result<()> {
+
+		if let Some(reference) = reply_to {
+			self.base.insert("INSERT INTO post_reference (post_id, channel_address, post_hash) VALUES (?,?,?)",
+				params![post_row_id, reference.channel.to_string(), reference.post_hash.to_string()]).await?;
+		}
+
+		Ok(())
+	}
+
+	/// Makes the given content the searchable content of the post, replacing the content it had before.
+	/// The posts of private channels aren't searchable, as the search index can't be encrypted.
+	pub async fn index_content( &self, post_row_id: i64, content: &str ) -> Result<()> {
+
+		self.base.execute("DELETE FROM post_search WHERE rowid = ?", params![post_row_id], |_| Ok(()) ).await?;
+		if !self.is_private().await? {
+			self.base.insert("INSERT INTO post_search (rowid, content) VALUES (?,?)", params![post_row_id, content]).await?;
+		}
+
+		Ok(())
+	}
+
 	async fn index_tags( &self, post_row_id: i64, tags: &[String] ) -> Result<()> {
 		
 		for keyword in tags {
@@ -159,26 +711,116 @@ impl Handle {
 		Ok(())
 	}
 	
-	pub async fn list_posts( &mut self, start: u64, count: u16 ) -> Result<Vec<Option<Post>>> {
-		debug_assert!(count > 0, "count should be positive");
+	/// Lists the posts that have the given tag, newest first.
+	/// Only the tags that the posts have been published with are considered, not the ones added by revising them.
+	/// Posts that may not be shown yet are left out, unless `include_embargoed` is set.
+	pub async fn list_posts_by_tag( &self, keyword: &str, include_embargoed: bool, start: u64, count: u16 ) -> Result<Vec<Post>> {
+
+		let now = SystemTime::now().duration_since( SystemTime::UNIX_EPOCH ).unwrap().as_millis() as i64;
+		let post_ids: Vec<i64> = self.base.query("SELECT p.id FROM tags t INNER JOIN post p ON p.ROWID = t.post_id \
+			WHERE p.publisher_id = ?1 AND t.keyword = ?2 AND (?3 OR p.visible_from IS NULL OR p.visible_from <= ?4) \
+			ORDER BY p.id DESC LIMIT ?5 OFFSET ?6",
+			params![self.id, keyword, include_embargoed, now, count, start as i64],
+			|_, rows| Ok( rows.map(|row| row.get(0)).collect()? )
+		).await?;
 
-		let latest_post_id = match self.load_latest_post_id().await? {
-			None => return Ok( Vec::new() ),
-			Some(x) => x
-		};
+		let mut posts = Vec::with_capacity( post_ids.len() );
+		for post_id in post_ids {
+			if let Some(post) = self.load_post( post_id as _ ).await? {
+				posts.push( post );
+			}
+		}
+		Ok( posts )
+	}
 
-		// Accumatively add all posts
-		let mut posts = Vec::with_capacity( count as usize );
-		let end = start + count as u64;
-		for id in start..end {
+	/// Lists the posts that pass the filter, newest first.
+	/// Posts that may not be shown yet are left out, unless `include_embargoed` is set.
+	pub async fn list_filtered_posts( &self, filter: &PostFilter, include_embargoed: bool, start: u64, count: u16 ) -> Result<Vec<Post>> {
+
+		let now = SystemTime::now().duration_since( SystemTime::UNIX_EPOCH ).unwrap().as_millis() as i64;
+		// The time range is always given, so that the index on the publish time can be used.
+		let post_ids: Vec<i64> = self.base.query("SELECT p.id FROM post p \
+			WHERE p.publisher_id = ?1 AND p.publish_timestamp >= ?2 AND p.publish_timestamp < ?3 \
+			AND (?4 IS NULL OR EXISTS (SELECT 1 FROM tags t WHERE t.post_id = p.ROWID AND t.keyword = ?4)) \
+			AND (?5 OR p.visible_from IS NULL OR p.visible_from <= ?6) \
+			ORDER BY p.id DESC LIMIT ?7 OFFSET ?8",
+			params![self.id, filter.since_param(), filter.until_param(), filter.tag, include_embargoed, now, count, start as i64],
+			|_, rows| Ok( rows.map(|row| row.get(0)).collect()? )
+		).await?;
 
-			let post = self.load_post( id ).await?;
-			posts.push(post);
+		let mut posts = Vec::with_capacity( post_ids.len() );
+		for post_id in post_ids {
+			if let Some(post) = self.load_post( post_id as _ ).await? {
+				posts.push( post );
+			}
 		}
-
 		Ok( posts )
 	}
 
+	/// Counts the posts that pass the filter, like `list_filtered_posts` lists them.
+	pub async fn count_filtered_posts( &self, filter: &PostFilter, include_embargoed: bool ) -> Result<u64> {
+
+		let now = SystemTime::now().duration_since( SystemTime::UNIX_EPOCH ).unwrap().as_millis() as i64;
+		let count: i64 = self.base.query_one("SELECT COUNT(*) FROM post p \
+			WHERE p.publisher_id = ?1 AND p.publish_timestamp >= ?2 AND p.publish_timestamp < ?3 \
+			AND (?4 IS NULL OR EXISTS (SELECT 1 FROM tags t WHERE t.post_id = p.ROWID AND t.keyword = ?4)) \
+			AND (?5 OR p.visible_from IS NULL OR p.visible_from <= ?6)",
+			params![self.id, filter.since_param(), filter.until_param(), filter.tag, include_embargoed, now],
+			|_, row| row.get(0)
+		).await?.unwrap_or(0);
+
+		Ok( count as _ )
+	}
+
+	/// Lists the tags that are used the most on this timeline, with the number of posts that have them.
+	/// Posts that may not be shown yet are not counted, unless `include_embargoed` is set.
+	pub async fn list_top_tags( &self, include_embargoed: bool, limit: u32 ) -> Result<Vec<TagCount>> {
+
+		let now = SystemTime::now().duration_since( SystemTime::UNIX_EPOCH ).unwrap().as_millis() as i64;
+		Ok( self.base.query("SELECT t.keyword, COUNT(*) AS uses FROM tags t INNER JOIN post p ON p.ROWID = t.post_id \
+			WHERE p.publisher_id = ?1 AND (?2 OR p.visible_from IS NULL OR p.visible_from <= ?3) \
+			GROUP BY t.keyword ORDER BY uses DESC, t.keyword LIMIT ?4",
+			params![self.id, include_embargoed, now, limit],
+			|_, rows| Ok( rows.map(|row| {
+				let count: i64 = row.get(1)?;
+				Ok( TagCount {
+					keyword: row.get(0)?,
+					count: count as _
+				})
+			}).collect()? )
+		).await? )
+	}
+
+	pub async fn list_posts( &mut self, start: u64, count: u16 ) -> Result<Vec<Option<Post>>> {
+		debug_assert!(count > 0, "count should be positive");
+
+		if self.load_latest_post_id().await?.is_none() {
+			return Ok( Vec::new() )
+		}
+
+		self.load_posts( start, start + count as u64 ).await
+	}
+
+	/// Lists the posts that are released at or after `since`, in milliseconds since the UNIX epoch, in the order in which they are released.
+	/// A post is released when it has been published, or at the time it has been scheduled for.
+	pub async fn list_schedule( &self, since: u64 ) -> Result<Vec<ScheduleEntry>> {
+
+		Ok( self.base.query("SELECT id, publish_timestamp, visible_from FROM post \
+			WHERE publisher_id = ? AND COALESCE(visible_from, publish_timestamp) >= ? ORDER BY COALESCE(visible_from, publish_timestamp)",
+			params![self.id, since as i64],
+			|_, rows| Ok( rows.map(|row| {
+				let post_id: i64 = row.get(0)?;
+				let timestamp: i64 = row.get(1)?;
+				let visible_from: Option<i64> = row.get(2)?;
+				Ok( ScheduleEntry {
+					post_id: post_id as _,
+					publish_timestamp: timestamp as _,
+					visible_from: visible_from.map(|t| t as _)
+				})
+			}).collect()? )
+		).await? )
+	}
+
 	pub fn into_post( self, post_row_id: i64 ) -> post::Handle {
 
 		post::Handle {
@@ -187,7 +829,8 @@ impl Handle {
 		}
 	}
 
-	async fn load_latest_post_id( &self ) -> Result<Option<u64>> {
+	/// Loads the id of the newest post of this publisher that we know of.
+	pub async fn load_latest_post_id( &self ) -> Result<Option<u64>> {
 		
 		let id: Option<i64> = self.base.query_one("SELECT last_post_id FROM publisher WHERE ROWID = ?",
 			params![self.id],
@@ -197,12 +840,13 @@ impl Handle {
 		Ok( id.map(|i| i as _) )
 	}
 
-	/// Stores an event message with the given id.
-	/// Storing multiple messages with the same id is possible.
+	/// Stores the message of an applied event with the given id in the event log of the publisher.
+	/// There is only one event per id, so a message that is stored with the id before is replaced.
 	pub async fn store_event( &self, id: u64, message: &[u8] ) -> Result<()> {
 
-		self.base.insert("INSERT INTO publisher_event (id, publisher_id, message) VALUES (?,?,?)",
-			params![id as i64, self.id, message]).await?;
+		let encrypt = self.is_private().await?;
+		self.base.insert("INSERT OR REPLACE INTO publisher_event (id, publisher_id, message, encrypted) VALUES (?,?,?,?)",
+			params![id as i64, self.id, self.base.storage_key.seal( message, encrypt ), encrypt]).await?;
 		Ok(())
 	}
 
@@ -214,4 +858,121 @@ impl Handle {
 
 		Ok(())
 	}
-}
\ No newline at end of file
+}
+
+impl persistence::Handle {
+
+	/// Searches the current content of all posts that we have, the best matches first.
+	/// Every word of the query needs to occur in a post for it to match.
+	/// Posts that may not be shown yet are left out, except for our own, and so are the posts of blocked publishers.
+	pub async fn search_posts( &self, query: &str, limit: u32, offset: u64 ) -> Result<Vec<SearchResult>> {
+		self.search( None, query, true, limit, offset ).await
+	}
+
+	/// Searches the current content of the posts of a single channel, like `search_posts` does.
+	/// Posts that may not be shown yet are left out, including our own, as the results are meant for other nodes.
+	pub async fn search_channel_posts( &self, channel_id: i64, query: &str, limit: u32 ) -> Result<Vec<SearchResult>> {
+		self.search( Some( channel_id ), query, false, limit, 0 ).await
+	}
+
+	async fn search( &self, channel_id: Option<i64>, query: &str, include_local: bool, limit: u32, offset: u64 ) -> Result<Vec<SearchResult>> {
+
+		let query = match match_expression( query ) {
+			None => return Ok( Vec::new() ),
+			Some(q) => q
+		};
+		let now = SystemTime::now().duration_since( SystemTime::UNIX_EPOCH ).unwrap().as_millis() as i64;
+
+		Ok( self.query("SELECT pb.address, p.id, snippet(post_search, 0, char(1), char(2), '…', 24), bm25(post_search) \
+			FROM post_search s INNER JOIN post p ON p.ROWID = s.rowid INNER JOIN publisher pb ON pb.ROWID = p.publisher_id \
+			WHERE post_search MATCH ?1 AND (?5 IS NULL OR pb.channel_id = ?5) \
+			AND pb.address NOT IN (SELECT address FROM blocked_publisher) \
+			AND (p.visible_from IS NULL OR p.visible_from <= ?2 OR (?6 AND p.publisher_id IN (SELECT publisher_id FROM local_publishers))) \
+			ORDER BY bm25(post_search) LIMIT ?3 OFFSET ?4",
+			params![query, now, limit, offset as i64, channel_id, include_local],
+			|_, rows| Ok( rows.map(|row| {
+				let address: String = row.get(0)?;
+				let post_id: i64 = row.get(1)?;
+				Ok( SearchResult {
+					publisher: PublicKey::from_string( &address ).expect("invalid publisher address"),
+					post_id: post_id as _,
+					snippet: row.get(2)?,
+					rank: row.get(3)?
+				})
+			}).collect()? )
+		).await? )
+	}
+
+	/// Loads the comments on the post with the given hash of the given channel, oldest first.
+	/// Only the comments of the publishers that we follow are known to us.
+	/// Comments that may not be shown yet are left out.
+	pub async fn load_comments( &self, channel: &PublicKey, post_hash: &HashCode ) -> Result<Vec<Comment>> {
+
+		let now = SystemTime::now().duration_since( SystemTime::UNIX_EPOCH ).unwrap().as_millis() as i64;
+		Ok( self.query("SELECT pub.address, p.id, p.publish_timestamp FROM post_reference r \
+			INNER JOIN post p ON p.ROWID = r.post_id INNER JOIN publisher pub ON pub.ROWID = p.publisher_id \
+			WHERE r.channel_address = ? AND r.post_hash = ? AND (p.visible_from IS NULL OR p.visible_from <= ?) ORDER BY p.publish_timestamp",
+			params![channel.to_string(), post_hash.to_string(), now],
+			|_, rows| Ok( rows.map(|row| {
+				let address: String = row.get(0)?;
+				let post_id: i64 = row.get(1)?;
+				let timestamp: i64 = row.get(2)?;
+				Ok( Comment {
+					publisher: PublicKey::from_string( &address ).expect("invalid publisher address"),
+					post_id: post_id as _,
+					publish_timestamp: timestamp as _
+				})
+			}).collect()? )
+		).await? )
+	}
+}
+
+/// Turns the words that the user searches for into an FTS5 match expression, in which each word is quoted.
+/// That way, characters that have a meaning in the FTS5 query syntax are searched for like any other.
+/// Returns `None` if there are no words to search for.
+fn match_expression( query: &str ) -> Option<String> {
+	let words: Vec<String> = query.split_whitespace()
+		.map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+		.collect();
+
+	if words.is_empty() { None } else { Some( words.join(" ") ) }
+}
+
+/// Reads a post from a row that has its hash, signature, publish timestamp, content hash, attachment count, visible from, format, series and content warning as columns 1 to 9.
+/// The tags, attachments and reference are stored in tables of their own, so they are left empty.
+fn read_post( post_id: u64, row: &rusqlite::Row<'_> ) -> rusqlite::Result<Post> {
+	let hash_str: String = row.get(1)?;
+	let signature: Vec<u8> = row.get(2)?;
+	let timestamp: i64 = row.get(3)?;
+	let content_id: String = row.get(4)?;
+	let visible_from: Option<i64> = row.get(6)?;
+	let format: u8 = row.get(7)?;
+	let series: Option<String> = row.get(8)?;
+	let content_warning: Option<String> = row.get(9)?;
+
+	Ok( Post {
+		id: post_id,
+		hash: HashCode::from_string( &hash_str ).unwrap(),
+		signature: bincode::deserialize( &*signature ).unwrap(),
+		meta: PostMeta {
+			info: PostInfo {
+				publish_timestamp: timestamp as _,
+				tags: Vec::new(),
+				visible_from: visible_from.map(|t| t as _),
+				format: ContentFormat::try_from( format ).expect("invalid content format"),
+				series,
+				content_warning
+			},
+			content_hash: HashCode::from_string( &content_id ).unwrap(),
+			attachment_ids: Vec::new(),
+			reply_to: None
+		}
+	})
+}
+
+fn read_post_reference( address: &str, hash: &str ) -> PostReference {
+	PostReference {
+		channel: PublicKey::from_string( address ).expect("invalid channel address"),
+		post_hash: HashCode::from_string( hash ).expect("invalid hash code")
+	}
+}
@@ -0,0 +1,219 @@
+//! Generates the previews of posts that are shown in the feeds, and the HTML of full posts.
+//!
+//! A preview ends at an explicit `<!--more-->` marker if the content has one.
+//! Otherwise it contains the first few paragraphs, and is cut off at a word boundary if that is still too long.
+//!
+//! The HTML itself is generated by the renderer for the format of the content.
+//!
+//! Where markup can't be used, like in the titles of feed entries or in the description of a page, a plain text excerpt is used instead.
+//! It is taken from the rendered HTML rather than from the source, so that no markup shows up in it.
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::post::ContentFormat;
+use crate::render::{self, RenderContext};
+
+
+
+/// The marker that authors can put in their content to indicate where the preview should end.
+pub const MORE_MARKER: &str = "<!--more-->";
+/// The maximum number of paragraphs in a preview.
+pub const PREVIEW_PARAGRAPHS: usize = 3;
+/// The maximum number of characters in a preview.
+pub const PREVIEW_MAX_CHARS: usize = 600;
+/// The maximum number of characters in a plain text excerpt.
+pub const EXCERPT_MAX_CHARS: usize = 200;
+
+pub struct Preview {
+	/// The HTML of the preview.
+	pub html: String,
+	/// Whether or not the preview leaves out some of the content.
+	pub truncated: bool
+}
+
+
+
+/// Generates the preview of the given content.
+pub fn summarize( content: &str, format: ContentFormat ) -> Preview {
+
+	if let Some(index) = content.find( MORE_MARKER ) {
+		return Preview {
+			html: to_html( split_paragraphs( &content[..index] ), format ),
+			truncated: true
+		}
+	}
+
+	let paragraphs = split_paragraphs( content );
+	let mut truncated = paragraphs.len() > PREVIEW_PARAGRAPHS;
+	let mut included = Vec::with_capacity( PREVIEW_PARAGRAPHS );
+	let mut chars_left = PREVIEW_MAX_CHARS;
+
+	for paragraph in paragraphs.into_iter().take( PREVIEW_PARAGRAPHS ) {
+		let char_count = paragraph.chars().count();
+
+		if char_count <= chars_left {
+			chars_left -= char_count;
+			included.push( paragraph.to_owned() );
+		}
+		else {
+			// Always show something of the first paragraph, but don't start a paragraph that won't fit otherwise.
+			if included.len() == 0 {
+				included.push( truncate_at_word( paragraph, chars_left ) );
+			}
+			truncated = true;
+			break;
+		}
+	}
+
+	Preview {
+		html: to_html( included, format ),
+		truncated
+	}
+}
+
+/// Renders the full content, for when the whole post is shown.
+pub fn render( content: &str, format: ContentFormat ) -> String {
+	render_with( content, format, &RenderContext::default() )
+}
+
+/// Renders the full content like `render`, passing the given context on to the renderer.
+pub fn render_with( content: &str, format: ContentFormat, context: &RenderContext ) -> String {
+	render::for_format( format ).render( content, context )
+}
+
+/// Generates a plain text excerpt of the given content, of at most `max_chars` characters.
+/// All whitespace, line breaks included, is collapsed into single spaces.
+pub fn excerpt( content: &str, format: ContentFormat, max_chars: usize ) -> String {
+	let text = to_plain_text( &render( content, format ) );
+	let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+	truncate_at_word( &text, max_chars )
+}
+
+/// Returns the first line of text of the given content, which serves as its title, of at most `max_chars` characters.
+pub fn first_line( content: &str, format: ContentFormat, max_chars: usize ) -> String {
+	let text = to_plain_text( &render( content, format ) );
+	let line = text.lines().map(|l| l.trim()).find(|l| !l.is_empty()).unwrap_or("");
+	truncate_at_word( &line.split_whitespace().collect::<Vec<_>>().join(" "), max_chars )
+}
+
+/// Strips the tags from the HTML that the renderers generate, and decodes its entities.
+/// Block elements and line breaks end a line.
+pub fn to_plain_text( html: &str ) -> String {
+	const LINE_ENDING_TAGS: &[&str] = &["p", "br", "div", "li", "h1", "h2", "h3", "h4", "h5", "h6", "pre", "blockquote", "tr", "hr"];
+
+	let mut text = String::with_capacity( html.len() );
+	let mut rest = html;
+	while let Some(start) = rest.find( |c: char| c == '<' || c == '&' ) {
+		text.push_str( &rest[..start] );
+		rest = &rest[start..];
+
+		if rest.starts_with('<') {
+			let end = match rest.find('>') {
+				None => { rest = ""; break },
+				Some(i) => i
+			};
+			let name = rest[1..end].trim_start_matches('/').split(|c: char| c.is_whitespace() || c == '/').next().unwrap_or("");
+			if LINE_ENDING_TAGS.contains( &name.to_ascii_lowercase().as_str() ) {
+				text.push('\n');
+			}
+			rest = &rest[(end + 1)..];
+		}
+		else {
+			match rest.find(';').filter(|i| *i <= 10).and_then(|i| decode_entity( &rest[1..i] ).map(|c| (i, c))) {
+				None => { text.push('&'); rest = &rest[1..]; },
+				Some((i, c)) => { text.push( c ); rest = &rest[(i + 1)..]; }
+			}
+		}
+	}
+	text.push_str( rest );
+	text
+}
+
+/// Decodes the name or number of a character reference, without its `&` and `;`.
+fn decode_entity( entity: &str ) -> Option<char> {
+	match entity {
+		"lt" => Some('<'),
+		"gt" => Some('>'),
+		"amp" => Some('&'),
+		"quot" => Some('"'),
+		"apos" => Some('\''),
+		"nbsp" => Some(' '),
+		_ => {
+			let number = entity.strip_prefix('#')?;
+			let code = match number.strip_prefix('x').or_else(|| number.strip_prefix('X')) {
+				Some(hex) => u32::from_str_radix( hex, 16 ).ok()?,
+				None => number.parse().ok()?
+			};
+			std::char::from_u32( code )
+		}
+	}
+}
+
+/// Converts the given paragraphs to HTML.
+/// A paragraph of markup may have been cut off, but the sanitizer closes the elements that are left open.
+fn to_html<S: AsRef<str>>( paragraphs: Vec<S>, format: ContentFormat ) -> String {
+	let source = paragraphs.iter().map(|p| p.as_ref()).collect::<Vec<_>>().join("\n\n");
+	render::for_format( format ).render( &source, &RenderContext::default() )
+}
+
+/// Splits the content up into paragraphs, which are separated by empty lines.
+pub fn split_paragraphs( content: &str ) -> Vec<&str> {
+	let normalized = content.trim();
+	let mut paragraphs = Vec::new();
+	let mut start = 0;
+	let mut offset = 0;
+
+	for line in normalized.split_inclusive('\n') {
+		if line.trim().len() == 0 {
+			let paragraph = normalized[start..offset].trim();
+			if paragraph.len() > 0 { paragraphs.push( paragraph ) }
+			start = offset + line.len();
+		}
+		offset += line.len();
+	}
+	let paragraph = normalized[start..].trim();
+	if paragraph.len() > 0 { paragraphs.push( paragraph ) }
+
+	paragraphs
+}
+
+/// Cuts the text off at the last word boundary before `max_chars` characters, and appends an ellipsis.
+/// Characters are counted as they are seen, so a letter with its accents or an emoji that consists of several code points is never split up.
+pub fn truncate_at_word( text: &str, max_chars: usize ) -> String {
+	let end = match text.grapheme_indices( true ).nth( max_chars ) {
+		None => return text.to_owned(),
+		Some((i, _)) => i
+	};
+
+	// Words are only split if the first one is too long already.
+	let cut = match text[..end].split_word_bound_indices().rev().find(|(_, w)| w.chars().all( char::is_whitespace )) {
+		None => end,
+		Some((i, _)) => i
+	};
+
+	let mut result = text[..cut].trim_end().to_owned();
+	result.push('…');
+	result
+}
+
+/// Escapes the characters that have a special meaning in HTML.
+pub fn escape_html( text: &str ) -> String {
+	let mut escaped = String::with_capacity( text.len() );
+
+	for c in text.chars() {
+		escape_char( c, &mut escaped );
+	}
+
+	escaped
+}
+
+pub fn escape_char( c: char, escaped: &mut String ) {
+	match c {
+		'<' => escaped.push_str("&lt;"),
+		'>' => escaped.push_str("&gt;"),
+		'&' => escaped.push_str("&amp;"),
+		'"' => escaped.push_str("&quot;"),
+		'\'' => escaped.push_str("&#39;"),
+		other => escaped.push( other )
+	}
+}
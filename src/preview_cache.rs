@@ -0,0 +1,223 @@
+//! Keeps the previews of posts that have been rendered, so that the feeds don't read and render the content of every post on every request.
+//!
+//! Previews are kept by the hash of the post and the number of its revision, so a revised post never gets the preview of an older revision.
+//! The cache holds at most `config::PREVIEW_CACHE_SIZE` bytes of HTML, and evicts the previews that haven't been used for the longest time first.
+//! The HTML feeds, the RSS and Atom feeds and the JSON API all share the same cache.
+//! The plain text excerpts are small enough to be kept in the database instead, see `persistence::excerpt`.
+
+use std::{
+	collections::{BTreeMap, HashMap},
+	sync::{Arc, Mutex}
+};
+
+use gnunet::crypto::HashCode;
+use lazy_static::lazy_static;
+
+use crate::{
+	config,
+	persistence::{timeline, Result},
+	post::Post,
+	preview::{self, Preview}
+};
+
+
+
+/// How the content of a post has been rendered.
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
+pub enum Rendering {
+	/// The preview that is shown in the feeds.
+	Summary,
+	/// All of the content, as the RSS and Atom feeds have it.
+	Full
+}
+
+#[derive(Clone, Eq, Hash, PartialEq)]
+struct Key {
+	post_hash: HashCode,
+	revision: u32,
+	rendering: Rendering
+}
+
+pub struct PreviewCache {
+	/// The maximum number of bytes of HTML that are kept.
+	max_size: usize,
+	state: Mutex<State>
+}
+
+struct State {
+	entries: HashMap<Key, Entry>,
+	/// The keys of the entries by the moment that they have last been used, least recently first.
+	order: BTreeMap<u64, Key>,
+	/// The moment of the next use, which is counted rather than timed so that no two uses are the same.
+	next_use: u64,
+	/// The number of bytes of HTML of all entries together.
+	size: usize
+}
+
+struct Entry {
+	preview: Arc<Preview>,
+	last_use: u64
+}
+
+
+
+lazy_static! {
+	/// The previews of all posts that have been shown lately.
+	pub static ref PREVIEWS: PreviewCache = PreviewCache::new( config::PREVIEW_CACHE_SIZE );
+}
+
+impl PreviewCache {
+
+	pub fn new( max_size: usize ) -> Self {
+		Self {
+			max_size,
+			state: Mutex::new( State {
+				entries: HashMap::new(),
+				order: BTreeMap::new(),
+				next_use: 0,
+				size: 0
+			})
+		}
+	}
+
+	pub fn get( &self, post_hash: &HashCode, revision: u32, rendering: Rendering ) -> Option<Arc<Preview>> {
+		let key = Key { post_hash: post_hash.clone(), revision, rendering };
+		let mut state = self.state.lock().unwrap();
+
+		let last_use = state.entries.get( &key )?.last_use;
+		let now = state.use_now();
+		state.order.remove( &last_use );
+		state.order.insert( now, key.clone() );
+
+		let entry = state.entries.get_mut( &key ).unwrap();
+		entry.last_use = now;
+		Some( entry.preview.clone() )
+	}
+
+	/// Keeps a preview, and evicts the least recently used ones if that makes the cache too big.
+	/// A preview that is bigger than the whole cache is not kept.
+	pub fn insert( &self, post_hash: &HashCode, revision: u32, rendering: Rendering, preview: Preview ) -> Arc<Preview> {
+		let preview = Arc::new( preview );
+		let size = preview.html.len();
+		if size > self.max_size {
+			return preview
+		}
+
+		let key = Key { post_hash: post_hash.clone(), revision, rendering };
+		let mut state = self.state.lock().unwrap();
+		state.remove( &key );
+
+		while state.size + size > self.max_size {
+			let oldest = match state.order.values().next() {
+				None => break,
+				Some(key) => key.clone()
+			};
+			state.remove( &oldest );
+		}
+
+		let now = state.use_now();
+		state.order.insert( now, key.clone() );
+		state.entries.insert( key, Entry { preview: preview.clone(), last_use: now } );
+		state.size += size;
+		preview
+	}
+
+	/// Drops the previews of all revisions of a post, for when it is revised or forgotten.
+	pub fn invalidate( &self, post_hash: &HashCode ) {
+		let mut state = self.state.lock().unwrap();
+
+		let keys: Vec<Key> = state.entries.keys().filter(|k| k.post_hash == *post_hash).cloned().collect();
+		for key in &keys {
+			state.remove( key );
+		}
+	}
+}
+
+impl State {
+
+	fn use_now( &mut self ) -> u64 {
+		let now = self.next_use;
+		self.next_use += 1;
+		now
+	}
+
+	fn remove( &mut self, key: &Key ) {
+		if let Some(entry) = self.entries.remove( key ) {
+			self.order.remove( &entry.last_use );
+			self.size -= entry.preview.html.len();
+		}
+	}
+}
+
+
+
+/// Returns the preview of the current revision of a post, from the cache if it is in there.
+/// Returns `None` if we don't have the content of the post.
+pub async fn load_summary( timeline: &timeline::Handle, post: &Post ) -> Result<Option<Arc<Preview>>> {
+	load( timeline, post, Rendering::Summary ).await
+}
+
+/// Returns the HTML of all of the content of the current revision of a post, from the cache if it is in there.
+/// Returns `None` if we don't have the content of the post.
+pub async fn load_full( timeline: &timeline::Handle, post: &Post ) -> Result<Option<Arc<Preview>>> {
+	load( timeline, post, Rendering::Full ).await
+}
+
+/// Returns the previews of the current revisions of a page of posts, which have ids within `start..end`, in the same order.
+/// The revisions and the content of the posts that aren't in the cache are read with a single query each, rather than post by post.
+pub async fn load_summaries( timeline: &timeline::Handle, start: u64, end: u64, posts: &[&Post] ) -> Result<Vec<Option<Arc<Preview>>>> {
+	let revisions = timeline.load_current_revision_numbers( start, end ).await?;
+	let revision = |post: &Post| revisions.get( &post.id ).cloned().unwrap_or(0);
+
+	let mut previews: Vec<Option<Arc<Preview>>> = posts.iter().map(|p| PREVIEWS.get( &p.hash, revision( p ), Rendering::Summary )).collect();
+	if previews.iter().all(|p| p.is_some()) {
+		return Ok( previews )
+	}
+
+	let contents = timeline.load_current_contents( start, end ).await?;
+	for (post, preview) in posts.iter().zip( previews.iter_mut() ) {
+		if preview.is_some() { continue }
+		if let Some(content) = contents.get( &post.id ) {
+			let summary = preview::summarize( content, post.meta.info.format );
+			*preview = Some( PREVIEWS.insert( &post.hash, revision( post ), Rendering::Summary, summary ) );
+		}
+	}
+	Ok( previews )
+}
+
+/// Returns the plain text excerpt of the current revision of a post, from the database if it has been made before.
+/// Returns `None` if we don't have the content of the post.
+pub async fn load_excerpt( timeline: &timeline::Handle, post: &Post ) -> Result<Option<String>> {
+	let revision = timeline.load_current_revision_number( post.id ).await?;
+	if let Some(excerpt) = timeline.base.load_excerpt( &post.hash, revision ).await? {
+		return Ok( Some( excerpt ) )
+	}
+
+	let content = match timeline.load_current_content( post.id ).await? {
+		None => return Ok(None),
+		Some(c) => c
+	};
+	let excerpt = preview::excerpt( &content, post.meta.info.format, preview::EXCERPT_MAX_CHARS );
+	timeline.base.store_excerpt( &post.hash, revision, &excerpt, timeline.is_private().await? ).await?;
+	Ok( Some( excerpt ) )
+}
+
+async fn load( timeline: &timeline::Handle, post: &Post, rendering: Rendering ) -> Result<Option<Arc<Preview>>> {
+	let revision = timeline.load_current_revision_number( post.id ).await?;
+	if let Some(preview) = PREVIEWS.get( &post.hash, revision, rendering ) {
+		return Ok( Some( preview ) )
+	}
+
+	let content = match timeline.load_current_content( post.id ).await? {
+		None => return Ok(None),
+		Some(c) => c
+	};
+	let preview = match rendering {
+		Rendering::Summary => preview::summarize( &content, post.meta.info.format ),
+		Rendering::Full => Preview {
+			html: preview::render( &content, post.meta.info.format ),
+			truncated: false
+		}
+	};
+	Ok( Some( PREVIEWS.insert( &post.hash, revision, rendering, preview ) ) )
+}
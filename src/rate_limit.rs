@@ -0,0 +1,63 @@
+//! Limits how fast something may happen, while allowing short bursts.
+//!
+//! A bucket holds a number of tokens, up to its burst size, and is refilled at a steady rate.
+//! Every time something happens, a token is taken, and when there are none left it has happened too fast.
+//! Things that can't be dropped, like the bytes that have already been sent, can be spent instead, which puts the bucket in debt until it has been refilled.
+
+use std::time::{Duration, Instant};
+
+
+
+pub struct TokenBucket {
+	tokens: f64,
+	last_refill: Instant
+}
+
+
+
+impl TokenBucket {
+
+	/// Creates a bucket that is full, for the given burst size.
+	pub fn new( burst: u32 ) -> Self {
+		Self {
+			tokens: burst as f64,
+			last_refill: Instant::now()
+		}
+	}
+
+	/// Takes a token, after refilling the bucket at `rate` tokens per second, up to `burst` tokens.
+	/// The rate and burst size are given every time, so that changes to them take effect right away.
+	/// Returns false if there is no token left.
+	pub fn take( &mut self, rate: u32, burst: u32 ) -> bool {
+		self.refill( rate, burst );
+
+		if self.tokens < 1.0 {
+			return false
+		}
+		self.tokens -= 1.0;
+		true
+	}
+
+	/// Takes the given number of tokens, even if there aren't that many left.
+	pub fn spend( &mut self, amount: u64, rate: u32, burst: u32 ) {
+		self.refill( rate, burst );
+		self.tokens -= amount as f64;
+	}
+
+	/// Returns how long it takes at `rate` tokens per second until the bucket is out of debt, which is zero if it isn't in debt.
+	pub fn debt( &mut self, rate: u32, burst: u32 ) -> Duration {
+		self.refill( rate, burst );
+
+		if self.tokens >= 0.0 || rate == 0 {
+			return Duration::from_secs( 0 )
+		}
+		Duration::from_secs_f64( -self.tokens / rate as f64 )
+	}
+
+	fn refill( &mut self, rate: u32, burst: u32 ) {
+		let now = Instant::now();
+		let elapsed = now.duration_since( self.last_refill ).as_secs_f64();
+		self.last_refill = now;
+		self.tokens = ( self.tokens + elapsed * rate as f64 ).min( burst as f64 );
+	}
+}
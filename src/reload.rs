@@ -0,0 +1,53 @@
+//! Reloads the configuration file while the node runs, on SIGHUP or from the admin pages of the web interface.
+//!
+//! The settings that are looked up whenever they are used, like the page size, the session timeout and the log levels, take effect right away.
+//! The relay power takes effect for the connections to swarms that are made after the reload.
+//! The settings that are only used while the node starts, like the data directory and the address of the web interface, keep their values until the node is restarted.
+
+#[cfg(unix)]
+use actix_web::rt::signal::unix::{signal, SignalKind};
+use tracing::{info, warn};
+#[cfg(unix)]
+use tracing::error;
+
+use crate::{
+	config,
+	logging
+};
+
+
+
+/// Reloads the configuration file, and returns the names of the settings that have been changed but need a restart to take effect.
+pub fn reload() -> Result<Vec<&'static str>, config::Error> {
+	let restart = config::reload()?;
+	logging::reload();
+
+	if restart.is_empty() {
+		info!("Configuration reloaded.");
+	} else {
+		warn!("Configuration reloaded, but the changes to {} only take effect after a restart.", restart.join(", "));
+	}
+	Ok( restart )
+}
+
+/// Reloads the configuration file every time that SIGHUP is received.
+/// A configuration file with errors is ignored, so that the node keeps running with the settings that it has.
+#[cfg(unix)]
+pub async fn reload_on_hangup() {
+	let mut hangup = match signal( SignalKind::hangup() ) {
+		Err(e) => {
+			warn!("Unable to listen for SIGHUP: {}", e);
+			return
+		},
+		Ok(s) => s
+	};
+
+	while hangup.recv().await.is_some() {
+		if let Err(e) = reload() {
+			error!("Unable to reload the configuration: {}", e);
+		}
+	}
+}
+
+#[cfg(not(unix))]
+pub async fn reload_on_hangup() {}
@@ -0,0 +1,122 @@
+//! The renderers that convert the content of posts to HTML, one for each content format.
+//!
+//! Every renderer returns HTML that is safe to put into a page as is.
+//! Content in a markup language comes from other peers, so the HTML generated from it is sanitized.
+//! Plain text is escaped.
+//!
+//! To support a new content format, implement `Renderer` for it and return it from `for_format`.
+
+use pulldown_cmark::{self, Options, Parser};
+
+use crate::post::ContentFormat;
+use crate::preview::{self, MORE_MARKER};
+
+
+
+/// Additional information that renderers may use.
+#[derive(Default)]
+pub struct RenderContext<'a> {
+	/// Passages to mark, as ranges of character offsets in the content.
+	/// Ignored by renderers that don't support highlights.
+	pub highlights: &'a [(usize, usize)]
+}
+
+/// Converts content of a certain format to sanitized HTML.
+///
+/// The content may still contain the `<!--more-->` marker, which should not show up in the HTML.
+pub trait Renderer: Sync {
+	fn render( &self, content: &str, context: &RenderContext ) -> String;
+
+	/// Whether the offsets of highlights can be related to the rendered content.
+	fn supports_highlights( &self ) -> bool { false }
+}
+
+pub struct PlainRenderer;
+
+pub struct MarkdownRenderer;
+
+pub struct ReStructuredTextRenderer;
+
+
+
+/// Returns the renderer for content of the given format.
+pub fn for_format( format: ContentFormat ) -> &'static dyn Renderer {
+	match format {
+		ContentFormat::Plain => &PlainRenderer,
+		ContentFormat::Markdown => &MarkdownRenderer,
+		ContentFormat::ReStructuredText => &ReStructuredTextRenderer
+	}
+}
+
+impl Renderer for PlainRenderer {
+
+	/// Puts every paragraph in its own `<p>` element, and separates lines with `<br />`.
+	fn render( &self, content: &str, context: &RenderContext ) -> String {
+
+		// Work with byte offsets, so that they can be compared with the positions of the lines in the content.
+		let byte_offset = |c: usize| content.char_indices().nth( c ).map(|(i, _)| i).unwrap_or( content.len() );
+		let ranges: Vec<(usize, usize)> = context.highlights.iter().map(|&(start, end)| (byte_offset( start ), byte_offset( end ))).collect();
+		let marker = content.find( MORE_MARKER ).map(|i| i..(i + MORE_MARKER.len()));
+
+		let mut html = String::new();
+		for paragraph in preview::split_paragraphs( content ) {
+			// Skip a paragraph that consists of nothing but the marker.
+			if paragraph == MORE_MARKER { continue }
+
+			html.push_str("<p>");
+			for (i, line) in paragraph.lines().enumerate() {
+				if i > 0 { html.push_str("<br />") }
+
+				let line_offset = line.as_ptr() as usize - content.as_ptr() as usize;
+				let mut marked = false;
+				for (j, c) in line.char_indices() {
+					let position = line_offset + j;
+					if marker.as_ref().map(|m| m.contains( &position )).unwrap_or(false) { continue }
+
+					let highlighted = ranges.iter().any(|&(start, end)| start <= position && position < end);
+					if highlighted != marked {
+						html.push_str( if highlighted { "<mark>" } else { "</mark>" } );
+						marked = highlighted;
+					}
+					preview::escape_char( c, &mut html );
+				}
+				if marked { html.push_str("</mark>") }
+			}
+			html.push_str("</p>");
+		}
+
+		html
+	}
+
+	fn supports_highlights( &self ) -> bool { true }
+}
+
+impl Renderer for MarkdownRenderer {
+
+	/// The marker is an HTML comment, which the sanitizer removes.
+	fn render( &self, content: &str, _context: &RenderContext ) -> String {
+		let options = Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES | Options::ENABLE_STRIKETHROUGH;
+		let mut html = String::new();
+		pulldown_cmark::html::push_html( &mut html, Parser::new_ext( content, options ) );
+		ammonia::clean( &html )
+	}
+}
+
+impl Renderer for ReStructuredTextRenderer {
+
+	/// Falls back to rendering the content as plain text if it is not valid reStructuredText.
+	fn render( &self, content: &str, context: &RenderContext ) -> String {
+		let source = content.replacen( MORE_MARKER, "", 1 );
+		let html = (|| {
+			let document = rst_parser::parse( &source ).ok()?;
+			let mut html = Vec::new();
+			rst_renderer::render_html( &document, &mut html, false ).ok()?;
+			String::from_utf8( html ).ok()
+		})();
+
+		match html {
+			Some(html) => ammonia::clean( &html ),
+			None => PlainRenderer.render( content, context )
+		}
+	}
+}
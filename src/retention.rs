@@ -0,0 +1,62 @@
+//! Removes the posts of the channels that we follow once they are older than their channel asked to keep them, along with the files and blocks that nothing uses anymore.
+//!
+//! The garbage is collected every so often in the background, and once right after the node starts.
+//! How much space it freed is logged, and added to the metrics.
+//! The posts of a channel are kept anyway if its subscription is set to keep everything.
+
+use std::{
+	sync::Arc,
+	time::Duration
+};
+
+use tracing::{error, info};
+
+use crate::{
+	config,
+	metrics,
+	persistence::{
+		self,
+		peer::now,
+		retention::Reclaimed,
+		Result
+	},
+	services::GnunetServices,
+	shutdown
+};
+
+
+
+/// Collects the garbage every so often, until the node shuts down.
+pub async fn collect_garbage_periodically( services: Arc<GnunetServices> ) {
+	loop {
+		// Before the setup has been done, there is nothing to collect.
+		if persistence::database_exists() {
+			match collect_garbage( &services ).await {
+				Err(e) => error!("Unable to collect garbage: {}", e),
+				Ok(r) => report( &r )
+			}
+		}
+
+		if !shutdown::sleep( Duration::from_secs( config::GARBAGE_COLLECTION_INTERVAL ) ).await { break }
+	}
+}
+
+/// Removes the posts that have expired, and the files and blocks that nothing uses.
+pub async fn collect_garbage( services: &Arc<GnunetServices> ) -> Result<Reclaimed> {
+	let db = persistence::Handle::connect( services.clone() ).await.map_err( persistence::Error::Database )?;
+
+	let now = now() as u64;
+	db.collect_garbage( now, now.saturating_sub( config::ORPHAN_GRACE_PERIOD * 1000 ) ).await
+}
+
+fn report( reclaimed: &Reclaimed ) {
+	metrics::POSTS_EXPIRED.inc_by( reclaimed.posts );
+	metrics::RECLAIMED_BYTES.inc_by( reclaimed.bytes );
+
+	if reclaimed.posts > 0 || reclaimed.files > 0 || reclaimed.blocks > 0 {
+		info!(
+			posts = reclaimed.posts, files = reclaimed.files, blocks = reclaimed.blocks, bytes = reclaimed.bytes,
+			"Collected garbage, {} KiB freed.", reclaimed.bytes / 1024
+		);
+	}
+}
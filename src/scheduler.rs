@@ -0,0 +1,75 @@
+//! Publishes the drafts that have been scheduled, once their time has come.
+//!
+//! Drafts are only signed when they are published, so the ego that they have been written for needs to be available at that time.
+//! A draft that can't be published yet is tried again the next time, without holding back the others.
+
+use std::{
+	sync::Arc,
+	time::Duration
+};
+
+use gnunet::identity::PublicKey;
+use tracing::{error, warn};
+
+use crate::{
+	config,
+	persistence::{
+		self,
+		audit::AuditAction,
+		peer::now,
+		Result
+	},
+	post::Post,
+	services::GnunetServices,
+	shutdown,
+	web::post_subject
+};
+
+
+
+/// Publishes the drafts whose time has come every so often, until the node shuts down.
+pub async fn publish_scheduled_drafts( services: Arc<GnunetServices> ) {
+	loop {
+		if !shutdown::sleep( Duration::from_secs( config::DRAFT_SCHEDULE_INTERVAL ) ).await { break }
+
+		// Before the setup has been done, there is nothing to publish.
+		if !persistence::database_exists() { continue }
+
+		if let Err(e) = publish_due_drafts( &services ).await {
+			error!("Unable to publish the scheduled drafts: {}", e);
+		}
+	}
+}
+
+async fn publish_due_drafts( services: &Arc<GnunetServices> ) -> Result<()> {
+	let db = persistence::Handle::connect( services.clone() ).await.map_err( persistence::Error::Database )?;
+
+	for due in db.list_due_drafts( now() as _ ).await? {
+		if let Err(e) = publish_draft( services, &db, &due.ego, &due.publisher, due.draft_id ).await {
+			warn!(publisher = %due.publisher, draft = due.draft_id, "Unable to publish draft: {}", e);
+		}
+	}
+	Ok(())
+}
+
+/// Signs and publishes a draft of the given publisher with the key of the given ego, and records this in the audit log.
+/// Returns the post, or `None` if there is no such draft.
+pub async fn publish_draft( services: &GnunetServices, db: &persistence::Handle, ego: &str, publisher: &PublicKey, draft_id: i64 ) -> Result<Option<Post>> {
+	let private_key = services.lookup_ego( ego ).await?;
+
+	let timeline = match db.get_timeline( publisher ).await? {
+		None => return Ok(None),
+		Some(t) => t
+	};
+	let channel = match timeline.get_channel().await? {
+		None => return Ok(None),
+		Some(c) => c
+	};
+	let post = match channel.publish_draft( &timeline, &private_key, draft_id ).await? {
+		None => return Ok(None),
+		Some(p) => p
+	};
+	db.record_action( Some( ego ), AuditAction::PostCreated, &post_subject( publisher, post.id ) ).await?;
+
+	Ok( Some( post ) )
+}
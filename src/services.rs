@@ -0,0 +1,238 @@
+//! A facade over the gnunet services that the node uses.
+//!
+//! The handles to the services are only connected when they are first needed, and are cached after that.
+//! When a request to a service fails, the connection is assumed to be broken, so it gets reconnected and the request is tried once more.
+
+use std::{
+	fmt,
+	sync::Arc
+};
+
+use async_std::sync::{Mutex, MutexGuard};
+use gnunet::{
+	cadet,
+	dht,
+	identity::{self, PrivateKey}
+};
+
+use crate::{
+	discovery::Discovery,
+	transport::{CadetTransport, Transport}
+};
+
+
+
+/// The number of times a request is attempted, before giving up.
+const ATTEMPTS: usize = 2;
+
+pub struct GnunetServices {
+	gnunet: gnunet::Handle,
+	identity: Mutex<Option<identity::Handle>>,
+	transport: Mutex<Option<Arc<dyn Transport>>>,
+	discovery: Mutex<Option<Arc<Discovery>>>
+}
+
+#[derive(Debug)]
+pub enum Error {
+	/// The service could not be reached.
+	Unavailable( &'static str, gnunet::Error ),
+	/// The service could be reached, but the request failed.
+	Request( &'static str, gnunet::Error ),
+	/// There is no ego with the given name.
+	EgoNotFound( String )
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+
+
+impl GnunetServices {
+
+	pub fn new( gnunet: gnunet::Handle ) -> Self {
+		Self {
+			gnunet,
+			identity: Mutex::new( None ),
+			transport: Mutex::new( None ),
+			discovery: Mutex::new( None )
+		}
+	}
+
+	/// The handle to gnunet itself.
+	pub fn gnunet( &self ) -> &gnunet::Handle {
+		&self.gnunet
+	}
+
+	/// Returns whether or not the identity service can be reached.
+	pub async fn is_available( &self ) -> bool {
+		self.check().await.is_ok()
+	}
+
+	/// Connects to the identity service if that hasn't been done yet, and returns the error if it can't be reached.
+	pub async fn check( &self ) -> Result<()> {
+		self.identity().await.map(|_| ())
+	}
+
+	/// Looks up the private key of the ego with the given name.
+	pub async fn lookup_ego( &self, name: &str ) -> Result<PrivateKey> {
+		let mut guard = self.identity().await?;
+
+		for attempt in 1..=ATTEMPTS {
+			match guard.as_mut().unwrap().lookup( name ).await {
+				Ok(Some(key)) => return Ok(key),
+				Ok(None) => return Err( Error::EgoNotFound( name.to_owned() ) ),
+				Err(e) => if attempt == ATTEMPTS {
+					*guard = None;
+					return Err( Error::Request("identity", e) )
+				}
+			}
+
+			Self::reconnect_identity( &self.gnunet, &mut guard ).await?;
+		}
+		unreachable!()
+	}
+
+	/// Creates a new ego with the given private key.
+	/// Returns false if an ego with that name already exists.
+	pub async fn create_ego( &self, name: &str, private_key: PrivateKey ) -> Result<bool> {
+		let mut guard = self.identity().await?;
+
+		for attempt in 1..=ATTEMPTS {
+			match guard.as_mut().unwrap().create( name, private_key.clone() ).await {
+				Ok(success) => return Ok(success),
+				Err(e) => if attempt == ATTEMPTS {
+					*guard = None;
+					return Err( Error::Request("identity", e) )
+				}
+			}
+
+			Self::reconnect_identity( &self.gnunet, &mut guard ).await?;
+		}
+		unreachable!()
+	}
+
+	/// Lists the names of all egos, along with their private keys.
+	pub async fn list_egos( &self ) -> Result<Vec<(String, PrivateKey)>> {
+		let mut guard = self.identity().await?;
+
+		for attempt in 1..=ATTEMPTS {
+			match guard.as_mut().unwrap().list().await {
+				Ok(egos) => return Ok(egos),
+				Err(e) => if attempt == ATTEMPTS {
+					*guard = None;
+					return Err( Error::Request("identity", e) )
+				}
+			}
+
+			Self::reconnect_identity( &self.gnunet, &mut guard ).await?;
+		}
+		unreachable!()
+	}
+
+	/// Gives an ego another name.
+	/// Returns false if the ego doesn't exist, or if an ego with the new name already exists.
+	pub async fn rename_ego( &self, name: &str, new_name: &str ) -> Result<bool> {
+		let mut guard = self.identity().await?;
+
+		for attempt in 1..=ATTEMPTS {
+			match guard.as_mut().unwrap().rename( name, new_name ).await {
+				Ok(success) => return Ok(success),
+				Err(e) => if attempt == ATTEMPTS {
+					*guard = None;
+					return Err( Error::Request("identity", e) )
+				}
+			}
+
+			Self::reconnect_identity( &self.gnunet, &mut guard ).await?;
+		}
+		unreachable!()
+	}
+
+	/// Deletes an ego, along with its private key.
+	pub async fn delete_ego( &self, name: &str ) -> Result<()> {
+		let mut guard = self.identity().await?;
+
+		for attempt in 1..=ATTEMPTS {
+			match guard.as_mut().unwrap().delete( name ).await {
+				Ok(true) => return Ok(()),
+				Ok(false) => return Err( Error::EgoNotFound( name.to_owned() ) ),
+				Err(e) => if attempt == ATTEMPTS {
+					*guard = None;
+					return Err( Error::Request("identity", e) )
+				}
+			}
+
+			Self::reconnect_identity( &self.gnunet, &mut guard ).await?;
+		}
+		unreachable!()
+	}
+
+	/// Returns the shared transport that the swarms are joined over, which connects to the CADET service the first time.
+	pub async fn transport( &self ) -> Result<Arc<dyn Transport>> {
+		let mut guard = self.transport.lock().await;
+
+		if guard.is_none() {
+			let handle = cadet::Handle::connect( self.gnunet.clone() ).await
+				.map_err(|e| Error::Unavailable("cadet", e))?;
+			*guard = Some( Arc::new( CadetTransport::new( Arc::new( Mutex::new( handle ) ) ) ) );
+		}
+
+		Ok( guard.as_ref().unwrap().clone() )
+	}
+
+	/// Forgets the transport, so that the next call to `transport` connects to the CADET service again.
+	/// This should be called when the transport turns out to be broken.
+	pub async fn reset_transport( &self ) {
+		*self.transport.lock().await = None;
+	}
+
+	/// Returns the shared peer discovery, which connects to the DHT service the first time.
+	pub async fn discovery( &self ) -> Result<Arc<Discovery>> {
+		let mut guard = self.discovery.lock().await;
+
+		if guard.is_none() {
+			let handle = dht::Handle::connect( self.gnunet.clone() ).await
+				.map_err(|e| Error::Unavailable("dht", e))?;
+			let local_peer = self.gnunet.peer_identity().await
+				.map_err(|e| Error::Request("peer identity", e))?;
+			*guard = Some( Arc::new( Discovery::new( Arc::new( Mutex::new( handle ) ), local_peer ) ) );
+		}
+
+		Ok( guard.as_ref().unwrap().clone() )
+	}
+
+	/// Forgets the peer discovery, so that the next call to `discovery` connects to the DHT service again.
+	pub async fn reset_discovery( &self ) {
+		*self.discovery.lock().await = None;
+	}
+
+	/// Returns the identity service handle, connecting to it if that hasn't been done yet.
+	async fn identity( &self ) -> Result<MutexGuard<'_, Option<identity::Handle>>> {
+		let mut guard = self.identity.lock().await;
+
+		if guard.is_none() {
+			Self::reconnect_identity( &self.gnunet, &mut guard ).await?;
+		}
+		Ok( guard )
+	}
+
+	async fn reconnect_identity( gnunet: &gnunet::Handle, guard: &mut Option<identity::Handle> ) -> Result<()> {
+		*guard = None;
+		*guard = Some( identity::Handle::connect( gnunet.clone() ).await
+			.map_err(|e| Error::Unavailable("identity", e))? );
+		Ok(())
+	}
+}
+
+
+
+impl fmt::Display for Error {
+	fn fmt( &self, f: &mut fmt::Formatter<'_> ) -> fmt::Result {
+		match self {
+			Self::Unavailable(service, e) => write!(f, "gnunet {} service unavailable: {}", service, e),
+			Self::Request(service, e) => write!(f, "gnunet {} service request failed: {}", service, e),
+			Self::EgoNotFound(name) => write!(f, "ego \"{}\" not found", name)
+		}
+	}
+}
+
+impl std::error::Error for Error {}
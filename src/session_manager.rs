@@ -1,8 +1,27 @@
 //! The session manager is responsible for connecting responses to requests.
+//!
+//! Every channel with a peer has a session manager of its own, which multiplexes the requests that we send over the channel.
+//! It gives every request an id that no other request on the channel is using, so that any number of them can be in flight at the same time.
+//! Only the peer that a request was sent to can respond to it, because its response can only arrive on the same channel.
+//! A request is waited for until its deadline, after which its session is cleaned up, as it is when the request is cancelled by dropping it.
+//! Sessions that are left behind anyway, because their deadline has passed or their receiver is gone, are swept up whenever a request is opened.
+//!
+//! Responses are buffered until the requester picks them up.
+//! To keep the memory this takes bounded, responses can't exceed a maximum size, and the total size of all buffered responses is limited.
+//! Large responses arrive in parts, which are put back together here before the requester gets them.
+//! The parts that have arrived count towards the limits as well.
 
 use std::{
 	collections::HashMap,
-	time::Duration
+	fmt,
+	mem,
+	ops::Deref,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc,
+		Mutex
+	},
+	time::{Duration, Instant}
 };
 
 use async_std::{
@@ -10,55 +29,256 @@ use async_std::{
 	future::timeout
 };
 
+use crate::config;
 
 
-pub const SESSION_TIMEOUT: u64 = 10000;
+
+/// The total number of bytes of all responses that are buffered, for all session managers together.
+static BUFFERED_BYTES: AtomicUsize = AtomicUsize::new( 0 );
 
 
 
 pub struct SessionManager {
-	sessions: HashMap<u32, SessionData>
+	state: Mutex<State>,
+	max_response_size: usize
+}
+
+struct State {
+	sessions: HashMap<u32, SessionData>,
+	/// The id that is tried first for the next request.
+	next_id: u32
 }
 
 struct SessionData {
-	tx: Sender<Vec<u8>>
+	tx: Sender<Response>,
+	/// When the request stops being waited for.
+	deadline: Instant,
+	/// The parts of the response that have arrived so far, put together.
+	partial: Vec<u8>,
+	/// The sequence number of the part that is expected next.
+	next_part: u32
+}
+
+/// A request that is in flight.
+/// Dropping it cancels the request, after which its response is ignored.
+pub struct PendingRequest {
+	id: u32,
+	rx: Receiver<Response>,
+	deadline: Instant,
+	manager: Arc<SessionManager>
+}
+
+/// A response that is buffered.
+/// Its bytes are accounted for until it is dropped.
+pub struct Response ( Vec<u8> );
+
+/// The reasons for which a response can be refused.
+#[derive(Debug)]
+pub enum RespondError {
+	/// The response is larger than the maximum response size.
+	/// This is the fault of the peer that sent it.
+	TooLarge( usize ),
+	/// Buffering the response would exceed the limit on the total size of buffered responses.
+	BufferFull( usize ),
+	/// A part of a response arrived with another sequence number than the one that was expected.
+	/// This is the fault of the peer that sent it.
+	OutOfOrder( u32 )
 }
 
 
 
 impl SessionManager {
 
-	pub fn new() -> Self {
+	/// Creates a session manager that refuses responses larger than `max_response_size` bytes.
+	pub fn new( max_response_size: usize ) -> Self {
 		Self {
-			sessions: HashMap::new()
+			state: Mutex::new( State {
+				sessions: HashMap::new(),
+				next_id: 0
+			}),
+			max_response_size
 		}
 	}
 
-	/// Returns the message as a byte vector, or nothing if not response was received within the `SESSION_TIMEOUT`.
-	pub async fn request( &mut self, session_id: u32 ) -> Option<Vec<u8>> {
+	/// Opens a session for a request that is about to be sent, under an id that no other request on the channel is using.
+	/// The request is waited for until the given timeout has passed.
+	/// Stale sessions are swept up first.
+	pub fn open( self: &Arc<Self>, timeout: Duration ) -> PendingRequest {
 		let (tx, rx) = bounded( 1 );
+		let deadline = Instant::now() + timeout;
 
-		self.sessions.insert( session_id, SessionData {tx});
+		let mut state = self.state.lock().unwrap();
+		state.sweep();
 
-		match timeout( Duration::from_millis(SESSION_TIMEOUT), rx.recv() ).await {
-			Err(_) => None,
-			Ok(r) => Some( r.expect("channel closed") )
+		// With less than 2^32 requests in flight, there is always an id that is free.
+		let mut id = state.next_id;
+		while state.sessions.contains_key( &id ) {
+			id = id.wrapping_add( 1 );
 		}
+		state.next_id = id.wrapping_add( 1 );
+		state.sessions.insert( id, SessionData {
+			tx,
+			deadline,
+			partial: Vec::new(),
+			next_part: 0
+		});
+
+		PendingRequest {
+			id,
+			rx,
+			deadline,
+			manager: self.clone()
+		}
+	}
+
+	/// Removes a session that is no longer waited for.
+	fn close( &self, session_id: u32 ) {
+		self.state.lock().unwrap().sessions.remove( &session_id );
 	}
 
 	/// Provides the response message that will be relayed to the requester.
+	/// The message is only copied after it has been checked against the limits.
+	/// Returns whether or not the session (still) existed.
+	pub fn respond( &self, session_id: u32, message: &[u8] ) -> Result<bool, RespondError> {
+
+		if message.len() > self.max_response_size {
+			return Err( RespondError::TooLarge( message.len() ) )
+		}
+
+		let session_data = match self.state.lock().unwrap().sessions.remove( &session_id ) {
+			None => return Ok(false),
+			Some(s) => s
+		};
+		if session_data.deadline <= Instant::now() {
+			return Ok(false)
+		}
+
+		let buffered = BUFFERED_BYTES.fetch_add( message.len(), Ordering::AcqRel ) + message.len();
+		if buffered > config::MAX_BUFFERED_RESPONSE_BYTES {
+			BUFFERED_BYTES.fetch_sub( message.len(), Ordering::AcqRel );
+			return Err( RespondError::BufferFull( message.len() ) )
+		}
+		let response = Response( message.to_owned() );
+
+		// If the requester has stopped waiting, the response is dropped here.
+		let _ = session_data.tx.try_send( response );
+
+		Ok(true)
+	}
+
+	/// Adds a part of a response to the parts that came before it.
+	/// Once the last part has arrived, the whole response is relayed to the requester, in the same form as if it had been sent at once.
 	/// Returns whether or not the session (still) existed.
-	pub async fn respond( &mut self, session_id: u32, message: Vec<u8> ) -> bool {
+	pub fn respond_part( &self, session_id: u32, sequence: u32, last: bool, data: &[u8] ) -> Result<bool, RespondError> {
+
+		let mut state = self.state.lock().unwrap();
+		let session_data = match state.sessions.get_mut( &session_id ) {
+			None => return Ok(false),
+			Some(s) => s
+		};
+		if session_data.deadline <= Instant::now() {
+			state.sessions.remove( &session_id );
+			return Ok(false)
+		}
+		if sequence != session_data.next_part {
+			state.sessions.remove( &session_id );
+			return Err( RespondError::OutOfOrder( sequence ) )
+		}
+
+		// The response starts with the session id, like a response that is sent at once.
+		let header = if sequence == 0 { 4 } else { 0 };
+		let size = session_data.partial.len() + header + data.len();
+		if size > self.max_response_size {
+			state.sessions.remove( &session_id );
+			return Err( RespondError::TooLarge( size ) )
+		}
+		let buffered = BUFFERED_BYTES.fetch_add( header + data.len(), Ordering::AcqRel ) + header + data.len();
+		if buffered > config::MAX_BUFFERED_RESPONSE_BYTES {
+			BUFFERED_BYTES.fetch_sub( header + data.len(), Ordering::AcqRel );
+			state.sessions.remove( &session_id );
+			return Err( RespondError::BufferFull( size ) )
+		}
+		if sequence == 0 {
+			session_data.partial.extend_from_slice( &session_id.to_le_bytes() );
+		}
+		session_data.partial.extend_from_slice( data );
+		session_data.next_part += 1;
 
-		match self.sessions.remove( &session_id ) {
-			None => false,
-			Some( session_data ) => {
+		if !last {
+			return Ok(true)
+		}
+		let mut session_data = state.sessions.remove( &session_id ).unwrap();
+		drop( state );
+		// The bytes are accounted for by the response from now on.
+		let response = Response( mem::take( &mut session_data.partial ) );
+
+		let _ = session_data.tx.try_send( response );
+
+		Ok(true)
+	}
+}
+
+impl State {
+
+	/// Removes the sessions that have passed their deadline, and the ones that nobody waits for anymore.
+	fn sweep( &mut self ) {
+		let now = Instant::now();
+		self.sessions.retain(|_, s| s.deadline > now && !s.tx.is_closed());
+	}
+}
+
+impl PendingRequest {
+
+	/// The id under which the request is sent, which the response refers to.
+	pub fn id( &self ) -> u32 {
+		self.id
+	}
+
+	/// Waits for the response, until the deadline of the request.
+	/// Returns nothing if no response was received in time.
+	pub async fn wait( self ) -> Option<Response> {
+		let remaining = self.deadline.saturating_duration_since( Instant::now() );
+
+		// The sender is only dropped without a response when the session is closed, which happens when it has expired.
+		timeout( remaining, self.rx.recv() ).await.ok()?.ok()
+	}
+}
+
+impl Drop for PendingRequest {
+	fn drop( &mut self ) {
+		// After a response has been received, the session is already gone.
+		self.manager.close( self.id );
+	}
+}
 
-				session_data.tx.send(message).await.expect("channel closed");
-				session_data.tx.close();
+impl Drop for SessionData {
+	fn drop( &mut self ) {
+		BUFFERED_BYTES.fetch_sub( self.partial.len(), Ordering::AcqRel );
+	}
+}
 
-				true
-			}
+impl Deref for Response {
+	type Target = [u8];
+
+	fn deref( &self ) -> &[u8] {
+		&self.0
+	}
+}
+
+impl Drop for Response {
+	fn drop( &mut self ) {
+		BUFFERED_BYTES.fetch_sub( self.0.len(), Ordering::AcqRel );
+	}
+}
+
+impl fmt::Display for RespondError {
+	fn fmt( &self, f: &mut fmt::Formatter<'_> ) -> fmt::Result {
+		match self {
+			Self::TooLarge(size) => write!(f, "response of {} bytes exceeds the maximum response size", size),
+			Self::BufferFull(size) => write!(f, "no room to buffer a response of {} bytes", size),
+			Self::OutOfOrder(sequence) => write!(f, "response part {} arrived out of order", sequence)
 		}
 	}
-}
\ No newline at end of file
+}
+
+impl std::error::Error for RespondError {}
@@ -0,0 +1,241 @@
+//! The first-run setup of a node.
+//!
+//! When no database exists yet, the user is guided through the setup at `/setup`.
+//! The choices made there are stored in the database, except for the data directory itself,
+//!  which is remembered in a small location file in the user's config directory.
+//!
+//! The data directory is the first of these that is given:
+//! 1. the `--data-dir` option on the command line;
+//! 2. the `QUARTZNET_DATA_DIR` environment variable;
+//! 3. the `data_dir` setting of the configuration file;
+//! 4. the directory that was chosen during the setup;
+//! 5. `~/.quartznet`, if a node has been set up there before;
+//! 6. `quartznet` in the XDG data directory, which is `~/.local/share` unless `XDG_DATA_HOME` says otherwise.
+
+use std::{
+	env,
+	fs,
+	io,
+	path::PathBuf,
+	sync::Arc
+};
+
+use gnunet::crypto::HashCode;
+use hmac::Hmac;
+use lazy_static::lazy_static;
+use rand::{distributions::Alphanumeric, Rng, RngCore};
+use serde::*;
+use sha2::Sha512;
+use subtle::ConstantTimeEq;
+
+use crate::{
+	config,
+	persistence,
+	services::GnunetServices
+};
+
+
+
+/// The setting that is set to "false" while the setup is in progress, and to "true" once it has completed.
+pub const SETTING_SETUP_COMPLETE: &str = "setup_complete";
+/// The setting that holds the salted hash of the admin password.
+pub const SETTING_ADMIN_PASSWORD: &str = "admin_password";
+/// The setting that holds the relay power that follows from the chosen contribution profile.
+pub const SETTING_RELAY_POWER: &str = "relay_power";
+/// The setting that holds the number of parents that every swarm stays connected to, see `tuning`.
+pub const SETTING_PARENT_COUNT: &str = "parent_count";
+/// The setting that is set to "true" when rebroadcasts always make way for requests and responses.
+pub const SETTING_BACKGROUND_REBROADCASTS: &str = "background_rebroadcasts";
+/// The settings that hold the number of seconds between connection checks, and the range of delays before searching for a swarm again.
+pub const SETTING_CONNECTION_CHECK_INTERVAL: &str = "connection_check_interval";
+pub const SETTING_RECONNECT_MIN_DELAY: &str = "reconnect_min_delay";
+pub const SETTING_RECONNECT_MAX_DELAY: &str = "reconnect_max_delay";
+/// The setting that overrides the maximum size of a response from a peer, in bytes.
+pub const SETTING_MAX_RESPONSE_SIZE: &str = "max_response_size";
+/// The setting that overrides the number of seconds that a misbehaving peer is blocked for, per offense.
+pub const SETTING_BAD_PEER_BAN_DURATION: &str = "bad_peer_ban_duration";
+/// The setting that holds the offset of the user's timezone from UTC, in minutes.
+/// Times that the user enters or reads in the web interface are in this timezone.
+pub const SETTING_TIMEZONE_OFFSET: &str = "timezone_offset";
+/// The setting that holds the code of the language in which the texts of this node itself are written.
+pub const SETTING_LANGUAGE: &str = "language";
+
+/// The name by which hashes of passwords made with `hash_password` are recognized.
+const PASSWORD_HASH_SCHEME: &str = "pbkdf2-sha512";
+/// The number of rounds of PBKDF2 that new passwords are hashed with.
+const PASSWORD_HASH_ROUNDS: u32 = 210_000;
+/// The number of bytes of the salt of a password.
+const PASSWORD_SALT_LENGTH: usize = 16;
+/// The number of bytes of the hash of a password.
+const PASSWORD_HASH_LENGTH: usize = 64;
+
+/// The number of characters of the setup token.
+const SETUP_TOKEN_LENGTH: usize = 24;
+
+lazy_static! {
+	/// The token that has to be given to complete the setup from another computer, see `check_setup_token`.
+	static ref SETUP_TOKEN: String = rand::thread_rng().sample_iter( &Alphanumeric ).take( SETUP_TOKEN_LENGTH ).map( char::from ).collect();
+}
+
+/// How much a node contributes to the swarms it participates in.
+#[derive(Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContributionProfile {
+	/// Accepts as few child peers as possible, for metered or slow connections.
+	Light,
+	Standard,
+	/// Accepts many child peers, for nodes with a lot of bandwidth.
+	Relay
+}
+
+
+
+impl ContributionProfile {
+
+	pub fn relay_power( &self ) -> u8 {
+		match self {
+			Self::Light => 0,
+			Self::Standard => 1,
+			Self::Relay => 4
+		}
+	}
+}
+
+/// The file that remembers which data directory was chosen during the setup.
+fn location_file() -> Option<PathBuf> {
+	env::var_os("HOME").map(|home| PathBuf::from( home ).join(".config").join("quartznet").join("data-dir"))
+}
+
+/// Returns the data directory to use when none has been chosen.
+pub fn default_data_dir() -> PathBuf {
+	let home = env::var_os("HOME").map(PathBuf::from);
+
+	// Nodes that have been set up before the XDG data directory was used keep their data where it is.
+	if let Some(home) = &home {
+		let legacy = home.join(".quartznet");
+		if legacy.join("db.sqlite").exists() {
+			return legacy
+		}
+	}
+
+	// Relative paths in XDG_DATA_HOME are invalid according to the specification, and should be ignored.
+	let data_home = env::var_os("XDG_DATA_HOME").map(PathBuf::from).filter(|p| p.is_absolute())
+		.or_else(|| home.map(|h| h.join(".local").join("share")));
+	match data_home {
+		None => PathBuf::from(".quartznet"),
+		Some(dir) => dir.join("quartznet")
+	}
+}
+
+/// Chooses the data directory, and creates it if it doesn't exist yet.
+/// `option` is the directory that was given on the command line, if any.
+pub fn load_data_dir( option: Option<PathBuf> ) -> io::Result<()> {
+	let path = match option.or_else(|| env::var_os( config::DATA_DIR_VAR ).filter(|v| !v.is_empty()).map(PathBuf::from)) {
+		Some(p) => p,
+		None => match config::get().data_dir.clone() {
+			Some(p) => p,
+			None => match load_location_file()? {
+				Some(p) => p,
+				None => default_data_dir()
+			}
+		}
+	};
+
+	fs::create_dir_all( &path )?;
+	persistence::set_data_dir( path );
+	Ok(())
+}
+
+/// Reads the data directory that was chosen during the setup, if any.
+fn load_location_file() -> io::Result<Option<PathBuf>> {
+	let path = match location_file() {
+		None => return Ok(None),
+		Some(p) => p
+	};
+
+	match fs::read_to_string( path ) {
+		Err(e) => if e.kind() == io::ErrorKind::NotFound { Ok(None) } else { Err(e) },
+		Ok(content) => Ok( Some( PathBuf::from( content.trim() ) ) )
+	}
+}
+
+/// Creates the data directory, starts using it, and remembers it for the next time the node starts.
+pub fn store_data_dir( path: PathBuf ) -> io::Result<()> {
+	fs::create_dir_all( &path )?;
+
+	if let Some(file) = location_file() {
+		fs::create_dir_all( file.parent().unwrap() )?;
+		fs::write( file, path.to_string_lossy().as_bytes() )?;
+	}
+
+	persistence::set_data_dir( path );
+	Ok(())
+}
+
+/// Returns whether or not the setup still needs to be completed.
+/// This is the case when there is no database yet, or when a previous setup attempt didn't finish.
+pub async fn is_required( services: Arc<GnunetServices> ) -> persistence::Result<bool> {
+	if !persistence::database_exists() {
+		return Ok(true)
+	}
+
+	let db = persistence::Handle::connect( services ).await?;
+	Ok( db.load_setting( SETTING_SETUP_COMPLETE ).await?.as_deref() == Some("false") )
+}
+
+/// The token that has to be given to complete the setup from another computer than the one that the node runs on.
+/// It is new every time the node starts, and is only written to the log, so that only whoever runs the node can set it up from elsewhere.
+pub fn setup_token() -> &'static str {
+	&SETUP_TOKEN
+}
+
+/// Checks the given setup token, in constant time like the passwords.
+pub fn check_setup_token( token: &str ) -> bool {
+	token.trim().as_bytes().ct_eq( SETUP_TOKEN.as_bytes() ).into()
+}
+
+/// Hashes the password with a new salt.
+/// The result is in the form `pbkdf2-sha512$rounds$salt$hash`, with the salt and the hash in base64.
+pub fn hash_password( password: &str ) -> String {
+	let mut salt = [0u8; PASSWORD_SALT_LENGTH];
+	rand::thread_rng().fill_bytes( &mut salt );
+
+	let hash = derive_password_key( password, &salt, PASSWORD_HASH_ROUNDS );
+	format!("{}${}${}${}", PASSWORD_HASH_SCHEME, PASSWORD_HASH_ROUNDS, base64::encode( &salt ), base64::encode( &hash ))
+}
+
+/// Checks the password against a hash that was created with `hash_password`, or with the single salted SHA-512 that was used before it.
+/// The hashes are compared in constant time, so that the time that it takes doesn't tell how much of it matched.
+pub fn verify_password( password: &str, hash: &str ) -> bool {
+	let parts: Vec<&str> = hash.split('$').collect();
+	match parts.as_slice() {
+		[scheme, rounds, salt, expected] if *scheme == PASSWORD_HASH_SCHEME => {
+			let (rounds, salt, expected) = match (rounds.parse::<u32>(), base64::decode( salt ), base64::decode( expected )) {
+				(Ok(r), Ok(s), Ok(e)) if r > 0 => (r, s, e),
+				_ => return false
+			};
+			derive_password_key( password, &salt, rounds )[..].ct_eq( &expected[..] ).into()
+		},
+		[salt, expected] => legacy_salted_hash( salt, password ).as_bytes().ct_eq( expected.as_bytes() ).into(),
+		_ => false
+	}
+}
+
+/// Whether the hash was made in an older way than `hash_password` makes them, and should be replaced once the password is known.
+pub fn needs_rehash( hash: &str ) -> bool {
+	hash.split('$').next() != Some( PASSWORD_HASH_SCHEME )
+}
+
+fn derive_password_key( password: &str, salt: &[u8], rounds: u32 ) -> Vec<u8> {
+	let mut key = vec![0u8; PASSWORD_HASH_LENGTH];
+	pbkdf2::pbkdf2::<Hmac<Sha512>>( password.as_bytes(), salt, rounds, &mut key );
+	key
+}
+
+/// The hash of the passwords that were stored before `hash_password` used PBKDF2.
+fn legacy_salted_hash( salt: &str, password: &str ) -> String {
+	let mut data = salt.as_bytes().to_vec();
+	data.extend_from_slice( password.as_bytes() );
+
+	HashCode::generate( &data ).to_string()
+}
@@ -0,0 +1,113 @@
+//! The links with which channels and posts are shared with people outside of their swarm.
+//!
+//! A share link names the channel, together with some peers of its swarm, so that a new subscriber can join the swarm even while the owner of the channel is offline.
+//! The peers follow the address of the channel, separated by dots: `/share/<channel>.<peer>.<peer>`, optionally followed by `/post/<id>`.
+//!
+//! Outside of the web interface, the same link is written as a URI: `quartznet://channel/<channel>.<peer>.<peer>`, again optionally followed by `/post/<id>`.
+//! Unlike the path, the URI doesn't depend on the node that it is opened on, so it is what is put in the QR codes.
+
+use gnunet::identity::PublicKey;
+
+use crate::config;
+
+
+
+pub struct ShareLink {
+	pub address: PublicKey,
+	/// Peers of the swarm of the channel, the ones to try first in front.
+	pub peers: Vec<PublicKey>,
+	/// The post that is shared, if not the whole channel.
+	pub post_id: Option<u64>
+}
+
+
+
+/// The segment of the path that the compact notation follows.
+const PATH_PREFIX: &str = "/share/";
+/// What the compact notation follows in a URI.
+pub const URI_PREFIX: &str = "quartznet://channel/";
+
+
+
+impl ShareLink {
+
+	/// The compact notation of the channel and its peers.
+	pub fn hint( &self ) -> String {
+		let mut hint = self.address.to_string();
+		for peer in &self.peers {
+			hint.push('.');
+			hint.push_str( &peer.to_string() );
+		}
+		hint
+	}
+
+	/// The path of the link, which can be opened on the web interface of any node.
+	pub fn to_path( &self ) -> String {
+		match self.post_id {
+			None => format!("{}{}", PATH_PREFIX, self.hint()),
+			Some(id) => format!("{}{}/post/{}", PATH_PREFIX, self.hint(), id)
+		}
+	}
+
+	/// The URI of the link, which can be opened on any node with `/open`.
+	pub fn to_uri( &self ) -> String {
+		match self.post_id {
+			None => format!("{}{}", URI_PREFIX, self.hint()),
+			Some(id) => format!("{}{}/post/{}", URI_PREFIX, self.hint(), id)
+		}
+	}
+
+	/// Parses the compact notation of a channel and its peers.
+	/// Peers that can't be parsed are left out, and so are the ones beyond `config::CACHED_PEERS_MAX`.
+	pub fn parse_hint( hint: &str ) -> Option<Self> {
+		let mut parts = hint.trim().split('.');
+		let address = PublicKey::from_string( parts.next()? )?;
+		let peers = parts
+			.filter_map(|p| PublicKey::from_string( p ))
+			.filter(|p| *p != address)
+			.take( config::CACHED_PEERS_MAX )
+			.collect();
+
+		Some( Self {
+			address,
+			peers,
+			post_id: None
+		})
+	}
+
+	/// Parses what a user has been given to follow a channel with.
+	/// This can be a URI, a whole share link (with or without the host), its compact notation, or just the address of the channel.
+	pub fn parse( string: &str ) -> Option<Self> {
+		let string = string.trim();
+		if let Some(link) = Self::parse_uri( string ) {
+			return Some( link )
+		}
+		let path = match string.find( PATH_PREFIX ) {
+			None => return Self::parse_hint( string ),
+			Some(i) => &string[(i + PATH_PREFIX.len())..]
+		};
+
+		Self::parse_segments( path )
+	}
+
+	/// Parses a `quartznet://` URI.
+	/// Returns `None` for anything else, including the URIs of things other than channels and posts.
+	pub fn parse_uri( uri: &str ) -> Option<Self> {
+		let uri = uri.trim();
+		if !uri.starts_with( URI_PREFIX ) {
+			return None
+		}
+
+		Self::parse_segments( &uri[URI_PREFIX.len()..] )
+	}
+
+	/// Parses the compact notation, optionally followed by `/post/<id>`.
+	fn parse_segments( path: &str ) -> Option<Self> {
+		let mut segments = path.split('/');
+		let mut link = Self::parse_hint( segments.next()? )?;
+		if segments.next() == Some("post") {
+			link.post_id = segments.next().and_then(|id| id.parse().ok());
+		}
+		Some( link )
+	}
+}
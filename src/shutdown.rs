@@ -0,0 +1,108 @@
+//! Stops the node in order when it is asked to, so that nothing is left half done.
+//!
+//! On SIGINT or SIGTERM, the HTTP server stops accepting connections and finishes the requests that it is handling.
+//! Then the background tasks are told to stop, the swarms are left, handing our children to our parents, and the subscriptions are saved.
+//! The database is closed last.
+//! A second signal exits right away, for when leaving the swarms takes too long.
+
+use std::{
+	process,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Mutex
+	},
+	time::Duration
+};
+
+use actix_web::{dev::Server, rt::signal};
+use async_std::future::timeout;
+use futures::{
+	channel::oneshot,
+	future::select
+};
+use lazy_static::lazy_static;
+use tracing::{info, warn};
+
+use crate::RETURN_CODE_UNEXPECTED;
+
+
+
+struct Shutdown {
+	started: AtomicBool,
+	/// The tasks that are waiting for the shutdown to start.
+	waiting: Mutex<Vec<oneshot::Sender<()>>>
+}
+
+
+
+lazy_static! {
+	static ref SHUTDOWN: Shutdown = Shutdown {
+		started: AtomicBool::new( false ),
+		waiting: Mutex::new( Vec::new() )
+	};
+}
+
+/// Starts the shutdown, which tells all background tasks to stop.
+pub fn start() {
+	if SHUTDOWN.started.swap( true, Ordering::SeqCst ) { return }
+
+	for sender in SHUTDOWN.waiting.lock().unwrap().drain(..) {
+		let _ = sender.send(());
+	}
+}
+
+/// Returns whether the node is shutting down.
+pub fn is_started() -> bool {
+	SHUTDOWN.started.load( Ordering::SeqCst )
+}
+
+/// Waits until the node starts shutting down.
+pub async fn started() {
+	let receiver = {
+		let mut waiting = SHUTDOWN.waiting.lock().unwrap();
+		// Checked while holding the lock, so that a shutdown can't start in between.
+		if is_started() { return }
+		let (sender, receiver) = oneshot::channel();
+		waiting.push( sender );
+		receiver
+	};
+
+	let _ = receiver.await;
+}
+
+/// Sleeps for the given duration, unless the node starts shutting down before then.
+/// Returns whether the node is still running.
+pub async fn sleep( duration: Duration ) -> bool {
+	timeout( duration, started() ).await.is_err()
+}
+
+/// Waits for SIGINT or SIGTERM, and then stops the HTTP server and starts the shutdown.
+/// The server finishes the requests that it is handling before it stops.
+pub async fn stop_on_signal( server: Server ) {
+	wait_for_signal().await;
+	info!("Shutting down, signal again to exit right away...");
+	start();
+	actix_web::rt::spawn( server.stop( true ) );
+
+	wait_for_signal().await;
+	process::exit( RETURN_CODE_UNEXPECTED );
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+	let mut terminate = match signal::unix::signal( signal::unix::SignalKind::terminate() ) {
+		Err(e) => {
+			warn!("Unable to listen for SIGTERM: {}", e);
+			let _ = signal::ctrl_c().await;
+			return
+		},
+		Ok(s) => s
+	};
+
+	select( Box::pin( signal::ctrl_c() ), Box::pin( terminate.recv() ) ).await;
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+	let _ = signal::ctrl_c().await;
+}
@@ -0,0 +1,253 @@
+//! Static HTML copies of channels, so that a channel can be archived or mirrored outside of the network, on any web server or on none at all.
+//!
+//! A copy consists of an index page, a page for every post that we have, the attached files of which we have all blocks, and a stylesheet.
+//! The pages are rendered with the templates in `site/`, and all links between them are relative, so they can be opened straight from the file system.
+//! Unlike an archival bundle (see `archive`), a copy only holds what a reader needs, not the signed events and posts.
+
+use std::{
+	fmt,
+	fs,
+	io,
+	path::Path,
+	time::{SystemTime, UNIX_EPOCH}
+};
+
+use gnunet::{
+	crypto::HashCode,
+	identity::PublicKey
+};
+use serde::Serialize;
+
+use crate::{
+	identicon,
+	persistence,
+	preview,
+	templates::Templates,
+	web::syndication_title
+};
+
+
+
+/// The files of a static copy, by their path relative to its root.
+pub struct StaticSite {
+	files: Vec<(String, Vec<u8>)>
+}
+
+#[derive(Serialize)]
+struct PostLink {
+	/// The path of the page, relative to the index page.
+	page: String,
+	title: String,
+	publisher: String,
+	/// In seconds since the UNIX epoch, for tera's date filter.
+	publish_timestamp: u64
+}
+
+#[derive(Serialize)]
+struct AttachmentLink {
+	/// The path of the file, relative to the page of the post.
+	path: String,
+	image: bool
+}
+
+#[derive(Debug)]
+pub enum Error {
+	Persistence( persistence::Error ),
+	Template( tera::Error ),
+	/// We don't know the channel.
+	UnknownChannel
+}
+
+
+
+/// Renders all posts that we have of a channel into a static copy.
+pub async fn export( db: &persistence::Handle, templates: &Templates, address: &PublicKey ) -> Result<StaticSite, Error> {
+	let channel = db.clone().get_channel( address ).await?
+		.ok_or( Error::UnknownChannel )?;
+	let profile = channel.fetch_profile().await?;
+	let title = profile.as_ref().map(|p| p.base.title.clone()).filter(|t| !t.is_empty())
+		.unwrap_or_else(|| address.to_string());
+	let now = SystemTime::now().duration_since( UNIX_EPOCH ).unwrap();
+
+	let mut site = StaticSite { files: Vec::new() };
+	let mut links = Vec::new();
+	let mut included: Vec<HashCode> = Vec::new();
+	let mut missing: Vec<HashCode> = Vec::new();
+
+	let mut publishers = vec![ address.clone() ];
+	publishers.extend( channel.list_publishers().await? );
+	for publisher in &publishers {
+		let timeline = match channel.get_timeline( publisher ).await? {
+			None => continue,
+			Some(t) => t
+		};
+		let latest_id = match timeline.load_latest_post_id().await? {
+			None => continue,
+			Some(id) => id
+		};
+
+		for post_id in 0..=latest_id {
+			let post = match timeline.load_post( post_id ).await? {
+				None => continue,
+				Some(p) => p
+			};
+			// Posts that aren't visible yet stay out, like they do in the feed.
+			if !post.meta.info.is_visible_at( now.as_millis() as u64 ) { continue }
+			let content = match timeline.load_current_content( post_id ).await? {
+				None => continue,
+				Some(c) => c
+			};
+
+			// Only the files of which we have all blocks can be included.
+			let mut attachments = Vec::with_capacity( post.meta.attachment_ids.len() );
+			for file_id in &post.meta.attachment_ids {
+				if !included.contains( file_id ) {
+					if missing.contains( file_id ) { continue }
+					match load_file_data( db, file_id ).await? {
+						None => { missing.push( file_id.clone() ); continue },
+						Some(data) => site.add( format!("file/{}", file_id), data )
+					}
+					included.push( file_id.clone() );
+				}
+				let mime_type = match db.load_file_mime_type( file_id ).await? {
+					Some(t) => Some( t ),
+					None => sniff_mime_type( db, file_id ).await?
+				};
+				attachments.push( AttachmentLink {
+					path: format!("../../file/{}", file_id),
+					image: mime_type.map(|t| t.starts_with("image/")).unwrap_or(false)
+				});
+			}
+
+			let mut context = tera::Context::new();
+			context.insert("channel_title", &title);
+			context.insert("address", &address.to_string());
+			context.insert("publisher", &publisher.to_string());
+			context.insert("post_id", &post_id);
+			context.insert("info", &timeline.load_current_info( &post ).await?);
+			context.insert("publish_timestamp", &(post.meta.info.publish_timestamp / 1000));
+			context.insert("html", &preview::render( &content, post.meta.info.format ));
+			context.insert("attachments", &attachments);
+			let page = format!("post/{}/{}.html", publisher, post_id);
+			site.add( page.clone(), templates.render("site/post.html", &context)?.into_bytes() );
+
+			links.push( PostLink {
+				page,
+				title: syndication_title( &content, post.meta.info.format ),
+				publisher: publisher.to_string(),
+				publish_timestamp: post.meta.info.publish_timestamp / 1000
+			});
+		}
+	}
+
+	// The stylesheet of the channel takes the place of the default one, if we have it.
+	let stylesheet = match profile.as_ref().and_then(|p| p.stylesheet.as_ref()) {
+		None => None,
+		Some(hash) => load_file_data( db, hash ).await?
+	};
+	let stylesheet = match stylesheet {
+		Some(s) => s,
+		None => templates.render("site/style.css", &tera::Context::new())?.into_bytes()
+	};
+	site.add( "style.css".to_owned(), stylesheet );
+
+	// The index page, newest posts first
+	links.sort_by(|a, b| b.publish_timestamp.cmp( &a.publish_timestamp ));
+	let mut context = tera::Context::new();
+	context.insert("title", &title);
+	context.insert("address", &address.to_string());
+	context.insert("description", &profile.as_ref().map(|p| p.base.description.as_str()).unwrap_or(""));
+	context.insert("exported_at", &now.as_secs());
+	context.insert("posts", &links);
+	site.add( "index.html".to_owned(), templates.render("site/index.html", &context)?.into_bytes() );
+
+	Ok( site )
+}
+
+/// Loads all blocks of a file, or returns `None` if we don't have all of them.
+async fn load_file_data( db: &persistence::Handle, file_id: &HashCode ) -> persistence::Result<Option<Vec<u8>>> {
+	let file = match db.load_file( file_id ).await? {
+		None => return Ok(None),
+		Some(f) => f
+	};
+	let mut data = Vec::new();
+	for block_id in &file.block_ids {
+		match db.load_block( block_id ).await? {
+			None => return Ok(None),
+			Some(block) => data.extend( block )
+		}
+	}
+	Ok( Some( data ) )
+}
+
+/// Guesses the MIME type of a file that came without one, from its first block.
+async fn sniff_mime_type( db: &persistence::Handle, file_id: &HashCode ) -> persistence::Result<Option<String>> {
+	let first_block = match db.load_file( file_id ).await?.and_then(|f| f.block_ids.first().cloned()) {
+		None => return Ok(None),
+		Some(id) => db.load_block( &id ).await?
+	};
+	Ok( first_block.as_ref()
+		.and_then(|b| identicon::sniff_image_type( b ))
+		.map(|t| t.to_owned()) )
+}
+
+
+
+impl StaticSite {
+
+	fn add( &mut self, path: String, data: Vec<u8> ) {
+		self.files.push(( path, data ));
+	}
+
+	/// Writes the files into the given directory, which is created if it doesn't exist yet.
+	/// Files that are already there are overwritten, so that a copy can be brought up to date.
+	pub fn write_to( &self, dir: &Path ) -> io::Result<()> {
+		for (path, data) in &self.files {
+			let path = dir.join( path );
+			if let Some(parent) = path.parent() {
+				fs::create_dir_all( parent )?;
+			}
+			fs::write( &path, data )?;
+		}
+		Ok(())
+	}
+
+	/// Packs the files into a tar archive, under a directory with the given name.
+	pub fn to_tar( &self, root: &str ) -> io::Result<Vec<u8>> {
+		let mtime = SystemTime::now().duration_since( UNIX_EPOCH ).unwrap().as_secs();
+
+		let mut builder = tar::Builder::new( Vec::new() );
+		for (path, data) in &self.files {
+			let mut header = tar::Header::new_gnu();
+			header.set_size( data.len() as u64 );
+			header.set_mode( 0o644 );
+			header.set_mtime( mtime );
+			builder.append_data( &mut header, format!("{}/{}", root, path), &data[..] )?;
+		}
+		builder.into_inner()
+	}
+}
+
+impl From<persistence::Error> for Error {
+	fn from( other: persistence::Error ) -> Self {
+		Self::Persistence( other )
+	}
+}
+
+impl From<tera::Error> for Error {
+	fn from( other: tera::Error ) -> Self {
+		Self::Template( other )
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt( &self, f: &mut fmt::Formatter<'_> ) -> fmt::Result {
+		match self {
+			Self::Persistence(e) => write!(f, "{}", e),
+			Self::Template(e) => write!(f, "Template error: {}", e),
+			Self::UnknownChannel => write!(f, "Unknown channel.")
+		}
+	}
+}
+
+impl std::error::Error for Error {}
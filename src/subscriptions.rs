@@ -1,30 +1,40 @@
 use std::{
-	io,
-	sync::Arc
+	cmp::min,
+	sync::{
+		self as std_sync,
+		atomic::{AtomicBool, Ordering},
+		Arc,
+		Weak
+	},
+	time::Duration
 };
 
 use async_std::{
-	fs::File,
 	prelude::*,
-	sync::Mutex
+	sync::RwLock,
+	task
 };
 
-use gnunet::{
-	cadet,
-	identity::PublicKey
-};
-use serde::*;
+use futures::stream::FuturesUnordered;
+use gnunet::identity::PublicKey;
+use tracing::{debug, error, info_span, warn, Instrument};
 
 use crate::{
 	config,
+	discovery::Discovery,
 	persistence::{
 		self,
-		channel,
-		DATABASE_DIR
+		channel
 	},
-	swarm::{self, Node}
+	setup,
+	shutdown,
+	swarm::{self, BadPeerStore, Node, Reputation},
+	transport::Transport,
+	tuning::SwarmTuning
 };
 
+pub use crate::persistence::subscription::Subscription;
+
 
 
 #[derive(Debug)]
@@ -32,26 +42,29 @@ pub enum Error {
 	
 }
 
-#[derive(Deserialize, Serialize)]
-pub struct Subscription {
-	/// The address of the owner of the channel.
-	/// This key also identifies the channel.
-	owner: PublicKey,
-	/// The set of publishers that are known for this channel
-	publishers: Vec<PublicKey>,
-	/// Some extra peers that were remembered from the last session.
-	/// The idea is that if the owner and the publishers are not online, these peers can be tried connecting to to find a connection back to the swarm.
-	cached_peers: Vec<PublicKey>
-}
-
 pub struct SubscriptionManager {
 	persistence: channel::Handle,
-	pub sub: Subscription,
-	node: Option<Node>
+	/// The address of the channel.
+	address: PublicKey,
+	state: Arc<SubscriptionState>
+}
+
+/// The part of a subscription manager that is shared with the task that keeps it connected.
+struct SubscriptionState {
+	sub: std_sync::Mutex<Subscription>,
+	/// The connection to the swarm, if there is one.
+	/// It is replaced by the reconnection task whenever it has been lost.
+	node: std_sync::Mutex<Option<Node>>
 }
 
+/// The subscriptions of the node, which are shared by everything that joins or leaves swarms.
+/// They are `None` until they have been loaded.
+pub type SharedSubscriptions = Arc<RwLock<Option<SubscriptionsManager>>>;
+
 pub struct SubscriptionsManager {
 	persistence: persistence::Handle,
+	transport: Arc<dyn Transport>,
+	discovery: Option<Arc<Discovery>>,
 	subs: Vec<SubscriptionManager>
 }
 
@@ -59,44 +72,102 @@ pub struct SubscriptionsManager {
 
 impl Subscription {
 
+	/// A subscription of which only the owner is known.
+	pub fn new( owner: PublicKey ) -> Self {
+		Self {
+			owner,
+			publishers: Vec::new(),
+			cached_peers: Vec::new()
+		}
+	}
+
+	/// Puts the given peers in front of the cached peers, so that they are tried first the next time.
+	/// The owner is left out, because it is always tried anyway.
+	/// Only the `CACHED_PEERS_MAX` most recent peers are kept.
+	pub fn remember_peers( &mut self, peers: &[PublicKey] ) {
+		let mut cached: Vec<PublicKey> = Vec::with_capacity( config::CACHED_PEERS_MAX );
+		for peer in peers.iter().chain( self.cached_peers.iter() ) {
+			if *peer != self.owner && !cached.contains( peer ) {
+				cached.push( peer.clone() );
+			}
+		}
+
+		cached.truncate( config::CACHED_PEERS_MAX );
+		self.cached_peers = cached;
+	}
+
+	/// Saves the subscription, replacing the one that was saved before.
+	/// Connects to more parents, until the node has as many as it should.
+	/// Parents are looked for in the same order as when the node first connects: the cached peers, the publishers and then the owner.
+	async fn add_parents( &self, node: &Node, wanted: u8 ) {
+		let wanted = wanted as usize;
+		if node.parent_count() >= wanted { return }
+
+		let candidates: Vec<PublicKey> = {
+			let sub = self.sub.lock().unwrap();
+			sub.cached_peers.iter().chain( sub.publishers.iter() ).chain( std::iter::once( &sub.owner ) ).cloned().collect()
+		};
+		for peer in candidates {
+			if node.parent_count() >= wanted { break }
+			if let Err(e) = node.add_parent( peer.clone() ).await {
+				debug!("Unable to connect to {} as another parent: {}", peer, e);
+			}
+		}
+	}
+
+	async fn save( &self, persistence: &persistence::Handle ) -> persistence::Result<()> {
+		persistence.save_subscription( self ).await
+	}
+
 	/// Attempts to find a connection to the swarm through any of the peers that it knows.
 	/// If it fails to connect to a peer, its error is given through `on_error`.
 	/// If connection could be made, `None` is returned.
 	/// 
 	/// # Arguments
-	/// `cadet` - A handle to the Gnunet cadet service.
+	/// `transport` - The transport over which the peers of the swarm are reached.
+	/// `discovery` - The DHT discovery, which is used when none of the known peers can be reached.
 	/// `relay_power` - The power of the number of child peers our peer will accept.
 	///                 So the number of accepted child peers is 2 to the power of `relay_power`.
 	///                 Generally speaking, you want to default to 1.
 	///                 If you want to provide a lot of bandwidth to the network, you can use very high numbers, and this will reduce latency in the network.
-	pub async fn find_swarm_connection( &self, persistence: channel::Handle, cadet: Arc<Mutex<cadet::Handle>>, relay_power: u8, on_error: impl Fn( &PublicKey, swarm::Error ) ) -> Option<Node> {
+	pub async fn find_swarm_connection( &self, persistence: channel::Handle, transport: Arc<dyn Transport>, discovery: Option<Arc<Discovery>>, relay_power: u8, on_error: impl Fn( &PublicKey, swarm::Error ) ) -> Option<Node> {
+
+		// Peers that have misbehaved are skipped until their ban expires.
+		let bad_peers = match BadPeerStore::load( persistence.base.clone() ).await {
+			Err(e) => { on_error( &self.owner, e.into() ); return None },
+			Ok(s) => s
+		};
+		// Peers that have been useful before are tried first.
+		let reputation = Reputation::new( persistence.base.clone() );
+		let cached_peers = reputation.rank( &self.cached_peers ).await.unwrap_or_else(|_| self.cached_peers.clone() );
+		let publishers = reputation.rank( &self.publishers ).await.unwrap_or_else(|_| self.publishers.clone() );
 
 		// First try some cached peer, so as to not overload the publisher nodes.
-		for peer in &self.cached_peers {
-			match Node::connect( persistence.clone(), cadet.clone(), peer.clone(), relay_power ).await {
-				Err(e) => on_error(&peer, e),
-				Ok(node) => return Some(node)
-			}
+		if let Some(node) = connect_any( &cached_peers, &persistence, &transport, &discovery, relay_power, &bad_peers, &on_error ).await {
+			return Some(node)
 		}
 
 		// Then try the publishers, so asto not overload the owner node.
-		for peer in &self.publishers {
-			match Node::connect( persistence.clone(), cadet.clone(), peer.clone(), relay_power ).await {
-				Err(e) => on_error(&peer, e),
-				Ok(node) => return Some(node)
-			}
+		if let Some(node) = connect_any( &publishers, &persistence, &transport, &discovery, relay_power, &bad_peers, &on_error ).await {
+			return Some(node)
 		}
 
 		// Then as a last resort, we try the owner node.
-		match Node::connect( persistence.clone(), cadet.clone(), self.owner.clone(), relay_power ).await {
+		match Node::connect( persistence.clone(), transport.clone(), self.owner.clone(), relay_power, discovery.clone() ).await {
 			Err(e) => on_error(&self.owner, e),
 			Ok(node) => return Some(node)
 		}
 
 		// If that doesn't work, try to find an available peer node from the DHT.
-		// TODO: Search the DHT for an address, and then connect to that.
-
-		None
+		let found = match &discovery {
+			None => return None,
+			Some(d) => match d.find_peers( &self.owner ).await {
+				Err(e) => { on_error( &self.owner, swarm::Error::Gnunet(e) ); return None },
+				Ok(peers) => peers
+			}
+		};
+		let found = reputation.rank( &found ).await.unwrap_or( found );
+		connect_any( &found, &persistence, &transport, &discovery, relay_power, &bad_peers, &on_error ).await
 	}
 }
 
@@ -105,73 +176,324 @@ impl SubscriptionManager {
 	/// Loads the subscription manager for channel with given `address`.
 	/// The subscription manager holds a live connection to the swarm.
 	/// If no such connection could be made, the subscription manager automatically retries to attempt a connection every so often.
-	pub async fn load( persistence: channel::Handle, cadet: Arc<Mutex<cadet::Handle>>, address: PublicKey ) -> persistence::Result<Self> {
+	pub async fn load( persistence: channel::Handle, transport: Arc<dyn Transport>, discovery: Option<Arc<Discovery>>, address: PublicKey ) -> persistence::Result<Self> {
 		
-		let sub = match File::open( DATABASE_DIR.join("subscriptions").join( address.to_string() ) ).await {
-			Err(e) => {
-				if e.kind() == io::ErrorKind::NotFound {
-					Subscription {
-						owner: address,
-						publishers: Vec::new(),
-						cached_peers: Vec::new()
+		let sub = persistence.load_subscription( &address ).await?
+			.unwrap_or_else(|| Subscription::new( address.clone() ));
+
+		let relay_power = load_relay_power( &persistence ).await?;
+		let span = info_span!("subscription", channel = %address);
+		let node = sub.find_swarm_connection( persistence.clone(), transport.clone(), discovery.clone(), relay_power, print_connect_error ).instrument( span.clone() ).await;
+		let state = Arc::new( SubscriptionState {
+			sub: std_sync::Mutex::new( sub ),
+			node: std_sync::Mutex::new( node )
+		});
+
+		actix_web::rt::spawn( Self::keep_connected( Arc::downgrade( &state ), persistence.clone(), transport, discovery ).instrument( span.clone() ) );
+		actix_web::rt::spawn( Self::keep_publishing( Arc::downgrade( &state ) ).instrument( span ) );
+
+		Ok( Self {
+			persistence,
+			address,
+			state
+		})
+	}
+
+	/// Keeps the connection to the swarm alive, for as long as the subscription manager exists and the node isn't shutting down.
+	/// The connection is checked every so often, and when it has been lost, a new one is searched for.
+	/// Failed searches are retried with a delay that doubles every time.
+	/// While connected, the peers we talk to are remembered, so that they can be tried first after a restart.
+	/// The swarm settings are loaded again for every check, so that the changes made on the settings page reach the swarm.
+	async fn keep_connected( state: Weak<SubscriptionState>, persistence: channel::Handle, transport: Arc<dyn Transport>, discovery: Option<Arc<Discovery>> ) {
+		// `None` until a search has failed.
+		let mut delay: Option<u64> = None;
+
+		loop {
+			// The swarm is left on shutdown, so it shouldn't be joined again.
+			if shutdown::is_started() { break }
+
+			let wait = {
+				let state = match state.upgrade() {
+					None => break,
+					Some(s) => s
+				};
+				let node = state.node.lock().unwrap().clone();
+				let tuning = match SwarmTuning::load( &persistence ).await {
+					Err(e) => { warn!("Unable to load the swarm settings: {}", e); SwarmTuning::default() },
+					Ok(t) => t
+				};
+
+				match node {
+					Some(node) if node.is_connected() => {
+						if state.learn_peers( &node ).await {
+							if let Err(e) = state.save( &persistence ).await {
+								error!("Unable to save the subscription: {}", e);
+							}
+						}
+						// A lowered relay power hands the children that are too many over to the others, and a lowered parent count leaves the slowest parents.
+						node.tune( &tuning ).await;
+						state.add_parents( &node, tuning.parent_count ).await;
+
+						delay = None;
+						tuning.connection_check_interval
+					},
+					dead => {
+						// Our parents are gone, and neither their ancestors nor our children got us back in, so we need to find our own way back into the swarm.
+						if let Some(node) = dead {
+							node.disconnect().await;
+						}
+
+						let sub = state.sub.lock().unwrap().clone();
+						match sub.find_swarm_connection( persistence.clone(), transport.clone(), discovery.clone(), tuning.relay_power, print_connect_error ).await {
+							Some(node) => {
+								*state.node.lock().unwrap() = Some( node );
+								delay = None;
+								tuning.connection_check_interval
+							},
+							None => {
+								// The maximum may have been lowered since the last failure.
+								let wait = min( delay.unwrap_or( tuning.reconnect_min_delay ), tuning.reconnect_max_delay );
+								delay = Some( min( wait * 2, tuning.reconnect_max_delay ) );
+								wait
+							}
+						}
 					}
 				}
-				else {
-					panic!("unable to open subscription file: {}", e)
+			};
+
+			if !shutdown::sleep( Duration::from_secs( wait ) ).await { break }
+		}
+	}
+
+	/// Publishes the events in the outbox of the channel, for as long as the subscription manager exists and the node isn't shutting down.
+	/// The outbox is checked every so often, besides whenever the node gets another peer, and its events are published in order.
+	async fn keep_publishing( state: Weak<SubscriptionState> ) {
+		loop {
+			{
+				let state = match state.upgrade() {
+					None => break,
+					Some(s) => s
+				};
+				let node = state.node.lock().unwrap().clone();
+
+				if let Some(node) = node.filter(|n| n.is_connected()) {
+					if let Err(e) = node.publish_outbox().await {
+						warn!("Unable to publish the outbox: {}", e);
+					}
 				}
-			},
-			Ok(mut file) => {
-				let mut content = Vec::new();
-				file.read_to_end( &mut content ).await.expect("I/O error while reading subscription file");
-				bincode::deserialize( &*content ).expect("deserialization error while reading subscription file")
 			}
-		};
 
-		let node = sub.find_swarm_connection( persistence.clone(), cadet, config::RELAY_POWER, |a,e| {
-			eprintln!("Unable to connect to peer {}: {}. Trying next...", a, e);
-		}).await;
+			if !shutdown::sleep( Duration::from_secs( config::OUTBOX_RETRY_INTERVAL ) ).await { break }
+		}
+	}
 
-		Ok( Self {
-			persistence,
-			sub,
-			node
-		})
+	/// Saves the subscription, including the peers that we've learned about.
+	pub async fn save( &self ) -> persistence::Result<()> {
+		self.state.save( &self.persistence ).await
+	}
+
+	/// Remembers the peers of the current connection, and disconnects from the swarm.
+	pub async fn disconnect( &self ) {
+		let node = self.state.node.lock().unwrap().take();
+		if let Some(node) = node {
+			self.state.learn_peers( &node ).await;
+			node.disconnect().await;
+		}
 	}
+}
 
-	pub async fn save( &self ) -> io::Result<()> {
+impl SubscriptionState {
 
-		let content = bincode::serialize( &self.sub ).expect("serialization error");
-		let mut file = File::create( DATABASE_DIR.join("subscriptions").join( self.sub.owner.to_string() ) ).await?;
-		file.write( &*content ).await?;
+	/// Adds the peers that the node is exchanging messages with to the cached peers.
+	/// Returns whether the cached peers have changed.
+	async fn learn_peers( &self, node: &Node ) -> bool {
+		let peers = node.active_peers().await;
 
-		Ok(())
+		let mut sub = self.sub.lock().unwrap();
+		let before = sub.cached_peers.clone();
+		sub.remember_peers( &peers );
+		sub.cached_peers != before
+	}
+
+	async fn save( &self, persistence: &persistence::Handle ) -> persistence::Result<()> {
+		let sub = self.sub.lock().unwrap().clone();
+		sub.save( persistence ).await
 	}
 }
 
 impl SubscriptionsManager {
 
-	pub async fn load( persistence: persistence::Handle, cadet: cadet::Handle ) -> persistence::Result<Self> {
+	pub async fn load( persistence: persistence::Handle, transport: Arc<dyn Transport>, discovery: Option<Arc<Discovery>> ) -> persistence::Result<Self> {
 
 		let channels = persistence.list_channels().await?;
 		let mut subs = Vec::with_capacity( channels.len() );
-		let cadet_shared = Arc::new( Mutex::new( cadet ) );
 
 		for channel in channels {
 			subs.push(
-				SubscriptionManager::load( channel.clone(), cadet_shared.clone(), channel.load_address().await? ).await?
+				SubscriptionManager::load( channel.clone(), transport.clone(), discovery.clone(), channel.load_address().await? ).await?
 			);
 		}
 
 		Ok( Self {
 			persistence,
+			transport,
+			discovery,
 			subs
 		})
 	}
 
-	pub async fn save() -> io::Result<()> {
+	/// Loads the subscription manager of a channel that has been added after the others were loaded.
+	/// It still needs to be added with `add`.
+	pub async fn load_channel( &self, channel: channel::Handle ) -> persistence::Result<SubscriptionManager> {
+		let address = channel.load_address().await?;
+		SubscriptionManager::load( channel, self.transport.clone(), self.discovery.clone(), address ).await
+	}
+
+	/// Adds a subscription manager, unless there already is one for the same channel.
+	pub fn add( &mut self, sub: SubscriptionManager ) {
+		if !self.is_subscribed( &sub.address ) {
+			self.subs.push( sub );
+		}
+	}
+
+	/// Takes out the subscription manager of the channel with the given address, which stops it from reconnecting.
+	/// It is still connected to the swarm, so it should be disconnected after this.
+	pub fn remove( &mut self, address: &PublicKey ) -> Option<SubscriptionManager> {
+		let index = self.subs.iter().position(|s| s.address == *address)?;
+		Some( self.subs.remove( index ) )
+	}
+
+	/// Returns the number of channels that we are subscribed to.
+	pub fn len( &self ) -> usize {
+		self.subs.len()
+	}
+
+	pub fn is_subscribed( &self, address: &PublicKey ) -> bool {
+		self.subs.iter().any(|s| s.address == *address)
+	}
+
+	/// Returns the node that is connected to the swarm of the channel with the given address, if there is one.
+	pub fn node( &self, address: &PublicKey ) -> Option<Node> {
 
-		// TODO: Save the list of subscriptions
+		self.subs.iter()
+			.find(|s| s.address == *address)
+			.and_then(|s| s.state.node.lock().unwrap().clone())
+	}
 
-		Ok(())
+	/// Returns the nodes of all swarms that we are connected to.
+	pub fn nodes( &self ) -> Vec<Node> {
+		self.subs.iter()
+			.filter_map(|s| s.state.node.lock().unwrap().clone())
+			.collect()
+	}
+
+	/// Disconnects from the swarms of all channels.
+	pub async fn disconnect( &self ) {
+		for sub in &self.subs {
+			sub.disconnect().await;
+		}
 	}
-}
\ No newline at end of file
+
+	/// Saves all subscriptions.
+	/// All of them are attempted, even if saving one of them fails.
+	pub async fn save( &self ) -> persistence::Result<()> {
+		let mut result = Ok(());
+		for sub in &self.subs {
+			if let Err(e) = sub.save().await {
+				error!(channel = %sub.address, "Unable to save the subscription: {}", e);
+				result = Err(e);
+			}
+		}
+		result
+	}
+}
+
+
+
+/// Joins the swarm of a channel that has just been subscribed to, in the background.
+/// If the subscriptions haven't been loaded yet, the channel is joined together with the others.
+pub fn join( subscriptions: SharedSubscriptions, channel: channel::Handle, address: PublicKey ) {
+	actix_web::rt::spawn(async move {
+		let sub = match &*subscriptions.read().await {
+			None => return,
+			Some(subs) if subs.is_subscribed( &address ) => return,
+			Some(subs) => subs.load_channel( channel ).await
+		};
+		match sub {
+			Err(e) => warn!(channel = %address, "Unable to subscribe to channel: {}", e),
+			Ok(sub) => if let Some(subs) = &mut *subscriptions.write().await {
+				subs.add( sub );
+			}
+		}
+	});
+}
+
+/// Tries to connect to the swarm through any of the given peers, a few at the same time.
+/// Within a batch, the attempts start shortly after each other, so that an earlier peer gets a head start without slow peers holding up the rest.
+/// The first connection that is made is kept.
+/// Attempts that haven't started by then are cancelled, and the connections that the running ones make are closed again in the background.
+async fn connect_any( peers: &[PublicKey], persistence: &channel::Handle, transport: &Arc<dyn Transport>, discovery: &Option<Arc<Discovery>>, relay_power: u8, bad_peers: &BadPeerStore, on_error: &impl Fn( &PublicKey, swarm::Error ) ) -> Option<Node> {
+
+	let mut candidates = Vec::with_capacity( peers.len() );
+	for peer in peers {
+		if !bad_peers.is_blocked( peer ).await.unwrap_or(false) {
+			candidates.push( peer.clone() );
+		}
+	}
+
+	for batch in candidates.chunks( config::CONNECT_BATCH_SIZE ) {
+		let done = Arc::new( AtomicBool::new( false ) );
+		let mut attempts = FuturesUnordered::new();
+		for (i, peer) in batch.iter().enumerate() {
+			let persistence = persistence.clone();
+			let transport = transport.clone();
+			let discovery = discovery.clone();
+			let peer = peer.clone();
+			let done = done.clone();
+			attempts.push(async move {
+				task::sleep( Duration::from_millis( config::CONNECT_STAGGER * i as u64 ) ).await;
+				if done.load( Ordering::Acquire ) { return None }
+
+				let result = Node::connect( persistence, transport, peer.clone(), relay_power, discovery ).await;
+				Some(( peer, result ))
+			});
+		}
+
+		while let Some(attempt) = attempts.next().await {
+			match attempt {
+				None => {},
+				Some((peer, Err(e))) => on_error( &peer, e ),
+				Some((_, Ok(node))) => {
+					done.store( true, Ordering::Release );
+					actix_web::rt::spawn(async move {
+						while let Some(attempt) = attempts.next().await {
+							if let Some((_, Ok(extra))) = attempt {
+								extra.disconnect().await;
+							}
+						}
+					});
+					return Some(node)
+				}
+			}
+		}
+	}
+
+	None
+}
+
+/// The number of child peers that we accept is two to the power of the relay power.
+/// The configuration file has the last word, over the contribution profile that was chosen during the setup.
+pub async fn load_relay_power( persistence: &persistence::Handle ) -> persistence::Result<u8> {
+	if let Some(power) = config::get().relay_power {
+		return Ok( power )
+	}
+
+	Ok( match persistence.load_setting( setup::SETTING_RELAY_POWER ).await? {
+		None => config::RELAY_POWER,
+		Some(power) => power.parse().unwrap_or( config::RELAY_POWER )
+	})
+}
+
+fn print_connect_error( peer: &PublicKey, error: swarm::Error ) {
+	debug!(peer = %peer, "Unable to connect to peer: {}. Trying next...", error);
+}
@@ -2,41 +2,77 @@
 //! The swarm is a P2P network that facilitates the sharing of data and events.
 
 use std::{
+	cmp::{self, min},
+	collections::{HashMap, VecDeque},
 	convert::TryInto,
 	fmt,
-	str::Utf8Error,
 	sync::{
 		atomic::*,
-		Arc
-	}
+		Arc,
+		Weak
+	},
+	time::{Duration, Instant}
 };
 
 use async_std::{
-	sync::Mutex
+	future::timeout,
+	sync::{Mutex, RwLock},
+	task
 };
 use bincode;
+use futures::future;
 use gnunet::{
-	cadet,
 	crypto::HashCode,
 	identity::PublicKey
 };
-use lazy_static::lazy_static;
 use serde::*;
-use unsafe_send_sync::UnsafeSend;
+use tracing::{debug, debug_span, info_span, trace, warn, Instrument, Span};
 
+pub use crate::validation::MessageMalformedError;
 use crate::{
-	common::*,
+	bus::{self, NewNotification, PostChange, PostChangeKind},
+	config,
+	discovery::Discovery,
+	encryption::ChannelKey,
+	error_report::ErrorReporter,
+	metrics,
 	event::*,
+	fair_queue::{FairQueue, QueueDepth, SourceSender},
 	message::*,
-	persistence::{self, channel},
+	page_cache,
+	persistence::{
+		self,
+		channel,
+		notification::{self, Notification, NotificationKind},
+		ownership::PendingTransfer,
+		peer
+	},
+	post::Attachment,
+	rate_limit::TokenBucket,
 	runtime,
-	session_manager::SessionManager
+	session_manager::{RespondError, SessionManager},
+	setup,
+	transport::{PeerChannel, Priority, Transport},
+	tuning::SwarmTuning,
+	validation::*
 };
 
 
 
+/// Remembers the peers that have misbehaved, so that we stay away from them for a while.
+/// Every offense lengthens the ban by the ban duration, and bans age out after that.
 pub struct BadPeerStore {
-	peers: Vec<PublicKey>
+	persistence: persistence::Handle,
+	ban_duration: Duration
+}
+
+/// Scores peers on how useful they have been.
+/// Useful responses raise the score, while timeouts and malformed messages lower it.
+/// Scores decay towards zero over time, so that recent behavior counts more than old behavior.
+pub struct Reputation {
+	persistence: persistence::Handle,
+	/// The number of milliseconds in which a score decays to half of what it was.
+	half_life: f64
 }
 
 #[derive(Debug)]
@@ -44,478 +80,3375 @@ pub enum Error {
 	MessageMalformed( MessageMalformedError ),
 	Gnunet( gnunet::Error ),
 	Persistence( persistence::Error ),
+	/// None of the peers responded to a request.
+	NoResponse,
+	/// The swarm didn't provide the genesis event of the channel.
+	GenesisMissing,
+	/// The genesis event of the channel has other parameters than the ones we know.
+	GenesisChanged,
+	/// The peer speaks a version of the protocol that we don't understand.
+	IncompatibleVersion( ProtocolVersion ),
+	/// The peer has misbehaved before, and is still blocked.
+	PeerBlocked,
+	/// The peer has kept sending messages faster than its rate limits allow.
+	RateLimitExceeded,
+	/// We've lost the connection to the swarm.
+	NotConnected,
 	Internal( Box<dyn std::error::Error> )
 }
 
-#[derive(Debug)]
-pub enum MessageMalformedError {
-	/// When a deserialization with bincode failed.
-	DeserializationIssue( bincode::Error, String ),
-	/// When expecting a boolean but something other than a 1 or 0 was given.
-	InvalidBoolean( u8, String ),
-	InvalidHash( String ),
-	InvalidSignature( String ),
-	/// When some sort of type is give as a byte, and that byte uses an unknown ID.
-	InvalidTypeId( u8, String ),
-	/// When reading a string failed.
-	InvalidUtf8( Utf8Error, String ),
-	/// When a event message appeared to be way to new.
-	InvalidEventId( u64 ),
-	/// When the message turns out to be too small for the data is should contain.
-	MissingData( String ),
-	UnknownPublisher( PublicKey )
-}
-
 pub type Result<T> = std::result::Result<T, Error>;
 
+#[derive(Clone)]
 pub struct Node ( Arc<NodeInner> );
 
 struct NodeInner {
 	pub connected: AtomicBool,
-	pub persistence: UnsafeSend<channel::Handle>,	// TODO: Find out why channel::Handle is not send...
-	pub parent_address: PublicKey,
-	pub relay_power: u8,
-	pub parent_socket: Mutex<cadet::Channel>,
-	pub child_sockets: Vec<Mutex<cadet::Channel>>,
-	session_manager: Mutex<SessionManager>,
-	latest_event_id: Mutex<u64>
+	pub persistence: channel::Handle,
+	/// A connection of its own that the events are applied with, only while `latest_event_id` is held.
+	/// Events are applied in transactions, which would otherwise take in the statements that the rest of the node runs in the meantime, and roll them back with the event.
+	event_persistence: channel::Handle,
+	/// The power of two of the number of children that we accept, which can be lowered with `Node::rebalance`.
+	pub relay_power: AtomicU8,
+	/// Whether our rebroadcasts always make way for requests and responses, see `SwarmTuning::background_rebroadcasts`.
+	background_rebroadcasts: AtomicBool,
+	/// Used to connect to a new parent, when our parent hands us off.
+	transport: Arc<dyn Transport>,
+	/// Whether the node has started the swarm, rather than having joined it through a parent.
+	root: bool,
+	/// The peers through which we are connected to the swarm, up to the number of parents that the configuration asks for.
+	/// Every event arrives through each of them, so that the events keep coming when one of them leaves.
+	/// The lock is never held for longer than it takes to clone or change the list.
+	parents: std::sync::RwLock<Vec<Arc<Link>>>,
+	/// The events that have been processed lately, so that the copies that arrive through the other parents are ignored, and peers that send the same event again are caught.
+	recent_events: std::sync::Mutex<VecDeque<SeenEvent>>,
+	/// The peers that joined the swarm through us.
+	children: RwLock<Vec<Arc<Link>>>,
+	/// The ancestors that we've last told our children about, so that they're only told again when they change.
+	announced_ancestors: std::sync::Mutex<Vec<PublicKey>>,
+	/// The maximum size of a response to one of our requests, in bytes.
+	max_response_size: usize,
+	latest_event_id: Mutex<u64>,
+	/// Held while the outbox is being published, so that no event of ours goes out twice.
+	outbox: Mutex<()>,
+	/// The events that have been received from our peers, waiting to be applied.
+	/// Every peer has a queue of its own, and the queues take turns, so that a peer that floods us can't hold back the events of the others.
+	events: Arc<FairQueue<PublicKey, QueuedEvent>>,
+	/// The requests that have been received from our peers, waiting to be answered by one of the `REQUEST_WORKERS`.
+	/// Like with the events, every peer has a bounded queue of its own, so that a slow answer doesn't hold up reading from the peer, and a peer that floods us only holds up itself.
+	requests: Arc<FairQueue<PublicKey, QueuedRequest>>,
+	/// Whether or not missing events are being requested at the moment.
+	backfilling: AtomicBool,
+	sync: SyncProgress,
+	errors: Arc<ErrorReporter>,
+	bad_peers: BadPeerStore,
+	reputation: Reputation,
+	/// The key with which the messages are encrypted, if the channel is private.
+	key: Option<ChannelKey>,
+	/// The address of the channel.
+	address: PublicKey,
+	/// What has been sent to and received from the peers of the swarm, over all sessions.
+	traffic: Arc<Traffic>,
+	/// The span that the node logs in, which the spans of the connections to its peers are in as well.
+	span: Span
+}
+
+/// The channel with a neighbouring peer in the swarm, which is either our parent or one of our children.
+struct Link {
+	session: PeerSession,
+	socket: Mutex<Box<dyn PeerChannel>>,
+	/// The requests that we've sent to the peer, and that are waiting for their response.
+	requests: Arc<SessionManager>,
+	/// The ancestors that a parent has told us about, nearest first, see `TopologyMessage::Ancestors`.
+	ancestors: std::sync::Mutex<Vec<PublicKey>>,
+	/// The number of children that a child can still take, and whether it has other parents than us, see `TopologyMessage::Capacity`.
+	/// This is `None` until the child has told us.
+	capacity: std::sync::Mutex<Option<(u16, bool)>>,
+	/// What the peer has last told us in a census: the share of a child in our subtree, or the size of the swarm according to a parent, see `CensusMessage`.
+	census: std::sync::Mutex<Option<u32>>
+}
+
+/// The number of bytes that the swarm of a channel has sent and received since we connected to it.
+struct Traffic {
+	sent: AtomicU64,
+	received: AtomicU64,
+	/// The number of rebroadcasts that have been held back because the channel went over its upload limit.
+	throttled: AtomicU64,
+	/// What is left of the upload limit, in bytes.
+	/// Everything that is sent is taken from it, but only the rebroadcasts wait for it.
+	upload_budget: std::sync::Mutex<TokenBucket>
+}
+
+/// An event that has been received from a peer, and hasn't been applied yet.
+struct QueuedEvent {
+	/// The peer that sent the event.
+	link: Arc<Link>,
+	/// The decrypted message, without its direction type.
+	message: Vec<u8>
+}
+
+/// A request that has been received from a peer, and hasn't been answered yet.
+struct QueuedRequest {
+	/// The peer that sent the request, and that the response goes to.
+	link: Arc<Link>,
+	/// The decrypted message, without its direction type.
+	message: Vec<u8>
+}
+
+/// The ends of the queues in which the receive loop of a peer puts what has to wait for the event loop or a request worker.
+struct PeerQueues {
+	events: SourceSender<QueuedEvent>,
+	requests: SourceSender<QueuedRequest>
 }
 
+/// An event that has been processed lately.
+struct SeenEvent {
+	id: u64,
+	/// The hash of the whole message, so that a copy with other content isn't taken for the same event.
+	hash: HashCode,
+	/// The peers that have sent it to us.
+	senders: Vec<PublicKey>
+}
+
+/// What has become of an event that has been received.
+enum Reception {
+	/// The event has been applied, or has been kept until the events preceding it are applied.
+	/// Only these events are passed on, as they are the only ones that are new to us.
+	Accepted,
+	/// The event is the one that has been applied with its id before.
+	Known,
+	/// The event differs from the one that has been applied with its id, or it has been kept already, or too many other messages have been kept for its id.
+	Dropped
+}
+
+/// An event that is about to be sent to a number of peers, sealed both as it is and compressed, so that every peer can be sent the form that it understands.
+struct SealedEvent {
+	plain: Vec<u8>,
+	/// `None` if the event isn't worth compressing.
+	compressed: Option<Vec<u8>>
+}
+
+/// Sends a response in parts of at most `RESPONSE_PART_MAX_SIZE` bytes, as it is written.
+/// A part is only sent once the next one has been started, so that the last part can be marked as such.
+struct ResponseWriter<'a> {
+	key: Option<&'a ChannelKey>,
+	/// Whether the parts are compressed, which the peer needs to understand.
+	compress: bool,
+	link: &'a Link,
+	request_id: u32,
+	sequence: u32,
+	buffer: Vec<u8>
+}
+
+/// The statistics of the session with a peer, which are stored every now and then.
+struct PeerSession {
+	address: PublicKey,
+	/// The id under which the session is stored.
+	id: i64,
+	/// When the session started, in milliseconds since the UNIX epoch.
+	started: u64,
+	messages: AtomicU64,
+	bytes: AtomicU64,
+	/// The number of bytes that we've sent the peer, which is counted in the traffic of the channel as well.
+	bytes_sent: AtomicU64,
+	traffic: Arc<Traffic>,
+	malformed: AtomicU64,
+	timeouts: AtomicU64,
+	/// The average number of milliseconds that the peer took to respond to our requests, or 0 if it hasn't responded to any yet.
+	latency: AtomicU64,
+	/// Whether we've sent our hello message to the peer.
+	hello_sent: AtomicBool,
+	/// The rate at which the peer may send us events and requests.
+	event_limit: std::sync::Mutex<TokenBucket>,
+	request_limit: std::sync::Mutex<TokenBucket>,
+	/// The number of messages that have been dropped because the peer sent them too fast.
+	rate_limited: AtomicU64,
+	/// The protocol version that was negotiated with the peer, or `None` if the peer hasn't said hello (yet).
+	version: Mutex<Option<ProtocolVersion>>,
+	/// The span that everything that happens with the peer is logged in.
+	span: Span
+}
+
+/// The progress of a catch-up sync, see `Node::sync`.
+#[derive(Default)]
+struct SyncProgress {
+	running: AtomicBool,
+	events_applied: AtomicU64,
+	posts_fetched: AtomicU64,
+	blocks_remaining: AtomicU64
+}
+
+/// A snapshot of the traffic of the swarm of a channel.
+pub struct TrafficStatus {
+	pub channel: PublicKey,
+	pub sent: u64,
+	pub received: u64,
+	pub throttled: u64,
+	/// The bytes sent to and received from the peers that we're connected to, during their current session.
+	pub peers: Vec<(PublicKey, u64, u64)>
+}
+
+/// A snapshot of who is connected to the swarm of a channel through us, for its owners.
+pub struct CensusStatus {
+	/// The estimated number of peers in the whole swarm, which is `None` until it has been counted.
+	pub swarm_size: Option<u32>,
+	/// The number of peers in our subtree, ourselves included, which may be shared with the other parents of our children.
+	pub subtree_size: f64,
+	/// Our parents, followed by our children.
+	pub peers: Vec<CensusPeer>
+}
 
+/// A peer that we're connected to, as it is shown on the followers dashboard.
+pub struct CensusPeer {
+	pub address: PublicKey,
+	pub child: bool,
+	/// When the current session with the peer started, in milliseconds since the UNIX epoch.
+	pub connected_since: u64,
+	pub sent: u64,
+	pub received: u64,
+	/// For a child, its share in our subtree in peers, once it has told us.
+	pub subtree_share: Option<f64>
+}
 
-lazy_static! {
-	pub static ref QUARTZ_PORT: HashCode = HashCode::generate( "QuartzNet".as_bytes() );
+/// A snapshot of the progress of a catch-up sync.
+pub struct SyncStatus {
+	/// Whether the sync is still going on.
+	pub running: bool,
+	/// The number of events that have been applied since the sync started.
+	pub events_applied: u64,
+	/// The number of posts that have been fetched since the sync started, because we were missing them.
+	pub posts_fetched: u64,
+	/// The number of attachment blocks that still need to be fetched.
+	pub blocks_remaining: u64,
+	/// The peers that the data is being requested from.
+	pub peers: Vec<PublicKey>
 }
 
 
 
+/// The change in reputation for a useful response.
+const REPUTATION_USEFUL: f64 = 1.0;
+/// The change in reputation for a request that wasn't responded to in time.
+const REPUTATION_TIMEOUT: f64 = -2.0;
+/// The change in reputation for a malformed message, which is a sign of malicious intent.
+const REPUTATION_MALFORMED: f64 = -20.0;
+/// The change in reputation for an event that the peer has sent before, or that is long outdated.
+/// Resending events makes every node in the swarm pass them on again, but a buggy peer can do so as well.
+const REPUTATION_REPLAYED: f64 = -5.0;
+
+/// The number of messages after which the statistics of a peer session are stored again.
+const PEER_STATS_STORE_INTERVAL: u64 = 100;
+/// The number of missing posts that are requested at once, when syncing a timeline.
+const POSTS_SYNC_BATCH: usize = 32;
+/// The number of events that are remembered, to recognize the events that arrive through more than one parent.
+/// The copies of an event arrive shortly after each other, so this only needs to cover the latest events.
+/// Events that are older than this, compared to the last one that has been applied, are taken to be replayed.
+const RECENT_EVENTS: usize = 1024;
+
+
+
 impl Node {
 
 	/// Connects to another node of the swarm that is open to accept child nodes.
 	/// 
 	/// # Arguments
+	/// `transport` - The transport over which the peers of the swarm are reached.
 	/// `parent_address` - The address of the parent node to connect to.
 	/// `relay_power` - The number of child peers this node is accepting.
-	pub async fn connect( persistence: channel::Handle, cadet_handle: Arc<Mutex<cadet::Handle>>, parent_address: PublicKey, relay_power: u8 ) -> Result<Self> {
+	/// `discovery` - The DHT discovery to advertise free relay slots with, if the DHT is available.
+	pub async fn connect( persistence: channel::Handle, transport: Arc<dyn Transport>, parent_address: PublicKey, relay_power: u8, discovery: Option<Arc<Discovery>> ) -> Result<Self> {
+		Self::open( persistence, transport, Some( parent_address ), relay_power, discovery ).await
+	}
 
-		let latest_event_id = persistence.get_latest_id("event").await?.expect("latest event id not found");
+	/// Starts the swarm of a channel that we have the genesis event of, without connecting to anybody.
+	/// Other nodes join the swarm through this one, when they are given to `admit_child`.
+	/// Such a node has no parents to lose, so it stays connected until it disconnects.
+	pub async fn start( persistence: channel::Handle, transport: Arc<dyn Transport>, relay_power: u8, discovery: Option<Arc<Discovery>> ) -> Result<Self> {
+		if persistence.load_parameters().await?.is_none() {
+			return Err( Error::GenesisMissing )
+		}
 
-		let parent_socket = cadet_handle.lock().await.channel_connect( &parent_address, &QUARTZ_PORT ).await
-			.map_err(|e| Error::Gnunet(e.into()))?;
+		Self::open( persistence, transport, None, relay_power, discovery ).await
+	}
+
+	/// Starts a node that is connected to the given parent, or that starts the swarm if there is none.
+	async fn open( persistence: channel::Handle, transport: Arc<dyn Transport>, parent_address: Option<PublicKey>, relay_power: u8, discovery: Option<Arc<Discovery>> ) -> Result<Self> {
+
+		// The node doesn't share its connections with other nodes, and applies the events with one of its own.
+		let persistence = channel::Handle {
+			base: persistence.base.reconnect().await.map_err( persistence::Error::Database )?,
+			id: persistence.id
+		};
+		let event_persistence = channel::Handle {
+			base: persistence.base.reconnect().await.map_err( persistence::Error::Database )?,
+			id: persistence.id
+		};
+
+		// Only the genesis event has been applied for channels that we've just joined.
+		let latest_event_id = persistence.load_last_event_id().await?.unwrap_or( GENESIS_EVENT_ID );
+
+		let bad_peers = BadPeerStore::load( persistence.base.clone() ).await?;
+		let reputation = Reputation::new( persistence.base.clone() );
+		if let Some(address) = &parent_address {
+			if bad_peers.is_blocked( address ).await? {
+				return Err( Error::PeerBlocked )
+			}
+		}
+
+		// The node outlives whatever connected it, so its span doesn't go in the current one.
+		let address = persistence.load_address().await?;
+		let span = info_span!(parent: None, "swarm", channel = %address);
+		let traffic = Arc::new( Traffic::new() );
+		let max_response_size = match persistence.load_setting( setup::SETTING_MAX_RESPONSE_SIZE ).await? {
+			None => config::MAX_RESPONSE_SIZE,
+			Some(size) => size.parse().unwrap_or( config::MAX_RESPONSE_SIZE )
+		};
+		let parent = match parent_address {
+			None => None,
+			Some(address) => Some( Self::open_link( &persistence, &*transport, address, max_response_size, &traffic, &span ).await? )
+		};
+		let key = persistence.load_key().await?;
+		let tuning = SwarmTuning::load( &persistence ).await?;
 		
 		let inner = Arc::new( NodeInner {
 			connected: true.into(),
-			persistence: UnsafeSend::new( persistence ),
-			parent_address: parent_address.clone(),
-			relay_power,
-			parent_socket: Mutex::new( parent_socket ),
-			child_sockets: Vec::with_capacity( relay_power as _ ),
-			session_manager: Mutex::new( SessionManager::new() ),
-			latest_event_id: Mutex::new( latest_event_id )
+			persistence,
+			event_persistence,
+			relay_power: relay_power.into(),
+			background_rebroadcasts: tuning.background_rebroadcasts.into(),
+			transport,
+			root: parent.is_none(),
+			parents: std::sync::RwLock::new( parent.iter().cloned().collect() ),
+			recent_events: std::sync::Mutex::new( VecDeque::with_capacity( RECENT_EVENTS ) ),
+			children: RwLock::new( Vec::with_capacity( 1 << relay_power ) ),
+			announced_ancestors: std::sync::Mutex::new( Vec::new() ),
+			max_response_size,
+			latest_event_id: Mutex::new( latest_event_id ),
+			outbox: Mutex::new(()),
+			events: Arc::new( FairQueue::new( config::PEER_EVENT_QUEUE_SIZE ) ),
+			requests: Arc::new( FairQueue::new( config::PEER_REQUEST_QUEUE_SIZE ) ),
+			backfilling: false.into(),
+			sync: SyncProgress::default(),
+			errors: Arc::new( ErrorReporter::new( Duration::from_secs( config::ERROR_REPORT_WINDOW ) ) ),
+			bad_peers,
+			reputation,
+			key,
+			address,
+			traffic,
+			span
 		});
 
-		// Runs the receive loop for the parent peer
-		let inner2 = inner.clone();
-		
+		// Let the parent know which protocol version we speak, before anything else.
+		if let Some(parent) = &parent {
+			Self::send_hello( parent ).await?;
+		}
+
+		// Applies the events that our peers send us, one peer after the other.
+		runtime::spawn( Node::event_loop( Arc::downgrade( &inner ), inner.events.clone() ).instrument( inner.span.clone() ) );
+		// Answers the requests of our peers, a few at a time.
+		for _ in 0..config::REQUEST_WORKERS {
+			runtime::spawn( Node::request_worker( Arc::downgrade( &inner ), inner.requests.clone() ).instrument( inner.span.clone() ) );
+		}
+
+		// Runs the receive loop for the first parent, the others are connected to later on
+		if let Some(parent) = parent {
+			runtime::spawn( Node::parent_receive_loop( inner.clone(), parent ).instrument( inner.span.clone() ) );
+			Self::drain_outbox( &inner );
+		}
+
+		// Prints the repeated errors every now and then, for as long as the node exists.
+		let weak = Arc::downgrade( &inner );
 		runtime::spawn(async move {
-			Node::parent_receive_loop( inner2, |peer| {
-				eprintln!("Peer {} is considered bad.", peer)
-			}, |e| {
-				eprintln!("Error occurred while listening to parent peer {}: {}", parent_address, e)
-			} );
-		});
+			loop {
+				let window = match weak.upgrade() {
+					None => break,
+					Some(this) => this.errors.window()
+				};
+				task::sleep( window ).await;
+
+				match weak.upgrade() {
+					None => break,
+					Some(this) => this.errors.flush()
+				}
+			}
+		}.instrument( inner.span.clone() ));
+
+		// Counts the peers of the swarm every so often, together with our parents and children.
+		let weak = Arc::downgrade( &inner );
+		runtime::spawn(async move {
+			let mut last_sample: Option<Instant> = None;
+			loop {
+				task::sleep( Duration::from_secs( config::CENSUS_INTERVAL ) ).await;
+				match weak.upgrade() {
+					None => break,
+					Some(this) => Self::take_census( &this, &mut last_sample ).await
+				}
+			}
+		}.instrument( inner.span.clone() ));
+
+		// Lets others that look for the swarm know that they can join through us, while we have room for them.
+		if let Some(discovery) = discovery {
+			let weak = Arc::downgrade( &inner );
+			runtime::spawn(async move {
+				loop {
+					match weak.upgrade() {
+						None => break,
+						Some(this) => if let Err(e) = Self::advertise( &this, &discovery ).await {
+							this.errors.report( None, format!("unable to advertise in the DHT: {}", e) );
+						}
+					}
+					task::sleep( Duration::from_secs( config::DHT_ADVERTISE_INTERVAL ) ).await;
+				}
+			}.instrument( inner.span.clone() ));
+		}
+
+		let node = Self ( inner );
+
+		// Don't join a swarm that disagrees with us about what the channel is.
+		if node.0.root {
+			return Ok( node )
+		}
+		if let Err(e) = node.check_genesis().await {
+			node.disconnect().await;
+			return Err(e)
+		}
+
+		Ok( node )
+	}
+
+	/// Advertises our node in the DHT, if it is connected and not all of its relay slots are taken.
+	async fn advertise( this: &Arc<NodeInner>, discovery: &Discovery ) -> Result<()> {
+		if !this.connected.load( Ordering::Acquire ) { return Ok(()) }
+		if this.free_slots().await == 0 { return Ok(()) }
+
+		let channel = this.persistence.load_address().await?;
+		discovery.advertise( &channel, Duration::from_secs( config::DHT_ADVERTISE_EXPIRATION ) ).await?;
+		Ok(())
+	}
+
+	/// Requests the genesis event of the channel, and checks it against the parameters we know of the channel.
+	/// If we don't know them yet, they are stored.
+	async fn check_genesis( &self ) -> Result<()> {
+		let this = &self.0;
+
+		let request = bincode::serialize( &EventsRequest { from_id: GENESIS_EVENT_ID, count: 1 } ).unwrap();
+		let payload = match Self::request_any( this, RequestType::Events, &request ).await? {
+			(ResponseResultType::Success, payload) => payload,
+			(ResponseResultType::InternalError, _) => return Err( Error::GenesisMissing )
+		};
+		let response: EventsResponse = bincode::deserialize( &payload )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "events response".to_owned()))?;
+
+		let event = response.events.first().ok_or( Error::GenesisMissing )?;
+		let (id, event_type, start) = Self::parse_event_header( event )?;
+		match event_type {
+			EventType::Channel if id == GENESIS_EVENT_ID &&
+				event.get( start ) == Some( &(ChannelEventType::Create as u8) ) => {},
+			_ => return Err( Error::GenesisMissing )
+		}
+
+		// The genesis event is kept the first time, so that we can provide it to peers that join later.
+		// It is applied like the other events, with the lock held.
+		let _latest_event_id = this.latest_event_id.lock().await;
+		let known = this.event_persistence.load_parameters().await?.is_some();
+		Self::process_event_channel_create( this.clone(), id, &event[(start + 1)..] ).await?;
+		if !known {
+			Self::store_event( this, id, &event_type, &event[start..] ).await?;
+		}
+
+		Ok(())
+	}
+
+	/// Leaves the swarm.
+	/// The child that can take the most children is elected to take our place, and is given the address of our fastest parent.
+	/// Its siblings are handed to it, for as long as it has room for them, so that our subtree stays together.
+	/// The others are given our fastest parent as well, so that none of them has to find its way back into the swarm from scratch.
+	pub async fn disconnect( &self ) {
+		// TODO: Maybe make this non-async.
+		let this = &self.0;
+		this.connected.store( false, Ordering::Release );
+
+		let parents = this.parents();
+		let fastest = parents.first().map(|p| p.session.address.clone());
+		let mut children = this.children.write().await;
+		let successor = Self::elect( &children, false );
+		for child in children.drain(..) {
+			let handoff = match &successor {
+				Some(s) if !Arc::ptr_eq( s, &child ) && s.take_slot() => GoodbyeMessage { parent: Some( s.session.address.clone() ) },
+				_ => GoodbyeMessage { parent: fastest.clone() }
+			};
+			if let Err(e) = Self::send_goodbye( &child, &handoff ).await {
+				this.errors.report( Some( &child.session.address ), format!("unable to say goodbye: {}", e) );
+			}
+			let _ = child.socket.lock().await.close().await;
+		}
+
+		// Our parents only need to know that our slot is free.
+		for parent in parents {
+			let _ = Self::send_goodbye( &parent, &GoodbyeMessage { parent: None } ).await;
+			let _ = parent.socket.lock().await.close().await;
+		}
+	}
+
+	/// Connects to another parent, besides the ones that we have, so that we stay in the swarm when one of them leaves.
+	/// Returns false if the peer already is one of our parents or children.
+	pub async fn add_parent( &self, address: PublicKey ) -> Result<bool> {
+		let this = &self.0;
+
+		if Self::is_linked( this, &address ).await {
+			return Ok(false)
+		}
+		Self::connect_parent( this.clone(), address, Vec::new() ).await?;
+		Ok(true)
+	}
+
+	/// Hands children over until we have no more than the given relay power allows, which happens when it has been lowered.
+	/// The latest children go first, to the children that have room for them, or else to our fastest parent.
+	pub async fn rebalance( &self, relay_power: u8 ) {
+		let this = &self.0;
+		let previous = this.relay_power.swap( relay_power, Ordering::AcqRel );
+
+		let mut children = this.children.write().await;
+		let slots = 1usize << relay_power;
+		if children.len() > slots {
+			let excess = children.split_off( slots );
+			debug!(children = excess.len(), "Over our relay capacity, handing children over");
+
+			let fastest = this.parents().first().map(|p| p.session.address.clone());
+			for child in excess {
+				let handoff = match Self::elect( &children, false ) {
+					Some(sibling) if sibling.take_slot() => GoodbyeMessage { parent: Some( sibling.session.address.clone() ) },
+					_ => GoodbyeMessage { parent: fastest.clone() }
+				};
+				if let Err(e) = Self::send_goodbye( &child, &handoff ).await {
+					this.errors.report( Some( &child.session.address ), format!("unable to hand over: {}", e) );
+				}
+				let _ = child.socket.lock().await.close().await;
+			}
+		}
+		else if previous == relay_power {
+			return
+		}
+		drop( children );
+
+		Self::announce_capacity( this ).await;
+	}
+
+	/// Applies changed swarm settings: hands over the children that are too many for the relay power, and leaves the parents that are too many, the slowest first.
+	/// More parents are connected to by the subscription manager, which knows where to look for them.
+	pub async fn tune( &self, tuning: &SwarmTuning ) {
+		let this = &self.0;
+		this.background_rebroadcasts.store( tuning.background_rebroadcasts, Ordering::Release );
+		self.rebalance( tuning.relay_power ).await;
+
+		// The parent is only told that our slot is free, and the end of its receive loop takes it out of our parents.
+		for parent in this.parents().iter().skip( tuning.parent_count as usize ) {
+			debug!(parent: &parent.session.span, "Over the number of parents, leaving");
+			let _ = Self::send_goodbye( parent, &GoodbyeMessage { parent: None } ).await;
+			let _ = parent.socket.lock().await.close().await;
+		}
+	}
+
+	/// The number of parents that we are connected to the swarm through.
+	pub fn parent_count( &self ) -> usize {
+		self.0.parents.read().unwrap().len()
+	}
+
+	/// Whether the peer is one of our parents or children.
+	async fn is_linked( this: &NodeInner, address: &PublicKey ) -> bool {
+		let parent = this.parents.read().unwrap().iter().any(|p| p.session.address == *address);
+		let child = this.children.read().await.iter().any(|c| c.session.address == *address);
+		parent || child
+	}
+
+	/// Opens a channel to the peer with the given address.
+	async fn open_link( persistence: &persistence::Handle, transport: &dyn Transport, address: PublicKey, max_response_size: usize, traffic: &Arc<Traffic>, span: &Span ) -> Result<Arc<Link>> {
+		let socket = transport.connect( &address ).await?;
+
+		let session = PeerSession::start( persistence, address, traffic, span ).await?;
+		debug!(parent: &session.span, "Connected to parent");
+		Ok( Arc::new( Link::new( session, socket, max_response_size ) ) )
+	}
+
+	/// Connects to the peer with the given address as another parent, and starts listening to it.
+	///
+	/// # Arguments
+	/// `ancestors` - What we know of the ancestors of the new parent, which stands until it tells us itself.
+	async fn connect_parent( this: Arc<NodeInner>, address: PublicKey, ancestors: Vec<PublicKey> ) -> Result<()> {
+		if this.bad_peers.is_blocked( &address ).await? {
+			return Err( Error::PeerBlocked )
+		}
+
+		let parent = Self::open_link( &this.persistence, &*this.transport, address, this.max_response_size, &this.traffic, &this.span ).await?;
+		*parent.ancestors.lock().unwrap() = ancestors;
+		Self::send_hello( &parent ).await?;
+		this.parents.write().unwrap().push( parent.clone() );
+
+		let span = this.span.clone();
+		runtime::spawn( Self::parent_receive_loop( this.clone(), parent ).instrument( span ) );
+
+		// Our children have another ancestor now, and our other parents another path through which we're connected.
+		Self::announce_ancestors( &this ).await;
+		Self::announce_capacity( &this ).await;
+		Self::drain_outbox( &this );
+		Ok(())
+	}
+
+	/// Gets us back into the swarm after we've lost our last parent, without leaving our subtree behind.
+	/// We reconnect to the ancestors that the lost parent has told us about, nearest first.
+	/// If none of them takes us, the child that can take the most children, and that is connected to the swarm through another parent, is elected to reconnect through instead.
+	/// Returns false if neither has worked, in which case the swarm has to be found again from scratch.
+	async fn heal( this: Arc<NodeInner>, lost: &Link ) -> bool {
+		let ancestors = lost.ancestors.lock().unwrap().clone();
+		for address in ancestors {
+			if Self::is_linked( &this, &address ).await { continue }
+
+			match Self::connect_parent( this.clone(), address.clone(), lost.ancestors_beyond( &address ) ).await {
+				Ok(()) => {
+					debug!(ancestor = %address, "Reconnected to an ancestor");
+					return true
+				},
+				Err(e) => this.errors.report( Some( &address ), format!("unable to reconnect to ancestor: {}", e) )
+			}
+		}
+
+		let elected = Self::elect( &*this.children.read().await, true );
+		if let Some(child) = elected {
+			// The child can't be our parent and our child at once, and it stays in the swarm through its other parents.
+			this.children.write().await.retain(|c| !Arc::ptr_eq( c, &child ));
+			let _ = child.socket.lock().await.close().await;
+
+			let address = child.session.address.clone();
+			match Self::connect_parent( this.clone(), address.clone(), Vec::new() ).await {
+				Ok(()) => {
+					debug!(child = %address, "Reconnected through a promoted child");
+					return true
+				},
+				Err(e) => this.errors.report( Some( &address ), format!("unable to reconnect through promoted child: {}", e) )
+			}
+		}
+		false
+	}
+
+	/// Picks the child that can take the most children, out of the ones that have room for at least one.
+	/// With `connected`, only the children that are connected to the swarm through other parents as well are considered.
+	fn elect( children: &[Arc<Link>], connected: bool ) -> Option<Arc<Link>> {
+		children.iter()
+			.filter_map(|c| c.capacity.lock().unwrap().map(|(free, others)| (c, free, others)))
+			.filter(|(_, free, others)| *free > 0 && (*others || !connected))
+			.max_by_key(|(_, free, _)| *free)
+			.map(|(c, _, _)| c.clone())
+	}
+
+	/// Tells our children about our ancestors, if they have changed since we last did.
+	/// They pass it on to their own children in turn, so that the whole subtree learns about the new structure.
+	async fn announce_ancestors( this: &NodeInner ) {
+		let ancestors = this.ancestors();
+		{
+			let mut announced = this.announced_ancestors.lock().unwrap();
+			if *announced == ancestors { return }
+			*announced = ancestors.clone();
+		}
+
+		let topology = TopologyMessage::Ancestors( ancestors );
+		let children = this.children.read().await.clone();
+		for child in children {
+			Self::send_topology( this, &child, &topology ).await;
+		}
+	}
+
+	/// Tells our parents how many more children we can take.
+	async fn announce_capacity( this: &NodeInner ) {
+		let topology = this.capacity().await;
+		for parent in this.parents() {
+			Self::send_topology( this, &parent, &topology ).await;
+		}
+	}
+
+	/// Sends a topology message to the peer, if it has said that it understands them.
+	/// Errors are only reported, as the topology only helps the swarm to heal.
+	async fn send_topology( this: &NodeInner, link: &Link, topology: &TopologyMessage ) {
+		if !link.session.confirms( ProtocolVersion::has_topology ).await { return }
+
+		let mut message = vec![ MessageDirectionType::Topology as u8 ];
+		message.extend( bincode::serialize( topology ).unwrap() );
+		let message = seal_message( this.key.as_ref(), message );
+		if let Err(e) = link.send( &*message, Priority::Normal ).await {
+			this.errors.report( Some( &link.session.address ), format!("unable to send topology: {}", e) );
+		}
+	}
+
+	/// Tells the peer that we are leaving, if it speaks a version of the protocol that has goodbye messages.
+	async fn send_goodbye( link: &Link, goodbye: &GoodbyeMessage ) -> Result<()> {
+		// Peers that haven't said hello are assumed to speak our version.
+		if !link.session.speaks( ProtocolVersion::has_goodbye ).await { return Ok(()) }
+
+		let mut message = vec![ MessageDirectionType::Goodbye as u8 ];
+		message.extend( bincode::serialize( goodbye ).unwrap() );
+
+		link.send( &*message, Priority::Normal ).await
+			.map_err(|e| Error::Gnunet(e.into()))?;
+		Ok(())
+	}
+
+	/// Accepts a peer that wants to join the swarm through us.
+	/// The number of children is limited to 2 to the power of the relay power.
+	/// If all relay slots are taken, the child with the worst reputation is evicted to make room, but only if the newcomer has a better reputation.
+	/// Returns whether the peer has been accepted.
+	pub async fn admit_child( &self, socket: Box<dyn PeerChannel> ) -> Result<bool> {
+		let this = &self.0;
+		let address = socket.peer().clone();
+
+		if this.bad_peers.is_blocked( &address ).await? {
+			return Ok(false)
+		}
+
+		let mut children = this.children.write().await;
+		if children.len() >= (1 << this.relay_power.load( Ordering::Acquire )) {
+			let addresses: Vec<PublicKey> = children.iter().map(|c| c.session.address.clone()).collect();
+			let (worst, worst_score) = match this.reputation.worst( &addresses ).await? {
+				None => return Ok(false),	// No relay slots at all
+				Some(w) => w
+			};
+			if this.reputation.score( &address ).await? <= worst_score {
+				return Ok(false)
+			}
+
+			// The evicted child is handed to a sibling that has room for it, or else to our fastest parent, rather than being cut off.
+			let evicted = children.remove( worst );
+			let handoff = match Self::elect( &children, false ) {
+				Some(sibling) if sibling.take_slot() => GoodbyeMessage { parent: Some( sibling.session.address.clone() ) },
+				_ => GoodbyeMessage { parent: this.parents().first().map(|p| p.session.address.clone()) }
+			};
+			let _ = Self::send_goodbye( &evicted, &handoff ).await;
+			let _ = evicted.socket.lock().await.close().await;
+		}
+
+		let session = PeerSession::start( &this.persistence, address, &this.traffic, &this.span ).await?;
+		let child = Arc::new( Link::new( session, socket, this.max_response_size ) );
+		children.push( child.clone() );
+		drop( children );
+		debug!(parent: &child.session.span, "Admitted as a child");
+		Self::announce_capacity( this ).await;
+		Self::drain_outbox( this );
+
+		let this2 = this.clone();
+		let span = child.session.span.clone();
+		runtime::spawn(async move {
+			let errors = this2.errors.clone();
+			let address = child.session.address.clone();
+			Self::peer_receive_loop( this2.clone(), &child, |e| {
+				errors.report( Some( &address ), format!("error while listening: {}", e) )
+			}).await;
+			child.session.store( &this2.persistence, true ).await;
+
+			// The child is gone, so its slot is free again.
+			this2.children.write().await.retain(|c| !Arc::ptr_eq( c, &child ));
+			Self::announce_capacity( &this2 ).await;
+		}.instrument( span ));
+
+		Ok(true)
+	}
+
+	/// Listens to one of our parents, until it leaves or the channel with it is closed.
+	/// When a parent hands us off to another peer, that peer has its own receive loop.
+	/// When the last parent vanishes, we try to heal the swarm before giving up on it.
+	async fn parent_receive_loop( this: Arc<NodeInner>, parent: Arc<Link> ) {
+		let errors = this.errors.clone();
+		let address = parent.session.address.clone();
+		Self::peer_receive_loop( this.clone(), &parent, |e| {
+			errors.report( Some( &address ), format!("error while listening: {}", e) )
+		}).instrument( parent.session.span.clone() ).await;
+		parent.session.store( &this.persistence, true ).await;
+
+		let orphaned = {
+			let mut parents = this.parents.write().unwrap();
+			parents.retain(|p| !Arc::ptr_eq( p, &parent ));
+			parents.is_empty()
+		};
+
+		// The parents are closed when we disconnect ourselves, which is nothing to heal.
+		if !this.connected.load( Ordering::Acquire ) { return }
+
+		if !orphaned {
+			Self::announce_ancestors( &this ).await;
+			Self::announce_capacity( &this ).await;
+		}
+		// Without a parent, we're cut off from the swarm.
+		else if !Self::heal( this.clone(), &parent ).await {
+			this.connected.store( false, Ordering::Release );
+		}
+	}
+
+	/// The loop that needs to be run in order to process the messages that this node may receive for a given peer
+	/// 
+	/// # Arguments
+	/// `on_error` - A closure that is called with the errors that occur on the channel.
+	///
+	/// When the peer turns out to be malicious, it is flagged in the bad peer store, and the loop ends.
+	/// Most often this is because a message has appeared incorrect.
+	/// The events that the peer sends are put in its queue, to be applied by the event loop.
+	async fn peer_receive_loop<E>( this_: Arc<NodeInner>, link: &Arc<Link>, on_error: E ) where
+		E: Fn( gnunet::Error )
+	{
+		let session = &link.session;
+		// The receiver is taken once, rather than locking the channel for every message, which everything that sends to the peer locks as well.
+		let receiver = link.socket.lock().await.receiver();
+		let queues = PeerQueues {
+			events: this_.events.add_source( session.address.clone() ),
+			requests: this_.requests.add_source( session.address.clone() )
+		};
+		// Anything that the peer sends shows that it is still there.
+		let mut last_seen = Instant::now();
+
+		// Loop until channel is closed
+		loop {
+			let this = this_.clone();
+			let result: gnunet::Result<bool> = async {
+
+				let ping_interval = Duration::from_secs( config::get().ping_interval );
+				let message = match timeout( ping_interval, receiver.receive() ).await {
+					Err(_) => return Self::keep_alive( &this, link, last_seen ).await,
+					Ok(None) => return Ok(false),	// break
+					Ok(Some(m)) => m
+				};
+				last_seen = Instant::now();
+				trace!(bytes = message.len(), "Received message");
+				let count = session.messages.fetch_add( 1, Ordering::AcqRel ) + 1;
+				session.bytes.fetch_add( message.len() as u64, Ordering::AcqRel );
+				session.traffic.received.fetch_add( message.len() as u64, Ordering::AcqRel );
+				metrics::BYTES_RECEIVED.inc_by( message.len() as u64 );
+				if count % PEER_STATS_STORE_INTERVAL == 0 {
+					session.store( &this.persistence, false ).await;
+				}
+
+				let direction = message.first().cloned();
+				let result = if direction == Some( MessageDirectionType::Hello as u8 ) {
+					Self::process_hello( &this, link, &message[1..] ).await
+				} else if direction == Some( MessageDirectionType::Goodbye as u8 ) {
+					// Nothing will come from a peer that said goodbye anymore.
+					match Self::process_goodbye( this, session, &message[1..] ).await {
+						Ok(()) => return Ok(false),	// break
+						Err(e) => Err(e)
+					}
+				} else {
+					Self::process_message( this, link, &queues, &*message ).await
+				};
+				match result {
+					Err(err) => {
+						match err {
+							Error::IncompatibleVersion(_) => {
+								this_.errors.report( Some( &session.address ), format!("disconnecting: {}", err) );
+								return Ok(false)	// break
+							},
+							Error::RateLimitExceeded => {
+								this_.reputation.adjust( &session.address, REPUTATION_MALFORMED ).await;
+								this_.errors.report( Some( &session.address ), format!("disconnecting: {}", err) );
+								this_.bad_peers.flag( &session.address, "flooding" ).await;
+								return Ok(false)	// break
+							},
+							Error::MessageMalformed(e) => {
+								// The malformed count is what lowers the reputation of the peer.
+								session.malformed.fetch_add( 1, Ordering::AcqRel );
+								metrics::MALFORMED_MESSAGES.inc();
+								this_.reputation.adjust( &session.address, REPUTATION_MALFORMED ).await;
+								this_.errors.report( Some( &session.address ), format!("malformed message, repelling it: {}", e) );
+								this_.bad_peers.flag( &session.address, &format!("malformed message: {}", e) ).await;
+								return Ok(false)	// break
+							},
+							Error::Gnunet(e) => Err(e)?,
+							other => panic!("Unexpected error occurred while processing message: {}", other)
+						}
+					},
+					Ok(()) => Ok(true)
+				}
+			}.await;
+
+			match result {
+				Err(e) => on_error( e ),
+				Ok(cont) => if !cont { break }
+			}
+		}
+		debug!("Disconnected");
+	}
+
+	/// Pings a peer that has been silent for a while, or closes the channel with it if it has been silent for too long.
+	/// Peers that don't answer pings are left alone, because they may just have nothing to say.
+	/// Returns false if the channel has been closed, after which the link is dropped like that of any peer that is gone.
+	async fn keep_alive( this: &NodeInner, link: &Link, last_seen: Instant ) -> gnunet::Result<bool> {
+		if !link.session.speaks( ProtocolVersion::has_ping ).await {
+			return Ok(true)
+		}
+
+		let silent = last_seen.elapsed();
+		if silent >= Duration::from_secs( config::get().liveness_deadline ) {
+			metrics::DEAD_CONNECTIONS.inc();
+			this.errors.report( Some( &link.session.address ), format!("no sign of life for {} seconds, closing the channel", silent.as_secs()) );
+			let _ = link.socket.lock().await.close().await;
+			return Ok(false)
+		}
+
+		trace!("Pinging");
+		let message = seal_message( this.key.as_ref(), vec![ MessageDirectionType::Ping as u8 ] );
+		link.send( &*message, Priority::Normal ).await?;
+		Ok(true)
+	}
+
+	/// Sends our hello message to the peer, if we haven't done so already.
+	async fn send_hello( link: &Link ) -> Result<()> {
+		if link.session.hello_sent.swap( true, Ordering::AcqRel ) {
+			return Ok(())
+		}
+
+		let mut message = vec![ MessageDirectionType::Hello as u8 ];
+		message.extend( bincode::serialize( &HelloMessage { version: PROTOCOL_VERSION } ).unwrap() );
+
+		link.send( &*message, Priority::Normal ).await
+			.map_err(|e| Error::Gnunet(e.into()))?;
+		Ok(())
+	}
+
+	/// Negotiates the protocol version with the peer that said hello.
+	/// Peers that haven't said hello are assumed to speak our version.
+	/// Once we know that the peer understands topology messages, it is told what it needs to know about the swarm around us.
+	async fn process_hello( this: &NodeInner, link: &Arc<Link>, message: &[u8] ) -> Result<()> {
+
+		let hello: HelloMessage = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "hello message".to_owned()))?;
+		let negotiated = PROTOCOL_VERSION.negotiate( &hello.version )
+			.ok_or_else(|| Error::IncompatibleVersion( hello.version.clone() ))?;
+		*link.session.version.lock().await = Some( negotiated );
+
+		// The peer that opened the channel is answered with our own hello.
+		Self::send_hello( link ).await?;
+
+		let child = this.children.read().await.iter().any(|c| Arc::ptr_eq( c, link ));
+		let topology = if child {
+			TopologyMessage::Ancestors( this.ancestors() )
+		} else {
+			this.capacity().await
+		};
+		Self::send_topology( this, link, &topology ).await;
+		Ok(())
+	}
+
+	/// Handles a peer that leaves the swarm.
+	/// If it is one of our parents, we connect to the parent that it has handed us instead, so that we keep our number of parents.
+	/// Either way, its link is removed once its receive loop ends.
+	async fn process_goodbye( this: Arc<NodeInner>, session: &PeerSession, message: &[u8] ) -> Result<()> {
+
+		let goodbye: GoodbyeMessage = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "goodbye message".to_owned()))?;
+
+		if !this.parents().iter().any(|p| std::ptr::eq( &p.session, session )) {
+			return Ok(())
+		}
+
+		// Errors other than malformed messages would end the receive loop badly, so they are only reported.
+		match goodbye.parent {
+			None => this.errors.report( Some( &session.address ), "parent left without handing us another one".to_owned() ),
+			// The parent that was handed to us may be one of our other parents already.
+			Some(address) => if !Self::is_linked( &this, &address ).await {
+				let ancestors = this.parents().iter()
+					.find(|p| std::ptr::eq( &p.session, session ))
+					.map(|p| p.ancestors_beyond( &address ))
+					.unwrap_or_default();
+				if let Err(e) = Self::connect_parent( this.clone(), address.clone(), ancestors ).await {
+					this.errors.report( Some( &address ), format!("unable to connect to the parent that was handed to us: {}", e) );
+				}
+			}
+		}
+		Ok(())
+	}
+
+	/// Processes a message from a peer.
+	/// Returns whether or not the message was considered to be benevolent.
+	/// If the message was malformed, the message is considered to be malicious.
+	/// Events and requests are only put in the queues of the peer here, they are checked when the event loop or a request worker gets to them.
+	async fn process_message( this: Arc<NodeInner>, link: &Arc<Link>, queues: &PeerQueues, message: &[u8] ) -> Result<()> {
+		// The messages of private channels need to be decrypted first, and then decompressed if they have been compressed.
+		let message = open_message( this.key.as_ref(), message )?;
+		let message = decompress_message( message, config::MAX_RESPONSE_SIZE )?;
+
+		let direction_type: MessageDirectionType = match message[0].try_into() {
+			Err(e) => Err(MessageMalformedError::InvalidTypeId(message[0], "direction type".to_owned()))?,
+			Ok(d) => d
+		};
 		
+		if !Self::within_rate_limit( &link.session, &direction_type )? {
+			return Ok(())
+		}
+
+		match direction_type {
+			MessageDirectionType::Event => Self::queue_event( link, &queues.events, &message[1..] ).await,
+			MessageDirectionType::Request => Self::queue_request( link, &queues.requests, &message[1..] ).await,
+			MessageDirectionType::Response => Self::process_response( this, link, &message[1..] ).await?,
+			MessageDirectionType::ResponsePart => Self::process_response_part( this, link, &message[1..] ).await?,
+			MessageDirectionType::Ping => {
+				let pong = seal_message( this.key.as_ref(), vec![ MessageDirectionType::Pong as u8 ] );
+				link.send( &*pong, Priority::Normal ).await
+					.map_err(|e| Error::Gnunet(e.into()))?
+			},
+			// Receiving the pong is all that it is for.
+			MessageDirectionType::Pong => {},
+			MessageDirectionType::Topology => Self::process_topology( this, link, &message[1..] ).await?,
+			MessageDirectionType::Census => Self::process_census( this, link, &message[1..] ).await?,
+			// Messages are only ever compressed once, which `decompress_message` holds peers to.
+			MessageDirectionType::Compressed => Err(MessageMalformedError::UnexpectedData("compressed message".to_owned()))?,
+			// Hello messages are handled before anything gets decrypted, so they should never end up here.
+			MessageDirectionType::Hello => Err(MessageMalformedError::UnexpectedData("hello message".to_owned()))?,
+			MessageDirectionType::Goodbye => Err(MessageMalformedError::UnexpectedData("goodbye message".to_owned()))?
+		};
+
+		Ok(())
+	}
+
+	/// Takes in what a neighbour tells us about the swarm around it.
+	/// Ancestors only come from parents, and capacities only from children.
+	/// Anything else is ignored rather than taken for malformed, as a peer may just have been promoted or handed over.
+	async fn process_topology( this: Arc<NodeInner>, link: &Arc<Link>, message: &[u8] ) -> Result<()> {
+
+		let topology: TopologyMessage = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "topology message".to_owned()))?;
+
+		match topology {
+			TopologyMessage::Ancestors( ancestors ) => {
+				if ancestors.len() > TOPOLOGY_ANCESTORS_MAX {
+					Err(MessageMalformedError::UnexpectedData("ancestors".to_owned()))?
+				}
+				let parent = this.parents.read().unwrap().iter().any(|p| Arc::ptr_eq( p, link ));
+				if !parent { return Ok(()) }
+
+				*link.ancestors.lock().unwrap() = ancestors;
+				// Our own ancestors may have changed along with those of our parent.
+				Self::announce_ancestors( &this ).await;
+			},
+			TopologyMessage::Capacity { free, other_parents } => {
+				let child = this.children.read().await.iter().any(|c| Arc::ptr_eq( c, link ));
+				if !child { return Ok(()) }
+
+				*link.capacity.lock().unwrap() = Some(( free, other_parents ));
+			}
+		}
+		Ok(())
+	}
+
+	/// Takes in what a neighbour has counted of the swarm.
+	/// Shares of subtrees only come from children, and swarm sizes only from parents, anything else is ignored like it is for topology messages.
+	async fn process_census( this: Arc<NodeInner>, link: &Arc<Link>, message: &[u8] ) -> Result<()> {
+
+		let census: CensusMessage = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "census message".to_owned()))?;
+
+		let (count, from_child) = match census {
+			CensusMessage::Subtree( share ) => (share, true),
+			CensusMessage::Swarm( size ) => (size, false)
+		};
+		let linked = if from_child {
+			this.children.read().await.iter().any(|c| Arc::ptr_eq( c, link ))
+		} else {
+			this.parents.read().unwrap().iter().any(|p| Arc::ptr_eq( p, link ))
+		};
+		if linked {
+			*link.census.lock().unwrap() = Some( count );
+		}
+		Ok(())
+	}
+
+	/// Tells our parents our share of the subtree, and our children the size of the swarm.
+	/// The owners of the channel keep a sample of the count every `CENSUS_SAMPLE_INTERVAL`, for the history on their dashboard.
+	async fn take_census( this: &Arc<NodeInner>, last_sample: &mut Option<Instant> ) {
+		if !this.connected.load( Ordering::Acquire ) { return }
+
+		let parents = this.parents();
+		let share = this.subtree_size().await / cmp::max( parents.len(), 1 ) as f64;
+		let subtree = CensusMessage::Subtree( (share * CENSUS_UNIT as f64).round() as u32 );
+		for parent in &parents {
+			Self::send_census( this, parent, &subtree ).await;
+		}
+
+		let swarm_size = this.swarm_size().await;
+		let children = this.children.read().await.clone();
+		if let Some(size) = swarm_size {
+			let swarm = CensusMessage::Swarm( size );
+			for child in &children {
+				Self::send_census( this, child, &swarm ).await;
+			}
+		}
+
+		let due = last_sample.map(|t| t.elapsed() >= Duration::from_secs( config::CENSUS_SAMPLE_INTERVAL )).unwrap_or( true );
+		if !due { return }
+		match this.persistence.load_owner_ego().await {
+			Err(e) => this.errors.report( None, format!("unable to look up the owner of the channel: {}", e) ),
+			Ok(None) => {},
+			Ok(Some(_)) => {
+				if let Err(e) = this.persistence.store_census( children.len() as _, swarm_size ).await {
+					this.errors.report( None, format!("unable to store the census: {}", e) );
+				}
+				*last_sample = Some( Instant::now() );
+			}
+		}
+	}
+
+	/// Sends a census message to the peer, if it has said that it understands them.
+	/// Errors are only reported, as the census is only informative.
+	async fn send_census( this: &NodeInner, link: &Link, census: &CensusMessage ) {
+		if !link.session.confirms( ProtocolVersion::has_census ).await { return }
+
+		let mut message = vec![ MessageDirectionType::Census as u8 ];
+		message.extend( bincode::serialize( census ).unwrap() );
+		let message = seal_message( this.key.as_ref(), message );
+		if let Err(e) = link.send( &*message, Priority::Normal ).await {
+			this.errors.report( Some( &link.session.address ), format!("unable to send census: {}", e) );
+		}
+	}
+
+	/// Takes a token from the bucket of the peer for events or requests, other messages aren't limited.
+	/// Pings count as requests, because they make us respond as well.
+	/// Returns false if the message should be dropped because the peer is sending too fast.
+	/// A peer that keeps doing so has gone over the number of strikes, which is an error.
+	fn within_rate_limit( session: &PeerSession, direction_type: &MessageDirectionType ) -> Result<bool> {
+		let config = config::get();
+		let allowed = match direction_type {
+			MessageDirectionType::Event => session.event_limit.lock().unwrap().take( config.peer_event_rate, config.peer_event_burst ),
+			MessageDirectionType::Request | MessageDirectionType::Ping => session.request_limit.lock().unwrap().take( config.peer_request_rate, config.peer_request_burst ),
+			_ => true
+		};
+		if allowed {
+			return Ok( true )
+		}
+
+		metrics::RATE_LIMITED_MESSAGES.inc();
+		let strikes = session.rate_limited.fetch_add( 1, Ordering::AcqRel ) + 1;
+		if strikes >= config::PEER_RATE_LIMIT_STRIKES {
+			return Err( Error::RateLimitExceeded )
+		}
+		// Only the first one is logged, so that the flood doesn't flood the log as well.
+		if strikes == 1 {
+			warn!("Peer is sending faster than allowed, dropping its messages.");
+		}
+		Ok( false )
+	}
+
+	/// Puts an event in the queue of the peer that sent it, waiting for room if the peer has sent more than we can keep up with.
+	async fn queue_event( link: &Arc<Link>, queue: &SourceSender<QueuedEvent>, message: &[u8] ) {
+		// The queue is only closed when the node is gone, in which case the event is of no use anymore.
+		queue.send( QueuedEvent {
+			link: link.clone(),
+			message: message.to_vec()
+		}).await;
+	}
+
+	/// Puts a request in the queue of the peer that sent it, waiting for room if the peer has sent more than the request workers can keep up with.
+	async fn queue_request( link: &Arc<Link>, queue: &SourceSender<QueuedRequest>, message: &[u8] ) {
+		// The queue is only closed when the node is gone, in which case nobody is left to answer.
+		queue.send( QueuedRequest {
+			link: link.clone(),
+			message: message.to_vec()
+		}).await;
+	}
+
+	/// Answers the requests in the queues of the peers, taking one from every peer in turn, until the node is gone.
+	/// Several of these run at the same time, which is fine as requests don't depend on each other.
+	async fn request_worker( this: Weak<NodeInner>, requests: Arc<FairQueue<PublicKey, QueuedRequest>> ) {
+		while let Some((address, request)) = requests.next().await {
+			let this = match this.upgrade() {
+				None => break,
+				Some(t) => t
+			};
+
+			let span = request.link.session.span.clone();
+			match Self::process_request( this.clone(), &request.link, &request.message ).instrument( span ).await {
+				Ok(()) => {},
+				Err(Error::MessageMalformed(e)) => {
+					request.link.session.malformed.fetch_add( 1, Ordering::AcqRel );
+					metrics::MALFORMED_MESSAGES.inc();
+					this.reputation.adjust( &address, REPUTATION_MALFORMED ).await;
+					this.errors.report( Some( &address ), format!("malformed request, repelling peer: {}", e) );
+					this.bad_peers.flag( &address, &format!("malformed message: {}", e) ).await;
+
+					// Closing the channel ends the receive loop of the peer.
+					let _ = request.link.socket.lock().await.close().await;
+				},
+				Err(other) => this.errors.report( Some( &address ), format!("unable to answer request: {}", other) )
+			}
+		}
+	}
+
+	/// Applies the events in the queues of the peers, taking one from every peer in turn, until the node is gone.
+	async fn event_loop( this: Weak<NodeInner>, events: Arc<FairQueue<PublicKey, QueuedEvent>> ) {
+		while let Some((address, event)) = events.next().await {
+			let this = match this.upgrade() {
+				None => break,
+				Some(t) => t
+			};
+
+			let span = event.link.session.span.clone();
+			match Self::process_event( this.clone(), &event ).instrument( span ).await {
+				Ok(true) => metrics::EVENTS_PROCESSED.inc(),
+				Ok(false) => {},
+				Err(Error::MessageMalformed(e)) => {
+					// The malformed count is what lowers the reputation of the peer.
+					event.link.session.malformed.fetch_add( 1, Ordering::AcqRel );
+					metrics::MALFORMED_MESSAGES.inc();
+					this.reputation.adjust( &address, REPUTATION_MALFORMED ).await;
+					this.errors.report( Some( &address ), format!("malformed event, repelling peer: {}", e) );
+					this.bad_peers.flag( &address, &format!("malformed message: {}", e) ).await;
+
+					// Closing the channel ends the receive loop of the peer.
+					let _ = event.link.socket.lock().await.close().await;
+				},
+				Err(other) => this.errors.report( Some( &address ), format!("unable to process event: {}", other) )
+			}
+		}
+	}
+
+	/// Processes an event and passes it on to our other peers.
+	/// Returns false if the event has already arrived through another parent, or isn't new to us otherwise, in which case it isn't passed on.
+	async fn process_event( this: Arc<NodeInner>, event: &QueuedEvent ) -> Result<bool> {
+		let (id, _, _) = Self::parse_event_header( &event.message )?;
+		let hash = HashCode::generate( &event.message );
+		let sender = &event.link.session.address;
+
+		// The copies that arrive through our other parents are expected, but the same peer sending an event twice isn't.
+		let mut replayed = false;
+		if let Some(seen) = this.recent_events.lock().unwrap().iter_mut().find(|e| e.id == id && e.hash == hash) {
+			if !seen.senders.contains( sender ) {
+				seen.senders.push( sender.clone() );
+				return Ok(false)
+			}
+			replayed = true;
+		}
+		if !replayed {
+			replayed = *this.latest_event_id.lock().await >= id.saturating_add( RECENT_EVENTS as u64 );
+		}
+		if replayed {
+			metrics::REPLAYED_EVENTS.inc();
+			this.reputation.adjust( sender, REPUTATION_REPLAYED ).await;
+			debug!(event_id = id, "Dropped a replayed event");
+			return Ok(false)
+		}
+
+		let reception = Self::receive_event( this.clone(), &event.message ).await?;
+		if let Reception::Dropped = reception {
+			debug!(event_id = id, "Dropped an event that differs from the one that has been applied, or that has been kept already");
+			return Ok(false)
+		}
+
+		// Only events that haven't been found to be malformed are remembered, so that a forged copy can't keep out the real one.
+		{
+			let mut recent = this.recent_events.lock().unwrap();
+			if recent.len() >= RECENT_EVENTS {
+				recent.pop_front();
+			}
+			recent.push_back( SeenEvent {
+				id,
+				hash,
+				senders: vec![ sender.clone() ]
+			});
+		}
+		// The events that have been applied before, through another peer or before a restart, have been passed on back then.
+		if let Reception::Known = reception {
+			return Ok(false)
+		}
+
+		// Either way, rebroadcast the message, as it is new to us and wasn't found to be malformed/invalid.
+		let address = sender.clone();
+		let errors = this.errors.clone();
+		let channel_id = event.link.socket.lock().await.id();
+		Self::rebroadcast_message( this, &event.message, channel_id, |e| {
+			errors.report( Some( &address ), format!("error while relaying: {}", e) )
+		}).await;
+
+		Ok(true)
+	}
+
+	/// Applies the event if it is the next one we need to process, or stores it for later processing otherwise.
+	/// If this reveals that we've missed some events, the missing range is requested from the parent.
+	/// Events with ids that have been applied already are compared to the events that have been applied with them.
+	async fn receive_event( this: Arc<NodeInner>, message: &[u8] ) -> Result<Reception> {
+
+		let (id, event_type, start) = Self::parse_event_header( message )?;
+		let event_message = &message[start..];
+
+		let mut latest_event_id = this.latest_event_id.lock().await;
+
+		// If this is the next event we need to process, process it immediately.
+		if id == (*latest_event_id + 1) {
+			Self::apply_event( this.clone(), id, &event_type, event_message ).await?;
+
+			// We can only update the event id after we know it wasn't malformed
+			*latest_event_id = id;
+
+			// The events that arrived too early might be next in line now.
+			Self::apply_pending_events( this.clone(), &mut *latest_event_id ).await?;
+			Ok( Reception::Accepted )
+		}
+		// Otherwise, store it for later processing
+		else if id > *latest_event_id {
+			// If the event is that much more newer than our last received/known event id,
+			//  we assume the message was malevolent.
+			// Otherwise, we'd be vurnerable to filling our disk with senseless data.
+			if id - *latest_event_id > EVENTS_REQUEST_MAX_COUNT as u64 {
+				Err( MessageMalformedError::InvalidEventId( id ) )?
+			}
+			// It can't be checked yet, so it is kept apart from the event log until it is applied.
+			if !this.event_persistence.store_pending_event( id, &event_type, event_message ).await? {
+				return Ok( Reception::Dropped )
+			}
+
+			// Request the events that we've missed, unless we're already doing so.
+			if !this.backfilling.swap( true, Ordering::AcqRel ) {
+				let from_id = *latest_event_id + 1;
+				let count = (id - from_id) as u16;
+				let this2 = this.clone();
+
+				runtime::spawn(async move {
+					if let Err(e) = Self::backfill_events( this2.clone(), from_id, count ).await {
+						this2.errors.report( None, format!("unable to backfill events {} to {}: {}", from_id, from_id + count as u64, e) );
+					}
+					this2.backfilling.store( false, Ordering::Release );
+				});
+			}
+			Ok( Reception::Accepted )
+		}
+		// An event that has been applied already is only the same event if it is exactly the message that has been applied.
+		else {
+			let stored = this.event_persistence.load_events( id, 1 ).await?;
+			match stored.first() {
+				Some((_, applied)) if applied.as_slice() == message => Ok( Reception::Known ),
+				_ => Ok( Reception::Dropped )
+			}
+		}
+	}
+
+	/// Reads the event id and event type from the start of an event message.
+	/// Also returns the index at which the rest of the event message starts.
+	fn parse_event_header( message: &[u8] ) -> Result<(u64, EventType, usize)> {
+
+		let id: u64 = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "event id".to_owned()))?;
+		if message.len() < 9 {
+			Err(MessageMalformedError::MissingData("event type".to_owned()))?
+		}
+		let event_type: EventType = bincode::deserialize( &message[8..] )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "event type".to_owned()))?;
+		let start = 8 + bincode::serialized_size( &event_type ).unwrap() as usize;
+
+		Ok(( id, event_type, start ))
+	}
+
+	/// Applies the event, stores it and marks it as the last applied event, all in one transaction.
+	/// That way, a crash can't leave an event half applied, or applied without being marked as such.
+	async fn apply_event( this: Arc<NodeInner>, id: u64, event_type: &EventType, message: &[u8] ) -> Result<()> {
+
+		let (change, notification, detached_files) = this.event_persistence.atomically(async {
+			let (change, notification, detached_files) = match event_type {
+				EventType::Channel => {
+					Self::process_event_channel( this.clone(), id, message ).await?;
+					(None, None, Vec::new())
+				},
+				EventType::Publisher( address ) => Self::process_event_publisher( this.clone(), id, address, message ).await?
+			};
+
+			// Keep the event around, so that we can provide it to peers that have missed it.
+			Self::store_event( &this, id, event_type, message ).await?;
+			this.event_persistence.store_last_event_id( id ).await?;
+			this.event_persistence.remove_pending_events( id ).await?;
+			Ok::<_, Error>(( change, notification, detached_files ))
+		}).await?;
+
+		// The files of a forgotten post can only be removed in a transaction of their own, and only once the post is gone.
+		if detached_files.len() > 0 {
+			this.event_persistence.base.remove_unused_files( &detached_files ).await?;
+		}
+
+		// The change is only announced once it has been committed, so that it can be loaded by whoever hears about it.
+		// For the same reason, the cached pages are only dropped now, as they could be rendered again from before the change otherwise.
+		page_cache::PAGES.invalidate( this.event_persistence.id );
+		if let Some(change) = change {
+			bus::POSTS.announce( change );
+		}
+		if let Some(notification) = notification {
+			let unread = this.event_persistence.base.count_unread_notifications().await?;
+			bus::NOTIFICATIONS.announce( NewNotification { notification, unread } );
+		}
+
+		this.sync.events_applied.fetch_add( 1, Ordering::AcqRel );
+		Ok(())
+	}
+
+	/// Applies the pending events that are next in line, until there is a gap again.
+	/// Any peer may have sent a message for the next id, so the first one that turns out to be valid is taken for the event.
+	/// If none of them is, they are all dropped, so that the event is requested again.
+	async fn apply_pending_events( this: Arc<NodeInner>, latest_event_id: &mut u64 ) -> Result<()> {
+
+		loop {
+			let next_id = *latest_event_id + 1;
+			let messages = this.event_persistence.load_pending_events( next_id ).await?;
+			if messages.len() == 0 {
+				return Ok(())
+			}
+
+			let mut applied = false;
+			for message in messages {
+				let (id, event_type, start) = Self::parse_event_header( &message )?;
+				match Self::apply_event( this.clone(), id, &event_type, &message[start..] ).await {
+					Ok(()) => {
+						applied = true;
+						break
+					},
+					Err(Error::MessageMalformed(e)) => this.errors.report( None, format!("dropping pending event {}: {}", id, e) ),
+					Err(e) => return Err(e)
+				}
+			}
+			if !applied {
+				this.event_persistence.remove_pending_events( next_id ).await?;
+				return Ok(())
+			}
+			*latest_event_id = next_id;
+		}
+	}
+
+	async fn store_event( this: &Arc<NodeInner>, id: u64, event_type: &EventType, message: &[u8] ) -> Result<()> {
+
+		match event_type {
+			EventType::Channel => this.event_persistence.store_event( id, message ).await?,
+			EventType::Publisher(address) => match this.event_persistence.get_timeline( address ).await? {
+				None => Err( MessageMalformedError::UnknownPublisher(address.clone()) )?,
+				Some( timeline ) => timeline.store_event( id, message ).await?
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Requests the posts `start..(start + count)` of the given publisher from the parent, and stores the ones that we receive.
+	/// Every received post is verified before it is stored.
+	/// Returns the ids of the posts that were stored.
+	pub async fn sync_posts( &self, publisher: &PublicKey, start: u64, count: u16, include_content: bool ) -> Result<Vec<u64>> {
+		let this = &self.0;
+
+		let timeline = match this.persistence.get_timeline( publisher ).await? {
+			None => Err( MessageMalformedError::UnknownPublisher( publisher.clone() ) )?,
+			Some(t) => t
+		};
+
+		Self::request_posts( this, &timeline, publisher, &IdSet::range( start, count as u64 ), include_content ).await
+	}
+
+	/// Finds out which posts of the given publisher we're missing, by asking a peer for a summary of the ones that it has, and requests only those.
+	/// This goes through all posts of the publisher, one summary at a time.
+	/// Returns the ids of the posts that were stored.
+	pub async fn sync_timeline( &self, publisher: &PublicKey, include_content: bool ) -> Result<Vec<u64>> {
+		let this = &self.0;
+
+		let timeline = match this.persistence.get_timeline( publisher ).await? {
+			None => Err( MessageMalformedError::UnknownPublisher( publisher.clone() ) )?,
+			Some(t) => t
+		};
+
+		let our_latest = timeline.load_latest_post_id().await?;
+		let mut stored = Vec::new();
+		let mut start = 0;
+		loop {
+			let request = bincode::serialize( &PostSummaryRequest { timeline_id: publisher.clone(), post_id_start: start } ).unwrap();
+			let (responder, payload) = match Self::request_from_any( this, RequestType::Summary, &request ).await? {
+				(peer, (ResponseResultType::Success, payload)) => (peer, payload),
+				(_, (ResponseResultType::InternalError, _)) => break
+			};
+			let summary: PostSummary = bincode::deserialize( &payload )
+				.map_err(|e| MessageMalformedError::DeserializationIssue(e, "post summary".to_owned()))?;
+			if summary.post_id_start != start || summary.post_id_count > SUMMARY_MAX_COUNT || summary.mask.len() != posts_mask_length( summary.post_id_count ) {
+				return Err( Self::reject_response( this, &responder, MessageMalformedError::UnexpectedData("post summary".to_owned()) ).await )
+			}
+			let end = start + summary.post_id_count as u64;
+
+			// Only the posts that the peer has and that we don't are requested, in batches.
+			let known = timeline.load_post_ids( start, end, true ).await?;
+			let missing: Vec<u64> = (0..summary.post_id_count)
+				.filter(|i| get_mask_bit( &summary.mask, *i ))
+				.map(|i| start + i as u64)
+				.filter(|id| known.binary_search( id ).is_err())
+				.collect();
+			for batch in missing.chunks( POSTS_SYNC_BATCH ) {
+				let post_ids = IdSet::from_ids( batch.iter().cloned() );
+				let fetched = Self::request_posts( this, &timeline, publisher, &post_ids, include_content ).await?;
+				this.sync.posts_fetched.fetch_add( fetched.len() as u64, Ordering::AcqRel );
+				stored.extend( fetched );
+			}
+
+			// A peer that claims to have newer posts needs to show some of them, or we need to know of them ourselves, so that it can't keep us going forever.
+			let has_any = summary.mask.iter().any(|b| *b != 0);
+			match summary.latest_post_id {
+				Some(latest) if latest >= end && summary.post_id_count > 0 && ( has_any || our_latest.map(|l| l >= end).unwrap_or(false) ) => start = end,
+				_ => break
+			}
+		}
+
+		Ok( stored )
+	}
+
+	/// Requests the posts of the given publisher with the given ids, and stores the ones that we receive.
+	/// If none of our peers understands sets of posts, the posts are requested with a mask instead.
+	/// Returns the ids of the posts that were stored.
+	async fn request_posts( this: &Arc<NodeInner>, timeline: &persistence::timeline::Handle, publisher: &PublicKey, post_ids: &IdSet, include_content: bool ) -> Result<Vec<u64>> {
+
+		let request = bincode::serialize( &PostSetRequest {
+			timeline_id: publisher.clone(),
+			post_ids: post_ids.clone(),
+			include_content
+		}).unwrap();
+		let (responder, response) = match Self::request_from_any( this, RequestType::PostSet, &request ).await {
+			Ok((peer, (ResponseResultType::Success, response))) => (peer, response),
+			Ok((_, (ResponseResultType::InternalError, _))) => return Ok( Vec::new() ),
+			Err(Error::NoResponse) => return Self::request_posts_masked( this, timeline, publisher, post_ids, include_content ).await,
+			Err(e) => return Err(e)
+		};
+		let posts: Vec<PostData> = bincode::deserialize( &response )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "posts response".to_owned()))?;
+
+		// The posts need to be ones that were requested, in order.
+		if posts.len() > POSTS_REQUEST_MAX_COUNT {
+			Err(MessageMalformedError::UnexpectedData("posts response".to_owned()))?
+		}
+		let mut previous = None;
+		for data in &posts {
+			if !post_ids.contains( data.post.id ) || previous.map(|p| p >= data.post.id).unwrap_or(false) {
+				Err(MessageMalformedError::InvalidEventId( data.post.id ))?
+			}
+			previous = Some( data.post.id );
+		}
+
+		Self::store_posts( this, timeline, publisher, &responder, posts ).await
+	}
+
+	/// Requests the posts of the given publisher with the given ids with a `PostsRequest`, which every peer understands.
+	/// The mask of such a request spans at most `POSTS_REQUEST_MAX_COUNT` ids, so the ids after that are left out.
+	async fn request_posts_masked( this: &Arc<NodeInner>, timeline: &persistence::timeline::Handle, publisher: &PublicKey, post_ids: &IdSet, include_content: bool ) -> Result<Vec<u64>> {
+		let (start, end) = match post_ids.bounds() {
+			None => return Ok( Vec::new() ),
+			Some(b) => b
+		};
+		let count = min( end - start, POSTS_REQUEST_MAX_COUNT as u64 ) as u16;
+		let mut mask = vec![ 0u8; posts_mask_length( count ) ];
+		for id in post_ids.iter().take_while(|id| id - start < count as u64) {
+			set_mask_bit( &mut mask, ( id - start ) as u16 );
+		}
+
+		let request = PostsRequest {
+			timeline_id: publisher.clone(),
+			post_id_start: start,
+			post_id_count: count,
+			include_content
+		};
+		let mask_length = mask.len();
+		let mut payload = bincode::serialize( &request ).unwrap();
+		payload.extend( mask );
+
+		let (responder, response) = match Self::request_from_any( this, RequestType::Posts, &payload ).await? {
+			(peer, (ResponseResultType::Success, response)) => (peer, response),
+			(_, (ResponseResultType::InternalError, _)) => return Ok( Vec::new() )
+		};
+		if response.len() < mask_length {
+			Err(MessageMalformedError::MissingData("posts response mask".to_owned()))?
+		}
+		let found_mask = &response[..mask_length];
+		let posts: Vec<PostData> = bincode::deserialize( &response[mask_length..] )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "posts response".to_owned()))?;
+
+		let mut found_ids = (0..count).filter(|i| get_mask_bit( found_mask, *i )).map(|i| start + i as u64);
+		for data in &posts {
+			// The posts need to be the ones that the mask says were found.
+			if found_ids.next() != Some( data.post.id ) {
+				Err(MessageMalformedError::InvalidEventId( data.post.id ))?
+			}
+		}
+
+		Self::store_posts( this, timeline, publisher, &responder, posts ).await
+	}
+
+	/// Verifies the posts that a peer has sent us, and stores them, unless their publisher is blocked.
+	/// Returns the ids of the posts that were stored, which leaves out the ones that we were asked to forget.
+	async fn store_posts( this: &Arc<NodeInner>, timeline: &persistence::timeline::Handle, publisher: &PublicKey, responder: &PublicKey, posts: Vec<PostData> ) -> Result<Vec<u64>> {
+
+		let blocked = this.persistence.base.is_publisher_blocked( publisher ).await?;
+		let mut stored = Vec::with_capacity( posts.len() );
+		for data in posts {
+			let valid = validate_post( &data.post, publisher ).and_then(|_| match &data.content {
+				None => Ok(()),
+				Some(content) => validate_post_content( &data.post.meta, content )
+			});
+			if let Err(e) = valid {
+				return Err( Self::reject_response( this, responder, e ).await )
+			}
+			if blocked { continue }
+
+			let post_handle = match timeline.store_post( &data.post ).await? {
+				None => continue,
+				Some(h) => h
+			};
+			if let Some(content) = &data.content {
+				post_handle.store_content( content ).await?;
+			}
+			stored.push( data.post.id );
+		}
+
+		Ok( stored )
+	}
+
+	/// Searches the posts that the parent and children have, for the ones that contain all of the keywords.
+	/// All of them are asked at once, and the posts that they find are verified and stored, along with their content if they sent it.
+	/// Returns the publisher and id of every found post, taking turns between the peers so that the best matches of each come first.
+	pub async fn search( &self, keywords: &[String] ) -> Result<Vec<(PublicKey, u64)>> {
+		let this = &self.0;
+
+		let request = bincode::serialize( &PostSearchRequest {
+			keywords: keywords.to_vec(),
+			max_results: SEARCH_REQUEST_MAX_RESULTS
+		}).unwrap();
+
+		let parents = this.parents();
+		let children = this.children.read().await.clone();
+		let requests = parents.iter().chain( children.iter() ).map(|link| {
+			let request = &request;
+			async move {
+				( link, Self::send_request( this, link, RequestType::Search, request ).await )
+			}
+		});
+
+		let mut responses = Vec::new();
+		for (link, result) in future::join_all( requests ).await {
+			let session = &link.session;
+			let payload = match result {
+				Err(Error::Gnunet(e)) => {
+					this.errors.report( Some( &session.address ), format!("unable to send search request: {}", e) );
+					continue
+				},
+				Err(e) => return Err(e),
+				Ok(None) => {
+					session.timeouts.fetch_add( 1, Ordering::AcqRel );
+					this.reputation.adjust( &session.address, REPUTATION_TIMEOUT ).await;
+					continue
+				},
+				Ok(Some((ResponseResultType::Success, payload))) => payload,
+				Ok(Some((ResponseResultType::InternalError, _))) => continue
+			};
+
+			let response: PostSearchResponse = bincode::deserialize( &payload )
+				.map_err(|e| MessageMalformedError::DeserializationIssue(e, "search response".to_owned()))?;
+			if response.results.len() > SEARCH_REQUEST_MAX_RESULTS as usize {
+				Err(MessageMalformedError::UnexpectedData("search response".to_owned()))?
+			}
+			if !response.results.is_empty() {
+				this.reputation.adjust( &session.address, REPUTATION_USEFUL ).await;
+			}
+			responses.push(( session.address.clone(), response.results.into_iter() ));
+		}
+
+		let mut found: Vec<(PublicKey, u64)> = Vec::new();
+		loop {
+			let mut exhausted = true;
+			for (responder, results) in responses.iter_mut() {
+				let result = match results.next() {
+					None => continue,
+					Some(r) => r
+				};
+				exhausted = false;
+
+				let data = &result.data;
+				if found.iter().any(|(publisher, id)| *publisher == result.publisher && *id == data.post.id) { continue }
+				if this.persistence.base.is_publisher_blocked( &result.publisher ).await? { continue }
+
+				let timeline = match this.persistence.get_timeline( &result.publisher ).await? {
+					None => Err( MessageMalformedError::UnknownPublisher( result.publisher.clone() ) )?,
+					Some(t) => t
+				};
+				let valid = validate_post( &data.post, &result.publisher ).and_then(|_| match &data.content {
+					None => Ok(()),
+					Some(content) => validate_post_content( &data.post.meta, content )
+				});
+				if let Err(e) = valid {
+					return Err( Self::reject_response( this, responder, e ).await )
+				}
+
+				// Posts that we have already are found just the same, but those that we were asked to forget are not.
+				let post_handle = match timeline.store_post( &data.post ).await? {
+					None => continue,
+					Some(h) => h
+				};
+				if let Some(content) = &data.content {
+					if timeline.load_post_content( data.post.id ).await?.is_none() {
+						post_handle.store_content( content ).await?;
+					}
+				}
+				found.push(( result.publisher.clone(), data.post.id ));
+			}
+			if exhausted { break }
+		}
+
+		Ok( found )
+	}
+
+	/// Requests which blocks make up the given file, and stores that information.
+	/// The manifest is asked for first, as it can be checked against the file id.
+	/// Files that are identified by the hash of their data have no manifest, so for those the blocks are asked for like before 0.11, and checked once they have all been received.
+	/// Returns `None` if the peer that responded doesn't know the file.
+	pub async fn fetch_file( &self, file_id: &HashCode ) -> Result<Option<Attachment>> {
+		let this = &self.0;
+
+		match Self::fetch_manifest( this, file_id ).await {
+			Ok(Some(file)) => return Ok(Some(file)),
+			Ok(None) | Err(Error::NoResponse) => {},
+			Err(e) => return Err(e)
+		}
+
+		let request = bincode::serialize( &FilesRequest { file_ids: vec![ file_id.clone() ] } ).unwrap();
+		let payload = match Self::request_any( this, RequestType::Files, &request ).await? {
+			(ResponseResultType::Success, payload) => payload,
+			(ResponseResultType::InternalError, _) => return Ok(None)
+		};
+
+		let mut response: FilesResponse = bincode::deserialize( &payload )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "files response".to_owned()))?;
+
+		let file = response.files.remove( file_id );
+		if let Some(f) = &file {
+			this.persistence.store_file( file_id, f ).await?;
+		}
+		Ok( file )
+	}
+
+	/// Requests the manifest of the given file, and stores it if it matches the file id.
+	/// Returns `None` if the peer that responded has no manifest of the file.
+	async fn fetch_manifest( this: &Arc<NodeInner>, file_id: &HashCode ) -> Result<Option<Attachment>> {
+
+		let request = bincode::serialize( &ManifestsRequest { file_ids: vec![ file_id.clone() ] } ).unwrap();
+		let (responder, payload) = match Self::request_from_any( this, RequestType::Manifests, &request ).await? {
+			(peer, (ResponseResultType::Success, payload)) => (peer, payload),
+			(_, (ResponseResultType::InternalError, _)) => return Ok(None)
+		};
+
+		let response: ManifestsResponse = bincode::deserialize( &payload )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "manifests response".to_owned()))?;
+		if response.manifests.len() > 1 {
+			Err(MessageMalformedError::UnexpectedData("manifests response".to_owned()))?
+		}
+
+		let file = match response.manifests.into_iter().next().flatten() {
+			None => return Ok(None),
+			Some(f) => f
+		};
+		if !file.is_manifest_of( file_id ) {
+			return Err( Self::reject_response( this, &responder, MessageMalformedError::InvalidHash("manifest".to_owned()) ).await )
+		}
+
+		this.persistence.store_file( file_id, &file ).await?;
+		Ok( Some( file ) )
+	}
+
+	/// Requests the given blocks of a post (or one of its attachments), and stores the ones that were received.
+	/// Once the blocks of a file that is identified by the hash of its data are all there, the file is checked, and forgotten if it doesn't match.
+	/// Returns the ids of the blocks that were stored.
+	pub async fn fetch_blocks( &self, post_id: &HashCode, block_ids: &[HashCode] ) -> Result<Vec<HashCode>> {
+		let this = &self.0;
+		let mut stored = Vec::with_capacity( block_ids.len() );
+		let private = this.persistence.is_private().await?;
+
+		for chunk in block_ids.chunks( BLOCKS_REQUEST_MAX_COUNT ) {
+			let request = bincode::serialize( &BlocksRequest {
+				post_id: post_id.clone(),
+				block_ids: chunk.to_vec()
+			} ).unwrap();
+			let (responder, payload) = match Self::request_from_any( this, RequestType::Blocks, &request ).await? {
+				(peer, (ResponseResultType::Success, payload)) => (peer, payload),
+				(_, (ResponseResultType::InternalError, _)) => continue
+			};
+
+			let response: BlocksResponse = bincode::deserialize( &payload )
+				.map_err(|e| MessageMalformedError::DeserializationIssue(e, "blocks response".to_owned()))?;
+			if response.data.len() > chunk.len() {
+				Err(MessageMalformedError::UnexpectedData("blocks response".to_owned()))?
+			}
+
+			for (block_id, data) in chunk.iter().zip( response.data.into_iter() ) {
+				if let Some(data) = data {
+					// The blocks that were received before are kept, they have been checked as well.
+					if HashCode::generate( &data ) != *block_id {
+						return Err( Self::reject_response( this, &responder, MessageMalformedError::InvalidHash("block".to_owned()) ).await )
+					}
+
+					this.persistence.store_block( block_id, &data, private ).await?;
+					stored.push( block_id.clone() );
+				}
+			}
+		}
+
+		// Which of the peers sent the wrong manifest can't be told anymore, so only the manifest is dropped.
+		if stored.len() > 0 && !this.persistence.verify_file( post_id ).await? {
+			this.persistence.forget_file( post_id ).await?;
+			this.errors.report( None, format!("the blocks of file {} don't add up to it, dropping its manifest", post_id) );
+			return Err( Error::MessageMalformed( MessageMalformedError::InvalidHash("file".to_owned()) ) )
+		}
+
+		Ok( stored )
+	}
+
+	/// Catches up with the swarm: requests all events that are newer than the ones we have, then the posts of every publisher that we're missing, and then fetches the missing blocks of all attachments and the profile picture.
+	/// The progress can be followed with `sync_status`.
+	/// Returns false without doing anything if a sync is already running.
+	pub async fn sync( &self ) -> Result<bool> {
+		let this = &self.0;
+
+		if this.sync.running.swap( true, Ordering::AcqRel ) {
+			return Ok(false)
+		}
+		this.sync.events_applied.store( 0, Ordering::Release );
+		this.sync.posts_fetched.store( 0, Ordering::Release );
+		this.sync.blocks_remaining.store( 0, Ordering::Release );
+
+		let result = self.catch_up().await;
+		this.sync.running.store( false, Ordering::Release );
+
+		result.map(|_| true)
+	}
+
+	/// Tries to apply the stored event with the given id, along with the pending events before and after it.
+	/// If events before it are missing, they are requested from the swarm first.
+	/// Returns whether the event has been applied.
+	pub async fn reapply_event( &self, id: u64 ) -> Result<bool> {
+		let this = &self.0;
+
+		let from_id = *this.latest_event_id.lock().await + 1;
+		if id < from_id {
+			return Ok(true)
+		}
+		if id > from_id {
+			let count = min( id - from_id, EVENTS_REQUEST_MAX_COUNT as u64 ) as u16;
+			Self::backfill_events( this.clone(), from_id, count ).await?;
+		}
+
+		let mut latest_event_id = this.latest_event_id.lock().await;
+		Self::apply_pending_events( this.clone(), &mut *latest_event_id ).await?;
+		Ok( *latest_event_id >= id )
+	}
+
+	/// The peers that we've received messages from during their current session, which are our parent and children.
+	pub async fn active_peers( &self ) -> Vec<PublicKey> {
+		let this = &self.0;
+
+		let parents = this.parents();
+		let children = this.children.read().await.clone();
+		parents.iter().chain( children.iter() )
+			.filter(|link| link.session.messages.load( Ordering::Acquire ) > 0)
+			.map(|link| link.session.address.clone())
+			.collect()
+	}
+
+	/// Returns the number of peers that are connected to the swarm through us.
+	pub async fn child_count( &self ) -> usize {
+		self.0.children.read().await.len()
+	}
+
+	/// Whether we're still connected to the swarm through a parent.
+	pub fn is_connected( &self ) -> bool {
+		self.0.connected.load( Ordering::Acquire )
+	}
+
+	/// Returns the number of received events that are waiting to be applied, per peer.
+	pub fn queue_depths( &self ) -> Vec<QueueDepth<PublicKey>> {
+		self.0.events.depths()
+	}
+
+	/// Returns the number of received requests that are waiting to be answered, per peer.
+	pub fn request_queue_depths( &self ) -> Vec<QueueDepth<PublicKey>> {
+		self.0.requests.depths()
+	}
+
+	/// Returns the progress of the current (or last) sync.
+	pub fn sync_status( &self ) -> SyncStatus {
+		let this = &self.0;
+
+		SyncStatus {
+			running: this.sync.running.load( Ordering::Acquire ),
+			events_applied: this.sync.events_applied.load( Ordering::Acquire ),
+			posts_fetched: this.sync.posts_fetched.load( Ordering::Acquire ),
+			blocks_remaining: this.sync.blocks_remaining.load( Ordering::Acquire ),
+			peers: this.parents().iter().map(|p| p.session.address.clone()).collect()
+		}
+	}
+
+	/// Returns what the swarm has sent and received since we connected to it, in total and per peer.
+	pub async fn traffic( &self ) -> TrafficStatus {
+		let this = &self.0;
+
+		let parents = this.parents();
+		let children = this.children.read().await.clone();
+		TrafficStatus {
+			channel: this.address.clone(),
+			sent: this.traffic.sent.load( Ordering::Acquire ),
+			received: this.traffic.received.load( Ordering::Acquire ),
+			throttled: this.traffic.throttled.load( Ordering::Acquire ),
+			peers: parents.iter().chain( children.iter() ).map(|link| (
+				link.session.address.clone(),
+				link.session.bytes_sent.load( Ordering::Acquire ),
+				link.session.bytes.load( Ordering::Acquire )
+			)).collect()
+		}
+	}
+
+	/// Returns who is connected to the swarm through us, and how large the swarm is estimated to be.
+	pub async fn census( &self ) -> CensusStatus {
+		let this = &self.0;
+
+		let parents = this.parents();
+		let children = this.children.read().await.clone();
+		let view = |link: &Arc<Link>, child: bool| CensusPeer {
+			address: link.session.address.clone(),
+			child,
+			connected_since: link.session.started,
+			sent: link.session.bytes_sent.load( Ordering::Acquire ),
+			received: link.session.bytes.load( Ordering::Acquire ),
+			subtree_share: if child { link.census.lock().unwrap().map(|s| s as f64 / CENSUS_UNIT as f64) } else { None }
+		};
+		CensusStatus {
+			swarm_size: this.swarm_size().await,
+			subtree_size: this.subtree_size().await,
+			peers: parents.iter().map(|p| view( p, false ))
+				.chain( children.iter().map(|c| view( c, true )) )
+				.collect()
+		}
+	}
+
+	async fn catch_up( &self ) -> Result<()> {
+		let this = &self.0;
+
+		// Keep requesting events until our peers don't have anything newer.
+		loop {
+			let from_id = *this.latest_event_id.lock().await + 1;
+			if Self::backfill_events( this.clone(), from_id, EVENTS_REQUEST_MAX_COUNT ).await? == 0 {
+				break
+			}
+			// If none of the events could be applied, asking again won't help.
+			if *this.latest_event_id.lock().await < from_id {
+				break
+			}
+		}
+
+		// Posts that were published while we were away, or that we never had, are found through the summaries of our peers.
+		// Peers that don't speak a version of the protocol with summaries are left out, and without any of those the posts are left to be fetched as they're viewed.
+		for publisher in this.persistence.list_publishers().await? {
+			match self.sync_timeline( &publisher, true ).await {
+				Err(Error::NoResponse) => break,
+				Err(e) => return Err(e),
+				Ok(_) => {}
+			}
+		}
+
+		// The profile picture is fetched along with the attachments, so that the channel can be shown with it.
+		let mut file_ids = this.persistence.list_attachment_ids().await?;
+		if let Some(picture) = this.persistence.fetch_profile().await?.and_then(|p| p.base.profile_picture) {
+			file_ids.push( picture );
+		}
+
+		// Find out which blocks we're missing, so that the remaining count is known up front.
+		let mut missing = Vec::new();
+		for file_id in file_ids {
+			let file = match this.persistence.load_file( &file_id ).await? {
+				Some(f) => f,
+				None => match self.fetch_file( &file_id ).await? {
+					None => continue,
+					Some(f) => f
+				}
+			};
+
+			let mut block_ids = Vec::new();
+			for block_id in file.block_ids {
+				if !this.persistence.has_block( &block_id ).await? {
+					block_ids.push( block_id );
+				}
+			}
+			if block_ids.len() > 0 {
+				this.sync.blocks_remaining.fetch_add( block_ids.len() as u64, Ordering::AcqRel );
+				missing.push(( file_id, block_ids ));
+			}
+		}
+
+		for (file_id, block_ids) in missing {
+			let stored = self.fetch_blocks( &file_id, &block_ids ).await?;
+			this.sync.blocks_remaining.fetch_sub( stored.len() as u64, Ordering::AcqRel );
+		}
+
+		Ok(())
+	}
+
+	/// Requests the events `from_id..(from_id + count)` from our peers, and processes them in order.
+	/// The events that arrived early and are waiting to be applied aren't requested again, unless none of our peers understands sets of events.
+	/// Returns the number of events that were received.
+	async fn backfill_events( this: Arc<NodeInner>, from_id: u64, count: u16 ) -> Result<usize> {
+
+		let mut event_ids = IdSet::range( from_id, count as u64 );
+		for id in this.persistence.load_pending_event_ids( from_id, count ).await? {
+			event_ids.except( id );
+		}
+		if event_ids.is_empty() {
+			return Ok(0)
+		}
+
+		let request = bincode::serialize( &EventSetRequest { event_ids } ).unwrap();
+		let response = match Self::request_any( &this, RequestType::EventSet, &request ).await {
+			Err(Error::NoResponse) => {
+				let request = bincode::serialize( &EventsRequest { from_id, count } ).unwrap();
+				Self::request_any( &this, RequestType::Events, &request ).await?
+			},
+			other => other?
+		};
+		let payload = match response {
+			(ResponseResultType::Success, payload) => payload,
+			(ResponseResultType::InternalError, _) => return Ok(0)
+		};
+
+		let response: EventsResponse = bincode::deserialize( &payload )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "events response".to_owned()))?;
+
+		let received = response.events.len();
+		for event in response.events {
+			Self::receive_event( this.clone(), &event ).await?;
+		}
+
+		Ok( received )
+	}
+
+	async fn process_event_channel( this: Arc<NodeInner>, id: u64, message: &[u8] ) -> Result<()> {
+		if message.len() == 0 {
+			Err(MessageMalformedError::MissingData("channel event".to_owned()))?
+		}
+
+		let event_type: ChannelEventType = match message[0].try_into() {
+			Err(_) => Err(MessageMalformedError::InvalidTypeId(message[0], "channel event type".to_owned()))?,
+			Ok(e) => e
+		};
+
+		match event_type {
+			ChannelEventType::UpdateChannelProfile => Self::process_event_channel_update_profile( this, id, &message[1..] ).await,
+			ChannelEventType::UpdatePublisherList => Self::process_event_channel_update_publisher_list( this, id, &message[1..] ).await,
+			ChannelEventType::Create => Self::process_event_channel_create( this, id, &message[1..] ).await,
+			ChannelEventType::TransferOwnership => Self::process_event_channel_transfer_ownership( this, &message[1..] ).await,
+			ChannelEventType::AcceptOwnership => Self::process_event_channel_accept_ownership( this, &message[1..] ).await,
+			ChannelEventType::UpdatePinnedPosts => Self::process_event_channel_update_pinned_posts( this, &message[1..] ).await,
+			ChannelEventType::ModerateComments => Self::process_event_channel_moderate_comments( this, &message[1..] ).await,
+			ChannelEventType::Close => Self::process_event_channel_close( this, &message[1..] ).await
+		}
+	}
+
+	/// Checks a channel event with `validate`, for each of the current owners of the channel.
+	/// The event is valid if it is valid for any of them, as both the previous and the new owner sign events during the grace period of a transfer.
+	async fn validate_by_owner<F>( this: &NodeInner, validate: F ) -> Result<()> where
+		F: Fn( &PublicKey ) -> std::result::Result<(), MessageMalformedError>
+	{
+		let mut result = Err( MessageMalformedError::InvalidSignature("channel event".to_owned()) );
+		for owner in this.event_persistence.load_owners().await? {
+			result = validate( &owner );
+			if result.is_ok() { break }
+		}
+
+		Ok( result? )
+	}
+
+	async fn process_event_channel_transfer_ownership( this: Arc<NodeInner>, message: &[u8] ) -> Result<()> {
+
+		let msg: TransferOwnershipEventMessage = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "transfer ownership event message".to_owned()))?;
+		Self::validate_by_owner( &this, |owner| validate_ownership_transfer( &msg, owner ) ).await?;
+
+		// A newer transfer replaces one that hasn't been accepted yet.
+		this.event_persistence.store_pending_transfer( &PendingTransfer {
+			new_owner: msg.transfer.new_owner,
+			hash: msg.hash,
+			grace_period: msg.transfer.grace_period
+		}).await?;
+
+		Ok(())
+	}
+
+	async fn process_event_channel_accept_ownership( this: Arc<NodeInner>, message: &[u8] ) -> Result<()> {
+
+		let msg: AcceptOwnershipEventMessage = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "accept ownership event message".to_owned()))?;
+
+		let transfer = match this.event_persistence.load_pending_transfer().await? {
+			None => Err( MessageMalformedError::InvalidHash("accept ownership event message".to_owned()) )?,
+			Some(t) => t
+		};
+		validate_ownership_acceptance( &msg, &transfer.hash, &transfer.new_owner )?;
+
+		this.event_persistence.complete_transfer( &transfer ).await?;
+
+		Ok(())
+	}
+
+	async fn process_event_channel_create( this: Arc<NodeInner>, id: u64, message: &[u8] ) -> Result<()> {
+
+		// A channel is only created once, at the very start.
+		if id != GENESIS_EVENT_ID {
+			Err(MessageMalformedError::InvalidEventId(id))?
+		}
+
+		let msg: ChannelCreateEventMessage = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "channel create event message".to_owned()))?;
+
+		let public_key = this.event_persistence.load_address().await?;
+		validate_channel_genesis( &msg, &public_key )?;
+
+		match this.event_persistence.load_parameters().await? {
+			None => this.event_persistence.store_parameters( &msg.data ).await?,
+			Some(parameters) => if parameters != msg.data {
+				return Err( Error::GenesisChanged )
+			}
+		}
+
+		Ok(())
+	}
+
+	async fn process_event_channel_update_profile( this: Arc<NodeInner>, id: u64, message: &[u8] ) -> Result<()> {
+
+		let msg: UpdateChannelProfileEventMessage = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "upgrade profile event message".to_owned()))?;
+		
+		Self::validate_by_owner( &this, |owner| validate_channel_profile_update( &msg, owner ) ).await?;
+
+		// Every node settles on the same profile, whatever order the updates come in.
+		this.event_persistence.apply_profile( &msg.profile ).await?;
+
+		Ok(())
+	}
+
+	async fn process_event_channel_update_publisher_list( this: Arc<NodeInner>, event_id: u64, message: &[u8] ) -> Result<()> {
+
+		let msg: UpdatePublisherListEventMessage = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "update publisher list event message".to_owned()))?;
+
+		Self::validate_by_owner( &this, |owner| validate_publisher_list_update( &msg, owner ) ).await?;
+
+		// Only replace the list with a newer one
+		let current_revision = this.event_persistence.load_publisher_list_revision().await?;
+		if current_revision.map(|r| msg.list.revision > r).unwrap_or(true) {
+			this.event_persistence.store_publisher_list( &msg.list ).await?;
+		}
+
+		Ok(())
+	}
+
+	async fn process_event_channel_update_pinned_posts( this: Arc<NodeInner>, message: &[u8] ) -> Result<()> {
+
+		let msg: UpdatePinnedPostsEventMessage = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "update pinned posts event message".to_owned()))?;
+
+		Self::validate_by_owner( &this, |owner| validate_pinned_posts_update( &msg, owner ) ).await?;
+
+		// Only replace the pinned posts with newer ones
+		let current_revision = this.event_persistence.load_pinned_posts_revision().await?;
+		if current_revision.map(|r| msg.pinned.revision > r).unwrap_or(true) {
+			this.event_persistence.store_pinned_posts( &msg.pinned ).await?;
+		}
+
+		Ok(())
+	}
+
+	async fn process_event_channel_moderate_comments( this: Arc<NodeInner>, message: &[u8] ) -> Result<()> {
+
+		let msg: ModerateCommentsEventMessage = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "moderate comments event message".to_owned()))?;
+
+		Self::validate_by_owner( &this, |owner| validate_comment_moderation( &msg, owner ) ).await?;
+
+		// Decisions that a later one has overridden already are left out.
+		this.event_persistence.store_moderation( &msg.moderation ).await?;
+		Ok(())
+	}
+
+	async fn process_event_channel_close( this: Arc<NodeInner>, message: &[u8] ) -> Result<()> {
+
+		let msg: CloseChannelEventMessage = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "close channel event message".to_owned()))?;
+
+		Self::validate_by_owner( &this, |owner| validate_channel_closing( &msg, owner ) ).await?;
+
+		// The posts stay until the channel is purged, so that the subscribers can still read them.
+		this.event_persistence.store_closing( &msg.closing ).await?;
+		Ok(())
+	}
+
+	/// Returns the change that the event made to a post, if any, the notification that it has been recorded with, and the files that are no longer attached to a post because of it.
+	async fn process_event_publisher( this: Arc<NodeInner>, event_id: u64, address: &PublicKey, message: &[u8] ) -> Result<(Option<PostChange>, Option<Notification>, Vec<HashCode>)> {
+		let mut step = 0usize;
+
+		// Only the publishers that the channel owner has listed may publish.
+		if !this.event_persistence.is_publisher( address ).await? {
+			Err( MessageMalformedError::UnknownPublisher( address.clone() ) )?
+		}
+
+		if message.len() < (step + 1) {
+			Err(MessageMalformedError::MissingData("publisher event".to_owned()))?
+		}
+
+		let event_type: PublisherEventType = match message[step].try_into() {
+			Err(_) => Err(MessageMalformedError::InvalidTypeId(message[step], "publisher event type".to_owned()))?,
+			Ok(e) => e
+		};
+		step += 1;
+
+		// The event is still stored and passed on, but nothing of a blocked publisher ends up in our own database.
+		if this.event_persistence.base.is_publisher_blocked( address ).await? {
+			return Ok(( None, None, Vec::new() ))
+		}
+
+		let mut detached_files = Vec::new();
+		let (post_id, kind) = match event_type {
+			PublisherEventType::UpdateProfile => {
+				Self::process_event_publisher_update_profile( this, &address, &message[step..] ).await?;
+				return Ok(( None, None, Vec::new() ))
+			},
+			PublisherEventType::PublishPost => (Self::process_event_publisher_publish_post( this.clone(), &address, &message[step..] ).await?, PostChangeKind::Published),
+			PublisherEventType::RevisePost => (Self::process_event_publisher_revise_post( this.clone(), &address, &message[step..] ).await?, PostChangeKind::Revised),
+			PublisherEventType::ForgetPost => match Self::process_event_publisher_forget_post( this.clone(), &address, &message[step..] ).await? {
+				None => (None, PostChangeKind::Forgotten),
+				Some((post_id, files)) => {
+					detached_files = files;
+					(Some( post_id ), PostChangeKind::Forgotten)
+				}
+			},
+			PublisherEventType::Comment => {
+				let comment = Self::process_event_publisher_comment( this.clone(), &address, &message[step..] ).await?;
+				let notification = match &comment {
+					None => None,
+					Some(data) => Self::notify_comment( &this, address, data ).await?
+				};
+				let change = match comment {
+					None => None,
+					Some(data) => Some( PostChange {
+						channel: this.event_persistence.load_address().await?,
+						publisher: address.clone(),
+						post_id: data.post_id,
+						kind: PostChangeKind::Commented
+					})
+				};
+				return Ok(( change, notification, Vec::new() ))
+			},
+			PublisherEventType::Reaction => (Self::process_event_publisher_reaction( this.clone(), &address, &message[step..] ).await?, PostChangeKind::Reacted)
+		};
+
+		let post_id = match post_id {
+			None => return Ok(( None, None, Vec::new() )),
+			Some(id) => id
+		};
+
+		// The posts of the channels that we follow are worth a notification, our own aren't.
+		let notification_kind = match kind {
+			PostChangeKind::Published => Some( NotificationKind::Post ),
+			PostChangeKind::Revised => Some( NotificationKind::Revision ),
+			_ => None
+		};
+		let notification = match notification_kind {
+			Some(kind) if !this.event_persistence.base.is_own_publisher( address ).await? =>
+				Some( this.event_persistence.record_notification( kind, address, post_id, None ).await? ),
+			_ => None
+		};
+
+		Ok(( Some( PostChange {
+			channel: this.event_persistence.load_address().await?,
+			publisher: address.clone(),
+			post_id,
+			kind
+		}), notification, detached_files ))
+	}
+
+	/// Records a notification for a new comment, if it is on one of our own posts, or if it mentions one of our egos.
+	/// The comments that we've written ourselves don't notify.
+	async fn notify_comment( this: &NodeInner, publisher: &PublicKey, data: &CommentEventData ) -> Result<Option<Notification>> {
+		let db = &this.event_persistence.base;
+
+		if db.is_own_publisher( &data.author ).await? {
+			return Ok(None)
+		}
+
+		let kind = if db.is_own_publisher( publisher ).await? {
+			NotificationKind::Comment
+		} else {
+			let egos = db.list_own_ego_names().await?;
+			if !egos.iter().any(|ego| notification::mentions( &data.comment.content, ego )) {
+				return Ok(None)
+			}
+			NotificationKind::Mention
+		};
+
+		Ok( Some( this.event_persistence.record_notification( kind, publisher, data.post_id, Some( &data.author ) ).await? ) )
+	}
+
+	/// Returns the comment, if it was new to us.
+	async fn process_event_publisher_comment( this: Arc<NodeInner>, publisher: &PublicKey, message: &[u8] ) -> Result<Option<CommentEventData>> {
+
+		let data: CommentEventData = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "comment event".to_owned()))?;
+
+		let timeline = match this.event_persistence.get_timeline( publisher ).await? {
+			None => Err( MessageMalformedError::UnknownPublisher( publisher.clone() ) )?,
+			Some(t) => t
+		};
+
+		// Without the post, the comment can't be verified, so it is ignored.
+		// The event itself is still stored, so that peers that do have the post can get it from us.
+		let post = match timeline.load_post( data.post_id ).await? {
+			None => return Ok(None),
+			Some(p) => p
+		};
+		validate_comment( &data, &post )?;
+
+		let new = timeline.store_comment( &data ).await?;
+
+		Ok( if new { Some( data ) } else { None } )
+	}
+
+	/// Returns the id of the post, if the reaction has replaced what we knew of its signer.
+	async fn process_event_publisher_reaction( this: Arc<NodeInner>, publisher: &PublicKey, message: &[u8] ) -> Result<Option<u64>> {
+
+		let data: ReactionEventData = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "reaction event".to_owned()))?;
+
+		let timeline = match this.event_persistence.get_timeline( publisher ).await? {
+			None => Err( MessageMalformedError::UnknownPublisher( publisher.clone() ) )?,
+			Some(t) => t
+		};
+
+		// Like with comments, a reaction to a post that we don't have can't be verified, so it is ignored.
+		let post = match timeline.load_post( data.post_id ).await? {
+			None => return Ok(None),
+			Some(p) => p
+		};
+		validate_reaction( &data, &post )?;
+
+		let stored = timeline.store_reaction( &data ).await?;
+
+		Ok( if stored { Some( data.post_id ) } else { None } )
+	}
+
+	/// Returns the id of the post and the files that were attached to it, unless we didn't have the post.
+	async fn process_event_publisher_forget_post( this: Arc<NodeInner>, publisher: &PublicKey, message: &[u8] ) -> Result<Option<(u64, Vec<HashCode>)>> {
+
+		let data: ForgetPostEventData = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "forget post event".to_owned()))?;
+
+		let timeline = match this.event_persistence.get_timeline( publisher ).await? {
+			None => Err( MessageMalformedError::UnknownPublisher( publisher.clone() ) )?,
+			Some(t) => t
+		};
+
+		// Without the post, the request can't be verified.
+		// Because we don't have the post, there is nothing to forget anyway.
+		let post = match timeline.load_post( data.post_id ).await? {
+			None => return Ok(None),
+			Some(p) => p
+		};
+		validate_forget_post( &data, &post, publisher )?;
+
+		let files = timeline.forget_post( data.post_id ).await?;
+
+		Ok( Some(( data.post_id, files )) )
+	}
+
+	/// Returns the id of the post, unless it has been forgotten already.
+	async fn process_event_publisher_publish_post( this: Arc<NodeInner>, publisher: &PublicKey, message: &[u8] ) -> Result<Option<u64>> {
+
+		let data: PublishPostEventData = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "publish post event".to_owned()))?;
+		validate_post( &data.post, publisher )?;
+
+		let timeline = match this.event_persistence.get_timeline( publisher ).await? {
+			None => Err( MessageMalformedError::UnknownPublisher( publisher.clone() ) )?,
+			Some(t) => t
+		};
+
+		// Only the meta data is stored here.
+		// The content and the attachments are fetched from the swarm when they are needed.
+		let stored = timeline.store_post( &data.post ).await?;
+
+		Ok( stored.map(|_| data.post.id) )
+	}
+
+	/// Returns the id of the post, if the revision was new to us.
+	async fn process_event_publisher_revise_post( this: Arc<NodeInner>, publisher: &PublicKey, message: &[u8] ) -> Result<Option<u64>> {
+
+		let data: RevisePostEventData = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "revise post event".to_owned()))?;
+
+		let timeline = match this.event_persistence.get_timeline( publisher ).await? {
+			None => Err( MessageMalformedError::UnknownPublisher( publisher.clone() ) )?,
+			Some(t) => t
+		};
+
+		// Without the post, the revision can't be verified, so it is ignored.
+		// The event itself is still stored, so that peers that do have the post can get it from us.
+		let post = match timeline.load_post( data.post_id ).await? {
+			None => return Ok(None),
+			Some(p) => p
+		};
+		validate_post_revision( &data, &post, publisher )?;
+
+		let new = timeline.store_revision( &data ).await?;
+
+		Ok( if new { Some( data.post_id ) } else { None } )
+	}
+
+	async fn process_event_publisher_update_profile( this: Arc<NodeInner>, publisher: &PublicKey, message: &[u8] ) -> Result<()> {
+
+		let profile: Profile = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "upgrade profile event profile".to_owned()))?;
+
+		// TODO: Store the post
+
+		Ok(())
+	}
+
+	async fn process_request( this: Arc<NodeInner>, link: &Link, message: &[u8] ) -> Result<()> {
+		if message.len() < 5 {
+			Err( MessageMalformedError::MissingData("request".to_owned()) )?;
+		}
+
+		let request_id: u32 = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "request id".to_owned()) )?;
+
+		let request_type: RequestType = message[4].try_into()
+			.map_err(|_| MessageMalformedError::InvalidTypeId(message[4], "request type".to_owned()))?;
+
+		let span = debug_span!("request", id = request_id, kind = ?request_type);
+		async move {
+			let (result_type, payload) = match request_type {
+				RequestType::Posts => Self::process_request_posts( this.clone(), &message[5..] ).await?,
+				RequestType::Files => Self::process_request_files( this.clone(), &message[5..] ).await?,
+				RequestType::Blocks => Self::process_request_blocks( this.clone(), &message[5..] ).await?,
+				RequestType::Events => Self::process_request_events( this.clone(), &message[5..] ).await?,
+				RequestType::Search => Self::process_request_search( this.clone(), &message[5..] ).await?,
+				RequestType::Summary => Self::process_request_summary( this.clone(), &message[5..] ).await?,
+				RequestType::PostSet => Self::process_request_post_set( this.clone(), &message[5..] ).await?,
+				RequestType::EventSet => Self::process_request_event_set( this.clone(), &message[5..] ).await?,
+				RequestType::Manifests => Self::process_request_manifests( this.clone(), &message[5..] ).await?
+			};
+			debug!(bytes = payload.len(), "Responding");
+
+			Self::respond( this, link, request_id, result_type, &*payload ).await
+		}.instrument( span ).await
+	}
+
+	async fn process_request_posts( this: Arc<NodeInner>, message: &[u8] ) -> Result<(ResponseResultType, Vec<u8>)> {
+
+		let request: PostsRequest = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "posts request".to_owned()))?;
+		// Like a set, a request may cover no more ids than posts are sent in response to it, and no ids beyond the last one there can be.
+		if request.post_id_count as usize > POSTS_REQUEST_MAX_COUNT || request.post_id_start.checked_add( request.post_id_count as u64 ).is_none() {
+			Err(MessageMalformedError::UnexpectedData("posts request".to_owned()))?
+		}
+
+		// The post id mask
+		let next = bincode::serialized_size( &request ).unwrap() as usize;
+		let mask_length = posts_mask_length( request.post_id_count );
+		if message.len() < (next + mask_length) {
+			Err(MessageMalformedError::MissingData("posts request mask".to_owned()))?
+		}
+		let mask = &message[next..(next+mask_length)];
+		let mut found_mask = vec!(0u8; mask_length);
+
+		// Collect all available posts and update the 'found' mask.
+		let mut posts = Vec::with_capacity( request.post_id_count as _ );
+		let timeline = match this.persistence.get_timeline( &request.timeline_id ).await? {
+			None => Err( MessageMalformedError::UnknownPublisher( request.timeline_id ) )?,
+			Some(t) => t
+		};
+		for i in 0..request.post_id_count {
+			if !get_mask_bit( mask, i ) { continue }
+			let post_id = request.post_id_start + i as u64;
+
+			if let Some(post) = timeline.load_post( post_id ).await? {
+				let content = if request.include_content {
+					timeline.load_post_content( post_id ).await?
+				} else {
+					None
+				};
+
+				posts.push( PostData { post, content } );
+				set_mask_bit( &mut *found_mask, i );
+			}
+		}
+
+		// The found mask is followed by the posts that were found, in the same order.
+		let mut response = found_mask;
+		bincode::serialize_into( &mut response, &posts ).expect("unable to serialize posts response");
+
+		Ok(( ResponseResultType::Success, response ))
+	}
+
+	async fn process_request_summary( this: Arc<NodeInner>, message: &[u8] ) -> Result<(ResponseResultType, Vec<u8>)> {
+
+		let request: PostSummaryRequest = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "post summary request".to_owned()))?;
+
+		// We may not have heard of a publisher that has only just been added, which is no fault of the requester.
+		let timeline = match this.persistence.get_timeline( &request.timeline_id ).await? {
+			None => return Ok(( ResponseResultType::InternalError, Vec::new() )),
+			Some(t) => t
+		};
+		let latest_post_id = timeline.load_latest_post_id().await?;
+
+		// The summary stops at the newest post, so that it doesn't cover ids that don't exist yet.
+		// It is clamped to `SUMMARY_MAX_COUNT` ids first, so that the count fits.
+		let post_id_count = match latest_post_id {
+			Some(latest) if latest >= request.post_id_start => min( ( latest - request.post_id_start ).saturating_add( 1 ), SUMMARY_MAX_COUNT as u64 ) as u16,
+			_ => 0
+		};
+		let end = request.post_id_start.saturating_add( post_id_count as u64 );
+		let mut mask = vec![ 0u8; posts_mask_length( post_id_count ) ];
+		for post_id in timeline.load_post_ids( request.post_id_start, end, false ).await? {
+			set_mask_bit( &mut mask, ( post_id - request.post_id_start ) as u16 );
+		}
+
+		let summary = PostSummary {
+			post_id_start: request.post_id_start,
+			post_id_count,
+			mask,
+			latest_post_id
+		};
+		Ok(( ResponseResultType::Success, bincode::serialize( &summary ).expect("unable to serialize post summary") ))
+	}
+
+	async fn process_request_post_set( this: Arc<NodeInner>, message: &[u8] ) -> Result<(ResponseResultType, Vec<u8>)> {
+
+		let request: PostSetRequest = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "post set request".to_owned()))?;
+		// A set may cover as many ids as a summary does.
+		request.post_ids.validate( SUMMARY_MAX_COUNT as u64 )?;
+
+		let timeline = match this.persistence.get_timeline( &request.timeline_id ).await? {
+			None => Err( MessageMalformedError::UnknownPublisher( request.timeline_id ) )?,
+			Some(t) => t
+		};
+
+		// Only the ids that we have are looked up, a range at a time.
+		let mut posts = Vec::new();
+		for range in request.post_ids.ranges() {
+			for post_id in timeline.load_post_ids( range.start, range.start + range.count, false ).await? {
+				if posts.len() >= POSTS_REQUEST_MAX_COUNT { break }
+				if !request.post_ids.contains( post_id ) { continue }
+
+				if let Some(post) = timeline.load_post( post_id ).await? {
+					let content = if request.include_content {
+						timeline.load_post_content( post_id ).await?
+					} else {
+						None
+					};
+					posts.push( PostData { post, content } );
+				}
+			}
+		}
+
+		Ok(( ResponseResultType::Success, bincode::serialize( &posts ).expect("unable to serialize posts response") ))
+	}
+
+	async fn process_request_event_set( this: Arc<NodeInner>, message: &[u8] ) -> Result<(ResponseResultType, Vec<u8>)> {
+
+		let request: EventSetRequest = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "event set request".to_owned()))?;
+		request.event_ids.validate( SUMMARY_MAX_COUNT as u64 )?;
+
+		// Only the events that we've applied ourselves are passed on, as the others haven't been checked yet.
+		let last_event_id = this.persistence.load_last_event_id().await?.unwrap_or( GENESIS_EVENT_ID );
+
+		let mut events = Vec::new();
+		for range in request.event_ids.ranges() {
+			let remaining = EVENTS_REQUEST_MAX_COUNT as usize - events.len();
+			if remaining == 0 { break }
+			if range.start > last_event_id { continue }
+
+			let count = min( min( range.count, remaining as u64 ), last_event_id + 1 - range.start ) as u16;
+			for (id, message) in this.persistence.load_events( range.start, count ).await? {
+				if request.event_ids.contains( id ) {
+					events.push( message );
+				}
+			}
+		}
+
+		let response = EventsResponse { events };
+		Ok(( ResponseResultType::Success, bincode::serialize( &response ).expect("unable to serialize events response") ))
+	}
+
+	async fn process_request_files( this: Arc<NodeInner>, message: &[u8] ) -> Result<(ResponseResultType, Vec<u8>)> {
+
+		let request: FilesRequest = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "files request".to_owned()))?;
+
+		let mut files = HashMap::with_capacity( request.file_ids.len() );
+		for file_id in request.file_ids.into_iter().take( FILES_REQUEST_MAX_COUNT ) {
+			if let Some(file) = this.persistence.load_file( &file_id ).await? {
+				files.insert( file_id, file );
+			}
+		}
+
+		let response = FilesResponse { files };
+		Ok(( ResponseResultType::Success, bincode::serialize( &response ).expect("unable to serialize files response") ))
+	}
+
+	async fn process_request_manifests( this: Arc<NodeInner>, message: &[u8] ) -> Result<(ResponseResultType, Vec<u8>)> {
+
+		let request: ManifestsRequest = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "manifests request".to_owned()))?;
+
+		let mut manifests = Vec::with_capacity( min( request.file_ids.len(), FILES_REQUEST_MAX_COUNT ) );
+		for file_id in request.file_ids.iter().take( FILES_REQUEST_MAX_COUNT ) {
+			let file = this.persistence.load_file( file_id ).await?;
+			manifests.push( file.filter(|f| f.is_manifest_of( file_id )) );
+		}
+
+		let response = ManifestsResponse { manifests };
+		Ok(( ResponseResultType::Success, bincode::serialize( &response ).expect("unable to serialize manifests response") ))
+	}
+
+	async fn process_request_blocks( this: Arc<NodeInner>, message: &[u8] ) -> Result<(ResponseResultType, Vec<u8>)> {
+
+		let request: BlocksRequest = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "blocks request".to_owned()))?;
+
+		let mut data = Vec::with_capacity( min( request.block_ids.len(), BLOCKS_REQUEST_MAX_COUNT ) );
+		for block_id in request.block_ids.iter().take( BLOCKS_REQUEST_MAX_COUNT ) {
+			data.push( this.persistence.load_block( block_id ).await? );
+		}
+
+		let response = BlocksResponse { data };
+		Ok(( ResponseResultType::Success, bincode::serialize( &response ).expect("unable to serialize blocks response") ))
+	}
+
+	async fn process_request_events( this: Arc<NodeInner>, message: &[u8] ) -> Result<(ResponseResultType, Vec<u8>)> {
+
+		let request: EventsRequest = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "events request".to_owned()))?;
+		// Only the events that we've applied ourselves are passed on, as the others haven't been checked yet.
+		let last_event_id = this.persistence.load_last_event_id().await?.unwrap_or( GENESIS_EVENT_ID );
+		let applied = ( last_event_id + 1 ).saturating_sub( request.from_id );
+		let count = min( min( request.count, EVENTS_REQUEST_MAX_COUNT ) as u64, applied ) as u16;
+
+		let events = this.persistence.load_events( request.from_id, count ).await?;
+		let response = EventsResponse {
+			events: events.into_iter().map(|(_, message)| message).collect()
+		};
+
+		Ok(( ResponseResultType::Success, bincode::serialize( &response ).expect("unable to serialize events response") ))
+	}
+
+	async fn process_request_search( this: Arc<NodeInner>, message: &[u8] ) -> Result<(ResponseResultType, Vec<u8>)> {
+
+		let request: PostSearchRequest = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "search request".to_owned()))?;
+		let max_results = min( request.max_results, SEARCH_REQUEST_MAX_RESULTS );
+
+		let matches = this.persistence.base.search_channel_posts( this.persistence.id, &request.keywords.join(" "), max_results as _ ).await?;
+		let mut results = Vec::with_capacity( matches.len() );
+		for found in matches {
+			let timeline = match this.persistence.get_timeline( &found.publisher ).await? {
+				None => continue,
+				Some(t) => t
+			};
+			if let Some(post) = timeline.load_post( found.post_id ).await? {
+				let content = timeline.load_post_content( found.post_id ).await?;
+				results.push( PostSearchResult {
+					publisher: found.publisher,
+					data: PostData { post, content }
+				});
+			}
+		}
+
+		let response = PostSearchResponse { results };
+		Ok(( ResponseResultType::Success, bincode::serialize( &response ).expect("unable to serialize search response") ))
+	}
+
+	/// Hands a response to the request that it belongs to, which has to have been sent to the same peer.
+	async fn process_response( this: Arc<NodeInner>, link: &Link, message: &[u8] ) -> Result<()> {
+
+		let session_id: u32 = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "response request id".to_owned()))?;
+
+		match link.requests.respond( session_id, message ) {
+			// Sending a response that is too large is the fault of the peer.
+			Err(RespondError::TooLarge(_)) => Err(MessageMalformedError::UnexpectedData("response".to_owned()))?,
+			// The response is dropped, so the request will time out as if it never arrived.
+			Err(e @ RespondError::BufferFull(_)) => this.errors.report( None, format!("dropping response: {}", e) ),
+			Ok(_) => {}
+		}
 
-		Ok( Self (
-			inner
-		))
+		Ok(())
 	}
 
-	pub async fn disconnect( &self ) {
-		// TODO: Notify children about disconnection, which gives them your parent node.
-		//       This way they don't have to reconnect to the network.
-		// TODO: Maybe make this non-async.
+	/// Adds a part of a response to the ones before it, until the whole response has arrived.
+	async fn process_response_part( this: Arc<NodeInner>, link: &Link, message: &[u8] ) -> Result<()> {
+
+		let header: ResponsePartHeader = bincode::deserialize( message )
+			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "response part header".to_owned()))?;
+		let data = &message[ bincode::serialized_size( &header ).unwrap() as usize.. ];
 
-		for child in &self.0.child_sockets {
-			let _ = child.lock().await.destroy().await;
+		match link.requests.respond_part( header.request_id, header.sequence, header.last, data ) {
+			// Sending a response that is too large, or its parts out of order, is the fault of the peer.
+			Err(RespondError::TooLarge(_)) | Err(RespondError::OutOfOrder(_)) => Err(MessageMalformedError::UnexpectedData("response part".to_owned()))?,
+			// The response is dropped, so the request will time out as if it never arrived.
+			Err(e @ RespondError::BufferFull(_)) => this.errors.report( None, format!("dropping response: {}", e) ),
+			Ok(_) => {}
 		}
 
-		let _ = self.0.parent_socket.lock().await.destroy().await;
+		Ok(())
 	}
 
-	async fn parent_receive_loop<F,E>( this: Arc<NodeInner>, on_bad_peer: F, on_error: E ) where
-		F: Fn( &PublicKey ),
-		E: Fn( gnunet::Error )
+	/// Rebroadcasts the given event message to the parents and children, except for the node which channel id is provided with `skip_channel_id`.
+	/// It tries to give the message to everybody.
+	/// This might mean that errors occur for multiple peers.
+	/// Every error occurence invokes `on_error` with the error provided.
+	/// Once the channel has gone over its upload limit, every rebroadcast waits until the channel is back under it, and is sent with a low priority.
+	/// With `background_rebroadcasts`, they are always sent with a low priority.
+	async fn rebroadcast_message<E>( this: Arc<NodeInner>, message: &[u8], skip_channel_id: u32, on_error: E ) where
+		E: Fn(gnunet::Error)
 	{
-		Self::peer_receive_loop( this.clone(), &this.parent_address, &this.parent_socket, on_bad_peer, on_error ).await;
+		let event = SealedEvent::new( this.key.as_ref(), message );
+
+		let parents = this.parents();
+		let children = this.children.read().await.clone();
+		for peer in parents.iter().chain( children.iter() ) {
+			if peer.socket.lock().await.id() == skip_channel_id { continue }
+			let complete_msg = event.for_peer( &peer.session ).await;
+
+			let delay = this.traffic.upload_delay();
+			let priority = if delay > Duration::from_secs( 0 ) {
+				this.traffic.throttled.fetch_add( 1, Ordering::AcqRel );
+				metrics::THROTTLED_REBROADCASTS.inc();
+				trace!(delay_ms = delay.as_millis() as u64, "Over the upload limit, holding back rebroadcast");
+				task::sleep( delay ).await;
+				Priority::Background
+			} else if this.background_rebroadcasts.load( Ordering::Acquire ) {
+				Priority::Background
+			} else {
+				Priority::Normal
+			};
+
+			match peer.send( complete_msg, priority ).await {
+				Err(e) => on_error(e.into()),
+				Ok(()) => metrics::EVENTS_REBROADCAST.inc()
+			}
+		}
 	}
 
-	/// The loop that needs to be run in order to process the messages that this node may receive for a given peer
-	/// 
-	/// # Arguments
-	/// `on_bad_peer` - A closure that is called whenever it is identified that the given peer is malicious.
-	///                 This can have multiple reasons. Most often it is because the message has appeared incorrect.
-	async fn peer_receive_loop<F,E>( this_: Arc<NodeInner>, address: &PublicKey, channel: &Mutex<cadet::Channel>, on_bad_peer: F, on_error: E ) where
-		F: Fn( &PublicKey ),
-		E: Fn( gnunet::Error )
-	{
-		// Loop until channel is closed
-		loop {
-			let this = this_.clone();
-			let receiver = channel.lock().await.clone_receiver();
-			let result: gnunet::Result<bool> = async {
-
-				let message = match receiver.receive().await {
-					None => return Ok(false),	// break
-					Some(m) => m
-				};
-				match Self::process_message( this, &channel, &*message.payload, &on_error ).await {
-					Err(err) => {
-						match err {
-							Error::MessageMalformed(e) => {
-								eprintln!("Malformed message received from peer: {}, repelling it...", e);
-								on_bad_peer( &address );
-								return Ok(false)	// break
-							},
-							Error::Gnunet(e) => Err(e)?,
-							other => panic!("Unexpected error occurred while processing message: {}", other)
-						}
-					},
-					Ok(()) => Ok(true)
+	/// Publishes the events in the outbox of the channel, oldest first.
+	/// An event leaves the outbox once it has been handed to a peer, and publishing stops at the first event that fails, so that the swarm never receives an event before the ones that precede it.
+	/// Besides being called every so often, this is done whenever we've connected to a parent or admitted a child, so that what has been made while we were on our own goes out right away.
+	pub async fn publish_outbox( &self ) -> Result<()> {
+		let this = &self.0;
+		let _publishing = this.outbox.lock().await;
+
+		for entry in this.persistence.list_outbox().await? {
+			let message = match this.persistence.load_events( entry.event_id, 1 ).await?.into_iter().next() {
+				Some((_, m)) => m,
+				// The event isn't stored anymore, so there is nothing to publish.
+				None => {
+					this.persistence.dequeue_event( entry.event_id ).await?;
+					continue
+				}
+			};
+
+			match self.publish_event( &message ).await {
+				Ok(()) => this.persistence.dequeue_event( entry.event_id ).await?,
+				// Being on our own isn't worth recording as a failure, the event just waits for a peer.
+				Err(Error::NotConnected) => break,
+				Err(e) => {
+					this.persistence.record_outbox_failure( entry.event_id, &e.to_string() ).await?;
+					break
 				}
-			}.await;
-
-			match result {
-				Err(e) => on_error( e ),
-				Ok(cont) => if !cont { break }
 			}
 		}
-	}
-
-	/// Processes a message from a peer.
-	/// Returns whether or not the message was considered to be benevolent.
-	/// If the message was malformed, the message is considered to be malicious.
-	/// When something goes wrong during relaying a message to other peers, `on_error` is called with the error.
-	/// This is because those errors shouldn't impede control flow.
-	async fn process_message<E>( this: Arc<NodeInner>, channel: &Mutex<cadet::Channel>, message: &[u8], on_error: &E ) -> Result<()> where
-		E: Fn(gnunet::Error)
-	{
-
-		let direction_type: MessageDirectionType = match message[0].try_into() {
-			Err(e) => Err(MessageMalformedError::InvalidTypeId(message[0], "direction type".to_owned()))?,
-			Ok(d) => d
-		};
-		
-		match direction_type {
-			MessageDirectionType::Event => Self::process_event( this.clone(), channel, &message[1..], on_error ).await?,
-			MessageDirectionType::Request => Self::process_request( this, channel, &message[5..] ).await?,
-			MessageDirectionType::Response => Self::process_response( this, &message[1..] ).await?
-		};
 
 		Ok(())
 	}
 
-	async fn process_event<E>( this: Arc<NodeInner>, channel: &Mutex<cadet::Channel>, message: &[u8], on_error: &E ) -> Result<()> where
-		E: Fn(gnunet::Error)
-	{
+	/// Publishes the outbox in the background, now that there is another peer to publish it to.
+	fn drain_outbox( this: &Arc<NodeInner> ) {
+		let node = Node( this.clone() );
+		runtime::spawn(async move {
+			if let Err(e) = node.publish_outbox().await {
+				node.0.errors.report( None, format!("unable to publish the outbox: {}", e) );
+			}
+		}.instrument( this.span.clone() ));
+	}
 
-		let id: u64 = bincode::deserialize( message )?;
+	/// Publishes an event of our own, in the form in which it is broadcasted, to the parents and the children.
+	/// Only one of the parents needs to receive it, because it passes it on to the rest of the swarm.
+	/// Failing to reach a child or some of the parents is reported, but doesn't make the publication fail.
+	/// The node that started the swarm has nobody to publish to until a child has joined, which is taken for not being connected.
+	pub async fn publish_event( &self, message: &[u8] ) -> Result<()> {
+		let this = &self.0;
 
-		let event_type: EventType = bincode::deserialize( &message[8..] )?;
+		if !this.connected.load( Ordering::Acquire ) {
+			return Err( Error::NotConnected )
+		}
 
-		{
-			let mut latest_event_id = this.latest_event_id.lock().await;
+		let event = SealedEvent::new( this.key.as_ref(), message );
+
+		let mut reached = None;
+		for parent in this.parents() {
+			let complete_msg = event.for_peer( &parent.session ).await;
+			match parent.send( complete_msg, Priority::Normal ).await {
+				Err(e) => {
+					this.errors.report( Some( &parent.session.address ), format!("unable to publish event: {}", e) );
+					if reached.is_none() { reached = Some( Err( e ) ) }
+				},
+				Ok(()) => reached = Some( Ok(()) )
+			}
+		}
+		match reached {
+			// The node that started the swarm only has children to publish to.
+			None if this.root => {},
+			None => return Err( Error::NotConnected ),
+			Some(Err(e)) => return Err( Error::Gnunet( e.into() ) ),
+			Some(Ok(())) => {}
+		}
 
-			// If this is the next event we need to process, process it immediately.
-			if id == (*latest_event_id + 1) {
+		let mut delivered = reached.is_some();
+		let children = this.children.read().await.clone();
+		for child in children.iter() {
+			let complete_msg = event.for_peer( &child.session ).await;
+			match child.send( complete_msg, Priority::Normal ).await {
+				Err(e) => this.errors.report( Some( &child.session.address ), format!("unable to publish event: {}", e) ),
+				Ok(()) => delivered = true
+			}
+		}
 
-				match event_type {
-					EventType::Channel => Self::process_event_channel( this.clone(), id, &message[1..] ).await?,
-					EventType::Publisher( address ) => Self::process_event_publisher( this.clone(), id, &address, &message[1..] ).await?
-				}
+		if !delivered {
+			return Err( Error::NotConnected )
+		}
+		Ok(())
+	}
 
-				// We can only update the event id after we know it wasn't malformed
-				*latest_event_id = id;
+	/// Sends a request into the swarm, and returns the response of the first peer that answers it.
+	/// The request is sent to the parent that has responded the fastest so far first.
+	/// If it doesn't respond in time, or is unable to send the message to it, the request is retried with the other parents, and then with the children.
+	pub async fn request( &self, request_type: RequestType, payload: &[u8] ) -> Result<(ResponseResultType, Vec<u8>)> {
+		Self::request_any( &self.0, request_type, payload ).await
+	}
+
+	async fn request_any( this: &Arc<NodeInner>, request_type: RequestType, payload: &[u8] ) -> Result<(ResponseResultType, Vec<u8>)> {
+		Self::request_from_any( this, request_type, payload ).await.map(|(_, response)| response)
+	}
+
+	/// Like `request_any`, but also returns the peer that responded, so that it can be held to what it sent.
+	async fn request_from_any( this: &Arc<NodeInner>, request_type: RequestType, payload: &[u8] ) -> Result<(PublicKey, (ResponseResultType, Vec<u8>))> {
+
+		let parents = this.parents();
+		let children = this.children.read().await.clone();
+		for link in parents.iter().chain( children.iter() ) {
+			let session = &link.session;
+			if !session.speaks(|v| v.has_request( request_type )).await { continue }
+			let span = debug_span!(parent: &session.span, "request", kind = ?request_type);
+			let started = Instant::now();
+			let result = Self::send_request( this, link, request_type, payload ).instrument( span.clone() ).await;
+			// Requests that time out are left out, so that the timings are those of the peers that respond.
+			if let Ok(Some(_)) = &result {
+				session.record_latency( started.elapsed() );
+				metrics::SWARM_REQUESTS.with_label_values( &[&config::request_kind( request_type )] ).observe( started.elapsed().as_secs_f64() );
 			}
-			// Otherwise, store it for later processing
-			else {
-				// If the event is that much more newer than our last received/known event id,
-				//  we assume the message was malevolent.
-				// Otherwise, we'd be vurnerable to filling our disk with senseless data.
-				if id - *latest_event_id > 100 {
-					Err( MessageMalformedError::InvalidEventId( id ) )?
-				}
-				if id > *latest_event_id {
-					let start = 8 + bincode::serialized_size( &event_type ).unwrap() as usize;
-					let event_message = &message[start..];
-
-					match event_type {
-						EventType::Channel => this.persistence.store_event( id, event_message ).await?,
-						EventType::Publisher(address) => match this.persistence.get_timeline( &address ).await? {
-							None => Err( MessageMalformedError::UnknownPublisher(address) )?,
-							Some( timeline ) => timeline.store_event( id, event_message ).await?
+			match result {
+				Err(Error::Gnunet(e)) => this.errors.report( Some( &session.address ), format!("unable to send request: {}", e) ),
+				Err(e) => return Err(e),
+				Ok(None) => {
+					debug!(parent: &span, "Timed out");
+					session.timeouts.fetch_add( 1, Ordering::AcqRel );
+					this.reputation.adjust( &session.address, REPUTATION_TIMEOUT ).await;
+				},
+				Ok(Some(response)) => {
+					// Remember who carries the channel, for the relay report.
+					if matches!( response.0, ResponseResultType::Success ) {
+						this.reputation.adjust( &session.address, REPUTATION_USEFUL ).await;
+						let result = this.persistence.base.get_peer( &session.address )
+							.record_served( this.persistence.id, request_type, response.1.len() as u64 ).await;
+						if let Err(e) = result {
+							this.errors.report( None, format!("unable to record served request: {}", e) );
 						}
 					}
+					return Ok(( session.address.clone(), response ))
 				}
 			}
 		}
 
-		// Either way, rebroadcast the message if the event wasn't found to be malformed/invalid.
-		Self::rebroadcast_message( this, message, channel.lock().await.id(), on_error ).await;
-
-		Ok(())
+		Err( Error::NoResponse )
 	}
 
-	async fn process_event_channel( this: Arc<NodeInner>, id: u64, message: &[u8] ) -> Result<()> {
-		if message.len() == 0 {
-			Err(MessageMalformedError::MissingData("channel event".to_owned()))?
-		}
+	/// Deals with a peer that responded to one of our requests with data that doesn't match its hash or signature, like with a malformed message.
+	/// Returns the error with which the request fails.
+	async fn reject_response( this: &NodeInner, peer: &PublicKey, error: MessageMalformedError ) -> Error {
+		metrics::MALFORMED_MESSAGES.inc();
+		this.reputation.adjust( peer, REPUTATION_MALFORMED ).await;
+		this.errors.report( Some( peer ), format!("corrupt response, repelling peer: {}", error) );
+		this.bad_peers.flag( peer, &format!("corrupt data: {}", error) ).await;
+		Error::MessageMalformed( error )
+	}
 
-		let event_type: ChannelEventType = match message[0].try_into() {
-			Err(_) => Err(MessageMalformedError::InvalidTypeId(message[0], "channel event type".to_owned()))?,
-			Ok(e) => e
-		};
+	/// Sends a request to the given peer, and waits for its response.
+	/// Returns `None` if no response was received within the session timeout.
+	/// Other requests can be sent to the same peer in the meantime, and the request is cancelled if this is dropped before the response has arrived.
+	async fn send_request( this: &Arc<NodeInner>, link: &Link, request_type: RequestType, payload: &[u8] ) -> Result<Option<(ResponseResultType, Vec<u8>)>> {
 
-		match event_type {
-			ChannelEventType::UpdateChannelProfile => Self::process_event_channel_update_profile( this, id, &message[1..] ).await,
-			ChannelEventType::UpdatePublisherList => Self::process_event_channel_update_publisher_list( this, id, &message[1..] ).await
-		}
-	}
+		// Open the session before sending, so that the response can't arrive before anybody is waiting for it.
+		let pending = link.requests.open( Duration::from_millis( config::get().session_timeout_for( request_type ) ) );
 
-	async fn process_event_channel_update_profile( this: Arc<NodeInner>, id: u64, message: &[u8] ) -> Result<()> {
+		let mut message = Vec::with_capacity( 6 + payload.len() );
+		message.push( MessageDirectionType::Request as u8 );
+		message.extend_from_slice( &pending.id().to_le_bytes() );
+		message.push( request_type as u8 );
+		message.extend_from_slice( payload );
+		let message = seal_message( this.key.as_ref(), message );
 
-		let msg: UpdateChannelProfileEventMessage = bincode::deserialize( message )
-			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "upgrade profile event message".to_owned()))?;
-		
-		let mut profile_data = bincode::serialize( &msg.profile ).expect("unable to serialize channel profile");
-		let mut checksum_content = bincode::serialize( &id ).unwrap();
-		checksum_content.append( &mut profile_data );
+		link.send( &*message, Priority::Normal ).await
+			.map_err(|e| Error::Gnunet(e.into()))?;
 
-		// Check hash
-		if HashCode::generate_from( &msg.profile ) != msg.hash {
-			Err(MessageMalformedError::InvalidHash("upgrade profile event message".to_owned()))?;
+		// The response starts with the session id, followed by the result type.
+		let response = match pending.wait().await {
+			None => return Ok(None),
+			Some(r) => r
+		};
+		if response.len() < 5 {
+			Err(MessageMalformedError::MissingData("response".to_owned()))?
 		}
+		let result_type: ResponseResultType = response[4].try_into()
+			.map_err(|_| MessageMalformedError::InvalidTypeId(response[4], "response result type".to_owned()))?;
+
+		Ok(Some(( result_type, response[5..].to_vec() )))
+	}
 
-		// Check signature
-		let public_key = this.persistence.load_address().await?;
-		if !msg.signature.verify_hash( &msg.hash, &public_key ) {
-			Err(MessageMalformedError::InvalidSignature("upgrade profile event message".to_owned()))?
+	/// Sends the response to a request back to the peer that made it.
+	/// Responses that don't fit in a single message are sent in parts, and large responses are compressed, to the peers that understand them.
+	async fn respond( this: Arc<NodeInner>, link: &Link, request_id: u32, result: ResponseResultType, response: &[u8] ) -> Result<()> {
+		let compress = link.session.speaks( ProtocolVersion::has_compression ).await;
+		if link.session.speaks( ProtocolVersion::has_response_parts ).await && 1 + response.len() > RESPONSE_PART_MAX_SIZE {
+			let mut writer = ResponseWriter::new( this.key.as_ref(), compress, link, request_id );
+			writer.write( &[result as u8] ).await?;
+			writer.write( response ).await?;
+			return writer.finish().await
 		}
 
-		let current_profile = this.persistence.fetch_profile().await?;
+		let mut message = Vec::with_capacity( 6 + response.len() );
+		message.push( MessageDirectionType::Response as u8 );
+		message.extend_from_slice( &request_id.to_le_bytes() );
+		message.push( result as u8 );
+		message.extend_from_slice( response );
+		let message = if compress { compress_message( &message ).unwrap_or( message ) } else { message };
+		let message = seal_message( this.key.as_ref(), message );
 
-		// If new, store it
-		if current_profile.is_none() || msg.profile.base.revision > current_profile.unwrap().base.revision {
-			this.persistence.store_profile( &msg.profile ).await?;
-		}
+		link.send( &*message, Priority::Normal ).await
+			.map_err(|e| Error::Gnunet(e.into()))?;
 
 		Ok(())
 	}
+}
 
-	async fn process_event_channel_update_publisher_list( this: Arc<NodeInner>, event_id: u64, message: &[u8] ) -> Result<()> {
-
-		let publisher_address: Vec<PublicKey> = bincode::deserialize( message )
-			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "publisher address".to_owned()))?;
+impl Traffic {
 
-		// TODO: Update the publisher list
+	fn new() -> Self {
+		let burst = Self::upload_limit().map(|(_, burst)| burst).unwrap_or(0);
 
-		Ok(())
+		Self {
+			sent: AtomicU64::new( 0 ),
+			received: AtomicU64::new( 0 ),
+			throttled: AtomicU64::new( 0 ),
+			upload_budget: std::sync::Mutex::new( TokenBucket::new( burst ) )
+		}
 	}
 
-	async fn process_event_publisher( this: Arc<NodeInner>, event_id: u64, address: &PublicKey, message: &[u8] ) -> Result<()> {
-		let mut step = 33usize;	// Size of the public key in bytes
+	/// The upload limit of a channel in bytes per second, and the number of bytes that it may upload at once, or `None` if there is no limit.
+	/// It is read from the configuration every time, so that a change takes effect right away.
+	fn upload_limit() -> Option<(u32, u32)> {
+		let limit = config::get().upload_limit;
+		if limit == 0 { return None }
 
-		if message.len() < (step + 1) {
-			Err(MessageMalformedError::MissingData("publisher event".to_owned()))?
-		}
+		let rate = limit.saturating_mul( 1024 );
+		Some(( rate, rate.saturating_mul( config::UPLOAD_BURST ) ))
+	}
 
-		let event_type: PublisherEventType = match message[step].try_into() {
-			Err(_) => Err(MessageMalformedError::InvalidTypeId(message[step], "publisher event type".to_owned()))?,
-			Ok(e) => e
-		};
-		step += 1;
+	fn record_sent( &self, bytes: usize ) {
+		self.sent.fetch_add( bytes as u64, Ordering::AcqRel );
+		metrics::BYTES_SENT.inc_by( bytes as u64 );
+		if let Some((rate, burst)) = Self::upload_limit() {
+			self.upload_budget.lock().unwrap().spend( bytes as u64, rate, burst );
+		}
+	}
 
-		match event_type {
-			PublisherEventType::UpdateProfile => Self::process_event_publisher_update_profile( this, &address, &message[step..] ).await,
-			PublisherEventType::PublishPost => Self::process_event_publisher_publish_post( this, &address, &message[step..] ).await,
-			PublisherEventType::RevisePost => Self::process_event_publisher_revise_post( this, &address, &message[step..] ).await,
-			PublisherEventType::ForgetPost => Self::process_event_publisher_forget_post( this, &address, &message[step..] ).await
+	/// How long a rebroadcast has to wait for the channel to get back under its upload limit.
+	fn upload_delay( &self ) -> Duration {
+		match Self::upload_limit() {
+			None => Duration::from_secs( 0 ),
+			Some((rate, burst)) => self.upload_budget.lock().unwrap().debt( rate, burst )
 		}
 	}
+}
 
-	async fn process_event_publisher_forget_post( this: Arc<NodeInner>, publisher: &PublicKey, message: &[u8] ) -> Result<()> {
+impl SealedEvent {
 
-		let post_id: u64 = match bincode::deserialize( message ) {
-			Err(e) => Err(MessageMalformedError::DeserializationIssue(e, "publisher event post id".to_owned()))?,
-			Ok(id) => id
-		};
+	fn new( key: Option<&ChannelKey>, message: &[u8] ) -> Self {
+		let mut complete_msg = Vec::<u8>::with_capacity( 1 + message.len() );
+		complete_msg.push( MessageDirectionType::Event.into() );
+		complete_msg.extend_from_slice( message );
 
-		// TODO: Store the post
+		Self {
+			compressed: compress_message( &complete_msg ).map(|c| seal_message( key, c )),
+			plain: seal_message( key, complete_msg )
+		}
+	}
 
-		Ok(())
+	/// The form of the event that the given peer understands.
+	async fn for_peer( &self, session: &PeerSession ) -> &[u8] {
+		match &self.compressed {
+			Some(compressed) if session.speaks( ProtocolVersion::has_compression ).await => compressed,
+			_ => &self.plain
+		}
 	}
+}
 
-	async fn process_event_publisher_publish_post( this: Arc<NodeInner>, publisher: &PublicKey, message: &[u8] ) -> Result<()> {
+impl<'a> ResponseWriter<'a> {
 
-		let post_id: u64 = match bincode::deserialize( message ) {
-			Err(e) => Err(MessageMalformedError::DeserializationIssue(e, "publisher event post id".to_owned()))?,
-			Ok(id) => id
-		};
+	fn new( key: Option<&'a ChannelKey>, compress: bool, link: &'a Link, request_id: u32 ) -> Self {
+		Self {
+			key,
+			compress,
+			link,
+			request_id,
+			sequence: 0,
+			buffer: Vec::with_capacity( RESPONSE_PART_MAX_SIZE )
+		}
+	}
 
-		// TODO: Store the post
+	/// Adds the data to the response, sending the parts that have been filled up.
+	async fn write( &mut self, mut data: &[u8] ) -> Result<()> {
+		while !data.is_empty() {
+			if self.buffer.len() == RESPONSE_PART_MAX_SIZE {
+				self.send_part( false ).await?;
+			}
 
+			let length = min( RESPONSE_PART_MAX_SIZE - self.buffer.len(), data.len() );
+			self.buffer.extend_from_slice( &data[..length] );
+			data = &data[length..];
+		}
 		Ok(())
 	}
 
-	async fn process_event_publisher_revise_post( this: Arc<NodeInner>, publisher: &PublicKey, message: &[u8] ) -> Result<()> {
+	/// Sends what is left of the response, as its last part.
+	async fn finish( mut self ) -> Result<()> {
+		self.send_part( true ).await
+	}
 
-		let post_id: u64 = match bincode::deserialize( message ) {
-			Err(e) => Err(MessageMalformedError::DeserializationIssue(e, "publisher event post id".to_owned()))?,
-			Ok(id) => id
+	async fn send_part( &mut self, last: bool ) -> Result<()> {
+		let header = ResponsePartHeader {
+			request_id: self.request_id,
+			sequence: self.sequence,
+			last
 		};
 
-		// TODO: Store the post
+		let mut message = Vec::with_capacity( 1 + 9 + self.buffer.len() );
+		message.push( MessageDirectionType::ResponsePart as u8 );
+		message.extend( bincode::serialize( &header ).unwrap() );
+		message.extend_from_slice( &self.buffer );
+		let message = if self.compress { compress_message( &message ).unwrap_or( message ) } else { message };
+		let message = seal_message( self.key, message );
 
+		// The lock is only held for a part at a time, so that the events that are sent in the meantime don't have to wait for the whole response.
+		self.link.send( &*message, Priority::Normal ).await
+			.map_err(|e| Error::Gnunet(e.into()))?;
+
+		self.sequence += 1;
+		self.buffer.clear();
 		Ok(())
 	}
+}
 
-	async fn process_event_publisher_update_profile( this: Arc<NodeInner>, publisher: &PublicKey, message: &[u8] ) -> Result<()> {
-
-		let profile: Profile = bincode::deserialize( message )
-			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "upgrade profile event profile".to_owned()))?;
+impl Link {
 
-		// TODO: Store the post
+	fn new( session: PeerSession, socket: Box<dyn PeerChannel>, max_response_size: usize ) -> Self {
+		Self {
+			session,
+			socket: Mutex::new( socket ),
+			requests: Arc::new( SessionManager::new( max_response_size ) ),
+			ancestors: std::sync::Mutex::new( Vec::new() ),
+			capacity: std::sync::Mutex::new( None ),
+			census: std::sync::Mutex::new( None )
+		}
+	}
 
-		Ok(())
+	/// The ancestors that this parent has told us about beyond the given one, which is the best guess for the ancestors of that one.
+	fn ancestors_beyond( &self, ancestor: &PublicKey ) -> Vec<PublicKey> {
+		let ancestors = self.ancestors.lock().unwrap();
+		match ancestors.iter().position(|a| a == ancestor) {
+			None => Vec::new(),
+			Some(i) => ancestors[(i + 1)..].to_vec()
+		}
 	}
 
-	async fn process_request( this: Arc<NodeInner>, channel: &Mutex<cadet::Channel>, message: &[u8] ) -> Result<()> {
-		if message.len() < 5 {
-			Err( MessageMalformedError::MissingData("request".to_owned()) )?;
+	/// Takes one of the free relay slots that this child has told us about, for a peer that we hand to it.
+	/// Returns false if it has none left, as far as we know.
+	fn take_slot( &self ) -> bool {
+		match &mut *self.capacity.lock().unwrap() {
+			Some((free, _)) if *free > 0 => {
+				*free -= 1;
+				true
+			},
+			_ => false
 		}
+	}
 
-		let request_id: u32 = bincode::deserialize( message )
-			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "request id".to_owned()) )?;
+	/// Sends a message to the peer, and counts it in the traffic of the session and the channel.
+	async fn send( &self, message: &[u8], priority: Priority ) -> gnunet::Result<()> {
+		self.socket.lock().await.send( message, priority ).await?;
+		self.session.bytes_sent.fetch_add( message.len() as u64, Ordering::AcqRel );
+		self.session.traffic.record_sent( message.len() );
+		Ok(())
+	}
+}
 
-		let request_type: RequestType = message[4].try_into()
-			.map_err(|_| MessageMalformedError::InvalidTypeId(message[4], "request type".to_owned()))?;
+impl NodeInner {
 
-		let (result_type, payload) = match request_type {
-			RequestType::Posts => Self::process_request_posts( this.clone(), &message[5..] ).await?,
-			RequestType::Files => { eprintln!("Files request not supported yet..."); return Ok(()) },
-			RequestType::Blocks => { eprintln!("Blocks request not supported yet..."); return Ok(()) }
-		};
+	/// The links with our parents, the one that has responded the fastest first.
+	/// Parents that haven't responded to any request yet come after the ones that have.
+	fn parents( &self ) -> Vec<Arc<Link>> {
+		let mut parents = self.parents.read().unwrap().clone();
+		parents.sort_by_key(|p| match p.session.latency.load( Ordering::Acquire ) {
+			0 => u64::MAX,
+			latency => latency
+		});
+		parents
+	}
 
-		Self::respond( this, &mut *channel.lock().await, request_id, result_type, &*payload ).await?;
+	/// The addresses of our parents, followed by the ancestors of the first one, at most `TOPOLOGY_ANCESTORS_MAX`.
+	/// The parents are kept in the order that we've connected to them, so that the list doesn't change with their latencies.
+	fn ancestors( &self ) -> Vec<PublicKey> {
+		let parents = self.parents.read().unwrap().clone();
+		let mut ancestors: Vec<PublicKey> = parents.iter().map(|p| p.session.address.clone()).collect();
+		if let Some(first) = parents.first() {
+			for ancestor in first.ancestors.lock().unwrap().iter() {
+				if !ancestors.contains( ancestor ) {
+					ancestors.push( ancestor.clone() );
+				}
+			}
+		}
+		ancestors.truncate( TOPOLOGY_ANCESTORS_MAX );
+		ancestors
+	}
 
-		Ok(())
+	/// The number of children that we can still take.
+	async fn free_slots( &self ) -> usize {
+		let slots = 1usize << self.relay_power.load( Ordering::Acquire );
+		slots.saturating_sub( self.children.read().await.len() )
 	}
 
-	async fn process_request_posts( this: Arc<NodeInner>, message: &[u8] ) -> Result<(ResponseResultType, Vec<u8>)> {
-		#[derive(Deserialize)]
-		struct Posts {
-			timeline_id: PublicKey,
-			post_id_start: u64,
-			post_id_count: u16
-		}
+	/// What we tell our parents about our capacity.
+	async fn capacity( &self ) -> TopologyMessage {
+		let free = min( self.free_slots().await, u16::MAX as usize ) as u16;
+		let other_parents = self.parents.read().unwrap().len() > 1;
+		TopologyMessage::Capacity { free, other_parents }
+	}
 
-		/// Reads the nth bit of the given mask, where n = `index + 1`.
-		fn get_bit( mask: &[u8], index: u16 ) -> bool {
-			debug_assert!(index/8 < mask.len() as u16, "index out of bounds");
-			let byte = mask[ (index / 8) as usize ];
-			let byte_index = index % 8;
-			byte & (1 << byte_index) > 0
-		}
+	/// The number of peers in our subtree, ourselves included, as far as our children have told us.
+	/// Children that haven't told us yet are counted as a whole peer.
+	async fn subtree_size( &self ) -> f64 {
+		let children = self.children.read().await;
+		1.0 + children.iter()
+			.map(|c| c.census.lock().unwrap().map(|s| s as f64 / CENSUS_UNIT as f64).unwrap_or( 1.0 ))
+			.sum::<f64>()
+	}
 
-		/// Reads the nth bit of the given mask, where n = `index + 1`.
-		fn set_bit( mask: &mut [u8], index: u16 ) {
-			debug_assert!(index/8 < mask.len() as u16, "index out of bounds");
-			let mut byte = 0u8;
-			let byte_index = index % 8;
-			byte |= 1 << byte_index;
-			mask[ (index / 8) as usize ] = byte;
+	/// The number of peers in the whole swarm.
+	/// The root has counted it itself, the others go by the largest count that their parents have passed down, if they have.
+	async fn swarm_size( &self ) -> Option<u32> {
+		if self.root {
+			return Some( self.subtree_size().await.round() as u32 )
 		}
+		self.parents.read().unwrap().iter()
+			.filter_map(|p| *p.census.lock().unwrap())
+			.max()
+	}
+}
 
-		let Posts{timeline_id, post_id_start, post_id_count} = bincode::deserialize( message )
-			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "posts request".to_owned()))?;
+impl Drop for NodeInner {
+	fn drop( &mut self ) {
+		// Lets the event loop and the request workers end, and the receive loops stop waiting for room in their queues.
+		self.events.close();
+		self.requests.close();
+	}
+}
 
-		// The post id mask
-		let next = 33 + 8 + 2;
-		let mut mask_length: usize = (post_id_count / 8) as _;	if post_id_count % 8 > 0 { mask_length += 1 };
-		let mask = &message[next..(next+mask_length)];
-		let mut found_mask = vec!(0u8; mask.len());
+impl BadPeerStore {
 
-		// Collect all available posts and update the 'found' mask.
-		let mut posts = Vec::with_capacity( post_id_count as _ );
-		let timeline = match this.persistence.get_timeline( &timeline_id ).await? {
-			None => Err( MessageMalformedError::UnknownPublisher( timeline_id ) )?,
-			Some(t) => t
+	/// Loads the ban duration from the settings, and lifts the bans that have expired.
+	pub async fn load( persistence: persistence::Handle ) -> persistence::Result<Self> {
+
+		let seconds = match persistence.load_setting( setup::SETTING_BAD_PEER_BAN_DURATION ).await? {
+			None => config::BAD_PEER_BAN_DURATION,
+			Some(duration) => duration.parse().unwrap_or( config::BAD_PEER_BAN_DURATION )
 		};
-		for i in 0..post_id_count {
-			let bit = get_bit( mask, i );
+		persistence.expire_bad_peers().await?;
 
-			let result = timeline.load_post( i as _ ).await?;
-			if !result.is_none() {
-				posts.push( result.unwrap() );
-				set_bit( &mut *found_mask, i );
-			}
+		Ok( Self {
+			persistence,
+			ban_duration: Duration::from_secs( seconds )
+		})
+	}
+
+	/// Blocks the peer for misbehaving.
+	/// Errors are only logged, because they shouldn't keep us from disconnecting from the peer.
+	pub async fn flag( &self, peer: &PublicKey, reason: &str ) {
+		if let Err(e) = self.ban( peer, reason ).await {
+			warn!(peer = %peer, "Unable to flag peer as bad: {}", e);
 		}
+	}
 
-		return Ok(( ResponseResultType::Success, found_mask ))
+	/// Blocks the peer, for one more ban duration than it was blocked for the last time.
+	pub async fn ban( &self, peer: &PublicKey, reason: &str ) -> persistence::Result<()> {
+		self.persistence.get_peer( peer ).flag_bad( reason, self.ban_duration ).await
 	}
 
-	async fn process_response( this: Arc<NodeInner>, message: &[u8] ) -> Result<()> {
+	/// Lifts the ban of the peer right away.
+	/// Returns whether the peer was flagged.
+	pub async fn unban( &self, peer: &PublicKey ) -> persistence::Result<bool> {
+		self.persistence.get_peer( peer ).unflag_bad().await
+	}
 
-		let session_id: u32 = bincode::deserialize( message )
-			.map_err(|e| MessageMalformedError::DeserializationIssue(e, "response request id".to_owned()))?;
+	pub async fn is_blocked( &self, peer: &PublicKey ) -> persistence::Result<bool> {
+		self.persistence.get_peer( peer ).is_blocked().await
+	}
+}
 
-		this.session_manager.lock().await.respond(session_id, message.to_owned()).await;
+impl Reputation {
 
-		Ok(())
+	pub fn new( persistence: persistence::Handle ) -> Self {
+		Self {
+			persistence,
+			half_life: (config::REPUTATION_HALF_LIFE * 1000) as f64
+		}
 	}
 
-	/// Rebroadcasts the given event message to the parent and children, except for the node which channel id is provided with `skip_channel_id`.
-	/// It tries to give the message to everybody.
-	/// This might mean that errors occur for multiple peers.
-	/// Every error occurence invokes `on_error` with the error provided.
-	async fn rebroadcast_message<E>( this: Arc<NodeInner>, message: &[u8], skip_channel_id: u32, on_error: E ) where
-		E: Fn(gnunet::Error)
-	{
-		let mut complete_msg = Vec::<u8>::with_capacity( 1 + message.len() );
-		complete_msg.push( MessageDirectionType::Event.into() );
-		complete_msg.extend_from_slice( message );
+	/// The current score of the peer.
+	/// Peers that we know nothing about have a score of zero.
+	pub async fn score( &self, peer: &PublicKey ) -> persistence::Result<f64> {
+		Ok( match self.persistence.get_peer( peer ).load_reputation().await? {
+			None => 0.0,
+			Some((score, updated)) => self.decay( score, updated )
+		})
+	}
 
-		let mut psock = this.parent_socket.lock().await;
-		if psock.id() != skip_channel_id {
-			match psock.send( cadet::PRIORITY_PREFERENCES_BEST_EFFORT, message ).await {
-				Err(e) => on_error(e.into()),
-				Ok(()) => {}
-			}
+	/// Changes the score of the peer by `delta`.
+	/// Errors are only logged, because they shouldn't interrupt the communication with the peer.
+	pub async fn adjust( &self, peer: &PublicKey, delta: f64 ) {
+		let result = async {
+			let score = self.score( peer ).await?;
+			self.persistence.get_peer( peer ).store_reputation( score + delta, peer::now() ).await
+		}.await;
+
+		if let Err(e) = result {
+			warn!(peer = %peer, "Unable to update the reputation of peer: {}", e);
 		}
+	}
 
-		for child in this.child_sockets.iter() {
-			let csock = child.lock().await;
-			match psock.send( cadet::PRIORITY_PREFERENCES_BEST_EFFORT, message ).await {
-				Err(e) => on_error(e.into()),
-				Ok(()) => {}
+	/// Orders the peers from the highest to the lowest score.
+	/// Peers with the same score keep their order.
+	pub async fn rank( &self, peers: &[PublicKey] ) -> persistence::Result<Vec<PublicKey>> {
+		let mut scored = Vec::with_capacity( peers.len() );
+		for peer in peers {
+			scored.push(( self.score( peer ).await?, peer.clone() ));
+		}
+
+		scored.sort_by(|a, b| b.0.partial_cmp( &a.0 ).unwrap_or( cmp::Ordering::Equal ));
+		Ok( scored.into_iter().map(|(_, peer)| peer).collect() )
+	}
+
+	/// Returns the index and the score of the peer with the lowest score, or `None` if there are no peers.
+	pub async fn worst( &self, peers: &[PublicKey] ) -> persistence::Result<Option<(usize, f64)>> {
+		let mut worst: Option<(usize, f64)> = None;
+		for (i, peer) in peers.iter().enumerate() {
+			let score = self.score( peer ).await?;
+			if worst.map(|(_, s)| score < s).unwrap_or(true) {
+				worst = Some(( i, score ));
 			}
 		}
+
+		Ok( worst )
 	}
 
-	async fn respond( this: Arc<NodeInner>, channel: &mut cadet::Channel, request_id: u32, result: ResponseResultType, response: &[u8] ) -> Result<()> {
-		
-		// TODO: Implement an interface in the gnunet::cadet::channel, that allows sending part by part,
-		//        so we don't have to construct a message first.
-		let mut message = Vec::with_capacity( 6 + response.len() );
-		message.push( MessageDirectionType::Response as u8 );
-		message.extend_from_slice( &request_id.to_le_bytes() );
-		message.push( result as u8 );
-		message.extend_from_slice( response );
+	/// Decays a score that was last updated at `updated`, in milliseconds since the UNIX epoch, to what it is now.
+	fn decay( &self, score: f64, updated: i64 ) -> f64 {
+		let elapsed = (peer::now() - updated).max(0) as f64;
+		score * 0.5f64.powf( elapsed / self.half_life )
+	}
+}
 
-		channel.send( cadet::PRIORITY_PREFERENCES_BEST_EFFORT, &*message ).await
-			.map_err(|e| Error::Gnunet(e.into()))?;
+impl PeerSession {
+
+	/// Starts a new session with the peer, and stores it.
+	/// What is sent and received in the session is counted in the given traffic of the channel as well.
+	/// The span of the session goes in the given one, which is the span of the node.
+	async fn start( persistence: &persistence::Handle, address: PublicKey, traffic: &Arc<Traffic>, span: &Span ) -> Result<Self> {
+		let id = persistence.get_peer( &address ).start_session().await?;
+		let config = config::get();
+
+		Ok( Self {
+			span: info_span!(parent: span, "peer", address = %address, session = id),
+			address,
+			id,
+			started: peer::now() as _,
+			messages: AtomicU64::new( 0 ),
+			bytes: AtomicU64::new( 0 ),
+			bytes_sent: AtomicU64::new( 0 ),
+			traffic: traffic.clone(),
+			malformed: AtomicU64::new( 0 ),
+			timeouts: AtomicU64::new( 0 ),
+			latency: AtomicU64::new( 0 ),
+			hello_sent: AtomicBool::new( false ),
+			event_limit: std::sync::Mutex::new( TokenBucket::new( config.peer_event_burst ) ),
+			request_limit: std::sync::Mutex::new( TokenBucket::new( config.peer_request_burst ) ),
+			rate_limited: AtomicU64::new( 0 ),
+			version: Mutex::new( None )
+		})
+	}
 
-		Ok(())
+	/// Whether the peer speaks a version of the protocol that has the given feature.
+	/// Peers that haven't said hello are assumed to speak our version.
+	async fn speaks( &self, feature: impl Fn( &ProtocolVersion ) -> bool ) -> bool {
+		self.version.lock().await.as_ref().map( feature ).unwrap_or( true )
+	}
+
+	/// Whether the peer has said hello with a version of the protocol that has the given feature.
+	/// Unlike `speaks`, this isn't assumed before the peer has said hello, for the messages that are sent unasked, which older peers would take for malformed ones.
+	async fn confirms( &self, feature: impl Fn( &ProtocolVersion ) -> bool ) -> bool {
+		self.version.lock().await.as_ref().map( feature ).unwrap_or( false )
+	}
+
+	/// Takes the time that the peer took to respond to a request into account, in its average latency.
+	/// Recent responses weigh the most, so that a parent that becomes slow is no longer asked first.
+	fn record_latency( &self, elapsed: Duration ) {
+		let millis = ( elapsed.as_millis() as u64 ).max( 1 );
+		let _ = self.latency.fetch_update( Ordering::AcqRel, Ordering::Acquire, |previous| match previous {
+			0 => Some( millis ),
+			previous => Some( ( previous * 3 + millis ) / 4 )
+		});
+	}
+
+	/// Stores the counters of the session.
+	/// Errors are only reported, because they shouldn't interrupt the session.
+	async fn store( &self, persistence: &persistence::Handle, ended: bool ) {
+		let counters = peer::Counters {
+			messages: self.messages.load( Ordering::Acquire ),
+			bytes: self.bytes.load( Ordering::Acquire ),
+			malformed: self.malformed.load( Ordering::Acquire ),
+			timeouts: self.timeouts.load( Ordering::Acquire )
+		};
+
+		if let Err(e) = persistence.get_peer( &self.address ).update_session( self.id, &counters, ended ).await {
+			warn!(parent: &self.span, "Unable to store the statistics of peer: {}", e);
+		}
 	}
 }
 
@@ -525,6 +3458,13 @@ impl fmt::Display for Error {
 			Self::MessageMalformed(e) => write!(f, "malformed message: {}", e),
 			Self::Gnunet(e) => write!(f, "gnunet issue: {}", e),
 			Self::Persistence(e) => write!(f, "persistence issue: {}", e),
+			Self::NoResponse => write!(f, "no peer responded to the request"),
+			Self::GenesisMissing => write!(f, "the genesis event of the channel is missing"),
+			Self::GenesisChanged => write!(f, "the parameters of the channel's genesis event have changed"),
+			Self::IncompatibleVersion(v) => write!(f, "incompatible protocol version {}, we speak {}", v, PROTOCOL_VERSION),
+			Self::PeerBlocked => write!(f, "the peer is blocked because it has misbehaved"),
+			Self::RateLimitExceeded => write!(f, "the peer kept sending messages faster than allowed"),
+			Self::NotConnected => write!(f, "not connected to the swarm"),
 			Self::Internal(e) => write!(f, "internal issue: {}", e)
 		}
 	}
@@ -553,6 +3493,12 @@ impl From<persistence::Error> for Error {
 	}
 }
 
+impl From<rusqlite::Error> for Error {
+	fn from( other: rusqlite::Error ) -> Self {
+		Self::Persistence( persistence::Error::Database(other) )
+	}
+}
+
 /// Care should be taken that deserialization errors from malformed messages don't get transformed into persistence errors.
 impl From<bincode::Error> for Error {
 	fn from( other: bincode::Error ) -> Self {
@@ -564,30 +3510,4 @@ impl From<MessageMalformedError> for Error {
 	fn from( other: MessageMalformedError ) -> Self {
 		Self::MessageMalformed(other)
 	}
-}
-
-impl fmt::Display for MessageMalformedError {
-	fn fmt( &self, f: &mut fmt::Formatter<'_> ) -> fmt::Result {
-		match self {
-			Self::DeserializationIssue(e, desc) => write!(f, "deserialization issue while parsing {}: {}", desc, e),
-			Self::InvalidBoolean(id, desc) => write!(f, "invalid boolean found for {}: {}", desc, id),
-			Self::InvalidEventId(id) => write!(f, "invalid event ID: {}", id),
-			Self::InvalidHash(desc) => write!(f, "invalid checksum for {}", desc),
-			Self::InvalidSignature(desc) => write!(f, "signature verification failed for {}", desc),
-			Self::InvalidTypeId(id, desc) => write!(f, "invalid type id found for {}: {}", desc, id),
-			Self::InvalidUtf8(e, desc) => write!(f, "invalid UTF-8 for {}: {}", desc, e),
-			Self::MissingData(desc) => write!(f, "missing data for {}", desc),
-			Self::UnknownPublisher(address) => write!(f, "unknown publisher address: {}", address.to_string())
-		}
-	}
-}
-
-impl std::error::Error for MessageMalformedError {
-	fn source( &self ) -> Option<&(dyn std::error::Error + 'static)> {
-		match self {
-			Self::DeserializationIssue(e, _) => Some(e),
-			Self::InvalidUtf8(e, _) => Some(e),
-			other => None
-		}
-	}
 }
\ No newline at end of file
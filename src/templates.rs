@@ -0,0 +1,126 @@
+//! The templates of the web interface.
+//!
+//! The templates are embedded in the binary, but any of them can be overridden by a file in the directory given by the `QUARTZNET_TEMPLATES` environment variable, or by `template_dir` in the configuration file.
+//! This way the look of the web interface can be changed without rebuilding.
+//! In debug builds, or when `watch_templates` is set, that directory is watched for changes, and the templates are reloaded when anything changes.
+
+use std::{
+	fs,
+	path::Path,
+	sync::{Arc, RwLock},
+	time::{Duration, SystemTime}
+};
+
+use async_std::task;
+use tera::{Context, Tera};
+use tracing::{info, warn};
+
+use crate::{
+	assets::Assets,
+	config
+};
+
+
+
+pub struct Templates {
+	tera: RwLock<Tera>,
+	assets: Assets
+}
+
+
+
+impl Templates {
+
+	/// Loads the templates, with the ones in the configured directory taking the place of the embedded ones.
+	pub fn load() -> tera::Result<Self> {
+		let assets = Assets::templates();
+		let tera = load_assets( &assets )?;
+
+		Ok( Self {
+			tera: RwLock::new( tera ),
+			assets
+		})
+	}
+
+	pub fn render( &self, template_name: &str, context: &Context ) -> tera::Result<String> {
+		self.tera.read().unwrap().render( template_name, context )
+	}
+
+	/// Reloads the templates whenever a file in the template directory changes, for as long as the templates are in use.
+	/// This only happens in debug builds or when `watch_templates` is set, and only if a template directory has been configured.
+	pub fn watch( self: &Arc<Self> ) {
+		if !cfg!(debug_assertions) && !config::get().watch_templates { return }
+		let dir = match self.assets.dir() {
+			None => return,
+			Some(d) => d.to_owned()
+		};
+
+		let weak = Arc::downgrade( self );
+		task::spawn(async move {
+			let mut last_modified = latest_modification( &dir );
+			loop {
+				task::sleep( Duration::from_millis( config::TEMPLATE_WATCH_INTERVAL ) ).await;
+				let this = match weak.upgrade() {
+					None => break,
+					Some(t) => t
+				};
+
+				let mut modified = latest_modification( &dir );
+				if modified == last_modified { continue }
+				// Waits for the changes to settle, so that files that are being saved aren't loaded half-written.
+				loop {
+					task::sleep( Duration::from_millis( config::TEMPLATE_RELOAD_DELAY ) ).await;
+					let settled = latest_modification( &dir );
+					if settled == modified { break }
+					modified = settled;
+				}
+				last_modified = modified;
+
+				// The old templates stay in use if the new ones contain errors.
+				match load_assets( &this.assets ) {
+					Err(e) => warn!("Unable to reload the templates: {}", e),
+					Ok(tera) => {
+						*this.tera.write().unwrap() = tera;
+						info!("Templates reloaded.");
+					}
+				}
+			}
+		});
+	}
+}
+
+/// Loads all templates, named by their path relative to the template directory, like Tera does.
+fn load_assets( assets: &Assets ) -> tera::Result<Tera> {
+	let mut templates = Vec::new();
+	for name in assets.list() {
+		let content = match assets.get( &name ) {
+			None => continue,
+			Some(c) => c
+		};
+		match String::from_utf8( content.into_owned() ) {
+			Err(_) => warn!("Template {} is not valid UTF-8, skipping it.", name),
+			Ok(content) => templates.push(( name, content ))
+		}
+	}
+
+	let mut tera = Tera::default();
+	tera.add_raw_templates( templates )?;
+	Ok( tera )
+}
+
+/// Returns the time of the latest modification of any file in the given directory or its subdirectories.
+fn latest_modification( dir: &Path ) -> Option<SystemTime> {
+	let mut latest = fs::metadata( dir ).and_then(|m| m.modified()).ok();
+
+	for entry in fs::read_dir( dir ).ok()?.flatten() {
+		let path = entry.path();
+		let modified = if path.is_dir() {
+			latest_modification( &path )
+		} else {
+			entry.metadata().and_then(|m| m.modified()).ok()
+		};
+		latest = latest.max( modified );
+	}
+
+	latest
+}
@@ -0,0 +1,40 @@
+//! Scales down the images that are attached to posts, so that feeds don't need to load them at their full size.
+//!
+//! Smaller versions are made on demand, in one of the widths of `config::PREVIEW_IMAGE_WIDTHS`.
+//! They are cached in the database by the hash of the attachment and the width.
+
+use image::{imageops::FilterType, ImageOutputFormat};
+
+
+
+/// The quality of the JPEG images that images without transparency are scaled into.
+const JPEG_QUALITY: u8 = 80;
+
+
+
+/// Whether smaller versions can be made of attachments with the given MIME type.
+/// Animated GIFs would lose their animation, and SVG images scale by themselves, so those are always served as they are.
+pub fn is_supported( mime_type: &str ) -> bool {
+	matches!( mime_type, "image/png" | "image/jpeg" | "image/webp" )
+}
+
+/// Scales the image down to the given width, keeping its aspect ratio.
+/// Returns the MIME type and the data of the smaller image, or `None` if the image isn't wider than that already.
+pub fn scale( data: &[u8], width: u32 ) -> image::ImageResult<Option<(&'static str, Vec<u8>)>> {
+
+	let image = image::load_from_memory( data )?;
+	if image.width() <= width {
+		return Ok(None)
+	}
+
+	let scaled = image.resize( width, u32::MAX, FilterType::Triangle );
+	let mut output = Vec::new();
+	if scaled.color().has_alpha() {
+		scaled.write_to( &mut output, ImageOutputFormat::Png )?;
+		Ok( Some(( "image/png", output )) )
+	}
+	else {
+		scaled.write_to( &mut output, ImageOutputFormat::Jpeg( JPEG_QUALITY ) )?;
+		Ok( Some(( "image/jpeg", output )) )
+	}
+}
@@ -0,0 +1,170 @@
+//! The transports over which the nodes of a swarm reach each other.
+//!
+//! The swarm only needs to open a channel to a peer by its address, accept the channels that peers open to it, and send and receive messages over them.
+//! That is what the `Transport` and `PeerChannel` traits describe, so that the swarm doesn't need to know which network it runs on.
+//! CADET is the transport that is used by default.
+//! Other backends, like one that links the nodes within a single process for the integration tests, only need to implement these traits.
+
+use std::sync::Arc;
+
+use async_std::sync::Mutex;
+use async_trait::async_trait;
+use gnunet::{
+	cadet,
+	crypto::HashCode,
+	identity::PublicKey
+};
+use lazy_static::lazy_static;
+
+pub mod loopback;
+
+
+
+/// Opens channels to peers, and accepts the channels that peers open to us.
+#[async_trait]
+pub trait Transport: Send + Sync {
+
+	/// Opens a channel to the peer with the given address.
+	async fn connect( &self, address: &PublicKey ) -> gnunet::Result<Box<dyn PeerChannel>>;
+
+	/// Waits for the next peer that opens a channel to us.
+	/// Returns `None` once the transport stops listening.
+	async fn listen( &self ) -> gnunet::Result<Option<Box<dyn PeerChannel>>>;
+}
+
+/// How urgent a message is.
+/// When the connection is busy, transports that have priorities send the urgent messages first.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Priority {
+	/// Requests, responses and everything else that someone is waiting for.
+	Normal,
+	/// Messages that can wait for the others, like the rebroadcasts of a channel that has gone over its upload limit.
+	Background
+}
+
+/// A channel with a single peer, over which messages are sent as a whole.
+#[async_trait]
+pub trait PeerChannel: Send + Sync {
+
+	/// The address of the peer at the other end.
+	fn peer( &self ) -> &PublicKey;
+
+	/// Identifies the channel among the other channels of the same transport.
+	fn id( &self ) -> u32;
+
+	/// Sends a message, which transports without priorities ignore the priority of.
+	async fn send( &mut self, message: &[u8], priority: Priority ) -> gnunet::Result<()>;
+
+	/// Returns a receiver for the messages of the peer.
+	/// Waiting for a message with it doesn't need the channel itself, so that messages can still be sent in the meantime.
+	fn receiver( &self ) -> Box<dyn PeerReceiver>;
+
+	/// Closes the channel, which ends the receivers of both ends.
+	async fn close( &mut self ) -> gnunet::Result<()>;
+}
+
+/// Receives the messages that arrive over a channel.
+#[async_trait]
+pub trait PeerReceiver: Send + Sync {
+
+	/// Waits for the next message.
+	/// Returns `None` once the channel has been closed.
+	async fn receive( &self ) -> Option<Vec<u8>>;
+}
+
+/// The transport over the CADET service of gnunet.
+pub struct CadetTransport {
+	handle: Arc<Mutex<cadet::Handle>>,
+	/// The port on which the channels of other peers are accepted, once we've started listening.
+	port: Mutex<Option<cadet::Port>>
+}
+
+struct CadetChannel {
+	peer: PublicKey,
+	channel: cadet::Channel
+}
+
+struct CadetReceiver( cadet::Receiver );
+
+
+
+lazy_static! {
+	/// The CADET port that QuartzNet nodes listen on.
+	pub static ref QUARTZ_PORT: HashCode = HashCode::generate( "QuartzNet".as_bytes() );
+}
+
+
+
+impl CadetTransport {
+
+	pub fn new( handle: Arc<Mutex<cadet::Handle>> ) -> Self {
+		Self {
+			handle,
+			port: Mutex::new( None )
+		}
+	}
+}
+
+#[async_trait]
+impl Transport for CadetTransport {
+
+	async fn connect( &self, address: &PublicKey ) -> gnunet::Result<Box<dyn PeerChannel>> {
+		let channel = self.handle.lock().await.channel_connect( address, &QUARTZ_PORT ).await?;
+		Ok( Box::new( CadetChannel {
+			peer: address.clone(),
+			channel
+		}) )
+	}
+
+	async fn listen( &self ) -> gnunet::Result<Option<Box<dyn PeerChannel>>> {
+		let mut port = self.port.lock().await;
+		if port.is_none() {
+			*port = Some( self.handle.lock().await.open_port( &QUARTZ_PORT ).await? );
+		}
+
+		Ok( port.as_mut().unwrap().accept().await.map(|channel| {
+			Box::new( CadetChannel {
+				peer: channel.peer().clone(),
+				channel
+			}) as Box<dyn PeerChannel>
+		}) )
+	}
+}
+
+#[async_trait]
+impl PeerChannel for CadetChannel {
+
+	fn peer( &self ) -> &PublicKey {
+		&self.peer
+	}
+
+	fn id( &self ) -> u32 {
+		self.channel.id()
+	}
+
+	async fn send( &mut self, message: &[u8], priority: Priority ) -> gnunet::Result<()> {
+		let preferences = match priority {
+			Priority::Normal => cadet::PRIORITY_PREFERENCES_BEST_EFFORT,
+			Priority::Background => cadet::PRIORITY_PREFERENCES_BACKGROUND
+		};
+		self.channel.send( preferences, message ).await?;
+		Ok(())
+	}
+
+	fn receiver( &self ) -> Box<dyn PeerReceiver> {
+		Box::new( CadetReceiver( self.channel.clone_receiver() ) )
+	}
+
+	async fn close( &mut self ) -> gnunet::Result<()> {
+		self.channel.destroy().await?;
+		Ok(())
+	}
+}
+
+#[async_trait]
+impl PeerReceiver for CadetReceiver {
+
+	async fn receive( &self ) -> Option<Vec<u8>> {
+		self.0.receive().await.map(|message| message.payload.into())
+	}
+}
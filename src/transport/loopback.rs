@@ -0,0 +1,158 @@
+//! A transport that links nodes within the same process, without any network in between.
+//!
+//! Every node joins the loopback network under an address of its own, and the other nodes can open channels to it by that address.
+//! The integration tests run their swarms over it, so that they don't need a running gnunet peer.
+
+use std::{
+	io,
+	sync::{
+		atomic::{AtomicU32, Ordering},
+		Arc,
+		Mutex
+	}
+};
+
+use async_std::channel::{self, Receiver, Sender};
+use async_trait::async_trait;
+use gnunet::identity::PublicKey;
+
+use crate::transport::{PeerChannel, PeerReceiver, Priority, Transport};
+
+
+
+/// The nodes that can reach each other.
+pub struct LoopbackNetwork {
+	/// The nodes that have joined, with the queue that the channels that are opened to them are put in.
+	listeners: Mutex<Vec<(PublicKey, Sender<Box<dyn PeerChannel>>)>>,
+	next_channel_id: AtomicU32
+}
+
+/// The transport of a node that has joined a loopback network.
+/// The node leaves the network when this is dropped.
+pub struct LoopbackTransport {
+	network: Arc<LoopbackNetwork>,
+	address: PublicKey,
+	incoming: Receiver<Box<dyn PeerChannel>>
+}
+
+/// One end of a channel between two nodes.
+struct LoopbackChannel {
+	peer: PublicKey,
+	id: u32,
+	sender: Sender<Vec<u8>>,
+	receiver: Receiver<Vec<u8>>
+}
+
+struct LoopbackReceiver( Receiver<Vec<u8>> );
+
+
+
+impl LoopbackNetwork {
+
+	pub fn new() -> Arc<Self> {
+		Arc::new( Self {
+			listeners: Mutex::new( Vec::new() ),
+			next_channel_id: AtomicU32::new( 1 )
+		})
+	}
+
+	/// Adds a node with the given address to the network, and returns its transport.
+	pub fn join( self: &Arc<Self>, address: PublicKey ) -> Arc<LoopbackTransport> {
+		let (sender, receiver) = channel::unbounded();
+		self.listeners.lock().unwrap().push(( address.clone(), sender ));
+
+		Arc::new( LoopbackTransport {
+			network: self.clone(),
+			address,
+			incoming: receiver
+		})
+	}
+}
+
+impl LoopbackTransport {
+
+	/// The address under which the other nodes reach this one.
+	pub fn address( &self ) -> &PublicKey {
+		&self.address
+	}
+}
+
+#[async_trait]
+impl Transport for LoopbackTransport {
+
+	async fn connect( &self, address: &PublicKey ) -> gnunet::Result<Box<dyn PeerChannel>> {
+		let listener = self.network.listeners.lock().unwrap().iter()
+			.find(|(a, _)| a == address)
+			.map(|(_, l)| l.clone());
+		let listener = match listener {
+			None => return Err( io::Error::new( io::ErrorKind::ConnectionRefused, format!("no node with address {} on the loopback network", address) ).into() ),
+			Some(l) => l
+		};
+
+		let id = self.network.next_channel_id.fetch_add( 1, Ordering::Relaxed );
+		let (to_peer, from_us) = channel::unbounded();
+		let (to_us, from_peer) = channel::unbounded();
+		let far_end = LoopbackChannel {
+			peer: self.address.clone(),
+			id,
+			sender: to_us,
+			receiver: from_us
+		};
+		listener.send( Box::new( far_end ) ).await
+			.map_err(|_| io::Error::new( io::ErrorKind::ConnectionRefused, format!("node {} has left the loopback network", address) ))?;
+
+		Ok( Box::new( LoopbackChannel {
+			peer: address.clone(),
+			id,
+			sender: to_peer,
+			receiver: from_peer
+		}) )
+	}
+
+	async fn listen( &self ) -> gnunet::Result<Option<Box<dyn PeerChannel>>> {
+		Ok( self.incoming.recv().await.ok() )
+	}
+}
+
+impl Drop for LoopbackTransport {
+	fn drop( &mut self ) {
+		self.network.listeners.lock().unwrap().retain(|(a, _)| *a != self.address);
+	}
+}
+
+#[async_trait]
+impl PeerChannel for LoopbackChannel {
+
+	fn peer( &self ) -> &PublicKey {
+		&self.peer
+	}
+
+	fn id( &self ) -> u32 {
+		self.id
+	}
+
+	async fn send( &mut self, message: &[u8], _priority: Priority ) -> gnunet::Result<()> {
+		self.sender.send( message.to_vec() ).await
+			.map_err(|_| io::Error::new( io::ErrorKind::BrokenPipe, "the loopback channel has been closed" ))?;
+		Ok(())
+	}
+
+	fn receiver( &self ) -> Box<dyn PeerReceiver> {
+		Box::new( LoopbackReceiver( self.receiver.clone() ) )
+	}
+
+	async fn close( &mut self ) -> gnunet::Result<()> {
+		// Closing both queues ends the receivers at both ends, like a destroyed CADET channel does.
+		self.sender.close();
+		self.receiver.close();
+		Ok(())
+	}
+}
+
+#[async_trait]
+impl PeerReceiver for LoopbackReceiver {
+
+	async fn receive( &self ) -> Option<Vec<u8>> {
+		self.0.recv().await.ok()
+	}
+}
@@ -0,0 +1,108 @@
+//! The settings of how the node takes part in the swarms of its channels, which can be changed on the settings page while it runs.
+//!
+//! They are stored as local settings in the database, and are loaded again whenever a swarm checks its connection, so that a change reaches every swarm within `connection_check_interval`.
+//! The settings page applies a change to the swarms that are connected right away as well, see `swarm::Node::tune`.
+//! A relay power or parent count in the configuration file takes precedence over the stored one, so that a node that is configured by hand isn't overruled from the web interface.
+
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::{
+	config,
+	persistence,
+	setup,
+	subscriptions
+};
+
+
+
+/// How the node takes part in the swarms of its channels.
+#[derive(Clone, Serialize)]
+pub struct SwarmTuning {
+	/// The power of two of the number of children that every swarm accepts.
+	pub relay_power: u8,
+	/// The number of parents that every swarm stays connected to.
+	pub parent_count: u8,
+	/// Whether rebroadcasts always make way for requests and responses, rather than only once a channel has gone over its upload limit.
+	pub background_rebroadcasts: bool,
+	/// The number of seconds between the checks of whether the connection to a swarm is still alive.
+	pub connection_check_interval: u64,
+	/// The number of seconds to wait before searching for a swarm again, after the first failure, which doubles with every failure after that.
+	pub reconnect_min_delay: u64,
+	/// The maximum number of seconds to wait before searching for a swarm again.
+	pub reconnect_max_delay: u64
+}
+
+
+
+impl SwarmTuning {
+
+	/// Loads the settings that are in use.
+	pub async fn load( db: &persistence::Handle ) -> persistence::Result<Self> {
+		Ok( Self {
+			relay_power: subscriptions::load_relay_power( db ).await?,
+			parent_count: match config::get().parent_count {
+				Some(count) => count,
+				None => load_parsed( db, setup::SETTING_PARENT_COUNT, config::PARENT_COUNT ).await?
+			},
+			background_rebroadcasts: load_parsed( db, setup::SETTING_BACKGROUND_REBROADCASTS, false ).await?,
+			connection_check_interval: load_parsed( db, setup::SETTING_CONNECTION_CHECK_INTERVAL, config::CONNECTION_CHECK_INTERVAL ).await?,
+			reconnect_min_delay: load_parsed( db, setup::SETTING_RECONNECT_MIN_DELAY, config::RECONNECT_MIN_DELAY ).await?,
+			reconnect_max_delay: load_parsed( db, setup::SETTING_RECONNECT_MAX_DELAY, config::RECONNECT_MAX_DELAY ).await?
+		})
+	}
+
+	/// Stores the settings, after they have been checked.
+	pub async fn store( &self, db: &persistence::Handle ) -> persistence::Result<()> {
+		db.store_setting( setup::SETTING_RELAY_POWER, &self.relay_power.to_string() ).await?;
+		db.store_setting( setup::SETTING_PARENT_COUNT, &self.parent_count.to_string() ).await?;
+		db.store_setting( setup::SETTING_BACKGROUND_REBROADCASTS, &self.background_rebroadcasts.to_string() ).await?;
+		db.store_setting( setup::SETTING_CONNECTION_CHECK_INTERVAL, &self.connection_check_interval.to_string() ).await?;
+		db.store_setting( setup::SETTING_RECONNECT_MIN_DELAY, &self.reconnect_min_delay.to_string() ).await?;
+		db.store_setting( setup::SETTING_RECONNECT_MAX_DELAY, &self.reconnect_max_delay.to_string() ).await?;
+		Ok(())
+	}
+
+	/// Checks whether the settings can be used.
+	/// Returns a description of the first one that can't.
+	pub fn check( &self ) -> Result<(), String> {
+		if self.relay_power > config::RELAY_POWER_MAX {
+			return Err( format!("The relay power can be at most {}.", config::RELAY_POWER_MAX) )
+		}
+		if self.parent_count == 0 || self.parent_count > config::PARENT_COUNT_MAX {
+			return Err( format!("The number of parents needs to be between 1 and {}.", config::PARENT_COUNT_MAX) )
+		}
+		if self.connection_check_interval == 0 {
+			return Err( "The connection check interval needs to be positive.".to_owned() )
+		}
+		if self.reconnect_min_delay == 0 || self.reconnect_max_delay < self.reconnect_min_delay {
+			return Err( "The reconnect delays need to be positive, and the maximum can't be lower than the minimum.".to_owned() )
+		}
+		Ok(())
+	}
+}
+
+impl Default for SwarmTuning {
+
+	/// The settings of a node on which nothing has been set on the settings page.
+	fn default() -> Self {
+		let config = config::get();
+		Self {
+			relay_power: config.relay_power.unwrap_or( config::RELAY_POWER ),
+			parent_count: config.parent_count.unwrap_or( config::PARENT_COUNT ),
+			background_rebroadcasts: false,
+			connection_check_interval: config::CONNECTION_CHECK_INTERVAL,
+			reconnect_min_delay: config::RECONNECT_MIN_DELAY,
+			reconnect_max_delay: config::RECONNECT_MAX_DELAY
+		}
+	}
+}
+
+/// Loads a setting, or returns the given default if it hasn't been set, or has been set to something that can't be parsed.
+async fn load_parsed<T: FromStr>( db: &persistence::Handle, key: &str, default: T ) -> persistence::Result<T> {
+	Ok( match db.load_setting( key ).await? {
+		None => default,
+		Some(value) => value.parse().unwrap_or( default )
+	})
+}
@@ -1,22 +1,63 @@
-use actix_web::{error, get, http::header, HttpResponse, HttpRequest, post, web};
+use actix_multipart::Multipart;
+use actix_web::{get, http::header, HttpMessage, HttpResponse, HttpRequest, post, web};
+use futures::{
+	future,
+	stream::{self, StreamExt}
+};
 use gnunet::{
 	self,
 	crypto::HashCode,
 	identity::*
 };
+use qrcode::QrCode;
 use serde::*;
 use rusqlite;
 use tera;
+use tracing::{error, warn};
 
 use std::{
 	cmp::min,
 	collections::HashMap,
+	convert::TryInto,
 	io,
+	path::PathBuf,
 	sync::Arc,
 	time::{SystemTime, UNIX_EPOCH}
 };
 
-use crate::persistence::{self, timeline};
+use crate::activitypub::{self, Signer};
+use crate::archive::{self, BundleKeys, BundleManifest, WarcWriter};
+use crate::assets;
+use crate::auth;
+use crate::config;
+use crate::daemon;
+use crate::directory;
+use crate::encryption::InviteCode;
+use crate::event::{ChannelEventType, ModerationAction, PublisherEventType, COMMENT_MAX_LEN, GENESIS_EVENT_ID, PINNED_POSTS_MAX, REACTION_MAX_LEN};
+use crate::feed_import;
+use crate::health::{self, HealthReport};
+use crate::identicon;
+use crate::language::{Language, LANGUAGES};
+use crate::maintenance;
+use crate::message::{EVENTS_REQUEST_MAX_COUNT, PROFILE_DESCRIPTION_MAX_LEN, PROTOCOL_VERSION};
+use crate::metrics;
+use crate::micropub;
+use crate::persistence::{self, audit::AuditAction, batch::BatchAction, comment::StoredComment, directory::DirectoryEntry, notification::{Notification, NotificationKind}, peer, post_defaults::PostDefaults, system_post::SystemPost, thumbnail::Thumbnail, timeline::{self, PostFilter}};
+use crate::page_cache::{self, Page};
+use crate::preview;
+use crate::preview_cache;
+use crate::reload;
+use crate::render::{self, RenderContext};
+use crate::runtime;
+use crate::scheduler;
+use crate::services;
+use crate::setup::{self, ContributionProfile};
+use crate::share::ShareLink;
+use crate::static_site;
+use crate::subscriptions;
+use crate::thumbnail;
+use crate::tuning::SwarmTuning;
+use crate::web_error::{self, WebError};
 use crate::Globals;
 use crate::post::*;
 
@@ -25,60 +66,122 @@ use crate::post::*;
 #[derive(Serialize)]
 pub struct Blog {
 	name: String,
-	address: String
+	address: String,
+	/// The number of peers that are connected to us in the swarm of the channel right now.
+	follower_count: usize
+}
+
+#[derive(Serialize)]
+pub struct FollowedChannel {
+	address: String,
+	title: Option<String>,
+	/// In seconds since the UNIX epoch, for tera's date filter.
+	last_post_timestamp: Option<u64>,
+	/// Whether we are connected to the swarm of the channel right now.
+	connected: bool
 }
 
 #[derive(Serialize)]
 pub struct HomepageContext {
-	follower_count: u32,
-	following_count: u32,
+	/// The number of followers of all our channels together.
+	follower_count: usize,
+	following_count: usize,
 
-	following: Vec<Blog>,
-	own_blogs: Vec<String>
+	following: Vec<FollowedChannel>,
+	own_blogs: Vec<Blog>
 }
 
 #[get("/")]
-pub async fn homepage(g: web::Data<Arc<Globals>>, _req: HttpRequest) -> error::Result<HttpResponse> {
+pub async fn homepage(g: web::Data<Arc<Globals>>, _req: HttpRequest) -> web_error::Result<HttpResponse> {
 
-	#[derive(Serialize)]
-	struct Blog {
-		name: String,
-		address: String
+	if setup::is_required( g.services.clone() ).await? {
+		return Ok( HttpResponse::Found().append_header((header::LOCATION, "/setup")).finish() )
 	}
 
-	let p = persistence::Handle::connect( g.gnunet.clone() ).await.map_err(|e| persistence::Error::Database(e))?;
+	let p = g.connect_database().await?;
+	let subscriptions = g.subscriptions.read().await;
 	let my_timelines = p.list_my_timelines().await?;
 	let mut blogs = Vec::with_capacity( my_timelines.len() );
 
-	let mut identity_service = gnunet::identity::Handle::connect( g.gnunet.clone() ).await
-		.map_err(|_| error::ErrorInternalServerError("Gnunet identity sere.into::<persistence::Error>()vice not available."))?;
-
 	for timeline in my_timelines {
-		let name = timeline.get_my_ego().await?.unwrap();
-		let priv_key = identity_service.lookup( &name ).await
-			.map_err(|e| { eprintln!("Unable to find ego with name \"{}\" due to error: {}", &name, e); error::ErrorInternalServerError("Internal server error") })?
-			.ok_or_else(|| { eprintln!("Unable to find ego with name \"{}\".", &name); error::ErrorInternalServerError("Internal server error") })?;
-		let pub_key = priv_key.extract_public().unwrap();
+		// The ego of a timeline that has just been removed, may be gone already.
+		let name = match timeline.get_my_ego().await? {
+			None => continue,
+			Some(n) => n
+		};
+		let priv_key = g.services.lookup_ego( &name ).await?;
+		let pub_key = ego_address( &priv_key )?;
 
+		// Our followers are the peers that have connected to us to receive the posts of the channel.
+		let follower_count = match subscriptions.as_ref().and_then(|s| s.node( &pub_key )) {
+			None => 0,
+			Some(node) => node.child_count().await
+		};
 		blogs.push( Blog {
 			name,
-			address: pub_key.to_string()
+			address: pub_key.to_string(),
+			follower_count
 		} )
 	}
 
-	let mut context = tera::Context::new();
-	context.insert("own_blogs", &blogs);
+	let mut following = Vec::new();
+	for address in p.list_subscriptions().await? {
+		if blogs.iter().any(|b| b.address == address.to_string()) { continue }
+		let channel = match p.clone().get_channel( &address ).await? {
+			None => continue,
+			Some(c) => c
+		};
+		following.push( FollowedChannel {
+			title: channel.fetch_profile().await?.map(|profile| profile.base.title).filter(|t| !t.is_empty()),
+			last_post_timestamp: channel.load_last_post_timestamp().await?.map(|t| t / 1000),
+			connected: subscriptions.as_ref().and_then(|s| s.node( &address )).map(|n| n.is_connected()).unwrap_or(false),
+			address: address.to_string()
+		});
+	}
+	drop( subscriptions );
+
+	let homepage = HomepageContext {
+		follower_count: blogs.iter().map(|b| b.follower_count).sum(),
+		following_count: following.len(),
+		following,
+		own_blogs: blogs
+	};
+
+	#[derive(Serialize)]
+	struct LanguageView {
+		code: &'static str,
+		name: &'static str
+	}
 
-	let html = g.tera.render("homepage.html", &context)
-		.map_err(|e| { eprintln!("Template error: {}", e); error::ErrorInternalServerError("Template error") } )?;
+	let mut context = tera::Context::from_serialize( &homepage )
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	context.insert("language", load_language( &p ).await?.code());
+	context.insert("languages", &LANGUAGES.iter().map(|l| LanguageView {
+		code: l.code(),
+		name: l.name()
+	}).collect::<Vec<_>>());
+
+	let html = g.templates.render("homepage.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
 	Ok(HttpResponse::Ok().content_type("text/html").body(html))
 }
 
 #[get("/channel/new")]
-pub async fn channel_new(g: web::Data<Arc<Globals>>) -> error::Result<HttpResponse> {
+pub async fn channel_new(g: web::Data<Arc<Globals>>) -> web_error::Result<HttpResponse> {
+	render_channel_new( &g, None, None )
+}
 
-	let html = g.tera.render("blog-new.html", &tera::Context::new())
-		.map_err(|e| { eprintln!("Template error: {}", e); error::ErrorInternalServerError("Template error") } )?;
+/// Renders the form to create a new channel.
+/// If the name that was given belongs to an ego without a channel, `adopt` is that name, so that its channel can be created instead.
+fn render_channel_new( g: &Globals, error: Option<&str>, adopt: Option<&FormData> ) -> web_error::Result<HttpResponse> {
+
+	let mut context = tera::Context::new();
+	context.insert("error", &error);
+	context.insert("adopt_name", &adopt.map(|f| &f.name));
+	context.insert("adopt_private", &adopt.map(|f| f.private.is_some()).unwrap_or(false));
+
+	let html = g.templates.render("blog-new.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
 
 	Ok(HttpResponse::Ok().content_type("text/html").body(html))
 }
@@ -87,27 +190,37 @@ pub async fn channel_new(g: web::Data<Arc<Globals>>) -> error::Result<HttpRespon
 
 #[derive(Deserialize)]
 pub struct FormData {
-	name: String
+	name: String,
+	/// Set if the "private" checkbox is checked.
+	private: Option<String>
 }
 
 #[post("/channel/new")]
-pub async fn channel_new_post<'s>(g: web::Data<Arc<Globals>>, form: web::Form<FormData>) -> error::Result<HttpResponse> {
+pub async fn channel_new_post<'s>(g: web::Data<Arc<Globals>>, form: web::Form<FormData>) -> web_error::Result<HttpResponse> {
 	
-	let mut db = persistence::Handle::connect( g.gnunet.clone() ).await.map_err(|e| persistence::Error::Database(e))?;
+	let mut db = g.connect_database().await?;
 
-	let result = match db.create_channel( &form.name ).await {
+	let result = match db.create_channel( &form.name, form.private.is_none() ).await {
 		Err(e) => {
 			match e {
 				persistence::Error::AlreadyExists => {
-					Err( error::ErrorBadRequest( "An ego with that name already exists!" ) )
+					render_channel_new( &g, Some("An ego with that name already exists!"), None )
+				},
+				persistence::Error::EgoConflict(persistence::EgoConflict::InUse(_)) => {
+					render_channel_new( &g, Some("You already have a channel with that name."), None )
+				},
+				persistence::Error::EgoConflict(persistence::EgoConflict::Unused(_)) => {
+					render_channel_new( &g, Some("An ego with that name already exists, but it has no channel. Creating its channel may have been interrupted."), Some( &form ) )
 				},
 				err => {
-					eprintln!("Internal server error: {}", err);
-					Err( error::ErrorConflict( "Internal server error occurred." ) )
+					error!("Internal server error: {}", err);
+					Err( WebError::conflict( "Internal server error occurred." ) )
 				}
 			}
 		},
-		Ok(_) => {
+		Ok(channel) => {
+			let address = channel.load_address().await?;
+			db.record_action( Some( &form.name ), AuditAction::ChannelCreated, &address.to_string() ).await?;
 			Ok( HttpResponse::Found().append_header((header::LOCATION, "/")).finish() )
 		}
 	};
@@ -115,134 +228,4622 @@ pub async fn channel_new_post<'s>(g: web::Data<Arc<Globals>>, form: web::Form<Fo
 	result
 }
 
+/// Creates the channel of an existing ego that doesn't have one yet.
+#[post("/channel/adopt")]
+pub async fn channel_adopt(g: web::Data<Arc<Globals>>, form: web::Form<FormData>) -> web_error::Result<HttpResponse> {
+
+	let mut db = g.connect_database().await?;
+
+	match db.adopt_channel( &form.name, form.private.is_none() ).await {
+		Err(persistence::Error::NotFound) => render_channel_new( &g, Some("There is no ego with that name."), None ),
+		Err(persistence::Error::EgoConflict(persistence::EgoConflict::InUse(_))) => {
+			render_channel_new( &g, Some("That ego already has a channel."), None )
+		},
+		Err(e) => Err( e.into() ),
+		Ok(channel) => {
+			let address = channel.load_address().await?;
+			db.record_action( Some( &form.name ), AuditAction::ChannelCreated, &address.to_string() ).await?;
+			let location = format!("/channel/feed/ego/{}", form.name);
+			Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+		}
+	}
+}
+
+#[derive(Serialize)]
+pub struct EgoView {
+	name: String,
+	address: String,
+	has_channel: bool
+}
+
 #[derive(Deserialize)]
-pub struct BlogFeedParams {
-	id: String,
-	id_type: String,
-	page: u32
+pub struct EgoNameParams {
+	name: String
 }
 
 #[derive(Deserialize)]
-pub struct BlogFeedIdParams {
-	id: String,
-	id_type: String
+pub struct EgoForm {
+	name: String
+}
+
+/// Lists the egos of gnunet, with which channels are published.
+#[get("/identities")]
+pub async fn identities(g: web::Data<Arc<Globals>>) -> web_error::Result<HttpResponse> {
+	render_identities( &g, None ).await
+}
+
+async fn render_identities( g: &Globals, error: Option<&str> ) -> web_error::Result<HttpResponse> {
+
+	let db = g.connect_database().await?;
+	let egos: Vec<EgoView> = db.list_egos().await?.into_iter().map(|e| EgoView {
+		name: e.name,
+		address: e.address.to_string(),
+		has_channel: e.has_channel
+	}).collect();
+
+	let mut context = tera::Context::new();
+	context.insert("egos", &egos);
+	context.insert("error", &error);
+
+	let html = g.templates.render("identities.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+/// Creates an ego without a channel, which can be adopted as a channel later.
+#[post("/identities/new")]
+pub async fn identity_new(g: web::Data<Arc<Globals>>, form: web::Form<EgoForm>) -> web_error::Result<HttpResponse> {
+
+	let name = form.name.trim();
+	if name.is_empty() {
+		return render_identities( &g, Some("An ego needs a name.") ).await
+	}
+	if !g.services.create_ego( name, PrivateKey::generate( KeyType::Eddsa ) ).await? {
+		return render_identities( &g, Some("An ego with that name already exists.") ).await
+	}
+
+	let db = g.connect_database().await?;
+	db.record_action( Some( name ), AuditAction::EgoCreated, name ).await?;
+	Ok( HttpResponse::SeeOther().append_header((header::LOCATION, "/identities")).finish() )
+}
+
+/// Gives an ego another name.
+/// Its channel, if it has one, is published with the new name from then on.
+#[post("/identities/{name}/rename")]
+pub async fn identity_rename(g: web::Data<Arc<Globals>>, p: web::Path<EgoNameParams>, form: web::Form<EgoForm>) -> web_error::Result<HttpResponse> {
+
+	let new_name = form.name.trim();
+	if new_name.is_empty() {
+		return render_identities( &g, Some("An ego needs a name.") ).await
+	}
+
+	let db = g.connect_database().await?;
+	match db.rename_ego( &p.name, new_name ).await {
+		Err(persistence::Error::Services(services::Error::EgoNotFound(_))) => return Err( WebError::not_found("Unknown ego.") ),
+		Err(persistence::Error::AlreadyExists) => return render_identities( &g, Some("An ego with that name already exists.") ).await,
+		other => other?
+	}
+	db.record_action( Some( new_name ), AuditAction::EgoRenamed, &format!("{} to {}", p.name, new_name) ).await?;
+	Ok( HttpResponse::SeeOther().append_header((header::LOCATION, "/identities")).finish() )
+}
+
+/// Deletes an ego, which is only possible for egos without a channel.
+#[post("/identities/{name}/delete")]
+pub async fn identity_delete(g: web::Data<Arc<Globals>>, p: web::Path<EgoNameParams>) -> web_error::Result<HttpResponse> {
+
+	let db = g.connect_database().await?;
+	match db.delete_ego( &p.name ).await {
+		Err(persistence::Error::NotFound) => return Err( WebError::not_found("Unknown ego.") ),
+		Err(persistence::Error::EgoConflict(persistence::EgoConflict::InUse(_))) => {
+			return render_identities( &g, Some("The ego of a channel can't be deleted, as nothing could be published in the channel anymore.") ).await
+		},
+		other => other?
+	}
+	db.record_action( None, AuditAction::EgoDeleted, &p.name ).await?;
+	Ok( HttpResponse::SeeOther().append_header((header::LOCATION, "/identities")).finish() )
+}
+
+#[get("/channel/subscribe")]
+pub async fn channel_subscribe(g: web::Data<Arc<Globals>>) -> web_error::Result<HttpResponse> {
+
+	let html = g.templates.render("channel-subscribe.html", &tera::Context::new())
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+
+	Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+#[derive(Deserialize)]
+pub struct SubscribeForm {
+	address: String
+}
+
+/// Starts following a remote channel.
+/// The channel can be given by its address, or by a share link, of which the peers are tried first to join the swarm.
+/// Joining its swarm can take a while, so that happens in the background.
+#[post("/channel/subscribe")]
+pub async fn channel_subscribe_post(g: web::Data<Arc<Globals>>, form: web::Form<SubscribeForm>) -> web_error::Result<HttpResponse> {
+
+	let link = ShareLink::parse( &form.address )
+		.ok_or_else(|| WebError::bad_request("Invalid channel address."))?;
+	let location = match link.post_id {
+		None => format!("/channel/feed/address/{}", link.address),
+		Some(id) => format!("/channel/address/{}/post/{}", link.address, id)
+	};
+
+	subscribe( g.get_ref(), link.address, &link.peers ).await?;
+
+	Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+}
+
+#[derive(Deserialize)]
+pub struct DiscoverQuery {
+	q: Option<String>
 }
 
 #[derive(Serialize)]
-pub struct PostPreview {
-	id: String,
-	info: PostInfo,
-	html: String
+pub struct ChannelListingView {
+	address: String,
+	title: String,
+	keywords: Vec<String>,
+	following: bool
 }
 
-async fn load_post_previews( blog_: &timeline::Handle, posts: &[Option<Post>] ) -> error::Result<Vec<PostPreview>> {
-	let mut previews = Vec::with_capacity( posts.len() );
-	
-	for opt_post in posts {
-
-		if let Some(post) = opt_post {
-			let blog = blog_.clone();
-			let persistence = blog.into_post( post.id.clone() as _ );	// TODO: Load post by postid
-
-			let content = persistence.load_content().await?.expect("missing content");
-			let end = min( 257, content.len() );
-			let mut preview = String::from_utf8_lossy( &content.as_bytes()[..end] ).to_string();
-			preview.pop();	// The last character may not have been valid UTF-8 because perhaps not all bytes of the character where contained within this block.
-			// This is a quickfix for dealing with that.
-
-			previews.push(PostPreview {
-				id: post.id.to_string(),
-				info: post.meta.info.clone(),
-				html: preview
-			})
+/// Searches the channel directory for the channels that are listed under all of the given keywords.
+#[get("/discover")]
+pub async fn discover(g: web::Data<Arc<Globals>>, q: web::Query<DiscoverQuery>) -> web_error::Result<HttpResponse> {
+
+	let query = q.q.as_deref().unwrap_or("").trim();
+	let mut context = tera::Context::new();
+	context.insert("query", query);
+
+	if !query.is_empty() {
+		let db = g.connect_database().await?;
+		let following = db.list_subscriptions().await?;
+
+		// Not being able to reach the DHT is shown on the page, as it is usually temporary.
+		match directory::search( &g.services, query ).await {
+			Err(e) => {
+				warn!("Unable to search the channel directory: {}", e);
+				context.insert("error", "The directory can't be reached right now, try again later.");
+			},
+			Ok(listings) => {
+				let channels: Vec<ChannelListingView> = listings.into_iter().map(|l| ChannelListingView {
+					following: following.contains( &l.address ),
+					address: l.address.to_string(),
+					title: l.title,
+					keywords: l.keywords
+				}).collect();
+				context.insert("channels", &channels);
+			}
 		}
 	}
 
-	Ok( previews )
+	let html = g.templates.render("discover.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok(HttpResponse::Ok().content_type("text/html").body(html))
 }
 
-#[get("/channel/feed/{id_type}/{id}/{page}")]
-pub async fn channel_feed(g: web::Data<Arc<Globals>>, p: web::Path<BlogFeedParams>) -> error::Result<HttpResponse> {
-	_channel_feed(g, &p.id, &p.id_type, p.page).await
+/// Stores the subscription to a channel, and joins its swarm in the background.
+/// The given peers are remembered for the swarm, so that they are tried before the owner of the channel.
+pub async fn subscribe( g: &Arc<Globals>, address: PublicKey, peers: &[PublicKey] ) -> web_error::Result<()> {
+
+	let db = g.connect_database().await?;
+	let (channel, _) = db.subscribe( &address, peers ).await?;
+	subscriptions::join( g.subscriptions.clone(), channel, address );
+
+	Ok(())
+}
+
+/// Stops following a remote channel: leaves its swarm and deletes everything that we've stored of it, including the files of its posts.
+#[post("/channel/unsubscribe")]
+pub async fn channel_unsubscribe(g: web::Data<Arc<Globals>>, form: web::Form<SubscribeForm>) -> web_error::Result<HttpResponse> {
+
+	let address = PublicKey::from_string( form.address.trim() )
+		.ok_or_else(|| WebError::bad_request("Invalid channel address."))?;
+
+	unsubscribe( &g, &address ).await?;
+
+	Ok( HttpResponse::Found().append_header((header::LOCATION, "/")).finish() )
+}
+
+pub async fn unsubscribe( g: &Globals, address: &PublicKey ) -> web_error::Result<()> {
+
+	let db = g.connect_database().await?;
+	if let Some(timeline) = db.get_timeline( address ).await? {
+		if timeline.get_my_ego().await?.is_some() {
+			return Err( WebError::bad_request("You can't unsubscribe from your own channel.") )
+		}
+	}
+	let channel = db.clone().get_channel( address ).await?
+		.ok_or_else(|| WebError::not_found("Not subscribed to this channel."))?;
+
+	let sub = g.subscriptions.write().await.as_mut().and_then(|s| s.remove( address ));
+	if let Some(sub) = sub {
+		sub.disconnect().await;
+	}
+	channel.purge().await?;
+	db.record_action( None, AuditAction::Unsubscribed, &address.to_string() ).await?;
+
+	Ok(())
 }
 
 #[derive(Deserialize)]
-pub struct PostCreateParams {
-	message: String,
-	tags: String
+pub struct SetupForm {
+	/// The token from the log, which is only needed when the setup isn't done on the computer that the node runs on.
+	#[serde(default)]
+	token: String,
+	data_dir: String,
+	name: String,
+	password: String,
+	password_confirmation: String,
+	contribution: ContributionProfile
+}
+
+async fn render_setup( g: &Globals, data_dir: &str, error: Option<&str> ) -> web_error::Result<HttpResponse> {
+
+	let gnunet_available = g.services.is_available().await;
+
+	let mut context = tera::Context::new();
+	context.insert("data_dir", data_dir);
+	context.insert("gnunet_available", &gnunet_available);
+	context.insert("error", &error);
+
+	let html = g.templates.render("setup.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	let response = match error {
+		None => HttpResponse::Ok(),
+		Some(_) => HttpResponse::BadRequest()
+	}.content_type("text/html").body(html);
+
+	Ok(response)
+}
+
+#[get("/setup")]
+pub async fn setup(g: web::Data<Arc<Globals>>) -> web_error::Result<HttpResponse> {
+
+	if !setup::is_required( g.services.clone() ).await? {
+		return Ok( HttpResponse::Found().append_header((header::LOCATION, "/")).finish() )
+	}
+
+	render_setup( &g, &persistence::data_dir().to_string_lossy(), None ).await
 }
 
-async fn _channel_feed( g: web::Data<Arc<Globals>>, id: &str, id_type: &str, page: u32 ) -> error::Result<HttpResponse> {
-	const PAGE_SIZE: u64 = 10;
+/// Completes the setup, which anyone who can reach the node could otherwise do, choosing its data directory and admin password.
+/// So it is only done for requests from this computer, or that give the setup token from the log.
+/// Once an admin password has been chosen, which may be by a setup that has been interrupted, it has to be given again.
+#[post("/setup")]
+pub async fn setup_post(g: web::Data<Arc<Globals>>, req: HttpRequest, form: web::Form<SetupForm>) -> web_error::Result<HttpResponse> {
+
+	if !setup::is_required( g.services.clone() ).await? {
+		return Ok( HttpResponse::Found().append_header((header::LOCATION, "/")).finish() )
+	}
+
+	let local = req.peer_addr().map(|a| a.ip().is_loopback()).unwrap_or(false);
+	if !local && !setup::check_setup_token( &form.token ) {
+		return Err( WebError::forbidden("The setup token is wrong. It is written to the log of the node when it starts.") )
+	}
+	if auth::has_password() && !auth::check_password( &form.password ) {
+		return Err( WebError::forbidden("An admin password has been chosen already, enter that one to complete the setup.") )
+	}
+
+	let data_dir = form.data_dir.trim();
+	if data_dir.len() == 0 {
+		return render_setup( &g, data_dir, Some("Please choose a data directory.") ).await
+	}
+	if form.password.len() < 8 {
+		return render_setup( &g, data_dir, Some("The admin password needs to be at least 8 characters long.") ).await
+	}
+	if form.password != form.password_confirmation {
+		return render_setup( &g, data_dir, Some("The admin passwords don't match.") ).await
+	}
+	if !g.services.is_available().await {
+		return render_setup( &g, data_dir, Some("The gnunet identity service is not reachable. Make sure gnunet is running, and try again.") ).await
+	}
+
+	if let Err(e) = setup::store_data_dir( PathBuf::from( data_dir ) ) {
+		error!("Unable to create data directory {}: {}", data_dir, e);
+		return render_setup( &g, data_dir, Some("Unable to create the data directory.") ).await
+	}
+
+	let mut db = g.connect_database().await?;
+	db.store_setting( setup::SETTING_SETUP_COMPLETE, "false" ).await?;
+	let password_hash = setup::hash_password( &form.password );
+	db.store_setting( setup::SETTING_ADMIN_PASSWORD, &password_hash ).await?;
+	auth::set_password_hash( password_hash );
+	db.store_setting( setup::SETTING_RELAY_POWER, &form.contribution.relay_power().to_string() ).await?;
+	db.record_action( None, AuditAction::SettingChanged, setup::SETTING_ADMIN_PASSWORD ).await?;
+	db.record_action( None, AuditAction::SettingChanged, setup::SETTING_RELAY_POWER ).await?;
 
-	let (address, public_key, local) = match id_type {
-		"address" => (id.to_owned(), PublicKey::from_string( &id ).unwrap(), false ),
-		"ego" => {
-			let mut identity_service = gnunet::identity::Handle::connect( g.gnunet.clone() ).await
-				.map_err(|_| error::ErrorInternalServerError("Gnunet identity service not available."))?;
-			let priv_key = identity_service.lookup( &id ).await.expect("unexpected gnunet error").expect("ego not found");
-			let public_key = priv_key.extract_public().expect("unable to extract public key");
-			( public_key.to_string(), public_key, true )
+	let channel = match db.create_channel( &form.name, config::CHANNEL_PUBLIC ).await {
+		Err(persistence::Error::AlreadyExists) => {
+			return render_setup( &g, data_dir, Some("An ego with that name already exists!") ).await
 		},
-		_ => {
-			panic!("ID type not supported");
-		}
+		Err(persistence::Error::EgoConflict(persistence::EgoConflict::Unused(_))) => {
+			// The setup has been interrupted after creating the ego, so continue with that one.
+			db.adopt_channel( &form.name, config::CHANNEL_PUBLIC ).await?
+		},
+		Err(persistence::Error::EgoConflict(persistence::EgoConflict::InUse(_))) => {
+			return render_setup( &g, data_dir, Some("You already have a channel with that name.") ).await
+		},
+		Err(e) => Err(e)?,
+		Ok(c) => c
 	};
+	let address = channel.load_address().await?;
+	db.record_action( Some( &form.name ), AuditAction::ChannelCreated, &address.to_string() ).await?;
+
+	db.store_setting( setup::SETTING_SETUP_COMPLETE, "true" ).await?;
+
+	// Whoever has just chosen the admin password is logged in with it.
+	Ok( HttpResponse::Found()
+		.append_header((header::LOCATION, "/"))
+		.append_header((header::SET_COOKIE, auth::session_cookie( &auth::start_session() )))
+		.finish() )
+}
+
+#[derive(Deserialize)]
+pub struct LoginParams {
+	next: Option<String>
+}
+
+#[derive(Deserialize)]
+pub struct LoginForm {
+	password: String,
+	next: Option<String>
+}
+
+/// Returns the page to go to after logging in, which has to be on this node.
+/// Browsers take backslashes for slashes and skip control characters, so `/\evil.com` and `/\t/evil.com` would lead to another host as much as `//evil.com` does.
+pub fn login_destination( next: Option<&str> ) -> &str {
+	match next {
+		Some(n) if n.starts_with('/') && !n.starts_with("//")
+			&& !n.contains('\\') && !n.chars().any( char::is_control ) => n,
+		_ => "/"
+	}
+}
+
+fn render_login( g: &Globals, next: &str, error: Option<&str> ) -> web_error::Result<HttpResponse> {
 
 	let mut context = tera::Context::new();
-	context.insert("address", &address);
+	context.insert("next", next);
+	context.insert("error", &error);
 
-	let mut db = persistence::Handle::connect( g.gnunet.clone() ).await.map_err(|e| persistence::Error::Database(e))?
-		.get_channel( &public_key ).await?.expect("unknown channel")
-		.get_timeline( &public_key ).await?.expect("unknown publisher");
-	let posts = db.list_posts( (page as u64 - 1)*PAGE_SIZE, PAGE_SIZE as _ ).await?;
+	let html = g.templates.render("login.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	let response = match error {
+		None => HttpResponse::Ok(),
+		Some(_) => HttpResponse::Unauthorized()
+	}.content_type("text/html").body(html);
 
-	let post_previews = load_post_previews( &db, &*posts ).await?;
-	context.insert("feed", &post_previews);
+	Ok(response)
+}
 
-	let template_file = if local { "blog/own-feed.html" } else { "blog/feed.html" };
+#[get("/login")]
+pub async fn login(g: web::Data<Arc<Globals>>, params: web::Query<LoginParams>) -> web_error::Result<HttpResponse> {
 
-	let html = g.tera.render(template_file, &context)
-		.map_err(|e| { eprintln!("Template error: {}", e); error::ErrorInternalServerError("Template error") } )?;
-	Ok(HttpResponse::Ok().content_type("text/html").body(html))
+	// Requests from this computer don't need to log in when no others are accepted.
+	if config::get().local_only {
+		return Ok( HttpResponse::Found().append_header((header::LOCATION, login_destination( params.next.as_deref() ))).finish() )
+	}
+
+	render_login( &g, login_destination( params.next.as_deref() ), None )
 }
 
-#[get("/channel/feed/{id_type}/{id}")]
-pub async fn channel_feed_first( g: web::Data<Arc<Globals>>, p: web::Path<BlogFeedIdParams>) -> error::Result<HttpResponse> {
-	_channel_feed( g, &p.id, &p.id_type, 1 ).await
+/// Logs in with the admin password, and goes on to the page that asked for it.
+#[post("/login")]
+pub async fn login_post(g: web::Data<Arc<Globals>>, req: HttpRequest, form: web::Form<LoginForm>) -> web_error::Result<HttpResponse> {
+
+	let next = login_destination( form.next.as_deref() );
+	match auth::attempt_login( req.peer_addr().map(|a| a.ip()), &form.password ) {
+		auth::LoginAttempt::Accepted => {},
+		auth::LoginAttempt::Refused => {
+			warn!("Failed login from {}", req.peer_addr().map(|a| a.ip().to_string()).unwrap_or_default());
+			return render_login( &g, next, Some("That is not the admin password.") )
+		},
+		auth::LoginAttempt::Throttled => {
+			warn!("Refused login from {} after too many failed ones", req.peer_addr().map(|a| a.ip().to_string()).unwrap_or_default());
+			return render_login( &g, next, Some("Too many wrong passwords have been given, try again later.") )
+		}
+	}
+
+	let db = g.connect_database().await?;
+	auth::upgrade_password_hash( &db, &form.password ).await?;
+
+	Ok( HttpResponse::SeeOther()
+		.append_header((header::LOCATION, next))
+		.append_header((header::SET_COOKIE, auth::session_cookie( &auth::start_session() )))
+		.finish() )
 }
 
-/*#[post("/channel/feed/{id_type}/{id}")]
-pub async fn channel_feed_post( g: web::Data<Arc<Globals>>, p: web::Path<BlogFeedIdParams>, f: web::Form<PostCreateParams>) -> error::Result<HttpResponse> {
-	
-	if p.id_type != "ego" {
-		panic!("Posts can only be created by local ego's!");
+#[post("/logout")]
+pub async fn logout(req: HttpRequest) -> web_error::Result<HttpResponse> {
+
+	if let Some(cookie) = req.cookie( auth::SESSION_COOKIE ) {
+		auth::end_session( cookie.value() );
 	}
 
-	let mut identity_service = gnunet::identity::Handle::connect( g.gnunet.clone() ).await
-		.map_err(|_| error::ErrorInternalServerError("Gnunet identity service not available."))?;
-	let private_key = identity_service.lookup( &p.id ).await.expect("gnunet error").expect("ego not found");
-	drop( identity_service );
+	Ok( HttpResponse::SeeOther()
+		.append_header((header::LOCATION, "/"))
+		.append_header((header::SET_COOKIE, auth::expired_session_cookie()))
+		.finish() )
+}
+
+#[derive(Deserialize)]
+pub struct ChannelAddressParams {
+	address: String
+}
 
-	// Create post
-	let blog_address = private_key.extract_public().unwrap();
-	let blog = persistence::Handle::connect( g.gnunet.clone() ).await?.get_channel( &blog_address ).await?;
+#[get("/favicon.svg")]
+pub async fn favicon() -> HttpResponse {
+	HttpResponse::Ok()
+		.content_type("image/svg+xml")
+		.append_header((header::CACHE_CONTROL, "max-age=86400"))
+		.body( identicon::generate_svg( b"QuartzNet" ) )
+}
 
-	let tags: Vec<String> = f.tags.split_whitespace().map(|x| x.to_owned()).collect();
-	let post_info = PostInfo {
-		tags,new
-		publish_timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as _
+#[derive(Deserialize)]
+pub struct StaticFileParams {
+	path: String
+}
+
+/// The scripts, stylesheets and other files of the web interface.
+#[get("/static/{path:.*}")]
+pub async fn static_file(g: web::Data<Arc<Globals>>, p: web::Path<StaticFileParams>) -> web_error::Result<HttpResponse> {
+
+	let content = g.static_files.get( &p.path )
+		.ok_or_else(|| WebError::not_found("Static file not found."))?;
+
+	Ok( HttpResponse::Ok()
+		.content_type( assets::mime_type( &p.path ) )
+		.append_header((header::CACHE_CONTROL, "max-age=86400"))
+		.body( content.into_owned() ) )
+}
+
+/// The icon of a channel.
+/// This is the profile picture of a public channel if we have it and it is a PNG, JPEG, GIF or WebP image, or the identicon of its address otherwise.
+#[get("/channel/{address}/icon.svg")]
+pub async fn channel_icon(g: web::Data<Arc<Globals>>, p: web::Path<ChannelAddressParams>) -> web_error::Result<HttpResponse> {
+
+	let public_key = PublicKey::from_string( &p.address )
+		.ok_or_else(|| WebError::bad_request("Invalid channel address."))?;
+
+	let db = g.connect_database().await?;
+	if let Some(channel) = db.clone().get_channel( &public_key ).await? {
+		// The picture of a private channel is only for its members, so they get the identicon as well.
+		let profile = if channel.is_private().await? { None } else { channel.fetch_profile().await? };
+		if let Some(picture_hash) = profile.and_then(|p| p.base.profile_picture) {
+			// Anyone can ask for the icon, so the picture isn't requested from the swarm for them, that is left to the synchronization of the channel.
+			// Until all of its blocks have arrived, the identicon is shown instead.
+			if let Some(data) = load_stored_file( &db, &picture_hash ).await? {
+				// Like with the attachments, only images that browsers can't run anything in are served, and anything else gets the identicon.
+				match identicon::sniff_image_type( &data ) {
+					Some(content_type) if INLINE_ATTACHMENT_TYPES.contains( &content_type ) => return Ok( HttpResponse::Ok()
+						.content_type(content_type)
+						.append_header((header::CONTENT_SECURITY_POLICY, ATTACHMENT_CONTENT_SECURITY_POLICY))
+						.append_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+						.append_header((header::CACHE_CONTROL, "max-age=3600"))
+						.body(data)
+					),
+					_ => {}
+				}
+			}
+		}
+	}
+
+	Ok( HttpResponse::Ok()
+		.content_type("image/svg+xml")
+		.append_header((header::CACHE_CONTROL, "max-age=3600"))
+		.body( identicon::generate_svg( p.address.as_bytes() ) )
+	)
+}
+
+/// The version of the manifest format, which is raised when fields are changed or removed.
+const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+pub struct ChannelManifest {
+	manifest_version: u32,
+	address: String,
+	profile: Option<ManifestProfile>,
+	/// Whether the posts may be shared with any subscriber, if we have the genesis event of the channel.
+	public: Option<bool>,
+	requested_replication_days: Option<u32>,
+	/// The keys that currently sign the events of the channel.
+	owners: Vec<String>,
+	/// The keys that may publish posts in the channel, besides the channel itself.
+	publishers: Vec<String>,
+	protocol_version: String,
+	feeds: ManifestFeeds,
+	api: ManifestApi,
+	gateway: ManifestGateway
+}
+
+#[derive(Serialize)]
+pub struct ManifestProfile {
+	title: String,
+	description: String,
+	icon: String
+}
+
+#[derive(Serialize)]
+pub struct ManifestFeeds {
+	html: String,
+	rss: String,
+	atom: String,
+	/// Pushes the changes to the posts of the channel as they arrive.
+	websocket: String
+}
+
+#[derive(Serialize)]
+pub struct ManifestApi {
+	posts: String,
+	subscription: String,
+	sync_status: String,
+	search: String
+}
+
+#[derive(Serialize)]
+pub struct ManifestGateway {
+	version: &'static str,
+	subscribed: bool,
+	/// Whether the gateway is connected to the swarm of the channel right now.
+	connected: bool,
+	/// Whether the channel belongs to one of the egos of the gateway, so that posts can be created through it.
+	writable: bool,
+	/// Whether the gateway has the key to decrypt the messages of the channel, if it is private.
+	invited: bool,
+	search: bool,
+	swarm_search: bool
+}
+
+/// Describes the channel and what this gateway offers for it, so that other tools can configure themselves against it.
+#[get("/channel/{address}/manifest.json")]
+pub async fn channel_manifest(g: web::Data<Arc<Globals>>, p: web::Path<ChannelAddressParams>, req: HttpRequest) -> web_error::Result<HttpResponse> {
+
+	let address = PublicKey::from_string( &p.address )
+		.ok_or_else(|| WebError::bad_request("Invalid channel address."))?;
+	let db = g.connect_database().await?;
+	let channel = db.clone().get_channel( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+
+	let base_url = {
+		let info = req.connection_info();
+		format!("{}://{}", info.scheme(), info.host())
+	};
+	let ws_base_url = base_url.replacen( "http", "ws", 1 );
+
+	let parameters = channel.load_parameters().await?;
+	let writable = match channel.get_timeline( &address ).await? {
+		None => false,
+		Some(timeline) => timeline.get_my_ego().await?.is_some()
+	};
+
+	let manifest = ChannelManifest {
+		manifest_version: MANIFEST_VERSION,
+		address: address.to_string(),
+		profile: channel.fetch_profile().await?.map(|profile| ManifestProfile {
+			title: profile.base.title,
+			description: profile.base.description,
+			icon: format!("{}/channel/{}/icon.svg", base_url, address)
+		}),
+		public: parameters.as_ref().map(|p| p.public),
+		requested_replication_days: parameters.map(|p| p.requested_replication_time),
+		owners: channel.load_owners().await?.iter().map(|o| o.to_string()).collect(),
+		publishers: channel.list_publishers().await?.iter().map(|p| p.to_string()).collect(),
+		protocol_version: format!("{}.{}", PROTOCOL_VERSION.major, PROTOCOL_VERSION.minor),
+		feeds: ManifestFeeds {
+			html: format!("{}/channel/feed/address/{}", base_url, address),
+			rss: format!("{}/channel/feed/address/{}/rss", base_url, address),
+			atom: format!("{}/channel/feed/address/{}/atom", base_url, address),
+			websocket: format!("{}/ws/channel/{}", ws_base_url, address)
+		},
+		api: ManifestApi {
+			posts: format!("{}/api/v1/timelines/{}/posts", base_url, address),
+			subscription: format!("{}/api/v1/subscriptions/{}", base_url, address),
+			sync_status: format!("{}/api/v1/subscriptions/{}/sync/status", base_url, address),
+			search: format!("{}/api/v1/search", base_url)
+		},
+		gateway: ManifestGateway {
+			version: env!("CARGO_PKG_VERSION"),
+			subscribed: db.load_subscription( &address ).await?.is_some(),
+			connected: g.subscriptions.read().await.as_ref().and_then(|s| s.node( &address )).is_some(),
+			writable,
+			invited: channel.load_invite_code().await?.is_some(),
+			search: true,
+			swarm_search: true
+		}
 	};
-	blog.create_post( &private_key, &f.message, post_info ).await?;
 
-	_channel_feed( g, &p.id, &p.id_type, 1 ).await
-}*/
+	Ok( HttpResponse::Ok()
+		.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+		.json( manifest ) )
+}
+
+#[derive(Deserialize)]
+pub struct AttachmentParams {
+	address: String,
+	hash: String
+}
+
+/// Serves an attachment, reassembled from its blocks.
+/// Any blocks that aren't available locally are requested from the swarm of the channel first.
+#[get("/channel/{address}/attachment/{hash}")]
+pub async fn channel_attachment(g: web::Data<Arc<Globals>>, p: web::Path<AttachmentParams>) -> web_error::Result<HttpResponse> {
+
+	let address = PublicKey::from_string( &p.address )
+		.ok_or_else(|| WebError::bad_request("Invalid channel address."))?;
+	let file_hash = HashCode::from_string( &p.hash )
+		.ok_or_else(|| WebError::bad_request("Invalid attachment hash."))?;
+
+	let db = g.connect_database().await?;
+	check_attached( &db, &address, &file_hash ).await?;
+	let (file, content_type) = load_attachment( &g, &db, &address, &file_hash ).await?;
+
+	// Stream the blocks one by one, so that large files don't need to be loaded into memory at once.
+	let blocks = stream::iter( file.block_ids ).then(move |block_id| {
+		let db = db.clone();
+		async move {
+			db.load_block( &block_id ).await?
+				.map(web::Bytes::from)
+				.ok_or_else(|| WebError::internal("Block went missing."))
+		}
+	});
+
+	// Only images that browsers can't run anything in are shown inline.
+	// Anything else, which may be HTML or SVG uploaded by anyone who can post to the channel, is offered as a download, so that it never runs on the origin of the node.
+	let mut response = HttpResponse::Ok();
+	if !INLINE_ATTACHMENT_TYPES.contains( &content_type.as_str() ) {
+		response.append_header((header::CONTENT_DISPOSITION, "attachment"));
+	}
+	Ok( response
+		.content_type(content_type)
+		.append_header((header::CACHE_CONTROL, "max-age=31536000, immutable"))
+		.append_header((header::CONTENT_SECURITY_POLICY, ATTACHMENT_CONTENT_SECURITY_POLICY))
+		.append_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+		.streaming( Box::pin( blocks ) )
+	)
+}
+
+/// The MIME types of the attachments that are served inline.
+/// Those are also the only types of images that Micropub clients can attach.
+pub const INLINE_ATTACHMENT_TYPES: &[&str] = &["image/png", "image/jpeg", "image/gif", "image/webp"];
+
+/// The policy that is sent along with every attachment, so that nothing in it can run scripts even if a browser ends up rendering it.
+const ATTACHMENT_CONTENT_SECURITY_POLICY: &str = "sandbox; script-src 'none'";
+
+/// Makes sure that the file is attached to a post of the channel, so that the channel's address can't be used to serve any file that the node happens to have or can find.
+async fn check_attached( db: &persistence::Handle, address: &PublicKey, file_hash: &HashCode ) -> web_error::Result<()> {
+
+	let channel = db.clone().get_channel( address ).await?
+		.ok_or_else(|| WebError::not_found("Channel not found."))?;
+	if !channel.has_attachment( file_hash ).await? {
+		return Err( WebError::not_found("Attachment not found.") )
+	}
+	Ok(())
+}
+
+/// Makes sure that the attachment and all of its blocks are available locally, requesting whatever is missing from the swarm of the channel.
+/// Returns the blocks that make up the attachment, and its MIME type.
+async fn load_attachment( g: &Globals, db: &persistence::Handle, address: &PublicKey, file_hash: &HashCode ) -> web_error::Result<(Attachment, String)> {
+
+	let node = g.subscriptions.read().await.as_ref().and_then(|s| s.node( address ));
+
+	let file = match db.load_file( file_hash ).await? {
+		Some(f) => f,
+		None => match &node {
+			None => None,
+			Some(n) => n.fetch_file( file_hash ).await?
+		}.ok_or_else(|| WebError::not_found("Attachment not found."))?
+	};
+
+	let mut missing = Vec::new();
+	for block_id in &file.block_ids {
+		if !db.has_block( block_id ).await? {
+			missing.push( block_id.clone() );
+		}
+	}
+	if missing.len() > 0 {
+		let fetched = match &node {
+			None => Vec::new(),
+			Some(n) => n.fetch_blocks( file_hash, &missing ).await?
+		};
+		if fetched.len() < missing.len() {
+			return Err( WebError::service_unavailable("Not all blocks of the attachment are available yet.") )
+		}
+	}
+
+	let content_type = load_mime_type( db, file_hash, &file ).await?
+		.unwrap_or_else(|| "application/octet-stream".to_owned());
+	Ok(( file, content_type ))
+}
 
+/// Loads the whole content of a file, if it and all of its blocks are stored locally.
+async fn load_stored_file( db: &persistence::Handle, file_hash: &HashCode ) -> web_error::Result<Option<Vec<u8>>> {
 
+	let file = match db.load_file( file_hash ).await? {
+		Some(f) => f,
+		None => return Ok(None)
+	};
 
-impl From<persistence::Error> for actix_web::Error {
-	fn from( other: persistence::Error ) -> Self {
-		eprintln!("Persistence error: {}", other);
-		error::ErrorInternalServerError("Internal server error occurred")
+	let mut data = Vec::new();
+	for block_id in &file.block_ids {
+		match db.load_block( block_id ).await? {
+			Some(block) => data.extend( block ),
+			None => return Ok(None)
+		}
 	}
-}
\ No newline at end of file
+	Ok( Some( data ) )
+}
+
+/// Loads the MIME type of a stored file.
+/// Files received from other peers come without a MIME type, so for those a guess is made from the first block, if we have it.
+async fn load_mime_type( db: &persistence::Handle, file_hash: &HashCode, file: &Attachment ) -> web_error::Result<Option<String>> {
+
+	if let Some(mime_type) = db.load_file_mime_type( file_hash ).await? {
+		return Ok( Some( mime_type ) )
+	}
+
+	let first_block = match file.block_ids.first() {
+		None => None,
+		Some(id) => db.load_block( id ).await?
+	};
+	Ok( first_block.as_ref()
+		.and_then(|b| identicon::sniff_image_type( b ))
+		.map(|t| t.to_owned()) )
+}
+
+#[derive(Deserialize)]
+pub struct ThumbnailParams {
+	address: String,
+	hash: String,
+	width: u32
+}
+
+/// Serves a smaller version of an attached image, in one of the widths of `config::PREVIEW_IMAGE_WIDTHS`.
+/// It is made the first time that it is requested.
+/// Attachments that can't be scaled, or that are narrow enough already, are redirected to as they are.
+#[get("/channel/{address}/attachment/{hash}/{width}")]
+pub async fn channel_attachment_thumbnail(g: web::Data<Arc<Globals>>, p: web::Path<ThumbnailParams>) -> web_error::Result<HttpResponse> {
+
+	let address = PublicKey::from_string( &p.address )
+		.ok_or_else(|| WebError::bad_request("Invalid channel address."))?;
+	let file_hash = HashCode::from_string( &p.hash )
+		.ok_or_else(|| WebError::bad_request("Invalid attachment hash."))?;
+	if !config::PREVIEW_IMAGE_WIDTHS.contains( &p.width ) {
+		return Err( WebError::not_found("Images aren't offered in this width.") )
+	}
+
+	let db = g.connect_database().await?;
+	check_attached( &db, &address, &file_hash ).await?;
+	let thumbnail = match db.load_thumbnail( &file_hash, p.width ).await? {
+		Some(t) => t,
+		None => {
+			let (file, mime_type) = load_attachment( &g, &db, &address, &file_hash ).await?;
+			let thumbnail = if thumbnail::is_supported( &mime_type ) {
+				let mut data = Vec::new();
+				for block_id in &file.block_ids {
+					data.extend( db.load_block( block_id ).await?
+						.ok_or_else(|| WebError::internal("Block went missing."))? );
+				}
+
+				let width = p.width;
+				match runtime::block_on(|| thumbnail::scale( &data, width )).await {
+					Ok(Some((mime_type, data))) => Thumbnail::Scaled { mime_type: mime_type.to_owned(), data },
+					Ok(None) => Thumbnail::Original,
+					Err(e) => {
+						warn!("Unable to scale attachment {}: {}", p.hash, e);
+						Thumbnail::Original
+					}
+				}
+			} else {
+				Thumbnail::Original
+			};
+			db.store_thumbnail( &file_hash, p.width, &thumbnail ).await?;
+			thumbnail
+		}
+	};
+
+	Ok( match thumbnail {
+		Thumbnail::Original => {
+			let location = format!("/channel/{}/attachment/{}", p.address, p.hash);
+			HttpResponse::Found().append_header((header::LOCATION, location)).finish()
+		},
+		Thumbnail::Scaled { mime_type, data } => HttpResponse::Ok()
+			.content_type( mime_type )
+			.append_header((header::CACHE_CONTROL, "max-age=31536000, immutable"))
+			.append_header((header::CONTENT_SECURITY_POLICY, ATTACHMENT_CONTENT_SECURITY_POLICY))
+			.append_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+			.body( data )
+	})
+}
+
+#[derive(Serialize)]
+pub struct ArchivePostView {
+	publisher: String,
+	id: u64,
+	/// The URI of the rendered page in the bundle.
+	page: String,
+	/// In seconds since the UNIX epoch, for tera's date filter.
+	publish_timestamp: u64
+}
+
+/// Exports everything that we have of a channel into an archival bundle, see `archive`.
+/// The events and posts are included as they were signed, so that the bundle can be verified without this node.
+#[get("/channel/{address}/archive.warc")]
+pub async fn channel_archive(g: web::Data<Arc<Globals>>, p: web::Path<ChannelAddressParams>) -> web_error::Result<HttpResponse> {
+
+	let address = PublicKey::from_string( &p.address )
+		.ok_or_else(|| WebError::bad_request("Invalid channel address."))?;
+	let db = g.connect_database().await?;
+	let channel = db.clone().get_channel( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+
+	let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+	let base_uri = format!("urn:quartznet:{}", address);
+	let mut warc = WarcWriter::new( format!("{}:{}", base_uri, now), now );
+	warc.write_info( &[
+		("software", format!("QuartzNet {}", env!("CARGO_PKG_VERSION"))),
+		("format", format!("QuartzNet channel bundle {}", archive::BUNDLE_VERSION)),
+		("description", format!("The channel {}, with its signed events and posts", address))
+	]);
+
+	// The keys
+	let mut publishers = vec![ address.clone() ];
+	publishers.extend( channel.list_publishers().await? );
+	let keys = BundleKeys {
+		channel: address.to_string(),
+		owners: channel.load_owners().await?.iter().map(|o| o.to_string()).collect(),
+		publishers: publishers.iter().map(|p| p.to_string()).collect()
+	};
+	warc.write_resource( &format!("{}/keys.json", base_uri), "application/json", &serde_json::to_vec_pretty( &keys ).unwrap() );
+
+	// The events, in the form in which they are exchanged in the swarm
+	let last_event_id = channel.load_last_event_id().await?.unwrap_or( GENESIS_EVENT_ID );
+	let mut from_id = GENESIS_EVENT_ID;
+	while from_id <= last_event_id {
+		for (id, message) in channel.load_events( from_id, EVENTS_REQUEST_MAX_COUNT ).await? {
+			warc.write_resource( &format!("{}/event/{}", base_uri, id), "application/octet-stream", &message );
+		}
+		from_id += EVENTS_REQUEST_MAX_COUNT as u64;
+	}
+
+	// The posts, and the files attached to them
+	let mut posts = Vec::new();
+	let mut file_ids: Vec<HashCode> = Vec::new();
+	for publisher in &publishers {
+		let timeline = match channel.get_timeline( publisher ).await? {
+			None => continue,
+			Some(t) => t
+		};
+		let latest_id = match timeline.load_latest_post_id().await? {
+			None => continue,
+			Some(id) => id
+		};
+
+		for post_id in 0..=latest_id {
+			let post = match timeline.load_post( post_id ).await? {
+				None => continue,
+				Some(p) => p
+			};
+			let post_uri = format!("{}/post/{}/{}", base_uri, publisher, post_id);
+			warc.write_resource( &format!("{}/meta", post_uri), "application/octet-stream", &bincode::serialize( &post ).map_err( persistence::Error::from )? );
+			if let Some(content) = timeline.load_post_content( post_id ).await? {
+				warc.write_resource( &format!("{}/content", post_uri), "text/plain; charset=utf-8", content.as_bytes() );
+			}
+
+			// The page shows the post as it is now, including its revisions.
+			if let Some(content) = timeline.load_current_content( post_id ).await? {
+				let mut context = tera::Context::new();
+				context.insert("address", &address.to_string());
+				context.insert("publisher", &publisher.to_string());
+				context.insert("post_id", &post_id);
+				context.insert("info", &timeline.load_current_info( &post ).await?);
+				context.insert("publish_timestamp", &(post.meta.info.publish_timestamp / 1000));
+				context.insert("html", &preview::render( &content, post.meta.info.format ));
+				context.insert("attachments", &post.meta.attachment_ids.iter().map(|h| format!("{}/file/{}", base_uri, h)).collect::<Vec<_>>());
+				let html = g.templates.render("archive/post.html", &context)
+					.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+
+				let page = format!("{}/page.html", post_uri);
+				warc.write_resource( &page, "text/html; charset=utf-8", html.as_bytes() );
+				posts.push( ArchivePostView {
+					publisher: publisher.to_string(),
+					id: post_id,
+					page,
+					publish_timestamp: post.meta.info.publish_timestamp / 1000
+				});
+			}
+
+			for file_id in &post.meta.attachment_ids {
+				if !file_ids.contains( file_id ) {
+					file_ids.push( file_id.clone() );
+				}
+			}
+		}
+	}
+
+	// Only the files of which we have all blocks can be included.
+	'files: for file_id in &file_ids {
+		let file = match db.load_file( file_id ).await? {
+			None => continue,
+			Some(f) => f
+		};
+		let mut data = Vec::new();
+		for block_id in &file.block_ids {
+			match db.load_block( block_id ).await? {
+				None => continue 'files,
+				Some(block) => data.extend( block )
+			}
+		}
+		let mime_type = load_mime_type( &db, file_id, &file ).await?
+			.unwrap_or_else(|| "application/octet-stream".to_owned());
+		warc.write_resource( &format!("{}/file/{}", base_uri, file_id), &mime_type, &data );
+	}
+
+	// The index page, newest posts first
+	posts.sort_by(|a, b| b.publish_timestamp.cmp( &a.publish_timestamp ));
+	let profile = channel.fetch_profile().await?;
+	let mut context = tera::Context::new();
+	context.insert("address", &address.to_string());
+	context.insert("title", &profile.as_ref().map(|p| p.base.title.as_str()).unwrap_or(""));
+	context.insert("description", &profile.as_ref().map(|p| p.base.description.as_str()).unwrap_or(""));
+	context.insert("exported_at", &now);
+	context.insert("posts", &posts);
+	let html = g.templates.render("archive/index.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	warc.write_resource( &format!("{}/index.html", base_uri), "text/html; charset=utf-8", html.as_bytes() );
+
+	let manifest = BundleManifest {
+		bundle_version: archive::BUNDLE_VERSION,
+		protocol_version: format!("{}.{}", PROTOCOL_VERSION.major, PROTOCOL_VERSION.minor),
+		channel: address.to_string(),
+		exported_at: now,
+		verification: archive::VERIFICATION,
+		records: warc.take_entries()
+	};
+	warc.write_resource( &format!("{}/manifest.json", base_uri), "application/json", &serde_json::to_vec_pretty( &manifest ).unwrap() );
+
+	let disposition = format!("attachment; filename=\"{}.warc\"", address);
+	Ok( HttpResponse::Ok()
+		.content_type("application/warc")
+		.append_header((header::CONTENT_DISPOSITION, disposition))
+		.body( warc.finish() )
+	)
+}
+
+/// Exports the posts that we have of a channel as a static site, in a tar archive, see `static_site`.
+#[get("/channel/{address}/site.tar")]
+pub async fn channel_static_site(g: web::Data<Arc<Globals>>, p: web::Path<ChannelAddressParams>) -> web_error::Result<HttpResponse> {
+
+	let address = PublicKey::from_string( &p.address )
+		.ok_or_else(|| WebError::bad_request("Invalid channel address."))?;
+	let db = g.connect_database().await?;
+
+	let site = match static_site::export( &db, &g.templates, &address ).await {
+		Err(static_site::Error::Persistence(e)) => return Err( e.into() ),
+		Err(static_site::Error::UnknownChannel) => return Err( WebError::not_found("Unknown channel.") ),
+		Err(e) => { error!("Unable to export channel {}: {}", address, e); return Err( WebError::internal("Template error") ) },
+		Ok(s) => s
+	};
+	let tar = site.to_tar( &address.to_string() )?;
+
+	let disposition = format!("attachment; filename=\"{}.tar\"", address);
+	Ok( HttpResponse::Ok()
+		.content_type("application/x-tar")
+		.append_header((header::CONTENT_DISPOSITION, disposition))
+		.body( tar )
+	)
+}
+
+#[derive(Deserialize)]
+pub struct BlogFeedParams {
+	id: String,
+	id_type: String,
+	page: u32
+}
+
+#[derive(Deserialize)]
+pub struct BlogFeedIdParams {
+	id: String,
+	id_type: String
+}
+
+#[derive(Serialize)]
+pub struct PostPreview {
+	id: String,
+	/// `None` if the post has been forgotten, in which case only a tombstone is shown.
+	info: Option<PostInfo>,
+	html: String,
+	/// Whether the preview leaves out some of the content, so that a "read more" link should be shown.
+	truncated: bool,
+	attachments: Vec<AttachmentPreview>,
+	/// Where the post was copied from, if it was copied from another channel.
+	origin: Option<PostOriginPreview>,
+	/// The post that this post comments on, if it is a comment.
+	reply_to: Option<PostOriginPreview>,
+	/// The time until which the post is hidden from subscribers, if that is still in the future.
+	/// In seconds since the UNIX epoch, for tera's date filter.
+	embargoed_until: Option<u64>,
+	/// Whether there are changes to the post of our own that haven't reached the swarm yet.
+	pending: bool,
+	reactions: Vec<ReactionCountView>
+}
+
+#[derive(Serialize)]
+pub struct ReactionCountView {
+	kind: String,
+	count: u64
+}
+
+#[derive(Serialize)]
+pub struct AttachmentPreview {
+	hash: String,
+	/// Whether the attachment is an image that smaller versions can be made of, so that it can be shown in the feed.
+	image: bool
+}
+
+#[derive(Serialize)]
+pub struct PostOriginPreview {
+	address: String,
+	hash: String
+}
+
+/// The maximum number of post previews that are loaded at the same time.
+const PREVIEW_CONCURRENCY: usize = 4;
+
+/// Loads the previews of the given posts, which have the ids `start..(start + posts.len())`.
+/// Posts that we don't have are left out, unless they have been forgotten, in which case a tombstone is shown.
+/// Posts that may not be shown yet are left out as well, unless `show_embargoed` is set, which is meant for the publisher.
+async fn load_post_previews( blog: &timeline::Handle, start: u64, posts: &[Option<Post>], show_embargoed: bool ) -> web_error::Result<Vec<PostPreview>> {
+
+	let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+
+	// The content of the whole page is read at once.
+	let shown: Vec<&Post> = posts.iter().flatten().filter(|p| show_embargoed || p.meta.info.is_visible_at( now )).collect();
+	let summaries = preview_cache::load_summaries( blog, start, start + posts.len() as u64, &shown ).await?;
+	let summaries: HashMap<u64, Arc<preview::Preview>> = shown.iter().zip( summaries )
+		.filter_map(|(p, summary)| summary.map(|s| (p.id, s)))
+		.collect();
+	let summaries = &summaries;
+
+	// Load the rest of the previews concurrently, but keep them in the order of the posts.
+	let results: Vec<web_error::Result<Option<PostPreview>>> = stream::iter( posts.iter().enumerate() )
+		.map(|(i, post)| async move {
+			match post {
+				// Posts of which the content hasn't arrived yet, are left out like the ones that we don't have.
+				Some(p) => match summaries.get( &p.id ).cloned() {
+					Some(summary) if show_embargoed || p.meta.info.is_visible_at( now ) =>
+						Ok( Some( load_post_preview( blog, p, summary, now ).await? ) ),
+					_ => Ok( None )
+				},
+				None => {
+					let post_id = start + i as u64;
+					if blog.is_forgotten( post_id ).await? {
+						let mut preview = tombstone_preview( post_id );
+						preview.pending = blog.is_pending( post_id ).await?;
+						Ok( Some( preview ) )
+					} else {
+						Ok( None )
+					}
+				}
+			}
+		})
+		.buffered( PREVIEW_CONCURRENCY )
+		.collect().await;
+
+	Ok( results.into_iter().collect::<web_error::Result<Vec<_>>>()?.into_iter().flatten().collect() )
+}
+
+fn tombstone_preview( post_id: u64 ) -> PostPreview {
+	PostPreview {
+		id: post_id.to_string(),
+		info: None,
+		html: String::new(),
+		truncated: false,
+		attachments: Vec::new(),
+		origin: None,
+		reply_to: None,
+		embargoed_until: None,
+		pending: false,
+		reactions: Vec::new()
+	}
+}
+
+async fn load_post_preview( blog: &timeline::Handle, post: &Post, preview: Arc<preview::Preview>, now: u64 ) -> web_error::Result<PostPreview> {
+
+	let origin = blog.load_post_origin( post.id ).await?;
+
+	Ok( PostPreview {
+		id: post.id.to_string(),
+		info: Some( blog.load_current_info( post ).await? ),
+		html: preview.html.clone(),
+		truncated: preview.truncated,
+		attachments: load_attachment_previews( &blog.base, &post.meta.attachment_ids ).await?,
+		origin: origin.map(|o| PostOriginPreview {
+			address: o.publisher.to_string(),
+			hash: o.hash.to_string()
+		}),
+		reply_to: post.meta.reply_to.as_ref().map(|r| PostOriginPreview {
+			address: r.channel.to_string(),
+			hash: r.post_hash.to_string()
+		}),
+		embargoed_until: post.meta.info.visible_from
+			.filter(|_| !post.meta.info.is_visible_at( now ))
+			.map(|from| from / 1000),
+		pending: blog.is_pending( post.id ).await?,
+		reactions: load_reaction_counts( blog, post.id ).await?
+	})
+}
+
+async fn load_reaction_counts( timeline: &timeline::Handle, post_id: u64 ) -> web_error::Result<Vec<ReactionCountView>> {
+	Ok( timeline.count_reactions( post_id ).await?
+		.into_iter().map(|r| ReactionCountView {
+			kind: r.kind,
+			count: r.count
+		}).collect() )
+}
+
+/// Finds out which attachments are images that can be shown in the feed.
+/// Attachments that we haven't received yet are shown as links.
+async fn load_attachment_previews( db: &persistence::Handle, attachment_ids: &[HashCode] ) -> web_error::Result<Vec<AttachmentPreview>> {
+
+	let mut previews = Vec::with_capacity( attachment_ids.len() );
+	for file_hash in attachment_ids {
+		let image = match db.load_file( file_hash ).await? {
+			None => false,
+			Some(file) => load_mime_type( db, file_hash, &file ).await?
+				.map(|t| thumbnail::is_supported( &t ))
+				.unwrap_or(false)
+		};
+		previews.push( AttachmentPreview {
+			hash: file_hash.to_string(),
+			image
+		});
+	}
+	Ok( previews )
+}
+
+#[derive(Deserialize)]
+pub struct PostParams {
+	address: String,
+	post_id: u64
+}
+
+#[derive(Serialize)]
+pub struct RevisionView {
+	number: u32,
+	received_timestamp: u64,
+	html: String
+}
+
+#[derive(Serialize)]
+pub struct CommentView {
+	address: String,
+	post_id: u64,
+	/// In seconds since the UNIX epoch, for tera's date filter.
+	publish_timestamp: u64,
+	/// `None` if the content of the comment hasn't been received yet.
+	html: Option<String>
+}
+
+/// A comment of the thread beneath a post, in the order in which it is shown.
+#[derive(Serialize)]
+pub struct ThreadCommentView {
+	hash: String,
+	author: String,
+	/// How many replies deep the comment is, where comments on the post itself are 0.
+	depth: usize,
+	/// In seconds since the UNIX epoch, for tera's date filter.
+	timestamp: u64,
+	html: String
+}
+
+#[derive(Serialize)]
+pub struct AnnotationView {
+	id: i64,
+	passage: String,
+	note: Option<String>,
+	/// In seconds since the UNIX epoch, for tera's date filter.
+	created: u64
+}
+
+#[derive(Deserialize)]
+pub struct PostPermalinkParams {
+	id_type: String,
+	id: String,
+	post_id: u64
+}
+
+/// Finds the address of a channel that is given by its address, or by the name of our own ego.
+async fn resolve_channel_id( g: &Globals, id_type: &str, id: &str ) -> web_error::Result<PublicKey> {
+	match id_type {
+		"address" => PublicKey::from_string( id )
+			.ok_or_else(|| WebError::bad_request("Invalid channel address.")),
+		"ego" => ego_address( &g.services.lookup_ego( id ).await? ),
+		_ => Err( WebError::not_found("Unknown channel ID type.") )
+	}
+}
+
+/// The address of the channel of one of our egos.
+fn ego_address( private_key: &PrivateKey ) -> web_error::Result<PublicKey> {
+	private_key.extract_public()
+		.ok_or_else(|| WebError::internal("Unable to extract public key."))
+}
+
+/// Puts together a link to share the channel, or one of its posts, with.
+/// The peers in it are the ones we're exchanging messages with in the swarm, followed by the ones we remember from before.
+async fn load_share_link( g: &Globals, db: &persistence::Handle, address: &PublicKey, post_id: Option<u64> ) -> web_error::Result<ShareLink> {
+
+	let mut candidates = match g.subscriptions.read().await.as_ref().and_then(|s| s.node( address )) {
+		None => Vec::new(),
+		Some(node) => node.active_peers().await
+	};
+	if let Some(sub) = db.load_subscription( address ).await? {
+		candidates.extend( sub.cached_peers );
+	}
+
+	let mut peers: Vec<PublicKey> = Vec::with_capacity( config::SHARE_LINK_PEERS );
+	for peer in candidates {
+		if peers.len() == config::SHARE_LINK_PEERS { break }
+		if peer != *address && !peers.contains( &peer ) {
+			peers.push( peer );
+		}
+	}
+
+	Ok( ShareLink {
+		address: address.clone(),
+		peers,
+		post_id
+	})
+}
+
+#[derive(Deserialize)]
+pub struct ShareParams {
+	hint: String
+}
+
+#[derive(Deserialize)]
+pub struct SharePostParams {
+	hint: String,
+	post_id: u64
+}
+
+/// Opens a share link of a channel.
+#[get("/share/{hint}")]
+pub async fn share(g: web::Data<Arc<Globals>>, p: web::Path<ShareParams>) -> web_error::Result<HttpResponse> {
+	_share( &g, &p.hint, None ).await
+}
+
+/// Opens a share link of a post.
+#[get("/share/{hint}/post/{post_id}")]
+pub async fn share_post(g: web::Data<Arc<Globals>>, p: web::Path<SharePostParams>) -> web_error::Result<HttpResponse> {
+	_share( &g, &p.hint, Some( p.post_id ) ).await
+}
+
+/// Sends the reader to the channel or post if we follow the channel already.
+/// Otherwise, the reader is offered to follow it, through the peers of the link.
+async fn _share( g: &Globals, hint: &str, post_id: Option<u64> ) -> web_error::Result<HttpResponse> {
+
+	let mut link = ShareLink::parse_hint( hint )
+		.ok_or_else(|| WebError::bad_request("Invalid share link."))?;
+	link.post_id = post_id;
+
+	let db = g.connect_database().await?;
+	if db.clone().get_channel( &link.address ).await?.is_some() {
+		let location = match post_id {
+			None => format!("/channel/feed/address/{}", link.address),
+			Some(id) => format!("/channel/address/{}/post/{}", link.address, id)
+		};
+		return Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+	}
+
+	let mut context = tera::Context::new();
+	context.insert("address", &link.address.to_string());
+	context.insert("peers", &link.peers.iter().map(|p| p.to_string()).collect::<Vec<_>>());
+	context.insert("post_id", &post_id);
+	context.insert("link", &link.to_path());
+
+	let html = g.templates.render("share.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+#[derive(Deserialize)]
+pub struct UriQuery {
+	uri: String
+}
+
+/// Opens a `quartznet://` URI, like the share link that it stands for.
+#[get("/open")]
+pub async fn open_uri(q: web::Query<UriQuery>) -> web_error::Result<HttpResponse> {
+
+	let link = ShareLink::parse_uri( &q.uri )
+		.ok_or_else(|| WebError::bad_request("Invalid QuartzNet URI."))?;
+
+	Ok( HttpResponse::Found().append_header((header::LOCATION, link.to_path())).finish() )
+}
+
+/// Renders a `quartznet://` URI as a QR code.
+/// Nothing but those URIs is rendered, so that this doesn't become a QR code service for anything.
+#[get("/qr.svg")]
+pub async fn qr_code(q: web::Query<UriQuery>) -> web_error::Result<HttpResponse> {
+
+	let link = ShareLink::parse_uri( &q.uri )
+		.ok_or_else(|| WebError::bad_request("Invalid QuartzNet URI."))?;
+	let code = QrCode::new( link.to_uri().as_bytes() )
+		.map_err(|e| { error!("Unable to generate QR code: {}", e); WebError::internal("Unable to generate QR code") } )?;
+	let svg = code.render::<qrcode::render::svg::Color>()
+		.min_dimensions( 200, 200 )
+		.build();
+
+	Ok( HttpResponse::Ok()
+		.content_type("image/svg+xml")
+		.append_header((header::CONTENT_SECURITY_POLICY, "script-src 'none'"))
+		.append_header((header::CACHE_CONTROL, "max-age=3600"))
+		.body( svg ) )
+}
+
+/// Shows a whole post, with all of its earlier revisions.
+/// The channel can be given by its address, or by the name of our own ego, so that the page can be linked to either way.
+#[get("/channel/{id_type}/{id}/post/{post_id}")]
+pub async fn channel_post(g: web::Data<Arc<Globals>>, p: web::Path<PostPermalinkParams>) -> web_error::Result<HttpResponse> {
+
+	let address = resolve_channel_id( &g, &p.id_type, &p.id ).await?;
+
+	let db = g.connect_database().await?;
+	let timeline = db.get_timeline( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown publisher."))?;
+	let post = timeline.load_post( p.post_id ).await?
+		.ok_or_else(|| WebError::not_found("Post not found."))?;
+	// Only the publisher gets to see a post before it may be shown.
+	let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+	if !post.meta.info.is_visible_at( now ) && timeline.get_my_ego().await?.is_none() {
+		return Err( WebError::not_found("Post not found.") )
+	}
+
+	// The content may not have been received yet, in which case we ask the swarm for it.
+	let mut original = timeline.load_post_content( p.post_id ).await?;
+	if original.is_none() {
+		let node = g.subscriptions.read().await.as_ref().and_then(|s| s.node( &address ));
+		if let Some(node) = node {
+			if let Err(e) = node.sync_posts( &address, p.post_id, 1, true ).await {
+				warn!("Unable to request the content of post {} of {}: {}", p.post_id, address, e);
+			}
+			original = timeline.load_post_content( p.post_id ).await?;
+		}
+	}
+
+	let mut revisions = Vec::new();
+	let mut annotations = Vec::new();
+	if let Some(original) = original {
+		revisions.push( RevisionView {
+			number: 0,
+			received_timestamp: post.meta.info.publish_timestamp,
+			html: preview::render( &original, post.meta.info.format )
+		});
+		let mut current = original;
+		for revision in timeline.load_revisions( p.post_id ).await? {
+			revisions.push( RevisionView {
+				number: revision.number,
+				received_timestamp: revision.received_timestamp,
+				html: preview::render( &revision.content, post.meta.info.format )
+			});
+			current = revision.content;
+		}
+
+		// Our own highlights are shown on the newest revision, which is what their offsets refer to.
+		annotations = db.load_annotations( &post.hash ).await?;
+		if annotations.len() > 0 && render::for_format( post.meta.info.format ).supports_highlights() {
+			let highlights: Vec<(usize, usize)> = annotations.iter().map(|a| (a.start as usize, a.end as usize)).collect();
+			let context = RenderContext { highlights: &highlights };
+			revisions.last_mut().unwrap().html = preview::render_with( &current, post.meta.info.format, &context );
+		}
+	}
+	let annotations: Vec<AnnotationView> = annotations.into_iter().map(|a| AnnotationView {
+		id: a.id,
+		passage: a.passage,
+		note: a.note,
+		created: a.created / 1000
+	}).collect();
+
+	// The newest revision is shown first.
+	revisions.reverse();
+
+	// The comments are posts on the timelines of the commenters, that link back to this post.
+	let mut comments = Vec::new();
+	for comment in db.load_comments( &address, &post.hash ).await? {
+		let html = match db.get_timeline( &comment.publisher ).await? {
+			None => None,
+			Some(t) => match t.load_post( comment.post_id ).await? {
+				None => None,
+				Some(c) => t.load_current_content( comment.post_id ).await?.map(|content| preview::render( &content, c.meta.info.format ))
+			}
+		};
+		comments.push( CommentView {
+			address: comment.publisher.to_string(),
+			post_id: comment.post_id,
+			publish_timestamp: comment.publish_timestamp / 1000,
+			html
+		});
+	}
+
+	let thread = thread_views( timeline.load_comment_thread( p.post_id ).await? );
+
+	// The egos that can be used to comment with
+	let mut egos = Vec::new();
+	for timeline in db.list_my_timelines().await? {
+		if let Some(ego) = timeline.get_my_ego().await? {
+			egos.push( ego );
+		}
+	}
+
+	let mut context = tera::Context::new();
+	context.insert("address", &address.to_string());
+	context.insert("post_id", &p.post_id);
+	context.insert("info", &post.meta.info);
+	context.insert("publish_timestamp", &(post.meta.info.publish_timestamp / 1000));
+	context.insert("attachments", &post.meta.attachment_ids.iter().map(|h| h.to_string()).collect::<Vec<_>>());
+	context.insert("revisions", &revisions);
+	context.insert("excerpt", &preview_cache::load_excerpt( &timeline, &post ).await?);
+	context.insert("comments", &comments);
+	context.insert("thread", &thread);
+	context.insert("comment_max_len", &COMMENT_MAX_LEN);
+	context.insert("reactions", &load_reaction_counts( &timeline, p.post_id ).await?);
+	context.insert("reaction_kinds", config::REACTION_KINDS);
+	context.insert("annotations", &annotations);
+	context.insert("egos", &egos);
+	let share_link = load_share_link( &g, &db, &address, Some( p.post_id ) ).await?;
+	context.insert("share_link", &share_link.to_path());
+	context.insert("share_uri", &share_link.to_uri());
+	// The ego that this post can be edited with, if it is one of ours.
+	context.insert("own_ego", &timeline.get_my_ego().await?);
+	// The ego that the comments can be moderated with, if it owns the channel of the post.
+	let moderator = match timeline.get_channel().await? {
+		None => None,
+		Some(channel) => channel.load_owner_ego().await?
+	};
+	context.insert("moderator", &moderator);
+
+	let html = g.templates.render("blog/post.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+#[derive(Deserialize)]
+pub struct CommentForm {
+	ego: String,
+	message: String
+}
+
+/// Comments on a post, by publishing a post that links back to it on the timeline of one of our own egos.
+/// This way, no write access to the channel of the post is needed.
+#[post("/channel/address/{address}/post/{post_id}/comment")]
+pub async fn channel_post_comment(g: web::Data<Arc<Globals>>, p: web::Path<PostParams>, form: web::Form<CommentForm>) -> web_error::Result<HttpResponse> {
+
+	let address = PublicKey::from_string( &p.address )
+		.ok_or_else(|| WebError::bad_request("Invalid channel address."))?;
+	if form.message.trim().is_empty() {
+		return Err( WebError::bad_request("A comment can't be empty.") )
+	}
+
+	let db = g.connect_database().await?;
+	let post = db.get_timeline( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown publisher."))?
+		.load_post( p.post_id ).await?
+		.filter(|post| post.meta.info.is_visible_at( SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as _ ))
+		.ok_or_else(|| WebError::not_found("Post not found."))?;
+
+	let private_key = g.services.lookup_ego( &form.ego ).await?;
+	let timeline = db.get_timeline( &ego_address( &private_key )? ).await?
+		.ok_or_else(|| WebError::not_found("Unknown publisher."))?;
+
+	let post_info = PostInfo {
+		tags: Vec::new(),
+		publish_timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as _,
+		visible_from: None,
+		format: ContentFormat::Plain,
+		series: None,
+		content_warning: None
+	};
+	let reply_to = PostReference {
+		channel: address,
+		post_hash: post.hash
+	};
+	let (_, comment) = timeline.create_post( &private_key, &form.message, post_info, Vec::new(), Some( reply_to ) ).await?;
+	db.clone().get_channel( &ego_address( &private_key )? ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?
+		.log_new_post( &timeline, &comment ).await?;
+	db.record_action( Some( &form.ego ), AuditAction::PostCreated, &post_subject( &ego_address( &private_key )?, comment.id ) ).await?;
+
+	let location = format!("/channel/address/{}/post/{}", p.address, p.post_id);
+	Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+}
+
+/// The deepest that replies are indented, replies to deeper comments are shown at this depth.
+const THREAD_MAX_DEPTH: usize = 6;
+
+/// Puts the comments in the order in which they are shown: every comment is followed by its replies, oldest first.
+/// Replies to comments that we don't have are shown as comments on the post itself.
+fn thread_views( comments: Vec<StoredComment> ) -> Vec<ThreadCommentView> {
+
+	fn add_replies( comments: &[StoredComment], parent: Option<&HashCode>, depth: usize, views: &mut Vec<ThreadCommentView> ) {
+		for comment in comments {
+			let is_top = comment.reply_to.as_ref().map(|h| !comments.iter().any(|c| c.hash == *h)).unwrap_or(true);
+			let matches = match parent {
+				None => is_top,
+				Some(parent) => comment.reply_to.as_ref() == Some( parent )
+			};
+			if !matches { continue }
+
+			views.push( ThreadCommentView {
+				hash: comment.hash.to_string(),
+				author: comment.author.to_string(),
+				depth: min( depth, THREAD_MAX_DEPTH ),
+				timestamp: comment.timestamp / 1000,
+				html: preview::render( &comment.content, ContentFormat::Plain )
+			});
+			add_replies( comments, Some( &comment.hash ), depth + 1, views );
+		}
+	}
+
+	let mut views = Vec::with_capacity( comments.len() );
+	add_replies( &comments, None, 0, &mut views );
+	views
+}
+
+#[derive(Deserialize)]
+pub struct ReplyForm {
+	ego: String,
+	message: String,
+	/// The hash of the comment that is replied to, or `None` to comment on the post itself.
+	reply_to: Option<String>
+}
+
+/// Comments on a post, or replies to a comment on it, with one of our own egos.
+/// The comment is spread in the swarm of the channel of the post, so that everyone who follows the channel can read it.
+#[post("/channel/address/{address}/post/{post_id}/reply")]
+pub async fn channel_post_reply(g: web::Data<Arc<Globals>>, p: web::Path<PostParams>, form: web::Form<ReplyForm>) -> web_error::Result<HttpResponse> {
+
+	let address = PublicKey::from_string( &p.address )
+		.ok_or_else(|| WebError::bad_request("Invalid channel address."))?;
+	let message = form.message.trim();
+	if message.is_empty() {
+		return Err( WebError::bad_request("A comment can't be empty.") )
+	}
+	if message.len() > COMMENT_MAX_LEN {
+		return Err( WebError::bad_request("The comment is too long.") )
+	}
+
+	let db = g.connect_database().await?;
+	let timeline = db.get_timeline( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown publisher."))?;
+	timeline.load_post( p.post_id ).await?
+		.filter(|post| post.meta.info.is_visible_at( SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as _ ))
+		.ok_or_else(|| WebError::not_found("Post not found."))?;
+
+	let reply_to = match form.reply_to.as_deref().map(str::trim).filter(|h| !h.is_empty()) {
+		None => None,
+		Some(hash) => {
+			let hash = HashCode::from_string( hash )
+				.ok_or_else(|| WebError::bad_request("Invalid hash of the comment to reply to."))?;
+			if !timeline.has_comment( p.post_id, &hash ).await? {
+				return Err( WebError::not_found("The comment to reply to wasn't found.") )
+			}
+			Some( hash )
+		}
+	};
+
+	let private_key = g.services.lookup_ego( &form.ego ).await?;
+	let channel = timeline.get_channel().await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+
+	let data = channel.post_comment( &timeline, &private_key, p.post_id, message, reply_to ).await?
+		.ok_or_else(|| WebError::not_found("Post not found."))?;
+	db.record_action( Some( &form.ego ), AuditAction::CommentPosted, &format!("{} on {}", data.hash, post_subject( &address, p.post_id )) ).await?;
+
+	let location = format!("/channel/address/{}/post/{}#comment-{}", p.address, p.post_id, data.hash);
+	Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+}
+
+#[derive(Deserialize)]
+pub struct ReactForm {
+	ego: String,
+	kind: String
+}
+
+/// Reacts to a post with one of our own egos, replacing the earlier reaction of that ego to the post, if any.
+#[post("/channel/address/{address}/post/{post_id}/react")]
+pub async fn channel_post_react(g: web::Data<Arc<Globals>>, p: web::Path<PostParams>, form: web::Form<ReactForm>) -> web_error::Result<HttpResponse> {
+
+	let address = PublicKey::from_string( &p.address )
+		.ok_or_else(|| WebError::bad_request("Invalid channel address."))?;
+	let kind = form.kind.trim();
+	if kind.is_empty() || kind.len() > REACTION_MAX_LEN || kind.chars().any(char::is_whitespace) {
+		return Err( WebError::bad_request("Invalid reaction.") )
+	}
+
+	let db = g.connect_database().await?;
+	let timeline = db.get_timeline( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown publisher."))?;
+	timeline.load_post( p.post_id ).await?
+		.filter(|post| post.meta.info.is_visible_at( SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as _ ))
+		.ok_or_else(|| WebError::not_found("Post not found."))?;
+
+	let private_key = g.services.lookup_ego( &form.ego ).await?;
+	let channel = timeline.get_channel().await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+
+	channel.post_reaction( &timeline, &private_key, p.post_id, kind ).await?
+		.ok_or_else(|| WebError::not_found("Post not found."))?;
+	db.record_action( Some( &form.ego ), AuditAction::ReactionPosted, &format!("{} on {}", kind, post_subject( &address, p.post_id )) ).await?;
+
+	let location = format!("/channel/address/{}/post/{}", p.address, p.post_id);
+	Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+}
+
+#[derive(Deserialize)]
+pub struct AnnotateForm {
+	/// The text that has been selected in the post.
+	passage: String,
+	note: String
+}
+
+/// Highlights a passage of the current content of a post, with an optional note.
+/// Annotations are only stored locally.
+#[post("/channel/address/{address}/post/{post_id}/annotate")]
+pub async fn channel_post_annotate(g: web::Data<Arc<Globals>>, p: web::Path<PostParams>, form: web::Form<AnnotateForm>) -> web_error::Result<HttpResponse> {
+
+	let address = PublicKey::from_string( &p.address )
+		.ok_or_else(|| WebError::bad_request("Invalid channel address."))?;
+	if form.passage.trim().is_empty() {
+		return Err( WebError::bad_request("Select a passage to highlight first.") )
+	}
+
+	let db = g.connect_database().await?;
+	let timeline = db.get_timeline( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown publisher."))?;
+	let post = timeline.load_post( p.post_id ).await?
+		.ok_or_else(|| WebError::not_found("Post not found."))?;
+	let content = timeline.load_current_content( p.post_id ).await?
+		.ok_or_else(|| WebError::service_unavailable("The content of this post is not available yet."))?;
+
+	// The offsets are in characters, as the browser doesn't know about the bytes of the content.
+	let index = content.find( &form.passage )
+		.ok_or_else(|| WebError::bad_request("The passage doesn't occur in the post."))?;
+	let start = content[..index].chars().count() as u64;
+	let end = start + form.passage.chars().count() as u64;
+	let note = Some( form.note.trim() ).filter(|n| n.len() > 0);
+	db.store_annotation( &address, &post.hash, start, end, &form.passage, note ).await?;
+
+	let location = format!("/channel/address/{}/post/{}", p.address, p.post_id);
+	Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+}
+
+#[derive(Deserialize)]
+pub struct AnnotationParams {
+	address: String,
+	post_id: u64,
+	id: i64
+}
+
+#[post("/channel/address/{address}/post/{post_id}/annotation/{id}/delete")]
+pub async fn channel_post_annotation_delete(g: web::Data<Arc<Globals>>, p: web::Path<AnnotationParams>) -> web_error::Result<HttpResponse> {
+
+	let db = g.connect_database().await?;
+	if !db.delete_annotation( p.id ).await? {
+		return Err( WebError::not_found("Annotation not found.") )
+	}
+
+	let location = format!("/channel/address/{}/post/{}", p.address, p.post_id);
+	Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+}
+
+#[derive(Serialize)]
+pub struct AnnotationExport {
+	channel: String,
+	post_hash: String,
+	start: u64,
+	end: u64,
+	passage: String,
+	note: Option<String>,
+	/// In milliseconds since the UNIX epoch.
+	created: u64
+}
+
+/// Exports everything that the reader has written down for themselves, as a JSON file.
+#[get("/export/annotations.json")]
+pub async fn export_annotations(g: web::Data<Arc<Globals>>) -> web_error::Result<HttpResponse> {
+
+	let db = g.connect_database().await?;
+	let annotations: Vec<AnnotationExport> = db.list_annotations().await?.into_iter().map(|a| AnnotationExport {
+		channel: a.channel.to_string(),
+		post_hash: a.post_hash.to_string(),
+		start: a.start,
+		end: a.end,
+		passage: a.passage,
+		note: a.note,
+		created: a.created
+	}).collect();
+
+	Ok( HttpResponse::Ok()
+		.append_header((header::CONTENT_DISPOSITION, "attachment; filename=\"annotations.json\""))
+		.json( annotations ) )
+}
+
+#[derive(Deserialize)]
+pub struct SyndicationParams {
+	id_type: String,
+	id: String,
+	/// Either `rss` or `atom`.
+	format: String
+}
+
+#[derive(Serialize)]
+pub struct SyndicationEntry {
+	link: String,
+	title: String,
+	/// The plain text excerpt of the post.
+	summary: Option<String>,
+	/// In seconds since the UNIX epoch, for tera's date filter.
+	publish_timestamp: u64,
+	tags: Vec<String>,
+	html: String
+}
+
+/// Serves the latest posts of a channel as an RSS 2.0 or an Atom feed, so that it can be followed with a regular feed reader.
+/// This needs to be registered before `channel_feed`, which would otherwise take the format for a page number.
+#[get("/channel/feed/{id_type}/{id}/{format:rss|atom}")]
+pub async fn channel_syndication(g: web::Data<Arc<Globals>>, p: web::Path<SyndicationParams>, req: HttpRequest) -> web_error::Result<HttpResponse> {
+
+	let address = resolve_channel_id( &g, &p.id_type, &p.id ).await?;
+	let db = g.connect_database().await?;
+	let channel = db.clone().get_channel( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+	let mut timeline = channel.get_timeline( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown publisher."))?;
+
+	// Feed readers need absolute links.
+	let base_url = {
+		let info = req.connection_info();
+		format!("{}://{}", info.scheme(), info.host())
+	};
+	let title = if p.id_type == "ego" { p.id.clone() } else { address.to_string() };
+
+	let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+	let mut entries = Vec::new();
+	let latest = if db.is_publisher_blocked( &address ).await? { None } else { timeline.load_latest_post_id().await? };
+	if let Some(latest) = latest {
+		let start = (latest + 1).saturating_sub( config::SYNDICATION_POSTS );
+		let posts = timeline.list_posts( start, (latest + 1 - start) as _ ).await?;
+
+		for post in posts.into_iter().rev().flatten() {
+			if !post.meta.info.is_visible_at( now ) { continue }
+			// The title is taken from the content itself, only the rendering of the content is cached.
+			let content = match timeline.load_current_content( post.id ).await? {
+				None => continue,
+				Some(c) => c
+			};
+			let rendered = match preview_cache::load_full( &timeline, &post ).await? {
+				None => continue,
+				Some(r) => r
+			};
+
+			entries.push( SyndicationEntry {
+				link: format!("{}/channel/address/{}/post/{}", base_url, address, post.id),
+				title: syndication_title( &content, post.meta.info.format ),
+				summary: preview_cache::load_excerpt( &timeline, &post ).await?,
+				publish_timestamp: post.meta.info.publish_timestamp / 1000,
+				tags: post.meta.info.tags.clone(),
+				html: rendered.html.clone()
+			});
+		}
+	}
+
+	let mut context = tera::Context::new();
+	context.insert("title", &title);
+	context.insert("address", &address.to_string());
+	context.insert("link", &format!("{}/channel/feed/address/{}", base_url, address));
+	context.insert("self_link", &format!("{}{}", base_url, req.path()));
+	context.insert("updated", &entries.first().map(|e| e.publish_timestamp).unwrap_or( now / 1000 ));
+	context.insert("entries", &entries);
+
+	let (template_file, content_type) = match &*p.format {
+		"atom" => ("feeds/atom.xml", "application/atom+xml"),
+		_ => ("feeds/rss.xml", "application/rss+xml")
+	};
+	let xml = g.templates.render(template_file, &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok(HttpResponse::Ok().content_type(content_type).body(xml))
+}
+
+/// Posts don't have a title, so the start of their first line of text is used.
+pub fn syndication_title( content: &str, format: ContentFormat ) -> String {
+	const MAX_CHARS: usize = 80;
+
+	preview::first_line( content, format, MAX_CHARS )
+}
+
+#[derive(Deserialize)]
+pub struct WebFingerQuery {
+	resource: String
+}
+
+/// Lets ActivityPub servers find the actor of a channel by its account, `acct:{address}@{host}`.
+#[get("/.well-known/webfinger")]
+pub async fn webfinger(g: web::Data<Arc<Globals>>, q: web::Query<WebFingerQuery>) -> web_error::Result<HttpResponse> {
+
+	let account = q.resource.strip_prefix("acct:").unwrap_or( &q.resource );
+	let address = account.split('@').next().unwrap_or("");
+	let (base_url, address, _) = federated_channel( &g, address ).await?;
+
+	let actor = activitypub::actor_id( &base_url, &address );
+	let links = serde_json::json!({
+		"subject": format!("acct:{}", account),
+		"aliases": [actor],
+		"links": [{
+			"rel": "self",
+			"type": activitypub::ACTIVITY_CONTENT_TYPE,
+			"href": actor
+		}, {
+			"rel": "http://webfinger.net/rel/profile-page",
+			"type": "text/html",
+			"href": format!("{}/channel/feed/address/{}", base_url, address)
+		}]
+	});
+	Ok( HttpResponse::Ok().content_type("application/jrd+json").body( links.to_string() ) )
+}
+
+/// Serves the ActivityPub actor of a channel, through which it can be followed.
+#[get("/channel/{address}/actor")]
+pub async fn channel_actor(g: web::Data<Arc<Globals>>, p: web::Path<ChannelAddressParams>) -> web_error::Result<HttpResponse> {
+
+	let (base_url, address, channel) = federated_channel( &g, &p.address ).await?;
+	let signer = Signer::load( &channel, &base_url, &address ).await.map_err( federation_error )?;
+	let (title, description) = channel.fetch_profile().await?
+		.map(|p| (p.base.title, p.base.description))
+		.unwrap_or_default();
+
+	let actor = activitypub::actor( &base_url, &address, &title, &description, &signer ).map_err( federation_error )?;
+	Ok( activity_response( &actor ) )
+}
+
+/// Serves the latest posts of the owner of a channel as `Create` activities.
+#[get("/channel/{address}/outbox")]
+pub async fn channel_outbox(g: web::Data<Arc<Globals>>, p: web::Path<ChannelAddressParams>) -> web_error::Result<HttpResponse> {
+
+	let (base_url, address, channel) = federated_channel( &g, &p.address ).await?;
+	let mut timeline = channel.get_timeline( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown publisher."))?;
+
+	let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+	let mut items = Vec::new();
+	if let Some(latest) = timeline.load_latest_post_id().await? {
+		let start = (latest + 1).saturating_sub( config::FEDERATION_OUTBOX_POSTS );
+		let posts = timeline.list_posts( start, (latest + 1 - start) as _ ).await?;
+
+		for post in posts.into_iter().rev().flatten() {
+			if !post.meta.info.is_visible_at( now ) { continue }
+			if let Some(activity) = activitypub::create_activity( &base_url, &address, &timeline, &post ).await.map_err( federation_error )? {
+				items.push( activity );
+			}
+		}
+	}
+
+	Ok( activity_response( &serde_json::json!({
+		"@context": "https://www.w3.org/ns/activitystreams",
+		"id": format!("{}/channel/{}/outbox", base_url, address),
+		"type": "OrderedCollection",
+		"totalItems": items.len(),
+		"orderedItems": items
+	}) ) )
+}
+
+/// Serves the number of followers of a channel on ActivityPub servers.
+/// The followers themselves aren't listed, because they are nobody else's business.
+#[get("/channel/{address}/followers")]
+pub async fn channel_followers(g: web::Data<Arc<Globals>>, p: web::Path<ChannelAddressParams>) -> web_error::Result<HttpResponse> {
+
+	let (base_url, address, channel) = federated_channel( &g, &p.address ).await?;
+	let followers = channel.list_followers().await?;
+
+	Ok( activity_response( &serde_json::json!({
+		"@context": "https://www.w3.org/ns/activitystreams",
+		"id": format!("{}/channel/{}/followers", base_url, address),
+		"type": "OrderedCollection",
+		"totalItems": followers.len()
+	}) ) )
+}
+
+/// Receives the activities that ActivityPub servers send to a channel.
+/// Follows make their actors followers of the channel, and are accepted right away.
+/// Undone follows remove their actors again, and all other activities are ignored.
+#[post("/channel/{address}/inbox")]
+pub async fn channel_inbox(g: web::Data<Arc<Globals>>, p: web::Path<ChannelAddressParams>, req: HttpRequest, body: web::Bytes) -> web_error::Result<HttpResponse> {
+
+	let (base_url, address, channel) = federated_channel( &g, &p.address ).await?;
+	let signer = Signer::load( &channel, &base_url, &address ).await.map_err( federation_error )?;
+	let activity: serde_json::Value = serde_json::from_slice( &body )
+		.map_err(|_| WebError::bad_request("Invalid activity."))?;
+
+	let actor = activitypub::verify( &req, &body, &signer ).await
+		.map_err(|e| { warn!("Refused an activity for channel {}: {}", address, e); WebError::unauthorized("Invalid signature.") })?;
+	let actor_id = actor["id"].as_str().unwrap_or("");
+	// A server can only act on behalf of the actors whose keys it holds.
+	if activity["actor"].as_str() != Some( actor_id ) {
+		return Err( WebError::forbidden("The activity is signed by another actor.") )
+	}
+
+	let object = &activity["object"];
+	let own_actor = activitypub::actor_id( &base_url, &address );
+	match activity["type"].as_str() {
+		Some("Follow") if object.as_str().or( object["id"].as_str() ) == Some( &own_actor ) => {
+			let inbox = actor["inbox"].as_str()
+				.ok_or_else(|| WebError::bad_request("The actor has no inbox."))?
+				.to_owned();
+			micropub::check_public_url( &inbox ).await
+				.map_err(|_| WebError::bad_request("The inbox of the actor isn't public."))?;
+			// Only the posts that are published from now on are delivered.
+			let next_post_id = match channel.get_timeline( &address ).await? {
+				None => 0,
+				Some(timeline) => timeline.load_latest_post_id().await?.map(|id| id + 1).unwrap_or(0)
+			};
+			channel.add_follower( actor_id, &inbox, next_post_id ).await?;
+
+			let accept = activitypub::accept_activity( &base_url, &address, &activity );
+			actix_web::rt::spawn(async move {
+				if let Err(e) = activitypub::deliver( &signer, &inbox, &accept ).await {
+					warn!("Unable to accept a follow of channel {}: {}", address, e);
+				}
+			});
+		},
+		Some("Undo") if object["type"].as_str() == Some("Follow") => {
+			channel.remove_follower( actor_id ).await?;
+		},
+		_ => {}
+	}
+
+	Ok( HttpResponse::Accepted().finish() )
+}
+
+/// Finds the channel that an ActivityPub request is for, if channels are federated at all.
+/// Returns the public URL of the web interface along with it.
+/// Only the public channels that we own are federated, all other channels are as unknown to ActivityPub as they are to anybody else.
+async fn federated_channel( g: &Globals, address: &str ) -> web_error::Result<(String, PublicKey, persistence::channel::Handle)> {
+
+	let base_url = activitypub::base_url()
+		.ok_or_else(|| WebError::not_found("Federation is disabled."))?;
+	let address = PublicKey::from_string( address )
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+	let db = g.connect_database().await?;
+	let channel = db.get_channel( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+	if channel.load_owner_ego().await?.is_none() || channel.is_private().await? {
+		return Err( WebError::not_found("Unknown channel.") )
+	}
+
+	Ok(( base_url, address, channel ))
+}
+
+fn activity_response( value: &serde_json::Value ) -> HttpResponse {
+	HttpResponse::Ok().content_type( activitypub::ACTIVITY_CONTENT_TYPE ).body( value.to_string() )
+}
+
+fn federation_error( e: activitypub::Error ) -> WebError {
+	error!("Federation error: {}", e);
+	WebError::internal("Federation error")
+}
+
+#[get("/channel/feed/{id_type}/{id}/{page}")]
+pub async fn channel_feed(g: web::Data<Arc<Globals>>, req: HttpRequest, p: web::Path<BlogFeedParams>, f: web::Query<FeedFilterQuery>) -> web_error::Result<HttpResponse> {
+	_channel_feed(g, &req, &p.id, &p.id_type, p.page, &f).await
+}
+
+/// The filters of a feed, as they are given in its query string.
+/// The filter form sends all of the fields, so the empty ones mean that the posts aren't filtered by them.
+#[derive(Default, Deserialize, Serialize)]
+pub struct FeedFilterQuery {
+	#[serde(default)]
+	tag: String,
+	/// The address of the publisher whose posts are shown, which is the channel itself by default.
+	#[serde(default)]
+	publisher: String,
+	/// The first day of which the posts are shown, as `YYYY-MM-DD` in the timezone of the user.
+	#[serde(default)]
+	from: String,
+	/// The last day of which the posts are shown, like `from`.
+	#[serde(default)]
+	until: String
+}
+
+impl FeedFilterQuery {
+
+	fn is_empty( &self ) -> bool {
+		self.tag.trim().is_empty() && self.publisher.trim().is_empty() && self.from.trim().is_empty() && self.until.trim().is_empty()
+	}
+
+	/// Turns the fields that are about the posts themselves into a filter for the timeline.
+	async fn to_post_filter( &self, db: &persistence::Handle ) -> web_error::Result<PostFilter> {
+
+		let tag = match self.tag.trim() {
+			"" => None,
+			tag => Some( normalize_tags( std::iter::once( tag ) ).pop()
+				.ok_or_else(|| WebError::bad_request("Invalid tag."))? )
+		};
+		let since = parse_local_date( db, &self.from ).await?;
+		// The last day is included, so the posts are the ones from before the start of the day after it.
+		let until = parse_local_date( db, &self.until ).await?.map(|t| t + 24 * 60 * 60 * 1000);
+		if let (Some(since), Some(until)) = (since, until) {
+			if since >= until {
+				return Err( WebError::bad_request("The first day comes after the last day.") )
+			}
+		}
+
+		Ok( PostFilter { tag, since, until } )
+	}
+}
+
+#[derive(Deserialize)]
+pub struct PostCreateParams {
+	message: String,
+	tags: String
+}
+
+async fn _channel_feed( g: web::Data<Arc<Globals>>, req: &HttpRequest, id: &str, id_type: &str, page: u32, filter: &FeedFilterQuery ) -> web_error::Result<HttpResponse> {
+	let page_size = config::get().page_size as u64;
+
+	if page == 0 {
+		return Err( WebError::not_found("Page not found.") )
+	}
+	let public_key = resolve_channel_id( &g, id_type, id ).await?;
+	let address = public_key.to_string();
+	let local = id_type == "ego";
+
+	let mut context = tera::Context::new();
+	context.insert("address", &address);
+
+	let channel = g.connect_database().await?
+		.get_channel( &public_key ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+	// Only the unfiltered pages are cached, as there are too many ways to filter them.
+	let filtered = !filter.is_empty();
+	if !local && !filtered {
+		if let Some(cached) = page_cache::PAGES.get( channel.id, page ) {
+			return Ok( page_response( req, &cached, true ) )
+		}
+	}
+	// Only the owner shares the invite code of a private channel.
+	if local {
+		context.insert("ego", id);
+		context.insert("invite_code", &channel.load_invite_code().await?.map(|c| c.to_string()));
+		context.insert("timezone", &format_utc_offset( load_timezone_offset( &channel.base ).await? ));
+		context.insert("grace_days", &config::OWNERSHIP_GRACE_PERIOD_DAYS);
+		context.insert("held_comments", &channel.count_held_comments().await?);
+		context.insert("directory_keywords", &channel.load_directory_keywords().await?);
+	}
+	// In seconds since the UNIX epoch, for tera's date filter.
+	context.insert("closed", &channel.load_closed_timestamp().await?.map(|t| t / 1000));
+	// A transfer is shown on both sides, so that the new owner can accept it.
+	if let Some(transfer) = channel.load_pending_transfer().await? {
+		context.insert("transfer_to", &transfer.new_owner.to_string());
+	}
+	let share_link = load_share_link( &g, &channel.base, &public_key, None ).await?;
+	context.insert("share_link", &share_link.to_path());
+	context.insert("share_uri", &share_link.to_uri());
+	// The feed can show the posts of one of the other publishers of the channel instead.
+	let channel_publishers = channel.list_publishers().await?;
+	let publisher = match filter.publisher.trim() {
+		"" => public_key.clone(),
+		address => {
+			let publisher = PublicKey::from_string( address )
+				.ok_or_else(|| WebError::bad_request("Invalid publisher address."))?;
+			if publisher != public_key && !channel_publishers.contains( &publisher ) {
+				return Err( WebError::not_found("This channel has no such publisher.") )
+			}
+			publisher
+		}
+	};
+	let mut db = channel.get_timeline( &publisher ).await?
+		.ok_or_else(|| WebError::not_found("Unknown publisher."))?;
+	if local {
+		context.insert("drafts", &load_drafts( &db ).await?);
+	}
+	context.insert("post_address", &publisher.to_string());
+	context.insert("filter", filter);
+	context.insert("filtered", &filtered);
+	context.insert("filter_publishers", &channel_publishers.iter().map(|p| p.to_string()).collect::<Vec<_>>());
+	// The other publishers can be blocked by those who follow the channel.
+	if !local {
+		let mut publishers = Vec::new();
+		for publisher in &channel_publishers {
+			publishers.push( PublisherBlockView {
+				blocked: channel.base.is_publisher_blocked( publisher ).await?,
+				address: publisher.to_string()
+			});
+		}
+		context.insert("publishers", &publishers);
+	}
+
+	// A channel can be run by a publisher that we've blocked in another channel.
+	let blocked = !local && channel.base.is_publisher_blocked( &publisher ).await?;
+	context.insert("blocked", &blocked);
+
+	if filtered {
+		let post_filter = filter.to_post_filter( &channel.base ).await?;
+		let post_count = if blocked { 0 } else { db.count_filtered_posts( &post_filter, local ).await? };
+		let last_page = ((post_count + page_size - 1) / page_size).max(1);
+		if page as u64 > last_page {
+			return Err( WebError::not_found("Page not found.") )
+		}
+		let posts = if blocked { Vec::new() } else {
+			db.list_filtered_posts( &post_filter, local, (page as u64 - 1) * page_size, page_size as _ ).await?
+		};
+
+		// The posts are newest first already, and aren't mixed with the system posts, which don't have a publisher or tags.
+		let mut feed = Vec::with_capacity( posts.len() );
+		for post in posts {
+			let post_id = post.id;
+			feed.extend( load_post_previews( &db, post_id, &[Some( post )], local ).await?.into_iter().map( FeedEntry::Post ) );
+		}
+		context.insert("feed", &feed);
+		context.insert("pinned", &Vec::<PostPreview>::new());
+		context.insert("pinned_ids", &Vec::<String>::new());
+		context.insert("pinned_max", &PINNED_POSTS_MAX);
+		context.insert("page", &page);
+		context.insert("last_page", &last_page);
+		context.insert("pages", &page_numbers( page as u64, last_page ));
+		context.insert("page_link", &format!("/channel/feed/{}/{}", id_type, id));
+		context.insert("tag_cloud", &load_tag_cloud( &db, local ).await?);
+		context.insert("preview_widths", config::PREVIEW_IMAGE_WIDTHS);
+
+		let template_file = if local { "blog/own-feed.html" } else { "blog/feed.html" };
+		let html = g.templates.render(template_file, &context)
+			.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+		return Ok( page_response( req, &Page::new( html ), false ) )
+	}
+
+	// The first page has the newest posts, so a page holds the ids from `start` up to the ones of the page before it.
+	let post_count = db.load_latest_post_id().await?.map(|id| id + 1).unwrap_or(0);
+	let last_page = ((post_count + page_size - 1) / page_size).max(1);
+	if page as u64 > last_page {
+		return Err( WebError::not_found("Page not found.") )
+	}
+	let end = post_count.saturating_sub( (page as u64 - 1) * page_size );
+	let start = end.saturating_sub( page_size );
+	let posts = if end > start && !blocked { db.list_posts( start, (end - start) as _ ).await? } else { Vec::new() };
+
+	let post_previews = load_post_previews( &db, start, &*posts, local ).await?;
+	// The pinned posts are shown above the first page, in the order in which the owner has pinned them.
+	let pinned_ids = channel.load_pinned_posts().await?;
+	let mut pinned = Vec::new();
+	if page == 1 && !blocked {
+		for post_id in &pinned_ids {
+			if let Some(post) = db.load_post( *post_id ).await? {
+				pinned.extend( load_post_previews( &db, *post_id, &[Some( post )], local ).await? );
+			}
+		}
+	}
+	context.insert("pinned", &pinned);
+	context.insert("pinned_ids", &pinned_ids.iter().map(|id| id.to_string()).collect::<Vec<_>>());
+	context.insert("pinned_max", &PINNED_POSTS_MAX);
+	// The system posts that are shown on this page are the ones from the time of the first post on it, until the first post of the newer page.
+	let since = if start > 0 { first_post_timestamp( &db, start ).await?.unwrap_or(0) } else { 0 };
+	let until = first_post_timestamp( &db, end ).await?;
+	let system_posts = channel.list_system_posts( since, until ).await?;
+	let language = load_language( &channel.base ).await?;
+	let mut feed = merge_system_posts( post_previews, system_posts, language );
+	feed.reverse();
+	context.insert("feed", &feed);
+	context.insert("page", &page);
+	context.insert("last_page", &last_page);
+	context.insert("pages", &page_numbers( page as u64, last_page ));
+	context.insert("page_link", &format!("/channel/feed/{}/{}", id_type, id));
+	context.insert("tag_cloud", &load_tag_cloud( &db, local ).await?);
+	context.insert("preview_widths", config::PREVIEW_IMAGE_WIDTHS);
+
+	let template_file = if local { "blog/own-feed.html" } else { "blog/feed.html" };
+
+	let html = g.templates.render(template_file, &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	if local {
+		Ok( page_response( req, &Page::new( html ), false ) )
+	} else {
+		Ok( page_response( req, &page_cache::PAGES.insert( channel.id, page, html ), true ) )
+	}
+}
+
+/// Responds with the page, or only tells that it hasn't changed if the browser asks for it with the tag of the same page.
+/// Browsers need to ask every time, as the page can change at any moment, but only public pages may be kept by gateways.
+fn page_response( req: &HttpRequest, page: &Page, public: bool ) -> HttpResponse {
+	let cache_control = if public { "no-cache" } else { "private, no-cache" };
+
+	let unchanged = req.headers().get( header::IF_NONE_MATCH )
+		.and_then(|v| v.to_str().ok())
+		.map(|v| page.matches( v ))
+		.unwrap_or(false);
+	if unchanged {
+		return HttpResponse::NotModified()
+			.append_header((header::ETAG, page.etag.clone()))
+			.append_header((header::CACHE_CONTROL, cache_control))
+			.finish()
+	}
+
+	HttpResponse::Ok()
+		.content_type("text/html")
+		.append_header((header::ETAG, page.etag.clone()))
+		.append_header((header::CACHE_CONTROL, cache_control))
+		.body( page.html.clone() )
+}
+
+#[derive(Serialize)]
+pub struct PublisherBlockView {
+	address: String,
+	blocked: bool
+}
+
+#[derive(Serialize)]
+pub struct DraftView {
+	id: i64,
+	content: String,
+	tags: Vec<String>,
+	attachments: usize,
+	/// In seconds since the UNIX epoch, for tera's date filter.
+	scheduled_at: Option<u64>
+}
+
+async fn load_drafts( timeline: &timeline::Handle ) -> web_error::Result<Vec<DraftView>> {
+	Ok( timeline.list_drafts().await?.into_iter().map(|d| DraftView {
+		id: d.id,
+		content: d.content,
+		tags: d.info.tags,
+		attachments: d.attachment_ids.len(),
+		scheduled_at: d.scheduled_at.map(|t| t / 1000)
+	}).collect() )
+}
+
+/// An entry in the feed of a channel.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum FeedEntry {
+	Post( PostPreview ),
+	/// A change to the channel, which the template tells apart from posts by its `system` field.
+	System { system: SystemPostView }
+}
+
+#[derive(Serialize)]
+pub struct SystemPostView {
+	text: String,
+	/// In seconds since the UNIX epoch, for tera's date filter.
+	timestamp: u64
+}
+
+/// The numbers of the pages to link to from the given page: the first and the last one, and the ones around the current one.
+/// The pages that are skipped in between are `None`.
+fn page_numbers( page: u64, last_page: u64 ) -> Vec<Option<u64>> {
+	const AROUND: u64 = 2;
+
+	let mut numbers = Vec::new();
+	for number in 1..=last_page {
+		if number == 1 || number == last_page || (number + AROUND >= page && number <= page + AROUND) {
+			numbers.push( Some( number ) );
+		}
+		else if numbers.last() != Some( &None ) {
+			numbers.push( None );
+		}
+	}
+	numbers
+}
+
+/// Returns the publish time of the first post with the given id or a higher one, if there is any.
+async fn first_post_timestamp( timeline: &timeline::Handle, from_id: u64 ) -> web_error::Result<Option<u64>> {
+	let latest_id = match timeline.load_latest_post_id().await? {
+		None => return Ok(None),
+		Some(id) => id
+	};
+
+	for post_id in from_id..=latest_id {
+		if let Some(post) = timeline.load_post( post_id ).await? {
+			return Ok( Some( post.meta.info.publish_timestamp ) )
+		}
+	}
+	Ok(None)
+}
+
+/// Puts the system posts between the posts of the feed, by the time at which they happened.
+/// Both need to be in chronological order.
+fn merge_system_posts( posts: Vec<PostPreview>, system_posts: Vec<SystemPost>, language: Language ) -> Vec<FeedEntry> {
+	let mut entries = Vec::with_capacity( posts.len() + system_posts.len() );
+	let mut system_posts = system_posts.into_iter().peekable();
+
+	let to_entry = |post: SystemPost| FeedEntry::System { system: SystemPostView {
+		text: language.system_post_text( post.kind, &post.subject.map(|s| s.to_string()).unwrap_or_default() ),
+		timestamp: post.timestamp / 1000
+	}};
+
+	for post in posts {
+		// Tombstones stay where they are, as their time isn't known anymore.
+		if let Some(info) = &post.info {
+			while system_posts.peek().map(|s| s.timestamp < info.publish_timestamp).unwrap_or(false) {
+				entries.push( to_entry( system_posts.next().unwrap() ) );
+			}
+		}
+		entries.push( FeedEntry::Post( post ) );
+	}
+	entries.extend( system_posts.map( to_entry ) );
+
+	entries
+}
+
+#[derive(Serialize)]
+pub struct TagCountView {
+	keyword: String,
+	count: u64
+}
+
+/// Loads the tags that are used the most on the timeline.
+async fn load_tag_cloud( timeline: &timeline::Handle, include_embargoed: bool ) -> web_error::Result<Vec<TagCountView>> {
+	Ok( timeline.list_top_tags( include_embargoed, config::TAG_CLOUD_SIZE ).await?
+		.into_iter().map(|t| TagCountView {
+			keyword: t.keyword,
+			count: t.count
+		}).collect() )
+}
+
+#[derive(Deserialize)]
+pub struct TagParams {
+	address: String,
+	keyword: String
+}
+
+/// Lists the posts of a channel that have been published with the given tag, newest first.
+#[get("/channel/{address}/tag/{keyword}")]
+pub async fn channel_tag( g: web::Data<Arc<Globals>>, p: web::Path<TagParams>, q: web::Query<PageQuery> ) -> web_error::Result<HttpResponse> {
+	let page_size = config::get().page_size;
+
+	let address = PublicKey::from_string( &p.address )
+		.ok_or_else(|| WebError::bad_request("Invalid channel address."))?;
+	let keyword = normalize_tags( std::iter::once( p.keyword.as_str() ) ).pop()
+		.ok_or_else(|| WebError::bad_request("Invalid tag."))?;
+	let page = q.page.unwrap_or(1).max(1);
+
+	let db = g.connect_database().await?;
+	let timeline = db.get_timeline( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+	let local = timeline.get_my_ego().await?.is_some();
+
+	// One more post than fits on the page is loaded, to know whether there is a next page.
+	let mut posts = if db.is_publisher_blocked( &address ).await? { Vec::new() } else {
+		timeline.list_posts_by_tag( &keyword, local, (page - 1) * page_size as u64, page_size.saturating_add( 1 ) ).await?
+	};
+	let has_more = posts.len() > page_size as usize;
+	posts.truncate( page_size as usize );
+	let posts: Vec<Option<Post>> = posts.into_iter().map( Some ).collect();
+
+	let mut context = tera::Context::new();
+	context.insert("address", &p.address);
+	context.insert("tag", &keyword);
+	context.insert("page", &page);
+	context.insert("has_more", &has_more);
+	context.insert("feed", &load_post_previews( &timeline, 0, &*posts, local ).await?);
+	context.insert("tag_cloud", &load_tag_cloud( &timeline, local ).await?);
+	context.insert("preview_widths", config::PREVIEW_IMAGE_WIDTHS);
+
+	let html = g.templates.render("blog/tag.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+#[get("/channel/feed/{id_type}/{id}")]
+pub async fn channel_feed_first( g: web::Data<Arc<Globals>>, req: HttpRequest, p: web::Path<BlogFeedIdParams>, f: web::Query<FeedFilterQuery> ) -> web_error::Result<HttpResponse> {
+	_channel_feed( g, &req, &p.id, &p.id_type, 1, &f ).await
+}
+
+/// The maximum size of a single attachment.
+pub const MAX_ATTACHMENT_SIZE: usize = 64 * 1024 * 1024;
+
+/// Creates a new post, with the files in the `attachments` fields as its attachments.
+/// If the `draft` button was used, or a time to publish it at has been given, the post is saved as a draft instead.
+#[post("/channel/feed/{id_type}/{id}")]
+pub async fn channel_feed_post( g: web::Data<Arc<Globals>>, p: web::Path<BlogFeedIdParams>, mut payload: Multipart ) -> web_error::Result<HttpResponse> {
+
+	if p.id_type != "ego" {
+		return Err( WebError::bad_request("Posts can only be created by local ego's!") )
+	}
+
+	// Read the form
+	let mut message = String::new();
+	let mut tags = String::new();
+	let mut visible_from = String::new();
+	let mut scheduled_at = String::new();
+	let mut save_draft = false;
+	let mut format = ContentFormat::Plain;
+	let mut attachments = Vec::new();
+	while let Some(field) = payload.next().await {
+		let mut field = field?;
+		let name = field.content_disposition()
+			.and_then(|cd| cd.get_name().map(|n| n.to_owned()))
+			.unwrap_or_default();
+		let mime_type = field.content_type().to_string();
+
+		let mut data = Vec::new();
+		while let Some(chunk) = field.next().await {
+			let chunk = chunk?;
+			if data.len() + chunk.len() > MAX_ATTACHMENT_SIZE {
+				return Err( WebError::payload_too_large("Attachment is too large.") )
+			}
+			data.extend_from_slice( &chunk );
+		}
+
+		match name.as_str() {
+			"message" => message = String::from_utf8( data ).map_err(|_| WebError::bad_request("Message is not valid UTF-8."))?,
+			"tags" => tags = String::from_utf8( data ).map_err(|_| WebError::bad_request("Tags are not valid UTF-8."))?,
+			"visible_from" => visible_from = String::from_utf8( data ).map_err(|_| WebError::bad_request("Release time is not valid UTF-8."))?,
+			"scheduled_at" => scheduled_at = String::from_utf8( data ).map_err(|_| WebError::bad_request("Publishing time is not valid UTF-8."))?,
+			"draft" => save_draft = true,
+			"format" => format = std::str::from_utf8( &data ).ok().and_then( parse_content_format )
+				.ok_or_else(|| WebError::bad_request("Unknown content format."))?,
+			"attachments" => if data.len() > 0 { attachments.push(( data, mime_type )) },
+			_ => {}
+		}
+	}
+	let tags = normalize_tags( tags.split_whitespace() );
+
+	let private_key = g.services.lookup_ego( &p.id ).await?;
+
+	let address = ego_address( &private_key )?;
+	let db = g.connect_database().await?;
+
+	let visible_from = parse_local_datetime( &db, &visible_from, "Invalid release time." ).await?;
+	let scheduled_at = parse_local_datetime( &db, &scheduled_at, "Invalid publishing time." ).await?;
+	let timeline = db.get_timeline( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown publisher."))?;
+
+	// The defaults of the ego are added to what has been written.
+	let defaults = timeline.load_post_defaults().await?;
+	let tags = defaults.apply_tags( tags );
+	check_tags( &tags ).map_err( WebError::bad_request )?;
+	let message = defaults.apply_footer( &message );
+
+	// Store the attachments as blocks
+	let mut attachment_ids = Vec::with_capacity( attachments.len() );
+	for (data, mime_type) in &attachments {
+		attachment_ids.push( db.store_attachment( data, mime_type ).await? );
+	}
+
+	let post_info = PostInfo {
+		tags,
+		publish_timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as _,
+		visible_from,
+		format,
+		series: None,
+		content_warning: None
+	};
+
+	// Drafts aren't signed, they only become posts when they are published.
+	if save_draft || scheduled_at.is_some() {
+		let draft_id = timeline.store_draft( &message, &post_info, &attachment_ids, scheduled_at ).await?;
+		db.record_action( Some( &p.id ), AuditAction::DraftSaved, &draft_subject( &address, draft_id ) ).await?;
+
+		let location = format!("/channel/feed/{}/{}", p.id_type, p.id);
+		return Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+	}
+
+	let (_, post) = timeline.create_post( &private_key, &message, post_info, attachment_ids, None ).await?;
+	db.clone().get_channel( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?
+		.log_new_post( &timeline, &post ).await?;
+	db.record_action( Some( &p.id ), AuditAction::PostCreated, &post_subject( &address, post.id ) ).await?;
+
+	let location = format!("/channel/feed/{}/{}", p.id_type, p.id);
+	Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+}
+
+/// The content format with the given name, as it is used in forms.
+fn parse_content_format( name: &str ) -> Option<ContentFormat> {
+	match name {
+		"plain" => Some( ContentFormat::Plain ),
+		"markdown" => Some( ContentFormat::Markdown ),
+		"rst" => Some( ContentFormat::ReStructuredText ),
+		_ => None
+	}
+}
+
+fn content_format_name( format: ContentFormat ) -> &'static str {
+	match format {
+		ContentFormat::Plain => "plain",
+		ContentFormat::Markdown => "markdown",
+		ContentFormat::ReStructuredText => "rst"
+	}
+}
+
+#[derive(Serialize)]
+pub struct EditorRevisionView {
+	number: u32,
+	/// In seconds since the UNIX epoch, for tera's date filter.
+	timestamp: u64,
+	html: String,
+	/// The markup itself, so that the revision can be taken up in the editor again.
+	content: String
+}
+
+/// Shows the editor for a new post, which is submitted to the feed like the form on the feed itself.
+#[get("/channel/ego/{ego}/editor")]
+pub async fn channel_editor(g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>) -> web_error::Result<HttpResponse> {
+
+	let address = ego_address( &g.services.lookup_ego( &p.ego ).await? )?;
+	let db = g.connect_database().await?;
+
+	let mut context = tera::Context::new();
+	context.insert("ego", &p.ego);
+	context.insert("address", &address.to_string());
+	context.insert("post_id", &None::<u64>);
+	context.insert("content", "");
+	context.insert("tags", "");
+	context.insert("format", content_format_name( ContentFormat::Plain ));
+	context.insert("revisions", &Vec::<EditorRevisionView>::new());
+	context.insert("timezone", &format_utc_offset( load_timezone_offset( &db ).await? ));
+
+	let html = g.templates.render("blog/editor.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+#[derive(Deserialize)]
+pub struct OwnPostParams {
+	ego: String,
+	post_id: u64
+}
+
+/// Shows the editor for one of our own posts, with the revisions that it has had so far.
+#[get("/channel/ego/{ego}/post/{post_id}/edit")]
+pub async fn channel_post_edit(g: web::Data<Arc<Globals>>, p: web::Path<OwnPostParams>) -> web_error::Result<HttpResponse> {
+
+	let address = ego_address( &g.services.lookup_ego( &p.ego ).await? )?;
+	let db = g.connect_database().await?;
+	let timeline = db.get_timeline( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown publisher."))?;
+	let post = timeline.load_post( p.post_id ).await?
+		.ok_or_else(|| WebError::not_found("Post not found."))?;
+	let original = timeline.load_post_content( p.post_id ).await?
+		.ok_or_else(|| WebError::not_found("The content of this post is not available."))?;
+	let info = timeline.load_current_info( &post ).await?;
+
+	let format = post.meta.info.format;
+	let mut revisions = vec![ EditorRevisionView {
+		number: 0,
+		timestamp: post.meta.info.publish_timestamp / 1000,
+		html: preview::render( &original, format ),
+		content: original
+	}];
+	for revision in timeline.load_revisions( p.post_id ).await? {
+		revisions.push( EditorRevisionView {
+			number: revision.number,
+			timestamp: revision.received_timestamp / 1000,
+			html: preview::render( &revision.content, format ),
+			content: revision.content
+		});
+	}
+	// The newest revision is shown first.
+	revisions.reverse();
+
+	let mut context = tera::Context::new();
+	context.insert("ego", &p.ego);
+	context.insert("address", &address.to_string());
+	context.insert("post_id", &Some( p.post_id ));
+	context.insert("content", &revisions[0].content);
+	context.insert("tags", &info.tags.join(" "));
+	context.insert("format", content_format_name( format ));
+	context.insert("revisions", &revisions);
+
+	let html = g.templates.render("blog/editor.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+#[derive(Deserialize)]
+pub struct ReviseForm {
+	message: String,
+	tags: String
+}
+
+/// Publishes a new revision of one of our own posts.
+/// The format of a post can't change, so only the content and the tags are taken from the form.
+#[post("/channel/ego/{ego}/post/{post_id}/edit")]
+pub async fn channel_post_revise(g: web::Data<Arc<Globals>>, p: web::Path<OwnPostParams>, form: web::Form<ReviseForm>) -> web_error::Result<HttpResponse> {
+
+	let tags = normalize_tags( form.tags.split_whitespace() );
+	check_tags( &tags ).map_err( WebError::bad_request )?;
+
+	let private_key = g.services.lookup_ego( &p.ego ).await?;
+	let address = ego_address( &private_key )?;
+	let db = g.connect_database().await?;
+	let timeline = db.get_timeline( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown publisher."))?;
+	let channel = timeline.get_channel().await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+	let post = timeline.load_post( p.post_id ).await?
+		.ok_or_else(|| WebError::not_found("Post not found."))?;
+
+	let info = PostInfo {
+		tags,
+		..timeline.load_current_info( &post ).await?
+	};
+	channel.publish_revision( &timeline, &private_key, p.post_id, &form.message, info ).await?
+		.ok_or_else(|| WebError::not_found("Post not found."))?;
+	db.record_action( Some( &p.ego ), AuditAction::PostsRevised, &post_subject( &address, p.post_id ) ).await?;
+
+	let location = format!("/channel/ego/{}/post/{}", p.ego, p.post_id);
+	Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+}
+
+#[derive(Deserialize)]
+pub struct PreviewForm {
+	content: String,
+	format: String
+}
+
+/// Renders markup the way it would be shown in a post, for the preview in the editor.
+#[post("/channel/preview")]
+pub async fn channel_preview(form: web::Form<PreviewForm>) -> web_error::Result<HttpResponse> {
+
+	let format = parse_content_format( &form.format )
+		.ok_or_else(|| WebError::bad_request("Unknown content format."))?;
+
+	Ok(HttpResponse::Ok().content_type("text/html").body( preview::render( &form.content, format ) ))
+}
+
+#[derive(Deserialize)]
+pub struct DraftParams {
+	ego: String,
+	draft_id: i64
+}
+
+/// Publishes a draft right away, whether it has been scheduled or not.
+#[post("/channel/feed/ego/{ego}/draft/{draft_id}/publish")]
+pub async fn channel_draft_publish( g: web::Data<Arc<Globals>>, p: web::Path<DraftParams> ) -> web_error::Result<HttpResponse> {
+
+	let address = ego_address( &g.services.lookup_ego( &p.ego ).await? )?;
+	let db = g.connect_database().await?;
+	scheduler::publish_draft( &g.services, &db, &p.ego, &address, p.draft_id ).await?
+		.ok_or_else(|| WebError::not_found("Unknown draft."))?;
+
+	let location = format!("/channel/feed/ego/{}", p.ego);
+	Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+}
+
+/// Throws a draft away without publishing it.
+#[post("/channel/feed/ego/{ego}/draft/{draft_id}/delete")]
+pub async fn channel_draft_delete( g: web::Data<Arc<Globals>>, p: web::Path<DraftParams> ) -> web_error::Result<HttpResponse> {
+
+	let address = ego_address( &g.services.lookup_ego( &p.ego ).await? )?;
+	let db = g.connect_database().await?;
+	let timeline = db.get_timeline( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown publisher."))?;
+	if !timeline.delete_draft( p.draft_id ).await? {
+		return Err( WebError::not_found("Unknown draft.") )
+	}
+	db.record_action( Some( &p.ego ), AuditAction::DraftDeleted, &draft_subject( &address, p.draft_id ) ).await?;
+
+	let location = format!("/channel/feed/ego/{}", p.ego);
+	Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+}
+
+
+/// Applies an action to the posts that have been selected on the feed of one of our own egos.
+/// The form has a `post` field for every selected post, the `action` to apply, and the `value` that goes with it, like the tag to add.
+#[post("/channel/feed/ego/{ego}/batch")]
+pub async fn channel_feed_batch( g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>, form: web::Form<Vec<(String, String)>> ) -> web_error::Result<HttpResponse> {
+
+	let mut post_ids = Vec::new();
+	let mut action = "";
+	let mut value = "";
+	for (name, field) in form.iter() {
+		match name.as_str() {
+			"post" => post_ids.push( field.parse().map_err(|_| WebError::bad_request("Invalid post id."))? ),
+			"action" => action = field,
+			"value" => value = field,
+			_ => {}
+		}
+	}
+	let action = parse_batch_action( action, value )?;
+
+	apply_batch( &g, &p.ego, &post_ids, &action ).await?;
+
+	let location = format!("/channel/feed/ego/{}", p.ego);
+	Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+}
+
+/// Parses one of the actions that can be applied to many posts at once.
+/// An empty `value` clears the series or content warning.
+pub fn parse_batch_action( action: &str, value: &str ) -> web_error::Result<BatchAction> {
+	let value = value.trim();
+	let label = if value.is_empty() { None } else { Some( value.to_owned() ) };
+	let too_long = |max: usize| label.as_ref().map(|l| l.chars().count() > max).unwrap_or(false);
+
+	Ok( match action {
+		"delete" => BatchAction::Delete,
+		"add_tag" => {
+			let tags = normalize_tags( std::iter::once( value ) );
+			check_tags( &tags ).map_err( WebError::bad_request )?;
+			BatchAction::AddTag( tags.into_iter().next().ok_or_else(|| WebError::bad_request("Missing tag."))? )
+		},
+		"set_series" => {
+			if too_long( SERIES_MAX_LEN ) { return Err( WebError::bad_request("Series name is too long.") ) }
+			BatchAction::SetSeries( label )
+		},
+		"set_content_warning" => {
+			if too_long( CONTENT_WARNING_MAX_LEN ) { return Err( WebError::bad_request("Content warning is too long.") ) }
+			BatchAction::SetContentWarning( label )
+		},
+		_ => return Err( WebError::bad_request("Unknown action.") )
+	})
+}
+
+/// Applies the action to the given posts of one of our own egos, in one transaction.
+/// Returns the number of posts that have been changed.
+pub async fn apply_batch( g: &Globals, ego: &str, post_ids: &[u64], action: &BatchAction ) -> web_error::Result<usize> {
+
+	let private_key = g.services.lookup_ego( ego ).await?;
+	let address = ego_address( &private_key )?;
+	let channel = g.connect_database().await?
+		.get_channel( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+
+	let changed = channel.apply_batch( &private_key, post_ids, action ).await?;
+	if changed > 0 {
+		let audit_action = match action {
+			BatchAction::Delete => AuditAction::PostsForgotten,
+			_ => AuditAction::PostsRevised
+		};
+		let ids: Vec<String> = post_ids.iter().map(|id| id.to_string()).collect();
+		channel.record_action( Some( ego ), audit_action, &format!("{}/{{{}}}", address, ids.join(",")) ).await?;
+	}
+	Ok( changed )
+}
+
+/// Identifies a post in the audit log.
+pub fn post_subject( publisher: &PublicKey, post_id: u64 ) -> String {
+	format!("{}/{}", publisher, post_id)
+}
+
+/// The subject under which an action on a draft is recorded in the audit log.
+pub fn draft_subject( publisher: &PublicKey, draft_id: i64 ) -> String {
+	format!("{}/draft/{}", publisher, draft_id)
+}
+
+/// Loads the offset of the user's timezone from UTC, in minutes.
+async fn load_timezone_offset( db: &persistence::Handle ) -> web_error::Result<i64> {
+	Ok( db.load_setting( setup::SETTING_TIMEZONE_OFFSET ).await?
+		.and_then(|o| o.parse().ok())
+		.unwrap_or(0) )
+}
+
+/// Parses a time that has been entered in the timezone of the user, in the format of a `datetime-local` input.
+/// Returns the time in milliseconds since the UNIX epoch, or `None` if no time has been entered.
+/// An invalid time is a bad request, with the given message.
+async fn parse_local_datetime( db: &persistence::Handle, time: &str, invalid: &'static str ) -> web_error::Result<Option<u64>> {
+	let time = time.trim();
+	if time.is_empty() { return Ok(None) }
+
+	let offset = load_timezone_offset( db ).await?;
+	let seconds = parse_utc_datetime( time )
+		.map(|t| t as i64 - offset * 60)
+		.filter(|t| *t >= 0)
+		.ok_or_else(|| WebError::bad_request( invalid ))?;
+	Ok( Some( seconds as u64 * 1000 ) )
+}
+
+/// Parses a date that has been entered in the timezone of the user, in the format of a `date` input.
+/// Returns the start of the day in milliseconds since the UNIX epoch, or `None` if no date has been entered.
+async fn parse_local_date( db: &persistence::Handle, date: &str ) -> web_error::Result<Option<u64>> {
+	let date = date.trim();
+	if date.is_empty() { return Ok(None) }
+	parse_local_datetime( db, &format!("{}T00:00", date ), "Invalid date." ).await
+}
+
+/// Loads the language in which the texts of this node itself are shown.
+async fn load_language( db: &persistence::Handle ) -> web_error::Result<Language> {
+	Ok( db.load_setting( setup::SETTING_LANGUAGE ).await?
+		.and_then(|code| Language::from_code( &code ))
+		.unwrap_or_default() )
+}
+
+/// Parses an offset from UTC like `+02:00` or `-0530`, into minutes.
+/// An empty offset, or `UTC` itself, is zero.
+fn parse_utc_offset( offset: &str ) -> Option<i64> {
+	let offset = offset.trim();
+	let offset = offset.strip_prefix("UTC").unwrap_or( offset );
+	if offset.is_empty() { return Some(0) }
+
+	let (sign, rest) = match offset.split_at(1) {
+		("+", rest) => (1, rest),
+		("-", rest) => (-1, rest),
+		_ => return None
+	};
+	let digits: String = rest.chars().filter(|c| *c != ':').collect();
+	let (hours, minutes) = match digits.len() {
+		1 | 2 => (digits.parse::<i64>().ok()?, 0),
+		3 | 4 => {
+			let (h, m) = digits.split_at( digits.len() - 2 );
+			(h.parse::<i64>().ok()?, m.parse::<i64>().ok()?)
+		},
+		_ => return None
+	};
+	if hours > 14 || minutes > 59 { return None }
+
+	Some( sign * (hours * 60 + minutes) )
+}
+
+fn format_utc_offset( minutes: i64 ) -> String {
+	let sign = if minutes < 0 { '-' } else { '+' };
+	format!("UTC{}{:02}:{:02}", sign, minutes.abs() / 60, minutes.abs() % 60)
+}
+
+/// Parses a time in the format of a `datetime-local` input (`YYYY-MM-DDTHH:MM`) as UTC.
+/// Returns the number of seconds since the UNIX epoch, or `None` if the time is invalid or before the epoch.
+fn parse_utc_datetime( time: &str ) -> Option<u64> {
+	let (date, clock) = time.split_at( time.find('T')? );
+	let date: Vec<i64> = date.split('-').map(|p| p.parse().ok()).collect::<Option<_>>()?;
+	let clock: Vec<i64> = clock[1..].split(':').map(|p| p.parse().ok()).collect::<Option<_>>()?;
+	if date.len() != 3 || clock.len() < 2 || clock.len() > 3 { return None }
+	let (year, month, day) = (date[0], date[1], date[2]);
+	if month < 1 || month > 12 || day < 1 || day > 31 || clock[0] > 23 || clock[1] > 59 { return None }
+
+	// The number of days since the epoch, from Howard Hinnant's `days_from_civil`.
+	let y = if month <= 2 { year - 1 } else { year };
+	let era = if y >= 0 { y } else { y - 399 } / 400;
+	let year_of_era = y - era * 400;
+	let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+	let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+	let days = era * 146097 + day_of_era - 719468;
+
+	let seconds = days * 86400 + clock[0] * 3600 + clock[1] * 60 + clock.get(2).cloned().unwrap_or(0);
+	if seconds < 0 { None } else { Some( seconds as u64 ) }
+}
+
+#[derive(Deserialize)]
+pub struct InviteForm {
+	code: String
+}
+
+/// Accepts an invite to a private channel, so that its messages can be decrypted.
+/// The channel is added if we didn't know it yet.
+#[post("/channel/feed/address/{address}/invite")]
+pub async fn channel_invite( g: web::Data<Arc<Globals>>, p: web::Path<ChannelAddressParams>, form: web::Form<InviteForm> ) -> web_error::Result<HttpResponse> {
+
+	let address = PublicKey::from_string( &p.address )
+		.ok_or_else(|| WebError::bad_request("Invalid channel address."))?;
+	let code = InviteCode::from_string( &form.code )
+		.ok_or_else(|| WebError::bad_request("Invalid invite code."))?;
+
+	let db = g.connect_database().await?;
+	db.add_channel( &address ).await?
+		.store_invite_code( &code ).await?;
+	db.record_action( None, AuditAction::InviteAccepted, &address.to_string() ).await?;
+
+	let location = format!("/channel/feed/address/{}", p.address);
+	Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+}
+
+#[derive(Deserialize)]
+pub struct TransferForm {
+	new_owner: String,
+	grace_days: u64
+}
+
+/// Offers the channel of one of our own egos to a new owner.
+#[post("/channel/ego/{ego}/transfer")]
+pub async fn channel_transfer( g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>, form: web::Form<TransferForm> ) -> web_error::Result<HttpResponse> {
+
+	let new_owner = PublicKey::from_string( form.new_owner.trim() )
+		.ok_or_else(|| WebError::bad_request("Invalid key of the new owner."))?;
+
+	let private_key = g.services.lookup_ego( &p.ego ).await?;
+	let channel = g.connect_database().await?
+		.get_channel( &ego_address( &private_key )? ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+
+	match channel.transfer( &private_key, new_owner.clone(), form.grace_days * 24 * 60 * 60 * 1000 ).await {
+		Err(persistence::Error::NotFound) => return Err( WebError::forbidden("This ego doesn't own the channel anymore.") ),
+		other => other?
+	}
+	channel.record_action( Some( &p.ego ), AuditAction::OwnershipOffered, &format!("{} to {}", ego_address( &private_key )?, new_owner) ).await?;
+
+	let location = format!("/channel/feed/ego/{}", p.ego);
+	Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+}
+
+#[derive(Deserialize)]
+pub struct DeleteChannelForm {
+	/// The name of the ego, typed again to confirm the deletion.
+	confirm: String,
+	/// Deletes the channel even if its closing hasn't reached any peer.
+	force: Option<String>
+}
+
+/// Deletes the channel of one of our own egos, along with the ego, after telling its swarm that the channel has been closed.
+#[post("/channel/ego/{ego}/delete")]
+pub async fn channel_delete( g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>, form: web::Form<DeleteChannelForm> ) -> web_error::Result<HttpResponse> {
+
+	if form.confirm.trim() != p.ego {
+		return Err( WebError::bad_request("Type the name of the ego to confirm that the channel should be deleted.") )
+	}
+
+	let db = g.connect_database().await?;
+	let deleted = daemon::delete_channel( &g.services, &db, &g.subscriptions, &p.ego, form.force.is_some() ).await.map_err(|e| match e {
+		daemon::Error::Invalid( message ) => WebError::forbidden( message ),
+		daemon::Error::Gnunet( e ) => e.into(),
+		daemon::Error::Persistence( e ) => e.into(),
+		e => WebError::internal( e.to_string() )
+	})?;
+	if !deleted {
+		return Err( WebError::conflict("The closing of the channel hasn't reached any peer yet. It is published as soon as one connects, so try again later, or delete the channel anyway.") )
+	}
+
+	Ok( HttpResponse::Found().append_header((header::LOCATION, "/")).finish() )
+}
+
+#[derive(Deserialize)]
+pub struct AcceptTransferForm {
+	ego: String
+}
+
+/// Accepts the channel with the given address, as the ego that it has been offered to.
+#[post("/channel/address/{address}/transfer/accept")]
+pub async fn channel_transfer_accept( g: web::Data<Arc<Globals>>, p: web::Path<ChannelAddressParams>, form: web::Form<AcceptTransferForm> ) -> web_error::Result<HttpResponse> {
+
+	let address = PublicKey::from_string( &p.address )
+		.ok_or_else(|| WebError::bad_request("Invalid channel address."))?;
+
+	let private_key = g.services.lookup_ego( form.ego.trim() ).await?;
+	let channel = g.connect_database().await?
+		.get_channel( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+
+	match channel.accept_transfer( &private_key ).await {
+		Err(persistence::Error::NotFound) => return Err( WebError::bad_request("The channel hasn't been offered to this ego.") ),
+		other => other?
+	}
+	channel.record_action( Some( form.ego.trim() ), AuditAction::OwnershipAccepted, &address.to_string() ).await?;
+
+	let location = format!("/channel/feed/address/{}", p.address);
+	Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+}
+
+#[derive(Deserialize)]
+pub struct ForkForm {
+	name: String
+}
+
+/// Creates a new channel for a new ego, with copies of all posts of the channel with the given address that we have stored.
+#[post("/channel/feed/address/{address}/fork")]
+pub async fn channel_fork( g: web::Data<Arc<Globals>>, p: web::Path<ChannelAddressParams>, form: web::Form<ForkForm> ) -> web_error::Result<HttpResponse> {
+
+	let original = PublicKey::from_string( &p.address )
+		.ok_or_else(|| WebError::bad_request("Invalid channel address."))?;
+
+	let mut db = g.connect_database().await?;
+	match db.fork_channel( &form.name, &original ).await {
+		Err(persistence::Error::AlreadyExists) | Err(persistence::Error::EgoConflict(_)) => Err( WebError::bad_request("An ego with that name already exists!") ),
+		Err(persistence::Error::NotFound) => Err( WebError::not_found("Unknown channel.") ),
+		Err(e) => Err( e.into() ),
+		Ok((channel, _)) => {
+			let address = channel.load_address().await?;
+			db.record_action( Some( &form.name ), AuditAction::ChannelForked, &format!("{} from {}", address, original) ).await?;
+			let location = format!("/channel/feed/ego/{}", form.name);
+			Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+		}
+	}
+}
+
+
+#[derive(Serialize)]
+pub struct FollowerView {
+	address: String,
+	child: bool,
+	/// How long the peer has been connected, in whole days, hours or minutes.
+	connected_for: String,
+	sent: u64,
+	received: u64,
+	/// The number of peers that the child stands for, if it has told us.
+	subtree: Option<String>
+}
+
+#[derive(Serialize)]
+pub struct CensusHourView {
+	/// In seconds since the UNIX epoch, for tera's date filter.
+	time: u64,
+	children: u32,
+	swarm_size: Option<u32>,
+	/// The height of the bar in the graph, in percent of the highest one.
+	height: u32
+}
+
+/// Shows the owner of a channel who is connected to its swarm through this node, and how large the swarm is estimated to be.
+/// The history of the census is only kept while the node is running, see `persistence::census`.
+#[get("/channel/ego/{ego}/followers")]
+pub async fn channel_followers_dashboard(g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>) -> web_error::Result<HttpResponse> {
+
+	let address = ego_address( &g.services.lookup_ego( &p.ego ).await? )?;
+	let db = g.connect_database().await?;
+	let channel = db.clone().get_channel( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+
+	let node = g.subscriptions.read().await.as_ref().and_then(|s| s.node( &address ));
+	let census = match &node {
+		None => None,
+		Some(node) => Some( node.census().await )
+	};
+	let now = peer::now() as u64;
+	let followers: Vec<FollowerView> = census.iter().flat_map(|c| c.peers.iter()).map(|peer| FollowerView {
+		address: peer.address.to_string(),
+		child: peer.child,
+		connected_for: format_connection_time( now.saturating_sub( peer.connected_since ) / 1000 ),
+		sent: peer.sent,
+		received: peer.received,
+		subtree: peer.subtree_share.map(|s| format!("{:.1}", s))
+	}).collect();
+
+	// The bars of the graph show the size of the swarm, or the number of children in the hours before it had been counted.
+	let history = channel.list_census_history( config::CENSUS_HISTORY_DAYS ).await?;
+	let highest = history.iter().map(|h| h.swarm_size.unwrap_or( h.children )).max().unwrap_or(0).max(1);
+	let history: Vec<CensusHourView> = history.into_iter().map(|h| CensusHourView {
+		time: h.timestamp / 1000,
+		children: h.children,
+		swarm_size: h.swarm_size,
+		height: h.swarm_size.unwrap_or( h.children ) * 100 / highest
+	}).collect();
+
+	let mut context = tera::Context::new();
+	context.insert("ego", &p.ego);
+	context.insert("address", &address.to_string());
+	context.insert("connected", &node.as_ref().map(|n| n.is_connected()).unwrap_or(false));
+	context.insert("children", &followers.iter().filter(|f| f.child).count());
+	context.insert("swarm_size", &census.as_ref().and_then(|c| c.swarm_size));
+	context.insert("followers", &followers);
+	context.insert("history", &history);
+	context.insert("days", &config::CENSUS_HISTORY_DAYS);
+
+	let html = g.templates.render("blog/followers.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+
+/// Formats the number of seconds that a peer has been connected, in the largest unit that fits.
+fn format_connection_time( seconds: u64 ) -> String {
+	match seconds {
+		s if s >= 24 * 60 * 60 => format!("{} days", s / (24 * 60 * 60)),
+		s if s >= 60 * 60 => format!("{} hours", s / (60 * 60)),
+		s => format!("{} minutes", s / 60)
+	}
+}
+
+
+#[derive(Deserialize)]
+pub struct EgoParams {
+	ego: String
+}
+
+#[derive(Serialize)]
+pub struct RelayView {
+	address: String,
+	posts: u64,
+	blocks: u64,
+	bytes: u64,
+	/// In seconds since the UNIX epoch, for tera's date filter.
+	last_served: u64,
+	/// Whether the peer is a designated publisher of the channel already.
+	publisher: bool
+}
+
+/// Shows the owner of a channel which peers have recently been serving the posts and blocks of the channel.
+/// This helps to decide whom to add as designated publishers.
+#[get("/channel/ego/{ego}/relays")]
+pub async fn channel_relays(g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>) -> web_error::Result<HttpResponse> {
+
+	let address = ego_address( &g.services.lookup_ego( &p.ego ).await? )?;
+	let db = g.connect_database().await?;
+	let channel = db.clone().get_channel( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+
+	let mut relays = Vec::new();
+	for relay in db.list_relays( channel.id, config::RELAY_REPORT_DAYS ).await? {
+		relays.push( RelayView {
+			address: relay.address.to_string(),
+			posts: relay.posts,
+			blocks: relay.blocks,
+			bytes: relay.bytes,
+			last_served: relay.last_day * 24 * 60 * 60,
+			publisher: channel.is_publisher( &relay.address ).await?
+		});
+	}
+
+	let mut context = tera::Context::new();
+	context.insert("ego", &p.ego);
+	context.insert("address", &address.to_string());
+	context.insert("days", &config::RELAY_REPORT_DAYS);
+	context.insert("relays", &relays);
+
+	let html = g.templates.render("blog/relays.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+
+#[derive(Deserialize)]
+pub struct ImportForm {
+	url: String
+}
+
+/// Shows the owner of a channel a form to import the posts of an existing blog from its feed.
+#[get("/channel/ego/{ego}/import")]
+pub async fn channel_import(g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>) -> web_error::Result<HttpResponse> {
+
+	let address = ego_address( &g.services.lookup_ego( &p.ego ).await? )?;
+
+	let mut context = tera::Context::new();
+	context.insert("ego", &p.ego);
+	context.insert("address", &address.to_string());
+	context.insert("url", "");
+
+	let html = g.templates.render("blog/import.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+/// Imports the entries of a feed as posts of the channel of one of our own egos, and shows how that went.
+/// A feed that can't be fetched or read is shown as an error, so that the address can be corrected.
+#[post("/channel/ego/{ego}/import")]
+pub async fn channel_import_feed(g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>, form: web::Form<ImportForm>) -> web_error::Result<HttpResponse> {
+
+	let private_key = g.services.lookup_ego( &p.ego ).await?;
+	let address = ego_address( &private_key )?;
+	let db = g.connect_database().await?;
+
+	let mut context = tera::Context::new();
+	context.insert("ego", &p.ego);
+	context.insert("address", &address.to_string());
+	context.insert("url", form.url.trim());
+	match feed_import::import_feed( &db, &private_key, &p.ego, form.url.trim() ).await {
+		Err(feed_import::Error::Persistence(e)) => return Err( e.into() ),
+		Err(e) => context.insert("error", &e.to_string()),
+		Ok(report) => {
+			context.insert("imported", &report.imported);
+			context.insert("skipped", &report.skipped);
+		}
+	}
+
+	let html = g.templates.render("blog/import.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+
+#[derive(Serialize)]
+pub struct MicropubTokenView {
+	id: i64,
+	name: String,
+	/// In seconds since the UNIX epoch, for tera's date filter.
+	created: u64
+}
+
+#[derive(Deserialize)]
+pub struct MicropubTokenForm {
+	name: String
+}
+
+#[derive(Deserialize)]
+pub struct MicropubTokenParams {
+	ego: String,
+	token_id: i64
+}
+
+/// Shows the owner of a channel the tokens with which Micropub clients publish in it.
+#[get("/channel/ego/{ego}/micropub")]
+pub async fn channel_micropub(g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>) -> web_error::Result<HttpResponse> {
+	render_micropub( &g, &p.ego, None ).await
+}
+
+/// Creates a token for a Micropub client, and shows it the only time that it can be seen.
+#[post("/channel/ego/{ego}/micropub/tokens")]
+pub async fn channel_micropub_token_create(g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>, form: web::Form<MicropubTokenForm>) -> web_error::Result<HttpResponse> {
+
+	let name = form.name.trim();
+	if name.is_empty() {
+		return Err( WebError::bad_request("The token needs a name.") )
+	}
+	let address = ego_address( &g.services.lookup_ego( &p.ego ).await? )?;
+	let db = g.connect_database().await?;
+	let channel = db.clone().get_channel( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+
+	let token = channel.create_micropub_token( name ).await?;
+	db.record_action( Some( &p.ego ), AuditAction::SettingChanged, &format!("micropub token {} of {}", name, address) ).await?;
+
+	render_micropub( &g, &p.ego, Some( &token ) ).await
+}
+
+/// Revokes a token, after which the client that has it can't publish anymore.
+#[post("/channel/ego/{ego}/micropub/tokens/{token_id}/revoke")]
+pub async fn channel_micropub_token_revoke(g: web::Data<Arc<Globals>>, p: web::Path<MicropubTokenParams>) -> web_error::Result<HttpResponse> {
+
+	let address = ego_address( &g.services.lookup_ego( &p.ego ).await? )?;
+	let db = g.connect_database().await?;
+	let channel = db.clone().get_channel( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+
+	if !channel.revoke_micropub_token( p.token_id ).await? {
+		return Err( WebError::not_found("Unknown token.") )
+	}
+	db.record_action( Some( &p.ego ), AuditAction::SettingChanged, &format!("micropub token {} of {} revoked", p.token_id, address) ).await?;
+
+	let location = format!("/channel/ego/{}/micropub", p.ego);
+	Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+}
+
+async fn render_micropub( g: &Globals, ego: &str, new_token: Option<&str> ) -> web_error::Result<HttpResponse> {
+
+	let address = ego_address( &g.services.lookup_ego( ego ).await? )?;
+	let db = g.connect_database().await?;
+	let channel = db.clone().get_channel( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+
+	let tokens: Vec<MicropubTokenView> = channel.list_micropub_tokens().await?.into_iter().map(|t| MicropubTokenView {
+		id: t.id,
+		name: t.name,
+		created: t.created / 1000
+	}).collect();
+
+	let mut context = tera::Context::new();
+	context.insert("ego", ego);
+	context.insert("address", &address.to_string());
+	context.insert("tokens", &tokens);
+	context.insert("new_token", &new_token);
+
+	let html = g.templates.render("blog/micropub.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+
+/// Shows the owner of a channel who may publish in it besides the owner, with a form to add or revoke publishers.
+#[get("/channel/ego/{ego}/publishers")]
+pub async fn channel_publishers(g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>) -> web_error::Result<HttpResponse> {
+
+	let address = ego_address( &g.services.lookup_ego( &p.ego ).await? )?;
+	let channel = g.connect_database().await?
+		.get_channel( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+
+	let mut context = tera::Context::new();
+	context.insert("ego", &p.ego);
+	context.insert("address", &address.to_string());
+	context.insert("revision", &channel.load_publisher_list_revision().await?);
+	context.insert("publishers", &channel.list_publishers().await?.iter().map(|p| p.to_string()).collect::<Vec<_>>());
+
+	let html = g.templates.render("blog/publishers.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+#[derive(Deserialize)]
+pub struct PublisherForm {
+	publisher: String
+}
+
+/// Allows another key to publish in the channel of one of our own egos.
+#[post("/channel/ego/{ego}/publishers/add")]
+pub async fn channel_publisher_add(g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>, form: web::Form<PublisherForm>) -> web_error::Result<HttpResponse> {
+
+	let publisher = PublicKey::from_string( form.publisher.trim() )
+		.ok_or_else(|| WebError::bad_request("Invalid key of the publisher."))?;
+
+	change_publishers( &g, &p.ego, AuditAction::PublisherAdded, &publisher, |publishers| {
+		if publishers.contains( &publisher ) { return false }
+		publishers.push( publisher.clone() );
+		true
+	}).await
+}
+
+/// Stops another key from publishing in the channel of one of our own egos.
+/// The posts that it has published before are kept.
+#[post("/channel/ego/{ego}/publishers/revoke")]
+pub async fn channel_publisher_revoke(g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>, form: web::Form<PublisherForm>) -> web_error::Result<HttpResponse> {
+
+	let publisher = PublicKey::from_string( form.publisher.trim() )
+		.ok_or_else(|| WebError::bad_request("Invalid key of the publisher."))?;
+
+	change_publishers( &g, &p.ego, AuditAction::PublisherRevoked, &publisher, |publishers| {
+		let before = publishers.len();
+		publishers.retain(|p| *p != publisher);
+		publishers.len() != before
+	}).await
+}
+
+/// Applies `change` to the publishers of the channel of the given ego, and publishes the new list if `change` returns that it changed anything.
+async fn change_publishers<F>( g: &Globals, ego: &str, action: AuditAction, publisher: &PublicKey, change: F ) -> web_error::Result<HttpResponse> where
+	F: FnOnce( &mut Vec<PublicKey> ) -> bool
+{
+	let private_key = g.services.lookup_ego( ego ).await?;
+	let address = ego_address( &private_key )?;
+	if *publisher == address {
+		return Err( WebError::bad_request("The channel itself can always publish in it.") )
+	}
+	let db = g.connect_database().await?;
+	let channel = db.clone().get_channel( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+
+	let mut publishers = channel.list_publishers().await?;
+	if change( &mut publishers ) {
+		match channel.update_publisher_list( &private_key, publishers ).await {
+			Err(persistence::Error::NotFound) => return Err( WebError::forbidden("This ego doesn't own the channel anymore.") ),
+			other => other?
+		};
+		db.record_action( Some( ego ), action, &format!("{} in {}", publisher, address) ).await?;
+	}
+
+	let location = format!("/channel/ego/{}/publishers", ego);
+	Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+}
+
+#[derive(Deserialize)]
+pub struct DirectoryForm {
+	/// Separated by whitespace, like tags. No keywords takes the channel out of the directory.
+	keywords: String
+}
+
+/// Lists the channel of one of our own egos in the channel directory under the given keywords, or takes it out of the directory.
+/// A listing takes effect right away, but one that has been taken out only disappears once it expires in the DHT.
+#[post("/channel/ego/{ego}/directory")]
+pub async fn channel_directory(g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>, form: web::Form<DirectoryForm>) -> web_error::Result<HttpResponse> {
+
+	let keywords = normalize_tags( form.keywords.split_whitespace() );
+	if keywords.len() > config::DIRECTORY_KEYWORDS_MAX {
+		return Err( WebError::bad_request( format!("A channel can be listed under at most {} keywords.", config::DIRECTORY_KEYWORDS_MAX) ) )
+	}
+
+	let address = ego_address( &g.services.lookup_ego( &p.ego ).await? )?;
+	let db = g.connect_database().await?;
+	let channel = db.clone().get_channel( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+
+	let listed = !keywords.is_empty();
+	channel.store_directory_keywords( if listed { Some( &keywords ) } else { None } ).await?;
+	db.record_action( Some( &p.ego ), AuditAction::SettingChanged, &format!("directory listing of {}", address) ).await?;
+
+	// The listing is renewed periodically anyway, so not reaching the DHT now isn't a reason to fail.
+	if listed {
+		let entry = DirectoryEntry { ego: p.ego.clone(), address, keywords };
+		if let Err(e) = directory::advertise( &g.services, &db, &entry ).await {
+			warn!("Unable to advertise the channel in the directory: {}", e);
+		}
+	}
+
+	let location = format!("/channel/feed/ego/{}", p.ego);
+	Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+}
+
+/// Pins one of our own posts to the top of the feed of the channel, after the ones that are pinned already.
+#[post("/channel/ego/{ego}/post/{post_id}/pin")]
+pub async fn channel_post_pin(g: web::Data<Arc<Globals>>, p: web::Path<OwnPostParams>) -> web_error::Result<HttpResponse> {
+	change_pinned_posts( &g, &p, true ).await
+}
+
+/// Takes a post out of the pinned ones.
+#[post("/channel/ego/{ego}/post/{post_id}/unpin")]
+pub async fn channel_post_unpin(g: web::Data<Arc<Globals>>, p: web::Path<OwnPostParams>) -> web_error::Result<HttpResponse> {
+	change_pinned_posts( &g, &p, false ).await
+}
+
+async fn change_pinned_posts( g: &Globals, p: &OwnPostParams, pin: bool ) -> web_error::Result<HttpResponse> {
+	let private_key = g.services.lookup_ego( &p.ego ).await?;
+	let address = ego_address( &private_key )?;
+	let db = g.connect_database().await?;
+	let channel = db.clone().get_channel( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+
+	let mut post_ids = channel.load_pinned_posts().await?;
+	let changed = if pin {
+		if post_ids.contains( &p.post_id ) {
+			false
+		}
+		else {
+			if post_ids.len() >= PINNED_POSTS_MAX {
+				return Err( WebError::bad_request( format!("At most {} posts can be pinned.", PINNED_POSTS_MAX) ) )
+			}
+			let timeline = channel.get_timeline( &address ).await?
+				.ok_or_else(|| WebError::not_found("Unknown publisher."))?;
+			if timeline.load_post( p.post_id ).await?.is_none() {
+				return Err( WebError::not_found("Post not found.") )
+			}
+			post_ids.push( p.post_id );
+			true
+		}
+	}
+	else {
+		let count = post_ids.len();
+		post_ids.retain(|id| *id != p.post_id);
+		post_ids.len() != count
+	};
+
+	if changed {
+		match channel.update_pinned_posts( &private_key, post_ids ).await {
+			Err(persistence::Error::NotFound) => return Err( WebError::forbidden("This ego doesn't own the channel anymore.") ),
+			other => other?
+		};
+		db.record_action( Some( &p.ego ), AuditAction::SettingChanged, &format!("pinned posts of {}", address) ).await?;
+	}
+
+	let location = format!("/channel/feed/ego/{}", p.ego);
+	Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+}
+
+/// Hides the posts of one of the publishers of a channel that we follow, and stops storing what it publishes.
+#[post("/channel/address/{address}/publishers/block")]
+pub async fn channel_publisher_block(g: web::Data<Arc<Globals>>, p: web::Path<ChannelAddressParams>, form: web::Form<PublisherForm>) -> web_error::Result<HttpResponse> {
+	change_blocklist( &g, &p.address, &form.publisher, true ).await
+}
+
+/// Shows the posts of a publisher again.
+/// What it has published while it was blocked is fetched from the swarm when it is needed.
+#[post("/channel/address/{address}/publishers/unblock")]
+pub async fn channel_publisher_unblock(g: web::Data<Arc<Globals>>, p: web::Path<ChannelAddressParams>, form: web::Form<PublisherForm>) -> web_error::Result<HttpResponse> {
+	change_blocklist( &g, &p.address, &form.publisher, false ).await
+}
+
+async fn change_blocklist( g: &Globals, address: &str, publisher: &str, block: bool ) -> web_error::Result<HttpResponse> {
+
+	let address = PublicKey::from_string( address )
+		.ok_or_else(|| WebError::bad_request("Invalid channel address."))?;
+	let publisher = PublicKey::from_string( publisher.trim() )
+		.ok_or_else(|| WebError::bad_request("Invalid key of the publisher."))?;
+
+	let db = g.connect_database().await?;
+	let channel = db.clone().get_channel( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+	if block && !channel.list_publishers().await?.contains( &publisher ) {
+		return Err( WebError::bad_request("Only the other publishers of a channel can be blocked.") )
+	}
+
+	if block {
+		db.block_publisher( &publisher ).await?;
+	} else {
+		db.unblock_publisher( &publisher ).await?;
+	}
+	// The publisher can publish in other channels as well.
+	page_cache::PAGES.clear();
+
+	let location = format!("/channel/feed/address/{}", address);
+	Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+}
+
+#[derive(Serialize)]
+pub struct HeldCommentView {
+	hash: String,
+	author: String,
+	/// The publisher of the post that is commented on.
+	publisher: String,
+	post_id: u64,
+	/// In seconds since the UNIX epoch, for tera's date filter.
+	timestamp: u64,
+	html: String
+}
+
+/// A decision in the moderation log of a channel.
+#[derive(Serialize)]
+pub struct ModerationEntryView {
+	/// In seconds since the UNIX epoch, for tera's date filter.
+	timestamp: u64,
+	action: &'static str,
+	/// The hash of the comment, or the address of the author.
+	subject: String
+}
+
+/// What the owner of a channel can decide about a comment.
+#[derive(Clone, Copy)]
+enum CommentDecision {
+	Approve,
+	/// Approves the author of the comment, which approves the comment along with the others that the author has made.
+	ApproveAuthor,
+	Hide,
+	Delete
+}
+
+/// The number of decisions that the moderation log of a channel shows.
+const MODERATION_LOG_LENGTH: u32 = 100;
+
+/// Shows the owner of a channel the comments on its posts that wait to be approved or hidden.
+#[get("/channel/ego/{ego}/moderation")]
+pub async fn channel_moderation(g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>) -> web_error::Result<HttpResponse> {
+
+	let address = ego_address( &g.services.lookup_ego( &p.ego ).await? )?;
+	let channel = g.connect_database().await?
+		.get_channel( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+
+	let comments: Vec<HeldCommentView> = channel.list_held_comments().await?.into_iter().map(|held| HeldCommentView {
+		hash: held.comment.hash.to_string(),
+		author: held.comment.author.to_string(),
+		publisher: held.publisher.to_string(),
+		post_id: held.post_id,
+		timestamp: held.comment.timestamp / 1000,
+		html: preview::render( &held.comment.content, ContentFormat::Plain )
+	}).collect();
+
+	let mut context = tera::Context::new();
+	context.insert("ego", &p.ego);
+	context.insert("address", &address.to_string());
+	context.insert("comments", &comments);
+
+	let html = g.templates.render("blog/moderation.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+#[derive(Deserialize)]
+pub struct ModerationForm {
+	/// The hash of the comment.
+	comment: String
+}
+
+#[post("/channel/ego/{ego}/moderation/approve")]
+pub async fn channel_moderation_approve(g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>, form: web::Form<ModerationForm>) -> web_error::Result<HttpResponse> {
+	moderate_comment( &g, &p.ego, &form.comment, CommentDecision::Approve ).await
+}
+
+/// Approves the author of a comment, whose comments on the channel aren't held anymore from then on.
+#[post("/channel/ego/{ego}/moderation/approve-author")]
+pub async fn channel_moderation_approve_author(g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>, form: web::Form<ModerationForm>) -> web_error::Result<HttpResponse> {
+	moderate_comment( &g, &p.ego, &form.comment, CommentDecision::ApproveAuthor ).await
+}
+
+/// Hides a comment, so that it isn't shown, not even when it arrives again.
+#[post("/channel/ego/{ego}/moderation/hide")]
+pub async fn channel_moderation_hide(g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>, form: web::Form<ModerationForm>) -> web_error::Result<HttpResponse> {
+	moderate_comment( &g, &p.ego, &form.comment, CommentDecision::Hide ).await
+}
+
+/// Deletes the content of a comment, on this node and on the nodes of the subscribers.
+#[post("/channel/ego/{ego}/moderation/delete")]
+pub async fn channel_moderation_delete(g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>, form: web::Form<ModerationForm>) -> web_error::Result<HttpResponse> {
+	moderate_comment( &g, &p.ego, &form.comment, CommentDecision::Delete ).await
+}
+
+/// Makes a decision about a comment, which reaches the subscribers of the channel as an event.
+async fn moderate_comment( g: &Globals, ego: &str, comment: &str, decision: CommentDecision ) -> web_error::Result<HttpResponse> {
+
+	let hash = HashCode::from_string( comment.trim() )
+		.ok_or_else(|| WebError::bad_request("Invalid comment hash."))?;
+
+	let private_key = g.services.lookup_ego( ego ).await?;
+	let address = ego_address( &private_key )?;
+	let db = g.connect_database().await?;
+	let channel = db.clone().get_channel( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+	let author = channel.load_comment_author( &hash ).await?
+		.ok_or_else(|| WebError::not_found("Unknown comment."))?;
+
+	let (action, audit_action, subject) = match decision {
+		CommentDecision::Approve => (ModerationAction::ApproveComment( hash.clone() ), AuditAction::CommentApproved, hash.to_string()),
+		CommentDecision::ApproveAuthor => (ModerationAction::ApproveAuthor( author.clone() ), AuditAction::CommenterApproved, author.to_string()),
+		CommentDecision::Hide => (ModerationAction::HideComment( hash.clone() ), AuditAction::CommentHidden, hash.to_string()),
+		CommentDecision::Delete => (ModerationAction::DeleteComment( hash.clone() ), AuditAction::CommentDeleted, hash.to_string())
+	};
+	match channel.moderate( &private_key, action ).await {
+		Err(persistence::Error::NotFound) => return Err( WebError::forbidden("This ego doesn't own the channel anymore.") ),
+		other => other?
+	};
+	db.record_action( Some( ego ), audit_action, &subject ).await?;
+
+	let location = format!("/channel/ego/{}/moderation", ego);
+	Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+}
+
+/// Shows the owner of a channel the decisions that have been made about the comments on its posts, the newest first.
+#[get("/channel/ego/{ego}/moderation/log")]
+pub async fn channel_moderation_log(g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>) -> web_error::Result<HttpResponse> {
+
+	let address = ego_address( &g.services.lookup_ego( &p.ego ).await? )?;
+	let channel = g.connect_database().await?
+		.get_channel( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+
+	let entries: Vec<ModerationEntryView> = channel.list_moderation_log( MODERATION_LOG_LENGTH ).await?.into_iter().map(|entry| {
+		let (action, subject) = match entry.action {
+			ModerationAction::ApproveComment(hash) => ("comment approved", hash.to_string()),
+			ModerationAction::HideComment(hash) => ("comment hidden", hash.to_string()),
+			ModerationAction::DeleteComment(hash) => ("comment deleted", hash.to_string()),
+			ModerationAction::ApproveAuthor(author) => ("commenter approved", author.to_string())
+		};
+		ModerationEntryView {
+			timestamp: entry.timestamp / 1000,
+			action,
+			subject
+		}
+	}).collect();
+
+	let mut context = tera::Context::new();
+	context.insert("ego", &p.ego);
+	context.insert("address", &address.to_string());
+	context.insert("entries", &entries);
+
+	let html = g.templates.render("blog/moderation-log.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+/// A profile that lost to another one with the same revision.
+#[derive(Serialize)]
+pub struct ProfileConflictView {
+	id: i64,
+	revision: u64,
+	title: String,
+	description: String,
+	/// In seconds since the UNIX epoch, for tera's date filter.
+	recorded: u64
+}
+
+#[derive(Deserialize)]
+pub struct ProfileConflictForm {
+	conflict: i64
+}
+
+/// Shows the owner of a channel a form to change the title, description and picture of the channel.
+#[get("/channel/ego/{ego}/profile")]
+pub async fn channel_profile(g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>) -> web_error::Result<HttpResponse> {
+
+	let address = ego_address( &g.services.lookup_ego( &p.ego ).await? )?;
+	let db = g.connect_database().await?;
+	let channel = db.clone().get_channel( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+	let profile = channel.fetch_profile().await?;
+	let conflicts: Vec<ProfileConflictView> = channel.list_profile_conflicts().await?.into_iter().map(|c| ProfileConflictView {
+		id: c.id,
+		revision: c.profile.base.revision,
+		title: c.profile.base.title,
+		description: c.profile.base.description,
+		recorded: c.recorded / 1000
+	}).collect();
+	let defaults = match db.get_timeline( &address ).await? {
+		None => PostDefaults::default(),
+		Some(timeline) => timeline.load_post_defaults().await?
+	};
+
+	let mut context = tera::Context::new();
+	context.insert("ego", &p.ego);
+	context.insert("address", &address.to_string());
+	context.insert("revision", &profile.as_ref().map(|p| p.base.revision).unwrap_or(0));
+	context.insert("title", &profile.as_ref().map(|p| p.base.title.as_str()).unwrap_or(""));
+	context.insert("description", &profile.as_ref().map(|p| p.base.description.as_str()).unwrap_or(""));
+	context.insert("has_picture", &profile.map(|p| p.base.profile_picture.is_some()).unwrap_or(false));
+	context.insert("description_max_len", &PROFILE_DESCRIPTION_MAX_LEN);
+	context.insert("picture_max_size", &config::PROFILE_PICTURE_MAX_SIZE);
+	context.insert("conflicts", &conflicts);
+	context.insert("default_tags", &defaults.tags.join(" "));
+	context.insert("footer", &defaults.footer.unwrap_or_default());
+
+	let html = g.templates.render("blog/profile.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+/// Signs and stores a new revision of the profile of a channel, and adds it to the event log so that it is spread through the swarm.
+/// The picture is kept unless a new one is uploaded, or `remove_picture` is checked.
+#[post("/channel/ego/{ego}/profile")]
+pub async fn channel_profile_post(g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>, mut payload: Multipart) -> web_error::Result<HttpResponse> {
+
+	// Read the form
+	let mut title = String::new();
+	let mut description = String::new();
+	let mut picture = Vec::new();
+	let mut remove_picture = false;
+	while let Some(field) = payload.next().await {
+		let mut field = field?;
+		let name = field.content_disposition()
+			.and_then(|cd| cd.get_name().map(|n| n.to_owned()))
+			.unwrap_or_default();
+
+		let mut data = Vec::new();
+		while let Some(chunk) = field.next().await {
+			let chunk = chunk?;
+			if data.len() + chunk.len() > config::PROFILE_PICTURE_MAX_SIZE {
+				return Err( WebError::payload_too_large("Picture is too large.") )
+			}
+			data.extend_from_slice( &chunk );
+		}
+
+		match name.as_str() {
+			"title" => title = String::from_utf8( data ).map_err(|_| WebError::bad_request("Title is not valid UTF-8."))?,
+			"description" => description = String::from_utf8( data ).map_err(|_| WebError::bad_request("Description is not valid UTF-8."))?,
+			"picture" => picture = data,
+			"remove_picture" => remove_picture = true,
+			_ => {}
+		}
+	}
+
+	// The title and description are prefixed with their length in the signed profile.
+	let title = title.trim().to_owned();
+	let description = description.trim().to_owned();
+	if title.len() > u8::MAX as usize {
+		return Err( WebError::bad_request("The title is too long.") )
+	}
+	if description.len() > PROFILE_DESCRIPTION_MAX_LEN as usize {
+		return Err( WebError::bad_request("The description is too long.") )
+	}
+
+	let private_key = g.services.lookup_ego( &p.ego ).await?;
+	let db = g.connect_database().await?;
+	let channel = db.clone().get_channel( &ego_address( &private_key )? ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+
+	// The picture is stored like an attachment, so that subscribers can fetch its blocks from the swarm.
+	let profile_picture = if picture.len() > 0 {
+		let mime_type = identicon::sniff_image_type( &picture )
+			.ok_or_else(|| WebError::bad_request("The picture is not an image that is supported."))?;
+		Some( db.store_attachment( &picture, mime_type ).await? )
+	}
+	else if remove_picture {
+		None
+	}
+	else {
+		channel.fetch_profile().await?.and_then(|p| p.base.profile_picture)
+	};
+
+	let profile = match channel.update_profile( &private_key, title, description, profile_picture ).await {
+		Err(persistence::Error::NotFound) => return Err( WebError::forbidden("This ego doesn't own the channel anymore.") ),
+		other => other?
+	};
+	db.record_action( Some( &p.ego ), AuditAction::ProfileUpdated, &format!("{} revision {}", ego_address( &private_key )?, profile.base.revision) ).await?;
+
+	let location = format!("/channel/feed/ego/{}", p.ego);
+	Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+}
+
+/// Makes a profile that lost to another one with the same revision the current profile again, as a new revision.
+#[post("/channel/ego/{ego}/profile/conflicts/restore")]
+pub async fn channel_profile_conflict_restore(g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>, form: web::Form<ProfileConflictForm>) -> web_error::Result<HttpResponse> {
+
+	let private_key = g.services.lookup_ego( &p.ego ).await?;
+	let db = g.connect_database().await?;
+	let channel = db.clone().get_channel( &ego_address( &private_key )? ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+	let lost = channel.load_profile_conflict( form.conflict ).await?
+		.ok_or_else(|| WebError::not_found("Unknown conflict."))?;
+
+	let profile = match channel.update_profile( &private_key, lost.base.title, lost.base.description, lost.base.profile_picture ).await {
+		Err(persistence::Error::NotFound) => return Err( WebError::forbidden("This ego doesn't own the channel anymore.") ),
+		other => other?
+	};
+	channel.delete_profile_conflict( form.conflict ).await?;
+	db.record_action( Some( &p.ego ), AuditAction::ProfileUpdated, &format!("{} revision {}", ego_address( &private_key )?, profile.base.revision) ).await?;
+
+	let location = format!("/channel/ego/{}/profile", p.ego);
+	Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+}
+
+/// Forgets a profile that lost to another one with the same revision.
+#[post("/channel/ego/{ego}/profile/conflicts/dismiss")]
+pub async fn channel_profile_conflict_dismiss(g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>, form: web::Form<ProfileConflictForm>) -> web_error::Result<HttpResponse> {
+
+	let address = ego_address( &g.services.lookup_ego( &p.ego ).await? )?;
+	let db = g.connect_database().await?;
+	let channel = db.get_channel( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+	if !channel.delete_profile_conflict( form.conflict ).await? {
+		return Err( WebError::not_found("Unknown conflict.") )
+	}
+
+	let location = format!("/channel/ego/{}/profile", p.ego);
+	Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+}
+
+#[derive(Deserialize)]
+pub struct PostDefaultsForm {
+	tags: String,
+	footer: String
+}
+
+/// Changes the tags and the footer that the new posts of one of our egos get.
+/// These are kept on this node only, nothing is sent to the swarm.
+#[post("/channel/ego/{ego}/defaults")]
+pub async fn channel_defaults_post(g: web::Data<Arc<Globals>>, p: web::Path<EgoParams>, form: web::Form<PostDefaultsForm>) -> web_error::Result<HttpResponse> {
+
+	let tags = normalize_tags( form.tags.split_whitespace() );
+	check_tags( &tags ).map_err( WebError::bad_request )?;
+	let footer = form.footer.trim();
+	let defaults = PostDefaults {
+		tags,
+		footer: if footer.is_empty() { None } else { Some( footer.to_owned() ) }
+	};
+
+	let address = ego_address( &g.services.lookup_ego( &p.ego ).await? )?;
+	let db = g.connect_database().await?;
+	let timeline = db.get_timeline( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown publisher."))?;
+	if !timeline.store_post_defaults( &defaults ).await? {
+		return Err( WebError::not_found("Unknown publisher.") )
+	}
+	db.record_action( Some( &p.ego ), AuditAction::SettingChanged, &format!("post defaults of {}", address) ).await?;
+
+	let location = format!("/channel/ego/{}/profile", p.ego);
+	Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+}
+
+
+#[derive(Serialize)]
+pub struct ChannelView {
+	address: String,
+	/// Whether we are connected to the swarm of the channel at the moment.
+	connected: bool,
+	/// The number of days that the channel asks its posts to be kept, where 0 is forever, or `None` if we don't know yet.
+	replication_days: Option<u32>,
+	/// Whether all posts of the channel are kept regardless, or `None` if we don't follow the channel.
+	keep_everything: Option<bool>
+}
+
+#[derive(Serialize)]
+pub struct CalendarEntry {
+	post_id: u64,
+	/// In seconds since the UNIX epoch, shifted into the timezone of the user, for tera's date filter.
+	time: i64,
+	/// Whether the post has been given a release time.
+	scheduled: bool
+}
+
+#[derive(Serialize)]
+pub struct CalendarDay {
+	/// The start of the day in the timezone of the user, shifted like the times of the entries.
+	date: i64,
+	entries: Vec<CalendarEntry>
+}
+
+#[derive(Serialize)]
+pub struct ChannelCalendar {
+	ego: String,
+	address: String,
+	upcoming: Vec<CalendarDay>,
+	/// The most recent day first.
+	history: Vec<CalendarDay>
+}
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+	#[serde(default)]
+	q: String,
+	#[serde(default = "first_page")]
+	page: u32
+}
+
+fn first_page() -> u32 { 1 }
+
+#[derive(Serialize)]
+pub struct SearchResultView {
+	address: String,
+	post_id: u64,
+	/// The matching part of the content, as HTML in which the matching words are marked.
+	html: String
+}
+
+/// Searches the content of all posts that we have stored.
+/// If only a few of them match, the swarms of our subscriptions are searched as well.
+#[get("/search")]
+pub async fn search( g: web::Data<Arc<Globals>>, q: web::Query<SearchQuery> ) -> web_error::Result<HttpResponse> {
+
+	let page = q.page.max(1);
+	let db = g.connect_database().await?;
+	let offset = (page as u64 - 1) * config::SEARCH_PAGE_SIZE as u64;
+	let results: Vec<SearchResultView> = db.search_posts( &q.q, config::SEARCH_PAGE_SIZE, offset ).await?
+		.into_iter().map( search_result_view ).collect();
+
+	let swarm_results = if page == 1 && results.len() < config::SEARCH_THIN_RESULTS {
+		search_swarms( &g, &db, &q.q, &results ).await?
+	} else {
+		Vec::new()
+	};
+
+	let mut context = tera::Context::new();
+	context.insert("query", &q.q);
+	context.insert("page", &page);
+	context.insert("has_more", &(results.len() == config::SEARCH_PAGE_SIZE as usize));
+	context.insert("results", &results);
+	context.insert("swarm_results", &swarm_results);
+
+	let html = g.templates.render("search.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok( HttpResponse::Ok().content_type("text/html").body( html ) )
+}
+
+fn search_result_view( result: timeline::SearchResult ) -> SearchResultView {
+	SearchResultView {
+		address: result.publisher.to_string(),
+		post_id: result.post_id,
+		html: preview::escape_html( &result.snippet )
+			.replace( timeline::SNIPPET_MATCH_START, "<mark>" )
+			.replace( timeline::SNIPPET_MATCH_END, "</mark>" )
+	}
+}
+
+/// Searches the swarms of all channels that we are connected to, and returns the found posts that aren't among the local results.
+/// The found posts are stored, so the ones that came with their content are searched locally from then on.
+async fn search_swarms( g: &Globals, db: &persistence::Handle, query: &str, local: &[SearchResultView] ) -> web_error::Result<Vec<SearchResultView>> {
+
+	let keywords: Vec<String> = query.split_whitespace().map(|w| w.to_owned()).collect();
+	if keywords.is_empty() {
+		return Ok( Vec::new() )
+	}
+	let nodes = g.subscriptions.read().await.as_ref().map(|s| s.nodes()).unwrap_or_default();
+
+	let mut found = Vec::new();
+	for result in future::join_all( nodes.iter().map(|node| node.search( &keywords )) ).await {
+		match result {
+			Err(e) => warn!("Unable to search swarm: {}", e),
+			Ok(posts) => found.extend( posts.into_iter().map(|(publisher, post_id)| ( publisher.to_string(), post_id )) )
+		}
+	}
+	found.retain(|(address, post_id)| !local.iter().any(|r| r.address == *address && r.post_id == *post_id));
+	if found.is_empty() {
+		return Ok( Vec::new() )
+	}
+
+	// The local index includes the found posts now, so it has their snippets.
+	let snippets: Vec<SearchResultView> = db.search_posts( query, config::SEARCH_PAGE_SIZE + found.len() as u32, 0 ).await?
+		.into_iter().map( search_result_view ).collect();
+	Ok( found.into_iter().map(|(address, post_id)| {
+		let html = snippets.iter()
+			.find(|r| r.address == address && r.post_id == post_id)
+			.map(|r| r.html.clone())
+			.unwrap_or_default();
+		SearchResultView { address, post_id, html }
+	}).collect() )
+}
+
+/// Shows the posts that are scheduled to be released, and the posts that have been released recently, of all our own channels.
+#[get("/calendar")]
+pub async fn calendar(g: web::Data<Arc<Globals>>) -> web_error::Result<HttpResponse> {
+
+	let db = g.connect_database().await?;
+	let offset = load_timezone_offset( &db ).await?;
+	let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+	let since = now.saturating_sub( config::CALENDAR_HISTORY_DAYS * 24 * 60 * 60 * 1000 );
+
+	let mut channels = Vec::new();
+	for timeline in db.list_my_timelines().await? {
+		let ego = match timeline.get_my_ego().await? {
+			None => continue,
+			Some(e) => e
+		};
+		let address = ego_address( &g.services.lookup_ego( &ego ).await? )?;
+
+		let mut upcoming = Vec::new();
+		let mut history = Vec::new();
+		for entry in timeline.list_schedule( since ).await? {
+			let release = entry.visible_from.unwrap_or( entry.publish_timestamp );
+			let calendar_entry = CalendarEntry {
+				post_id: entry.post_id,
+				time: (release / 1000) as i64 + offset * 60,
+				scheduled: entry.visible_from.is_some()
+			};
+			if release > now { upcoming.push( calendar_entry ) } else { history.push( calendar_entry ) }
+		}
+		let mut history = group_by_day( history );
+		history.reverse();
+
+		channels.push( ChannelCalendar {
+			ego,
+			address: address.to_string(),
+			upcoming: group_by_day( upcoming ),
+			history
+		});
+	}
+
+	let mut context = tera::Context::new();
+	context.insert("channels", &channels);
+	context.insert("timezone", &format_utc_offset( offset ));
+	context.insert("days", &config::CALENDAR_HISTORY_DAYS);
+
+	let html = g.templates.render("calendar.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+/// Groups entries that are in chronological order by the day they fall on.
+fn group_by_day( entries: Vec<CalendarEntry> ) -> Vec<CalendarDay> {
+	let mut days: Vec<CalendarDay> = Vec::new();
+
+	for entry in entries {
+		let date = entry.time - entry.time.rem_euclid( 24 * 60 * 60 );
+		match days.last_mut() {
+			Some(day) if day.date == date => day.entries.push( entry ),
+			_ => days.push( CalendarDay {
+				date,
+				entries: vec![ entry ]
+			})
+		}
+	}
+
+	days
+}
+
+#[derive(Deserialize)]
+pub struct TimezoneForm {
+	timezone: String
+}
+
+/// Sets the timezone in which times are entered and shown.
+#[post("/calendar/timezone")]
+pub async fn calendar_timezone(g: web::Data<Arc<Globals>>, form: web::Form<TimezoneForm>) -> web_error::Result<HttpResponse> {
+
+	let offset = parse_utc_offset( &form.timezone )
+		.ok_or_else(|| WebError::bad_request("Invalid timezone, give it as an offset from UTC like +02:00."))?;
+
+	let db = g.connect_database().await?;
+	db.store_setting( setup::SETTING_TIMEZONE_OFFSET, &offset.to_string() ).await?;
+	db.record_action( None, AuditAction::SettingChanged, setup::SETTING_TIMEZONE_OFFSET ).await?;
+
+	Ok( HttpResponse::Found().append_header((header::LOCATION, "/calendar")).finish() )
+}
+
+#[derive(Deserialize)]
+pub struct LanguageForm {
+	language: String
+}
+
+/// Sets the language in which the texts of this node itself, like the system posts in feeds, are shown.
+#[post("/settings/language")]
+pub async fn settings_language(g: web::Data<Arc<Globals>>, form: web::Form<LanguageForm>) -> web_error::Result<HttpResponse> {
+
+	let language = Language::from_code( &form.language )
+		.ok_or_else(|| WebError::bad_request("Unsupported language."))?;
+
+	let db = g.connect_database().await?;
+	db.store_setting( setup::SETTING_LANGUAGE, language.code() ).await?;
+	db.record_action( None, AuditAction::SettingChanged, setup::SETTING_LANGUAGE ).await?;
+
+	Ok( HttpResponse::Found().append_header((header::LOCATION, "/")).finish() )
+}
+
+#[derive(Deserialize)]
+pub struct SwarmSettingsForm {
+	relay_power: u8,
+	parent_count: u8,
+	/// Only sent when it is checked.
+	background_rebroadcasts: Option<String>,
+	connection_check_interval: u64,
+	reconnect_min_delay: u64,
+	reconnect_max_delay: u64
+}
+
+async fn render_settings( g: &Globals, tuning: &SwarmTuning, error: Option<&str> ) -> web_error::Result<HttpResponse> {
+
+	let config = config::get();
+	let mut context = tera::Context::new();
+	context.insert("tuning", tuning);
+	// The settings that the configuration file sets can't be changed here.
+	context.insert("relay_power_fixed", &config.relay_power.is_some());
+	context.insert("parent_count_fixed", &config.parent_count.is_some());
+	context.insert("relay_power_max", &config::RELAY_POWER_MAX);
+	context.insert("parent_count_max", &config::PARENT_COUNT_MAX);
+	context.insert("error", &error);
+
+	let html = g.templates.render("settings.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	let response = match error {
+		None => HttpResponse::Ok(),
+		Some(_) => HttpResponse::BadRequest()
+	}.content_type("text/html").body(html);
+
+	Ok(response)
+}
+
+/// Shows the settings of how this node takes part in the swarms of its channels.
+#[get("/settings")]
+pub async fn settings(g: web::Data<Arc<Globals>>) -> web_error::Result<HttpResponse> {
+
+	let db = g.connect_database().await?;
+	render_settings( &g, &SwarmTuning::load( &db ).await?, None ).await
+}
+
+/// Changes the swarm settings, and applies them to the swarms that we're connected to right away.
+/// Lowering the relay power hands the children that are too many over to other peers, rather than dropping them.
+#[post("/settings/swarm")]
+pub async fn settings_swarm(g: web::Data<Arc<Globals>>, form: web::Form<SwarmSettingsForm>) -> web_error::Result<HttpResponse> {
+
+	let db = g.connect_database().await?;
+	let current = SwarmTuning::load( &db ).await?;
+	let config = config::get();
+	let tuning = SwarmTuning {
+		relay_power: if config.relay_power.is_some() { current.relay_power } else { form.relay_power },
+		parent_count: if config.parent_count.is_some() { current.parent_count } else { form.parent_count },
+		background_rebroadcasts: form.background_rebroadcasts.is_some(),
+		connection_check_interval: form.connection_check_interval,
+		reconnect_min_delay: form.reconnect_min_delay,
+		reconnect_max_delay: form.reconnect_max_delay
+	};
+	if let Err(e) = tuning.check() {
+		return render_settings( &g, &tuning, Some( e.as_str() ) ).await
+	}
+
+	tuning.store( &db ).await?;
+	db.record_action( None, AuditAction::SettingChanged, "swarm settings" ).await?;
+
+	if let Some(subscriptions) = &*g.subscriptions.read().await {
+		for node in subscriptions.nodes() {
+			node.tune( &tuning ).await;
+		}
+	}
+
+	Ok( HttpResponse::Found().append_header((header::LOCATION, "/settings")).finish() )
+}
+
+/// Lists all channels that we know, with links to their event logs.
+#[get("/admin/channels")]
+pub async fn admin_channels(g: web::Data<Arc<Globals>>) -> web_error::Result<HttpResponse> {
+
+	let db = g.connect_database().await?;
+	let subscriptions = g.subscriptions.read().await;
+
+	let mut channels = Vec::new();
+	let subscribed = db.list_subscriptions().await?;
+	for channel in db.list_channels().await? {
+		let address = channel.load_address().await?;
+		channels.push( ChannelView {
+			connected: subscriptions.as_ref().and_then(|s| s.node( &address )).is_some(),
+			replication_days: channel.load_parameters().await?.map(|p| p.requested_replication_time),
+			keep_everything: if subscribed.contains( &address ) { Some( db.load_keep_everything( &address ).await? ) } else { None },
+			address: address.to_string()
+		});
+	}
+
+	let mut context = tera::Context::new();
+	context.insert("channels", &channels);
+	context.insert("data_dir", &db.data_dir().to_string_lossy());
+
+	let html = g.templates.render("admin/channels.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+#[derive(Deserialize)]
+pub struct PageQuery {
+	page: Option<u64>
+}
+
+#[derive(Serialize)]
+pub struct EventView {
+	id: u64,
+	publisher: Option<String>,
+	event_type: String,
+	size: usize,
+	hash: String,
+	applied: bool
+}
+
+/// The number of events that are shown on a page of the event log.
+const EVENT_LOG_PAGE_SIZE: u32 = 50;
+
+/// Returns a readable name for the type of a stored event.
+fn event_type_name( publisher_event: bool, event_type: Option<u8> ) -> String {
+	let byte = match event_type {
+		None => return "empty".to_owned(),
+		Some(b) => b
+	};
+
+	let name = if publisher_event {
+		match byte.try_into() {
+			Ok(PublisherEventType::UpdateProfile) => "update profile",
+			Ok(PublisherEventType::PublishPost) => "publish post",
+			Ok(PublisherEventType::RevisePost) => "revise post",
+			Ok(PublisherEventType::ForgetPost) => "forget post",
+			Ok(PublisherEventType::Comment) => "comment",
+			Ok(PublisherEventType::Reaction) => "reaction",
+			Err(_) => "unknown"
+		}
+	} else {
+		match byte.try_into() {
+			Ok(ChannelEventType::UpdateChannelProfile) => "update channel profile",
+			Ok(ChannelEventType::UpdatePublisherList) => "update publisher list",
+			Ok(ChannelEventType::Create) => "create",
+			Ok(ChannelEventType::TransferOwnership) => "transfer ownership",
+			Ok(ChannelEventType::AcceptOwnership) => "accept ownership",
+			Ok(ChannelEventType::UpdatePinnedPosts) => "update pinned posts",
+			Ok(ChannelEventType::ModerateComments) => "moderate comments",
+			Ok(ChannelEventType::Close) => "close",
+			Err(_) => "unknown"
+		}
+	};
+	format!("{} ({})", name, byte)
+}
+
+/// Shows the raw event log of a channel, the newest events first.
+#[get("/admin/channels/{address}/events")]
+pub async fn admin_channel_events(g: web::Data<Arc<Globals>>, p: web::Path<ChannelAddressParams>, q: web::Query<PageQuery>) -> web_error::Result<HttpResponse> {
+
+	let address = PublicKey::from_string( &p.address )
+		.ok_or_else(|| WebError::bad_request("Invalid channel address."))?;
+	let page = q.page.unwrap_or(1).max(1);
+
+	let channel = g.connect_database().await?
+		.get_channel( &address ).await?
+		.ok_or_else(|| WebError::not_found("Unknown channel."))?;
+
+	let count = channel.count_events().await?;
+	let events: Vec<EventView> = channel.list_events( (page - 1) * EVENT_LOG_PAGE_SIZE as u64, EVENT_LOG_PAGE_SIZE ).await?
+		.into_iter().map(|e| EventView {
+			event_type: event_type_name( e.publisher.is_some(), e.event_type ),
+			id: e.id,
+			publisher: e.publisher.map(|a| a.to_string()),
+			size: e.size,
+			hash: e.hash.to_string(),
+			applied: e.applied
+		}).collect();
+
+	let mut context = tera::Context::new();
+	context.insert("address", &p.address);
+	context.insert("events", &events);
+	context.insert("page", &page);
+	context.insert("last_page", &((count + EVENT_LOG_PAGE_SIZE as u64 - 1) / EVENT_LOG_PAGE_SIZE as u64).max(1));
+
+	let html = g.templates.render("admin/events.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+#[derive(Deserialize)]
+pub struct EventParams {
+	address: String,
+	id: u64
+}
+
+/// Tries to apply a pending event again, requesting the events before it from the swarm if they are missing.
+#[post("/admin/channels/{address}/events/{id}/reapply")]
+pub async fn admin_channel_event_reapply(g: web::Data<Arc<Globals>>, p: web::Path<EventParams>) -> web_error::Result<HttpResponse> {
+
+	let address = PublicKey::from_string( &p.address )
+		.ok_or_else(|| WebError::bad_request("Invalid channel address."))?;
+	let node = g.subscriptions.read().await.as_ref()
+		.and_then(|s| s.node( &address ))
+		.ok_or_else(|| WebError::service_unavailable("Not connected to the swarm of this channel."))?;
+
+	if !node.reapply_event( p.id ).await? {
+		return Err( WebError::conflict("The event can't be applied yet, because earlier events are still missing.") )
+	}
+
+	let location = format!("/admin/channels/{}/events", p.address);
+	Ok( HttpResponse::Found().append_header((header::LOCATION, location)).finish() )
+}
+
+#[derive(Deserialize)]
+pub struct RetentionForm {
+	keep_everything: bool
+}
+
+/// Sets whether all posts of a channel that we follow are kept, rather than only as long as the channel asks for.
+#[post("/admin/channels/{address}/retention")]
+pub async fn admin_channel_retention(g: web::Data<Arc<Globals>>, p: web::Path<ChannelAddressParams>, form: web::Form<RetentionForm>) -> web_error::Result<HttpResponse> {
+
+	let address = PublicKey::from_string( &p.address )
+		.ok_or_else(|| WebError::bad_request("Invalid channel address."))?;
+	let db = g.connect_database().await?;
+
+	if !db.store_keep_everything( &address, form.keep_everything ).await? {
+		return Err( WebError::not_found("Not subscribed to this channel.") )
+	}
+	db.record_action( None, AuditAction::SettingChanged, &format!("retention of {}", address) ).await?;
+
+	Ok( HttpResponse::Found().append_header((header::LOCATION, "/admin/channels")).finish() )
+}
+
+#[derive(Serialize)]
+pub struct AuditEntryView {
+	id: i64,
+	/// In milliseconds since the UNIX epoch.
+	time: u64,
+	actor: Option<String>,
+	action: String,
+	subject: String
+}
+
+/// The number of entries that are shown on a page of the audit log.
+const AUDIT_LOG_PAGE_SIZE: u32 = 50;
+
+impl From<persistence::audit::AuditEntry> for AuditEntryView {
+	fn from( entry: persistence::audit::AuditEntry ) -> Self {
+		Self {
+			id: entry.id,
+			time: entry.time,
+			actor: entry.actor,
+			action: entry.action,
+			subject: entry.subject
+		}
+	}
+}
+
+/// Shows the administrative actions that have been taken on this node, the newest first.
+#[get("/admin/audit")]
+pub async fn admin_audit(g: web::Data<Arc<Globals>>, q: web::Query<PageQuery>) -> web_error::Result<HttpResponse> {
+
+	let page = q.page.unwrap_or(1).max(1);
+	let db = g.connect_database().await?;
+
+	let count = db.count_audit_entries().await?;
+	let entries: Vec<AuditEntryView> = db.list_audit_entries( (page - 1) * AUDIT_LOG_PAGE_SIZE as u64, AUDIT_LOG_PAGE_SIZE ).await?
+		.into_iter().map(|e| e.into()).collect();
+	// Times are shown in the timezone of the user, in seconds for tera's date filter.
+	let offset = load_timezone_offset( &db ).await?;
+	let times: Vec<i64> = entries.iter().map(|e| (e.time / 1000) as i64 + offset * 60).collect();
+
+	let mut context = tera::Context::new();
+	context.insert("entries", &entries);
+	context.insert("times", &times);
+	context.insert("timezone", &format_utc_offset( offset ));
+	context.insert("page", &page);
+	context.insert("last_page", &((count + AUDIT_LOG_PAGE_SIZE as u64 - 1) / AUDIT_LOG_PAGE_SIZE as u64).max(1));
+
+	let html = g.templates.render("admin/audit.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+/// Exports the whole audit log as a JSON file, oldest entries first.
+#[get("/admin/audit/export.json")]
+pub async fn admin_audit_export(g: web::Data<Arc<Globals>>) -> web_error::Result<HttpResponse> {
+
+	let db = g.connect_database().await?;
+	let entries: Vec<AuditEntryView> = db.export_audit_log().await?.into_iter().map(|e| e.into()).collect();
+
+	Ok( HttpResponse::Ok()
+		.append_header((header::CONTENT_DISPOSITION, "attachment; filename=\"audit-log.json\""))
+		.json( entries ) )
+}
+
+
+#[derive(Serialize)]
+pub struct PeerStatsView {
+	address: String,
+	sessions: u64,
+	messages: u64,
+	bytes: u64,
+	malformed: u64,
+	timeouts: u64,
+	uptime_minutes: u64,
+	/// In seconds since the UNIX epoch, for tera's date filter.
+	last_seen: u64,
+	score: f64
+}
+
+/// The timestamps are in seconds since the UNIX epoch, for tera's date filter.
+#[derive(Serialize)]
+pub struct PeerSessionView {
+	started: u64,
+	ended: Option<u64>,
+	messages: u64,
+	bytes: u64,
+	malformed: u64,
+	timeouts: u64
+}
+
+/// The maximum number of sessions that is shown on the page of a peer.
+const PEER_SESSION_HISTORY: u32 = 50;
+
+impl From<peer::Stats> for PeerStatsView {
+	fn from( stats: peer::Stats ) -> Self {
+		Self {
+			score: stats.score(),
+			address: stats.address,
+			sessions: stats.sessions,
+			messages: stats.counters.messages,
+			bytes: stats.counters.bytes,
+			malformed: stats.counters.malformed,
+			timeouts: stats.counters.timeouts,
+			uptime_minutes: stats.uptime / 60000,
+			last_seen: stats.last_seen / 1000
+		}
+	}
+}
+
+/// Lists all peers that we've been connected to.
+#[get("/admin/peers")]
+pub async fn admin_peers(g: web::Data<Arc<Globals>>) -> web_error::Result<HttpResponse> {
+
+	let db = g.connect_database().await?;
+	let peers: Vec<PeerStatsView> = db.list_peer_stats().await?.into_iter().map(|s| s.into()).collect();
+
+	let mut context = tera::Context::new();
+	context.insert("peers", &peers);
+
+	let html = g.templates.render("admin/peers.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+/// Shows the statistics of a peer, and the history of our sessions with it.
+#[get("/admin/peers/{address}")]
+pub async fn admin_peer(g: web::Data<Arc<Globals>>, p: web::Path<ChannelAddressParams>) -> web_error::Result<HttpResponse> {
+
+	let address = PublicKey::from_string( &p.address )
+		.ok_or_else(|| WebError::bad_request("Invalid peer address."))?;
+
+	let db = g.connect_database().await?;
+	let peer = db.get_peer( &address );
+	let stats: PeerStatsView = peer.load_stats().await?
+		.ok_or_else(|| WebError::not_found("We haven't been connected to this peer."))?
+		.into();
+	let sessions: Vec<PeerSessionView> = peer.load_sessions( PEER_SESSION_HISTORY ).await?.into_iter().map(|s| PeerSessionView {
+		started: s.started / 1000,
+		ended: s.ended.map(|e| e / 1000),
+		messages: s.counters.messages,
+		bytes: s.counters.bytes,
+		malformed: s.counters.malformed,
+		timeouts: s.counters.timeouts
+	}).collect();
+
+	let mut context = tera::Context::new();
+	context.insert("peer", &stats);
+	context.insert("sessions", &sessions);
+
+	let html = g.templates.render("admin/peer.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+/// Exposes the metrics of the node in the Prometheus text format.
+#[get("/metrics")]
+pub async fn prometheus_metrics(g: web::Data<Arc<Globals>>) -> HttpResponse {
+	metrics::update( &g.subscriptions ).await;
+
+	HttpResponse::Ok()
+		.content_type("text/plain; version=0.0.4")
+		.body( metrics::encode() )
+}
+
+/// Tells whether the node can do its work, with a JSON report of every dependency that has been checked.
+/// Responds with 503 if any of them fails, so that a service manager can restart the node.
+#[get("/healthz")]
+pub async fn healthz(g: web::Data<Arc<Globals>>) -> HttpResponse {
+	health_response( health::check_health( &g.services ).await )
+}
+
+/// Tells whether the node is healthy and has tried to join the swarms of its channels, like `/healthz` does.
+#[get("/readyz")]
+pub async fn readyz(g: web::Data<Arc<Globals>>) -> HttpResponse {
+	health_response( health::check_readiness( &g.services, &g.subscriptions ).await )
+}
+
+fn health_response( report: HealthReport ) -> HttpResponse {
+	let mut response = if report.ok { HttpResponse::Ok() } else { HttpResponse::ServiceUnavailable() };
+	response
+		.append_header((header::CACHE_CONTROL, "no-store"))
+		.json( report )
+}
+
+/// Summarizes the metrics of the node, for people to read.
+#[get("/status")]
+pub async fn status(g: web::Data<Arc<Globals>>) -> web_error::Result<HttpResponse> {
+	metrics::update( &g.subscriptions ).await;
+
+	let mut context = tera::Context::new();
+	context.insert("status", &metrics::summarize());
+	context.insert("traffic", &metrics::traffic( &g.subscriptions ).await);
+	context.insert("upload_limit", &config::get().upload_limit);
+	context.insert("maintenance", &maintenance::last_status());
+
+	let html = g.templates.render("status.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+async fn render_admin_config( g: &Globals, error: Option<&str>, restart: Option<&[&str]> ) -> web_error::Result<HttpResponse> {
+
+	let mut context = tera::Context::new();
+	context.insert("config", &*config::get());
+	context.insert("config_file", &config::config_file().map(|p| p.display().to_string()));
+	context.insert("error", &error);
+	context.insert("reloaded", &restart.is_some());
+	context.insert("restart", &restart.unwrap_or(&[]));
+
+	let html = g.templates.render("admin/config.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	let response = match error {
+		None => HttpResponse::Ok(),
+		Some(_) => HttpResponse::BadRequest()
+	}.content_type("text/html").body(html);
+
+	Ok(response)
+}
+
+/// Shows the settings of the configuration file that are in use.
+#[get("/admin/config")]
+pub async fn admin_config(g: web::Data<Arc<Globals>>) -> web_error::Result<HttpResponse> {
+	render_admin_config( &g, None, None ).await
+}
+
+/// Reloads the configuration file, like SIGHUP does.
+/// A configuration file with errors is not used, and its errors are shown instead.
+#[post("/admin/config/reload")]
+pub async fn admin_config_reload(g: web::Data<Arc<Globals>>) -> web_error::Result<HttpResponse> {
+
+	let restart = match reload::reload() {
+		Err(e) => return render_admin_config( &g, Some( &e.to_string() ), None ).await,
+		Ok(r) => r
+	};
+
+	// Before the setup has been done, there is no audit log yet.
+	if persistence::database_exists() {
+		let db = g.connect_database().await?;
+		let subject = config::config_file().map(|p| p.display().to_string()).unwrap_or_default();
+		db.record_action( None, AuditAction::ConfigReloaded, &subject ).await?;
+	}
+
+	render_admin_config( &g, None, Some( restart.as_slice() ) ).await
+}
+
+
+#[derive(Serialize)]
+pub struct NotificationView {
+	kind: &'static str,
+	/// The page of the post that the notification is about.
+	url: String,
+	channel: String,
+	author: Option<String>,
+	/// In milliseconds since the UNIX epoch.
+	time: u64,
+	read: bool
+}
+
+/// The number of notifications that are shown on a page.
+const NOTIFICATIONS_PAGE_SIZE: u32 = 50;
+
+impl From<Notification> for NotificationView {
+	fn from( notification: Notification ) -> Self {
+		Self {
+			kind: match notification.kind {
+				NotificationKind::Post => "New post",
+				NotificationKind::Revision => "Revised post",
+				NotificationKind::Comment => "Comment on your post",
+				NotificationKind::Mention => "Mentioned in a comment"
+			},
+			url: format!("/channel/address/{}/post/{}", notification.publisher, notification.post_id),
+			channel: notification.channel.to_string(),
+			author: notification.author.map(|a| a.to_string()),
+			time: notification.time,
+			read: notification.read
+		}
+	}
+}
+
+/// Shows what has happened in the channels that we follow and publish in, the newest first.
+#[get("/notifications")]
+pub async fn notifications(g: web::Data<Arc<Globals>>, q: web::Query<PageQuery>) -> web_error::Result<HttpResponse> {
+
+	let page = q.page.unwrap_or(1).max(1);
+	let db = g.connect_database().await?;
+
+	let count = db.count_notifications().await?;
+	let unread = db.count_unread_notifications().await?;
+	let notifications: Vec<NotificationView> = db.list_notifications( (page - 1) * NOTIFICATIONS_PAGE_SIZE as u64, NOTIFICATIONS_PAGE_SIZE ).await?
+		.into_iter().map(|n| n.into()).collect();
+	// Times are shown in the timezone of the user, in seconds for tera's date filter.
+	let offset = load_timezone_offset( &db ).await?;
+	let times: Vec<i64> = notifications.iter().map(|n| (n.time / 1000) as i64 + offset * 60).collect();
+
+	let mut context = tera::Context::new();
+	context.insert("notifications", &notifications);
+	context.insert("unread", &unread);
+	context.insert("times", &times);
+	context.insert("timezone", &format_utc_offset( offset ));
+	context.insert("page", &page);
+	context.insert("last_page", &((count + NOTIFICATIONS_PAGE_SIZE as u64 - 1) / NOTIFICATIONS_PAGE_SIZE as u64).max(1));
+
+	let html = g.templates.render("notifications.html", &context)
+		.map_err(|e| { error!("Template error: {}", e); WebError::internal("Template error") } )?;
+	Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+/// Marks all notifications as read.
+#[post("/notifications/read")]
+pub async fn notifications_read(g: web::Data<Arc<Globals>>) -> web_error::Result<HttpResponse> {
+
+	let db = g.connect_database().await?;
+	db.mark_notifications_read().await?;
+
+	Ok( HttpResponse::Found().append_header((header::LOCATION, "/notifications")).finish() )
+}
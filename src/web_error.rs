@@ -0,0 +1,186 @@
+//! The errors of the web interface, which are shown as a page that tells what went wrong.
+//!
+//! Errors that are caused by the request, like an invalid address or an unknown channel, tell so in their message.
+//! Errors of the node itself are logged where they occur, and only show a general message.
+//! The page is rendered with the `error.html` template, or is plain text if that fails, so that an error never goes without a response.
+//! The JSON API answers with plain errors instead, see `api`.
+
+use std::{
+	fmt,
+	io,
+	sync::{Arc, RwLock}
+};
+
+use actix_multipart::MultipartError;
+use actix_web::{
+	http::StatusCode,
+	HttpResponse,
+	ResponseError
+};
+use lazy_static::lazy_static;
+use tracing::{error, warn};
+
+use crate::{
+	persistence,
+	services,
+	swarm,
+	templates::Templates
+};
+
+
+
+pub type Result<T> = std::result::Result<T, WebError>;
+
+#[derive(Debug)]
+pub struct WebError {
+	status: StatusCode,
+	/// What is told to the user.
+	message: String
+}
+
+
+
+lazy_static! {
+	/// The templates that the error pages are rendered with, once the web interface has started.
+	static ref TEMPLATES: RwLock<Option<Arc<Templates>>> = RwLock::new( None );
+}
+
+
+
+/// Renders the error pages with the given templates from now on.
+pub fn use_templates( templates: Arc<Templates> ) {
+	*TEMPLATES.write().unwrap() = Some( templates );
+}
+
+impl WebError {
+
+	pub fn new( status: StatusCode, message: impl Into<String> ) -> Self {
+		Self {
+			status,
+			message: message.into()
+		}
+	}
+
+	pub fn bad_request( message: impl Into<String> ) -> Self {
+		Self::new( StatusCode::BAD_REQUEST, message )
+	}
+
+	pub fn unauthorized( message: impl Into<String> ) -> Self {
+		Self::new( StatusCode::UNAUTHORIZED, message )
+	}
+
+	pub fn forbidden( message: impl Into<String> ) -> Self {
+		Self::new( StatusCode::FORBIDDEN, message )
+	}
+
+	pub fn not_found( message: impl Into<String> ) -> Self {
+		Self::new( StatusCode::NOT_FOUND, message )
+	}
+
+	pub fn conflict( message: impl Into<String> ) -> Self {
+		Self::new( StatusCode::CONFLICT, message )
+	}
+
+	pub fn payload_too_large( message: impl Into<String> ) -> Self {
+		Self::new( StatusCode::PAYLOAD_TOO_LARGE, message )
+	}
+
+	/// An error of the node itself, which should have been logged already.
+	pub fn internal( message: impl Into<String> ) -> Self {
+		Self::new( StatusCode::INTERNAL_SERVER_ERROR, message )
+	}
+
+	pub fn bad_gateway( message: impl Into<String> ) -> Self {
+		Self::new( StatusCode::BAD_GATEWAY, message )
+	}
+
+	pub fn service_unavailable( message: impl Into<String> ) -> Self {
+		Self::new( StatusCode::SERVICE_UNAVAILABLE, message )
+	}
+}
+
+impl ResponseError for WebError {
+
+	fn status_code( &self ) -> StatusCode {
+		self.status
+	}
+
+	fn error_response( &self ) -> HttpResponse {
+		let mut context = tera::Context::new();
+		context.insert("status", &self.status.as_u16());
+		context.insert("reason", self.status.canonical_reason().unwrap_or("Error"));
+		context.insert("message", &self.message);
+
+		let html = match &*TEMPLATES.read().unwrap() {
+			None => None,
+			Some(templates) => match templates.render("error.html", &context) {
+				Err(e) => { error!("Template error: {}", e); None },
+				Ok(html) => Some( html )
+			}
+		};
+		match html {
+			None => HttpResponse::build( self.status ).content_type("text/plain").body( self.message.clone() ),
+			Some(html) => HttpResponse::build( self.status ).content_type("text/html").body( html )
+		}
+	}
+}
+
+impl From<persistence::Error> for WebError {
+	fn from( other: persistence::Error ) -> Self {
+		match other {
+			persistence::Error::NotFound => Self::not_found("Not found."),
+			other => {
+				error!("Persistence error: {}", other);
+				Self::internal("Internal server error occurred")
+			}
+		}
+	}
+}
+
+impl From<services::Error> for WebError {
+	fn from( other: services::Error ) -> Self {
+		match other {
+			services::Error::EgoNotFound(_) => Self::not_found("Ego not found."),
+			services::Error::Unavailable(_, _) => {
+				error!("Gnunet error: {}", other);
+				Self::service_unavailable("Gnunet is not available.")
+			},
+			services::Error::Request(_, _) => {
+				error!("Gnunet error: {}", other);
+				Self::internal("Internal server error occurred")
+			}
+		}
+	}
+}
+
+impl From<swarm::Error> for WebError {
+	fn from( other: swarm::Error ) -> Self {
+		warn!("Swarm error: {}", other);
+		Self::bad_gateway("Unable to retrieve the data from the swarm.")
+	}
+}
+
+impl From<MultipartError> for WebError {
+	fn from( other: MultipartError ) -> Self {
+		Self::bad_request( format!("Invalid form: {}", other) )
+	}
+}
+
+impl From<io::Error> for WebError {
+	fn from( other: io::Error ) -> Self {
+		error!("I/O error: {}", other);
+		Self::internal("Internal server error occurred")
+	}
+}
+
+impl From<actix_web::Error> for WebError {
+	fn from( other: actix_web::Error ) -> Self {
+		Self::new( other.as_response_error().status_code(), other.to_string() )
+	}
+}
+
+impl fmt::Display for WebError {
+	fn fmt( &self, f: &mut fmt::Formatter<'_> ) -> fmt::Result {
+		write!(f, "{}", self.message)
+	}
+}
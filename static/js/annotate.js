@@ -0,0 +1,19 @@
+// Fills in the passage of the annotation form with the text that is selected in the post.
+
+function selected_passage() {
+	let selection = window.getSelection()
+	let post = document.querySelector(".post")
+	if ( selection.isCollapsed || !post.contains( selection.anchorNode ) ) {
+		return ""
+	}
+	return selection.toString()
+}
+
+let form = document.getElementById("annotate-form")
+
+document.addEventListener("selectionchange", () => {
+	let passage = selected_passage()
+	if ( passage != "" ) {
+		form.elements["passage"].value = passage
+	}
+})
@@ -0,0 +1,67 @@
+// Shows how the post will look while it is being written, and lets earlier revisions be taken up again.
+
+// The number of milliseconds to wait after the last keystroke before the preview is updated.
+const PREVIEW_DELAY = 300
+
+let form = document.getElementById("editor")
+let preview = document.getElementById("editor-preview")
+let timer = null
+
+async function update_preview() {
+	let body = new URLSearchParams()
+	body.append("content", form.elements["message"].value)
+	body.append("format", form.elements["format"].value)
+
+	let response = await fetch("/channel/preview", { method: "POST", body: body })
+	if ( !response.ok ) {
+		preview.innerText = "Unable to show the preview: " + response.statusText
+		return
+	}
+	preview.innerHTML = await response.text()
+}
+
+function schedule_preview() {
+	clearTimeout( timer )
+	timer = setTimeout( update_preview, PREVIEW_DELAY )
+}
+
+form.elements["message"].addEventListener("input", schedule_preview)
+form.elements["format"].addEventListener("change", schedule_preview)
+update_preview()
+
+// Lists the files that have been picked, so that it is clear what will be attached.
+let attachments = document.getElementById("editor-attachments")
+if ( attachments != null ) {
+	attachments.addEventListener("change", () => {
+		let list = document.getElementById("editor-attachment-list")
+		list.innerHTML = ""
+		for ( let file of attachments.files ) {
+			let item = document.createElement("li")
+			item.innerText = file.name + " (" + Math.ceil( file.size / 1024 ) + " KiB)"
+			list.appendChild( item )
+		}
+	})
+}
+
+// Only one tab is shown at a time, the editor first.
+function show_tab( id ) {
+	for ( let tab of document.querySelectorAll(".editor-tab") ) {
+		tab.hidden = tab.id != id
+	}
+}
+
+let tab_buttons = document.querySelectorAll(".editor-tabs button")
+if ( tab_buttons.length > 0 ) {
+	for ( let button of tab_buttons ) {
+		button.addEventListener("click", () => show_tab( button.dataset.tab ))
+	}
+	show_tab("editor-write")
+}
+
+for ( let button of document.querySelectorAll(".restore") ) {
+	button.addEventListener("click", () => {
+		form.elements["message"].value = button.dataset.content
+		show_tab("editor-write")
+		update_preview()
+	})
+}
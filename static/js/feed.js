@@ -1,8 +1,10 @@
-function set_error_status( post ) {
+// Keeps the feed up to date with the posts that arrive from the swarm, without having to refresh the page.
+
+function set_error_status( message ) {
 
 	var el = document.getElementById("feed-status")
 
-	el.innerText = "Error: " + post.message
+	el.innerText = "Error: " + message
 	el.className += " error"
 }
 
@@ -11,38 +13,43 @@ function set_status( message ) {
 	el.innerText = message
 }
 
-function add_blog_post( post ) {
-	
-	var el = document.createElement("div")
-	el.className = "feed-post"
-	el.innerText = post.html
-
-	document.getElementById("posts").prepend( el )
+// Loads the page again, and takes the posts from it.
+async function load_feed() {
+	let response = await fetch( window.location.href )
+	let html = await response.text()
+	return new DOMParser().parseFromString( html, "text/html" ).querySelector(".feed-posts")
 }
 
-async function load_posts( address, post_handler ) {
-	let socket = new WebSocket("/api/posts/" + address)
-
-	return new Promise((ok, err) => {
+// Replaces the post that has changed, or all posts if it is a new one, as it may need to go at the top.
+async function update_post( change ) {
+	let feed = await load_feed()
+	let current = document.getElementById("post-" + change.post_id)
 
-		socket.onopen = () => console.log(""),
-		socket.onerror = (e) => err(e),
-		socket.onmessage = (e) => {
-			let object = JSON.parse( e.data )
-
-			post_handler( object )
+	if ( change.kind != "published" && current != null ) {
+		let updated = feed.querySelector("#post-" + change.post_id)
+		if ( updated != null ) {
+			current.replaceWith( updated )
+		}
+		else {
+			current.remove()
 		}
-	})
+	}
+	else if ( change.kind == "published" ) {
+		document.querySelector(".feed-posts").replaceWith( feed )
+		set_status("A new post has arrived.")
+	}
 }
 
+function listen( address, on_change ) {
+	let protocol = window.location.protocol == "https:" ? "wss://" : "ws://"
+	let socket = new WebSocket( protocol + window.location.host + "/ws/channel/" + address )
 
+	socket.onerror = () => set_error_status("unable to receive new posts, refresh the page to see them.")
+	socket.onmessage = (e) => on_change( JSON.parse( e.data ) )
+}
 
-await load_posts( ADDRESS, (post) => {
 
-	if ( 'error' in post ) {
-		set_error_status( post )
-	}
-	else {
-		append_post( post )
-	}
+
+listen( ADDRESS, (change) => {
+	update_post( change ).catch((e) => set_error_status( e.message ))
 })
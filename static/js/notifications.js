@@ -0,0 +1,56 @@
+// Keeps the unread counter in the header up to date, and shows desktop notifications if the user has allowed those.
+
+const DESCRIPTIONS = {
+	"post": "A channel that you follow has published a new post.",
+	"revision": "A channel that you follow has revised a post.",
+	"comment": "Someone has commented on your post.",
+	"mention": "Someone has mentioned you in a comment."
+}
+
+function set_count( unread ) {
+	var el = document.getElementById("notification-count")
+	el.innerText = unread > 0 ? "(" + unread + ")" : ""
+}
+
+function show_desktop_notification( notification ) {
+	if ( !("Notification" in window) || Notification.permission != "granted" ) {
+		return
+	}
+
+	let shown = new Notification( "QuartzNet", { body: DESCRIPTIONS[ notification.kind ] } )
+	shown.onclick = () => window.open( notification.url )
+}
+
+// The button on the notifications page asks for the permission to show desktop notifications.
+function setup_enable_button() {
+	let button = document.getElementById("enable-desktop-notifications")
+	if ( button == null ) {
+		return
+	}
+	if ( !("Notification" in window) || Notification.permission != "default" ) {
+		button.hidden = true
+		return
+	}
+
+	button.onclick = () => {
+		Notification.requestPermission().then(() => { button.hidden = true })
+	}
+}
+
+function listen( on_notification ) {
+	let protocol = window.location.protocol == "https:" ? "wss://" : "ws://"
+	let socket = new WebSocket( protocol + window.location.host + "/ws/notifications" )
+
+	socket.onmessage = (e) => on_notification( JSON.parse( e.data ) )
+}
+
+
+
+setup_enable_button()
+listen( (notification) => {
+	set_count( notification.unread )
+	// The first message only tells how many notifications are unread.
+	if ( notification.kind != null ) {
+		show_desktop_notification( notification )
+	}
+})
@@ -0,0 +1,173 @@
+//! Runs the swarm of a channel within the test process.
+//!
+//! Every node has a database of its own in memory, and the nodes reach each other over the loopback transport, so no gnunet peer needs to be running.
+//! The swarm is started by the node of the owner of the channel, and the other nodes join it through any node that is already in it.
+
+use std::{
+	future::Future,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc
+	},
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH}
+};
+
+use gnunet::identity::{KeyType, PrivateKey, PublicKey};
+use quartz_net::{
+	persistence::{self, channel},
+	post::{ContentFormat, Post, PostInfo},
+	services::GnunetServices,
+	swarm::Node,
+	transport::loopback::{LoopbackNetwork, LoopbackTransport}
+};
+
+
+
+/// The relay power of every node, which is enough for the swarms of the tests.
+const RELAY_POWER: u8 = 2;
+/// How long `wait_until` waits for something to happen in the swarm.
+const WAIT_TIMEOUT: Duration = Duration::from_secs( 10 );
+/// How often `wait_until` checks whether it has happened.
+const WAIT_INTERVAL: Duration = Duration::from_millis( 20 );
+
+/// Gives every database in memory a name of its own, as the tests of a file share the process.
+static NEXT_DATABASE: AtomicUsize = AtomicUsize::new( 0 );
+
+/// The swarm of a channel, and the network that its nodes are on.
+pub struct TestSwarm {
+	network: Arc<LoopbackNetwork>,
+	services: Arc<GnunetServices>,
+	/// The key of the channel, which the owner signs its posts with.
+	private_key: PrivateKey,
+	/// The address of the channel.
+	pub address: PublicKey,
+	/// The node that started the swarm.
+	pub owner: TestNode
+}
+
+/// A node in the swarm.
+pub struct TestNode {
+	/// The address that the other nodes reach this one by.
+	pub address: PublicKey,
+	pub db: persistence::Handle,
+	pub channel: channel::Handle,
+	pub node: Node
+}
+
+
+
+impl TestSwarm {
+
+	/// Creates a channel, and starts its swarm with the node of its owner.
+	pub async fn start() -> Self {
+		let network = LoopbackNetwork::new();
+		// The services are never reached, as the channel is created with a key of its own.
+		let services = Arc::new( GnunetServices::new( gnunet::Handle::default() ) );
+
+		let private_key = PrivateKey::generate( KeyType::Eddsa );
+		let address = private_key.extract_public().unwrap();
+		let db = connect_database( &services ).await;
+		let channel = db.init_channel( "owner", &private_key, true ).await.unwrap();
+
+		let transport = network.join( new_address() );
+		let node = Node::start( channel.clone(), transport.clone(), RELAY_POWER, None ).await.unwrap();
+		let owner = TestNode::new( db, channel, node, transport );
+
+		Self {
+			network,
+			services,
+			private_key,
+			address,
+			owner
+		}
+	}
+
+	/// Adds a node to the swarm, which joins it through the given node.
+	pub async fn join( &self, parent: &TestNode ) -> TestNode {
+		let db = connect_database( &self.services ).await;
+		let channel = db.add_channel( &self.address ).await.unwrap();
+
+		let transport = self.network.join( new_address() );
+		let node = Node::connect( channel.clone(), transport.clone(), parent.address.clone(), RELAY_POWER, None ).await.unwrap();
+		TestNode::new( db, channel, node, transport )
+	}
+
+	/// Has the owner publish a post, and sends it into the swarm.
+	pub async fn publish( &self, content: &str ) -> Post {
+		let owner = &self.owner;
+		let timeline = owner.db.get_timeline( &self.address ).await.unwrap().unwrap();
+		let info = PostInfo {
+			publish_timestamp: SystemTime::now().duration_since( UNIX_EPOCH ).unwrap().as_millis() as _,
+			tags: Vec::new(),
+			visible_from: None,
+			format: ContentFormat::Plain,
+			series: None,
+			content_warning: None
+		};
+
+		let (_, post) = timeline.create_post( &self.private_key, content, info, Vec::new(), None ).await.unwrap();
+		owner.channel.log_new_post( &timeline, &post ).await.unwrap();
+		owner.node.publish_outbox().await.unwrap();
+		post
+	}
+
+	/// Has the owner close the channel, and sends the closing into the swarm.
+	pub async fn close( &self ) {
+		self.owner.channel.close( &self.private_key ).await.unwrap();
+		self.owner.node.publish_outbox().await.unwrap();
+	}
+}
+
+impl TestNode {
+
+	/// Admits the nodes that join through this one, for as long as the test runs.
+	fn new( db: persistence::Handle, channel: channel::Handle, node: Node, transport: Arc<LoopbackTransport> ) -> Self {
+		let address = transport.address().clone();
+
+		let parent = node.clone();
+		tokio::spawn(async move {
+			while let Ok(Some(child)) = transport.listen().await {
+				parent.admit_child( child ).await.unwrap();
+			}
+		});
+
+		Self {
+			address,
+			db,
+			channel,
+			node
+		}
+	}
+
+	/// Whether this node has stored the post of the given publisher with the given id.
+	pub async fn has_post( &self, publisher: &PublicKey, post_id: u64 ) -> bool {
+		match self.db.get_timeline( publisher ).await.unwrap() {
+			None => false,
+			Some(timeline) => timeline.load_post( post_id ).await.unwrap().is_some()
+		}
+	}
+}
+
+/// Waits until the condition holds, and fails the test if that takes too long.
+pub async fn wait_until<C, F>( what: &str, condition: C ) where
+	C: Fn() -> F,
+	F: Future<Output=bool>
+{
+	let start = Instant::now();
+	while !condition().await {
+		if start.elapsed() > WAIT_TIMEOUT {
+			panic!("timed out waiting until {}", what);
+		}
+		tokio::time::sleep( WAIT_INTERVAL ).await;
+	}
+}
+
+async fn connect_database( services: &Arc<GnunetServices> ) -> persistence::Handle {
+	let name = format!("test-{}", NEXT_DATABASE.fetch_add( 1, Ordering::Relaxed ));
+	persistence::Handle::connect_in_memory( services.clone(), &name ).await.unwrap()
+}
+
+/// Makes up the address of a node, as nodes are reached by the key of their peer.
+fn new_address() -> PublicKey {
+	PrivateKey::generate( KeyType::Eddsa ).extract_public().unwrap()
+}
@@ -0,0 +1,31 @@
+//! Checks where the login page sends the browser once the admin has logged in.
+
+use quartz_net::web::login_destination;
+
+
+
+#[test]
+fn pages_on_this_node_are_kept() {
+	assert_eq!( login_destination( Some("/admin/channels?page=2") ), "/admin/channels?page=2" );
+	assert_eq!( login_destination( None ), "/" );
+}
+
+#[test]
+fn other_hosts_lead_home() {
+	assert_eq!( login_destination( Some("https://evil.com/") ), "/" );
+	assert_eq!( login_destination( Some("//evil.com") ), "/" );
+}
+
+#[test]
+fn backslashes_lead_home() {
+	assert_eq!( login_destination( Some("/\\evil.com") ), "/" );
+	assert_eq!( login_destination( Some("\\\\evil.com") ), "/" );
+	assert_eq!( login_destination( Some("/channel\\..\\evil") ), "/" );
+}
+
+#[test]
+fn control_characters_lead_home() {
+	assert_eq!( login_destination( Some("/\t/evil.com") ), "/" );
+	assert_eq!( login_destination( Some("/\n/evil.com") ), "/" );
+	assert_eq!( login_destination( Some("/admin\r\nSet-Cookie: a=b") ), "/" );
+}
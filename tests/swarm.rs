@@ -0,0 +1,93 @@
+//! Runs small swarms within the test process, to see that events and requests find their way through them.
+
+mod harness;
+
+use harness::{wait_until, TestSwarm};
+
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn events_reach_the_children() {
+	let swarm = TestSwarm::start().await;
+	let child = swarm.join( &swarm.owner ).await;
+
+	let post = swarm.publish("Hello, swarm.").await;
+
+	wait_until( "the child has the post", || child.has_post( &swarm.address, post.id ) ).await;
+	assert_eq!( swarm.owner.node.child_count().await, 1 );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn events_are_rebroadcasted_down_the_swarm() {
+	let swarm = TestSwarm::start().await;
+	let child = swarm.join( &swarm.owner ).await;
+	let grandchild = swarm.join( &child ).await;
+
+	let post = swarm.publish("Passed on.").await;
+
+	wait_until( "the grandchild has the post", || grandchild.has_post( &swarm.address, post.id ) ).await;
+	assert!( child.has_post( &swarm.address, post.id ).await );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn missing_events_are_backfilled() {
+	let swarm = TestSwarm::start().await;
+	// These are published before anybody is around to receive them.
+	swarm.publish("First.").await;
+	swarm.publish("Second.").await;
+
+	let child = swarm.join( &swarm.owner ).await;
+	let last_id = swarm.publish("Third.").await.id;
+
+	let (swarm, child) = (&swarm, &child);
+	wait_until( "the child has caught up", move || async move {
+		for id in 0..=last_id {
+			if !child.has_post( &swarm.address, id ).await { return false }
+		}
+		true
+	}).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn posts_are_fetched_from_the_parent() {
+	let swarm = TestSwarm::start().await;
+	let post = swarm.publish("Fetch me.").await;
+	let child = swarm.join( &swarm.owner ).await;
+
+	child.node.sync_timeline( &swarm.address, true ).await.unwrap();
+
+	let timeline = child.db.get_timeline( &swarm.address ).await.unwrap().unwrap();
+	assert_eq!( timeline.load_post_content( post.id ).await.unwrap().as_deref(), Some("Fetch me.") );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn the_closing_of_the_channel_reaches_the_subscribers() {
+	let swarm = TestSwarm::start().await;
+	let child = swarm.join( &swarm.owner ).await;
+	let grandchild = swarm.join( &child ).await;
+
+	swarm.close().await;
+
+	wait_until( "the grandchild knows that the channel is closed", || async {
+		grandchild.channel.load_closed_timestamp().await.unwrap().is_some()
+	}).await;
+	assert!( swarm.owner.channel.list_outbox().await.unwrap().is_empty() );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn the_subtree_stays_in_the_swarm_when_its_relay_leaves() {
+	let swarm = TestSwarm::start().await;
+	let relay = swarm.join( &swarm.owner ).await;
+	let first = swarm.join( &relay ).await;
+	let second = swarm.join( &relay ).await;
+
+	relay.node.disconnect().await;
+	wait_until( "the children have found new parents", || async {
+		first.node.parent_count() > 0 && second.node.parent_count() > 0
+	}).await;
+
+	let post = swarm.publish("Still here.").await;
+	wait_until( "both children have the post", || async {
+		first.has_post( &swarm.address, post.id ).await && second.has_post( &swarm.address, post.id ).await
+	}).await;
+}